@@ -0,0 +1,82 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rts::bench::{
+    chunk_voxels, dyn_mesh_data, hash_geometry, mesh_cubic, mesh_simplified, mesh_smooth,
+    queue_dyn_meshes, ChunkPattern, TerrainMeshStyle, UPLOAD_MESHES,
+};
+
+fn mesher(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mesher");
+    for pattern in ChunkPattern::ALL {
+        let voxels = chunk_voxels(pattern, TerrainMeshStyle::Cubic);
+        group.bench_with_input(BenchmarkId::new("cubic", pattern), &voxels, |b, voxels| {
+            b.iter(|| mesh_cubic(black_box(voxels)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("simplified", pattern),
+            &voxels,
+            |b, voxels| {
+                b.iter_batched(
+                    || voxels.clone(),
+                    |voxels| mesh_simplified(black_box(voxels)),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+        let voxels = chunk_voxels(pattern, TerrainMeshStyle::Smooth);
+        group.bench_with_input(BenchmarkId::new("smooth", pattern), &voxels, |b, voxels| {
+            b.iter(|| mesh_smooth(black_box(voxels)))
+        });
+    }
+    group.finish();
+}
+
+fn upload_queue(c: &mut Criterion) {
+    let mut group = c.benchmark_group("upload_queue");
+    for pattern in ChunkPattern::ALL {
+        let geometry = match mesh_cubic(&chunk_voxels(pattern, TerrainMeshStyle::Cubic)) {
+            Some(geometry) => geometry,
+            None => continue,
+        };
+        group.bench_with_input(
+            BenchmarkId::new("hash", pattern),
+            &geometry,
+            |b, geometry| b.iter(|| hash_geometry(black_box(geometry))),
+        );
+        // every mesh queued for upload
+        group.bench_with_input(
+            BenchmarkId::new("distinct", pattern),
+            &geometry,
+            |b, geometry| {
+                b.iter_batched(
+                    || {
+                        (0..UPLOAD_MESHES)
+                            .map(|idx| dyn_mesh_data(geometry, idx as f32))
+                            .collect()
+                    },
+                    queue_dyn_meshes,
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        // all but the first waiting to share its upload
+        group.bench_with_input(
+            BenchmarkId::new("shared", pattern),
+            &geometry,
+            |b, geometry| {
+                b.iter_batched(
+                    || {
+                        (0..UPLOAD_MESHES)
+                            .map(|_| dyn_mesh_data(geometry, 0.))
+                            .collect()
+                    },
+                    queue_dyn_meshes,
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mesher, upload_queue);
+criterion_main!(benches);
@@ -4,13 +4,20 @@ use rafx::renderer::RendererAssetPlugin;
 use rafx_plugins::assets::anim::AnimAssetTypeRendererPlugin;
 use rafx_plugins::assets::font::FontAssetTypeRendererPlugin;
 use rts::daemon_args::AssetDaemonArgs;
-use std::path::PathBuf;
+use rts::packfile::PackPreset;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug, Clone, PartialEq)]
 pub enum CliCommandArgs {
     HostDaemon,
-    Pack { path: PathBuf },
+    Pack {
+        path: PathBuf,
+        // Texture quality of the materials: current (from the settings), low, medium or high.
+        // Other than current, the daemon keeps its imports in a database of its own.
+        #[structopt(long, default_value = "current")]
+        preset: PackPreset,
+    },
 }
 
 #[derive(StructOpt, Debug, Clone)]
@@ -36,8 +43,17 @@ pub struct CliArgs {
 }
 
 fn create_daemon(args: &CliArgs) -> AssetDaemon {
+    let preset = match &args.cmd {
+        CliCommandArgs::Pack { preset, .. } => *preset,
+        CliCommandArgs::HostDaemon => PackPreset::Current,
+    };
+    let db_dir = if preset == PackPreset::Current {
+        args.daemon_args.db_dir.clone()
+    } else {
+        args.daemon_args.db_dir.with_extension(preset.name())
+    };
     let mut asset_daemon = rafx::assets::distill_impl::default_daemon()
-        .with_db_path(&args.daemon_args.db_dir)
+        .with_db_path(&db_dir)
         .with_address(args.daemon_args.address)
         .with_asset_dirs(args.daemon_args.asset_dirs.clone());
 
@@ -46,10 +62,25 @@ fn create_daemon(args: &CliArgs) -> AssetDaemon {
 
     asset_daemon = FontAssetTypeRendererPlugin.configure_asset_daemon(asset_daemon);
     asset_daemon = AnimAssetTypeRendererPlugin.configure_asset_daemon(asset_daemon);
+    asset_daemon = rts::packfile::configure_asset_daemon(
+        asset_daemon,
+        preset,
+        args.daemon_args.asset_dirs.clone(),
+    );
 
     asset_daemon
 }
 
+/// Packs every asset of the daemon listening on the default address into the file at `path`.
+async fn pack(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let context = distill_cli::create_context().await?;
+    let cmd_pack = distill_cli::CmdPack;
+    cmd_pack
+        .run(&context, vec![&path.to_string_lossy()])
+        .await?;
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Setup logging
     env_logger::Builder::from_default_env()
@@ -60,6 +91,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = CliArgs::from_args();
     if args.external_daemon && args.cmd == CliCommandArgs::HostDaemon {
         Err("external-daemon and host-daemon args are incompatible".into())
+    } else if args.external_daemon
+        && matches!(args.cmd, CliCommandArgs::Pack { preset, .. } if preset != PackPreset::Current)
+    {
+        Err("external-daemon packs at the quality the daemon imported with, drop --preset".into())
     } else if args.cmd == CliCommandArgs::HostDaemon {
         let asset_daemon = create_daemon(&args);
 
@@ -95,13 +130,6 @@ async fn async_main(args: CliArgs) -> Result<(), Box<dyn std::error::Error>> {
 
     match args.cmd {
         CliCommandArgs::HostDaemon => unreachable!(),
-        CliCommandArgs::Pack { path } => {
-            let context = distill_cli::create_context().await?;
-            let cmd_pack = distill_cli::CmdPack;
-            cmd_pack
-                .run(&context, vec![&path.to_string_lossy()])
-                .await?;
-            Ok(())
-        }
+        CliCommandArgs::Pack { path, .. } => pack(&path).await,
     }
 }
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+
+use egui::{Color32, Id, LayerId, Order, Stroke};
+use legion::{IntoQuery, Read, Resources};
+use rafx_plugins::features::egui::EguiContextResource;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::RTSCamera,
+    env::{env::TileComponent, simulation::Simulation, ui::TerrainTool},
+    input::{ButtonState, InputResource, KeyboardKey, MouseButton},
+    settings::Settings,
+    ui::UiState,
+    unit::{economy::PlayerEconomy, unit::UnitType},
+};
+
+const DESCRIPTION_PATH: &str = "accessibility.txt";
+const MAX_ALERTS: usize = 5;
+const CURSOR_SIZE: f32 = 10.; // points
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// Game hotkeys for the spawn and edit tools, and Space as a left click at the screen
+    /// center, so everything works without a mouse.
+    pub keyboard_navigation: bool,
+    /// Keep `accessibility.txt` up to date with the description, for external readers.
+    pub export_description: bool,
+    /// Log new alerts at info level under the `accessibility` target.
+    pub announce_alerts: bool,
+    /// No flashes on units taking damage, see `HitFlashResource`.
+    pub reduce_flashing: bool,
+}
+
+/// Keys of the keyboard navigation mode, listed in the settings UI. Tab and Shift+Tab move
+/// the egui focus between widgets and Space or Enter press the focused one, both handled by
+/// egui itself.
+pub const HOTKEYS: [(&str, &str); 8] = [
+    ("Space", "accessibility.key.click"),
+    ("U", "accessibility.key.spawn_unit"),
+    ("1-3", "accessibility.key.unit_type"),
+    ("B", "accessibility.key.place_building"),
+    ("T", "accessibility.key.edit_terrain"),
+    ("R", "accessibility.key.terrain_tool"),
+    ("F1", "accessibility.key.describe"),
+    ("W/A/S/D/Q/E", "accessibility.key.camera"),
+];
+
+/// Keyboard only operation and a plain text description of the game state. The description
+/// (current tool, selection, stock and the latest alerts) is rebuilt every frame and exported
+/// when it changes, as a base for screen reader integration.
+pub struct AccessibilityResource {
+    pub settings: AccessibilitySettings,
+    description: Vec<String>,
+    alerts: VecDeque<String>,
+    // alert sources as of the last update, to only report changes
+    last_sources: Vec<String>,
+}
+
+impl AccessibilityResource {
+    pub fn new(settings: AccessibilitySettings) -> Self {
+        Self {
+            settings,
+            description: vec![],
+            alerts: Default::default(),
+            last_sources: vec![],
+        }
+    }
+
+    pub fn description(&self) -> String {
+        self.description.join("\n")
+    }
+
+    pub fn alert(&mut self, message: String) {
+        if self.settings.announce_alerts {
+            log::info!(target: "accessibility", "{}", message);
+        }
+        self.alerts.push_back(message);
+        while self.alerts.len() > MAX_ALERTS {
+            self.alerts.pop_front();
+        }
+    }
+
+    /// Handles the hotkeys and turns Space into a click at the screen center. Call at the start
+    /// of the scene update, before anything reads the mouse.
+    pub fn handle_input(&mut self, resources: &Resources, ui_state: &mut UiState) {
+        if !self.settings.keyboard_navigation {
+            return;
+        }
+        let mut input = resources.get_mut::<InputResource>().unwrap();
+        if input.is_key_just_down(KeyboardKey::Space) {
+            let camera = resources.get::<RTSCamera>().unwrap();
+            let center = glam::Vec2::new(camera.win_width as f32, camera.win_height as f32) / 2.;
+            input.handle_mouse_update_position(center);
+            input.handle_mouse_button_event(MouseButton::LEFT, ButtonState::Pressed);
+            input.handle_mouse_button_event(MouseButton::LEFT, ButtonState::Released);
+        }
+
+        let unit = &mut ui_state.unit;
+        let env = &mut ui_state.env;
+        if input.is_key_just_down(KeyboardKey::U) {
+            unit.spawning = !unit.spawning;
+            env.tile_spawn.active = false;
+        }
+        let type_keys = [KeyboardKey::Key1, KeyboardKey::Key2, KeyboardKey::Key3];
+        for (key, unit_type) in type_keys.iter().zip(UnitType::ALL.iter()) {
+            if input.is_key_just_down(*key) {
+                unit.object_type = *unit_type;
+                unit.spawning = true;
+                env.tile_spawn.active = false;
+            }
+        }
+        if input.is_key_just_down(KeyboardKey::B) {
+            env.tile_spawn.active = !env.tile_spawn.active;
+            unit.spawning = false;
+        }
+        if input.is_key_just_down(KeyboardKey::T) {
+            env.terrain_edit.active = !env.terrain_edit.active;
+        }
+        if input.is_key_just_down(KeyboardKey::R) && env.terrain_edit.active {
+            env.terrain_edit.tool = match env.terrain_edit.tool {
+                TerrainTool::Voxel => TerrainTool::Road,
+                TerrainTool::Road => TerrainTool::Select,
+                TerrainTool::Select => TerrainTool::Voxel,
+            };
+        }
+        if input.is_key_just_down(KeyboardKey::F1) {
+            let description = self.description();
+            log::info!(target: "accessibility", "{}", description);
+        }
+    }
+
+    /// Rebuilds the description, picks up new alerts and draws the keyboard cursor.
+    pub fn update(
+        &mut self,
+        simulation: &mut Simulation,
+        resources: &Resources,
+        ui_state: &UiState,
+    ) {
+        let economy = resources.get::<PlayerEconomy>().unwrap();
+        let sources: Vec<String> = economy
+            .shortfall()
+            .into_iter()
+            .chain(Some(ui_state.last_error()).filter(|error| !error.is_empty()))
+            .map(|message| message.to_string())
+            .collect();
+        for source in &sources {
+            if !self.last_sources.contains(source) {
+                self.alert(source.clone());
+            }
+        }
+        self.last_sources = sources;
+
+        let unit = &ui_state.unit;
+        let env = &ui_state.env;
+        let mut description = vec![];
+        description.push(if unit.spawning {
+            tr!("accessibility.mode.spawn_unit", unit = unit.object_type)
+        } else if env.tile_spawn.active {
+            tr!(
+                "accessibility.mode.place_building",
+                tile = env.tile_spawn.tile
+            )
+        } else if env.terrain_edit.active {
+            tr!(
+                "accessibility.mode.edit_terrain",
+                tool = env.terrain_edit.tool.name()
+            )
+        } else {
+            tr!("accessibility.mode.select")
+        });
+        if unit.selected_count == 0 {
+            description.push(tr!("accessibility.selection.none"));
+        } else {
+            let mut types: Vec<_> = unit
+                .selected
+                .iter()
+                .map(|(unit_type, count)| format!("{} {}", count, unit_type))
+                .collect();
+            types.sort();
+            description.push(tr!(
+                "accessibility.selection.units",
+                count = unit.selected_count,
+                types = types.join(", ")
+            ));
+        }
+        let universe = simulation.universe();
+        for tile in <Read<TileComponent>>::query().iter(&universe.world) {
+            if tile.selected {
+                description.push(tr!(
+                    "accessibility.selection.building",
+                    health = format!("{:.0}", tile.health * 100.)
+                ));
+            }
+        }
+        let (minerals, energy) = economy.stock(unit.team);
+        description.push(tr!(
+            "accessibility.stock",
+            team = unit.team,
+            minerals = minerals,
+            energy = energy
+        ));
+        for alert in &self.alerts {
+            description.push(tr!("accessibility.alert", message = alert));
+        }
+
+        if description != self.description {
+            self.description = description;
+            if self.settings.export_description {
+                if let Err(err) = std::fs::write(DESCRIPTION_PATH, self.description()) {
+                    log::error!("Cannot write {}: {}", DESCRIPTION_PATH, err);
+                }
+            }
+        }
+
+        if self.settings.keyboard_navigation {
+            let context = resources.get::<EguiContextResource>().unwrap().context();
+            let center = context.input().screen_rect().center();
+            let painter =
+                context.layer_painter(LayerId::new(Order::Foreground, Id::new("kb_cursor")));
+            let stroke = Stroke::new(2., Color32::WHITE);
+            let dx = egui::vec2(CURSOR_SIZE, 0.);
+            let dy = egui::vec2(0., CURSOR_SIZE);
+            painter.line_segment([center - dx, center + dx], stroke);
+            painter.line_segment([center - dy, center + dy], stroke);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.accessibility"))
+            .id_source("accessibility")
+            .default_open(false)
+            .show(ui, |ui| {
+                let settings = &mut self.settings;
+                let mut changed = ui
+                    .checkbox(
+                        &mut settings.keyboard_navigation,
+                        tr!("settings.accessibility.keyboard"),
+                    )
+                    .changed();
+                changed |= ui
+                    .checkbox(
+                        &mut settings.export_description,
+                        tr!("settings.accessibility.export"),
+                    )
+                    .changed();
+                changed |= ui
+                    .checkbox(
+                        &mut settings.announce_alerts,
+                        tr!("settings.accessibility.announce"),
+                    )
+                    .changed();
+                changed |= ui
+                    .checkbox(
+                        &mut settings.reduce_flashing,
+                        tr!("settings.accessibility.flashing"),
+                    )
+                    .changed();
+                if changed {
+                    let settings = *settings;
+                    Settings::update(|s| s.accessibility = settings);
+                }
+                egui::Grid::new("hotkeys").show(ui, |ui| {
+                    for (key, action) in HOTKEYS.iter() {
+                        ui.label(*key);
+                        ui.label(crate::locale::tr(*action));
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+                ui.label(self.description());
+            });
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use distill::loader::{
+    storage::{IndirectIdentifier, LoadStatus},
+    LoadHandle,
+};
+use rafx::{
+    assets::{distill_impl::AssetResource, AssetManager},
+    base::Instant,
+};
+
+use crate::assets::status::AssetStatusResource;
+
+const POLL_INTERVAL: f32 = 0.5; // s
+const MAX_ROWS: usize = 200;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AssetKind {
+    Mesh,
+    Material,
+    Texture,
+    Tile,
+    Other,
+}
+
+impl AssetKind {
+    pub const ALL: [AssetKind; 5] = [
+        AssetKind::Mesh,
+        AssetKind::Material,
+        AssetKind::Texture,
+        AssetKind::Tile,
+        AssetKind::Other,
+    ];
+
+    /// Distill doesn't report asset types, they are told apart by the source file extension.
+    pub fn from_path(path: &str) -> Self {
+        let path = path.to_lowercase();
+        let extension = path.rsplit('.').next().unwrap_or("");
+        match extension {
+            "gltf" | "glb" | "blender_mesh" => AssetKind::Mesh,
+            "pbrmaterial" | "material" | "materialinstance" => AssetKind::Material,
+            "png" | "jpg" | "jpeg" | "tga" | "basis" => AssetKind::Texture,
+            "tile" | "tilesets" => AssetKind::Tile,
+            _ => AssetKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AssetKind::Mesh => tr!("browser.kind.mesh"),
+            AssetKind::Material => tr!("browser.kind.material"),
+            AssetKind::Texture => tr!("browser.kind.texture"),
+            AssetKind::Tile => tr!("browser.kind.tile"),
+            AssetKind::Other => tr!("browser.kind.other"),
+        };
+        f.write_str(&name)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum AssetLoadState {
+    /// A source file the loader has no handle for.
+    NotLoaded,
+    Unresolved,
+    Loading,
+    Loaded,
+    Error(String),
+    Unloading,
+}
+
+impl AssetLoadState {
+    fn from_status(status: LoadStatus) -> Self {
+        match status {
+            LoadStatus::NotRequested => AssetLoadState::NotLoaded,
+            LoadStatus::Unresolved => AssetLoadState::Unresolved,
+            LoadStatus::Loading => AssetLoadState::Loading,
+            LoadStatus::Loaded => AssetLoadState::Loaded,
+            LoadStatus::Error(err) => AssetLoadState::Error(err.to_string()),
+            _ => AssetLoadState::Unloading,
+        }
+    }
+
+    fn color(&self) -> egui::Color32 {
+        match self {
+            AssetLoadState::Loaded => egui::Color32::GREEN,
+            AssetLoadState::Loading | AssetLoadState::Unresolved => egui::Color32::YELLOW,
+            AssetLoadState::Error(_) => egui::Color32::RED,
+            AssetLoadState::NotLoaded | AssetLoadState::Unloading => egui::Color32::GRAY,
+        }
+    }
+}
+
+impl fmt::Display for AssetLoadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AssetLoadState::NotLoaded => tr!("browser.state.not_loaded"),
+            AssetLoadState::Unresolved => tr!("browser.state.unresolved"),
+            AssetLoadState::Loading => tr!("browser.state.loading"),
+            AssetLoadState::Loaded => tr!("browser.state.loaded"),
+            AssetLoadState::Error(_) => tr!("browser.state.error"),
+            AssetLoadState::Unloading => tr!("browser.state.unloading"),
+        };
+        f.write_str(&name)
+    }
+}
+
+pub struct AssetEntry {
+    pub path: String,
+    /// Name of the asset inside the file, files like `.gltf` hold several.
+    pub name: String,
+    pub kind: AssetKind,
+    pub state: AssetLoadState,
+    pub refs: u32,
+    /// Size of the source file, unknown when loading from a packfile.
+    pub size: Option<u64>,
+}
+
+/// Searchable list of the assets known to the loader, plus the source files in the daemon's
+/// asset directories that nothing loaded yet. Assets can be loaded from here and released
+/// again, releasing only drops the references taken by the browser.
+pub struct AssetBrowserResource {
+    pub search: String,
+    pub kind: Option<AssetKind>,
+    pub loaded_only: bool,
+    asset_dirs: Vec<PathBuf>,
+    source_files: Vec<PathBuf>,
+    entries: Vec<AssetEntry>,
+    last_poll: Option<Instant>,
+    forced: Vec<(String, LoadHandle)>,
+    result: String,
+}
+
+impl AssetBrowserResource {
+    /// `asset_dirs` are the directories watched by the daemon, empty when there is none.
+    pub fn new(asset_dirs: Vec<PathBuf>) -> Self {
+        let mut browser = Self {
+            search: "".to_string(),
+            kind: None,
+            loaded_only: false,
+            asset_dirs,
+            source_files: vec![],
+            entries: vec![],
+            last_poll: None,
+            forced: vec![],
+            result: "".to_string(),
+        };
+        browser.rescan();
+        browser
+    }
+
+    /// Lists the source files in the asset directories again, for files added while running.
+    pub fn rescan(&mut self) {
+        self.source_files.clear();
+        for dir in &self.asset_dirs {
+            Self::scan_dir(dir, &mut self.source_files);
+        }
+        self.source_files.sort();
+    }
+
+    fn scan_dir(dir: &Path, files: &mut Vec<PathBuf>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::error!("Cannot list {}: {}", dir.display(), err);
+                return;
+            }
+        };
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            if path.is_dir() {
+                Self::scan_dir(&path, files);
+            } else if path.extension().map_or(true, |ext| ext != "meta") {
+                files.push(path);
+            }
+        }
+    }
+
+    fn source_file(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        self.asset_dirs
+            .iter()
+            .map(|dir| dir.join(path))
+            .find(|p| p.is_file())
+    }
+
+    /// One entry per asset with a load handle, and one per source file without any.
+    pub fn query(&self, asset_resource: &AssetResource) -> Vec<AssetEntry> {
+        let loader = asset_resource.loader();
+        let mut seen_ids = HashSet::new();
+        let mut sizes = HashMap::new();
+        let mut loaded_files = HashSet::new();
+        let mut entries = vec![];
+        for handle in loader.get_active_loads() {
+            let info = match loader.get_load_info(handle) {
+                Some(info) => info,
+                None => continue,
+            };
+            // the indirect handles taken by path share the asset of a direct one
+            if !seen_ids.insert(info.asset_id) {
+                continue;
+            }
+            let path = info
+                .path
+                .or_else(|| info.file_name.clone())
+                .unwrap_or_else(|| "???".to_string());
+            let size = *sizes.entry(path.clone()).or_insert_with(|| {
+                let file = self.source_file(&path)?;
+                let size = std::fs::metadata(&file).ok().map(|m| m.len());
+                loaded_files.insert(file);
+                size
+            });
+            entries.push(AssetEntry {
+                name: info
+                    .asset_name
+                    .unwrap_or_else(|| format!("{}", info.asset_id)),
+                kind: AssetKind::from_path(&path),
+                state: AssetLoadState::from_status(loader.get_load_status(handle)),
+                refs: info.refs,
+                size,
+                path,
+            });
+        }
+        for file in &self.source_files {
+            if loaded_files.contains(file) {
+                continue;
+            }
+            let path = file.display().to_string();
+            entries.push(AssetEntry {
+                name: file
+                    .file_name()
+                    .map_or_else(|| path.clone(), |name| name.to_string_lossy().to_string()),
+                kind: AssetKind::from_path(&path),
+                state: AssetLoadState::NotLoaded,
+                refs: 0,
+                size: std::fs::metadata(file).ok().map(|m| m.len()),
+                path,
+            });
+        }
+        entries.sort_by(|x, y| x.path.cmp(&y.path).then_with(|| x.name.cmp(&y.name)));
+        entries
+    }
+
+    pub fn update(&mut self, asset_resource: &AssetResource) {
+        let now = Instant::now();
+        if let Some(last_poll) = self.last_poll {
+            if (now - last_poll).as_secs_f32() < POLL_INTERVAL {
+                return;
+            }
+        }
+        self.last_poll = Some(now);
+        self.entries = self.query(asset_resource);
+    }
+
+    fn matches(&self, entry: &AssetEntry) -> bool {
+        if self.kind.map_or(false, |kind| kind != entry.kind) {
+            return false;
+        }
+        if self.loaded_only && entry.state == AssetLoadState::NotLoaded {
+            return false;
+        }
+        let search = self.search.trim().to_lowercase();
+        search.is_empty()
+            || entry.path.to_lowercase().contains(&search)
+            || entry.name.to_lowercase().contains(&search)
+    }
+
+    pub fn is_forced(&self, path: &str) -> bool {
+        self.forced.iter().any(|(p, _)| p == path)
+    }
+
+    /// Takes a reference to the asset at `path`, keeping it loaded until released.
+    pub fn force_load(&mut self, asset_resource: &AssetResource, path: &str) {
+        if self.is_forced(path) {
+            return;
+        }
+        let handle = asset_resource
+            .loader()
+            .add_ref_indirect(IndirectIdentifier::Path(path.to_string()));
+        log::info!("Force loading asset {}", path);
+        self.forced.push((path.to_string(), handle));
+        self.last_poll = None;
+    }
+
+    /// Drops the reference taken by `force_load`, the asset unloads if nothing else uses it.
+    pub fn release(&mut self, asset_resource: &AssetResource, path: &str) {
+        let loader = asset_resource.loader();
+        self.forced.retain(|(p, handle)| {
+            if p == path {
+                loader.remove_ref(*handle);
+                log::info!("Released asset {}", path);
+                false
+            } else {
+                true
+            }
+        });
+        self.last_poll = None;
+    }
+
+    pub fn release_all(&mut self, asset_resource: &AssetResource) {
+        let loader = asset_resource.loader();
+        for (_, handle) in self.forced.drain(..) {
+            loader.remove_ref(handle);
+        }
+        self.last_poll = None;
+    }
+
+    fn format_size(size: Option<u64>) -> String {
+        match size {
+            Some(size) if size >= 1 << 20 => format!("{:.1} MB", size as f32 / (1 << 20) as f32),
+            Some(size) if size >= 1 << 10 => format!("{:.1} KB", size as f32 / (1 << 10) as f32),
+            Some(size) => format!("{} B", size),
+            None => "-".to_string(),
+        }
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        asset_resource: &AssetResource,
+        asset_manager: &AssetManager,
+        asset_status: &mut AssetStatusResource,
+    ) {
+        self.update(asset_resource);
+
+        ui.horizontal(|ui| {
+            ui.label(tr!("browser.search"));
+            ui.text_edit_singleline(&mut self.search);
+        });
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label(tr!("browser.kind"))
+                .selected_text(
+                    self.kind
+                        .map_or(tr!("browser.kind.all"), |kind| kind.to_string()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.kind, None, tr!("browser.kind.all"));
+                    for kind in AssetKind::ALL {
+                        ui.selectable_value(&mut self.kind, Some(kind), kind.to_string());
+                    }
+                });
+            ui.checkbox(&mut self.loaded_only, tr!("browser.loaded_only"));
+        });
+        ui.horizontal(|ui| {
+            if !self.asset_dirs.is_empty() && ui.button(tr!("browser.rescan")).clicked() {
+                self.rescan();
+                self.last_poll = None;
+            }
+            if !self.forced.is_empty()
+                && ui
+                    .button(tr!("browser.release_all", count = self.forced.len()))
+                    .clicked()
+            {
+                self.release_all(asset_resource);
+            }
+        });
+
+        let shown: Vec<_> = (0..self.entries.len())
+            .filter(|i| self.matches(&self.entries[*i]))
+            .collect();
+        let loaded = shown
+            .iter()
+            .filter(|i| self.entries[**i].state == AssetLoadState::Loaded)
+            .count();
+        ui.label(tr!(
+            "browser.counts",
+            shown = shown.len(),
+            loaded = loaded,
+            kept = self.forced.len()
+        ));
+
+        let mut load = None;
+        let mut release = None;
+        egui::ScrollArea::vertical()
+            .max_height(400.)
+            .show(ui, |ui| {
+                for i in shown.iter().take(MAX_ROWS) {
+                    let entry = &self.entries[*i];
+                    ui.horizontal(|ui| {
+                        ui.colored_label(entry.state.color(), entry.state.to_string());
+                        let res = ui.selectable_label(
+                            entry.path == asset_status.reimport_path,
+                            format!("{}:{}", entry.path, entry.name),
+                        );
+                        if res.clicked() {
+                            asset_status.reimport_path = entry.path.clone();
+                        }
+                        if let AssetLoadState::Error(err) = &entry.state {
+                            res.on_hover_text(err);
+                        }
+                        ui.label(tr!(
+                            "browser.entry",
+                            refs = entry.refs,
+                            size = Self::format_size(entry.size),
+                            kind = entry.kind
+                        ));
+                        if self.is_forced(&entry.path) {
+                            if ui.small_button(tr!("browser.release")).clicked() {
+                                release = Some(entry.path.clone());
+                            }
+                        } else if ui.small_button(tr!("browser.load")).clicked() {
+                            load = Some(entry.path.clone());
+                        }
+                    });
+                }
+                if shown.len() > MAX_ROWS {
+                    ui.label(tr!("browser.more", count = shown.len() - MAX_ROWS));
+                }
+            });
+        if let Some(path) = load {
+            self.force_load(asset_resource, &path);
+        }
+        if let Some(path) = release {
+            self.release(asset_resource, &path);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button(tr!("browser.load_path")).clicked() {
+                let path = self.search.trim().to_string();
+                if path.is_empty() {
+                    self.result = tr!("browser.load_path.empty");
+                } else {
+                    self.force_load(asset_resource, &path);
+                    self.result = tr!("browser.load_path.requested", path = path);
+                }
+            }
+            if !self.result.is_empty() {
+                ui.label(&self.result);
+            }
+        });
+
+        egui::CollapsingHeader::new(tr!("browser.metrics"))
+            .id_source("asset manager metrics")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.label(format!("{:#?}", asset_manager.metrics()));
+            });
+    }
+}
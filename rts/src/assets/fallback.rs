@@ -0,0 +1,177 @@
+use distill::loader::{
+    handle::{AssetHandle, Handle},
+    storage::LoadStatus,
+};
+use rafx::{
+    api::RafxResult,
+    assets::{distill_impl::AssetResource, AssetManager},
+    base::Instant,
+};
+use rafx_plugins::assets::mesh_adv::MeshAdvAsset as MeshAsset;
+
+use crate::assets::pbr_material::PbrMaterialAsset;
+
+const FALLBACK_MATERIAL_PATH: &str = "materials/fallback.pbrmaterial";
+const FALLBACK_MESH_PATH: &str = "meshes/fallback_cube.gltf";
+// terrain materials loading longer than this are listed as missing
+const SLOW_MATERIAL_LOAD: f32 = 5.; // s
+
+struct Substitution {
+    asset: String,
+    kind: &'static str,
+    reason: String,
+}
+
+struct PendingMaterial {
+    name: String,
+    since: Instant,
+    warned: bool,
+}
+
+/// Placeholders loaded at init (a magenta checker material and a unit cube mesh) that stand
+/// in for assets that failed to load, so a broken asset shows up in the world instead of
+/// panicking or silently dropping the chunks that use it. Every substitution is listed in the
+/// "Asset warnings" panel, along with the terrain materials still loading after
+/// `SLOW_MATERIAL_LOAD`, which the chunks are meshed without until they are ready.
+pub struct FallbackAssetsResource {
+    material: Handle<PbrMaterialAsset>,
+    mesh: Handle<MeshAsset>,
+    substitutions: Vec<Substitution>,
+    pending_materials: Vec<PendingMaterial>,
+}
+
+impl FallbackAssetsResource {
+    pub fn new(
+        asset_manager: &mut AssetManager,
+        asset_resource: &mut AssetResource,
+    ) -> RafxResult<Self> {
+        let material = asset_resource.load_asset_path(FALLBACK_MATERIAL_PATH);
+        let mesh = asset_resource.load_asset_path(FALLBACK_MESH_PATH);
+        asset_manager.wait_for_asset_to_load(&material, asset_resource, "fallback material")?;
+        asset_manager.wait_for_asset_to_load(&mesh, asset_resource, "fallback mesh")?;
+        Ok(Self {
+            material,
+            mesh,
+            substitutions: vec![],
+            pending_materials: vec![],
+        })
+    }
+
+    /// True once the loader gave up on the asset, as opposed to it still loading.
+    pub fn load_failed<T>(asset_resource: &AssetResource, handle: &Handle<T>) -> bool {
+        matches!(
+            asset_resource
+                .loader()
+                .get_load_status(handle.load_handle()),
+            LoadStatus::Error(_) | LoadStatus::DoesNotExist
+        )
+    }
+
+    fn substitute(&mut self, asset: &str, kind: &'static str, reason: String) {
+        if self.substitutions.iter().any(|s| s.asset == asset) {
+            return;
+        }
+        log::warn!("Using the fallback {} for {}: {}", kind, asset, reason);
+        self.substitutions.push(Substitution {
+            asset: asset.to_string(),
+            kind,
+            reason,
+        });
+    }
+
+    /// The committed material, the fallback if it failed to load, or `None` while loading.
+    pub fn material(
+        &mut self,
+        asset_manager: &AssetManager,
+        asset_resource: &AssetResource,
+        handle: &Handle<PbrMaterialAsset>,
+        name: &str,
+    ) -> Option<PbrMaterialAsset> {
+        if let Some(material) = asset_manager.committed_asset(handle) {
+            return Some(material.clone());
+        }
+        if !Self::load_failed(asset_resource, handle) {
+            return None;
+        }
+        self.substitute(name, "material", tr!("fallback.load_failed"));
+        asset_manager.committed_asset(&self.material).cloned()
+    }
+
+    /// Terrain materials still loading, replacing the previous list.
+    pub fn set_pending_materials(&mut self, names: &[&str]) {
+        self.pending_materials
+            .retain(|pending| names.contains(&pending.name.as_str()));
+        let now = Instant::now();
+        for name in names {
+            if !self.pending_materials.iter().any(|p| p.name == *name) {
+                self.pending_materials.push(PendingMaterial {
+                    name: name.to_string(),
+                    since: now,
+                    warned: false,
+                });
+            }
+        }
+        for pending in &mut self.pending_materials {
+            if !pending.warned && (now - pending.since).as_secs_f32() > SLOW_MATERIAL_LOAD {
+                log::warn!(
+                    "Terrain material {} still loading, chunks are meshed without it",
+                    pending.name
+                );
+                pending.warned = true;
+            }
+        }
+    }
+
+    /// Blocks until the mesh is loaded, returning the fallback cube if it fails.
+    pub fn wait_for_mesh(
+        &mut self,
+        asset_manager: &mut AssetManager,
+        asset_resource: &mut AssetResource,
+        handle: Handle<MeshAsset>,
+        name: &str,
+    ) -> Handle<MeshAsset> {
+        match asset_manager.wait_for_asset_to_load(&handle, asset_resource, name) {
+            Ok(_) => handle,
+            Err(err) => {
+                self.substitute(name, "mesh", err.to_string());
+                self.mesh.clone()
+            }
+        }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let missing: Vec<_> = self
+            .pending_materials
+            .iter()
+            .filter(|pending| pending.warned)
+            .collect();
+        let count = self.substitutions.len() + missing.len();
+        if count == 0 {
+            return;
+        }
+        egui::CollapsingHeader::new(tr!("fallback", count = count))
+            .id_source("asset warnings")
+            .default_open(true)
+            .show(ui, |ui| {
+                for substitution in &self.substitutions {
+                    let text = if substitution.kind == "material" {
+                        tr!("fallback.material", asset = substitution.asset)
+                    } else {
+                        tr!("fallback.mesh", asset = substitution.asset)
+                    };
+                    ui.colored_label(egui::Color32::YELLOW, text)
+                        .on_hover_text(&substitution.reason);
+                }
+                for pending in missing {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        tr!("fallback.material.loading", asset = pending.name),
+                    )
+                    .on_hover_text(tr!(
+                        "fallback.material.loading.hint",
+                        time = format!("{:.0}", (Instant::now() - pending.since).as_secs_f32())
+                    ));
+                }
+            });
+    }
+}
@@ -1,3 +1,7 @@
+pub mod browser;
+pub mod fallback;
 pub mod pbr_material;
+pub mod status;
+pub mod texture_quality;
 pub mod tile;
 pub mod tilesets;
@@ -1,4 +1,7 @@
-use std::io::Read;
+use std::{
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
 
 use distill::{
     core::AssetUuid,
@@ -6,12 +9,21 @@ use distill::{
     loader::handle::Handle,
     make_handle, make_handle_from_str,
 };
-use rafx::assets::{ImageAsset, MaterialInstanceAssetData, MaterialInstanceSlotAssignment};
+use rafx::{
+    api::RafxResourceType,
+    assets::{
+        ImageAsset, ImageAssetColorSpaceConfig, ImageAssetData, ImageAssetDataFormatConfig,
+        ImageAssetMipGeneration, MaterialInstanceAssetData, MaterialInstanceSlotAssignment,
+    },
+};
 use rafx_plugins::assets::mesh_adv::MeshAdvMaterialDataShaderParam;
 use serde::{Deserialize, Serialize};
 use type_uuid::*;
 
-use crate::assets::pbr_material::{PbrMaterialAssetData, PbrMaterialSource};
+use crate::assets::{
+    pbr_material::{PbrMaterialAssetData, PbrMaterialSource},
+    texture_quality::TextureQuality,
+};
 
 #[derive(TypeUuid, Serialize, Deserialize, Default, Clone, Debug)]
 #[uuid = "6b5e8cc4-9a8e-45e2-9e25-7f1ab02f4ca0"]
@@ -44,19 +56,92 @@ impl From<PbrMaterialImporterStateStable> for PbrMaterialImporterStateUnstable {
     }
 }
 
+/// The texture paths of a `PbrMaterialSource`, as written in the file.
+#[derive(Deserialize)]
+struct PbrMaterialTexturePaths {
+    base_color_texture: Option<String>,
+    metallic_roughness_texture: Option<String>,
+    normal_texture: Option<String>,
+    occlusion_texture: Option<String>,
+    emissive_texture: Option<String>,
+}
+
 #[derive(TypeUuid)]
 #[uuid = "32ca7189-ac8b-4e4e-a7a3-4f43e115bc1f"]
-pub struct PbrMaterialImporter;
+pub struct PbrMaterialImporter {
+    pub quality: TextureQuality,
+    /// Directories watched by the daemon, the texture paths are resolved against them.
+    pub asset_dirs: Vec<PathBuf>,
+}
+
+impl PbrMaterialImporter {
+    /// The materials reference their textures from the asset root (`../../textures/..`), so the
+    /// path without its `..` components is found under one of the asset dirs.
+    fn resolve_texture(&self, path: &str) -> Option<PathBuf> {
+        let relative = Path::new(path)
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect::<PathBuf>();
+        self.asset_dirs
+            .iter()
+            .map(|dir| dir.join(&relative))
+            .find(|path| path.is_file())
+    }
+
+    /// A copy of the texture at `path` downsized to the quality's resolution cap, `None` if it
+    /// fits already.
+    fn downsize_texture(
+        &self,
+        path: &Path,
+        color_space: ImageAssetColorSpaceConfig,
+    ) -> Result<Option<ImageAssetData>, String> {
+        let image = image::open(path)
+            .map_err(|err| format!("Cannot load texture {}: {}", path.display(), err))?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+        let largest = width.max(height);
+        let cap = self.quality.max_resolution.max(1);
+        if largest <= cap {
+            return Ok(None);
+        }
+        let width = (width as u64 * cap as u64 / largest as u64).max(1) as u32;
+        let height = (height as u64 * cap as u64 / largest as u64).max(1) as u32;
+        let image =
+            image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle);
+        ImageAssetData::from_raw_rgba32(
+            width,
+            height,
+            color_space,
+            ImageAssetDataFormatConfig::Uncompressed,
+            ImageAssetMipGeneration::Runtime,
+            RafxResourceType::TEXTURE,
+            image.as_raw(),
+        )
+        .map(Some)
+        .map_err(|err| format!("Cannot downsize texture {}: {:?}", path.display(), err))
+    }
+}
+
+/// Uuid of the downsized copy of a material's texture. Derived from the material instance so
+/// it stays the same across imports without being kept in the importer state.
+fn downsized_texture_uuid(material_instance_uuid: AssetUuid, slot_index: u8) -> AssetUuid {
+    let mut bytes = material_instance_uuid.0;
+    bytes[15] ^= 0x80 | slot_index;
+    AssetUuid(bytes)
+}
+
 impl Importer for PbrMaterialImporter {
     fn version_static() -> u32
     where
         Self: Sized,
     {
-        1
+        2
     }
 
     fn version(&self) -> u32 {
         Self::version_static()
+            .wrapping_mul(31)
+            .wrapping_add(self.quality.import_key())
     }
 
     type Options = ();
@@ -79,7 +164,10 @@ impl Importer for PbrMaterialImporter {
                 .unwrap_or_else(|| AssetUuid(*uuid::Uuid::new_v4().as_bytes())),
         );
 
-        let source = ron::de::from_reader::<_, PbrMaterialSource>(source)?;
+        let mut text = String::new();
+        source.read_to_string(&mut text)?;
+        let texture_paths = ron::de::from_str::<PbrMaterialTexturePaths>(&text)?;
+        let mut source = ron::de::from_str::<PbrMaterialSource>(&text)?;
         let material_handle = make_handle_from_str("92a98639-de0d-40cf-a222-354f616346c3")?;
         let null_image_handle = make_handle_from_str("fc937369-cad2-4a00-bf42-5968f1210784")?;
 
@@ -127,36 +215,75 @@ impl Importer for PbrMaterialImporter {
             });
         }
 
-        push_image_slot_assignment(
-            "base_color_texture",
-            &mut slot_assignments,
-            &source.base_color_texture,
-            &null_image_handle,
-        );
-        push_image_slot_assignment(
-            "metallic_roughness_texture",
-            &mut slot_assignments,
-            &source.metallic_roughness_texture,
-            &null_image_handle,
-        );
-        push_image_slot_assignment(
-            "normal_texture",
-            &mut slot_assignments,
-            &source.normal_texture,
-            &null_image_handle,
-        );
-        push_image_slot_assignment(
-            "occlusion_texture",
-            &mut slot_assignments,
-            &source.occlusion_texture,
-            &null_image_handle,
-        );
-        push_image_slot_assignment(
-            "emissive_texture",
-            &mut slot_assignments,
-            &source.emissive_texture,
-            &null_image_handle,
-        );
+        // overrides the default sampler of the material
+        slot_assignments.push(MaterialInstanceSlotAssignment {
+            slot_name: "smp".to_string(),
+            array_index: 0,
+            image: None,
+            sampler: Some(self.quality.sampler_def()),
+            buffer_data: None,
+        });
+
+        let textures = [
+            (
+                "base_color_texture",
+                &mut source.base_color_texture,
+                &texture_paths.base_color_texture,
+                ImageAssetColorSpaceConfig::Srgb,
+            ),
+            (
+                "metallic_roughness_texture",
+                &mut source.metallic_roughness_texture,
+                &texture_paths.metallic_roughness_texture,
+                ImageAssetColorSpaceConfig::Linear,
+            ),
+            (
+                "normal_texture",
+                &mut source.normal_texture,
+                &texture_paths.normal_texture,
+                ImageAssetColorSpaceConfig::Linear,
+            ),
+            (
+                "occlusion_texture",
+                &mut source.occlusion_texture,
+                &texture_paths.occlusion_texture,
+                ImageAssetColorSpaceConfig::Linear,
+            ),
+            (
+                "emissive_texture",
+                &mut source.emissive_texture,
+                &texture_paths.emissive_texture,
+                ImageAssetColorSpaceConfig::Srgb,
+            ),
+        ];
+        for (slot_index, (slot_name, image, path, color_space)) in textures.into_iter().enumerate()
+        {
+            // textures above the resolution cap are replaced by a downsized copy, also in the
+            // source, so the full size one is not loaded as a dependency
+            let downsized = path
+                .as_ref()
+                .and_then(|path| self.resolve_texture(path))
+                .and_then(|path| match self.downsize_texture(&path, color_space) {
+                    Ok(downsized) => downsized,
+                    Err(err) => {
+                        log::warn!("{}, using it at full resolution", err);
+                        None
+                    }
+                });
+            if let Some(image_data) = downsized {
+                let uuid = downsized_texture_uuid(material_instance_uuid, slot_index as u8);
+                imported_assets.push(ImportedAsset {
+                    id: uuid,
+                    search_tags: vec![],
+                    build_deps: vec![],
+                    load_deps: vec![],
+                    build_pipeline: None,
+                    asset_data: Box::new(image_data),
+                });
+                *image = Some(make_handle(uuid));
+            }
+            push_image_slot_assignment(slot_name, &mut slot_assignments, image, &null_image_handle);
+        }
 
         let material_instance_asset = MaterialInstanceAssetData {
             material: material_handle.clone(),
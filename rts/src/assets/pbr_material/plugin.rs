@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use rafx::{
     assets::{distill_impl::AssetResource, AssetManager},
     distill::daemon::AssetDaemon,
@@ -5,12 +7,23 @@ use rafx::{
 };
 
 use super::{PbrMaterialAssetType, PbrMaterialImporter};
+use crate::assets::texture_quality::TextureQuality;
 
-pub struct PbrMaterialAssetTypeRendererPlugin;
+pub struct PbrMaterialAssetTypeRendererPlugin {
+    pub quality: TextureQuality,
+    /// Directories watched by the daemon, empty when loading from a packfile.
+    pub asset_dirs: Vec<PathBuf>,
+}
 
 impl RendererAssetPlugin for PbrMaterialAssetTypeRendererPlugin {
     fn configure_asset_daemon(&self, asset_daemon: AssetDaemon) -> AssetDaemon {
-        asset_daemon.with_importer(&["pbrmaterial"], PbrMaterialImporter)
+        asset_daemon.with_importer(
+            &["pbrmaterial"],
+            PbrMaterialImporter {
+                quality: self.quality,
+                asset_dirs: self.asset_dirs.clone(),
+            },
+        )
     }
 
     fn register_asset_types(
@@ -0,0 +1,519 @@
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    net::{SocketAddr, TcpStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    time::{Duration, SystemTime},
+};
+
+use distill::loader::{
+    packfile_io::PackfileReader,
+    storage::{IndirectIdentifier, LoadStatus},
+    LoadHandle,
+};
+use rafx::{assets::distill_impl::AssetResource, base::Instant, renderer::AssetSource};
+
+use crate::file_dialog::{DialogPurpose, FileDialogResource};
+
+const POLL_INTERVAL: f32 = 0.5; // s
+const MAX_ERRORS: usize = 32;
+const DAEMON_STALL_TIMEOUT: f32 = 5.; // s
+
+// Distill doesn't expose the state of its RPC connection, so the daemon's port is probed instead.
+const PROBE_INTERVAL: f32 = 2.; // s
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub enum AssetStatusSource {
+    Packfile(PathBuf),
+    Daemon {
+        address: SocketAddr,
+        asset_dirs: Vec<PathBuf>,
+        external: bool,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DaemonConnection {
+    /// Not probed yet.
+    Unknown,
+    Connected,
+    /// The daemon accepts connections but the loads stopped making progress.
+    Stalled,
+    Unreachable,
+}
+
+/// Asset source to switch to, applied between frames by rebuilding the app on the same window.
+pub enum SourceRequest {
+    Packfile(PathBuf),
+    Daemon,
+}
+
+pub struct ImportError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Asset pipeline state polled from the `AssetResource` loader, shown in the "Asset pipeline"
+/// debug panel.
+pub struct AssetStatusResource {
+    source: AssetStatusSource,
+    last_poll: Instant,
+    loading: Vec<String>,
+    unresolved: usize,
+    loaded: usize,
+    progress_at: Instant,
+    errors: VecDeque<ImportError>,
+    new_errors: usize,
+    pub reimport_path: String,
+    reimport_result: String,
+    pub pack_path: String,
+    source_request: Option<SourceRequest>,
+    source_result: String,
+    // assets of the previous source, re-resolved against the current one
+    expected: Vec<(String, LoadHandle)>,
+    conflicts: Vec<String>,
+    reachable: Option<bool>,
+    probe: Option<Receiver<bool>>,
+    probed_at: Instant,
+}
+
+impl AssetStatusResource {
+    pub fn new(asset_source: &AssetSource) -> Self {
+        let source = match asset_source {
+            AssetSource::Packfile(path) => AssetStatusSource::Packfile(path.clone()),
+            AssetSource::Daemon {
+                external_daemon,
+                daemon_args,
+            } => AssetStatusSource::Daemon {
+                address: daemon_args.address,
+                asset_dirs: daemon_args.asset_dirs.clone(),
+                external: *external_daemon,
+            },
+        };
+        let now = Instant::now();
+        Self {
+            source,
+            last_poll: now,
+            loading: vec![],
+            unresolved: 0,
+            loaded: 0,
+            progress_at: now,
+            errors: VecDeque::new(),
+            new_errors: 0,
+            reimport_path: "".to_string(),
+            reimport_result: "".to_string(),
+            pack_path: "".to_string(),
+            source_request: None,
+            source_result: "".to_string(),
+            expected: vec![],
+            conflicts: vec![],
+            reachable: None,
+            probe: None,
+            probed_at: now,
+        }
+    }
+
+    /// Paths of the assets currently loaded, to be checked against the next source.
+    pub fn loaded_paths(asset_resource: &AssetResource) -> Vec<String> {
+        let loader = asset_resource.loader();
+        let mut paths: Vec<_> = loader
+            .get_active_loads()
+            .into_iter()
+            .filter(|handle| matches!(loader.get_load_status(*handle), LoadStatus::Loaded))
+            .filter_map(|handle| loader.get_load_info(handle).and_then(|info| info.path))
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Requests every path by name from the current source. The ones that fail to resolve are
+    /// reported as conflicts once the loader settles.
+    pub fn expect(&mut self, asset_resource: &AssetResource, paths: Vec<String>) {
+        let loader = asset_resource.loader();
+        self.release_expected(asset_resource);
+        self.conflicts.clear();
+        self.expected = paths
+            .into_iter()
+            .map(|path| {
+                let handle = loader.add_ref_indirect(IndirectIdentifier::Path(path.clone()));
+                (path, handle)
+            })
+            .collect();
+    }
+
+    fn release_expected(&mut self, asset_resource: &AssetResource) {
+        let loader = asset_resource.loader();
+        for (_, handle) in self.expected.drain(..) {
+            loader.remove_ref(handle);
+        }
+    }
+
+    pub fn conflicts(&self) -> &[String] {
+        &self.conflicts
+    }
+
+    pub fn take_source_request(&mut self) -> Option<SourceRequest> {
+        self.source_request.take()
+    }
+
+    pub fn request_packfile(&mut self, path: &Path) -> Result<(), String> {
+        let metadata = std::fs::metadata(path).map_err(|err| {
+            tr!(
+                "assets.packfile.cannot_open",
+                path = path.display(),
+                error = err
+            )
+        })?;
+        if !metadata.is_file() || metadata.len() == 0 {
+            return Err(tr!("assets.packfile.not_packfile", path = path.display()));
+        }
+        if let AssetStatusSource::Packfile(current) = &self.source {
+            if current == path {
+                return Err(tr!("assets.packfile.mounted", path = path.display()));
+            }
+        }
+        // the switch tears the renderer down, a pack that can't be read must not get that far
+        std::fs::File::open(path)
+            .and_then(PackfileReader::new)
+            .map_err(|err| {
+                tr!(
+                    "assets.packfile.invalid",
+                    path = path.display(),
+                    error = err
+                )
+            })?;
+        self.source_request = Some(SourceRequest::Packfile(path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Shown under the source switch, eg. why the last switch was rolled back.
+    pub fn set_source_result(&mut self, result: String) {
+        self.source_result = result;
+    }
+
+    pub fn request_daemon(&mut self) -> Result<(), String> {
+        if let AssetStatusSource::Daemon { .. } = &self.source {
+            return Err(tr!("assets.daemon.already"));
+        }
+        self.source_request = Some(SourceRequest::Daemon);
+        Ok(())
+    }
+
+    pub fn connection(&self) -> DaemonConnection {
+        let settled = self.unresolved == 0 && self.loading.is_empty();
+        match self.reachable {
+            None => DaemonConnection::Unknown,
+            Some(false) => DaemonConnection::Unreachable,
+            Some(true)
+                if !settled
+                    && (Instant::now() - self.progress_at).as_secs_f32()
+                        >= DAEMON_STALL_TIMEOUT =>
+            {
+                DaemonConnection::Stalled
+            }
+            Some(true) => DaemonConnection::Connected,
+        }
+    }
+
+    /// Connects to the daemon's port on a thread of its own, a remote daemon may take the whole
+    /// timeout to answer.
+    fn update_probe(&mut self, now: Instant) {
+        let address = match &self.source {
+            AssetStatusSource::Daemon { address, .. } => *address,
+            AssetStatusSource::Packfile(_) => return,
+        };
+        if let Some(probe) = &self.probe {
+            match probe.try_recv() {
+                Ok(reachable) => {
+                    self.reachable = Some(reachable);
+                    self.probe = None;
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => self.probe = None,
+            }
+        }
+        let due =
+            self.reachable.is_none() || (now - self.probed_at).as_secs_f32() >= PROBE_INTERVAL;
+        if self.probe.is_none() && due {
+            self.probed_at = now;
+            let (tx, rx) = channel();
+            std::thread::spawn(move || {
+                let _result = tx.send(TcpStream::connect_timeout(&address, PROBE_TIMEOUT).is_ok());
+            });
+            self.probe = Some(rx);
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ImportError> {
+        self.errors.iter()
+    }
+
+    /// Returns the errors that appeared since the last call.
+    pub fn take_new_errors(&mut self) -> impl Iterator<Item = &ImportError> {
+        let count = std::mem::take(&mut self.new_errors);
+        self.errors.iter().rev().take(count)
+    }
+
+    #[profiling::function]
+    pub fn update(&mut self, asset_resource: &AssetResource) {
+        let now = Instant::now();
+        if (now - self.last_poll).as_secs_f32() < POLL_INTERVAL {
+            return;
+        }
+        self.last_poll = now;
+        self.update_probe(now);
+
+        let loader = asset_resource.loader();
+        let mut loading = vec![];
+        let mut unresolved = 0;
+        let mut loaded = 0;
+        for handle in loader.get_active_loads() {
+            let path = || {
+                loader
+                    .get_load_info(handle)
+                    .and_then(|info| info.path.or(info.file_name))
+                    .unwrap_or_else(|| "???".to_string())
+            };
+            match loader.get_load_status(handle) {
+                LoadStatus::Unresolved => unresolved += 1,
+                LoadStatus::Loading => loading.push(path()),
+                LoadStatus::Loaded => loaded += 1,
+                LoadStatus::Error(err) => {
+                    let path = path();
+                    let message = err.to_string();
+                    if !self
+                        .errors
+                        .iter()
+                        .any(|e| e.path == path && e.message == message)
+                    {
+                        if self.errors.len() == MAX_ERRORS {
+                            self.errors.pop_front();
+                        }
+                        self.errors.push_back(ImportError { path, message });
+                        self.new_errors = (self.new_errors + 1).min(MAX_ERRORS);
+                    }
+                }
+                _ => {}
+            }
+        }
+        loading.sort();
+
+        let settled = unresolved == 0 && loading.is_empty();
+        let stalled = (now - self.progress_at).as_secs_f32() >= DAEMON_STALL_TIMEOUT;
+        if !self.expected.is_empty() && (settled || stalled) {
+            let conflicts: Vec<_> = self
+                .expected
+                .iter()
+                .filter(|(_, handle)| {
+                    !matches!(loader.get_load_status(*handle), LoadStatus::Loaded)
+                })
+                .map(|(path, _)| path.clone())
+                .collect();
+            for path in &conflicts {
+                log::error!("Asset {} is missing from the new source", path);
+            }
+            log::info!(
+                "Asset source switched, {} of {} assets resolved",
+                self.expected.len() - conflicts.len(),
+                self.expected.len()
+            );
+            self.conflicts = conflicts;
+            self.release_expected(asset_resource);
+        }
+
+        if loaded != self.loaded || loading != self.loading || unresolved != self.unresolved {
+            self.progress_at = now;
+        }
+        self.loading = loading;
+        self.unresolved = unresolved;
+        self.loaded = loaded;
+    }
+
+    fn source_file(&self, path: &str) -> Result<PathBuf, String> {
+        match &self.source {
+            AssetStatusSource::Packfile(_) => Err(tr!("assets.reimport.packfile")),
+            AssetStatusSource::Daemon { asset_dirs, .. } => {
+                let path = Path::new(path);
+                if path.is_file() {
+                    return Ok(path.to_path_buf());
+                }
+                asset_dirs
+                    .iter()
+                    .map(|dir| dir.join(path))
+                    .find(|p| p.is_file())
+                    .ok_or_else(|| tr!("assets.reimport.not_found", path = path.display()))
+            }
+        }
+    }
+
+    /// Bumps the modification time of the source's `.meta` file, so the daemon's file watcher
+    /// imports the pair again. The source itself is left alone.
+    pub fn reimport(&mut self) -> Result<PathBuf, String> {
+        let path = self.source_file(self.reimport_path.trim())?;
+        let mut meta_path = path.clone().into_os_string();
+        meta_path.push(".meta");
+        let meta_path = PathBuf::from(meta_path);
+        OpenOptions::new()
+            .write(true)
+            .open(&meta_path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .map_err(|err| {
+                tr!(
+                    "assets.reimport.touch_failed",
+                    path = meta_path.display(),
+                    error = err
+                )
+            })?;
+        log::info!("Reimporting {}", path.display());
+        Ok(path)
+    }
+
+    fn source_ui(&mut self, ui: &mut egui::Ui, file_dialogs: &mut FileDialogResource) {
+        egui::CollapsingHeader::new(tr!("assets.source"))
+            .id_source("switch source")
+            .default_open(false)
+            .show(ui, |ui| {
+                if let Some(path) = file_dialogs.take(DialogPurpose::Packfile) {
+                    self.pack_path = path.display().to_string();
+                }
+                ui.label(tr!("assets.source.hint"));
+                let packs: Vec<_> = std::fs::read_dir(".")
+                    .map(|dir| {
+                        dir.filter_map(|entry| entry.ok())
+                            .map(|entry| entry.path())
+                            .filter(|path| path.extension().map_or(false, |ext| ext == "pack"))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                for pack in packs {
+                    let name = pack.display().to_string();
+                    if ui.selectable_label(self.pack_path == name, &name).clicked() {
+                        self.pack_path = name;
+                    }
+                }
+                ui.horizontal(|ui| {
+                    ui.label(tr!("assets.packfile.label"));
+                    ui.text_edit_singleline(&mut self.pack_path);
+                    if ui.button(tr!("assets.browse")).clicked() {
+                        file_dialogs.open(DialogPurpose::Packfile, None);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let mut result = None;
+                    if ui.button(tr!("assets.packfile.mount")).clicked() {
+                        let path = PathBuf::from(self.pack_path.trim());
+                        result = Some(self.request_packfile(&path));
+                    }
+                    if ui.button(tr!("assets.daemon.use")).clicked() {
+                        result = Some(self.request_daemon());
+                    }
+                    match result {
+                        Some(Ok(())) => self.source_result = tr!("console.switching"),
+                        Some(Err(err)) => {
+                            log::error!("{}", err);
+                            self.source_result = err;
+                        }
+                        None => {}
+                    }
+                });
+                if !self.source_result.is_empty() {
+                    ui.label(&self.source_result);
+                }
+                if !self.expected.is_empty() {
+                    ui.label(tr!("assets.source.resolving", count = self.expected.len()));
+                }
+                if !self.conflicts.is_empty() {
+                    ui.label(tr!("assets.source.missing", count = self.conflicts.len()));
+                    for path in &self.conflicts {
+                        ui.label(format!("- {}", path));
+                    }
+                }
+            });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, file_dialogs: &mut FileDialogResource) {
+        match &self.source {
+            AssetStatusSource::Packfile(path) => {
+                ui.label(tr!("assets.packfile.current", path = path.display()));
+            }
+            AssetStatusSource::Daemon {
+                address, external, ..
+            } => {
+                ui.label(tr!(
+                    "assets.daemon.current",
+                    address = address,
+                    kind = if *external {
+                        tr!("assets.daemon.external")
+                    } else {
+                        tr!("assets.daemon.in_process")
+                    }
+                ));
+                ui.label(tr!(
+                    "assets.daemon.connection",
+                    connection = format!("{:?}", self.connection())
+                ));
+            }
+        }
+        self.source_ui(ui, file_dialogs);
+        ui.label(tr!(
+            "assets.counts",
+            loaded = self.loaded,
+            unresolved = self.unresolved,
+            building = self.loading.len()
+        ));
+
+        egui::CollapsingHeader::new(tr!("assets.building", count = self.loading.len()))
+            .id_source("building")
+            .default_open(false)
+            .show(ui, |ui| {
+                for path in &self.loading {
+                    ui.label(path);
+                }
+            });
+
+        egui::CollapsingHeader::new(tr!("assets.errors", count = self.errors.len()))
+            .id_source("import errors")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.)
+                    .show(ui, |ui| {
+                        let mut selected = None;
+                        for error in self.errors.iter().rev() {
+                            let label = format!("{}: {}", error.path, error.message);
+                            if ui
+                                .selectable_label(self.reimport_path == error.path, label)
+                                .clicked()
+                            {
+                                selected = Some(error.path.clone());
+                            }
+                        }
+                        if let Some(path) = selected {
+                            self.reimport_path = path;
+                        }
+                    });
+                if !self.errors.is_empty() && ui.button(tr!("assets.errors.clear")).clicked() {
+                    self.errors.clear();
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.label(tr!("assets.reimport.path"));
+            ui.text_edit_singleline(&mut self.reimport_path);
+        });
+        if ui.button(tr!("assets.reimport")).clicked() {
+            self.reimport_result = match self.reimport() {
+                Ok(path) => tr!("assets.reimport.started", path = path.display()),
+                Err(err) => {
+                    log::error!("{}", err);
+                    err
+                }
+            };
+        }
+        if !self.reimport_result.is_empty() {
+            ui.label(&self.reimport_result);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use rafx::api::RafxSamplerDef;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+// resolution the terrain textures are authored at
+const SOURCE_RESOLUTION: u32 = 2048;
+
+/// Quality of the PBR material textures. `PbrMaterialImporter` applies it when the materials are
+/// imported: larger textures are downsized to `max_resolution`, so their top mips are never
+/// loaded, and the sampler settings are baked into the material instances.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct TextureQuality {
+    pub max_resolution: u32,
+    pub anisotropy: f32,
+    pub mip_bias: f32,
+}
+
+impl Default for TextureQuality {
+    fn default() -> Self {
+        Self {
+            max_resolution: SOURCE_RESOLUTION,
+            anisotropy: 16.,
+            mip_bias: 0.,
+        }
+    }
+}
+
+impl TextureQuality {
+    pub const RESOLUTIONS: [u32; 5] = [128, 256, 512, 1024, 2048];
+
+    pub fn sampler_def(&self) -> RafxSamplerDef {
+        RafxSamplerDef {
+            mip_lod_bias: self.mip_bias,
+            max_anisotropy: self.anisotropy.max(1.),
+            ..Default::default()
+        }
+    }
+
+    /// Mixed into the importer version, so the daemon reimports the materials when it starts with
+    /// a different quality than the cached imports were made with.
+    pub fn import_key(&self) -> u32 {
+        let mut hasher = DefaultHasher::new();
+        (
+            self.max_resolution,
+            self.anisotropy.to_bits(),
+            self.mip_bias.to_bits(),
+        )
+            .hash(&mut hasher);
+        hasher.finish() as u32
+    }
+}
+
+/// Texture quality setting. The in-process daemon imports the materials with the quality the
+/// settings had at startup, changes are saved and take effect on the next start. A packfile keeps
+/// the quality it was packed with.
+pub struct TextureQualityResource {
+    pub quality: TextureQuality,
+    /// The quality at startup, the one the materials are imported with.
+    running: TextureQuality,
+    daemon: bool,
+}
+
+impl TextureQualityResource {
+    /// `daemon` tells if the assets come from the in-process daemon, the only one it applies to.
+    pub fn new(quality: TextureQuality, daemon: bool) -> Self {
+        Self {
+            quality,
+            running: quality,
+            daemon,
+        }
+    }
+
+    pub fn running(&self) -> TextureQuality {
+        self.running
+    }
+
+    /// If the assets come from the in-process daemon, the one importing at `running`.
+    pub fn applies(&self) -> bool {
+        self.daemon
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("texture_quality"))
+            .id_source("texture quality")
+            .default_open(false)
+            .show(ui, |ui| {
+                let old_quality = self.quality;
+                egui::ComboBox::from_label(tr!("texture_quality.max_resolution"))
+                    .selected_text(format!("{}", self.quality.max_resolution))
+                    .show_ui(ui, |ui| {
+                        for resolution in TextureQuality::RESOLUTIONS {
+                            ui.selectable_value(
+                                &mut self.quality.max_resolution,
+                                resolution,
+                                format!("{}", resolution),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut self.quality.anisotropy, 1.0..=16.)
+                        .text(tr!("texture_quality.anisotropy")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.quality.mip_bias, -2.0..=4.)
+                        .text(tr!("texture_quality.mip_bias")),
+                );
+                if self.quality != old_quality {
+                    let quality = self.quality;
+                    Settings::update(|settings| settings.texture_quality = quality);
+                }
+                if !self.daemon {
+                    ui.label(tr!("texture_quality.daemon_only"));
+                } else if self.quality != self.running {
+                    ui.colored_label(egui::Color32::YELLOW, tr!("texture_quality.restart"));
+                }
+            });
+    }
+}
@@ -10,20 +10,65 @@ use type_uuid::*;
 
 use crate::env::simulation::MaterialVoxel;
 
+/// Gameplay metadata of a tile, optional in `.tile` files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct TileProperties {
+    /// Ground cells covered, centered on the tile. Defaults to the voxel extent.
+    pub footprint: Option<[u32; 2]>,
+    /// Ground units can cross the footprint (roads, rubble).
+    pub walkable: bool,
+    /// Units entering the tile stand on top of it instead of going inside, with more range
+    /// the higher they stand. Ground paths still go around it.
+    pub wall: bool,
+    pub max_health: f32, // hp
+}
+
+impl Default for TileProperties {
+    fn default() -> Self {
+        Self {
+            footprint: None,
+            walkable: false,
+            wall: false,
+            max_health: 100.,
+        }
+    }
+}
+
 #[derive(TypeUuid, Serialize, Deserialize, Debug, Clone)]
 #[uuid = "e0b18b31-dcff-4e31-85dd-2e224bb1d04b"]
 pub struct TileAssetData {
     pub name: String,
+    #[serde(default)]
+    pub properties: TileProperties,
     pub palette: Vec<String>,
     pub voxels: Vec<Vec<String>>,
 }
 
 pub struct TileAssetInner {
     pub name: String,
+    pub properties: TileProperties,
     pub palette: Vec<String>,
     pub voxels: Array3x1<MaterialVoxel>,
 }
 
+impl TileAssetInner {
+    pub fn footprint(&self) -> [u32; 2] {
+        self.properties.footprint.unwrap_or_else(|| {
+            let shape = self.voxels.extent().shape;
+            [shape.x() as u32, shape.y() as u32]
+        })
+    }
+
+    /// Properties with the footprint filled in.
+    pub fn resolved_properties(&self) -> TileProperties {
+        TileProperties {
+            footprint: Some(self.footprint()),
+            ..self.properties
+        }
+    }
+}
+
 #[derive(TypeUuid, Clone)]
 #[uuid = "76097c2c-4d34-4957-bae1-8369f4a1d856"]
 pub struct TileAsset {
@@ -104,6 +149,7 @@ impl DefaultAssetTypeLoadHandler<TileAssetData, TileAsset> for TileLoadHandler {
         Ok(TileAsset {
             inner: Arc::new(TileAssetInner {
                 name: asset_data.name,
+                properties: asset_data.properties,
                 palette: asset_data.palette,
                 voxels,
             }),
@@ -1,9 +1,9 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
 use building_blocks::prelude::*;
 
 use crate::{
-    assets::tile::TileAssetData,
+    assets::tile::{TileAssetData, TileProperties},
     env::simulation::{MaterialVoxel, Universe},
 };
 
@@ -12,7 +12,9 @@ pub struct TileExporter;
 
 impl TileExporter {
     pub fn export(
+        path: &Path,
         name: String,
+        properties: TileProperties,
         voxels: Array3x1<MaterialVoxel>,
         universe: &Universe,
     ) -> Option<()> {
@@ -39,12 +41,13 @@ impl TileExporter {
         }
         let asset_data = TileAssetData {
             name: name.clone(),
+            properties,
             palette,
             voxels: voxels_str,
         };
         let asset_string =
             ron::ser::to_string_pretty::<TileAssetData>(&asset_data, Default::default()).ok()?;
-        std::fs::write(Self::get_tile_path(&name, true), asset_string).ok()
+        std::fs::write(path, asset_string).ok()
     }
 
     pub fn get_tile_path(tile_name: &str, include_root_dir: bool) -> String {
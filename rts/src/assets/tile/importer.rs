@@ -44,7 +44,7 @@ impl Importer for EnvTileImporter {
     where
         Self: Sized,
     {
-        1
+        2
     }
 
     fn version(&self) -> u32 {
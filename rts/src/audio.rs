@@ -0,0 +1,292 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::RTSCamera, env::simulation::Universe, settings::Settings};
+
+// sounds closer than this to the listener play at full volume
+const REFERENCE_DISTANCE: f32 = 20.; // m
+const MAX_DISTANCE: f32 = 200.; // m
+const OCCLUDED_GAIN: f32 = 0.4;
+// a hit closer than this to the source doesn't count, it is the ground the source stands on
+const OCCLUSION_MARGIN: f32 = 2.; // m
+const OCCLUSION_INTERVAL: f32 = 0.1; // s
+const OCCLUSION_FADE: f32 = 5.; // per s
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SoundCategory {
+    Ui,
+    Effects,
+    Ambient,
+    Music,
+}
+
+impl SoundCategory {
+    pub const ALL: [SoundCategory; 4] = [
+        SoundCategory::Ui,
+        SoundCategory::Effects,
+        SoundCategory::Ambient,
+        SoundCategory::Music,
+    ];
+
+    /// Translated bus name.
+    pub fn label(self) -> String {
+        match self {
+            SoundCategory::Ui => tr!("settings.audio.ui"),
+            SoundCategory::Effects => tr!("settings.audio.effects"),
+            SoundCategory::Ambient => tr!("settings.audio.ambient"),
+            SoundCategory::Music => tr!("settings.audio.music"),
+        }
+    }
+}
+
+/// Volume of each bus, multiplied by `master`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub ui: f32,
+    pub effects: f32,
+    pub ambient: f32,
+    pub music: f32,
+    /// How far sounds at the screen edges pan, 0 for mono.
+    pub stereo_width: f32,
+    /// Muffle sounds behind terrain.
+    pub occlusion: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.,
+            ui: 1.,
+            effects: 1.,
+            ambient: 0.7,
+            music: 0.6,
+            stereo_width: 1.,
+            occlusion: true,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn bus(&self, category: SoundCategory) -> f32 {
+        match category {
+            SoundCategory::Ui => self.ui,
+            SoundCategory::Effects => self.effects,
+            SoundCategory::Ambient => self.ambient,
+            SoundCategory::Music => self.music,
+        }
+    }
+
+    fn bus_mut(&mut self, category: SoundCategory) -> &mut f32 {
+        match category {
+            SoundCategory::Ui => &mut self.ui,
+            SoundCategory::Effects => &mut self.effects,
+            SoundCategory::Ambient => &mut self.ambient,
+            SoundCategory::Music => &mut self.music,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct VoiceId(u64);
+
+/// A playing sound and how it should be mixed this frame.
+pub struct Voice {
+    pub id: VoiceId,
+    pub sound: String,
+    pub category: SoundCategory,
+    /// World position, `None` for sounds without one (UI, music).
+    pub position: Option<Vec3>,
+    /// Seconds left, `None` for loops that play until stopped.
+    pub remaining: Option<f32>,
+    pub gain: f32,
+    /// -1 is fully left and 1 fully right.
+    pub pan: f32,
+    /// 0 when the source is in sight and 1 when behind terrain, for a low-pass filter.
+    pub muffle: f32,
+    occluded: bool,
+    next_occlusion_check: f32, // s
+}
+
+/// Mixing state of the game sounds. The listener follows the RTS camera: distance attenuation
+/// is measured from the look-at point, panning follows the position of the source on screen,
+/// and sources hidden from the eye by terrain are muffled. The gain, pan and muffle of each
+/// voice are what an output backend plays them with.
+pub struct AudioResource {
+    pub settings: AudioSettings,
+    voices: Vec<Voice>,
+    next_id: u64,
+}
+
+impl AudioResource {
+    pub fn new(settings: AudioSettings) -> Self {
+        Self {
+            settings,
+            voices: vec![],
+            next_id: 0,
+        }
+    }
+
+    /// Starts `sound`, at `position` if it has one, for `duration` seconds or until stopped.
+    pub fn play(
+        &mut self,
+        sound: &str,
+        category: SoundCategory,
+        position: Option<Vec3>,
+        duration: Option<f32>,
+    ) -> VoiceId {
+        let id = VoiceId(self.next_id);
+        self.next_id += 1;
+        log::trace!("Play {} ({:?}) at {:?}", sound, category, position);
+        self.voices.push(Voice {
+            id,
+            sound: sound.to_string(),
+            category,
+            position,
+            remaining: duration,
+            gain: 0.,
+            pan: 0.,
+            muffle: 0.,
+            occluded: false,
+            next_occlusion_check: 0.,
+        });
+        id
+    }
+
+    pub fn stop(&mut self, id: VoiceId) {
+        self.voices.retain(|voice| voice.id != id);
+    }
+
+    pub fn voices(&self) -> impl Iterator<Item = &Voice> {
+        self.voices.iter()
+    }
+
+    /// Call after the camera update, with unscaled time.
+    pub fn update(&mut self, dt: f32, camera: &RTSCamera, universe: &Universe) {
+        for voice in &mut self.voices {
+            if let Some(remaining) = &mut voice.remaining {
+                *remaining -= dt;
+            }
+        }
+        self.voices
+            .retain(|voice| voice.remaining.map_or(true, |remaining| remaining > 0.));
+
+        let eye = camera.eye();
+        let view_proj = camera.view_proj();
+        let settings = self.settings;
+        for voice in &mut self.voices {
+            let bus = settings.master * settings.bus(voice.category);
+            let position = match voice.position {
+                Some(position) => position,
+                None => {
+                    voice.gain = bus;
+                    voice.pan = 0.;
+                    voice.muffle = 0.;
+                    continue;
+                }
+            };
+
+            let distance = position.distance(camera.look_at);
+            let attenuation = if distance > MAX_DISTANCE {
+                0.
+            } else {
+                REFERENCE_DISTANCE / distance.max(REFERENCE_DISTANCE)
+            };
+
+            let clip = view_proj * position.extend(1.);
+            let x = if clip.w > 0. {
+                clip.x / clip.w
+            } else {
+                // behind the camera, pan by the side it is on
+                (position - eye).dot(camera.right()).signum()
+            };
+            voice.pan = x.max(-1.).min(1.) * settings.stereo_width;
+
+            voice.next_occlusion_check -= dt;
+            if voice.next_occlusion_check <= 0. {
+                voice.next_occlusion_check = OCCLUSION_INTERVAL;
+                voice.occluded = settings.occlusion
+                    && attenuation > 0.
+                    && Self::is_occluded(universe, eye, position);
+            }
+            let target = if voice.occluded { 1. } else { 0. };
+            let step = OCCLUSION_FADE * dt;
+            voice.muffle += (target - voice.muffle).max(-step).min(step);
+
+            let occlusion = 1. - (1. - OCCLUDED_GAIN) * voice.muffle;
+            voice.gain = bus * attenuation * occlusion;
+        }
+    }
+
+    fn is_occluded(universe: &Universe, eye: Vec3, position: Vec3) -> bool {
+        let to_source = position - eye;
+        let distance = to_source.length();
+        if distance <= OCCLUSION_MARGIN {
+            return false;
+        }
+        universe.ray_cast(eye, to_source).map_or(false, |result| {
+            let hit = Vec3::new(
+                result.hit.x() as f32,
+                result.hit.y() as f32,
+                result.hit.z() as f32,
+            ) + Vec3::splat(0.5);
+            hit.distance(eye) < distance - OCCLUSION_MARGIN
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.audio"))
+            .id_source("audio")
+            .default_open(false)
+            .show(ui, |ui| {
+                let settings = &mut self.settings;
+                let mut changed = ui
+                    .add(
+                        egui::Slider::new(&mut settings.master, 0.0..=1.)
+                            .text(tr!("settings.audio.master")),
+                    )
+                    .changed();
+                for category in SoundCategory::ALL {
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(settings.bus_mut(category), 0.0..=1.)
+                                .text(category.label()),
+                        )
+                        .changed();
+                }
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut settings.stereo_width, 0.0..=1.)
+                            .text(tr!("settings.audio.stereo_width")),
+                    )
+                    .changed();
+                changed |= ui
+                    .checkbox(&mut settings.occlusion, tr!("settings.audio.occlusion"))
+                    .changed();
+                if changed {
+                    let settings = *settings;
+                    Settings::update(|s| s.audio = settings);
+                }
+            });
+    }
+
+    /// The voices playing and how they are mixed, for the debug panel.
+    pub fn debug_ui(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("audio.voices", count = self.voices.len()))
+            .id_source("audio voices")
+            .default_open(false)
+            .show(ui, |ui| {
+                for voice in &self.voices {
+                    ui.label(tr!(
+                        "audio.voice",
+                        sound = voice.sound,
+                        category = voice.category.label(),
+                        gain = format!("{:.2}", voice.gain),
+                        pan = format!("{:.2}", voice.pan),
+                        muffle = format!("{:.2}", voice.muffle)
+                    ));
+                }
+            });
+    }
+}
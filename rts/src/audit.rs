@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    panic::Location,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use lazy_static::lazy_static;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum AuditedHandle {
+    RenderObject,
+    VisibilityObject,
+    DynMesh,
+}
+
+impl Display for AuditedHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            AuditedHandle::RenderObject => write!(f, "RenderObjectHandle"),
+            AuditedHandle::VisibilityObject => write!(f, "VisibilityObjectArc"),
+            AuditedHandle::DynMesh => write!(f, "DynMeshHandle"),
+        }
+    }
+}
+
+type AuditKey = (AuditedHandle, &'static Location<'static>);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref LIVE: Mutex<HashMap<AuditKey, i64>> = Mutex::new(HashMap::new());
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Tokens created while disabled are never counted, so toggling doesn't skew the numbers.
+pub fn set_enabled(enabled: bool) {
+    if enabled != is_enabled() {
+        log::info!(
+            "Handle audit {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn add(key: AuditKey, delta: i64) {
+    let mut live = LIVE.lock().unwrap();
+    let count = live.entry(key).or_insert(0);
+    *count += delta;
+    if *count == 0 {
+        live.remove(&key);
+    }
+}
+
+/// Counts a handle as alive, tagged with the source location that registered it, until the
+/// token is dropped. Keep it next to the handle it stands for.
+pub struct AuditToken {
+    key: Option<AuditKey>,
+}
+
+impl AuditToken {
+    #[track_caller]
+    pub fn new(kind: AuditedHandle) -> Self {
+        let key = if is_enabled() {
+            let key = (kind, Location::caller());
+            add(key, 1);
+            Some(key)
+        } else {
+            None
+        };
+        Self { key }
+    }
+}
+
+impl Drop for AuditToken {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            add(key, -1);
+        }
+    }
+}
+
+/// Audit tokens of the handles owned by an entity, dropped together with it.
+pub struct AuditComponent(pub Vec<AuditToken>);
+
+/// A handle kept together with its token, so it counts exactly as long as it lives.
+pub struct Audited<T> {
+    handle: T,
+    _token: AuditToken,
+}
+
+impl<T> Audited<T> {
+    #[track_caller]
+    pub fn new(handle: T, kind: AuditedHandle) -> Self {
+        Self {
+            handle,
+            _token: AuditToken::new(kind),
+        }
+    }
+
+    pub fn handle(&self) -> &T {
+        &self.handle
+    }
+}
+
+pub fn live_counts() -> Vec<(AuditedHandle, String, i64)> {
+    let live = LIVE.lock().unwrap();
+    let mut counts: Vec<_> = live
+        .iter()
+        .map(|((kind, location), count)| (*kind, location.to_string(), *count))
+        .collect();
+    counts.sort_by(|a, b| a.1.cmp(&b.1));
+    counts
+}
+
+/// Logs every handle still alive and returns how many there are. Meant to run after a scene
+/// was cleaned up, when nothing registered by it should be left.
+pub fn report_leaks(context: &str) -> i64 {
+    if !is_enabled() {
+        return 0;
+    }
+    let counts = live_counts();
+    let total = counts.iter().map(|(_, _, count)| count).sum();
+    if total == 0 {
+        log::info!("Handle audit ({}): no live handles", context);
+    } else {
+        log::error!("Handle audit ({}): {} live handles", context, total);
+        for (kind, location, count) in counts {
+            log::error!("  {} x{} registered at {}", kind, count, location);
+        }
+    }
+    total
+}
+
+pub fn ui(ui: &mut egui::Ui) {
+    let counts = live_counts();
+    if counts.is_empty() {
+        ui.label(tr!("audit.empty"));
+    }
+    egui::Grid::new("handle_audit")
+        .striped(true)
+        .show(ui, |ui| {
+            for (kind, location, count) in counts {
+                ui.label(format!("{}", kind));
+                ui.label(location);
+                ui.label(format!("{}", count));
+                ui.end_row();
+            }
+        });
+}
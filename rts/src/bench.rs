@@ -0,0 +1,348 @@
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
+
+use building_blocks::{
+    core::prelude::*,
+    mesh::{
+        greedy_quads, padded_greedy_quads_chunk_extent, surface_nets, GreedyQuadsBuffer,
+        SurfaceNetsBuffer, RIGHT_HANDED_Y_UP_CONFIG,
+    },
+    storage::prelude::*,
+};
+use glam::Vec3;
+use legion::Resources;
+use rafx::base::Instant;
+
+pub use crate::env::simulation::{ChunkGeometry, MaterialVoxel, TerrainMeshStyle};
+use crate::{
+    assets::pbr_material::PbrMaterialAsset,
+    env::{
+        noise::{NoiseFn2D, NoiseParams},
+        simulation::{Simulation, Universe},
+    },
+    features::dyn_mesh::{DynMeshData, DynMeshHandle, DynMeshManager},
+};
+
+pub const CHUNK_SIZE: i32 = 16;
+// patterns cycle through this many materials, all of them in the default palette
+pub const PATTERN_MATERIALS: u16 = 4;
+const MESHER_ITERATIONS: usize = 200;
+pub const UPLOAD_MESHES: usize = 64;
+
+/// Voxel layouts the mesher benchmarks run on, from the cheapest to the worst case.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChunkPattern {
+    /// A few solid layers of one material.
+    Flat,
+    /// One layer of two materials alternating in x and y.
+    Checkers,
+    /// Noise heightfield, materials in altitude bands.
+    Noise,
+    /// Every other voxel solid in all three axes, solid ones cycling through the materials.
+    /// Nothing can be merged, so every face becomes a quad.
+    Alternating,
+}
+
+impl ChunkPattern {
+    pub const ALL: [ChunkPattern; 4] = [
+        ChunkPattern::Flat,
+        ChunkPattern::Checkers,
+        ChunkPattern::Noise,
+        ChunkPattern::Alternating,
+    ];
+}
+
+impl Display for ChunkPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            ChunkPattern::Flat => write!(f, "flat"),
+            ChunkPattern::Checkers => write!(f, "checkers"),
+            ChunkPattern::Noise => write!(f, "noise"),
+            ChunkPattern::Alternating => write!(f, "alternating"),
+        }
+    }
+}
+
+/// The voxels of a chunk at the origin, padded the way the mesher jobs pad them.
+pub fn chunk_voxels(
+    pattern: ChunkPattern,
+    mesh_style: TerrainMeshStyle,
+) -> Array3x1<MaterialVoxel> {
+    let chunk_extent = Extent3i::from_min_and_shape(Point3i::ZERO, Point3i::fill(CHUNK_SIZE));
+    let padded_extent = match mesh_style {
+        TerrainMeshStyle::Cubic => padded_greedy_quads_chunk_extent(&chunk_extent),
+        TerrainMeshStyle::Smooth => chunk_extent.padded(2),
+    };
+    let material = |idx: i32| {
+        MaterialVoxel::from_material_index(idx.rem_euclid(PATTERN_MATERIALS as i32) as u16 + 1)
+    };
+    let noise_fn = NoiseParams {
+        amplitude: 6.,
+        bias: 8.,
+        scale: 64.,
+        ..Default::default()
+    }
+    .build();
+    Array3x1::fill_with(padded_extent, |p: Point3i| {
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        match pattern {
+            ChunkPattern::Flat if z < 4 => material(0),
+            ChunkPattern::Checkers if z == 0 => material((x + y).rem_euclid(2)),
+            ChunkPattern::Noise => {
+                let height = noise_fn.get_2d(x as f64, y as f64) as i32;
+                if z <= height {
+                    material(z / 4)
+                } else {
+                    MaterialVoxel::empty()
+                }
+            }
+            ChunkPattern::Alternating if (x + y + z).rem_euclid(2) == 0 => material(x + y),
+            _ => MaterialVoxel::empty(),
+        }
+    })
+}
+
+/// Greedy quads plus vertex buffer generation, what a cubic mesher job does minus the
+/// material lookup.
+pub fn mesh_cubic(voxels: &Array3x1<MaterialVoxel>) -> Option<ChunkGeometry> {
+    let extent = voxels.extent().clone();
+    let mut buffer = GreedyQuadsBuffer::new(extent, RIGHT_HANDED_Y_UP_CONFIG.quad_groups());
+    greedy_quads(voxels, &extent, &mut buffer);
+    if buffer.num_quads() == 0 {
+        return None;
+    }
+    Universe::make_chunk_geometry(voxels, &buffer, true, true)
+}
+
+/// Surface nets counterpart of `mesh_cubic`.
+pub fn mesh_smooth(voxels: &Array3x1<MaterialVoxel>) -> Option<ChunkGeometry> {
+    let sdf = Universe::smooth_sdf(voxels);
+    let mut buffer = SurfaceNetsBuffer::default();
+    surface_nets(&sdf, sdf.extent(), 1.0, false, &mut buffer);
+    if buffer.mesh.indices.is_empty() {
+        return None;
+    }
+    Universe::make_smooth_chunk_geometry(voxels, &buffer)
+}
+
+/// `mesh_cubic` of the voxels flattened to their most common material, how far chunks mesh.
+pub fn mesh_simplified(voxels: Array3x1<MaterialVoxel>) -> Option<ChunkGeometry> {
+    mesh_cubic(&Universe::flatten_materials(voxels))
+}
+
+/// The content hash `ChunkGeometry::into_dyn_mesh_data` computes for deduplication, minus the
+/// materials of the parts.
+pub fn hash_geometry(geometry: &ChunkGeometry) -> u64 {
+    geometry.buffer_hasher(false).finish()
+}
+
+/// `geometry` as mesh data without parts, which the `DynMeshManager` queue and deduplication
+/// never look at, so no materials are needed. Copies with different `offset`s get different
+/// content hashes, the same `offset` makes them share one mesh.
+pub fn dyn_mesh_data(geometry: &ChunkGeometry, offset: f32) -> DynMeshData {
+    let mut hasher = geometry.buffer_hasher(false);
+    offset.to_bits().hash(&mut hasher);
+    let origin = geometry.origin + offset * Vec3::X;
+    DynMeshData {
+        mesh_parts: vec![],
+        vertex_full_buffer: Some(geometry.vertex_full_buffer.clone()),
+        vertex_position_buffer: Some(geometry.vertex_position_buffer.clone()),
+        index_buffer: Some(geometry.index_buffer.clone()),
+        visible_bounds: geometry.visible_bounds.clone(),
+        content_hash: Some(hasher.finish()),
+        world_position: Some(origin),
+        vertex_format: geometry.vertex_format,
+        origin,
+    }
+}
+
+/// Adds `meshes` to a `DynMeshManager` without a buffer uploader: the CPU side of the upload
+/// path, validation, deduplication and queueing. The GPU side runs in `--bench-mode`.
+pub fn queue_dyn_meshes(meshes: Vec<DynMeshData>) -> DynMeshManager {
+    let mut dyn_mesh_manager = DynMeshManager::new();
+    for mesh in meshes {
+        if let Err(err) = dyn_mesh_manager.add_dyn_mesh(mesh) {
+            log::error!("Bench queue failed: {}", err);
+        }
+    }
+    dyn_mesh_manager
+}
+
+pub struct MesherTiming {
+    pub pattern: ChunkPattern,
+    pub mesh_style: TerrainMeshStyle,
+    pub vertices: usize,
+    pub vertex_bytes: usize, // both vertex buffers
+    pub parts: usize,
+    pub mesh_time: f64, // µs per chunk
+}
+
+pub fn time_mesher(
+    pattern: ChunkPattern,
+    mesh_style: TerrainMeshStyle,
+    iterations: usize,
+) -> MesherTiming {
+    let voxels = chunk_voxels(pattern, mesh_style);
+    let mesh = |voxels: &Array3x1<MaterialVoxel>| match mesh_style {
+        TerrainMeshStyle::Cubic => mesh_cubic(voxels),
+        TerrainMeshStyle::Smooth => mesh_smooth(voxels),
+    };
+    let geometry = mesh(&voxels);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        mesh(&voxels);
+    }
+    MesherTiming {
+        pattern,
+        mesh_style,
+        vertices: geometry.as_ref().map_or(0, |g| g.num_vertices()),
+        vertex_bytes: geometry.as_ref().map_or(0, |g| {
+            g.vertex_full_buffer.len() + g.vertex_position_buffer.len()
+        }),
+        parts: geometry.as_ref().map_or(0, |g| g.parts.len()),
+        mesh_time: (Instant::now() - start).as_micros() as f64 / iterations.max(1) as f64,
+    }
+}
+
+enum BenchStage {
+    WaitingForMaterials,
+    Uploading {
+        pattern: usize,
+        handles: Vec<DynMeshHandle>,
+        start: Instant,
+        frames: u32,
+    },
+    Done,
+}
+
+/// Drives the `--bench-mode` run: times the mesher on every pattern and style, then pushes
+/// `UPLOAD_MESHES` copies of each cubic pattern through the `DynMeshManager`, one pattern at a
+/// time, and measures how long the uploads take to complete. Everything is logged and the app
+/// exits. Combined with the `rafx-empty` feature this runs without a GPU.
+pub struct BenchModeState {
+    stage: BenchStage,
+    report: Vec<String>,
+}
+
+impl BenchModeState {
+    pub fn new() -> Self {
+        Self {
+            stage: BenchStage::WaitingForMaterials,
+            report: vec![],
+        }
+    }
+
+    fn run_mesher() -> Vec<String> {
+        let mut report = vec![];
+        for mesh_style in [TerrainMeshStyle::Cubic, TerrainMeshStyle::Smooth] {
+            for pattern in ChunkPattern::ALL {
+                let timing = time_mesher(pattern, mesh_style, MESHER_ITERATIONS);
+                report.push(format!(
+                    "mesh {:?} {}: {:.1}µs/chunk ({} vertices, {} KiB, {} parts)",
+                    timing.mesh_style,
+                    timing.pattern,
+                    timing.mesh_time,
+                    timing.vertices,
+                    timing.vertex_bytes / 1024,
+                    timing.parts
+                ));
+            }
+        }
+        report
+    }
+
+    fn start_upload(
+        pattern: usize,
+        dyn_mesh_manager: &mut DynMeshManager,
+        materials: &[PbrMaterialAsset],
+    ) -> BenchStage {
+        let voxels = chunk_voxels(ChunkPattern::ALL[pattern], TerrainMeshStyle::Cubic);
+        let mut handles = vec![];
+        if let Some(geometry) = mesh_cubic(&voxels) {
+            for _ in 0..UPLOAD_MESHES {
+                let mesh = match geometry.clone().into_dyn_mesh_data(materials, false) {
+                    Some(mesh) => mesh,
+                    None => break,
+                };
+                match dyn_mesh_manager.add_dyn_mesh(mesh) {
+                    Ok(handle) => handles.push(handle),
+                    Err(err) => log::error!("Bench upload failed: {}", err),
+                }
+            }
+        }
+        BenchStage::Uploading {
+            pattern,
+            handles,
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    /// Call once per frame after the `DynMeshManager` update. Returns true when finished.
+    pub fn update(&mut self, resources: &Resources, simulation: &mut Simulation) -> bool {
+        let mut dyn_mesh_manager = resources.get_mut::<DynMeshManager>().unwrap();
+        let next = match &mut self.stage {
+            BenchStage::WaitingForMaterials => {
+                let materials = simulation.universe().get_loaded_materials(resources);
+                match materials {
+                    Some(materials) => {
+                        self.report.extend(Self::run_mesher());
+                        Some(Self::start_upload(0, &mut dyn_mesh_manager, &materials))
+                    }
+                    None => None,
+                }
+            }
+            BenchStage::Uploading {
+                pattern,
+                handles,
+                start,
+                frames,
+            } => {
+                *frames += 1;
+                if handles
+                    .iter()
+                    .all(|handle| dyn_mesh_manager.is_upload_finished(handle))
+                {
+                    let elapsed = (Instant::now() - *start).as_secs_f64() * 1000.;
+                    self.report.push(format!(
+                        "upload {}: {} meshes in {:.1}ms ({} frames)",
+                        ChunkPattern::ALL[*pattern],
+                        handles.len(),
+                        elapsed,
+                        frames
+                    ));
+                    let pattern = *pattern + 1;
+                    if pattern < ChunkPattern::ALL.len() {
+                        let materials = simulation
+                            .universe()
+                            .get_loaded_materials(resources)
+                            .unwrap_or_default();
+                        Some(Self::start_upload(
+                            pattern,
+                            &mut dyn_mesh_manager,
+                            &materials,
+                        ))
+                    } else {
+                        Some(BenchStage::Done)
+                    }
+                } else {
+                    None
+                }
+            }
+            BenchStage::Done => return true,
+        };
+        if let Some(next) = next {
+            self.stage = next;
+            if let BenchStage::Done = self.stage {
+                log::info!("Bench mode results:");
+                for line in &self.report {
+                    log::info!("  {}", line);
+                }
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -1,8 +1,11 @@
-use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+use std::{
+    f32::consts::{FRAC_PI_2, FRAC_PI_4, PI},
+    fmt::Display,
+};
 
-use glam::{Mat4, Quat, Vec3, Vec4Swizzles};
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4Swizzles};
 use rafx::{
-    rafx_visibility::{DepthRange, PerspectiveParameters, Projection},
+    rafx_visibility::{DepthRange, OrthographicParameters, PerspectiveParameters, Projection},
     render_features::{
         RenderFeatureFlagMaskBuilder, RenderFeatureMaskBuilder, RenderPhaseMaskBuilder,
         RenderViewDepthRange,
@@ -29,19 +32,148 @@ use rafx_plugins::{
         UiRenderPhase, WireframeRenderPhase,
     },
 };
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "physics")]
+use crate::env::physics::{PhysicsRayHit, PhysicsResource};
 use crate::{
     env::simulation::{RayCastResult, Universe},
     features::dyn_mesh::{
         DynMeshNoShadowsRenderFeatureFlag, DynMeshRenderFeature, DynMeshUnlitRenderFeatureFlag,
         DynMeshUntexturedRenderFeatureFlag, DynMeshWireframeRenderFeatureFlag,
     },
-    input::{InputResource, KeyboardKey},
+    input::{InputResource, KeyboardKey, MouseButton},
+    picking::PickRay,
+    settings::Settings,
     time::TimeState,
     ui::UiState,
+    zoom_scaling::ZoomScalingSettings,
     RenderOptions,
 };
 
+const DRAG_YAW_SPEED: f32 = 0.005; // rad per pixel
+
+// true isometric, the view is atan(1/sqrt(2)) above the horizon
+const ISOMETRIC_PITCH: f32 = 0.9553166; // rad
+
+// the orthographic eye sits this far behind the usual one, so hills in front aren't clipped
+const ISOMETRIC_BACKOFF: f32 = 64.; // m
+const ROTATION_SNAP: f32 = FRAC_PI_4; // rad
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraProjection {
+    Perspective,
+    /// Orthographic at the isometric angle, zoomed by the look-at distance. Yaw stays free.
+    /// Only a view mode; there is no offscreen export since the renderer can't read frames back.
+    Isometric,
+}
+
+/// The keys that turn the camera left and right.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RotationKeys {
+    QE,
+    Brackets,
+    PageUpDown,
+}
+
+impl RotationKeys {
+    pub const ALL: [RotationKeys; 3] = [
+        RotationKeys::QE,
+        RotationKeys::Brackets,
+        RotationKeys::PageUpDown,
+    ];
+
+    fn keys(&self) -> (KeyboardKey, KeyboardKey) {
+        match self {
+            RotationKeys::QE => (KeyboardKey::Q, KeyboardKey::E),
+            RotationKeys::Brackets => (KeyboardKey::LBracket, KeyboardKey::RBracket),
+            RotationKeys::PageUpDown => (KeyboardKey::PageUp, KeyboardKey::PageDown),
+        }
+    }
+}
+
+impl Display for RotationKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            RotationKeys::QE => write!(f, "Q / E"),
+            RotationKeys::Brackets => write!(f, "[ / ]"),
+            RotationKeys::PageUpDown => write!(f, "Page Up / Page Down"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RotationPivot {
+    /// The terrain under the middle of the screen.
+    ScreenCenter,
+    /// The terrain under the mouse cursor when the rotation starts.
+    Cursor,
+}
+
+/// How the rotation keys turn the camera.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct CameraRotationSettings {
+    pub keys: RotationKeys,
+    pub pivot: RotationPivot,
+    /// Each key press turns to the next multiple of 45° instead of turning while held.
+    pub snap: bool,
+}
+
+impl Default for CameraRotationSettings {
+    fn default() -> Self {
+        Self {
+            keys: RotationKeys::QE,
+            pivot: RotationPivot::ScreenCenter,
+            snap: false,
+        }
+    }
+}
+
+impl CameraRotationSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.camera_rotation"))
+            .id_source("camera rotation")
+            .default_open(false)
+            .show(ui, |ui| {
+                let old = *self;
+                egui::ComboBox::from_label(tr!("settings.camera_rotation.keys"))
+                    .selected_text(format!("{}", self.keys))
+                    .show_ui(ui, |ui| {
+                        for keys in RotationKeys::ALL {
+                            ui.selectable_value(&mut self.keys, keys, format!("{}", keys));
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label(tr!("settings.camera_rotation.pivot"));
+                    ui.radio_value(
+                        &mut self.pivot,
+                        RotationPivot::ScreenCenter,
+                        tr!("settings.camera_rotation.screen_center"),
+                    );
+                    ui.radio_value(
+                        &mut self.pivot,
+                        RotationPivot::Cursor,
+                        tr!("settings.camera_rotation.cursor"),
+                    );
+                });
+                ui.checkbox(&mut self.snap, tr!("settings.camera_rotation.snap"));
+                if *self != old {
+                    let settings = *self;
+                    Settings::update(|s| s.camera_rotation = settings);
+                }
+            });
+    }
+}
+
+/// View set by the camera director, replaces the RTS controls while present.
+#[derive(Clone, Copy, Debug)]
+pub struct CinematicView {
+    pub eye: Vec3,
+    pub look_at: Vec3,
+    pub fov_y: f32,
+}
+
 #[derive(Clone, Copy)]
 pub struct RTSCamera {
     pub pitch_default: f32,
@@ -62,6 +194,18 @@ pub struct RTSCamera {
     pub win_width: u32,
     pub win_height: u32,
     pub win_scale_factor: f32,
+    pub cinematic: Option<CinematicView>,
+    pub projection_mode: CameraProjection,
+    /// Set by `CameraShakeResource`, moves the rendered view without moving the camera.
+    pub shake_offset: Vec3,
+    /// XY rectangle the look-at point is kept in, set from `WorldEdgeResource`.
+    pub bounds: Option<[Vec2; 2]>,
+    pub zoom_scaling: ZoomScalingSettings,
+    pub rotation: CameraRotationSettings,
+    // the terrain point the keyboard rotation in progress turns around
+    rotation_pivot: Option<Vec3>,
+    // the yaw a snapping rotation turns to
+    snap_yaw: Option<f32>, // rad
 }
 
 impl Default for RTSCamera {
@@ -85,16 +229,38 @@ impl Default for RTSCamera {
             win_width: 0,
             win_height: 0,
             win_scale_factor: 1.,
+            cinematic: None,
+            projection_mode: CameraProjection::Perspective,
+            shake_offset: Vec3::ZERO,
+            bounds: None,
+            zoom_scaling: Default::default(),
+            rotation: Default::default(),
+            rotation_pivot: None,
+            snap_yaw: None,
         }
     }
 }
 
 impl RTSCamera {
     pub fn eye(&self) -> Vec3 {
-        if self.pitch.abs() < f32::EPSILON {
+        if let Some(view) = &self.cinematic {
+            view.eye
+        } else if self.pitch.abs() < f32::EPSILON {
             Vec3::new(self.look_at.x, self.look_at.y, self.look_at_dist)
         } else {
-            self.look_at - self.right().cross(self.up()) * self.look_at_dist
+            self.look_at - self.right().cross(self.up()) * self.eye_dist()
+        }
+    }
+
+    fn is_isometric(&self) -> bool {
+        self.projection_mode == CameraProjection::Isometric && self.cinematic.is_none()
+    }
+
+    fn eye_dist(&self) -> f32 {
+        if self.is_isometric() {
+            self.look_at_dist + ISOMETRIC_BACKOFF
+        } else {
+            self.look_at_dist
         }
     }
 
@@ -113,6 +279,10 @@ impl RTSCamera {
         Quat::from_rotation_z(FRAC_PI_2).mul_vec3(self.forward())
     }
 
+    pub fn fov_y(&self) -> f32 {
+        self.fov_y
+    }
+
     pub fn view_proj(&self) -> Mat4 {
         self.projection_matrix * self.view_matrix
     }
@@ -126,19 +296,47 @@ impl RTSCamera {
             / 180.
     }
 
-    pub fn make_ray(&self, screen_x: u32, screen_y: u32) -> Vec3 {
-        // https://antongerdelan.net/opengl/raycasting.html
-        let ray_nds = Vec3::new(
+    fn screen_to_ndc(&self, screen_x: u32, screen_y: u32) -> Vec3 {
+        Vec3::new(
             (2. * screen_x as f32) / self.win_width as f32 - 1.,
             1. - (2. * screen_y as f32) / self.win_height as f32,
             1.,
-        );
+        )
+    }
+
+    /// Start of the ray under the cursor, the eye except for the orthographic projection, where
+    /// every pixel has its own origin on the near plane.
+    pub fn ray_origin(&self, screen_x: u32, screen_y: u32) -> Vec3 {
+        if !self.is_isometric() {
+            return self.eye();
+        }
+        let ndc = self.screen_to_ndc(screen_x, screen_y);
+        // reverse depth, the near plane is at 1
+        let near = self.view_proj().inverse() * glam::Vec4::new(ndc.x, ndc.y, 1., 1.);
+        near.xyz() / near.w
+    }
+
+    pub fn make_ray(&self, screen_x: u32, screen_y: u32) -> Vec3 {
+        if self.is_isometric() {
+            return (self.look_at - self.eye()).normalize();
+        }
+        // https://antongerdelan.net/opengl/raycasting.html
+        let ray_nds = self.screen_to_ndc(screen_x, screen_y);
         let ray_clip = glam::Vec4::new(ray_nds.x, ray_nds.y, -1.0, 1.0);
         let ray_eye = self.projection_matrix.inverse() * ray_clip;
         let ray_eye = glam::Vec4::new(ray_eye.x, ray_eye.y, -1.0, 0.0);
         (self.view_matrix.inverse() * ray_eye).xyz().normalize()
     }
 
+    /// Ray through a point on the screen for `PickRay::pick`.
+    pub fn pick_ray(&self, screen_x: u32, screen_y: u32) -> PickRay {
+        PickRay {
+            origin: self.ray_origin(screen_x, screen_y),
+            direction: self.make_ray(screen_x, screen_y),
+            unit_scale: self.pick_scale(),
+        }
+    }
+
     pub fn ray_cast_terrain(
         &self,
         screen_x: u32,
@@ -146,44 +344,77 @@ impl RTSCamera {
         universe: &Universe,
         ui_state: &mut UiState,
     ) -> Option<RayCastResult> {
-        let eye = self.eye();
+        let eye = self.ray_origin(screen_x, screen_y);
         let ray = self.make_ray(screen_x, screen_y);
         if let Some(result) = universe.ray_cast(eye, ray) {
             Some(result)
         } else {
-            ui_state.error(format!(
-                "Failed terrain ray cast, start: {}, ray: {}",
-                eye, ray
-            ));
+            ui_state.error(tr!("camera.ray_cast_failed", start = eye, ray = ray));
             None
         }
     }
 
+    /// Casts against the physics colliders instead of the voxel grid, so dynamic bodies are hit
+    /// too. Only terrain near the camera has colliders.
+    #[cfg(feature = "physics")]
+    pub fn ray_cast_physics(
+        &self,
+        screen_x: u32,
+        screen_y: u32,
+        physics: &PhysicsResource,
+    ) -> Option<PhysicsRayHit> {
+        physics.ray_cast(
+            self.ray_origin(screen_x, screen_y),
+            self.make_ray(screen_x, screen_y),
+            self.far_plane,
+        )
+    }
+
     pub fn ray_cast_screen(&self, screen_x: u32, screen_y: u32, screen_center_ray: Vec3) -> Vec3 {
         let ray_vec = self.make_ray(screen_x, screen_y);
         let angle = ray_vec.angle_between(screen_center_ray);
         let len = (self.near_plane + 1.) / f32::cos(angle);
-        self.eye() + len * ray_vec
+        self.ray_origin(screen_x, screen_y) + len * ray_vec
+    }
+
+    /// Scale of the terrain brush at the current zoom.
+    pub fn brush_scale(&self) -> f32 {
+        self.zoom_scaling
+            .factor(&self.zoom_scaling.brush, self.look_at_dist)
+    }
+
+    /// Scale of the unit click radius at the current zoom.
+    pub fn pick_scale(&self) -> f32 {
+        self.zoom_scaling
+            .factor(&self.zoom_scaling.pick, self.look_at_dist)
     }
 
     fn update_transform(&mut self, dt: f32, input: &InputResource) {
+        let move_speed = self.move_speed
+            * self
+                .zoom_scaling
+                .factor(&self.zoom_scaling.pan, self.look_at_dist);
         if input.is_key_down(KeyboardKey::W) {
-            self.look_at += dt * self.move_speed * self.forward();
+            self.look_at += dt * move_speed * self.forward();
         }
         if input.is_key_down(KeyboardKey::S) {
-            self.look_at -= dt * self.move_speed * self.forward();
+            self.look_at -= dt * move_speed * self.forward();
         }
         if input.is_key_down(KeyboardKey::A) {
-            self.look_at += dt * self.move_speed * self.right();
+            self.look_at += dt * move_speed * self.right();
         }
         if input.is_key_down(KeyboardKey::D) {
-            self.look_at -= dt * self.move_speed * self.right();
+            self.look_at -= dt * move_speed * self.right();
         }
-        if input.is_key_down(KeyboardKey::Q) {
-            self.yaw -= dt * self.yaw_speed;
+        // middle drag turns the camera, a middle double click faces north again
+        if let Some(drag) = input.mouse_drag_in_progress(MouseButton::MIDDLE) {
+            self.yaw += drag.previous_frame_delta.x * DRAG_YAW_SPEED;
         }
-        if input.is_key_down(KeyboardKey::E) {
-            self.yaw += dt * self.yaw_speed;
+        if input
+            .mouse_button_just_double_clicked_position(MouseButton::MIDDLE)
+            .is_some()
+        {
+            self.yaw = 0.;
         }
         if input.mouse_wheel_delta().y.abs() > f32::EPSILON {
             self.look_at_dist = (self.look_at_dist
@@ -195,6 +426,104 @@ impl RTSCamera {
                 .min(1000.);
             self.pitch = self.pitch_by_distance();
         }
+        if let Some([min, max]) = self.bounds {
+            self.look_at.x = self.look_at.x.max(min.x).min(max.x);
+            self.look_at.y = self.look_at.y.max(min.y).min(max.y);
+        }
+    }
+
+    /// Moves the camera to the other side of a wrapping terrain once its look-at point crossed
+    /// an edge, see `Universe::wrap_position`.
+    pub fn wrap(&mut self, universe: &Universe) {
+        let wrapped = universe.wrap_position(self.look_at);
+        let offset = wrapped - self.look_at;
+        if offset == Vec3::ZERO || self.cinematic.is_some() {
+            return;
+        }
+        self.look_at = wrapped;
+        if let Some(pivot) = &mut self.rotation_pivot {
+            *pivot += offset;
+        }
+    }
+
+    /// Turns the camera with the rotation keys around the terrain point picked by
+    /// `rotation.pivot`. Call before `update`, with unscaled time.
+    pub fn update_rotation(&mut self, dt: f32, input: &InputResource, universe: &Universe) {
+        if self.cinematic.is_some() {
+            self.rotation_pivot = None;
+            self.snap_yaw = None;
+            return;
+        }
+        let (left, right) = self.rotation.keys.keys();
+        let direction = match (input.is_key_down(left), input.is_key_down(right)) {
+            (true, false) => -1.,
+            (false, true) => 1.,
+            _ => 0.,
+        };
+        let max_step = dt * self.yaw_speed;
+        let delta = if self.rotation.snap {
+            let pressed = input.is_key_just_down(left) || input.is_key_just_down(right);
+            if pressed && direction != 0. {
+                // presses during a turn add a step to where it was going
+                let from = self.snap_yaw.unwrap_or(self.yaw) / ROTATION_SNAP;
+                let to = if direction > 0. {
+                    (from + 1e-3).floor() + 1.
+                } else {
+                    (from - 1e-3).ceil() - 1.
+                };
+                self.snap_yaw = Some(to * ROTATION_SNAP);
+            }
+            match self.snap_yaw {
+                Some(target) => {
+                    let left_to_turn = target - self.yaw;
+                    if left_to_turn.abs() <= max_step {
+                        self.snap_yaw = None;
+                    }
+                    left_to_turn.max(-max_step).min(max_step)
+                }
+                None => 0.,
+            }
+        } else {
+            self.snap_yaw = None;
+            direction * max_step
+        };
+        if delta == 0. {
+            self.rotation_pivot = None;
+            return;
+        }
+
+        let pivot = match self.rotation_pivot {
+            Some(pivot) => pivot,
+            None => {
+                let pivot = self.find_rotation_pivot(input, universe);
+                self.rotation_pivot = Some(pivot);
+                pivot
+            }
+        };
+        // a larger yaw turns the view clockwise seen from above
+        let offset = Quat::from_rotation_z(-delta).mul_vec3(self.look_at - pivot);
+        self.look_at = pivot + offset;
+        self.yaw += delta;
+    }
+
+    fn find_rotation_pivot(&self, input: &InputResource, universe: &Universe) -> Vec3 {
+        let (x, y) = match self.rotation.pivot {
+            RotationPivot::ScreenCenter => (self.win_width / 2, self.win_height / 2),
+            RotationPivot::Cursor => {
+                let cursor = input.mouse_position();
+                (cursor.x.max(0.) as u32, cursor.y.max(0.) as u32)
+            }
+        };
+        // the look-at point when the ray misses, eg. over the void past the world edge
+        universe
+            .ray_cast(self.ray_origin(x, y), self.make_ray(x, y))
+            .map_or(self.look_at, |result| {
+                Vec3::new(
+                    result.hit.x() as f32,
+                    result.hit.y() as f32,
+                    result.hit.z() as f32,
+                ) + Vec3::splat(0.5)
+            })
     }
 
     fn update_main_view_meta(
@@ -280,22 +609,51 @@ impl RTSCamera {
         viewports_resource: &mut ViewportsResource,
         input: &InputResource,
     ) {
-        self.update_transform(time_state.previous_update_dt(), input);
-
         let aspect_ratio = self.win_width as f32 / self.win_height.max(1) as f32;
 
-        let eye = self.eye();
-        let look_at = self.look_at;
-        let up = self.up();
+        let (eye, look_at, up, fov_y) = if let Some(view) = self.cinematic {
+            let dir = view.look_at - view.eye;
+            // looking straight down, any horizontal up works
+            let up = if dir.cross(Vec3::Z).length_squared() < 1e-6 * dir.length_squared() {
+                Vec3::Y
+            } else {
+                Vec3::Z
+            };
+            (view.eye, view.look_at, up, view.fov_y)
+        } else {
+            self.update_transform(time_state.unscaled_dt(), input);
+            if self.is_isometric() {
+                self.pitch = ISOMETRIC_PITCH;
+            }
+            (self.eye(), self.look_at, self.up(), self.fov_y)
+        };
+        let eye = eye + self.shake_offset;
+        let look_at = look_at + self.shake_offset;
         self.view_matrix = glam::Mat4::look_at_rh(eye, look_at, up);
 
-        let projection = Projection::Perspective(PerspectiveParameters::new(
-            self.fov_y,
-            aspect_ratio,
-            self.near_plane,
-            self.far_plane,
-            DepthRange::InfiniteReverse,
-        ));
+        let projection = if self.is_isometric() {
+            // same framing as the perspective view at the look-at point
+            let half_height = self.look_at_dist * (fov_y / 2.).tan();
+            let half_width = half_height * aspect_ratio;
+            Projection::Orthographic(OrthographicParameters::new(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                self.near_plane,
+                self.far_plane,
+                // an ortho projection has no infinite far plane
+                DepthRange::Reverse,
+            ))
+        } else {
+            Projection::Perspective(PerspectiveParameters::new(
+                fov_y,
+                aspect_ratio,
+                self.near_plane,
+                self.far_plane,
+                DepthRange::InfiniteReverse,
+            ))
+        };
 
         main_view_frustum
             .set_projection(&projection)
@@ -313,18 +671,43 @@ impl RTSCamera {
     }
 
     pub fn update_ui(&mut self, _ui_state: &mut UiState, ui: &mut egui::Ui) {
-        egui::CollapsingHeader::new("RTS Camera")
+        egui::CollapsingHeader::new(tr!("camera"))
+            .id_source("rts camera")
             .default_open(false)
             .show(ui, |ui| {
+                let old_projection_mode = self.projection_mode;
+                ui.horizontal(|ui| {
+                    ui.label(tr!("camera.projection"));
+                    ui.radio_value(
+                        &mut self.projection_mode,
+                        CameraProjection::Perspective,
+                        tr!("camera.projection.perspective"),
+                    );
+                    ui.radio_value(
+                        &mut self.projection_mode,
+                        CameraProjection::Isometric,
+                        tr!("camera.projection.isometric"),
+                    );
+                });
+                if old_projection_mode != self.projection_mode
+                    && self.projection_mode == CameraProjection::Perspective
+                {
+                    self.pitch = self.pitch_by_distance();
+                }
                 let old_pitch_default = self.pitch_default;
                 let old_pitch_zero_height = self.pitch_zero_height;
                 let old_pitch_height_power = self.pitch_height_power;
-                ui.add(egui::Slider::new(&mut self.pitch_default, 0.0..=90.).text("default pitch"));
                 ui.add(
-                    egui::Slider::new(&mut self.pitch_zero_height, 10.0..=500.).text("pitch max h"),
+                    egui::Slider::new(&mut self.pitch_default, 0.0..=90.)
+                        .text(tr!("camera.pitch_default")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.pitch_zero_height, 10.0..=500.)
+                        .text(tr!("camera.pitch_zero_height")),
                 );
                 ui.add(
-                    egui::Slider::new(&mut self.pitch_height_power, 1..=8).text("pitch h power"),
+                    egui::Slider::new(&mut self.pitch_height_power, 1..=8)
+                        .text(tr!("camera.pitch_height_power")),
                 );
                 if old_pitch_default != self.pitch_default
                     || old_pitch_zero_height != self.pitch_zero_height
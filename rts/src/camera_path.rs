@@ -0,0 +1,300 @@
+use std::path::Path;
+
+use glam::{Vec3, Vec4};
+use rafx_plugins::features::debug3d::Debug3DResource;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{CinematicView, RTSCamera};
+
+const PATH_EXTENSION: &str = "campath";
+const DRAW_SEGMENTS: usize = 16; // per keyframe pair
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct CameraKeyframe {
+    pub time: f32, // s
+    pub eye: Vec3,
+    pub look_at: Vec3,
+    pub fov_y: f32, // rad
+}
+
+/// Keyframes interpolated with a Catmull-Rom spline, stored as RON in `<name>.campath` files.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * ((2. * p1)
+        + (p2 - p0) * t
+        + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+        + (3. * p1 - p0 - 3. * p2 + p3) * t3)
+}
+
+impl CameraPath {
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |key| key.time)
+    }
+
+    fn sort(&mut self) {
+        self.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    pub fn sample(&self, time: f32) -> Option<CinematicView> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        let (eye, look_at, fov_y) = if time <= first.time || keys.len() == 1 {
+            (first.eye, first.look_at, first.fov_y)
+        } else if time >= last.time {
+            (last.eye, last.look_at, last.fov_y)
+        } else {
+            let idx = keys.iter().rposition(|key| key.time <= time).unwrap();
+            let k0 = &keys[idx.saturating_sub(1)];
+            let k1 = &keys[idx];
+            let k2 = &keys[idx + 1];
+            let k3 = &keys[(idx + 2).min(keys.len() - 1)];
+            let t = (time - k1.time) / (k2.time - k1.time).max(f32::EPSILON);
+            // smoothstep on fov, it has no tangents worth keeping
+            let s = t * t * (3. - 2. * t);
+            (
+                catmull_rom(k0.eye, k1.eye, k2.eye, k3.eye, t),
+                catmull_rom(k0.look_at, k1.look_at, k2.look_at, k3.look_at, t),
+                k1.fov_y + (k2.fov_y - k1.fov_y) * s,
+            )
+        };
+        Some(CinematicView {
+            eye,
+            look_at,
+            fov_y,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut camera_path: CameraPath =
+            ron::de::from_str(&contents).map_err(|err| err.to_string())?;
+        camera_path.sort();
+        Ok(camera_path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents =
+            ron::ser::to_string_pretty(self, Default::default()).map_err(|err| err.to_string())?;
+        std::fs::write(path, contents).map_err(|err| err.to_string())
+    }
+}
+
+/// Plays camera paths back, overriding the `RTSCamera` while playing. Paths are edited by
+/// capturing the current view as keyframes. Used for trailers and for getting back to the exact
+/// viewpoint of a rendering bug.
+pub struct CameraDirectorResource {
+    pub path: CameraPath,
+    pub looping: bool,
+    pub show_path: bool,
+    playing: Option<f32>,
+    file_name: String,
+    message: Option<String>,
+}
+
+impl Default for CameraDirectorResource {
+    fn default() -> Self {
+        Self {
+            path: Default::default(),
+            looping: false,
+            show_path: true,
+            playing: None,
+            file_name: "path".to_string(),
+            message: None,
+        }
+    }
+}
+
+impl CameraDirectorResource {
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    pub fn play(&mut self) {
+        if !self.path.keyframes.is_empty() {
+            self.playing = Some(0.);
+        }
+    }
+
+    pub fn stop(&mut self, camera: &mut RTSCamera) {
+        self.playing = None;
+        if let Some(view) = camera.cinematic.take() {
+            // hand control back where the path left the camera
+            camera.look_at = view.look_at;
+        }
+    }
+
+    /// Call before `RTSCamera::update`, with unscaled time.
+    pub fn update(&mut self, dt: f32, camera: &mut RTSCamera) {
+        let time = match &mut self.playing {
+            Some(time) => {
+                *time += dt;
+                *time
+            }
+            None => return,
+        };
+        let duration = self.path.duration();
+        let time = if time > duration && self.looping && duration > 0. {
+            let time = time % duration;
+            self.playing = Some(time);
+            time
+        } else {
+            time
+        };
+        camera.cinematic = self.path.sample(time);
+        if time > duration && !self.looping {
+            self.stop(camera);
+        }
+    }
+
+    pub fn add_keyframe(&mut self, camera: &RTSCamera) {
+        let time = self.path.duration()
+            + if self.path.keyframes.is_empty() {
+                0.
+            } else {
+                2.
+            };
+        self.path.keyframes.push(CameraKeyframe {
+            time,
+            eye: camera.eye(),
+            look_at: camera.look_at,
+            fov_y: camera.fov_y(),
+        });
+    }
+
+    fn file_path(&self) -> String {
+        format!("{}.{}", self.file_name, PATH_EXTENSION)
+    }
+
+    pub fn add_debug_draw(&self, debug_draw: &mut Debug3DResource) {
+        if !self.show_path || self.is_playing() || self.path.keyframes.len() < 2 {
+            return;
+        }
+        let color = Vec4::new(1., 0.8, 0.2, 1.);
+        let steps = DRAW_SEGMENTS * (self.path.keyframes.len() - 1);
+        let duration = self.path.duration();
+        let points: Vec<_> = (0..=steps)
+            .filter_map(|idx| self.path.sample(duration * idx as f32 / steps as f32))
+            .map(|view| view.eye)
+            .collect();
+        for segment in points.windows(2) {
+            debug_draw.add_line(segment[0], segment[1], color);
+        }
+        for key in &self.path.keyframes {
+            debug_draw.add_line(key.eye, key.look_at, Vec4::new(0.2, 0.8, 1., 0.5));
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, camera: &mut RTSCamera) {
+        egui::CollapsingHeader::new(tr!("camera_path"))
+            .id_source("camera director")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    if self.is_playing() {
+                        if ui.button(tr!("camera_path.stop")).clicked() {
+                            self.stop(camera);
+                        }
+                    } else if ui.button(tr!("camera_path.play")).clicked() {
+                        self.play();
+                    }
+                    if ui.button(tr!("camera_path.add")).clicked() {
+                        self.add_keyframe(camera);
+                    }
+                });
+                ui.checkbox(&mut self.looping, tr!("camera_path.loop"));
+                ui.checkbox(&mut self.show_path, tr!("camera_path.show"));
+
+                let mut remove = None;
+                let mut goto = None;
+                let mut retimed = false;
+                for (idx, key) in self.path.keyframes.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        retimed |= ui
+                            .add(
+                                egui::DragValue::new(&mut key.time)
+                                    .speed(0.1)
+                                    .clamp_range(0.0..=3600.)
+                                    .suffix(" s"),
+                            )
+                            .changed();
+                        let mut fov = key.fov_y.to_degrees();
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut fov)
+                                    .clamp_range(10.0..=120.)
+                                    .suffix("°"),
+                            )
+                            .changed()
+                        {
+                            key.fov_y = fov.to_radians();
+                        }
+                        if ui.small_button(tr!("camera_path.go")).clicked() {
+                            goto = Some(idx);
+                        }
+                        if ui.small_button("x").clicked() {
+                            remove = Some(idx);
+                        }
+                    });
+                }
+                if retimed {
+                    self.path.sort();
+                }
+                if let Some(idx) = remove {
+                    self.path.keyframes.remove(idx);
+                }
+                if let Some(key) = goto.and_then(|idx| self.path.keyframes.get(idx)) {
+                    // one frame path, holds the exact view until stopped
+                    camera.cinematic = Some(CinematicView {
+                        eye: key.eye,
+                        look_at: key.look_at,
+                        fov_y: key.fov_y,
+                    });
+                }
+                if camera.cinematic.is_some()
+                    && !self.is_playing()
+                    && ui.button(tr!("camera_path.release")).clicked()
+                {
+                    self.stop(camera);
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.file_name).desired_width(100.));
+                    ui.label(format!(".{}", PATH_EXTENSION));
+                });
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("camera_path.save")).clicked() {
+                        let file_path = self.file_path();
+                        self.message = Some(match self.path.save(Path::new(&file_path)) {
+                            Ok(()) => tr!("camera_path.saved", path = file_path),
+                            Err(err) => {
+                                tr!("camera_path.save_failed", path = file_path, error = err)
+                            }
+                        });
+                    }
+                    if ui.button(tr!("camera_path.load")).clicked() {
+                        let file_path = self.file_path();
+                        self.message = Some(match CameraPath::load(Path::new(&file_path)) {
+                            Ok(path) => {
+                                self.path = path;
+                                tr!("camera_path.loaded", path = file_path)
+                            }
+                            Err(err) => {
+                                tr!("camera_path.load_failed", path = file_path, error = err)
+                            }
+                        });
+                    }
+                });
+                if let Some(message) = &self.message {
+                    ui.label(message);
+                }
+            });
+    }
+}
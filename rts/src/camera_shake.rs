@@ -0,0 +1,102 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::RTSCamera, settings::Settings};
+
+const MAX_OFFSET: f32 = 0.6; // m, at full trauma and intensity
+const TRAUMA_DECAY: f32 = 1.5; // per s
+const FREQUENCY: f32 = 15.; // rad/s
+
+// events further than this from the look-at point don't shake the camera
+const FALLOFF_DISTANCE: f32 = 120.; // m
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct CameraShakeSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+}
+
+impl Default for CameraShakeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 1.,
+        }
+    }
+}
+
+/// Trauma based camera shake. Events (explosions, collapses, large buildings placed, combat
+/// hits) add trauma in [0, 1] at a world position, attenuated by their distance to the camera.
+/// Trauma decays linearly and the shake grows with its square, so small hits barely register
+/// while big ones kick hard and settle fast.
+pub struct CameraShakeResource {
+    pub settings: CameraShakeSettings,
+    trauma: f32,
+    time: f32, // s
+    pending: Vec<(f32, Vec3)>,
+}
+
+impl CameraShakeResource {
+    pub fn new(settings: CameraShakeSettings) -> Self {
+        Self {
+            settings,
+            trauma: 0.,
+            time: 0.,
+            pending: vec![],
+        }
+    }
+
+    /// The hook for gameplay events, applied on the next camera update.
+    pub fn add_trauma(&mut self, amount: f32, position: Vec3) {
+        self.pending.push((amount, position));
+    }
+
+    /// Call before `RTSCamera::update`, with unscaled time.
+    pub fn update(&mut self, dt: f32, camera: &mut RTSCamera) {
+        for (amount, position) in self.pending.drain(..) {
+            let falloff = 1. - (position - camera.look_at).length() / FALLOFF_DISTANCE;
+            self.trauma += amount * falloff.max(0.);
+        }
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).clamp(0., 1.);
+        self.time += dt;
+
+        camera.shake_offset = if self.settings.enabled && self.trauma > 0. {
+            let t = self.time * FREQUENCY;
+            // incommensurate frequencies, so the pattern doesn't visibly repeat
+            let wobble = Vec3::new(
+                (t * 1.0).sin() + 0.5 * (t * 2.3).sin(),
+                (t * 1.3 + 1.).sin() + 0.5 * (t * 2.9).sin(),
+                (t * 0.7 + 2.).sin() + 0.5 * (t * 1.9).sin(),
+            ) / 1.5;
+            wobble * MAX_OFFSET * self.settings.intensity * self.trauma * self.trauma
+        } else {
+            Vec3::ZERO
+        };
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.camera_shake"))
+            .id_source("camera shake")
+            .default_open(false)
+            .show(ui, |ui| {
+                let settings = &mut self.settings;
+                let mut changed = ui
+                    .checkbox(&mut settings.enabled, tr!("settings.enabled"))
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut settings.intensity, 0.0..=2.)
+                            .text(tr!("settings.camera_shake.intensity")),
+                    )
+                    .changed();
+                if changed {
+                    let settings = *settings;
+                    Settings::update(|s| s.camera_shake = settings);
+                }
+                if ui.button(tr!("settings.test")).clicked() {
+                    self.trauma = 0.8;
+                }
+            });
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::VecDeque, path::PathBuf};
+
+use crate::input::{InputResource, KeyboardKey};
+
+const MAX_OUTPUT: usize = 100; // lines
+
+/// A command typed in the console. Like file dialog picks, it waits in the resource until the
+/// flow that runs it `take`s it, the ones nobody took are dropped on the next frame.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConsoleCommand {
+    MountPackfile(PathBuf),
+    UseDaemon,
+    /// Unit count and spacing, the stress test panel's values for the ones left out.
+    StressTest(Option<u32>, Option<f32>),
+}
+
+impl ConsoleCommand {
+    const USAGE: &'static [&'static str] = &[
+        "mount <packfile>",
+        "daemon",
+        "stress [count] [spacing]",
+        "help",
+    ];
+
+    /// `None` for `help`, which the console answers itself.
+    fn parse(line: &str) -> Result<Option<Self>, String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let rest = line.trim_start()[name.len()..].trim();
+        match name {
+            "mount" if !rest.is_empty() => Ok(Some(ConsoleCommand::MountPackfile(rest.into()))),
+            "daemon" if rest.is_empty() => Ok(Some(ConsoleCommand::UseDaemon)),
+            "stress" => {
+                let args: Vec<_> = words.collect();
+                let count = args.first().map(|arg| arg.parse::<u32>()).transpose();
+                let spacing = args.get(1).map(|arg| arg.parse::<f32>()).transpose();
+                match (count, spacing) {
+                    (Ok(count), Ok(spacing)) if args.len() <= 2 => {
+                        Ok(Some(ConsoleCommand::StressTest(count, spacing)))
+                    }
+                    _ => Err(tr!("console.unknown", line = line.trim())),
+                }
+            }
+            "help" => Ok(None),
+            _ => Err(tr!("console.unknown", line = line.trim())),
+        }
+    }
+}
+
+/// A one-line prompt for debug commands, toggled with the grave key.
+#[derive(Default)]
+pub struct ConsoleResource {
+    open: bool,
+    line: String,
+    output: VecDeque<String>,
+    pending: Vec<ConsoleCommand>,
+}
+
+impl ConsoleResource {
+    pub fn print(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        log::info!("console: {}", text);
+        self.output.push_back(text);
+        if self.output.len() > MAX_OUTPUT {
+            self.output.pop_front();
+        }
+    }
+
+    /// Removes and returns the first pending command `f` maps to something.
+    pub fn take<T>(&mut self, mut f: impl FnMut(&ConsoleCommand) -> Option<T>) -> Option<T> {
+        let (index, value) = self
+            .pending
+            .iter()
+            .enumerate()
+            .find_map(|(index, command)| f(command).map(|value| (index, value)))?;
+        self.pending.remove(index);
+        Some(value)
+    }
+
+    fn run(&mut self, line: &str) {
+        self.print(format!("> {}", line));
+        match ConsoleCommand::parse(line) {
+            Ok(Some(command)) => self.pending.push(command),
+            Ok(None) => {
+                for usage in ConsoleCommand::USAGE {
+                    self.print(*usage);
+                }
+            }
+            Err(err) => self.print(err),
+        }
+    }
+
+    /// Call early in the frame, so the commands entered are taken later in the same frame.
+    pub fn ui(&mut self, context: &egui::CtxRef, input: &InputResource) {
+        self.pending.clear();
+        if input.is_key_just_down(KeyboardKey::Grave) {
+            self.open = !self.open;
+            return;
+        }
+        if !self.open {
+            return;
+        }
+        let mut entered = None;
+        egui::Window::new(tr!("console.title"))
+            .default_width(480.)
+            .show(context, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.)
+                    .show(ui, |ui| {
+                        for line in self.output.iter().rev() {
+                            ui.monospace(line);
+                        }
+                    });
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.line)
+                        .code_editor()
+                        .desired_width(f32::INFINITY),
+                );
+                if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                    entered = Some(std::mem::take(&mut self.line));
+                    response.request_focus();
+                }
+            });
+        if let Some(line) = entered.filter(|line| !line.trim().is_empty()) {
+            self.run(&line);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use egui::{Color32, CtxRef, Id, LayerId, Order, Pos2, Stroke};
+use winit::window::{CursorIcon, Window};
+
+use crate::settings::Settings;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CursorMode {
+    Default,
+    /// Placing a unit, tile or task.
+    Spawn,
+    TerrainEdit,
+    /// Right click would attack the unit under the cursor.
+    Attack,
+    /// Right click would harvest or enter what is under the cursor.
+    Interact,
+    /// Placing, but nothing valid under the cursor.
+    Invalid,
+}
+
+impl CursorMode {
+    fn icon(&self) -> CursorIcon {
+        match self {
+            CursorMode::Default => CursorIcon::Default,
+            CursorMode::Spawn => CursorIcon::Copy,
+            CursorMode::TerrainEdit => CursorIcon::Cell,
+            CursorMode::Attack => CursorIcon::Crosshair,
+            CursorMode::Interact => CursorIcon::Hand,
+            CursorMode::Invalid => CursorIcon::NotAllowed,
+        }
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            CursorMode::Default => Color32::WHITE,
+            CursorMode::Spawn => Color32::from_rgb(80, 200, 255),
+            CursorMode::TerrainEdit => Color32::from_rgb(255, 200, 80),
+            CursorMode::Attack => Color32::from_rgb(255, 60, 60),
+            CursorMode::Interact => Color32::from_rgb(80, 255, 80),
+            CursorMode::Invalid => Color32::GRAY,
+        }
+    }
+}
+
+/// Picks the mouse cursor from what the scene reports each frame. The mode is reset to
+/// `Default` at the start of the frame, the scene sets it while handling input and `apply`
+/// pushes it to the window. While the pointer is over egui, egui's own cursor wins.
+/// The software cursor hides the hardware one and draws a sprite with egui instead, for
+/// platforms where custom hardware cursors misbehave.
+pub struct CursorResource {
+    pub mode: CursorMode,
+    software: bool,
+    applied: Option<(CursorMode, bool)>,
+}
+
+impl CursorResource {
+    pub fn new(software: bool) -> Self {
+        Self {
+            mode: CursorMode::Default,
+            software,
+            applied: None,
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.mode = CursorMode::Default;
+    }
+
+    pub fn apply(&mut self, window: &Window, context: &CtxRef) {
+        if context.is_pointer_over_area() {
+            if self.applied.is_some() {
+                window.set_cursor_visible(true);
+                self.applied = None;
+            }
+            return;
+        }
+        let state = (self.mode, self.software);
+        if self.applied != Some(state) {
+            window.set_cursor_visible(!self.software);
+            window.set_cursor_icon(self.mode.icon());
+            self.applied = Some(state);
+        }
+        if self.software {
+            if let Some(pos) = context.input().pointer.hover_pos() {
+                self.draw_software(context, pos);
+            }
+        }
+    }
+
+    fn draw_software(&self, context: &CtxRef, pos: Pos2) {
+        const SIZE: f32 = 8.;
+        let painter = context.layer_painter(LayerId::new(Order::Tooltip, Id::new("cursor")));
+        let stroke = Stroke::new(2., self.mode.color());
+        let dx = egui::vec2(SIZE, 0.);
+        let dy = egui::vec2(0., SIZE);
+        match self.mode {
+            CursorMode::Default => {
+                painter.line_segment([pos, pos + dx + dy], stroke);
+                painter.line_segment([pos, pos + dx * 0.6], stroke);
+                painter.line_segment([pos, pos + dy * 0.6], stroke);
+            }
+            CursorMode::Invalid => {
+                painter.circle_stroke(pos, SIZE * 0.8, stroke);
+                painter.line_segment([pos - (dx + dy) * 0.55, pos + (dx + dy) * 0.55], stroke);
+            }
+            CursorMode::Attack => {
+                painter.circle_stroke(pos, SIZE * 0.8, stroke);
+                painter.line_segment([pos - dx * 1.2, pos + dx * 1.2], stroke);
+                painter.line_segment([pos - dy * 1.2, pos + dy * 1.2], stroke);
+            }
+            _ => {
+                painter.line_segment([pos - dx, pos + dx], stroke);
+                painter.line_segment([pos - dy, pos + dy], stroke);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(&mut self.software, tr!("settings.software_cursor"))
+            .changed()
+        {
+            let software = self.software;
+            Settings::update(|settings| settings.software_cursor = software);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use glam::{Vec3, Vec4};
+
+use crate::camera::RTSCamera;
+
+/// Culls debug overlays against the main view, so drawing them stays cheap with large armies.
+/// Only the side planes of the frustum are tested, they don't depend on the depth convention
+/// of the projection, and the far end is cut at `max_distance` from the eye instead.
+pub struct DebugCullResource {
+    pub enabled: bool,
+    /// Overlays further than this from the eye are not drawn, 0 for no limit.
+    pub max_distance: f32, // voxels
+    // left, right, bottom and top planes, normals pointing in
+    planes: [Vec4; 4],
+    eye: Vec3,
+    tested: usize,
+    culled: usize,
+    last_tested: usize,
+    last_culled: usize,
+}
+
+impl Default for DebugCullResource {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_distance: 512.,
+            planes: [Vec4::ZERO; 4],
+            eye: Vec3::ZERO,
+            tested: 0,
+            culled: 0,
+            last_tested: 0,
+            last_culled: 0,
+        }
+    }
+}
+
+impl DebugCullResource {
+    /// Takes the frustum of this frame, call after the camera update.
+    pub fn update(&mut self, camera: &RTSCamera) {
+        let m = camera.view_proj().transpose();
+        let (x, y, w) = (m.x_axis, m.y_axis, m.w_axis);
+        self.planes = [w + x, w - x, w + y, w - y];
+        for plane in self.planes.iter_mut() {
+            let length = plane.truncate().length();
+            if length > 0. {
+                *plane /= length;
+            }
+        }
+        self.eye = camera.eye();
+        self.last_tested = self.tested;
+        self.last_culled = self.culled;
+        self.tested = 0;
+        self.culled = 0;
+    }
+
+    /// True if a sphere at `center` may be on screen and is within the distance limit.
+    pub fn is_visible(&mut self, center: Vec3, radius: f32) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        self.tested += 1;
+        let visible = (self.max_distance <= 0.
+            || center.distance(self.eye) - radius <= self.max_distance)
+            && self
+                .planes
+                .iter()
+                .all(|plane| plane.truncate().dot(center) + plane.w >= -radius);
+        if !visible {
+            self.culled += 1;
+        }
+        visible
+    }
+
+    /// `is_visible` for the bounding sphere of the segment from `a` to `b`.
+    pub fn is_line_visible(&mut self, a: Vec3, b: Vec3) -> bool {
+        self.is_visible((a + b) / 2., a.distance(b) / 2.)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("debug_cull"))
+            .id_source("debug draw culling")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("debug_cull.enabled"));
+                ui.add(
+                    egui::Slider::new(&mut self.max_distance, 0.0..=2048.)
+                        .text(tr!("debug_cull.max_distance")),
+                );
+                ui.label(tr!(
+                    "debug_cull.stats",
+                    culled = self.last_culled,
+                    tested = self.last_tested
+                ));
+            });
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+};
+
+use building_blocks::core::prelude::*;
+use glam::Vec3;
+use legion::{IntoQuery, Read, Resources};
+use rafx_plugins::components::TransformComponent;
+
+use super::{
+    env::TileComponent,
+    overlay_mesh::OverlayQuads,
+    simulation::{ChunkGeometry, Universe},
+};
+
+// a column is walkable when no neighbor is more than this many voxels higher or lower per voxel
+const MAX_WALK_SLOPE: f32 = 1.;
+// recompute when the camera target moved this far
+const REFRESH_DISTANCE: f32 = 8.; // m
+
+const OVERLAY_OWNER: &str = "analysis";
+// from good to bad, emissive so the shading of the terrain doesn't change their color
+const RAMP_MATERIALS: [&str; 5] = [
+    "analysis_0",
+    "analysis_1",
+    "analysis_2",
+    "analysis_3",
+    "analysis_4",
+];
+// side of a column's pad, relative to the sampling step
+const PAD_SIZE: f32 = 0.9;
+// above the column top, clear of the depth precision at the usual camera distances
+const PAD_LIFT: f32 = 0.1; // voxels
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AnalysisLayer {
+    Height,
+    Slope,
+    Reachability,
+}
+
+impl AnalysisLayer {
+    pub const ALL: [AnalysisLayer; 3] = [
+        AnalysisLayer::Height,
+        AnalysisLayer::Slope,
+        AnalysisLayer::Reachability,
+    ];
+}
+
+impl Display for AnalysisLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            AnalysisLayer::Height => write!(f, "{}", tr!("analysis.layer.height")),
+            AnalysisLayer::Slope => write!(f, "{}", tr!("analysis.layer.slope")),
+            AnalysisLayer::Reachability => write!(f, "{}", tr!("analysis.layer.reachability")),
+        }
+    }
+}
+
+struct ColumnSample {
+    x: i32,
+    y: i32,
+    height: i32,
+    // voxels of climb per voxel of run, to the steepest neighbor
+    slope: f32,
+    // under the footprint of a tile units cannot cross
+    blocked: bool,
+    reachable: bool,
+}
+
+impl ColumnSample {
+    fn walkable(&self) -> bool {
+        !self.blocked && self.slope <= MAX_WALK_SLOPE
+    }
+}
+
+/// Per-column height, slope and reachability of the terrain around the camera target, drawn as
+/// a grid of colored pads on top of the surface. Reachability is a flood fill over walkable columns
+/// starting at the column under the camera target, the way ground units would see the map.
+/// Sampling is redone when the target moves or a chunk in range is edited.
+pub struct TerrainAnalysisResource {
+    pub enabled: bool,
+    pub layer: AnalysisLayer,
+    pub radius: u32, // m
+    pub step: u32,   // m
+    samples: Vec<ColumnSample>,
+    height_range: (i32, i32),
+    sampled_at: Option<Vec3>,
+    sampled_params: (u32, u32),
+    chunk_versions: u64,
+    // bumped on every sampling, the overlay is rebuilt when it or the layer changes
+    generation: u64,
+    built: Option<(AnalysisLayer, u64)>,
+}
+
+impl Default for TerrainAnalysisResource {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            layer: AnalysisLayer::Slope,
+            radius: 48,
+            step: 2,
+            samples: vec![],
+            height_range: (0, 0),
+            sampled_at: None,
+            sampled_params: (0, 0),
+            chunk_versions: 0,
+            generation: 0,
+            built: None,
+        }
+    }
+}
+
+impl TerrainAnalysisResource {
+    #[profiling::function]
+    pub fn update(&mut self, universe: &mut Universe, center: Vec3) {
+        if !self.enabled {
+            self.samples.clear();
+            self.sampled_at = None;
+            return;
+        }
+        // chunk versions come from one counter shared by every universe, so a terrain reset
+        // or a new map changes them too
+        let chunk_versions = universe
+            .chunks_near(center, self.radius as f32 + REFRESH_DISTANCE)
+            .iter()
+            .fold(0u64, |acc, (key, version)| {
+                acc.wrapping_mul(31)
+                    .wrapping_add(*version)
+                    .wrapping_add((key.minimum.x() as u64) << 32)
+                    .wrapping_add(key.minimum.y() as u64)
+            });
+        let stale = match self.sampled_at {
+            Some(at) => {
+                (at - center).truncate().length() > REFRESH_DISTANCE
+                    || self.sampled_params != (self.radius, self.step)
+                    || self.chunk_versions != chunk_versions
+            }
+            None => true,
+        };
+        if stale {
+            self.sample(universe, center);
+            self.sampled_at = Some(center);
+            self.sampled_params = (self.radius, self.step);
+            self.chunk_versions = chunk_versions;
+            self.generation += 1;
+        }
+    }
+
+    fn sample(&mut self, universe: &mut Universe, center: Vec3) {
+        let step = self.step.max(1) as i32;
+        let cells = self.radius as i32 / step;
+        let cx = center.x.floor() as i32;
+        let cy = center.y.floor() as i32;
+        let mut columns = vec![];
+        for j in -cells..=cells {
+            for i in -cells..=cells {
+                columns.push((cx + i * step, cy + j * step));
+            }
+        }
+        let tops = universe.column_tops(&columns);
+        let heights: HashMap<(i32, i32), i32> = columns
+            .iter()
+            .zip(tops)
+            .filter_map(|(column, top)| top.map(|z| ((column.0, column.1), z)))
+            .collect();
+
+        let mut query = <(Read<TransformComponent>, Read<TileComponent>)>::query();
+        let blockers: Vec<_> = query
+            .iter(&universe.world)
+            .filter(|(_, tile)| !tile.properties.walkable)
+            .filter_map(|(transform, tile)| {
+                let [w, h] = tile.properties.footprint?;
+                let min_x = transform.translation.x.floor() as i32 - w as i32 / 2;
+                let min_y = transform.translation.y.floor() as i32 - h as i32 / 2;
+                Some((min_x, min_y, min_x + w as i32, min_y + h as i32))
+            })
+            .collect();
+        let blocked = |x: i32, y: i32| {
+            blockers
+                .iter()
+                .any(|(x0, y0, x1, y1)| x >= *x0 && x < *x1 && y >= *y0 && y < *y1)
+        };
+
+        let mut samples: Vec<_> = heights
+            .iter()
+            .map(|(&(x, y), &height)| {
+                let slope = [(step, 0), (-step, 0), (0, step), (0, -step)]
+                    .iter()
+                    .filter_map(|(dx, dy)| heights.get(&(x + dx, y + dy)))
+                    .map(|z| (z - height).abs() as f32 / step as f32)
+                    .fold(0., f32::max);
+                ColumnSample {
+                    x,
+                    y,
+                    height,
+                    slope,
+                    blocked: blocked(x, y),
+                    reachable: false,
+                }
+            })
+            .collect();
+
+        let index: HashMap<(i32, i32), usize> = samples
+            .iter()
+            .enumerate()
+            .map(|(idx, sample)| ((sample.x, sample.y), idx))
+            .collect();
+        let start = samples
+            .iter()
+            .enumerate()
+            .filter(|(_, sample)| sample.walkable())
+            .min_by_key(|(_, sample)| (sample.x - cx).abs() + (sample.y - cy).abs())
+            .map(|(idx, _)| idx);
+        let mut queue: VecDeque<usize> = start.into_iter().collect();
+        if let Some(start) = start {
+            samples[start].reachable = true;
+        }
+        while let Some(idx) = queue.pop_front() {
+            let (x, y) = (samples[idx].x, samples[idx].y);
+            for (dx, dy) in [(step, 0), (-step, 0), (0, step), (0, -step)] {
+                if let Some(&next) = index.get(&(x + dx, y + dy)) {
+                    if !samples[next].reachable && samples[next].walkable() {
+                        samples[next].reachable = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        self.height_range = (
+            samples.iter().map(|s| s.height).min().unwrap_or(0),
+            samples.iter().map(|s| s.height).max().unwrap_or(0),
+        );
+        self.samples = samples;
+    }
+
+    /// Position of the sample on the color ramp, 0 the good end.
+    fn ramp(&self, sample: &ColumnSample) -> f32 {
+        match self.layer {
+            AnalysisLayer::Height => {
+                let (min, max) = self.height_range;
+                (sample.height - min) as f32 / (max - min).max(1) as f32
+            }
+            AnalysisLayer::Slope => (sample.slope / (2. * MAX_WALK_SLOPE)).min(1.),
+            AnalysisLayer::Reachability => {
+                if sample.reachable {
+                    0.
+                } else if sample.walkable() {
+                    0.5
+                } else {
+                    1.
+                }
+            }
+        }
+    }
+
+    /// Lays a colored pad on the top of every sampled column, as an overlay mesh of the
+    /// universe. Rebuilt when the samples or the layer change.
+    pub fn update_overlay(&mut self, universe: &mut Universe, resources: &Resources) {
+        let key = Point3i::ZERO;
+        if !self.enabled || self.samples.is_empty() {
+            if self.built.take().is_some() {
+                universe.set_overlay_mesh(OVERLAY_OWNER, key, Vec3::ZERO, None, resources);
+            }
+            return;
+        }
+        let built = (self.layer, self.generation);
+        // the universe drops its overlays when the terrain is reset
+        if self.built == Some(built) && universe.has_overlay_mesh(OVERLAY_OWNER, key) {
+            return;
+        }
+        let (geometry, position) = match self.geometry(universe) {
+            Some((geometry, position)) => (Some(geometry), position),
+            None => (None, Vec3::ZERO),
+        };
+        if universe.set_overlay_mesh(OVERLAY_OWNER, key, position, geometry, resources) {
+            self.built = Some(built);
+        }
+    }
+
+    fn geometry(&self, universe: &Universe) -> Option<(ChunkGeometry, Vec3)> {
+        let material_names = universe.get_material_names();
+        let ramp: Vec<u16> = RAMP_MATERIALS
+            .iter()
+            .filter_map(|name| match material_names.iter().position(|m| m == name) {
+                Some(material) => Some(material as u16),
+                None => {
+                    log::error!("Analysis material {} not found", name);
+                    None
+                }
+            })
+            .collect();
+        if ramp.len() != RAMP_MATERIALS.len() {
+            return None;
+        }
+        let half = self.step.max(1) as f32 * PAD_SIZE / 2.;
+        let mut quads = OverlayQuads::default();
+        for sample in &self.samples {
+            let t = self.ramp(sample);
+            let material = ramp[(t * (ramp.len() - 1) as f32).round() as usize];
+            let center = Vec3::new(
+                sample.x as f32 + 0.5,
+                sample.y as f32 + 0.5,
+                sample.height as f32 + 1. + PAD_LIFT,
+            );
+            quads.add(
+                material,
+                [
+                    center + Vec3::new(-half, -half, 0.),
+                    center + Vec3::new(half, -half, 0.),
+                    center + Vec3::new(half, half, 0.),
+                    center + Vec3::new(-half, half, 0.),
+                ],
+                Vec3::Z,
+                Vec3::X,
+            );
+        }
+        quads.build()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("analysis"))
+            .id_source("terrain analysis")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("analysis.enabled"));
+                egui::ComboBox::from_label(tr!("analysis.layer"))
+                    .selected_text(format!("{}", self.layer))
+                    .show_ui(ui, |ui| {
+                        for layer in AnalysisLayer::ALL {
+                            ui.selectable_value(&mut self.layer, layer, format!("{}", layer));
+                        }
+                    });
+                ui.add(egui::Slider::new(&mut self.radius, 8..=128).text(tr!("analysis.radius")));
+                ui.add(egui::Slider::new(&mut self.step, 1..=8).text(tr!("analysis.step")));
+                if self.enabled {
+                    let walkable = self.samples.iter().filter(|s| s.walkable()).count();
+                    let reachable = self.samples.iter().filter(|s| s.reachable).count();
+                    ui.label(tr!(
+                        "analysis.stats",
+                        columns = self.samples.len(),
+                        walkable = walkable,
+                        reachable = reachable
+                    ));
+                    ui.label(tr!(
+                        "analysis.height",
+                        min = self.height_range.0,
+                        max = self.height_range.1
+                    ));
+                }
+            });
+    }
+}
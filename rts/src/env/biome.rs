@@ -0,0 +1,160 @@
+use std::fmt::Display;
+
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+use super::noise::{
+    fractal::{Fbm, FractalParams},
+    simplex::Simplex,
+    NoiseFn2D,
+};
+use crate::ui::UiState;
+
+// offsets added to the terrain seed, so the climate maps don't follow the height noise
+const TEMPERATURE_SEED: u32 = 1013;
+const MOISTURE_SEED: u32 = 2029;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Biome {
+    Tundra,
+    Taiga,
+    Desert,
+    Jungle,
+}
+
+impl Biome {
+    /// In palette order: cold & dry, cold & wet, hot & dry, hot & wet.
+    pub const ALL: [Biome; 4] = [Biome::Tundra, Biome::Taiga, Biome::Desert, Biome::Jungle];
+
+    fn from_climate(temperature: f64, moisture: f64) -> Self {
+        match (temperature > 0., moisture > 0.) {
+            (false, false) => Biome::Tundra,
+            (false, true) => Biome::Taiga,
+            (true, false) => Biome::Desert,
+            (true, true) => Biome::Jungle,
+        }
+    }
+}
+
+impl Display for Biome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Biome::Tundra => write!(f, "{}", tr!("biome.tundra")),
+            Biome::Taiga => write!(f, "{}", tr!("biome.taiga")),
+            Biome::Desert => write!(f, "{}", tr!("biome.desert")),
+            Biome::Jungle => write!(f, "{}", tr!("biome.jungle")),
+        }
+    }
+}
+
+/// Materials of one biome: `surface` for the top voxel of a column, `ground` below it.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct BiomePalette {
+    pub surface: String,
+    pub ground: String,
+}
+
+impl BiomePalette {
+    fn new(surface: &str, ground: &str) -> Self {
+        Self {
+            surface: surface.to_string(),
+            ground: ground.to_string(),
+        }
+    }
+}
+
+/// Temperature and moisture maps splitting noise terrain into biomes, each with its own
+/// material palette. Both maps are seeded from the terrain seed, so a seed always gives the same
+/// regions. Near a border, within `blend` of the threshold, the biome is picked per voxel with a
+/// hashed jitter, dithering the two palettes into each other.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct BiomeParams {
+    pub enabled: bool,
+    pub scale: f64, // m per climate noise unit
+    pub blend: f64, // climate noise units
+    pub palettes: [BiomePalette; 4],
+}
+
+impl Default for BiomeParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            scale: 1024.,
+            blend: 0.1,
+            palettes: [
+                BiomePalette::new("flat_blue", "blue_metal"),
+                BiomePalette::new("round_tile", "black_plastic"),
+                BiomePalette::new("old_bronze", "flat_red"),
+                BiomePalette::new("flat_green", "curly_tile"),
+            ],
+        }
+    }
+}
+
+impl BiomeParams {
+    pub fn build(&self, seed: u32) -> BiomeMap {
+        let params = FractalParams {
+            octaves: 3,
+            ..Default::default()
+        };
+        BiomeMap {
+            temperature: Fbm {
+                source: Simplex::new(seed.wrapping_add(TEMPERATURE_SEED)),
+                params,
+            },
+            moisture: Fbm {
+                source: Simplex::new(seed.wrapping_add(MOISTURE_SEED)),
+                params,
+            },
+            scale: self.scale.max(1.),
+            blend: self.blend,
+            seed,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, materials: &Vec<String>) {
+        ui.checkbox(&mut self.enabled, tr!("biome.enabled"));
+        if !self.enabled {
+            return;
+        }
+        ui.add(egui::Slider::new(&mut self.scale, 64.0..=8192.0).text(tr!("biome.scale")));
+        ui.add(egui::Slider::new(&mut self.blend, 0.0..=0.5).text(tr!("biome.blend")));
+        for (biome, palette) in Biome::ALL.iter().zip(self.palettes.iter_mut()) {
+            ui.label(format!("{}", biome));
+            let surface = tr!("biome.surface", biome = biome);
+            palette.surface =
+                UiState::combo_box(ui, materials, &palette.surface, &surface).to_string();
+            let ground = tr!("biome.ground", biome = biome);
+            palette.ground =
+                UiState::combo_box(ui, materials, &palette.ground, &ground).to_string();
+        }
+    }
+}
+
+pub struct BiomeMap {
+    temperature: Fbm<Simplex>,
+    moisture: Fbm<Simplex>,
+    scale: f64,
+    blend: f64,
+    seed: u32,
+}
+
+impl BiomeMap {
+    pub fn biome_at(&self, x: i32, y: i32) -> Biome {
+        let (nx, ny) = (x as f64 / self.scale, y as f64 / self.scale);
+        let jitter = self.blend * (2. * self.hash(x, y) - 1.);
+        let temperature = self.temperature.get_2d(nx, ny) + jitter;
+        let moisture = self.moisture.get_2d(nx, ny) - jitter;
+        Biome::from_climate(temperature, moisture)
+    }
+
+    /// Uniform in `[0, 1)`, stable for a position and seed.
+    fn hash(&self, x: i32, y: i32) -> f64 {
+        let mut h = (x as u32 as u64) << 32 | y as u32 as u64;
+        h ^= (self.seed as u64).wrapping_mul(0x9E3779B97F4A7C15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D049BB133111EB);
+        h ^= h >> 31;
+        (h >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
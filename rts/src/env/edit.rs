@@ -0,0 +1,191 @@
+use std::collections::{HashMap, VecDeque};
+
+use building_blocks::core::prelude::*;
+use glam::{Vec2, Vec3};
+
+use super::simulation::{MaterialVoxel, Universe};
+
+const MAX_UNDO_STEPS: usize = 64;
+
+/// Voxel writes collected first and applied together, so every touched chunk is marked dirty
+/// once and the whole batch is a single undo step. Later writes to the same voxel replace the
+/// earlier ones. Tools, brushes and scripted edits should build one of these instead of
+/// writing voxels one at a time.
+#[derive(Default)]
+pub struct TerrainEditBatch {
+    edits: HashMap<Point3i, MaterialVoxel>,
+    merge: bool,
+}
+
+impl TerrainEditBatch {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set(&mut self, point: Point3i, voxel: MaterialVoxel) -> &mut Self {
+        self.edits.insert(point, voxel);
+        self
+    }
+
+    pub fn clear(&mut self, point: Point3i) -> &mut Self {
+        self.set(point, MaterialVoxel::empty())
+    }
+
+    /// Adds to the last undo step instead of starting a new one, for strokes spanning frames.
+    pub fn merged(&mut self) -> &mut Self {
+        self.merge = true;
+        self
+    }
+
+    pub fn apply(self, universe: &mut Universe) {
+        universe.apply_edits(self);
+    }
+
+    pub(super) fn into_edits(self) -> (HashMap<Point3i, MaterialVoxel>, bool) {
+        (self.edits, self.merge)
+    }
+}
+
+/// Voxels within `radius` of `center`, just `center` for a radius of 0.
+pub fn sphere_points(center: Point3i, radius: i32) -> Vec<Point3i> {
+    let extent = Extent3i::from_min_and_shape(
+        center - Point3i::fill(radius),
+        Point3i::fill(2 * radius + 1),
+    );
+    extent
+        .iter_points()
+        .filter(|p| {
+            let d = *p - center;
+            d.x() * d.x() + d.y() * d.y() + d.z() * d.z() <= radius * radius
+        })
+        .collect()
+}
+
+/// Copies of every tile edit placed around a vertical axis, for symmetric buildings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Symmetry {
+    Off,
+    /// Mirrored across the plane of constant X through the axis.
+    MirrorX,
+    MirrorY,
+    /// Mirrored across both planes, 4 copies.
+    MirrorXY,
+    /// Turned by 90° steps around the axis, 4 copies.
+    Radial4,
+}
+
+impl std::fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symmetry::Off => write!(f, "{}", tr!("symmetry.off")),
+            Symmetry::MirrorX => write!(f, "{}", tr!("symmetry.mirror_x")),
+            Symmetry::MirrorY => write!(f, "{}", tr!("symmetry.mirror_y")),
+            Symmetry::MirrorXY => write!(f, "{}", tr!("symmetry.mirror_xy")),
+            Symmetry::Radial4 => write!(f, "{}", tr!("symmetry.radial4")),
+        }
+    }
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 5] = [
+        Symmetry::Off,
+        Symmetry::MirrorX,
+        Symmetry::MirrorY,
+        Symmetry::MirrorXY,
+        Symmetry::Radial4,
+    ];
+
+    // xy matrices of the copies, row major, the identity first
+    fn matrices(self) -> &'static [[f32; 4]] {
+        const IDENTITY: [f32; 4] = [1., 0., 0., 1.];
+        match self {
+            Symmetry::Off => &[IDENTITY],
+            Symmetry::MirrorX => &[IDENTITY, [-1., 0., 0., 1.]],
+            Symmetry::MirrorY => &[IDENTITY, [1., 0., 0., -1.]],
+            Symmetry::MirrorXY => &[
+                IDENTITY,
+                [-1., 0., 0., 1.],
+                [1., 0., 0., -1.],
+                [-1., 0., 0., -1.],
+            ],
+            Symmetry::Radial4 => &[
+                IDENTITY,
+                [0., -1., 1., 0.],
+                [-1., 0., 0., -1.],
+                [0., 1., -1., 0.],
+            ],
+        }
+    }
+
+    fn transform(m: &[f32; 4], v: Vec2) -> Vec2 {
+        Vec2::new(m[0] * v.x + m[1] * v.y, m[2] * v.x + m[3] * v.y)
+    }
+
+    /// `point` and its copies around the axis through `center`, which lies on voxel corners
+    /// or voxel centers (xy multiples of 0.5). Copies landing on the same voxel are merged.
+    pub fn points(self, point: Point3i, center: Vec2) -> Vec<Point3i> {
+        let cell = Vec2::new(point.x() as f32 + 0.5, point.y() as f32 + 0.5) - center;
+        let mut points: Vec<Point3i> = vec![];
+        for m in self.matrices() {
+            let p = Self::transform(m, cell) + center;
+            let image = PointN([p.x.floor() as i32, p.y.floor() as i32, point.z()]);
+            if !points.contains(&image) {
+                points.push(image);
+            }
+        }
+        points
+    }
+
+    /// Copies of a `position` and a `direction` at it, for previews.
+    pub fn positions(self, position: Vec3, direction: Vec3, center: Vec2) -> Vec<(Vec3, Vec3)> {
+        let offset = Vec2::new(position.x, position.y) - center;
+        let xy = Vec2::new(direction.x, direction.y);
+        self.matrices()
+            .iter()
+            .map(|m| {
+                let p = Self::transform(m, offset) + center;
+                let d = Self::transform(m, xy);
+                (
+                    Vec3::new(p.x, p.y, position.z),
+                    Vec3::new(d.x, d.y, direction.z),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Previous voxels of the last applied batches, newest last.
+#[derive(Default)]
+pub struct TerrainUndoHistory {
+    steps: VecDeque<Vec<(Point3i, MaterialVoxel)>>,
+}
+
+impl TerrainUndoHistory {
+    pub fn step_count(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    pub(super) fn push(&mut self, previous: Vec<(Point3i, MaterialVoxel)>, merge: bool) {
+        if previous.is_empty() {
+            return;
+        }
+        match self.steps.back_mut() {
+            // undo walks a step backwards, so voxels written twice end with their oldest value
+            Some(step) if merge => step.extend(previous),
+            _ => {
+                self.steps.push_back(previous);
+                if self.steps.len() > MAX_UNDO_STEPS {
+                    self.steps.pop_front();
+                }
+            }
+        }
+    }
+
+    pub(super) fn pop(&mut self) -> Option<Vec<(Point3i, MaterialVoxel)>> {
+        self.steps.pop_back()
+    }
+}
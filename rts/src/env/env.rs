@@ -1,45 +1,106 @@
 use building_blocks::core::prelude::*;
 use distill::loader::handle::Handle;
-use glam::{Quat, Vec3};
-use legion::Resources;
+use glam::{Quat, Vec2, Vec3, Vec4};
+use legion::{IntoQuery, Read, Resources};
 use rafx::{
     assets::{distill_impl::AssetResource, AssetManager},
+    base::Instant,
     renderer::ViewportsResource,
 };
-use rafx_plugins::components::{DirectionalLightComponent, TransformComponent};
+use rafx_plugins::{
+    components::{DirectionalLightComponent, TransformComponent},
+    features::{
+        debug3d::Debug3DResource, egui::EguiContextResource,
+        mesh_adv::MeshAdvRenderOptions as MeshRenderOptions,
+    },
+};
 
+#[cfg(feature = "physics")]
+use super::physics::PhysicsResource;
 use super::{
-    simulation::UniverseId,
-    ui::{EnvUiCmd, TerrainEditUiState, TerrainResetUiState, TileEditUiState, TileSpawnUiState},
+    analysis::TerrainAnalysisResource,
+    edit::{self, Symmetry, TerrainEditBatch},
+    grid_overlay::GridOverlayResource,
+    integrity::IntegrityResource,
+    lighting::LightingResource,
+    road::RoadPainter,
+    save::AutosaveResource,
+    selection::TerrainSelection,
+    simulation::{FootprintError, Foundation, MaterialVoxel, UniverseId},
+    terrain_gen,
+    territory::TerritoryResource,
+    throttle::MeshThrottleResource,
+    ui::{
+        EnvUiCmd, TerrainEditUiState, TerrainResetUiState, TerrainTool, TileEditUiState,
+        TileSpawnUiState, MAX_FOUNDATION_DEPTH,
+    },
+    visibility_updates::VisibilityUpdateResource,
+    weather::WeatherResource,
+    world_edge::WorldEdgeResource,
 };
 use crate::{
     assets::{
         pbr_material::PbrMaterialAsset,
-        tile::{TileAsset, TileExporter},
+        tile::{TileAsset, TileExporter, TileProperties},
         tilesets::{TileSetsAsset, TileSetsExportData, TileSetsExporter},
     },
+    audio::{AudioResource, SoundCategory},
     camera::RTSCamera,
-    env::simulation::{Simulation, TerrainFillStyle, Universe},
+    camera_path::CameraDirectorResource,
+    camera_shake::CameraShakeResource,
+    debug_cull::DebugCullResource,
+    env::simulation::{Simulation, TerrainFillStyle, TerrainMeshStyle, Universe},
     features::dyn_mesh::DynMeshManager,
-    input::{InputResource, KeyboardKey, MouseButton},
+    file_dialog::{DialogPurpose, FileDialogResource},
+    hitch::{HitchResource, HitchStage},
+    input::{InputResource, KeyModifiers, KeyboardKey, MouseButton},
+    labels::{LabelComponent, LabelKind},
+    power::PowerSavingResource,
+    resize::ResizeResource,
+    scenes::GameState,
     time::TimeState,
+    tutorial::TutorialResource,
     ui::{SpawnMode, UiState},
+    unit::{
+        creep::CreepResource,
+        economy::{Cost, PlayerEconomy},
+        tech::TechResource,
+    },
     RenderOptions,
 };
 
 #[derive(Clone)]
 pub struct TileComponent {
     pub asset: Handle<TileAsset>,
+    /// Copied from the tile asset at spawn, with the footprint resolved.
+    pub properties: TileProperties,
+    /// Fraction of `properties.max_health`.
     pub health: f32,
     pub selected: bool,
+    /// Owner, `None` for neutral tiles like the ones in the tile editor.
+    pub team: Option<u8>,
+    /// Team tiles are placed as construction sites, workers finish them with a build task.
+    pub built: bool,
+    /// The terrain voxels the tile replaced, put back when it is demolished.
+    pub ground: Vec<(Point3i, MaterialVoxel)>,
 }
 
 const TILESETS_PATH: &str = "tiles/main.tilesets";
 
+struct TilePlacement {
+    tile: TileAsset,
+    position: Point3i,
+    extent: Extent3i,
+    /// With the foundation to fill first, if it needs one.
+    valid: Result<Option<Foundation>, FootprintError>,
+}
+
 pub struct EnvState {
     tilesets: Handle<TileSetsAsset>,
     main_universe: UniverseId,
     tile_edit_universe: UniverseId,
+    road: RoadPainter,
+    selection: TerrainSelection,
 }
 
 impl EnvState {
@@ -58,14 +119,15 @@ impl EnvState {
             })
             .collect();
 
+        let terrain = resources.get::<TerrainResetUiState>().unwrap();
         let main_universe = simulation.new_universe(
             &dyn_mesh_manager,
             terrain_materials.clone(),
             Point3i::ZERO,
-            4096,
-            TerrainFillStyle::FlatBoard {
-                material: "basic_tile".to_string(),
-            },
+            terrain.size,
+            terrain.style.clone(),
+            terrain.mesh_style,
+            terrain.wrap,
         );
 
         let tile_edit_universe = simulation.new_universe(
@@ -76,6 +138,8 @@ impl EnvState {
             TerrainFillStyle::FlatBoard {
                 material: "basic_tile".to_string(),
             },
+            TerrainMeshStyle::Cubic,
+            false,
         );
 
         simulation.set_active_universe(main_universe);
@@ -84,6 +148,8 @@ impl EnvState {
             tilesets,
             main_universe,
             tile_edit_universe,
+            road: Default::default(),
+            selection: Default::default(),
         }
     }
 
@@ -94,14 +160,40 @@ impl EnvState {
         resources: &mut Resources,
         ui_state: &mut UiState,
     ) {
+        simulation.update_terrain_gen();
+        simulation.drain_removed_voxels();
         let universe = simulation.universe();
+        let idle = resources.get::<PowerSavingResource>().unwrap().is_idle();
+        if let Some(progress) = universe.terrain_gen_progress() {
+            let context = resources.get::<EguiContextResource>().unwrap().context();
+            if terrain_gen::progress_ui(&context, progress) {
+                universe.cancel_terrain_gen();
+            }
+        }
 
         {
             let input = resources.get::<InputResource>().unwrap();
             let time_state = resources.get::<TimeState>().unwrap();
             let mut viewports_resource = resources.get_mut::<ViewportsResource>().unwrap();
             let render_options = resources.get::<RenderOptions>().unwrap();
+            universe.set_chunk_ao(render_options.enable_chunk_ao);
+            universe.set_compact_vertices(render_options.enable_compact_chunk_vertices);
             let mut camera = resources.get_mut::<RTSCamera>().unwrap();
+            let world_edge = resources.get::<WorldEdgeResource>().unwrap();
+            camera.bounds = if universe.wrap {
+                None
+            } else {
+                world_edge.camera_bounds(universe.terrain_bounds())
+            };
+            universe.set_skirt_depth(world_edge.skirt_depth);
+            let mut director = resources.get_mut::<CameraDirectorResource>().unwrap();
+            director.update(time_state.unscaled_dt(), &mut camera);
+            resources
+                .get_mut::<CameraShakeResource>()
+                .unwrap()
+                .update(time_state.unscaled_dt(), &mut camera);
+            camera.update_rotation(time_state.unscaled_dt(), &input, universe);
+            camera.wrap(universe);
 
             camera.update(
                 &*time_state,
@@ -110,6 +202,39 @@ impl EnvState {
                 &mut *viewports_resource,
                 &input,
             );
+            resources.get_mut::<AudioResource>().unwrap().update(
+                time_state.unscaled_dt(),
+                &camera,
+                universe,
+            );
+
+            resources
+                .get_mut::<VisibilityUpdateResource>()
+                .unwrap()
+                .begin_frame(camera.look_at);
+            let mut dyn_mesh_manager = resources.get_mut::<DynMeshManager>().unwrap();
+            dyn_mesh_manager.upload_focus = camera.look_at;
+            let mut cull = resources.get_mut::<DebugCullResource>().unwrap();
+            cull.update(&camera);
+
+            let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+            director.add_debug_draw(&mut debug_draw);
+
+            let mut analysis = resources.get_mut::<TerrainAnalysisResource>().unwrap();
+            analysis.update(universe, camera.look_at);
+            analysis.update_overlay(universe, resources);
+            universe.navmesh.add_debug_draw(&mut debug_draw, &mut cull);
+        }
+
+        {
+            let time_state = resources.get::<TimeState>().unwrap();
+            let look_at = resources.get::<RTSCamera>().unwrap().look_at;
+            let mut weather = resources.get_mut::<WeatherResource>().unwrap();
+            if !idle {
+                weather.update(time_state.scaled_dt(), look_at, universe, resources);
+            }
+            let mut mesh_render_options = resources.get_mut::<MeshRenderOptions>().unwrap();
+            mesh_render_options.ambient_light = weather.fogged_ambient_light();
         }
 
         if let Some(main_light) = universe.main_light {
@@ -121,7 +246,7 @@ impl EnvState {
                         const LIGHT_Z: f32 = 50.0;
                         const LIGHT_ROTATE_SPEED: f32 = 0.2;
                         const LIGHT_LOOP_OFFSET: f32 = 2.0;
-                        let loop_time = time_state.total_time().as_secs_f32();
+                        let loop_time = time_state.simulation_total_time().as_secs_f32();
                         let light_from = Vec3::new(
                             LIGHT_XY_DISTANCE
                                 * f32::cos(LIGHT_ROTATE_SPEED * loop_time + LIGHT_LOOP_OFFSET),
@@ -142,12 +267,171 @@ impl EnvState {
                         light.direction = q.mul_vec3(Vec3::Y);
                     }
                     light.color = ui_state.main_light_color;
-                    light.intensity = ui_state.main_light_intensity;
+                    let weather = resources.get::<WeatherResource>().unwrap();
+                    light.intensity = ui_state.main_light_intensity * weather.light_factor();
                 }
             }
         }
 
-        universe.update_chunks(resources);
+        {
+            let input = resources.get::<InputResource>().unwrap();
+            let camera = resources.get::<RTSCamera>().unwrap();
+            let mut lighting = resources.get_mut::<LightingResource>().unwrap();
+            let clicked_voxel = if lighting.is_placing()
+                && input.is_mouse_just_down(MouseButton::LEFT)
+            {
+                let cursor_pos = input.mouse_position();
+                camera
+                    .ray_cast_terrain(cursor_pos.x as u32, cursor_pos.y as u32, universe, ui_state)
+                    .map(|result| result.hit)
+            } else {
+                None
+            };
+            let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+            lighting.update(universe, ui_state, clicked_voxel, &mut debug_draw);
+        }
+
+        if !idle {
+            let dt = resources.get::<TimeState>().unwrap().unscaled_dt();
+            let mut throttle = resources.get_mut::<MeshThrottleResource>().unwrap();
+            throttle.update(dt);
+            resources
+                .get_mut::<DynMeshManager>()
+                .unwrap()
+                .upload_budget_scale = throttle.upload_scale();
+        }
+        let resizing = resources.get::<ResizeResource>().unwrap().is_resizing();
+        if !idle && !resizing {
+            let start = Instant::now();
+            universe.update_chunks(resources);
+            let sector_visibility = resources
+                .get::<RenderOptions>()
+                .unwrap()
+                .enable_sector_visibility;
+            let view_proj = resources.get::<RTSCamera>().unwrap().view_proj();
+            universe.update_sector_visibility(if sector_visibility {
+                Some(view_proj)
+            } else {
+                None
+            });
+            resources
+                .get_mut::<HitchResource>()
+                .unwrap()
+                .record(HitchStage::ChunkJobs, start);
+        }
+
+        {
+            let time_state = resources.get::<TimeState>().unwrap();
+            let mut integrity = resources.get_mut::<IntegrityResource>().unwrap();
+            let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+            let mut shake = resources.get_mut::<CameraShakeResource>().unwrap();
+            integrity.update(time_state.scaled_dt(), universe, &mut shake);
+            integrity.add_debug_draw(universe, &mut debug_draw);
+            let mut territory = resources.get_mut::<TerritoryResource>().unwrap();
+            territory.update(time_state.unscaled_dt(), universe, resources);
+            let camera = resources.get::<RTSCamera>().unwrap();
+            let mut grid = resources.get_mut::<GridOverlayResource>().unwrap();
+            let placing = ui_state.env.tile_spawn.active;
+            grid.update(
+                time_state.unscaled_dt(),
+                universe,
+                &camera,
+                placing,
+                resources,
+            );
+        }
+
+        #[cfg(feature = "physics")]
+        {
+            let time_state = resources.get::<TimeState>().unwrap();
+            let camera = resources.get::<RTSCamera>().unwrap();
+            let mut physics = resources.get_mut::<PhysicsResource>().unwrap();
+            let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+            physics.update(time_state.scaled_dt(), universe, camera.look_at);
+            physics.add_debug_draw(&mut debug_draw);
+        }
+
+        {
+            let time_state = resources.get::<TimeState>().unwrap();
+            let mut autosave = resources.get_mut::<AutosaveResource>().unwrap();
+            let mut tech = resources.get_mut::<TechResource>().unwrap();
+            let mut creeps = resources.get_mut::<CreepResource>().unwrap();
+            let main_universe = simulation.get_universe_mut(self.main_universe);
+            autosave.update(time_state.scaled_dt(), main_universe, &tech, &creeps);
+            // the units follow in `UnitsState`, once the world is cleared here
+            if let Some(loaded) = autosave.take_loaded() {
+                main_universe.load_terrain(loaded.voxels, loaded.base, loaded.sectors);
+                tech.restore(loaded.tech);
+                creeps.restore(loaded.creeps);
+                self.selection.clear();
+            }
+        }
+
+        self.update_file_dialogs(simulation, resources, ui_state);
+    }
+
+    /// Hands the paths picked in file dialogs to the flows that opened them.
+    fn update_file_dialogs(
+        &self,
+        simulation: &mut Simulation,
+        resources: &Resources,
+        ui_state: &mut UiState,
+    ) {
+        let mut file_dialogs = resources.get_mut::<FileDialogResource>().unwrap();
+        file_dialogs.update();
+        if let Some(dir) = file_dialogs.take(DialogPurpose::SaveGame) {
+            resources
+                .get_mut::<AutosaveResource>()
+                .unwrap()
+                .request_save_to(dir);
+        }
+        if let Some(dir) = file_dialogs.take(DialogPurpose::LoadGame) {
+            resources
+                .get_mut::<AutosaveResource>()
+                .unwrap()
+                .request_load(dir);
+        }
+        if let Some(picked) = file_dialogs.take(DialogPurpose::HeightmapImport) {
+            if let TerrainFillStyle::Heightmap { path, .. } = &mut ui_state.env.terrain_reset.style
+            {
+                *path = picked.to_string_lossy().to_string();
+            }
+        }
+        if let Some(path) = file_dialogs.take(DialogPurpose::TileExport) {
+            let ed = &ui_state.env.tile_edit;
+            if !ed.active {
+                return;
+            }
+            let universe = simulation.get_universe_mut(self.tile_edit_universe);
+            let properties = Self::edited_tile_properties(universe, resources);
+            let tile_name = if ed.tile.is_empty() {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            } else {
+                ed.tile.clone()
+            };
+            if universe
+                .export_edited_tile(&tile_name, properties, &path)
+                .is_some()
+            {
+                log::info!("Exported tile '{}' to {}", tile_name, path.display());
+            } else {
+                ui_state.error(tr!("env.tile_export.failed", path = path.display()));
+            }
+        }
+    }
+
+    // keeps the metadata of the tile being edited, new tiles get the defaults
+    fn edited_tile_properties(universe: &Universe, resources: &Resources) -> TileProperties {
+        let asset_manager = resources.get::<AssetManager>().unwrap();
+        let mut query = <Read<TileComponent>>::query();
+        query
+            .iter(&universe.world)
+            .filter_map(|tile| asset_manager.committed_asset(&tile.asset))
+            .map(|tile| tile.inner.properties)
+            .next()
+            .unwrap_or_default()
     }
 
     pub fn update_ui(
@@ -162,7 +446,7 @@ impl EnvState {
             if let Some(asset) = asset_manager.committed_asset(&self.tilesets) {
                 asset.clone()
             } else {
-                ui.label("Waiting for tilesets asset to load...");
+                ui.label(tr!("env.tilesets_loading"));
                 return;
             }
         };
@@ -171,26 +455,175 @@ impl EnvState {
             tilesets.get_loaded_tilesets(&mut asset_manager)
         };
 
+        let section_top = ui.cursor().top();
         TileSpawnUiState::ui(ui_state, ui, &tilesets);
+        resources
+            .get_mut::<TutorialResource>()
+            .unwrap()
+            .mark_section("tile_spawn", ui, section_top);
         if !ui_state.env.tile_spawn.active && !ui_state.unit.spawning {
             let materials = simulation.universe().get_material_names().clone();
             TileEditUiState::ui(ui_state, ui, &tilesets, |cmd| {
                 self.ui_cmd_handler(cmd, simulation, resources)
             });
-            TerrainEditUiState::ui(ui_state, ui, &materials);
+            let undo_steps = simulation.universe().undo_steps();
+            let section_top = ui.cursor().top();
+            let paused = resources.get::<GameState>().unwrap().is_paused();
+            if TerrainEditUiState::ui(ui_state, ui, &materials, undo_steps) && !paused {
+                simulation.universe().undo_edit();
+            }
+            let terrain_edit = &ui_state.env.terrain_edit;
+            if terrain_edit.active && terrain_edit.tool == TerrainTool::Select {
+                let universe = simulation.universe();
+                self.selection.update(universe);
+                let replace = self.selection.ui(ui, &materials).filter(|_| !paused);
+                if let Some((from, to)) = replace {
+                    let extent = self.selection.extent().unwrap();
+                    let from = universe.voxel_by_material(&from).unwrap();
+                    let to = universe.voxel_by_material(&to).unwrap();
+                    let count = universe.replace_material(extent, from, to);
+                    log::info!("Replaced {} voxels in the selection", count);
+                }
+            }
+            resources
+                .get_mut::<TutorialResource>()
+                .unwrap()
+                .mark_section("terrain_edit", ui, section_top);
             TerrainResetUiState::ui(ui_state, ui, &materials, |cmd| {
                 self.ui_cmd_handler(cmd, simulation, resources)
             });
+            resources.get_mut::<GridOverlayResource>().unwrap().ui(ui);
         }
 
-        if ui_state.env.tile_spawn.active
-            || (ui_state.env.terrain_edit.active && !ui_state.unit.spawning)
+        // the terrain stays as it is while paused, like the units
+        let paused = resources.get::<GameState>().unwrap().is_paused();
+        if !paused
+            && (ui_state.env.tile_spawn.active
+                || (ui_state.env.terrain_edit.active && !ui_state.unit.spawning))
         {
             let input = resources.get::<InputResource>().unwrap();
             let camera = resources.get::<RTSCamera>().unwrap();
             let universe = simulation.universe();
+            let painting_road = !ui_state.env.tile_spawn.active
+                && ui_state.env.terrain_edit.tool == TerrainTool::Road;
+            let selecting = !ui_state.env.tile_spawn.active
+                && ui_state.env.terrain_edit.tool == TerrainTool::Select;
+            if !ui_state.env.tile_spawn.active
+                && input.is_key_chord_just_down(KeyModifiers::CTRL, KeyboardKey::Z)
+            {
+                universe.undo_edit();
+            }
 
-            if input.is_mouse_just_down(MouseButton::LEFT) {
+            let brush_scale = camera.brush_scale();
+            let (symmetry, symmetry_center) = Self::edit_symmetry(ui_state, universe);
+            if !ui_state.env.tile_spawn.active {
+                let cursor_pos = input.mouse_position();
+                let hovered = camera.ray_cast_terrain(
+                    cursor_pos.x as u32,
+                    cursor_pos.y as u32,
+                    universe,
+                    ui_state,
+                );
+                if let Some(result) = hovered {
+                    let ed = &ui_state.env.terrain_edit;
+                    let radius = if painting_road {
+                        ed.scaled_road_width(brush_scale) as f32 / 2.
+                    } else {
+                        ed.brush_radius(brush_scale) as f32 + 0.5
+                    };
+                    // roads follow the ground, the brush builds out of the face under the cursor
+                    let normal = if painting_road {
+                        Vec3::Z
+                    } else {
+                        let n = result.normal;
+                        Vec3::new(n.x() as f32, n.y() as f32, n.z() as f32)
+                    };
+                    let center = Vec3::new(
+                        result.hit.x() as f32 + 0.5,
+                        result.hit.y() as f32 + 0.5,
+                        result.hit.z() as f32 + 0.5,
+                    ) + 0.55 * normal;
+                    let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+                    if selecting {
+                        Self::add_face_highlight(&mut debug_draw, center, normal);
+                    } else if painting_road {
+                        Self::add_brush_preview(&mut debug_draw, center, normal, radius);
+                    } else {
+                        for (center, normal) in symmetry.positions(center, normal, symmetry_center)
+                        {
+                            Self::add_brush_preview(&mut debug_draw, center, normal, radius);
+                            Self::add_face_highlight(&mut debug_draw, center, normal);
+                        }
+                    }
+                }
+            } else {
+                let cursor_pos = input.mouse_position();
+                let hovered = camera.ray_cast_terrain(
+                    cursor_pos.x as u32,
+                    cursor_pos.y as u32,
+                    universe,
+                    ui_state,
+                );
+                ui_state.env.tile_spawn.placement_error = None;
+                if let Some(result) = hovered {
+                    let placement = self.tile_placement(
+                        &ui_state.env.tile_spawn,
+                        result.hit,
+                        universe,
+                        resources,
+                    );
+                    let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+                    Self::add_footprint_preview(
+                        &mut debug_draw,
+                        &placement.extent,
+                        placement.valid.is_ok(),
+                    );
+                    if let Ok(Some(foundation)) = &placement.valid {
+                        Self::add_foundation_preview(&mut debug_draw, foundation);
+                    }
+                    ui_state.env.tile_spawn.placement_error = placement.valid.err();
+                }
+            }
+
+            if selecting {
+                if input.is_mouse_down(MouseButton::LEFT) {
+                    let cursor_pos = input.mouse_position();
+                    let cast_result = camera.ray_cast_terrain(
+                        cursor_pos.x as u32,
+                        cursor_pos.y as u32,
+                        universe,
+                        ui_state,
+                    );
+                    if let Some(result) = cast_result {
+                        self.selection.drag(result.hit);
+                    }
+                } else {
+                    self.selection.end_drag();
+                }
+                let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+                self.selection.add_debug_draw(&mut debug_draw);
+            } else if painting_road {
+                if input.is_mouse_down(MouseButton::LEFT) {
+                    let cursor_pos = input.mouse_position();
+                    let cast_result = camera.ray_cast_terrain(
+                        cursor_pos.x as u32,
+                        cursor_pos.y as u32,
+                        universe,
+                        ui_state,
+                    );
+                    let material = universe.voxel_by_material(&ui_state.env.terrain_edit.material);
+                    if let (Some(result), Some(material)) = (cast_result, material) {
+                        self.road.paint_to(
+                            universe,
+                            result.hit,
+                            ui_state.env.terrain_edit.scaled_road_width(brush_scale),
+                            material,
+                        );
+                    }
+                } else {
+                    self.road.end();
+                }
+            } else if input.is_mouse_just_down(MouseButton::LEFT) {
                 let cursor_pos = input.mouse_position();
                 let (cast_result, default_material) = {
                     let cast_result = camera.ray_cast_terrain(
@@ -206,19 +639,57 @@ impl EnvState {
                 };
                 if let Some(result) = cast_result {
                     if ui_state.env.tile_spawn.active {
-                        self.spawn(
-                            &ui_state.env.tile_spawn.tileset,
-                            &ui_state.env.tile_spawn.tile,
-                            PointN([result.hit.x(), result.hit.y(), result.hit.z() + 1]),
-                            resources,
-                            universe,
-                        );
+                        let spawn = &ui_state.env.tile_spawn;
+                        let placement = self.tile_placement(spawn, result.hit, universe, resources);
+                        let paid = placement.valid.is_ok()
+                            && resources.get_mut::<PlayerEconomy>().unwrap().try_spend(
+                                ui_state.unit.team,
+                                Cost::building(placement.tile.inner.footprint()),
+                                &spawn.tile,
+                            );
+                        if paid {
+                            if let Ok(Some(foundation)) = placement.valid {
+                                let mut batch = TerrainEditBatch::new();
+                                for point in foundation.fill {
+                                    batch.set(point, foundation.material);
+                                }
+                                batch.apply(universe);
+                            }
+                            self.spawn(
+                                &spawn.tileset,
+                                &spawn.tile,
+                                placement.position,
+                                Some(ui_state.unit.team),
+                                resources,
+                                universe,
+                            );
+                        } else if let Err(err) = placement.valid {
+                            let message = format!("Cannot place {} here, {}", spawn.tile, err);
+                            ui_state.error(message);
+                        }
                     } else if ui_state.env.terrain_edit.active {
+                        let radius = ui_state.env.terrain_edit.brush_radius(brush_scale);
+                        let mut batch = TerrainEditBatch::new();
                         if input.is_key_down(KeyboardKey::LControl) {
-                            universe.clear_voxel(result.hit);
+                            for point in edit::sphere_points(result.hit, radius) {
+                                for point in symmetry.points(point, symmetry_center) {
+                                    batch.clear(point);
+                                }
+                            }
+                            #[cfg(feature = "physics")]
+                            Self::spawn_voxel_debris(resources, result.hit);
                         } else {
-                            universe.update_voxel(result.before_hit, default_material);
+                            // grow the terrain out of the clicked face, without overwriting
+                            // what is already there
+                            for point in edit::sphere_points(result.face_neighbor(), radius) {
+                                for point in symmetry.points(point, symmetry_center) {
+                                    if universe.get_voxel(point) == MaterialVoxel::empty() {
+                                        batch.set(point, default_material);
+                                    }
+                                }
+                            }
                         }
+                        batch.apply(universe);
                     }
                 }
                 if ui_state.env.tile_spawn.mode == SpawnMode::OneShot {
@@ -228,6 +699,161 @@ impl EnvState {
         }
     }
 
+    /// Symmetry of the voxel edits and its axis. Only tile editing has one, the axis goes
+    /// through the middle of the footprint of the tile being edited, which is centered on the
+    /// origin of the platform.
+    fn edit_symmetry(ui_state: &UiState, universe: &Universe) -> (Symmetry, Vec2) {
+        let tile_edit = &ui_state.env.tile_edit;
+        if !tile_edit.active {
+            return (Symmetry::Off, Vec2::ZERO);
+        }
+        let mut query = <Read<TileComponent>>::query();
+        let center = query
+            .iter(&universe.world)
+            .next()
+            .and_then(|tile| tile.properties.footprint)
+            .map_or(Vec2::ZERO, |[w, h]| {
+                // odd footprints have a column of voxels on the axis
+                Vec2::new((w % 2) as f32 / 2., (h % 2) as f32 / 2.)
+            });
+        (tile_edit.symmetry, center)
+    }
+
+    /// Where the tile picked in `spawn` goes with the cursor over `hit`, snapped to the lattice,
+    /// and whether it fits there, with the foundation it needs when auto foundation is on.
+    fn tile_placement(
+        &self,
+        spawn: &TileSpawnUiState,
+        hit: Point3i,
+        universe: &Universe,
+        resources: &Resources,
+    ) -> TilePlacement {
+        let tile = self.tile_asset(&spawn.tileset, &spawn.tile, resources);
+        let position = spawn.snap(
+            PointN([hit.x(), hit.y(), hit.z() + 1]),
+            tile.inner.footprint(),
+        );
+        let extent = Universe::tile_extent(&tile, position);
+        let valid = if spawn.auto_foundation {
+            universe
+                .validate_footprint_with_foundation(extent, MAX_FOUNDATION_DEPTH)
+                .map(Some)
+        } else {
+            universe.validate_footprint(extent).map(|_| None)
+        };
+        TilePlacement {
+            tile,
+            position,
+            extent,
+            valid,
+        }
+    }
+
+    /// A vertical stripe through every voxel a foundation fills.
+    fn add_foundation_preview(debug_draw: &mut Debug3DResource, foundation: &Foundation) {
+        let color = Vec4::new(0.9, 0.6, 0.2, 1.);
+        for point in &foundation.fill {
+            let bottom = Vec3::new(
+                point.x() as f32 + 0.5,
+                point.y() as f32 + 0.5,
+                point.z() as f32,
+            );
+            debug_draw.add_line(bottom, bottom + Vec3::Z, color);
+        }
+    }
+
+    /// Outline of the ground a tile would cover, green if it fits and red if it doesn't.
+    fn add_footprint_preview(debug_draw: &mut Debug3DResource, extent: &Extent3i, valid: bool) {
+        let color = if valid {
+            Vec4::new(0.2, 1., 0.2, 1.)
+        } else {
+            Vec4::new(1., 0.2, 0.2, 1.)
+        };
+        let min = extent.minimum;
+        let max = extent.least_upper_bound();
+        let z = min.z() as f32 + 0.05;
+        let corners = [
+            Vec3::new(min.x() as f32, min.y() as f32, z),
+            Vec3::new(max.x() as f32, min.y() as f32, z),
+            Vec3::new(max.x() as f32, max.y() as f32, z),
+            Vec3::new(min.x() as f32, max.y() as f32, z),
+        ];
+        let height = Vec3::new(0., 0., (max.z() - min.z()) as f32);
+        for i in 0..4 {
+            let (a, b) = (corners[i], corners[(i + 1) % 4]);
+            debug_draw.add_line(a, b, color);
+            debug_draw.add_line(a + height, b + height, color);
+            debug_draw.add_line(a, a + height, color);
+        }
+    }
+
+    /// Two unit vectors spanning the plane perpendicular to the axis aligned `normal`.
+    fn face_axes(normal: Vec3) -> (Vec3, Vec3) {
+        if normal.z != 0. {
+            (Vec3::X, Vec3::Y)
+        } else if normal.y != 0. {
+            (Vec3::X, Vec3::Z)
+        } else {
+            (Vec3::Y, Vec3::Z)
+        }
+    }
+
+    /// Ring of the brush on the plane of the hovered face.
+    fn add_brush_preview(
+        debug_draw: &mut Debug3DResource,
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+    ) {
+        const SEGMENTS: usize = 32;
+        let color = Vec4::new(1., 0.8, 0.3, 1.);
+        let (u, v) = Self::face_axes(normal);
+        let point = |i: usize| {
+            let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            center + radius * (angle.cos() * u + angle.sin() * v)
+        };
+        for i in 0..SEGMENTS {
+            debug_draw.add_line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Outline of the voxel face under the cursor, `center` is just off its middle.
+    fn add_face_highlight(debug_draw: &mut Debug3DResource, center: Vec3, normal: Vec3) {
+        let color = Vec4::new(1., 1., 1., 1.);
+        let (u, v) = Self::face_axes(normal);
+        let corners = [
+            center - 0.5 * u - 0.5 * v,
+            center + 0.5 * u - 0.5 * v,
+            center + 0.5 * u + 0.5 * v,
+            center - 0.5 * u + 0.5 * v,
+        ];
+        for i in 0..4 {
+            debug_draw.add_line(corners[i], corners[(i + 1) % 4], color);
+        }
+    }
+
+    #[cfg(feature = "physics")]
+    fn spawn_voxel_debris(resources: &Resources, point: Point3i) {
+        use rand::Rng;
+        let mut physics = resources.get_mut::<PhysicsResource>().unwrap();
+        let mut rng = rand::thread_rng();
+        let center =
+            Vec3::new(point.x() as f32, point.y() as f32, point.z() as f32) + Vec3::splat(0.5);
+        for _ in 0..4 {
+            let offset = Vec3::new(
+                rng.gen_range(-0.3..0.3),
+                rng.gen_range(-0.3..0.3),
+                rng.gen_range(0.0..0.3),
+            );
+            let velocity = Vec3::new(
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(2.0..5.0),
+            );
+            physics.spawn_debris(center + offset, velocity, 0.15);
+        }
+    }
+
     fn ui_cmd_handler(
         &mut self,
         command: EnvUiCmd,
@@ -240,11 +866,13 @@ impl EnvState {
                 tile_name,
             } => {
                 simulation.set_active_universe(self.tile_edit_universe);
+                self.selection.clear();
                 if !tile_name.is_empty() {
                     self.spawn(
                         &tileset_name,
                         &tile_name,
                         Point3i::ZERO,
+                        None,
                         resources,
                         simulation.universe(),
                     );
@@ -255,7 +883,10 @@ impl EnvState {
                 tileset_name,
                 tile_name,
             } => {
-                simulation.universe().save_edited_tile(&tile_name)?;
+                let properties = Self::edited_tile_properties(simulation.universe(), resources);
+                simulation
+                    .universe()
+                    .save_edited_tile(&tile_name, properties)?;
                 if let Some(tileset_name) = tileset_name {
                     let tilesets = {
                         let asset_manager = resources.get::<AssetManager>().unwrap();
@@ -274,6 +905,18 @@ impl EnvState {
                     Some(())
                 }
             }
+            EnvUiCmd::ExportEditedTile { tile_name } => {
+                let file_name = if tile_name.is_empty() {
+                    None
+                } else {
+                    Some(TileExporter::get_tile_file_name(&tile_name))
+                };
+                resources
+                    .get_mut::<FileDialogResource>()
+                    .unwrap()
+                    .open(DialogPurpose::TileExport, file_name.as_deref());
+                Some(())
+            }
             EnvUiCmd::FinishEditTile => {
                 simulation.universe().reset(
                     Point3i::ZERO,
@@ -283,22 +926,61 @@ impl EnvState {
                     },
                 );
                 simulation.set_active_universe(self.main_universe);
+                self.selection.clear();
+                Some(())
+            }
+            EnvUiCmd::BrowseHeightmap => {
+                resources
+                    .get_mut::<FileDialogResource>()
+                    .unwrap()
+                    .open(DialogPurpose::HeightmapImport, None);
                 Some(())
             }
             EnvUiCmd::ResetTerrain(params) => {
-                simulation
-                    .universe()
-                    .reset(Point3i::ZERO, params.size, params.style.clone());
+                let universe = simulation.universe();
+                universe.mesh_style = params.mesh_style;
+                universe.wrap = params.wrap;
+                universe.start_reset(Point3i::ZERO, params.size, params.style.clone());
+                self.selection.clear();
+                resources
+                    .get_mut::<CreepResource>()
+                    .unwrap()
+                    .start(params.creeps, params.size);
                 Some(())
             }
         }
     }
 
+    fn tile_asset(&self, tileset_name: &str, tile_name: &str, resources: &Resources) -> TileAsset {
+        let tilesets = {
+            let asset_manager = resources.get::<AssetManager>().unwrap();
+            asset_manager
+                .committed_asset(&self.tilesets)
+                .unwrap()
+                .clone()
+        };
+        let tilesets = {
+            let mut asset_manager = resources.get_mut::<AssetManager>().unwrap();
+            tilesets.get_loaded_tilesets(&mut asset_manager)
+        };
+        let tileset = tilesets
+            .iter()
+            .find(|tileset| &tileset.name == tileset_name)
+            .unwrap();
+        tileset
+            .tiles
+            .iter()
+            .find(|tile| &tile.inner.name == tile_name)
+            .unwrap()
+            .clone()
+    }
+
     pub fn spawn(
         &self,
         tileset_name: &str,
         tile_name: &str,
         position: Point3i,
+        team: Option<u8>,
         resources: &Resources,
         universe: &mut Universe,
     ) {
@@ -314,47 +996,58 @@ impl EnvState {
             rotation: Quat::IDENTITY,
         };
 
+        let tile = self.tile_asset(tileset_name, tile_name, resources);
+
+        // update voxels
+        let ground = universe.instance_tile(&tile, position);
+
         // tile component
         let tile_component = TileComponent {
             asset: {
                 let asset_resource = resources.get::<AssetResource>().unwrap();
                 asset_resource.load_asset_path(TileExporter::get_tile_path(tile_name, false))
             },
+            properties: tile.inner.resolved_properties(),
             health: 1.,
             selected: false,
+            team,
+            built: team.is_none(),
+            ground,
         };
 
-        // entity
+        // entity, labelled above the middle of the footprint
+        let [width, depth] = tile.inner.footprint();
+        let label = LabelComponent::new(
+            tile_name,
+            LabelKind::Building,
+            Vec3::new(width as f32 / 2., depth as f32 / 2., BUILDING_LABEL_HEIGHT),
+        );
         log::info!("Spawn tile {} at: {}", tile_name, translation);
-        let _entity = universe.world.push((transform_component, tile_component));
+        let entity = universe
+            .world
+            .push((transform_component, tile_component, label));
+        universe.register_entity(entity);
 
-        // update voxels
-        let tile = {
-            let tilesets = {
-                let asset_manager = resources.get::<AssetManager>().unwrap();
-                asset_manager
-                    .committed_asset(&self.tilesets)
-                    .unwrap()
-                    .clone()
-            };
-            let tilesets = {
-                let mut asset_manager = resources.get_mut::<AssetManager>().unwrap();
-                tilesets.get_loaded_tilesets(&mut asset_manager)
-            };
-            let tileset = tilesets
-                .iter()
-                .find(|tileset| &tileset.name == tileset_name)
-                .unwrap();
-            tileset
-                .tiles
-                .iter()
-                .find(|tile| &tile.inner.name == tile_name)
-                .unwrap()
-                .clone()
-        };
+        resources.get_mut::<AudioResource>().unwrap().play(
+            "build",
+            SoundCategory::Effects,
+            Some(translation),
+            Some(BUILD_SOUND_DURATION),
+        );
 
-        universe.instance_tile(&tile, position);
+        let area = (width * depth) as f32;
+        if area >= LARGE_TILE_AREA {
+            resources
+                .get_mut::<CameraShakeResource>()
+                .unwrap()
+                .add_trauma((area / 1000.).min(0.6), translation);
+        }
     }
 }
 
 const TILE_EDIT_PLATFORM_SIZE: i32 = 32;
+const BUILD_SOUND_DURATION: f32 = 1.5; // s
+const BUILDING_LABEL_HEIGHT: f32 = 8.; // m, above the tile origin
+
+// placing tiles with a footprint at least this large shakes the camera
+const LARGE_TILE_AREA: f32 = 64.; // voxels
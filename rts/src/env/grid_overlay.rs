@@ -0,0 +1,196 @@
+use building_blocks::core::prelude::*;
+use glam::Vec3;
+use legion::Resources;
+
+use super::{
+    overlay_mesh::OverlayQuads,
+    simulation::{ChunkGeometry, Universe},
+};
+use crate::camera::RTSCamera;
+
+const OVERLAY_OWNER: &str = "grid";
+const LINE_WIDTH: f32 = 0.05; // voxels, at the zoom scaling reference distance
+const BUILD_LINE_SCALE: f32 = 2.5;
+// above the surface, at the zoom scaling reference distance like the width, so depth precision
+// lost with distance doesn't make the lines z-fight with the terrain
+const LINE_LIFT: f32 = 0.02; // voxels
+
+// the grid follows the camera in steps of this
+const FOCUS_STEP: i32 = 8; // voxels
+
+// rebuilt this often while shown, for terrain changes `edit_count` doesn't see, cheap since
+// the universe caches the column tops of unchanged chunks
+const REFRESH_INTERVAL: f32 = 2.; // s
+
+/// What the grid mesh was built for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct GridKey {
+    focus: [i32; 2],
+    voxel_grid: bool,
+    build_grid: bool,
+    build_cell: i32,
+    radius: i32,
+    // line width, in half octaves of the zoom factor
+    width_step: i32,
+    edit_count: u64,
+}
+
+/// Grid lines laid on the terrain surface around the camera target, one per voxel edge and
+/// thicker ones on the build grid. They are overlay meshes of the universe, so unlike debug
+/// draw they are depth tested and lit with the terrain, and their width follows the zoom so
+/// they stay visible from far away.
+pub struct GridOverlayResource {
+    pub voxel_grid: bool,
+    pub build_grid: bool,
+    /// Shows the build grid while placing tiles, even when it is off.
+    pub build_grid_when_placing: bool,
+    pub build_cell: i32, // voxels
+    /// Half the side of the square covered around the camera target.
+    pub radius: i32, // voxels
+    pub voxel_material: String,
+    pub build_material: String,
+    built: Option<GridKey>,
+    timer: f32, // s
+}
+
+impl Default for GridOverlayResource {
+    fn default() -> Self {
+        Self {
+            voxel_grid: false,
+            build_grid: false,
+            build_grid_when_placing: true,
+            build_cell: 8,
+            radius: 48,
+            voxel_material: "black_plastic".to_string(),
+            build_material: "flat_green".to_string(),
+            built: None,
+            timer: 0.,
+        }
+    }
+}
+
+impl GridOverlayResource {
+    pub fn update(
+        &mut self,
+        dt: f32,
+        universe: &mut Universe,
+        camera: &RTSCamera,
+        placing: bool,
+        resources: &Resources,
+    ) {
+        let key_point = Point3i::ZERO;
+        let build_grid = self.build_grid || (placing && self.build_grid_when_placing);
+        if !self.voxel_grid && !build_grid {
+            if self.built.take().is_some() {
+                universe.set_overlay_mesh(OVERLAY_OWNER, key_point, Vec3::ZERO, None, resources);
+            }
+            return;
+        }
+
+        let width_step = (camera.brush_scale().log2() * 2.).round() as i32;
+        let focus = [
+            (camera.look_at.x as i32).div_euclid(FOCUS_STEP) * FOCUS_STEP,
+            (camera.look_at.y as i32).div_euclid(FOCUS_STEP) * FOCUS_STEP,
+        ];
+        let key = GridKey {
+            focus,
+            voxel_grid: self.voxel_grid,
+            build_grid,
+            build_cell: self.build_cell.max(1),
+            radius: self.radius,
+            width_step,
+            edit_count: universe.edit_count(),
+        };
+        self.timer -= dt;
+        // the universe drops its overlays when the terrain is reset
+        let current = self.built == Some(key)
+            && self.timer > 0.
+            && universe.has_overlay_mesh(OVERLAY_OWNER, key_point);
+        if current {
+            return;
+        }
+
+        let (geometry, position) = match self.geometry(universe, &key) {
+            Some((geometry, position)) => (Some(geometry), position),
+            None => (None, Vec3::ZERO),
+        };
+        if universe.set_overlay_mesh(OVERLAY_OWNER, key_point, position, geometry, resources) {
+            self.built = Some(key);
+            self.timer = REFRESH_INTERVAL;
+        }
+    }
+
+    fn geometry(&self, universe: &mut Universe, key: &GridKey) -> Option<(ChunkGeometry, Vec3)> {
+        let material_names = universe.get_material_names();
+        let material = |name: &str| match material_names.iter().position(|m| m == name) {
+            Some(material) => Some(material as u16),
+            None => {
+                log::error!("Grid material {} not found", name);
+                None
+            }
+        };
+        let voxel_material = material(&self.voxel_material)?;
+        let build_material = material(&self.build_material)?;
+
+        let min = [key.focus[0] - key.radius, key.focus[1] - key.radius];
+        let side = 2 * key.radius;
+        let columns: Vec<(i32, i32)> = (min[1]..min[1] + side)
+            .flat_map(|y| (min[0]..min[0] + side).map(move |x| (x, y)))
+            .collect();
+        let tops = universe.column_tops(&columns);
+
+        let zoom = 2f32.powf(key.width_step as f32 / 2.);
+        let width = LINE_WIDTH * zoom;
+        let lift = LINE_LIFT * zoom;
+        let mut quads = OverlayQuads::default();
+        for ((x, y), top) in columns.iter().zip(tops) {
+            let z = match top {
+                Some(top) => (top + 1) as f32 + lift,
+                None => continue,
+            };
+            let (x, y) = (*x, *y);
+            // the lines on the min x and min y edges of the top face
+            for (on_build_line, along) in [
+                (x.rem_euclid(key.build_cell) == 0, Vec3::Y),
+                (y.rem_euclid(key.build_cell) == 0, Vec3::X),
+            ] {
+                let (material, half_width) = if key.build_grid && on_build_line {
+                    (build_material, width * BUILD_LINE_SCALE / 2.)
+                } else if key.voxel_grid {
+                    (voxel_material, width / 2.)
+                } else {
+                    continue;
+                };
+                let start = Vec3::new(x as f32, y as f32, z);
+                let across = along.cross(Vec3::Z) * half_width;
+                quads.add(
+                    material,
+                    [
+                        start - across,
+                        start + across,
+                        start + across + along,
+                        start - across + along,
+                    ],
+                    Vec3::Z,
+                    along,
+                );
+            }
+        }
+        quads.build()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("grid"))
+            .id_source("grid overlay")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.voxel_grid, tr!("grid.voxel"));
+                ui.checkbox(&mut self.build_grid, tr!("grid.build"));
+                ui.checkbox(&mut self.build_grid_when_placing, tr!("grid.when_placing"));
+                ui.add(
+                    egui::Slider::new(&mut self.build_cell, 2..=32).text(tr!("grid.build_cell")),
+                );
+                ui.add(egui::Slider::new(&mut self.radius, 16..=128).text(tr!("grid.radius")));
+            });
+    }
+}
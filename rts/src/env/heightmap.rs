@@ -0,0 +1,60 @@
+use std::path::Path;
+
+/// Grayscale height field with samples normalized to `0..=1`.
+/// Any format the `image` crate decodes works; 16 bit grayscale PNGs keep full precision.
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    samples: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .map_err(|err| format!("Cannot load heightmap {}: {}", path.display(), err))?
+            .to_luma16();
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Err(format!("Heightmap {} is empty", path.display()));
+        }
+        let samples = image
+            .pixels()
+            .map(|p| p.0[0] as f32 / u16::MAX as f32)
+            .collect();
+        log::info!("Loaded {}x{} heightmap {}", width, height, path.display());
+        Ok(Self {
+            width,
+            height,
+            samples,
+        })
+    }
+
+    fn sample(&self, x: u32, y: u32) -> f32 {
+        self.samples[(y.min(self.height - 1) * self.width + x.min(self.width - 1)) as usize]
+    }
+
+    /// Bilinear sample at normalized coordinates `u, v` in `0..=1`.
+    pub fn get_height(&self, u: f32, v: f32) -> f32 {
+        let x = u.clamp(0., 1.) * (self.width - 1) as f32;
+        let y = v.clamp(0., 1.) * (self.height - 1) as f32;
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (tx, ty) = (x.fract(), y.fract());
+        let top = self.sample(x0, y0) * (1. - tx) + self.sample(x0 + 1, y0) * tx;
+        let bottom = self.sample(x0, y0 + 1) * (1. - tx) + self.sample(x0 + 1, y0 + 1) * tx;
+        top * (1. - ty) + bottom * ty
+    }
+
+    /// Resamples the heightmap to a `size` x `size` grid of voxel heights in `0..=vertical_scale`.
+    pub fn resample(&self, size: u32, vertical_scale: f32) -> Vec<i32> {
+        let denom = (size.max(2) - 1) as f32;
+        let mut heights = Vec::with_capacity((size * size) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let h = self.get_height(x as f32 / denom, y as f32 / denom);
+                heights.push((h * vertical_scale).round() as i32);
+            }
+        }
+        heights
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use legion::{Entity, EntityStore, IntoQuery, Read, World};
+use rafx_plugins::components::{TransformComponent, VisibilityComponent};
+
+// deeper chains are treated as cycles
+const MAX_DEPTH: usize = 16;
+
+/// Positions an entity relative to `parent`. The entity's `TransformComponent` becomes its world
+/// transform, recomputed from the parent's and `local` by `update_world_transforms`. Children
+/// of moving parents should register dynamic visibility objects.
+#[derive(Clone, Copy)]
+pub struct ParentComponent {
+    pub parent: Entity,
+    pub local: TransformComponent,
+}
+
+pub fn combine(parent: &TransformComponent, local: &TransformComponent) -> TransformComponent {
+    TransformComponent {
+        translation: parent.translation + parent.rotation * (parent.scale * local.translation),
+        rotation: parent.rotation * local.rotation,
+        scale: parent.scale * local.scale,
+    }
+}
+
+pub fn children_of(world: &World, parent: Entity) -> Vec<Entity> {
+    let mut query = <(Entity, Read<ParentComponent>)>::query();
+    query
+        .iter(world)
+        .filter(|(_, p)| p.parent == parent)
+        .map(|(entity, _)| *entity)
+        .collect()
+}
+
+fn resolve(
+    world: &World,
+    entity: Entity,
+    parents: &HashMap<Entity, ParentComponent>,
+    resolved: &mut HashMap<Entity, Option<TransformComponent>>,
+    depth: usize,
+) -> Option<TransformComponent> {
+    if let Some(transform) = resolved.get(&entity) {
+        return *transform;
+    }
+    let transform = match parents.get(&entity) {
+        Some(parent) if depth < MAX_DEPTH => {
+            resolve(world, parent.parent, parents, resolved, depth + 1)
+                .map(|parent_transform| combine(&parent_transform, &parent.local))
+        }
+        Some(_) => None,
+        None => world
+            .entry_ref(entity)
+            .ok()
+            .and_then(|entry| entry.get_component::<TransformComponent>().ok().copied()),
+    };
+    resolved.insert(entity, transform);
+    transform
+}
+
+/// Computes the world transform of every entity with a `ParentComponent`, parents first, and
+/// moves its visibility object. Must run after gameplay moved the roots
+/// and before extract. Returns the children whose root is gone (or that are part of a cycle),
+/// they are left untouched for the caller to despawn.
+#[profiling::function]
+pub fn update_world_transforms(world: &mut World) -> Vec<Entity> {
+    let mut query = <(Entity, Read<ParentComponent>)>::query();
+    let parents: HashMap<_, _> = query
+        .iter(world)
+        .map(|(entity, parent)| (*entity, *parent))
+        .collect();
+    if parents.is_empty() {
+        return vec![];
+    }
+
+    let mut resolved = HashMap::new();
+    let mut orphans = vec![];
+    let mut transforms = vec![];
+    for entity in parents.keys() {
+        match resolve(world, *entity, &parents, &mut resolved, 0) {
+            Some(transform) => transforms.push((*entity, transform)),
+            None => orphans.push(*entity),
+        }
+    }
+
+    for (entity, transform) in transforms {
+        if let Some(mut entry) = world.entry(entity) {
+            if let Ok(current) = entry.get_component_mut::<TransformComponent>() {
+                *current = transform;
+            } else {
+                entry.add_component(transform);
+            }
+            if let Ok(visibility) = entry.get_component::<VisibilityComponent>() {
+                visibility.visibility_object_handle.set_transform(
+                    transform.translation,
+                    transform.rotation,
+                    transform.scale,
+                );
+            }
+        }
+    }
+
+    orphans
+}
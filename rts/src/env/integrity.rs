@@ -0,0 +1,321 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy_tasks::{Task, TaskPool, TaskPoolBuilder};
+use building_blocks::{core::prelude::*, storage::prelude::*};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use glam::{Quat, Vec3, Vec4};
+use legion::{Entity, IntoQuery, Read, Write};
+use rafx_plugins::{components::TransformComponent, features::debug3d::Debug3DResource};
+
+use super::simulation::{MaterialVoxel, Universe, UniverseId};
+use crate::camera_shake::CameraShakeResource;
+
+// clusters reaching this far from the removed voxel are assumed to be supported
+const SEARCH_RADIUS: i32 = 24; // voxels
+const MAX_DEBRIS: usize = 4096;
+const DEBRIS_LIFETIME: f32 = 3.; // s
+const GRAVITY: f32 = 9.81; // m/s²
+
+// collapsing clusters this large shake the camera at full strength
+const SHAKE_CLUSTER_SIZE: f32 = 500.; // voxels
+
+const NEIGHBORS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+fn neighbors(p: Point3i) -> impl Iterator<Item = Point3i> {
+    NEIGHBORS.iter().map(move |d| p + PointN(*d))
+}
+
+struct SupportJob {
+    voxels: Array3x1<MaterialVoxel>,
+    removed: Vec<Point3i>,
+    ground_level: i32,
+}
+
+impl SupportJob {
+    /// Flood fills from the solid neighbors of every removed voxel. A fill that reaches the
+    /// ground, a supported voxel or the border of the extracted region stops early and is
+    /// supported, the others are returned as collapsing clusters.
+    fn run(self) -> Vec<Vec<(Point3i, MaterialVoxel)>> {
+        let extent = *self.voxels.extent();
+        let border = extent.padded(-1);
+        let mut supported = HashSet::new();
+        let mut collapsing = HashSet::new();
+        let mut clusters = vec![];
+        for removed in &self.removed {
+            for seed in neighbors(*removed) {
+                if !extent.contains(seed)
+                    || self.voxels.get(seed).is_empty()
+                    || supported.contains(&seed)
+                    || collapsing.contains(&seed)
+                {
+                    continue;
+                }
+                let mut visited = HashSet::new();
+                let mut queue = VecDeque::new();
+                visited.insert(seed);
+                queue.push_back(seed);
+                let mut grounded = false;
+                'fill: while let Some(p) = queue.pop_front() {
+                    if p.z() <= self.ground_level || !border.contains(p) {
+                        grounded = true;
+                        break;
+                    }
+                    for next in neighbors(p) {
+                        if self.voxels.get(next).is_empty() {
+                            continue;
+                        }
+                        if supported.contains(&next) {
+                            grounded = true;
+                            break 'fill;
+                        }
+                        if visited.insert(next) {
+                            queue.push_back(next);
+                        }
+                    }
+                }
+                if grounded {
+                    supported.extend(visited);
+                } else {
+                    clusters.push(visited.iter().map(|p| (*p, self.voxels.get(*p))).collect());
+                    collapsing.extend(visited);
+                }
+            }
+        }
+        clusters
+    }
+}
+
+/// A voxel of a collapsed cluster falling from where it was, until it lands on the terrain or
+/// expires. Its entity has a `TransformComponent` at the voxel's center.
+pub struct DebrisComponent {
+    pub voxel: MaterialVoxel,
+    pub velocity: Vec3,
+    pub age: f32,
+}
+
+/// Optional rule making voxel clusters without a path to the ground collapse. Voxels removed by
+/// edits are checked in a background task, on a copy of the region around them. Unsupported
+/// clusters are cleared from the map and fall as debris entities.
+pub struct IntegrityResource {
+    pub enabled: bool,
+    // the universe the pending checks and the running job belong to
+    universe: Option<UniverseId>,
+    pending: Vec<Point3i>,
+    task_pool: TaskPool,
+    task: Option<Task<()>>,
+    result_tx: Sender<Vec<Vec<(Point3i, MaterialVoxel)>>>,
+    result_rx: Receiver<Vec<Vec<(Point3i, MaterialVoxel)>>>,
+    debris: usize,
+    collapsed: usize,
+}
+
+impl Default for IntegrityResource {
+    fn default() -> Self {
+        let (result_tx, result_rx) = unbounded();
+        Self {
+            enabled: false,
+            universe: None,
+            pending: vec![],
+            task_pool: TaskPoolBuilder::new().num_threads(1).build(),
+            task: None,
+            result_tx,
+            result_rx,
+            debris: 0,
+            collapsed: 0,
+        }
+    }
+}
+
+impl IntegrityResource {
+    /// Forgets the pending checks, the debris entities go with the world.
+    pub fn reset(&mut self) {
+        self.universe = None;
+        self.pending.clear();
+        self.task = None;
+        for _ in self.result_rx.try_iter() {}
+        self.debris = 0;
+        self.collapsed = 0;
+    }
+
+    #[profiling::function]
+    pub fn update(&mut self, dt: f32, universe: &mut Universe, shake: &mut CameraShakeResource) {
+        // checks of another universe don't apply to this one
+        if self.universe != Some(universe.id()) {
+            self.pending.clear();
+            self.task = None;
+            for _ in self.result_rx.try_iter() {}
+            self.universe = Some(universe.id());
+            self.debris = <Read<DebrisComponent>>::query()
+                .iter(&universe.world)
+                .count();
+        }
+        let removed = universe.take_removed_voxels();
+        if self.enabled {
+            self.pending.extend(removed);
+        }
+
+        for clusters in self.result_rx.try_iter() {
+            self.task = None;
+            for cluster in clusters {
+                // skip clusters edited while the job was running, they get checked again
+                if cluster
+                    .iter()
+                    .any(|(p, voxel)| universe.get_voxel(*p) != *voxel)
+                {
+                    continue;
+                }
+                self.collapsed += cluster.len();
+                let (p, _) = cluster[0];
+                shake.add_trauma(
+                    (cluster.len() as f32 / SHAKE_CLUSTER_SIZE).min(1.),
+                    Vec3::new(p.x() as f32, p.y() as f32, p.z() as f32),
+                );
+                let room = MAX_DEBRIS.saturating_sub(self.debris);
+                self.debris += cluster.len().min(room);
+                universe
+                    .world
+                    .extend(cluster.iter().take(room).map(|(p, voxel)| {
+                        (
+                            TransformComponent {
+                                translation: Vec3::new(p.x() as f32, p.y() as f32, p.z() as f32)
+                                    + Vec3::splat(0.5),
+                                rotation: Quat::IDENTITY,
+                                scale: Vec3::ONE,
+                            },
+                            DebrisComponent {
+                                voxel: *voxel,
+                                velocity: Vec3::ZERO,
+                                age: 0.,
+                            },
+                        )
+                    }));
+                universe.update_voxels(
+                    cluster
+                        .into_iter()
+                        .map(|(p, _)| (p, MaterialVoxel::empty())),
+                );
+            }
+            // the collapse's own removals leave nothing unsupported, they need no check
+            universe.take_removed_voxels();
+        }
+
+        if self.task.is_none() && !self.pending.is_empty() {
+            self.start_job(universe);
+        }
+
+        self.update_debris(dt, universe);
+    }
+
+    /// Moves the debris entities, stopping them on solid voxels, and removes the expired ones.
+    fn update_debris(&mut self, dt: f32, universe: &mut Universe) {
+        if self.debris == 0 {
+            return;
+        }
+        let step = |transform: &TransformComponent, debris: &DebrisComponent| {
+            transform.translation + (debris.velocity - Vec3::Z * GRAVITY * dt) * dt
+        };
+        let landed: HashSet<Entity> =
+            <(Entity, Read<TransformComponent>, Read<DebrisComponent>)>::query()
+                .iter(&universe.world)
+                .filter(|(_, transform, debris)| {
+                    let below = step(transform, debris) - Vec3::Z * 0.5;
+                    let below = PointN([
+                        below.x.floor() as i32,
+                        below.y.floor() as i32,
+                        below.z.floor() as i32,
+                    ]);
+                    !universe.get_voxel(below).is_empty()
+                })
+                .map(|(entity, _, _)| *entity)
+                .collect();
+
+        let mut expired = vec![];
+        let mut query = <(Entity, Write<TransformComponent>, Write<DebrisComponent>)>::query();
+        for (entity, transform, debris) in query.iter_mut(&mut universe.world) {
+            debris.age += dt;
+            if debris.age >= DEBRIS_LIFETIME {
+                expired.push(*entity);
+            } else if landed.contains(entity) {
+                debris.velocity = Vec3::ZERO;
+            } else {
+                transform.translation = step(transform, debris);
+                debris.velocity.z -= GRAVITY * dt;
+            }
+        }
+        self.debris -= expired.len();
+        for entity in expired {
+            universe.world.remove(entity);
+        }
+    }
+
+    fn start_job(&mut self, universe: &Universe) {
+        // one neighborhood per job, the rest waits for the next one
+        let first = self.pending[0];
+        let near = |p: &Point3i| {
+            let d = *p - first;
+            d.x().abs().max(d.y().abs()).max(d.z().abs()) <= SEARCH_RADIUS
+        };
+        let removed: Vec<_> = self.pending.iter().cloned().filter(near).collect();
+        self.pending.retain(|p| !near(p));
+
+        let extent = removed[1..].iter().fold(
+            Extent3i::from_min_and_shape(first, Point3i::ONES),
+            |extent, p| {
+                Extent3i::from_min_and_lub(
+                    extent.minimum.meet(*p),
+                    extent.least_upper_bound().join(*p + Point3i::ONES),
+                )
+            },
+        );
+        let extent = extent.padded(SEARCH_RADIUS);
+        let job = SupportJob {
+            voxels: universe.extract_voxels(&extent),
+            removed,
+            ground_level: universe.ground_level(),
+        };
+        let result_tx = self.result_tx.clone();
+        self.task = Some(self.task_pool.spawn(async move {
+            let _result = result_tx.send(job.run());
+        }));
+    }
+
+    pub fn add_debug_draw(&self, universe: &Universe, debug_draw: &mut Debug3DResource) {
+        if self.debris == 0 {
+            return;
+        }
+        let mut query = <(Read<TransformComponent>, Read<DebrisComponent>)>::query();
+        for (transform, debris) in query.iter(&universe.world) {
+            let alpha = 1. - debris.age / DEBRIS_LIFETIME;
+            debug_draw.add_sphere(
+                transform.translation,
+                0.5,
+                Vec4::new(0.6, 0.45, 0.3, alpha),
+                6,
+            );
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("integrity"))
+            .id_source("structural integrity")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("integrity.enabled"));
+                ui.label(tr!(
+                    "integrity.stats",
+                    collapsed = self.collapsed,
+                    falling = self.debris
+                ));
+                if self.task.is_some() || !self.pending.is_empty() {
+                    ui.label(tr!("integrity.checking", count = self.pending.len()));
+                }
+            });
+    }
+}
@@ -0,0 +1,524 @@
+use std::path::{Path, PathBuf};
+
+use building_blocks::core::prelude::*;
+use glam::{Quat, Vec3, Vec4};
+use legion::Entity;
+use rafx_plugins::{
+    components::{
+        DirectionalLightComponent, PointLightComponent, SpotLightComponent, TransformComponent,
+    },
+    features::debug3d::Debug3DResource,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{env::simulation::Universe, ui::UiState};
+
+const PRESET_DIR: &str = "lighting";
+const PRESET_EXTENSION: &str = "lighting";
+/// Loaded when a scene starts, if present.
+pub const DEFAULT_PRESET: &str = "default";
+const PLACE_HEIGHT: f32 = 4.; // voxels above the clicked voxel
+const GIZMO_COLOR: [f32; 4] = [1., 1., 0.2, 1.];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot,
+}
+
+impl std::fmt::Display for LightKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LightKind::Directional => tr!("lighting.directional"),
+            LightKind::Point => tr!("lighting.point"),
+            LightKind::Spot => tr!("lighting.spot"),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One light of a preset. Directions are given as yaw (around Z, from +X) and pitch (below the
+/// horizon), in degrees.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LightDesc {
+    pub kind: LightKind,
+    pub position: Vec3, // unused by directional lights
+    pub yaw: f32,       // deg, unused by point lights
+    pub pitch: f32,     // deg, unused by point lights
+    pub color: Vec4,
+    pub intensity: f32,
+    pub range: f32,      // voxels, unused by directional lights
+    pub half_angle: f32, // deg, spot lights only
+}
+
+impl LightDesc {
+    pub fn new(kind: LightKind, position: Vec3) -> Self {
+        let (intensity, range) = match kind {
+            LightKind::Directional => (1., 0.),
+            LightKind::Point => (130., 25.),
+            LightKind::Spot => (500., 12.),
+        };
+        Self {
+            kind,
+            position,
+            yaw: 0.,
+            pitch: 60.,
+            color: Vec4::ONE,
+            intensity,
+            range,
+            half_angle: 40.,
+        }
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        Vec3::new(
+            yaw.cos() * pitch.cos(),
+            yaw.sin() * pitch.cos(),
+            -pitch.sin(),
+        )
+    }
+
+    fn spawn(&self, universe: &mut Universe) -> Entity {
+        let transform = TransformComponent {
+            translation: self.position,
+            scale: Vec3::ONE,
+            rotation: Quat::IDENTITY,
+        };
+        match self.kind {
+            LightKind::Directional => universe.world.push((DirectionalLightComponent {
+                direction: self.direction(),
+                color: self.color,
+                intensity: self.intensity,
+                view_frustum: universe.visibility_region.register_view_frustum(),
+            },)),
+            LightKind::Point => {
+                let region = &universe.visibility_region;
+                let light = PointLightComponent {
+                    color: self.color,
+                    intensity: self.intensity,
+                    range: self.range,
+                    view_frustums: [
+                        region.register_view_frustum(),
+                        region.register_view_frustum(),
+                        region.register_view_frustum(),
+                        region.register_view_frustum(),
+                        region.register_view_frustum(),
+                        region.register_view_frustum(),
+                    ],
+                };
+                universe.world.push((transform, light))
+            }
+            LightKind::Spot => {
+                let light = SpotLightComponent {
+                    direction: self.direction(),
+                    color: self.color,
+                    intensity: self.intensity,
+                    range: self.range,
+                    spotlight_half_angle: self.half_angle.to_radians(),
+                    view_frustum: universe.visibility_region.register_view_frustum(),
+                };
+                universe.world.push((transform, light))
+            }
+        }
+    }
+
+    /// Copies the settings into the components of `entity`, false if it is gone.
+    fn apply(&self, universe: &mut Universe, entity: Entity) -> bool {
+        let mut entry = match universe.world.entry(entity) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if let Ok(transform) = entry.get_component_mut::<TransformComponent>() {
+            transform.translation = self.position;
+        }
+        match self.kind {
+            LightKind::Directional => {
+                if let Ok(light) = entry.get_component_mut::<DirectionalLightComponent>() {
+                    light.direction = self.direction();
+                    light.color = self.color;
+                    light.intensity = self.intensity;
+                    return true;
+                }
+            }
+            LightKind::Point => {
+                if let Ok(light) = entry.get_component_mut::<PointLightComponent>() {
+                    light.color = self.color;
+                    light.intensity = self.intensity;
+                    light.range = self.range;
+                    return true;
+                }
+            }
+            LightKind::Spot => {
+                if let Ok(light) = entry.get_component_mut::<SpotLightComponent>() {
+                    light.direction = self.direction();
+                    light.color = self.color;
+                    light.intensity = self.intensity;
+                    light.range = self.range;
+                    light.spotlight_half_angle = self.half_angle.to_radians();
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(tr!("lighting.color"));
+            let mut rgb = [self.color.x, self.color.y, self.color.z];
+            if ui.color_edit_button_rgb(&mut rgb).changed() {
+                self.color = Vec4::new(rgb[0], rgb[1], rgb[2], 1.);
+            }
+        });
+        let max_intensity = match self.kind {
+            LightKind::Directional => 10.,
+            _ => 1000.,
+        };
+        ui.add(
+            egui::Slider::new(&mut self.intensity, 0.0..=max_intensity)
+                .logarithmic(true)
+                .text(tr!("lighting.intensity")),
+        );
+        if self.kind != LightKind::Directional {
+            ui.add(egui::Slider::new(&mut self.range, 1.0..=100.).text(tr!("lighting.range")));
+            ui.add(
+                egui::Slider::new(&mut self.position.z, -64.0..=128.).text(tr!("lighting.height")),
+            );
+        }
+        if self.kind != LightKind::Point {
+            ui.add(egui::Slider::new(&mut self.yaw, -180.0..=180.).text(tr!("lighting.yaw")));
+            ui.add(egui::Slider::new(&mut self.pitch, -90.0..=90.).text(tr!("lighting.pitch")));
+        }
+        if self.kind == LightKind::Spot {
+            ui.add(
+                egui::Slider::new(&mut self.half_angle, 1.0..=89.).text(tr!("lighting.half_angle")),
+            );
+        }
+    }
+}
+
+/// Settings of the scene's main directional light, which `UiState` owns.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MainLightDesc {
+    pub rotates: bool,
+    pub pitch: f32, // deg
+    pub color: Vec4,
+    pub intensity: f32,
+}
+
+impl MainLightDesc {
+    fn from_ui_state(ui_state: &UiState) -> Self {
+        Self {
+            rotates: ui_state.main_light_rotates,
+            pitch: ui_state.main_light_pitch,
+            color: ui_state.main_light_color,
+            intensity: ui_state.main_light_intensity,
+        }
+    }
+
+    fn apply(&self, ui_state: &mut UiState) {
+        ui_state.main_light_rotates = self.rotates;
+        ui_state.main_light_pitch = self.pitch;
+        ui_state.main_light_color = self.color;
+        ui_state.main_light_intensity = self.intensity;
+    }
+}
+
+/// The main light plus the lights added in the editor, stored as RON in
+/// `lighting/<scene>/<name>.lighting`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LightingPreset {
+    pub main_light: MainLightDesc,
+    pub lights: Vec<LightDesc>,
+}
+
+impl LightingPreset {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        ron::de::from_str(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let contents =
+            ron::ser::to_string_pretty(self, Default::default()).map_err(|err| err.to_string())?;
+        std::fs::write(path, contents).map_err(|err| err.to_string())
+    }
+}
+
+struct EditorLight {
+    desc: LightDesc,
+    entity: Option<Entity>,
+}
+
+impl From<LightDesc> for EditorLight {
+    fn from(desc: LightDesc) -> Self {
+        Self { desc, entity: None }
+    }
+}
+
+/// Runtime editor for the lights of a scene, on top of the main directional light. Point and
+/// spot lights are placed by clicking the terrain. The lights are regular light entities in
+/// the universe world, respawned if the world is reset. Presets are saved per scene, and the
+/// `DEFAULT_PRESET` of a scene is loaded when it starts.
+pub struct LightingResource {
+    lights: Vec<EditorLight>,
+    // entities of deleted lights, despawned in `update`
+    removed: Vec<Entity>,
+    selected: Option<usize>,
+    // kind of light to add, or `None` with `selected` set to move the selected light
+    placing: Option<Option<LightKind>>,
+    show_gizmos: bool,
+    scene: String,
+    // the default preset is loaded on the first update of a scene
+    load_default: bool,
+    preset_name: String,
+    message: Option<String>,
+}
+
+impl Default for LightingResource {
+    fn default() -> Self {
+        Self {
+            lights: vec![],
+            removed: vec![],
+            selected: None,
+            placing: None,
+            show_gizmos: true,
+            scene: "main".to_string(),
+            load_default: false,
+            preset_name: DEFAULT_PRESET.to_string(),
+            message: None,
+        }
+    }
+}
+
+impl LightingResource {
+    /// Drops the editor lights (the world they live in is reset along with the scene), the
+    /// default preset of `scene` is loaded on the next update, if there is one.
+    pub fn start_scene(&mut self, scene: &str) {
+        *self = Self {
+            scene: scene.to_string(),
+            load_default: true,
+            show_gizmos: self.show_gizmos,
+            ..Default::default()
+        };
+    }
+
+    pub fn is_placing(&self) -> bool {
+        self.placing.is_some()
+    }
+
+    fn preset_path(&self, name: &str) -> PathBuf {
+        Path::new(PRESET_DIR)
+            .join(&self.scene)
+            .join(format!("{}.{}", name, PRESET_EXTENSION))
+    }
+
+    fn preset(&self, ui_state: &UiState) -> LightingPreset {
+        LightingPreset {
+            main_light: MainLightDesc::from_ui_state(ui_state),
+            lights: self.lights.iter().map(|light| light.desc.clone()).collect(),
+        }
+    }
+
+    fn apply_preset(&mut self, preset: LightingPreset, ui_state: &mut UiState) {
+        preset.main_light.apply(ui_state);
+        self.removed
+            .extend(self.lights.drain(..).filter_map(|light| light.entity));
+        self.lights = preset.lights.into_iter().map(EditorLight::from).collect();
+        self.selected = None;
+    }
+
+    fn load(&mut self, path: &Path, ui_state: &mut UiState) {
+        self.message = Some(match LightingPreset::load(path) {
+            Ok(preset) => {
+                self.apply_preset(preset, ui_state);
+                tr!("lighting.loaded", path = path.display())
+            }
+            Err(err) => err,
+        });
+    }
+
+    fn add(&mut self, desc: LightDesc) {
+        self.lights.push(desc.into());
+        self.selected = Some(self.lights.len() - 1);
+    }
+
+    fn remove(&mut self, idx: usize) {
+        let light = self.lights.remove(idx);
+        self.removed.extend(light.entity);
+        self.selected = None;
+    }
+
+    /// Places the pending light on a left click, and syncs the light entities with the editor.
+    pub fn update(
+        &mut self,
+        universe: &mut Universe,
+        ui_state: &mut UiState,
+        clicked_voxel: Option<Point3i>,
+        debug_draw: &mut Debug3DResource,
+    ) {
+        if self.load_default {
+            self.load_default = false;
+            let path = self.preset_path(DEFAULT_PRESET);
+            if path.exists() {
+                self.load(&path, ui_state);
+            }
+        }
+        if let (Some(placing), Some(voxel)) = (self.placing, clicked_voxel) {
+            let position = Vec3::new(voxel.x() as f32, voxel.y() as f32, voxel.z() as f32)
+                + Vec3::new(0.5, 0.5, PLACE_HEIGHT);
+            match (placing, self.selected) {
+                (Some(kind), _) => self.add(LightDesc::new(kind, position)),
+                (None, Some(idx)) => self.lights[idx].desc.position = position,
+                (None, None) => {}
+            }
+            self.placing = None;
+        }
+
+        for entity in self.removed.drain(..) {
+            universe.world.remove(entity);
+        }
+        for light in &mut self.lights {
+            let alive = match light.entity {
+                Some(entity) => light.desc.apply(universe, entity),
+                None => false,
+            };
+            if !alive {
+                light.entity = Some(light.desc.spawn(universe));
+            }
+        }
+
+        if self.show_gizmos {
+            for (idx, light) in self.lights.iter().enumerate() {
+                let desc = &light.desc;
+                let color = if Some(idx) == self.selected {
+                    GIZMO_COLOR.into()
+                } else {
+                    desc.color
+                };
+                match desc.kind {
+                    LightKind::Directional => {}
+                    LightKind::Point => {
+                        debug_draw.add_sphere(desc.position, 0.25, color, 12);
+                        if Some(idx) == self.selected {
+                            debug_draw.add_sphere(desc.position, desc.range, color, 16);
+                        }
+                    }
+                    LightKind::Spot => {
+                        let length = if Some(idx) == self.selected {
+                            desc.range
+                        } else {
+                            2.
+                        };
+                        debug_draw.add_cone(
+                            desc.position,
+                            desc.position + desc.direction() * length,
+                            length * desc.half_angle.to_radians().tan(),
+                            color,
+                            10,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, ui_state: &mut UiState) {
+        egui::CollapsingHeader::new(tr!("lighting"))
+            .id_source("lighting editor")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr!("lighting.add"));
+                    if ui.button(LightKind::Point.to_string()).clicked() {
+                        self.placing = Some(Some(LightKind::Point));
+                    }
+                    if ui.button(LightKind::Spot.to_string()).clicked() {
+                        self.placing = Some(Some(LightKind::Spot));
+                    }
+                    if ui.button(LightKind::Directional.to_string()).clicked() {
+                        self.add(LightDesc::new(LightKind::Directional, Vec3::ZERO));
+                    }
+                });
+                if self.placing.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.label(tr!("lighting.click"));
+                        if ui.button(tr!("lighting.cancel")).clicked() {
+                            self.placing = None;
+                        }
+                    });
+                }
+                ui.checkbox(&mut self.show_gizmos, tr!("lighting.gizmos"));
+
+                let mut remove = None;
+                for (idx, light) in self.lights.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let p = light.desc.position;
+                        let label = match light.desc.kind {
+                            LightKind::Directional => format!("{} {}", idx, light.desc.kind),
+                            _ => tr!(
+                                "lighting.light_at",
+                                index = idx,
+                                kind = light.desc.kind,
+                                position = format!("({:.0}, {:.0}, {:.0})", p.x, p.y, p.z)
+                            ),
+                        };
+                        if ui
+                            .selectable_label(self.selected == Some(idx), label)
+                            .clicked()
+                        {
+                            self.selected = if self.selected == Some(idx) {
+                                None
+                            } else {
+                                Some(idx)
+                            };
+                        }
+                        if ui.small_button("x").clicked() {
+                            remove = Some(idx);
+                        }
+                    });
+                }
+                if let Some(idx) = remove {
+                    self.remove(idx);
+                }
+
+                if let Some(idx) = self.selected {
+                    let light = &mut self.lights[idx];
+                    ui.separator();
+                    light.desc.ui(ui);
+                    if light.desc.kind != LightKind::Directional
+                        && ui.button(tr!("lighting.move")).clicked()
+                    {
+                        self.placing = Some(None);
+                    }
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label(tr!("lighting.preset"));
+                    ui.add(egui::TextEdit::singleline(&mut self.preset_name).desired_width(100.));
+                });
+                ui.horizontal(|ui| {
+                    let path = self.preset_path(self.preset_name.trim());
+                    if ui.button(tr!("lighting.save")).clicked() {
+                        self.message = Some(match self.preset(ui_state).save(&path) {
+                            Ok(()) => tr!("lighting.saved", path = path.display()),
+                            Err(err) => err,
+                        });
+                    }
+                    if ui.button(tr!("lighting.load")).clicked() {
+                        self.load(&path, ui_state);
+                    }
+                });
+                if let Some(message) = &self.message {
+                    ui.label(message);
+                }
+            });
+    }
+}
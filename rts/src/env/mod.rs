@@ -1,4 +1,28 @@
+pub mod analysis;
+pub mod biome;
+pub mod edit;
 pub mod env;
-pub mod perlin;
+pub mod grid_overlay;
+pub mod heightmap;
+pub mod hierarchy;
+pub mod integrity;
+pub mod lighting;
+pub mod navmesh;
+pub mod noise;
+pub mod overlay_mesh;
+#[cfg(feature = "physics")]
+pub mod physics;
+pub mod ray_accel;
+pub mod road;
+pub mod save;
+pub mod schedule;
+pub mod selection;
 pub mod simulation;
+pub mod stable_id;
+pub mod terrain_gen;
+pub mod territory;
+pub mod throttle;
 pub mod ui;
+pub mod visibility_updates;
+pub mod weather;
+pub mod world_edge;
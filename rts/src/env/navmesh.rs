@@ -0,0 +1,541 @@
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, BTreeMap, BinaryHeap, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use building_blocks::{core::prelude::*, storage::prelude::*};
+use glam::{Vec2, Vec3, Vec4};
+use legion::{IntoQuery, Read, World};
+use rafx_plugins::{components::TransformComponent, features::debug3d::Debug3DResource};
+
+use super::{env::TileComponent, simulation::MaterialVoxels};
+use crate::debug_cull::DebugCullResource;
+
+// the chunk size, so an edited chunk invalidates exactly one tile
+const TILE_SIZE: i32 = 16; // voxels
+
+// A* gives up after expanding this many rects, the unit then walks straight to its target
+const MAX_SEARCH_NODES: usize = 4096;
+// portals are narrowed by this much at both ends, so paths don't graze corners
+const PORTAL_MARGIN: f32 = 0.4; // m
+
+// unit origins are this far above the surface they stand on
+const UNIT_HEIGHT: f32 = 1.; // m
+
+/// A walkable area of a tile, at a constant height. `min` is inclusive and `max` exclusive,
+/// `z` is the first empty voxel above the ground.
+#[derive(Clone, Copy, Debug)]
+struct NavRect {
+    min: (i32, i32),
+    max: (i32, i32),
+    z: i32,
+}
+
+impl NavRect {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.min.0 && x < self.max.0 && y >= self.min.1 && y < self.max.1
+    }
+
+    fn center(&self) -> Vec2 {
+        Vec2::new(
+            (self.min.0 + self.max.0) as f32 / 2.,
+            (self.min.1 + self.max.1) as f32 / 2.,
+        )
+    }
+}
+
+type RectId = ((i32, i32), usize);
+
+struct OpenNode {
+    estimate: f32,
+    id: RectId,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    // reversed, so the heap pops the cheapest node first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Walkable surface of the terrain as axis aligned rects, for ground unit paths. A voxel column
+/// is walkable on top of a solid voxel with `clearance` empty voxels above it, outside the
+/// footprints of tiles that aren't walkable, except on top of walls. The walkable cells of a
+/// 16x16 tile are greedily merged into rects per height, and two rects touching along an edge
+/// connect when their heights differ by at most `max_step`. Tiles are built when a path query first reaches them,
+/// and dropped when their chunks are edited. Paths are searched over the rects with A*, then
+/// pulled tight through the shared edges with a funnel, so units walk straight across open
+/// ground and only turn at corners.
+pub struct NavMesh {
+    pub max_step: i32,  // voxels of climb between neighboring cells
+    pub clearance: i32, // voxels
+    pub show: bool,
+    tiles: HashMap<(i32, i32), Vec<NavRect>>,
+    blockers_hash: u64,
+    last_path: Vec<Vec3>,
+}
+
+impl Default for NavMesh {
+    fn default() -> Self {
+        Self {
+            max_step: 1,
+            clearance: 2,
+            show: false,
+            tiles: HashMap::new(),
+            blockers_hash: 0,
+            last_path: vec![],
+        }
+    }
+}
+
+impl NavMesh {
+    fn tile_key(x: i32, y: i32) -> (i32, i32) {
+        (x.div_euclid(TILE_SIZE), y.div_euclid(TILE_SIZE))
+    }
+
+    /// Drops the tile over the chunk at `chunk_min`, it is rebuilt on the next query reaching it.
+    pub fn invalidate(&mut self, chunk_min: Point3i) {
+        self.tiles
+            .remove(&Self::tile_key(chunk_min.x(), chunk_min.y()));
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+        self.last_path.clear();
+    }
+
+    /// Waypoints from `from` to `to` in walking order, ending at `to`. `None` when either end is
+    /// off the mesh or the search gave up.
+    #[profiling::function]
+    pub fn find_path(
+        &mut self,
+        voxels: &MaterialVoxels,
+        world: &World,
+        from: Vec3,
+        to: Vec3,
+    ) -> Option<Vec<Vec3>> {
+        let blockers = Self::blockers(world);
+        let mut hasher = DefaultHasher::new();
+        blockers.hash(&mut hasher);
+        let blockers_hash = hasher.finish();
+        if blockers_hash != self.blockers_hash {
+            self.tiles.clear();
+            self.blockers_hash = blockers_hash;
+        }
+
+        let start = self.locate(voxels, &blockers, from)?;
+        let goal = self.locate(voxels, &blockers, to)?;
+        let rects = self.search(voxels, &blockers, start, goal, to.truncate())?;
+        let path = self.string_pull(&rects, from.truncate(), to);
+        self.last_path = std::iter::once(from).chain(path.iter().copied()).collect();
+        Some(path)
+    }
+
+    // footprints of the tiles that aren't walkable, and the height cells inside them have to
+    // be at to stay walkable: the top of walls, nothing for other tiles
+    fn blockers(world: &World) -> Vec<[i32; 5]> {
+        let mut query = <(Read<TransformComponent>, Read<TileComponent>)>::query();
+        query
+            .iter(world)
+            .filter(|(_, tile)| !tile.properties.walkable)
+            .filter_map(|(transform, tile)| {
+                let [w, h] = tile.properties.footprint?;
+                let min_x = transform.translation.x.floor() as i32 - w as i32 / 2;
+                let min_y = transform.translation.y.floor() as i32 - h as i32 / 2;
+                let min_z = if tile.properties.wall {
+                    transform.translation.z.floor() as i32 + 1
+                } else {
+                    i32::MAX
+                };
+                Some([min_x, min_y, min_x + w as i32, min_y + h as i32, min_z])
+            })
+            .collect()
+    }
+
+    fn tile(
+        &mut self,
+        voxels: &MaterialVoxels,
+        blockers: &[[i32; 5]],
+        key: (i32, i32),
+    ) -> &Vec<NavRect> {
+        let clearance = self.clearance.max(1);
+        self.tiles
+            .entry(key)
+            .or_insert_with(|| Self::build_tile(voxels, blockers, key, clearance))
+    }
+
+    fn build_tile(
+        voxels: &MaterialVoxels,
+        blockers: &[[i32; 5]],
+        key: (i32, i32),
+        clearance: i32,
+    ) -> Vec<NavRect> {
+        let (x0, y0) = (key.0 * TILE_SIZE, key.1 * TILE_SIZE);
+        let bounds = voxels.bounding_extent(0);
+        let column = Extent3i::from_min_and_shape(
+            PointN([x0, y0, bounds.minimum.z()]),
+            PointN([TILE_SIZE, TILE_SIZE, bounds.shape.z().max(1)]),
+        );
+        let mut z_ranges = vec![];
+        voxels.visit_occupied_chunks(0, &column, |chunk| {
+            let extent = chunk.extent();
+            z_ranges.push(extent.minimum.z()..extent.least_upper_bound().z());
+        });
+        let solid = |x: i32, y: i32, z: i32| !voxels.get_point(0, PointN([x, y, z])).is_empty();
+        // `z` is the first empty voxel above the cell
+        let blocked = |x: i32, y: i32, z: i32| {
+            blockers.iter().any(|[bx0, by0, bx1, by1, bz]| {
+                x >= *bx0 && x < *bx1 && y >= *by0 && y < *by1 && z < *bz
+            })
+        };
+
+        let cell = |x: i32, y: i32| (y * TILE_SIZE + x) as usize;
+        let mut layers: BTreeMap<i32, Vec<bool>> = BTreeMap::new();
+        for z_range in z_ranges {
+            for z in z_range {
+                for y in 0..TILE_SIZE {
+                    for x in 0..TILE_SIZE {
+                        let (vx, vy) = (x0 + x, y0 + y);
+                        if solid(vx, vy, z)
+                            && (1..=clearance).all(|dz| !solid(vx, vy, z + dz))
+                            && !blocked(vx, vy, z + 1)
+                        {
+                            layers
+                                .entry(z + 1)
+                                .or_insert_with(|| vec![false; (TILE_SIZE * TILE_SIZE) as usize])
+                                [cell(x, y)] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut rects = vec![];
+        for (z, mut cells) in layers {
+            for y in 0..TILE_SIZE {
+                let mut x = 0;
+                while x < TILE_SIZE {
+                    if !cells[cell(x, y)] {
+                        x += 1;
+                        continue;
+                    }
+                    let mut x1 = x + 1;
+                    while x1 < TILE_SIZE && cells[cell(x1, y)] {
+                        x1 += 1;
+                    }
+                    let mut y1 = y + 1;
+                    while y1 < TILE_SIZE && (x..x1).all(|i| cells[cell(i, y1)]) {
+                        y1 += 1;
+                    }
+                    for j in y..y1 {
+                        for i in x..x1 {
+                            cells[cell(i, j)] = false;
+                        }
+                    }
+                    rects.push(NavRect {
+                        min: (x0 + x, y0 + y),
+                        max: (x0 + x1, y0 + y1),
+                        z,
+                    });
+                    x = x1;
+                }
+            }
+        }
+        rects
+    }
+
+    /// The rect under `p`, closest in height when several levels overlap.
+    fn locate(
+        &mut self,
+        voxels: &MaterialVoxels,
+        blockers: &[[i32; 5]],
+        p: Vec3,
+    ) -> Option<RectId> {
+        let (x, y) = (p.x.floor() as i32, p.y.floor() as i32);
+        let key = Self::tile_key(x, y);
+        self.tile(voxels, blockers, key)
+            .iter()
+            .enumerate()
+            .filter(|(_, rect)| rect.contains(x, y))
+            .min_by(|(_, a), (_, b)| {
+                let da = (a.z as f32 + UNIT_HEIGHT - p.z).abs();
+                let db = (b.z as f32 + UNIT_HEIGHT - p.z).abs();
+                da.partial_cmp(&db).unwrap_or(Ordering::Equal)
+            })
+            .map(|(idx, _)| (key, idx))
+    }
+
+    fn rect(&self, id: RectId) -> NavRect {
+        self.tiles[&id.0][id.1]
+    }
+
+    /// The edge shared by `a` and `b`, if they touch along one and the step is low enough.
+    fn portal(&self, a: &NavRect, b: &NavRect) -> Option<(Vec2, Vec2)> {
+        if (a.z - b.z).abs() > self.max_step {
+            return None;
+        }
+        let x = if a.max.0 == b.min.0 {
+            Some(a.max.0)
+        } else if b.max.0 == a.min.0 {
+            Some(a.min.0)
+        } else {
+            None
+        };
+        if let Some(x) = x {
+            let (y0, y1) = (a.min.1.max(b.min.1), a.max.1.min(b.max.1));
+            return (y1 > y0).then(|| {
+                (
+                    Vec2::new(x as f32, y0 as f32),
+                    Vec2::new(x as f32, y1 as f32),
+                )
+            });
+        }
+        let y = if a.max.1 == b.min.1 {
+            Some(a.max.1)
+        } else if b.max.1 == a.min.1 {
+            Some(a.min.1)
+        } else {
+            None
+        };
+        let y = y?;
+        let (x0, x1) = (a.min.0.max(b.min.0), a.max.0.min(b.max.0));
+        (x1 > x0).then(|| {
+            (
+                Vec2::new(x0 as f32, y as f32),
+                Vec2::new(x1 as f32, y as f32),
+            )
+        })
+    }
+
+    /// Connected rects of `id` with the portal midpoints, from its own and the 4 adjacent tiles.
+    fn neighbors(
+        &mut self,
+        voxels: &MaterialVoxels,
+        blockers: &[[i32; 5]],
+        id: RectId,
+    ) -> Vec<(RectId, Vec2)> {
+        let rect = self.rect(id);
+        let (tx, ty) = id.0;
+        let mut neighbors = vec![];
+        for key in [
+            (tx, ty),
+            (tx - 1, ty),
+            (tx + 1, ty),
+            (tx, ty - 1),
+            (tx, ty + 1),
+        ] {
+            let candidates = self.tile(voxels, blockers, key).clone();
+            for (idx, other) in candidates.iter().enumerate() {
+                if (key, idx) == id {
+                    continue;
+                }
+                if let Some((p0, p1)) = self.portal(&rect, other) {
+                    neighbors.push(((key, idx), (p0 + p1) / 2.));
+                }
+            }
+        }
+        neighbors
+    }
+
+    /// A* over the rects, with costs measured between portal midpoints.
+    fn search(
+        &mut self,
+        voxels: &MaterialVoxels,
+        blockers: &[[i32; 5]],
+        start: RectId,
+        goal: RectId,
+        target: Vec2,
+    ) -> Option<Vec<RectId>> {
+        let mut open = BinaryHeap::new();
+        let mut entries: HashMap<RectId, (f32, Vec2)> = HashMap::new();
+        let mut came_from: HashMap<RectId, RectId> = HashMap::new();
+        let start_point = self.rect(start).center();
+        entries.insert(start, (0., start_point));
+        open.push(OpenNode {
+            estimate: (target - start_point).length(),
+            id: start,
+        });
+        let mut expanded = 0;
+        while let Some(OpenNode { id, .. }) = open.pop() {
+            if id == goal {
+                let mut rects = vec![id];
+                let mut current = id;
+                while let Some(previous) = came_from.get(&current) {
+                    rects.push(*previous);
+                    current = *previous;
+                }
+                rects.reverse();
+                return Some(rects);
+            }
+            expanded += 1;
+            if expanded > MAX_SEARCH_NODES {
+                log::debug!("Path search gave up after {} rects", MAX_SEARCH_NODES);
+                return None;
+            }
+            let (cost, point) = entries[&id];
+            let z = self.rect(id).z;
+            for (next, entry) in self.neighbors(voxels, blockers, id) {
+                let climb = (self.rect(next).z - z).abs() as f32;
+                let next_cost = cost + (entry - point).length() + climb;
+                if entries.get(&next).map_or(true, |(c, _)| next_cost < *c) {
+                    entries.insert(next, (next_cost, entry));
+                    came_from.insert(next, id);
+                    open.push(OpenNode {
+                        estimate: next_cost + (target - entry).length(),
+                        id: next,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Funnel algorithm over the portals of `rects`, keeping only the corners the straight line
+    /// from `from` to `to` has to bend around.
+    fn string_pull(&self, rects: &[RectId], from: Vec2, to: Vec3) -> Vec<Vec3> {
+        // (left, right, height) as seen walking through, narrowed by `PORTAL_MARGIN`
+        let mut portals = vec![(from, from, 0.)];
+        for pair in rects.windows(2) {
+            let (a, b) = (self.rect(pair[0]), self.rect(pair[1]));
+            let (p0, p1) = match self.portal(&a, &b) {
+                Some(portal) => portal,
+                None => continue,
+            };
+            let margin = PORTAL_MARGIN.min((p1 - p0).length() / 2.);
+            let along = (p1 - p0).normalize() * margin;
+            let (p0, p1) = (p0 + along, p1 - along);
+            let heading = b.center() - a.center();
+            let z = a.z.max(b.z) as f32 + UNIT_HEIGHT;
+            if cross(heading, p0 - (p0 + p1) / 2.) > 0. {
+                portals.push((p0, p1, z));
+            } else {
+                portals.push((p1, p0, z));
+            }
+        }
+        let end = to.truncate();
+        portals.push((end, end, to.z));
+
+        let mut path = vec![];
+        let (mut apex, mut left, mut right) = (from, from, from);
+        let (mut apex_idx, mut left_idx, mut right_idx) = (0, 0, 0);
+        let mut i = 1;
+        while i < portals.len() {
+            let (portal_left, portal_right, _) = portals[i];
+            if cross(right - apex, portal_right - apex) >= 0. {
+                if apex == right || apex == left || cross(left - apex, portal_right - apex) < 0. {
+                    right = portal_right;
+                    right_idx = i;
+                } else {
+                    let z = portals[left_idx].2;
+                    path.push(Vec3::new(left.x, left.y, z));
+                    apex = left;
+                    apex_idx = left_idx;
+                    right = apex;
+                    right_idx = apex_idx;
+                    i = apex_idx + 1;
+                    continue;
+                }
+            }
+            if cross(left - apex, portal_left - apex) <= 0. {
+                if apex == left || apex == right || cross(right - apex, portal_left - apex) > 0. {
+                    left = portal_left;
+                    left_idx = i;
+                } else {
+                    let z = portals[right_idx].2;
+                    path.push(Vec3::new(right.x, right.y, z));
+                    apex = right;
+                    apex_idx = right_idx;
+                    left = apex;
+                    left_idx = apex_idx;
+                    i = apex_idx + 1;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        path.dedup_by(|a, b| a.truncate() == b.truncate());
+        path.retain(|corner| corner.truncate() != from && corner.truncate() != end);
+        path.push(to);
+        path
+    }
+
+    /// Outlines of the tiles built so far, and the last path found.
+    pub fn add_debug_draw(&self, debug_draw: &mut Debug3DResource, cull: &mut DebugCullResource) {
+        if !self.show {
+            return;
+        }
+        let color = Vec4::new(0.2, 0.8, 1., 1.);
+        for rect in self.tiles.values().flatten() {
+            let z = rect.z as f32 + 0.1;
+            let (x0, y0) = (rect.min.0 as f32, rect.min.1 as f32);
+            let (x1, y1) = (rect.max.0 as f32, rect.max.1 as f32);
+            if !cull.is_line_visible(Vec3::new(x0, y0, z), Vec3::new(x1, y1, z)) {
+                continue;
+            }
+            let corners = [
+                Vec3::new(x0, y0, z),
+                Vec3::new(x1, y0, z),
+                Vec3::new(x1, y1, z),
+                Vec3::new(x0, y1, z),
+            ];
+            for idx in 0..4 {
+                debug_draw.add_line(corners[idx], corners[(idx + 1) % 4], color);
+            }
+        }
+        let path_color = Vec4::new(1., 1., 0.2, 1.);
+        for pair in self.last_path.windows(2) {
+            debug_draw.add_line(pair[0], pair[1], path_color);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("navmesh"))
+            .id_source("navigation mesh")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.show, tr!("navmesh.show"));
+                let mut changed = ui
+                    .add(egui::Slider::new(&mut self.max_step, 0..=4).text(tr!("navmesh.max_step")))
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.clearance, 1..=8)
+                            .text(tr!("navmesh.clearance")),
+                    )
+                    .changed();
+                if changed {
+                    self.clear();
+                }
+                ui.label(tr!(
+                    "navmesh.stats",
+                    tiles = self.tiles.len(),
+                    rects = self.tiles.values().map(|rects| rects.len()).sum::<usize>()
+                ));
+                if ui.button(tr!("navmesh.rebuild")).clicked() {
+                    self.clear();
+                }
+            });
+    }
+}
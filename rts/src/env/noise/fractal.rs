@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+
+use super::{NoiseFn2D, NoiseFn3D};
+
+// shifts every octave so their lattice origins don't line up
+const OCTAVE_OFFSET: f64 = 31.7;
+const RIDGED_OFFSET: f64 = 1.;
+const RIDGED_GAIN: f64 = 2.;
+
+/// Octave parameters shared by the fractal combinators:
+///
+/// * `octaves` - The number of source samples summed together.
+/// * `frequency` - Frequency of the first octave.
+/// * `lacunarity` - Frequency multiplier between successive octaves.
+/// * `persistence` - Amplitude multiplier between successive octaves.
+///
+/// The sums are normalized by the total amplitude, so the output stays in `[-1, 1]`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct FractalParams {
+    pub octaves: u32,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+}
+
+impl Default for FractalParams {
+    fn default() -> Self {
+        Self {
+            octaves: 6,
+            frequency: 1.,
+            lacunarity: 2.,
+            persistence: 0.5,
+        }
+    }
+}
+
+impl FractalParams {
+    fn octaves(&self) -> impl Iterator<Item = (f64, f64, f64)> {
+        let params = *self;
+        (0..params.octaves.max(1)).map(move |octave| {
+            let frequency = params.frequency * params.lacunarity.powi(octave as i32);
+            let amplitude = params.persistence.powi(octave as i32);
+            (frequency, amplitude, octave as f64 * OCTAVE_OFFSET)
+        })
+    }
+
+    fn sum<F: FnMut(f64, f64) -> f64>(&self, mut sample: F) -> f64 {
+        let mut total = 0.;
+        let mut total_amplitude = 0.;
+        for (frequency, amplitude, offset) in self.octaves() {
+            total += sample(frequency, offset) * amplitude;
+            total_amplitude += amplitude;
+        }
+        if total_amplitude > 0. {
+            total / total_amplitude
+        } else {
+            0.
+        }
+    }
+}
+
+/// Fractal Brownian motion, the plain sum of octaves.
+pub struct Fbm<N> {
+    pub source: N,
+    pub params: FractalParams,
+}
+
+impl<N: NoiseFn2D> NoiseFn2D for Fbm<N> {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        self.params
+            .sum(|f, o| self.source.get_2d(x * f + o, y * f + o))
+    }
+}
+
+impl<N: NoiseFn3D> NoiseFn3D for Fbm<N> {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.params
+            .sum(|f, o| self.source.get_3d(x * f + o, y * f + o, z * f + o))
+    }
+}
+
+/// Sum of folded octaves (`2|n| - 1`), giving puffy, rounded hills.
+pub struct Billow<N> {
+    pub source: N,
+    pub params: FractalParams,
+}
+
+impl<N: NoiseFn2D> NoiseFn2D for Billow<N> {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        self.params
+            .sum(|f, o| 2. * self.source.get_2d(x * f + o, y * f + o).abs() - 1.)
+    }
+}
+
+impl<N: NoiseFn3D> NoiseFn3D for Billow<N> {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.params
+            .sum(|f, o| 2. * self.source.get_3d(x * f + o, y * f + o, z * f + o).abs() - 1.)
+    }
+}
+
+/// Ridged multifractal: inverted folded octaves, each weighted by the previous one, so ridges
+/// stay sharp while the valleys are smooth.
+pub struct RidgedMulti<N> {
+    pub source: N,
+    pub params: FractalParams,
+}
+
+impl<N> RidgedMulti<N> {
+    fn ridged<F: FnMut(f64, f64) -> f64>(&self, mut sample: F) -> f64 {
+        let mut weight = 1.;
+        let ridged = self.params.sum(|f, o| {
+            let signal = RIDGED_OFFSET - sample(f, o).abs();
+            let signal = signal * signal * weight;
+            weight = (signal * RIDGED_GAIN).clamp(0., 1.);
+            signal
+        });
+        ridged * 2. - 1.
+    }
+}
+
+impl<N: NoiseFn2D> NoiseFn2D for RidgedMulti<N> {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        self.ridged(|f, o| self.source.get_2d(x * f + o, y * f + o))
+    }
+}
+
+impl<N: NoiseFn3D> NoiseFn3D for RidgedMulti<N> {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.ridged(|f, o| self.source.get_3d(x * f + o, y * f + o, z * f + o))
+    }
+}
+
+/// Samples `source` at coordinates displaced by `warp`, scaled by `strength`.
+pub struct DomainWarp<N, W> {
+    pub source: N,
+    pub warp: W,
+    pub strength: f64,
+}
+
+impl<N: NoiseFn2D, W: NoiseFn2D> NoiseFn2D for DomainWarp<N, W> {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        let dx = self.warp.get_2d(x + 5.2, y + 1.3);
+        let dy = self.warp.get_2d(x + 1.7, y + 9.2);
+        self.source
+            .get_2d(x + self.strength * dx, y + self.strength * dy)
+    }
+}
+
+impl<N: NoiseFn3D, W: NoiseFn3D> NoiseFn3D for DomainWarp<N, W> {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        let dx = self.warp.get_3d(x + 5.2, y + 1.3, z + 3.1);
+        let dy = self.warp.get_3d(x + 1.7, y + 9.2, z + 4.6);
+        let dz = self.warp.get_3d(x + 8.3, y + 2.8, z + 7.4);
+        self.source.get_3d(
+            x + self.strength * dx,
+            y + self.strength * dy,
+            z + self.strength * dz,
+        )
+    }
+}
+
+/// Maps world coordinates to noise space and the `[-1, 1]` output to `bias ± amplitude`.
+pub struct ScaleBias<N> {
+    pub source: N,
+    pub scale: f64,
+    pub amplitude: f64,
+    pub bias: f64,
+}
+
+impl<N: NoiseFn2D> NoiseFn2D for ScaleBias<N> {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        self.bias + self.amplitude * self.source.get_2d(x / self.scale, y / self.scale)
+    }
+}
+
+impl<N: NoiseFn3D> NoiseFn3D for ScaleBias<N> {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.bias
+            + self.amplitude
+                * self
+                    .source
+                    .get_3d(x / self.scale, y / self.scale, z / self.scale)
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt::Display;
+
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+pub mod fractal;
+pub mod perlin;
+pub mod preview;
+pub mod simplex;
+
+use fractal::{Billow, DomainWarp, Fbm, FractalParams, RidgedMulti, ScaleBias};
+use perlin::PerlinNoise2D;
+use simplex::Simplex;
+
+pub trait NoiseFn2D {
+    fn get_2d(&self, x: f64, y: f64) -> f64;
+}
+
+pub trait NoiseFn3D {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64;
+}
+
+impl<N: NoiseFn2D + ?Sized> NoiseFn2D for Box<N> {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        (**self).get_2d(x, y)
+    }
+}
+
+impl<N: NoiseFn3D + ?Sized> NoiseFn3D for Box<N> {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        (**self).get_3d(x, y, z)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NoiseKind {
+    Perlin,
+    Simplex,
+    Billow,
+    RidgedMulti,
+    DomainWarped,
+}
+
+impl NoiseKind {
+    pub const ALL: [NoiseKind; 5] = [
+        NoiseKind::Perlin,
+        NoiseKind::Simplex,
+        NoiseKind::Billow,
+        NoiseKind::RidgedMulti,
+        NoiseKind::DomainWarped,
+    ];
+}
+
+impl Display for NoiseKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            NoiseKind::Perlin => write!(f, "{}", tr!("noise.kind.perlin")),
+            NoiseKind::Simplex => write!(f, "{}", tr!("noise.kind.simplex")),
+            NoiseKind::Billow => write!(f, "{}", tr!("noise.kind.billow")),
+            NoiseKind::RidgedMulti => write!(f, "{}", tr!("noise.kind.ridged")),
+            NoiseKind::DomainWarped => write!(f, "{}", tr!("noise.kind.warped")),
+        }
+    }
+}
+
+/// Terrain height noise. Positions are divided by `scale` before sampling and the output is
+/// `bias + amplitude * noise`. The same parameters always produce the same terrain.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct NoiseParams {
+    pub kind: NoiseKind,
+    pub seed: u32,
+    pub fractal: FractalParams,
+    pub amplitude: f64,
+    pub bias: f64,
+    pub scale: f64,
+    /// Displacement of the domain warped variant, in noise space units.
+    pub warp_strength: f64,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Perlin,
+            seed: 42,
+            fractal: FractalParams {
+                octaves: 6,
+                frequency: 1.,
+                lacunarity: 2.,
+                persistence: 1.,
+            },
+            amplitude: 10.,
+            bias: 0.,
+            scale: 4096.,
+            warp_strength: 0.5,
+        }
+    }
+}
+
+impl NoiseParams {
+    pub fn build(&self) -> Box<dyn NoiseFn2D + Send + Sync> {
+        let fractal = self.fractal;
+        let source: Box<dyn NoiseFn2D + Send + Sync> = match self.kind {
+            NoiseKind::Perlin => {
+                return Box::new(PerlinNoise2D {
+                    octaves: fractal.octaves as i32,
+                    amplitude: self.amplitude,
+                    frequency: fractal.frequency,
+                    persistence: fractal.persistence,
+                    lacunarity: fractal.lacunarity,
+                    scale: (self.scale, self.scale),
+                    bias: self.bias,
+                    seed: self.seed as i32,
+                })
+            }
+            NoiseKind::Simplex => Box::new(Fbm {
+                source: Simplex::new(self.seed),
+                params: fractal,
+            }),
+            NoiseKind::Billow => Box::new(Billow {
+                source: Simplex::new(self.seed),
+                params: fractal,
+            }),
+            NoiseKind::RidgedMulti => Box::new(RidgedMulti {
+                source: Simplex::new(self.seed),
+                params: fractal,
+            }),
+            NoiseKind::DomainWarped => Box::new(DomainWarp {
+                source: Fbm {
+                    source: Simplex::new(self.seed),
+                    params: fractal,
+                },
+                warp: Fbm {
+                    source: Simplex::new(self.seed.wrapping_add(1)),
+                    params: FractalParams {
+                        octaves: 3,
+                        ..fractal
+                    },
+                },
+                strength: self.warp_strength,
+            }),
+        };
+        Box::new(ScaleBias {
+            source,
+            scale: self.scale,
+            amplitude: self.amplitude,
+            bias: self.bias,
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, max_bias: f64) {
+        ui.horizontal_wrapped(|ui| {
+            for kind in NoiseKind::ALL {
+                ui.radio_value(&mut self.kind, kind, format!("{}", kind));
+            }
+        });
+        let fractal = &mut self.fractal;
+        ui.add(egui::Slider::new(&mut fractal.octaves, 0..=8).text(tr!("noise.octaves")));
+        ui.add(egui::Slider::new(&mut self.amplitude, 0.0..=64.0).text(tr!("noise.amplitude")));
+        ui.add(egui::Slider::new(&mut fractal.frequency, 0.0..=4.0).text(tr!("noise.frequency")));
+        ui.add(
+            egui::Slider::new(&mut fractal.persistence, 0.0..=2.0).text(tr!("noise.persistence")),
+        );
+        ui.add(egui::Slider::new(&mut fractal.lacunarity, 1.0..=4.0).text(tr!("noise.lacunarity")));
+        if self.kind == NoiseKind::DomainWarped {
+            ui.add(egui::Slider::new(&mut self.warp_strength, 0.0..=2.0).text(tr!("noise.warp")));
+        }
+        ui.add(egui::Slider::new(&mut self.bias, 0.0..=max_bias).text(tr!("noise.bias")));
+        ui.add(egui::Slider::new(&mut self.seed, 0..=16384).text(tr!("noise.seed")));
+    }
+}
@@ -1,5 +1,7 @@
 use std::num::Wrapping;
 
+use super::NoiseFn2D;
+
 // source: https://github.com/gp-97/perlin2d
 
 /// Perlin noise generator parameters:
@@ -111,3 +113,9 @@ impl PerlinNoise2D {
         return fin;
     }
 }
+
+impl NoiseFn2D for PerlinNoise2D {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        self.get_noise(x, y)
+    }
+}
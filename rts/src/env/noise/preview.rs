@@ -0,0 +1,62 @@
+use egui::{epaint::Mesh, vec2, Color32, Rect, Sense, Shape, Ui};
+
+use super::{NoiseFn2D, NoiseParams};
+
+const RESOLUTION: usize = 64;
+const PREVIEW_SIZE: f32 = 192.;
+
+/// Grayscale top down view of a noise heightfield over a square terrain of side `size`
+/// centered on the origin. Samples are cached until the parameters change.
+#[derive(Clone, Default)]
+pub struct NoisePreview {
+    key: Option<(NoiseParams, u32)>,
+    values: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl NoisePreview {
+    fn sample(&mut self, params: &NoiseParams, size: u32) {
+        let noise = params.build();
+        let step = size as f64 / RESOLUTION as f64;
+        let half = size as f64 / 2.;
+        self.values.clear();
+        for row in 0..RESOLUTION {
+            // image rows go down, world y goes up
+            let y = half - (row as f64 + 0.5) * step;
+            for col in 0..RESOLUTION {
+                let x = (col as f64 + 0.5) * step - half;
+                self.values.push(noise.get_2d(x, y));
+            }
+        }
+        self.min = self.values.iter().cloned().fold(f64::MAX, f64::min);
+        self.max = self.values.iter().cloned().fold(f64::MIN, f64::max);
+        self.key = Some((*params, size));
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, params: &NoiseParams, size: u32) {
+        if self.key != Some((*params, size)) {
+            self.sample(params, size);
+        }
+
+        let (rect, _) = ui.allocate_exact_size(vec2(PREVIEW_SIZE, PREVIEW_SIZE), Sense::hover());
+        let cell = PREVIEW_SIZE / RESOLUTION as f32;
+        let range = (self.max - self.min).max(f64::EPSILON);
+        let mut mesh = Mesh::default();
+        for (idx, value) in self.values.iter().enumerate() {
+            let (row, col) = (idx / RESOLUTION, idx % RESOLUTION);
+            let gray = ((value - self.min) / range * 255.) as u8;
+            let min = rect.min + vec2(col as f32 * cell, row as f32 * cell);
+            mesh.add_colored_rect(
+                Rect::from_min_size(min, vec2(cell, cell)),
+                Color32::from_gray(gray),
+            );
+        }
+        ui.painter().add(Shape::Mesh(mesh));
+        ui.label(tr!(
+            "noise.preview.height",
+            min = format!("{:.1}", self.min),
+            max = format!("{:.1}", self.max)
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use super::{NoiseFn2D, NoiseFn3D};
+
+// source: Stefan Gustavson, "Simplex noise demystified"
+
+const GRAD3: [[f64; 3]; 12] = [
+    [1., 1., 0.],
+    [-1., 1., 0.],
+    [1., -1., 0.],
+    [-1., -1., 0.],
+    [1., 0., 1.],
+    [-1., 0., 1.],
+    [1., 0., -1.],
+    [-1., 0., -1.],
+    [0., 1., 1.],
+    [0., -1., 1.],
+    [0., 1., -1.],
+    [0., -1., -1.],
+];
+
+/// Simplex gradient noise in 2D and 3D, output roughly in `[-1, 1]`. The gradient permutation
+/// is shuffled from `seed`, so equal seeds always give the same field.
+#[derive(Clone)]
+pub struct Simplex {
+    perm: [u8; 512],
+}
+
+impl Simplex {
+    pub fn new(seed: u32) -> Self {
+        let mut p = [0u8; 256];
+        for (i, v) in p.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        // splitmix64 driven Fisher-Yates shuffle
+        let mut state = seed as u64;
+        let mut next = || {
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+        for i in (1..256).rev() {
+            let j = (next() % (i as u64 + 1)) as usize;
+            p.swap(i, j);
+        }
+        let mut perm = [0u8; 512];
+        for (i, v) in perm.iter_mut().enumerate() {
+            *v = p[i & 255];
+        }
+        Self { perm }
+    }
+
+    fn perm(&self, i: i32) -> i32 {
+        self.perm[(i & 511) as usize] as i32
+    }
+
+    fn gradient(&self, i: i32, j: i32, k: i32) -> &[f64; 3] {
+        let h = self.perm(i + self.perm(j + self.perm(k)));
+        &GRAD3[(h % 12) as usize]
+    }
+}
+
+impl NoiseFn2D for Simplex {
+    fn get_2d(&self, x: f64, y: f64) -> f64 {
+        let f2 = 0.5 * (3f64.sqrt() - 1.);
+        let g2 = (3. - 3f64.sqrt()) / 6.;
+
+        // skew into simplex cell space
+        let s = (x + y) * f2;
+        let i = (x + s).floor();
+        let j = (y + s).floor();
+        let t = (i + j) * g2;
+        let x0 = x - (i - t);
+        let y0 = y - (j - t);
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - i1 as f64 + g2;
+        let y1 = y0 - j1 as f64 + g2;
+        let x2 = x0 - 1. + 2. * g2;
+        let y2 = y0 - 1. + 2. * g2;
+
+        let ii = (i as i64 & 255) as i32;
+        let jj = (j as i64 & 255) as i32;
+        let corner = |dx: f64, dy: f64, g: &[f64; 3]| {
+            let t = 0.5 - dx * dx - dy * dy;
+            if t < 0. {
+                0.
+            } else {
+                let t = t * t;
+                t * t * (g[0] * dx + g[1] * dy)
+            }
+        };
+        let n0 = corner(x0, y0, self.gradient(ii, jj, 0));
+        let n1 = corner(x1, y1, self.gradient(ii + i1, jj + j1, 0));
+        let n2 = corner(x2, y2, self.gradient(ii + 1, jj + 1, 0));
+
+        70. * (n0 + n1 + n2)
+    }
+}
+
+impl NoiseFn3D for Simplex {
+    fn get_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        let f3 = 1. / 3.;
+        let g3 = 1. / 6.;
+
+        let s = (x + y + z) * f3;
+        let i = (x + s).floor();
+        let j = (y + s).floor();
+        let k = (z + s).floor();
+        let t = (i + j + k) * g3;
+        let x0 = x - (i - t);
+        let y0 = y - (j - t);
+        let z0 = z - (k - t);
+
+        // which of the six tetrahedra of the cube we are in
+        let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let x1 = x0 - i1 as f64 + g3;
+        let y1 = y0 - j1 as f64 + g3;
+        let z1 = z0 - k1 as f64 + g3;
+        let x2 = x0 - i2 as f64 + 2. * g3;
+        let y2 = y0 - j2 as f64 + 2. * g3;
+        let z2 = z0 - k2 as f64 + 2. * g3;
+        let x3 = x0 - 1. + 3. * g3;
+        let y3 = y0 - 1. + 3. * g3;
+        let z3 = z0 - 1. + 3. * g3;
+
+        let ii = (i as i64 & 255) as i32;
+        let jj = (j as i64 & 255) as i32;
+        let kk = (k as i64 & 255) as i32;
+        let corner = |dx: f64, dy: f64, dz: f64, g: &[f64; 3]| {
+            let t = 0.6 - dx * dx - dy * dy - dz * dz;
+            if t < 0. {
+                0.
+            } else {
+                let t = t * t;
+                t * t * (g[0] * dx + g[1] * dy + g[2] * dz)
+            }
+        };
+        let n0 = corner(x0, y0, z0, self.gradient(ii, jj, kk));
+        let n1 = corner(x1, y1, z1, self.gradient(ii + i1, jj + j1, kk + k1));
+        let n2 = corner(x2, y2, z2, self.gradient(ii + i2, jj + j2, kk + k2));
+        let n3 = corner(x3, y3, z3, self.gradient(ii + 1, jj + 1, kk + 1));
+
+        32. * (n0 + n1 + n2 + n3)
+    }
+}
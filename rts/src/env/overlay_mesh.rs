@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use glam::Vec3;
+use rafx::{
+    api::RafxIndexType,
+    assets::push_buffer::PushBuffer,
+    rafx_visibility::{
+        geometry::{AxisAlignedBoundingBox, BoundingSphere},
+        VisibleBounds,
+    },
+};
+use rafx_plugins::features::mesh_adv::MeshVertexPosition;
+
+use super::simulation::{ChunkGeometry, ChunkGeometryPart};
+use crate::features::dyn_mesh::{DynMeshVertex, DynMeshVertexFormat};
+
+struct Quad {
+    corners: [Vec3; 4],
+    normal: Vec3,
+    tangent: Vec3,
+}
+
+/// Collects the quads of an overlay mesh (see `Universe::set_overlay_mesh`) by terrain material
+/// and packs them into a `ChunkGeometry`, one part per material.
+#[derive(Default)]
+pub struct OverlayQuads {
+    quads: BTreeMap<u16, Vec<Quad>>,
+}
+
+impl OverlayQuads {
+    /// Adds a quad with `corners` counter-clockwise around `normal`.
+    pub fn add(&mut self, material: u16, corners: [Vec3; 4], normal: Vec3, tangent: Vec3) {
+        self.quads.entry(material).or_default().push(Quad {
+            corners,
+            normal,
+            tangent,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quads.is_empty()
+    }
+
+    /// The world space geometry and the position its visible bounds are relative to, the
+    /// minimum corner of the quads.
+    pub fn build(self) -> Option<(ChunkGeometry, Vec3)> {
+        if self.quads.is_empty() {
+            return None;
+        }
+        let count: usize = self.quads.values().map(|q| q.len()).sum();
+        let mut vertices_full = PushBuffer::new(count * 4 * std::mem::size_of::<DynMeshVertex>());
+        let mut vertices_position =
+            PushBuffer::new(count * 4 * std::mem::size_of::<MeshVertexPosition>());
+        let mut indices = PushBuffer::new(count * 6 * std::mem::size_of::<u32>());
+        let mut parts = vec![];
+        let (mut low, mut high) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+        for (material, quads) in self.quads {
+            let vertex_full_offset = vertices_full.len();
+            let vertex_position_offset = vertices_position.len();
+            let index_offset = indices.len();
+            let mut part_indices: Vec<u32> = Vec::with_capacity(quads.len() * 6);
+            for quad in quads {
+                let first = part_indices.len() as u32 / 6 * 4;
+                let binormal = quad.normal.cross(quad.tangent);
+                for (i, corner) in quad.corners.iter().enumerate() {
+                    low = low.min(*corner);
+                    high = high.max(*corner);
+                    let vertex = DynMeshVertex {
+                        position: (*corner).into(),
+                        normal: quad.normal.into(),
+                        tangent: quad.tangent.into(),
+                        binormal: binormal.into(),
+                        tex_coord: [(i == 1 || i == 2) as u8 as f32, (i >= 2) as u8 as f32],
+                        ao: 1.,
+                    };
+                    vertices_full.push(&[vertex], 1);
+                    vertices_position.push(
+                        &[MeshVertexPosition {
+                            position: (*corner).into(),
+                        }],
+                        1,
+                    );
+                }
+                part_indices.extend([0, 1, 2, 0, 2, 3].iter().map(|i| first + i));
+            }
+            indices.push(&part_indices, std::mem::size_of::<u32>());
+            parts.push(ChunkGeometryPart {
+                material,
+                vertex_full_buffer_offset_in_bytes: vertex_full_offset as u32,
+                vertex_full_buffer_size_in_bytes: (vertices_full.len() - vertex_full_offset) as u32,
+                vertex_position_buffer_offset_in_bytes: vertex_position_offset as u32,
+                vertex_position_buffer_size_in_bytes: (vertices_position.len()
+                    - vertex_position_offset)
+                    as u32,
+                index_buffer_offset_in_bytes: index_offset as u32,
+                index_buffer_size_in_bytes: (indices.len() - index_offset) as u32,
+                index_type: RafxIndexType::Uint32,
+            });
+        }
+
+        let max = high - low;
+        let center = max / 2.;
+        let geometry = ChunkGeometry {
+            parts,
+            vertex_full_buffer: vertices_full.into_data(),
+            vertex_position_buffer: vertices_position.into_data(),
+            index_buffer: indices.into_data(),
+            visible_bounds: VisibleBounds {
+                aabb: AxisAlignedBoundingBox {
+                    min: Vec3::ZERO,
+                    max,
+                },
+                obb: Default::default(),
+                bounding_sphere: BoundingSphere::new(center, center.length()),
+                hash: 0,
+            },
+            vertex_format: DynMeshVertexFormat::Full,
+            origin: Vec3::ZERO,
+        };
+        Some((geometry, low))
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use building_blocks::storage::prelude::ChunkKey3;
+use glam::{Vec3, Vec4};
+use rafx_plugins::features::debug3d::Debug3DResource;
+use rapier3d::prelude::*;
+
+use crate::env::simulation::Universe;
+
+const FIXED_DT: f32 = 1. / 60.;
+const MAX_STEPS_PER_FRAME: usize = 4;
+const TERRAIN_RADIUS: f32 = 96.;
+const MAX_TERRAIN_BUILDS_PER_FRAME: usize = 8;
+const DEBRIS_LIFETIME: f32 = 10.; // s
+
+fn to_vector(v: Vec3) -> Vector<Real> {
+    vector![v.x, v.y, v.z]
+}
+
+fn to_vec3(v: &Vector<Real>) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
+
+struct TerrainCollider {
+    body: RigidBodyHandle,
+    version: u64,
+}
+
+struct Debris {
+    body: RigidBodyHandle,
+    radius: f32,
+    age: f32,
+}
+
+pub struct PhysicsRayHit {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+    pub collider: ColliderHandle,
+}
+
+/// Rapier world for the active scene. Terrain chunks near the camera get static colliders built
+/// from their voxel columns, debris and projectiles are dynamic bodies that expire after a while.
+pub struct PhysicsResource {
+    pub enabled: bool,
+    pub show_debug: bool,
+    pub gravity: Vec3,
+    accumulator: f32,
+    integration_parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+    terrain: HashMap<ChunkKey3, TerrainCollider>,
+    debris: Vec<Debris>,
+}
+
+impl Default for PhysicsResource {
+    fn default() -> Self {
+        let mut integration_parameters = IntegrationParameters::default();
+        integration_parameters.dt = FIXED_DT;
+        Self {
+            enabled: true,
+            show_debug: false,
+            gravity: Vec3::new(0., 0., -9.81),
+            accumulator: 0.,
+            integration_parameters,
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            joints: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            terrain: HashMap::new(),
+            debris: vec![],
+        }
+    }
+}
+
+impl PhysicsResource {
+    pub fn reset(&mut self) {
+        let enabled = self.enabled;
+        let show_debug = self.show_debug;
+        *self = Self::default();
+        self.enabled = enabled;
+        self.show_debug = show_debug;
+    }
+
+    fn remove_body(&mut self, body: RigidBodyHandle) {
+        self.bodies.remove(
+            body,
+            &mut self.islands,
+            &mut self.colliders,
+            &mut self.joints,
+        );
+    }
+
+    /// Builds colliders for new or edited chunks around `center` and drops the far ones.
+    #[profiling::function]
+    fn sync_terrain(&mut self, universe: &Universe, center: Vec3) {
+        let near = universe.chunks_near(center, TERRAIN_RADIUS);
+
+        let far: Vec<_> = self
+            .terrain
+            .keys()
+            .filter(|key| !near.iter().any(|(k, _)| k == *key))
+            .cloned()
+            .collect();
+        for key in far {
+            if let Some(collider) = self.terrain.remove(&key) {
+                self.remove_body(collider.body);
+            }
+        }
+
+        let changed = near.into_iter().filter(|(key, version)| {
+            self.terrain
+                .get(key)
+                .map_or(true, |collider| collider.version != *version)
+        });
+        let changed: Vec<_> = changed.take(MAX_TERRAIN_BUILDS_PER_FRAME).collect();
+        for (key, version) in changed {
+            if let Some(collider) = self.terrain.remove(&key) {
+                self.remove_body(collider.body);
+            }
+            let shapes: Vec<_> = universe
+                .collision_boxes(key)
+                .into_iter()
+                .map(|(center, half)| {
+                    (
+                        Isometry::translation(center.x, center.y, center.z),
+                        SharedShape::cuboid(half.x, half.y, half.z),
+                    )
+                })
+                .collect();
+            let body = self.bodies.insert(RigidBodyBuilder::new_static().build());
+            if !shapes.is_empty() {
+                let collider = ColliderBuilder::compound(shapes).build();
+                self.colliders
+                    .insert_with_parent(collider, body, &mut self.bodies);
+            }
+            self.terrain.insert(key, TerrainCollider { body, version });
+        }
+    }
+
+    #[profiling::function]
+    pub fn update(&mut self, dt: f32, universe: &Universe, center: Vec3) {
+        if !self.enabled {
+            return;
+        }
+        self.sync_terrain(universe, center);
+
+        self.accumulator = (self.accumulator + dt).min(FIXED_DT * MAX_STEPS_PER_FRAME as f32);
+        let gravity = to_vector(self.gravity);
+        while self.accumulator >= FIXED_DT {
+            self.accumulator -= FIXED_DT;
+            self.pipeline.step(
+                &gravity,
+                &self.integration_parameters,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.joints,
+                &mut self.ccd_solver,
+                &(),
+                &(),
+            );
+        }
+        self.query_pipeline
+            .update(&self.islands, &self.bodies, &self.colliders);
+
+        for debris in &mut self.debris {
+            debris.age += dt;
+        }
+        let expired: Vec<_> = self
+            .debris
+            .iter()
+            .filter(|debris| debris.age > DEBRIS_LIFETIME)
+            .map(|debris| debris.body)
+            .collect();
+        self.debris.retain(|debris| debris.age <= DEBRIS_LIFETIME);
+        for body in expired {
+            self.remove_body(body);
+        }
+    }
+
+    fn spawn_ball(
+        &mut self,
+        position: Vec3,
+        velocity: Vec3,
+        radius: f32,
+        ccd: bool,
+    ) -> RigidBodyHandle {
+        let mut body = RigidBodyBuilder::new_dynamic()
+            .position(Isometry::translation(position.x, position.y, position.z))
+            .ccd_enabled(ccd)
+            .build();
+        body.set_linvel(to_vector(velocity), true);
+        let body = self.bodies.insert(body);
+        let collider = ColliderBuilder::ball(radius).restitution(0.3).build();
+        self.colliders
+            .insert_with_parent(collider, body, &mut self.bodies);
+        self.debris.push(Debris {
+            body,
+            radius,
+            age: 0.,
+        });
+        body
+    }
+
+    pub fn spawn_debris(&mut self, position: Vec3, velocity: Vec3, radius: f32) -> RigidBodyHandle {
+        self.spawn_ball(position, velocity, radius, false)
+    }
+
+    /// Fast moving body with continuous collision detection so it doesn't tunnel through walls.
+    pub fn spawn_projectile(&mut self, position: Vec3, velocity: Vec3) -> RigidBodyHandle {
+        self.spawn_ball(position, velocity, 0.1, true)
+    }
+
+    pub fn body_position(&self, body: RigidBodyHandle) -> Option<Vec3> {
+        self.bodies.get(body).map(|b| to_vec3(b.translation()))
+    }
+
+    pub fn ray_cast(
+        &self,
+        start: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<PhysicsRayHit> {
+        self.cast(start, direction, max_distance, None)
+    }
+
+    /// Like `ray_cast`, but only hits dynamic bodies, the terrain colliders are skipped.
+    pub fn ray_cast_bodies(
+        &self,
+        start: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<PhysicsRayHit> {
+        let is_dynamic = |handle: ColliderHandle| {
+            self.colliders
+                .get(handle)
+                .and_then(|collider| collider.parent())
+                .and_then(|body| self.bodies.get(body))
+                .map_or(false, |body| body.is_dynamic())
+        };
+        self.cast(start, direction, max_distance, Some(&is_dynamic))
+    }
+
+    fn cast(
+        &self,
+        start: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        filter: Option<&dyn Fn(ColliderHandle) -> bool>,
+    ) -> Option<PhysicsRayHit> {
+        let direction = direction.normalize();
+        let ray = Ray::new(point![start.x, start.y, start.z], to_vector(direction));
+        self.query_pipeline
+            .cast_ray_and_get_normal(
+                &self.colliders,
+                &ray,
+                max_distance,
+                true,
+                InteractionGroups::all(),
+                filter,
+            )
+            .map(|(collider, intersection)| PhysicsRayHit {
+                point: start + direction * intersection.toi,
+                normal: to_vec3(&intersection.normal),
+                distance: intersection.toi,
+                collider,
+            })
+    }
+
+    pub fn add_debug_draw(&self, debug_draw: &mut Debug3DResource) {
+        if !self.show_debug {
+            return;
+        }
+        let color = Vec4::new(1., 0.5, 0., 1.);
+        for debris in &self.debris {
+            if let Some(position) = self.body_position(debris.body) {
+                debug_draw.add_sphere(position, debris.radius, color, 8);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("physics"))
+            .id_source("physics")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("physics.enabled"));
+                ui.checkbox(&mut self.show_debug, tr!("physics.show_bodies"));
+                ui.add(
+                    egui::Slider::new(&mut self.gravity.z, -30.0..=0.0)
+                        .text(tr!("physics.gravity")),
+                );
+                ui.label(tr!(
+                    "physics.stats",
+                    bodies = self.bodies.len(),
+                    colliders = self.colliders.len(),
+                    chunks = self.terrain.len(),
+                    debris = self.debris.len()
+                ));
+            });
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use building_blocks::core::prelude::*;
+use glam::Vec3;
+
+pub const CHUNK_SIZE: i32 = 16; // voxels
+pub const SECTOR_CHUNKS: i32 = 16; // chunks per sector side
+const SECTOR_SIZE: i32 = CHUNK_SIZE * SECTOR_CHUNKS; // voxels
+const SECTOR_WORDS: usize = (SECTOR_CHUNKS * SECTOR_CHUNKS * SECTOR_CHUNKS) as usize / 64;
+// how far past a cell boundary the first cell is looked up, so rays starting on one pick
+// the cell they go into
+const BOUNDARY_NUDGE: f32 = 1e-4; // voxels
+
+/// Which chunks may hold voxels, as one bitmap of chunks per sector of 16^3 chunks. Chunks are
+/// marked when they are meshed or edited and only cleared when the whole map is reset, so an
+/// unmarked chunk is known to be empty. Ray casts use it to cross an empty sector or chunk in
+/// one step instead of voxel by voxel.
+#[derive(Default)]
+pub struct ChunkOccupancy {
+    sectors: HashMap<Point3i, Box<[u64; SECTOR_WORDS]>>,
+}
+
+impl ChunkOccupancy {
+    pub fn clear(&mut self) {
+        self.sectors.clear();
+    }
+
+    fn split(chunk_min: Point3i) -> (Point3i, usize) {
+        let chunk = PointN([
+            chunk_min.x().div_euclid(CHUNK_SIZE),
+            chunk_min.y().div_euclid(CHUNK_SIZE),
+            chunk_min.z().div_euclid(CHUNK_SIZE),
+        ]);
+        let sector = PointN([
+            chunk.x().div_euclid(SECTOR_CHUNKS),
+            chunk.y().div_euclid(SECTOR_CHUNKS),
+            chunk.z().div_euclid(SECTOR_CHUNKS),
+        ]);
+        let local = chunk - SECTOR_CHUNKS * sector;
+        let bit = (local.z() * SECTOR_CHUNKS + local.y()) * SECTOR_CHUNKS + local.x();
+        (SECTOR_SIZE * sector, bit as usize)
+    }
+
+    pub fn mark(&mut self, chunk_min: Point3i) {
+        let (sector, bit) = Self::split(chunk_min);
+        let words = self
+            .sectors
+            .entry(sector)
+            .or_insert_with(|| Box::new([0; SECTOR_WORDS]));
+        words[bit / 64] |= 1 << (bit % 64);
+    }
+
+    fn has_sector(&self, sector_min: Point3i) -> bool {
+        self.sectors.contains_key(&sector_min)
+    }
+
+    fn has_chunk(&self, chunk_min: Point3i) -> bool {
+        let (sector, bit) = Self::split(chunk_min);
+        self.sectors
+            .get(&sector)
+            .map_or(false, |words| words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Visits the voxels on `origin + t * dir` for `t` in `0..t_max` that are in marked chunks,
+    /// in order, until `visit` returns false.
+    pub fn traverse_voxels<F: FnMut(Point3i) -> bool>(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        t_max: f32,
+        mut visit: F,
+    ) {
+        traverse_cells(origin, dir, SECTOR_SIZE, 0., t_max, |sector, t0, t1| {
+            if !self.has_sector(sector) {
+                return true;
+            }
+            traverse_cells(origin, dir, CHUNK_SIZE, t0, t1, |chunk, t0, t1| {
+                if !self.has_chunk(chunk) {
+                    return true;
+                }
+                traverse_cells(origin, dir, 1, t0, t1, |voxel, _, _| visit(voxel))
+            })
+        });
+    }
+}
+
+/// Visits the cells of a grid of `cell_size` the ray `origin + t * dir` crosses for `t` in
+/// `t_min..t_max`, in order, with the part of the range inside each (Amanatides & Woo). Stops
+/// and returns false once `visit` does.
+pub fn traverse_cells<F: FnMut(Point3i, f32, f32) -> bool>(
+    origin: Vec3,
+    dir: Vec3,
+    cell_size: i32,
+    t_min: f32,
+    t_max: f32,
+    mut visit: F,
+) -> bool {
+    if t_min >= t_max {
+        return true;
+    }
+    let size = cell_size as f32;
+    let nudge = BOUNDARY_NUDGE / dir.length().max(f32::EPSILON);
+    let start = origin + dir * (t_min + nudge.min((t_max - t_min) / 2.));
+    let origin = [origin.x, origin.y, origin.z];
+    let dir = [dir.x, dir.y, dir.z];
+    let start = [start.x, start.y, start.z];
+    let mut cell = [0i32; 3];
+    let mut step = [0i32; 3];
+    let mut t_next = [f32::INFINITY; 3];
+    let mut t_delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        cell[axis] = (start[axis] / size).floor() as i32;
+        if dir[axis] > 0. {
+            step[axis] = 1;
+            t_next[axis] = ((cell[axis] + 1) as f32 * size - origin[axis]) / dir[axis];
+            t_delta[axis] = size / dir[axis];
+        } else if dir[axis] < 0. {
+            step[axis] = -1;
+            t_next[axis] = (cell[axis] as f32 * size - origin[axis]) / dir[axis];
+            t_delta[axis] = -size / dir[axis];
+        }
+    }
+    let mut t = t_min;
+    loop {
+        let axis = if t_next[0] <= t_next[1] && t_next[0] <= t_next[2] {
+            0
+        } else if t_next[1] <= t_next[2] {
+            1
+        } else {
+            2
+        };
+        let exit = t_next[axis].min(t_max);
+        let cell_min = cell_size * PointN([cell[0], cell[1], cell[2]]);
+        if !visit(cell_min, t, exit) {
+            return false;
+        }
+        if t_next[axis] >= t_max {
+            return true;
+        }
+        t = t_next[axis];
+        cell[axis] += step[axis];
+        t_next[axis] += t_delta[axis];
+    }
+}
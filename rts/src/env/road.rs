@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use building_blocks::{core::prelude::*, storage::prelude::*};
+use glam::{Vec2, Vec3};
+
+use super::{
+    edit::TerrainEditBatch,
+    simulation::{MaterialVoxel, Universe},
+};
+
+// horizontal voxels needed to climb one voxel
+const SLOPE_RUN: f32 = 3.;
+// empty voxels kept above the road when it cuts through a hill
+const CLEARANCE: i32 = 4;
+// how far down the road is propped up when it bridges a dip
+const FILL_DEPTH: i32 = 8;
+const SAMPLE_STEP: f32 = 0.5;
+const MIN_SEGMENT: f32 = 1.;
+
+/// Paints a continuous road while the mouse is dragged over the terrain. The road follows
+/// the surface under the cursor but its height changes by at most one voxel every
+/// `SLOPE_RUN` voxels, cutting into hills and filling dips as needed.
+#[derive(Default)]
+pub struct RoadPainter {
+    // center of the road end and its height
+    last: Option<Vec3>,
+}
+
+impl RoadPainter {
+    pub fn is_painting(&self) -> bool {
+        self.last.is_some()
+    }
+
+    pub fn end(&mut self) {
+        self.last = None;
+    }
+
+    /// Extends the road to the surface voxel `surface`. A stroke is one undo step.
+    pub fn paint_to(
+        &mut self,
+        universe: &mut Universe,
+        surface: Point3i,
+        width: u32,
+        material: MaterialVoxel,
+    ) {
+        let target = Vec3::new(
+            surface.x() as f32 + 0.5,
+            surface.y() as f32 + 0.5,
+            surface.z() as f32,
+        );
+        let mut batch = TerrainEditBatch::new();
+        if self.last.is_some() {
+            batch.merged();
+        }
+        let (from, to) = match self.last {
+            None => (target, target),
+            Some(last) => {
+                let run = Vec2::new(target.x - last.x, target.y - last.y).length();
+                if run < MIN_SEGMENT {
+                    return;
+                }
+                let max_climb = run / SLOPE_RUN;
+                let z = last.z + (target.z - last.z).clamp(-max_climb, max_climb);
+                (last, Vec3::new(target.x, target.y, z))
+            }
+        };
+        self.last = Some(to);
+
+        let radius = width.max(1) as f32 / 2.;
+        let reach = radius.ceil() as i32;
+        let run = Vec2::new(to.x - from.x, to.y - from.y).length();
+        let steps = (run / SAMPLE_STEP).ceil().max(1.) as usize;
+        let mut columns = HashMap::new();
+        for step in 0..=steps {
+            let center = from.lerp(to, step as f32 / steps as f32);
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    let offset = Vec2::new(dx as f32, dy as f32);
+                    if offset.length() > radius {
+                        continue;
+                    }
+                    let x = (center.x + offset.x).floor() as i32;
+                    let y = (center.y + offset.y).floor() as i32;
+                    columns.insert((x, y), center.z.round() as i32);
+                }
+            }
+        }
+
+        for ((x, y), z) in columns {
+            batch.set(PointN([x, y, z]), material);
+            for above in z + 1..=z + CLEARANCE {
+                batch.clear(PointN([x, y, above]));
+            }
+            for below in (z - FILL_DEPTH..z).rev() {
+                let point = PointN([x, y, below]);
+                if !universe.get_voxel(point).is_empty() {
+                    break;
+                }
+                batch.set(point, material);
+            }
+        }
+        batch.apply(universe);
+    }
+}
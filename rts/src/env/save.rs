@@ -0,0 +1,607 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use bevy_tasks::{Task, TaskPool, TaskPoolBuilder};
+use building_blocks::{core::prelude::*, storage::prelude::*};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use legion::{IntoQuery, Read, World};
+use rafx::base::Instant;
+use rafx_plugins::components::TransformComponent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    env::{
+        simulation::{MaterialVoxel, MaterialVoxels, TerrainBase, Universe},
+        stable_id::StableId,
+        terrain_gen::TerrainGenerator,
+    },
+    file_dialog::{DialogPurpose, FileDialogResource},
+    unit::{
+        creep::{CreepResource, CreepSave},
+        tech::{TeamTech, TechResource},
+        unit::{UnitComponent, UnitType},
+    },
+};
+
+const SAVE_DIR: &str = "saves";
+
+/// Manifest slot of the saves written to a picked folder instead of a rotating slot.
+pub const CUSTOM_SLOT: u32 = u32::MAX;
+
+/// Voxels of one chunk, x fastest, then y, then z.
+#[derive(Serialize, Deserialize)]
+pub struct ChunkSave {
+    pub minimum: [i32; 3],
+    pub shape: [i32; 3],
+    pub voxels: Vec<u16>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SectorSave {
+    pub key: [i32; 3],
+    pub chunks: Vec<ChunkSave>,
+}
+
+impl SectorSave {
+    pub fn file_name(key: Point3i) -> String {
+        format!("{}_{}_{}.bin", key.x(), key.y(), key.z())
+    }
+
+    /// Replaces the chunks of the sector in `voxels`. `remap` maps the saved voxel values to the
+    /// current material indices.
+    pub fn write_into(self, voxels: &mut MaterialVoxels, remap: &[u16]) -> Result<(), String> {
+        let key = PointN(self.key);
+        let mut stale = vec![];
+        voxels.visit_occupied_chunks(0, &Universe::sector_extent(key), |chunk| {
+            stale.push(chunk.extent().minimum);
+        });
+        for chunk in self.chunks {
+            let extent = Extent3i::from_min_and_shape(PointN(chunk.minimum), PointN(chunk.shape));
+            if chunk.voxels.len() != extent.num_points() {
+                return Err(format!("Corrupt chunk at {:?}", chunk.minimum));
+            }
+            let mut array = Array3x1::fill(extent, MaterialVoxel::empty());
+            let mut values = chunk.voxels.into_iter();
+            let (min, lub) = (extent.minimum, extent.least_upper_bound());
+            for z in min.z()..lub.z() {
+                for y in min.y()..lub.y() {
+                    for x in min.x()..lub.x() {
+                        let value = values.next().unwrap() as usize;
+                        *array.get_mut(PointN([x, y, z])) = MaterialVoxel::from_material_index(
+                            remap.get(value).copied().unwrap_or(0),
+                        );
+                    }
+                }
+            }
+            stale.retain(|minimum| *minimum != extent.minimum);
+            voxels.write_chunk(ChunkKey3::new(0, extent.minimum), array);
+        }
+        // chunks of the generated terrain that were dug out before the save
+        for minimum in stale {
+            let extent = voxels.indexer.extent_for_chunk_with_min(minimum);
+            voxels.write_chunk(
+                ChunkKey3::new(0, minimum),
+                Array3x1::fill(extent, MaterialVoxel::empty()),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The chunks of a sector as copied on the main thread, see `Universe::snapshot_sector`.
+pub struct SectorSnapshot {
+    pub key: Point3i,
+    pub chunks: Vec<Array3x1<MaterialVoxel>>,
+}
+
+impl SectorSnapshot {
+    pub fn into_save(self) -> SectorSave {
+        let chunks = self
+            .chunks
+            .into_iter()
+            .map(|chunk| {
+                let extent = *chunk.extent();
+                let (min, lub) = (extent.minimum, extent.least_upper_bound());
+                let mut data = Vec::with_capacity(extent.num_points());
+                for z in min.z()..lub.z() {
+                    for y in min.y()..lub.y() {
+                        for x in min.x()..lub.x() {
+                            data.push(chunk.get(PointN([x, y, z])).material_index());
+                        }
+                    }
+                }
+                ChunkSave {
+                    minimum: min.0,
+                    shape: extent.shape.0,
+                    voxels: data,
+                }
+            })
+            .collect();
+        SectorSave {
+            key: self.key.0,
+            chunks,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UnitSave {
+    pub id: StableId,
+    pub unit_type: UnitType,
+    pub team: u8,
+    pub health: f32,
+    pub position: [f32; 3],
+    pub move_target: Option<[f32; 3]>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct WorldSave {
+    pub units: Vec<UnitSave>,
+    /// Per team, missing in saves from before research existed.
+    #[serde(default)]
+    pub tech: Vec<TeamTech>,
+    #[serde(default)]
+    pub creeps: CreepSave,
+}
+
+impl WorldSave {
+    pub fn extract(world: &World, tech: &TechResource, creeps: &CreepResource) -> Self {
+        let mut query = <(
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<UnitComponent>,
+        )>::query();
+        let units = query
+            .iter(world)
+            .map(|(id, transform, unit)| UnitSave {
+                id: *id,
+                unit_type: unit.object_type,
+                team: unit.team,
+                health: unit.health,
+                position: transform.translation.into(),
+                move_target: unit.move_target.map(|t| t.into()),
+            })
+            .collect();
+        Self {
+            units,
+            tech: tech.save(),
+            creeps: creeps.save(),
+        }
+    }
+}
+
+/// Written last, so a slot with a manifest has all its files in place. The terrain is `base`
+/// generated again with the sectors in `sectors` written over it, or only those sectors in saves
+/// without a base. Sector files not listed are leftovers and should be ignored.
+#[derive(Serialize, Deserialize)]
+pub struct SaveManifest {
+    pub slot: u32,
+    pub materials: Vec<String>,
+    #[serde(default)]
+    pub base: Option<TerrainBase>,
+    pub sectors: Vec<[i32; 3]>,
+}
+
+struct SaveReport {
+    slot: u32,
+    dir: PathBuf,
+    sectors: usize,
+    duration: f32,
+}
+
+/// A save read back, with the terrain already rebuilt. The units wait in `AutosaveResource`
+/// until the terrain is installed, see `take_loaded_units`.
+pub struct LoadedSave {
+    pub base: Option<TerrainBase>,
+    pub sectors: Vec<Point3i>,
+    pub voxels: MaterialVoxels,
+    pub tech: Vec<TeamTech>,
+    pub creeps: CreepSave,
+    units: Vec<UnitSave>,
+}
+
+enum TaskResult {
+    Saved(Result<SaveReport, (u32, String)>),
+    Loaded(PathBuf, Result<LoadedSave, String>),
+}
+
+/// Periodically saves the main universe into rotating slots under `saves/`. A save stores what
+/// the terrain was generated from and the sectors edited since, each slot only writes the ones
+/// edited since it was last written. The sectors are copied on the main thread and converted and
+/// serialized on a background task. Loading reads and rebuilds the terrain on the same task.
+pub struct AutosaveResource {
+    pub enabled: bool,
+    pub interval: f32, // s
+    pub slots: u32,
+    next_slot: u32,
+    elapsed: f32,
+    // terrain revision the edits are counted from, see `Universe::terrain_revision`
+    revision: Option<u64>,
+    // sectors edited since the terrain was generated or loaded
+    edited: HashSet<Point3i>,
+    // per slot, `None` means the slot needs a full save
+    dirty: Vec<Option<HashSet<Point3i>>>,
+    save_requested: bool,
+    save_to: Option<PathBuf>,
+    load_from: Option<PathBuf>,
+    loaded: Option<LoadedSave>,
+    loaded_units: Vec<UnitSave>,
+    task_pool: TaskPool,
+    task: Option<Task<()>>,
+    result_tx: Sender<TaskResult>,
+    result_rx: Receiver<TaskResult>,
+    status: String,
+}
+
+impl Default for AutosaveResource {
+    fn default() -> Self {
+        let (result_tx, result_rx) = unbounded();
+        Self {
+            enabled: true,
+            interval: 120.,
+            slots: 3,
+            next_slot: 0,
+            elapsed: 0.,
+            revision: None,
+            edited: HashSet::new(),
+            dirty: vec![None; 3],
+            save_requested: false,
+            save_to: None,
+            load_from: None,
+            loaded: None,
+            loaded_units: vec![],
+            task_pool: TaskPoolBuilder::new().num_threads(1).build(),
+            task: None,
+            result_tx,
+            result_rx,
+            status: "".to_string(),
+        }
+    }
+}
+
+impl AutosaveResource {
+    pub fn slot_path(slot: u32) -> PathBuf {
+        Path::new(SAVE_DIR).join(format!("slot{}", slot))
+    }
+
+    /// Forgets what was saved, the slots start over with the next terrain.
+    pub fn reset(&mut self) {
+        self.elapsed = 0.;
+        self.save_requested = false;
+        self.revision = None;
+        self.loaded = None;
+        self.loaded_units.clear();
+    }
+
+    pub fn request_save(&mut self) {
+        self.save_requested = true;
+    }
+
+    /// Saves into `dir` once the running save is done.
+    pub fn request_save_to(&mut self, dir: PathBuf) {
+        self.save_to = Some(dir);
+    }
+
+    /// Reads the save in `dir` once the running save is done, `take_loaded` returns it when
+    /// ready.
+    pub fn request_load(&mut self, dir: PathBuf) {
+        self.load_from = Some(dir);
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// The save read after `request_load`, once. Install its terrain before the units from
+    /// `take_loaded_units` are spawned.
+    pub fn take_loaded(&mut self) -> Option<LoadedSave> {
+        let mut loaded = self.loaded.take()?;
+        self.loaded_units = std::mem::take(&mut loaded.units);
+        Some(loaded)
+    }
+
+    /// The units of the last save taken with `take_loaded`, once.
+    pub fn take_loaded_units(&mut self) -> Vec<UnitSave> {
+        std::mem::take(&mut self.loaded_units)
+    }
+
+    #[profiling::function]
+    pub fn update(
+        &mut self,
+        dt: f32,
+        universe: &mut Universe,
+        tech: &TechResource,
+        creeps: &CreepResource,
+    ) {
+        if self.revision != Some(universe.terrain_revision()) {
+            // new terrain, the slots are written again from its base, or in full without one
+            self.revision = Some(universe.terrain_revision());
+            self.edited.clear();
+            let dirty = universe.terrain_base().map(|_| HashSet::new());
+            self.dirty = vec![dirty; self.slots as usize];
+        }
+        let edited = universe.take_edited_sectors();
+        if !edited.is_empty() {
+            for sectors in self.dirty.iter_mut().flatten() {
+                sectors.extend(edited.iter().cloned());
+            }
+            self.edited.extend(edited);
+        }
+
+        for result in self.result_rx.try_iter() {
+            self.task = None;
+            self.status = match result {
+                TaskResult::Saved(Ok(report)) => {
+                    log::info!(
+                        "Saved {} sectors to {} in {:.2}s",
+                        report.sectors,
+                        report.dir.display(),
+                        report.duration
+                    );
+                    if report.slot == CUSTOM_SLOT {
+                        tr!(
+                            "save.saved",
+                            path = report.dir.display(),
+                            sectors = report.sectors
+                        )
+                    } else {
+                        tr!(
+                            "save.saved_slot",
+                            slot = report.slot,
+                            sectors = report.sectors
+                        )
+                    }
+                }
+                TaskResult::Saved(Err((slot, err))) if slot == CUSTOM_SLOT => {
+                    log::error!("Saving failed: {}", err);
+                    tr!("save.failed", error = err)
+                }
+                TaskResult::Saved(Err((slot, err))) => {
+                    log::error!("Saving slot {} failed: {}", slot, err);
+                    if let Some(sectors) = self.dirty.get_mut(slot as usize) {
+                        *sectors = None;
+                    }
+                    tr!("save.slot_failed", slot = slot, error = err)
+                }
+                TaskResult::Loaded(dir, Ok(loaded)) => {
+                    log::info!("Loaded {}", dir.display());
+                    self.loaded = Some(loaded);
+                    self.elapsed = 0.;
+                    tr!("save.loaded", path = dir.display())
+                }
+                TaskResult::Loaded(dir, Err(err)) => {
+                    log::error!("Loading {} failed: {}", dir.display(), err);
+                    tr!("save.load_failed", error = err)
+                }
+            };
+        }
+
+        if self.enabled {
+            self.elapsed += dt;
+        }
+        // nothing to save while the terrain is being replaced
+        if self.task.is_some() || self.loaded.is_some() || universe.terrain_gen_progress().is_some()
+        {
+            return;
+        }
+        if let Some(dir) = self.load_from.take() {
+            self.spawn_read(dir, universe);
+            return;
+        }
+        if let Some(dir) = self.save_to.take() {
+            let sectors = self.full_save_sectors(universe);
+            self.spawn_write(dir, CUSTOM_SLOT, sectors, universe, tech, creeps);
+            return;
+        }
+        let due = self.enabled && self.elapsed >= self.interval;
+        if due || self.save_requested {
+            self.elapsed = 0.;
+            self.save_requested = false;
+            self.start_save(universe, tech, creeps);
+        }
+    }
+
+    /// Sectors a save needs to rebuild the terrain: the edited ones over a base, all of them
+    /// without one.
+    fn full_save_sectors(&self, universe: &Universe) -> Vec<Point3i> {
+        if universe.terrain_base().is_some() {
+            self.edited.iter().cloned().collect()
+        } else {
+            universe.occupied_sectors()
+        }
+    }
+
+    fn start_save(&mut self, universe: &Universe, tech: &TechResource, creeps: &CreepResource) {
+        if self.dirty.len() != self.slots as usize {
+            self.dirty.resize(self.slots as usize, None);
+        }
+        let slot = self.next_slot % self.slots;
+        self.next_slot = (slot + 1) % self.slots;
+
+        let sectors = match self.dirty[slot as usize].replace(HashSet::new()) {
+            Some(dirty) => dirty.into_iter().collect(),
+            None => self.full_save_sectors(universe),
+        };
+        self.spawn_write(Self::slot_path(slot), slot, sectors, universe, tech, creeps);
+    }
+
+    fn spawn_write(
+        &mut self,
+        dir: PathBuf,
+        slot: u32,
+        sectors: Vec<Point3i>,
+        universe: &Universe,
+        tech: &TechResource,
+        creeps: &CreepResource,
+    ) {
+        let snapshots: Vec<_> = sectors
+            .into_iter()
+            .map(|key| universe.snapshot_sector(key))
+            .collect();
+        let manifest = SaveManifest {
+            slot,
+            materials: universe.get_material_names().clone(),
+            base: universe.terrain_base().cloned(),
+            sectors: self
+                .full_save_sectors(universe)
+                .iter()
+                .map(|p| p.0)
+                .collect(),
+        };
+        let world = WorldSave::extract(&universe.world, tech, creeps);
+
+        let result_tx = self.result_tx.clone();
+        self.task = Some(self.task_pool.spawn(async move {
+            let start = Instant::now();
+            let sectors: Vec<_> = snapshots
+                .into_iter()
+                .map(SectorSnapshot::into_save)
+                .collect();
+            let result = Self::write_slot(&dir, &manifest, &sectors, &world)
+                .map(|_| SaveReport {
+                    slot,
+                    dir,
+                    sectors: sectors.len(),
+                    duration: (Instant::now() - start).as_secs_f32(),
+                })
+                .map_err(|err| (slot, err));
+            let _result = result_tx.send(TaskResult::Saved(result));
+        }));
+        self.status = if slot == CUSTOM_SLOT {
+            tr!("save.saving")
+        } else {
+            tr!("save.saving_slot", slot = slot)
+        };
+    }
+
+    fn spawn_read(&mut self, dir: PathBuf, universe: &Universe) {
+        let materials = universe.get_materials_map().clone();
+        let result_tx = self.result_tx.clone();
+        self.task = Some(self.task_pool.spawn(async move {
+            let result = Self::read_slot(&dir, &materials);
+            let _result = result_tx.send(TaskResult::Loaded(dir, result));
+        }));
+        self.status = tr!("save.loading");
+    }
+
+    fn write_slot(
+        dir: &Path,
+        manifest: &SaveManifest,
+        sectors: &[SectorSave],
+        world: &WorldSave,
+    ) -> Result<(), String> {
+        let sectors_dir = dir.join("sectors");
+        std::fs::create_dir_all(&sectors_dir)
+            .map_err(|err| format!("Cannot create {}: {}", sectors_dir.display(), err))?;
+        // the manifest goes last, remove it first so a half written slot is recognizable
+        let manifest_path = dir.join("manifest.ron");
+        if manifest_path.exists() {
+            std::fs::remove_file(&manifest_path)
+                .map_err(|err| format!("Cannot remove {}: {}", manifest_path.display(), err))?;
+        }
+        for sector in sectors {
+            let path = sectors_dir.join(SectorSave::file_name(PointN(sector.key)));
+            let bytes = bincode::serialize(sector).map_err(|err| err.to_string())?;
+            std::fs::write(&path, bytes)
+                .map_err(|err| format!("Cannot write {}: {}", path.display(), err))?;
+        }
+        let world_path = dir.join("world.ron");
+        let world_str =
+            ron::ser::to_string_pretty(world, Default::default()).map_err(|err| err.to_string())?;
+        std::fs::write(&world_path, world_str)
+            .map_err(|err| format!("Cannot write {}: {}", world_path.display(), err))?;
+        let manifest_str = ron::ser::to_string_pretty(manifest, Default::default())
+            .map_err(|err| err.to_string())?;
+        std::fs::write(&manifest_path, manifest_str)
+            .map_err(|err| format!("Cannot write {}: {}", manifest_path.display(), err))
+    }
+
+    fn read_slot(dir: &Path, materials: &HashMap<String, u16>) -> Result<LoadedSave, String> {
+        let read = |path: &Path| {
+            std::fs::read(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))
+        };
+        let manifest_path = dir.join("manifest.ron");
+        let manifest: SaveManifest = ron::de::from_bytes(&read(&manifest_path)?)
+            .map_err(|err| format!("Cannot parse {}: {}", manifest_path.display(), err))?;
+        let world_path = dir.join("world.ron");
+        let world: WorldSave = ron::de::from_bytes(&read(&world_path)?)
+            .map_err(|err| format!("Cannot parse {}: {}", world_path.display(), err))?;
+
+        // saved voxel values are indices into the saved materials plus one, materials that no
+        // longer exist load as empty voxels
+        let remap: Vec<u16> = std::iter::once(0)
+            .chain(
+                manifest
+                    .materials
+                    .iter()
+                    .map(|name| materials.get(name).map_or(0, |index| index + 1)),
+            )
+            .collect();
+        let mut voxels = match &manifest.base {
+            Some(base) => base.generator(materials)?.generate(),
+            None => TerrainGenerator::empty_voxels(),
+        };
+        let sectors_dir = dir.join("sectors");
+        for key in &manifest.sectors {
+            let path = sectors_dir.join(SectorSave::file_name(PointN(*key)));
+            let sector: SectorSave = bincode::deserialize(&read(&path)?)
+                .map_err(|err| format!("Cannot parse {}: {}", path.display(), err))?;
+            sector.write_into(&mut voxels, &remap)?;
+        }
+        Ok(LoadedSave {
+            base: manifest.base,
+            sectors: manifest.sectors.into_iter().map(PointN).collect(),
+            voxels,
+            tech: world.tech,
+            creeps: world.creeps,
+            units: world.units,
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, file_dialogs: &mut FileDialogResource) {
+        egui::CollapsingHeader::new(tr!("save"))
+            .id_source("autosave")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("save.enabled"));
+                ui.add(
+                    egui::Slider::new(&mut self.interval, 10.0..=600.0).text(tr!("save.interval")),
+                );
+                ui.add(egui::Slider::new(&mut self.slots, 1..=10).text(tr!("save.slots")));
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("save.now")).clicked() {
+                        self.request_save();
+                    }
+                    if ui.button(tr!("save.as")).clicked() {
+                        file_dialogs.open(DialogPurpose::SaveGame, None);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    for slot in 0..self.slots {
+                        let path = Self::slot_path(slot);
+                        // a slot without a manifest was never written or is half written
+                        if path.join("manifest.ron").exists()
+                            && ui.button(tr!("save.load_slot", slot = slot)).clicked()
+                        {
+                            self.request_load(path);
+                        }
+                    }
+                    if ui.button(tr!("save.load")).clicked() {
+                        file_dialogs.open(DialogPurpose::LoadGame, None);
+                    }
+                });
+                if self.enabled {
+                    ui.label(tr!(
+                        "save.next",
+                        seconds = format!("{:.0}", (self.interval - self.elapsed).max(0.)),
+                        slot = self.next_slot % self.slots
+                    ));
+                }
+                if !self.status.is_empty() {
+                    ui.label(&self.status);
+                }
+            });
+    }
+}
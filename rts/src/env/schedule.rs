@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use legion::{systems::Builder, Schedule};
+
+/// Order of the per-universe gameplay systems. Systems of one stage can run in parallel when
+/// their component and resource accesses don't conflict, stages are separated by a flush.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum UpdateStage {
+    Movement,
+    Combat,
+    /// Applies the visibility transforms pushed by the earlier stages.
+    VisibilitySync,
+}
+
+impl UpdateStage {
+    pub const ALL: [UpdateStage; 3] = [
+        UpdateStage::Movement,
+        UpdateStage::Combat,
+        UpdateStage::VisibilitySync,
+    ];
+}
+
+type SystemRegistration = Box<dyn Fn(&mut Builder) + Send + Sync>;
+
+/// Systems run on the world of the active universe every unpaused frame. Modules (and plugins)
+/// register a builder function per system in `DemoApp::init`, each universe builds its own
+/// `Schedule` from them on its first update and rebuilds it when registrations change.
+/// Updates that need more than the world and the resources, like orders resolving stable ids
+/// or chunk meshing, are still method calls on `UnitsState` and `Universe`.
+#[derive(Default)]
+pub struct UniverseScheduleResource {
+    stages: BTreeMap<UpdateStage, Vec<(&'static str, SystemRegistration)>>,
+    version: u64,
+}
+
+impl UniverseScheduleResource {
+    pub fn add_system<F>(&mut self, stage: UpdateStage, name: &'static str, register: F)
+    where
+        F: Fn(&mut Builder) + Send + Sync + 'static,
+    {
+        self.stages
+            .entry(stage)
+            .or_default()
+            .push((name, Box::new(register)));
+        self.version += 1;
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn build(&self) -> Schedule {
+        let mut builder = Schedule::builder();
+        for systems in self.stages.values() {
+            for (_, register) in systems {
+                register(&mut builder);
+            }
+            builder.flush();
+        }
+        builder.build()
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("schedule"))
+            .id_source("systems")
+            .default_open(false)
+            .show(ui, |ui| {
+                for stage in UpdateStage::ALL {
+                    let systems = self.stages.get(&stage).map_or(&[][..], |s| &s[..]);
+                    if systems.is_empty() {
+                        ui.label(format!("{:?}: -", stage));
+                    } else {
+                        let names: Vec<_> = systems.iter().map(|(name, _)| *name).collect();
+                        ui.label(format!("{:?}: {}", stage, names.join(", ")));
+                    }
+                }
+            });
+    }
+}
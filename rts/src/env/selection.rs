@@ -0,0 +1,272 @@
+use bevy_tasks::Task;
+use building_blocks::{
+    core::prelude::*,
+    mesh::{greedy_quads, GreedyQuadsBuffer, MergeVoxel, RIGHT_HANDED_Y_UP_CONFIG},
+    storage::prelude::*,
+};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use glam::{Vec3, Vec4};
+use rafx_plugins::features::debug3d::Debug3DResource;
+
+use super::simulation::{MaterialVoxel, Universe};
+use crate::ui::UiState;
+
+// selections bigger than this are not analyzed, the copy alone would stall the frame
+const MAX_SELECTION_VOLUME: i32 = 256 * 256 * 64; // voxels
+
+/// What is in a selected box of terrain, computed on the task pool from a copy of its voxels.
+pub struct SelectionStats {
+    /// Voxel count per material index, empty voxels at 0.
+    pub counts: Vec<usize>,
+    /// Smallest box around the solid voxels, `None` when the selection is empty.
+    pub filled: Option<Extent3i>,
+    /// Quads of the cubic mesh of the selection alone, as if it were a chunk of its own.
+    pub quads: usize,
+}
+
+fn point_min(a: Point3i, b: Point3i) -> Point3i {
+    PointN([a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())])
+}
+
+fn point_max(a: Point3i, b: Point3i) -> Point3i {
+    PointN([a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())])
+}
+
+impl SelectionStats {
+    /// `voxels` covers the selection and one voxel around it, the neighbors decide which faces
+    /// of the border voxels are visible.
+    fn compute(extent: Extent3i, voxels: Array3x1<MaterialVoxel>) -> Self {
+        let mut counts = vec![];
+        let mut min = extent.max();
+        let mut max = extent.minimum;
+        voxels.for_each(&extent, |p: Point3i, voxel: MaterialVoxel| {
+            let idx = voxel.voxel_merge_value() as usize;
+            if counts.len() <= idx {
+                counts.resize(idx + 1, 0);
+            }
+            counts[idx] += 1;
+            if !voxel.is_empty() {
+                min = point_min(min, p);
+                max = point_max(max, p);
+            }
+        });
+        let filled = if counts.iter().skip(1).any(|count| *count > 0) {
+            Some(Extent3i::from_min_and_max(min, max))
+        } else {
+            None
+        };
+        let padded = *voxels.extent();
+        let mut buffer = GreedyQuadsBuffer::new(padded, RIGHT_HANDED_Y_UP_CONFIG.quad_groups());
+        greedy_quads(&voxels, &padded, &mut buffer);
+        Self {
+            counts,
+            filled,
+            quads: buffer.num_quads(),
+        }
+    }
+
+    pub fn solid(&self) -> usize {
+        self.counts.iter().skip(1).sum()
+    }
+}
+
+/// Box of terrain dragged out with the select tool. Its stats are recomputed in the background
+/// whenever the box or the terrain changes, the material replacement is a single undo step.
+pub struct TerrainSelection {
+    // the voxels the drag started and ended on
+    corners: Option<(Point3i, Point3i)>,
+    /// The box reaches this far above and below the dragged corners.
+    pub depth: i32, // voxels
+    dragging: bool,
+    stats: Option<SelectionStats>,
+    // extent and terrain edit count the stats are for, or being computed for
+    computed: Option<(Extent3i, u64)>,
+    task: Option<Task<()>>,
+    result_tx: Sender<(Extent3i, u64, SelectionStats)>,
+    result_rx: Receiver<(Extent3i, u64, SelectionStats)>,
+    replace_from: String,
+    replace_to: String,
+}
+
+impl Default for TerrainSelection {
+    fn default() -> Self {
+        let (result_tx, result_rx) = unbounded();
+        Self {
+            corners: None,
+            depth: 8,
+            dragging: false,
+            stats: None,
+            computed: None,
+            task: None,
+            result_tx,
+            result_rx,
+            replace_from: "".to_string(),
+            replace_to: "".to_string(),
+        }
+    }
+}
+
+impl TerrainSelection {
+    pub fn clear(&mut self) {
+        self.corners = None;
+        self.dragging = false;
+        self.stats = None;
+        self.computed = None;
+    }
+
+    pub fn extent(&self) -> Option<Extent3i> {
+        let (start, end) = self.corners?;
+        let depth = PointN([0, 0, self.depth]);
+        Some(Extent3i::from_min_and_max(
+            point_min(start, end) - depth,
+            point_max(start, end) + depth,
+        ))
+    }
+
+    /// Call while the mouse is down with the voxel under the cursor, the box spans from where
+    /// the drag started.
+    pub fn drag(&mut self, hit: Point3i) {
+        let start = match self.corners {
+            Some((start, _)) if self.dragging => start,
+            _ => hit,
+        };
+        self.corners = Some((start, hit));
+        self.dragging = true;
+    }
+
+    pub fn end_drag(&mut self) {
+        self.dragging = false;
+    }
+
+    #[profiling::function]
+    pub fn update(&mut self, universe: &Universe) {
+        for (extent, edits, stats) in self.result_rx.try_iter() {
+            self.task = None;
+            if self.computed == Some((extent, edits)) {
+                self.stats = Some(stats);
+            }
+        }
+        let extent = match self.extent() {
+            Some(extent) if !self.dragging => extent,
+            _ => return,
+        };
+        let key = (extent, universe.edit_count());
+        if self.computed == Some(key) || self.task.is_some() {
+            return;
+        }
+        if self.computed.map(|(computed, _)| computed) != Some(extent) {
+            self.stats = None;
+        }
+        self.computed = Some(key);
+        if extent.num_points() > MAX_SELECTION_VOLUME as usize {
+            self.stats = None;
+            return;
+        }
+        let voxels = universe.extract_voxels(&extent.padded(1));
+        let result_tx = self.result_tx.clone();
+        self.task = Some(universe.task_pool().spawn(async move {
+            let stats = SelectionStats::compute(extent, voxels);
+            let _result = result_tx.send((key.0, key.1, stats));
+        }));
+    }
+
+    /// Returns the materials to replace, by name, when the button is clicked.
+    pub fn ui(&mut self, ui: &mut egui::Ui, materials: &Vec<String>) -> Option<(String, String)> {
+        let extent = self.extent()?;
+        let mut replace = None;
+        egui::CollapsingHeader::new(tr!("selection"))
+            .id_source("terrain selection")
+            .default_open(true)
+            .show(ui, |ui| {
+                let shape = extent.shape;
+                ui.label(tr!(
+                    "selection.box",
+                    x = shape.x(),
+                    y = shape.y(),
+                    z = shape.z(),
+                    count = extent.num_points()
+                ));
+                ui.add(egui::Slider::new(&mut self.depth, 0..=64).text(tr!("selection.depth")));
+                if extent.num_points() > MAX_SELECTION_VOLUME as usize {
+                    ui.label(tr!("selection.too_big"));
+                } else {
+                    match &self.stats {
+                        Some(stats) => Self::stats_ui(ui, stats, materials),
+                        None => {
+                            ui.label(tr!("selection.counting"));
+                        }
+                    }
+                }
+                if self.replace_from.is_empty() {
+                    self.replace_from = materials[0].clone();
+                    self.replace_to = materials[0].clone();
+                }
+                let from_label = tr!("selection.replace_from");
+                self.replace_from =
+                    UiState::combo_box(ui, materials, &self.replace_from, &from_label).to_string();
+                let to_label = tr!("selection.replace_to");
+                self.replace_to =
+                    UiState::combo_box(ui, materials, &self.replace_to, &to_label).to_string();
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("selection.replace")).clicked()
+                        && self.replace_from != self.replace_to
+                    {
+                        replace = Some((self.replace_from.clone(), self.replace_to.clone()));
+                    }
+                    if ui.button(tr!("selection.clear")).clicked() {
+                        self.clear();
+                    }
+                });
+            });
+        replace
+    }
+
+    fn stats_ui(ui: &mut egui::Ui, stats: &SelectionStats, materials: &Vec<String>) {
+        match stats.filled {
+            Some(filled) => ui.label(tr!(
+                "selection.solid",
+                count = stats.solid(),
+                x = filled.shape.x(),
+                y = filled.shape.y(),
+                z = filled.shape.z()
+            )),
+            None => ui.label(tr!("selection.empty")),
+        };
+        for (idx, count) in stats.counts.iter().enumerate().skip(1) {
+            if *count > 0 {
+                let name = materials.get(idx - 1).map_or("???", |name| name.as_str());
+                ui.label(format!("{}: {}", name, count));
+            }
+        }
+        ui.label(tr!(
+            "selection.mesh",
+            quads = stats.quads,
+            triangles = 2 * stats.quads
+        ))
+        .on_hover_text(tr!("selection.mesh.hint"));
+    }
+
+    pub fn add_debug_draw(&self, debug_draw: &mut Debug3DResource) {
+        let extent = match self.extent() {
+            Some(extent) => extent,
+            None => return,
+        };
+        let color = Vec4::new(0.3, 0.8, 1., 1.);
+        let min = extent.minimum;
+        let max = extent.least_upper_bound();
+        let corner = |x: i32, y: i32, z: i32| Vec3::new(x as f32, y as f32, z as f32);
+        let bottom = [
+            corner(min.x(), min.y(), min.z()),
+            corner(max.x(), min.y(), min.z()),
+            corner(max.x(), max.y(), min.z()),
+            corner(min.x(), max.y(), min.z()),
+        ];
+        let height = Vec3::new(0., 0., (max.z() - min.z()) as f32);
+        for i in 0..4 {
+            let (a, b) = (bottom[i], bottom[(i + 1) % 4]);
+            debug_draw.add_line(a, b, color);
+            debug_draw.add_line(a + height, b + height, color);
+            debug_draw.add_line(a, a + height, color);
+        }
+    }
+}
@@ -1,26 +1,31 @@
 use std::{
     cmp::{max, min},
-    collections::{HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 use bevy_tasks::{Task, TaskPool, TaskPoolBuilder};
 use building_blocks::{
     core::prelude::*,
     mesh::{
-        greedy_quads, padded_greedy_quads_chunk_extent, GreedyQuadsBuffer, IsOpaque, MergeVoxel,
-        QuadGroup, RIGHT_HANDED_Y_UP_CONFIG,
+        greedy_quads, padded_greedy_quads_chunk_extent, surface_nets, GreedyQuadsBuffer, IsOpaque,
+        MergeVoxel, QuadGroup, SurfaceNetsBuffer, RIGHT_HANDED_Y_UP_CONFIG,
     },
-    search::GridRayTraversal3,
     storage::{prelude::*, ChunkHashMap3},
 };
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use distill::loader::handle::Handle;
 use fnv::FnvHashMap;
-use glam::{Quat, Vec3};
-use legion::{Entity, Resources, World};
+use glam::{Mat4, Quat, Vec3, Vec4};
+use legion::{Entity, IntoQuery, Read, Resources, Schedule, World};
 use rafx::{
     api::RafxIndexType,
-    assets::{push_buffer::PushBuffer, AssetManager},
+    assets::{distill_impl::AssetResource, push_buffer::PushBuffer, AssetManager},
     base::Instant,
     rafx_visibility::{
         geometry::{AxisAlignedBoundingBox, BoundingSphere},
@@ -34,22 +39,74 @@ use rafx_plugins::{
     components::{
         DirectionalLightComponent, MeshComponent, TransformComponent, VisibilityComponent,
     },
-    features::mesh_adv::{MeshVertexFull, MeshVertexPosition},
+    features::mesh_adv::MeshVertexPosition,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
     assets::{
+        fallback::FallbackAssetsResource,
         pbr_material::PbrMaterialAsset,
-        tile::{TileAsset, TileExporter},
+        tile::{TileAsset, TileExporter, TileProperties},
+    },
+    audit::{AuditToken, AuditedHandle},
+    env::env::TileComponent,
+    env::{
+        biome::BiomeParams,
+        edit::{TerrainEditBatch, TerrainUndoHistory},
+        hierarchy::{self, ParentComponent},
+        navmesh::NavMesh,
+        noise::NoiseParams,
+        ray_accel::ChunkOccupancy,
+        save::SectorSnapshot,
+        schedule::UniverseScheduleResource,
+        stable_id::{StableId, StableIdRegistry},
+        terrain_gen::{TerrainGenJob, TerrainGenerator},
+        throttle::MeshThrottleResource,
+        world_edge::{self, SKIRT_SIDES},
     },
-    env::perlin::PerlinNoise2D,
     features::dyn_mesh::{
         DynMeshCommand, DynMeshCommandResults, DynMeshData, DynMeshDataPart, DynMeshHandle,
-        DynMeshManager, DynMeshRenderObject, DynMeshRenderObjectSet,
+        DynMeshManager, DynMeshRenderObject, DynMeshRenderObjectSet, DynMeshVertex,
+        DynMeshVertexCompact, DynMeshVertexFormat, DynMeshVertexPositionCompact,
     },
+    perf_experiments::{PerfExperimentsResource, Subsystem},
+    unit::{building, garrison::GarrisonComponent, unit::UnitComponent},
 };
 
-#[derive(Clone, Copy, Default)]
+/// Why a tile doesn't fit, see `Universe::validate_footprint`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FootprintError {
+    /// Some ground under the footprint is missing or lower than the rest.
+    Uneven,
+    /// Voxels fill the space the tile takes.
+    Blocked,
+    /// Overlaps another building.
+    Building,
+    /// A unit stands on the footprint.
+    Unit,
+}
+
+impl std::fmt::Display for FootprintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            FootprintError::Uneven => tr!("footprint.uneven"),
+            FootprintError::Blocked => tr!("footprint.blocked"),
+            FootprintError::Building => tr!("footprint.building"),
+            FootprintError::Unit => tr!("footprint.unit"),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// Voxels to fill under a tile so it has ground under its whole footprint, see
+/// `Universe::validate_footprint_with_foundation`.
+pub struct Foundation {
+    pub fill: Vec<Point3i>,
+    pub material: MaterialVoxel,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub struct MaterialVoxel(u16);
 
 impl MaterialVoxel {
@@ -60,6 +117,10 @@ impl MaterialVoxel {
     pub fn from_material_index(material: u16) -> Self {
         Self(material)
     }
+
+    pub fn material_index(&self) -> u16 {
+        self.0
+    }
 }
 
 impl MergeVoxel for MaterialVoxel {
@@ -91,6 +152,8 @@ struct ChunkTaskMetrics {
 struct ChunkExtractMetrics {
     pub tasks: u32,
     pub extract_time: u32, // µs
+    // task pool jobs the copies were split in, 1 when done on the main thread
+    pub batches: u32,
 }
 
 struct SingleDistributionMetrics {
@@ -182,7 +245,12 @@ impl ChunkMetrics {
             samples: extract_total,
             failed: 0,
             min_time: 0.,
-            max_time: 0.,
+            max_time: self
+                .extract
+                .iter()
+                .map(|r| r.extract_time)
+                .max()
+                .unwrap_or_default() as f64,
             avg_time: self
                 .extract
                 .iter()
@@ -223,15 +291,29 @@ impl ChunkMetrics {
 struct ChunkTaskResults {
     pub key: ChunkKey3,
     pub mesh: Option<DynMeshData>,
+    /// Parts were left out of `mesh` because their materials are still loading.
+    pub pending_materials: bool,
+    /// Materials ready when the job started.
+    pub ready_materials: usize,
     pub metrics: ChunkTaskMetrics,
 }
 
 struct Chunk {
     pub entity: Option<Entity>,
+    pub id: Option<StableId>,
     pub mesh: Option<DynMeshHandle>,
     pub render_object: Option<RenderObjectHandle>,
     pub visibility_object: Option<VisibilityObjectArc>,
+    // position and bounds of the mesh, kept for `register_visibility`
+    pub bounds: Option<(Vec3, VisibleBounds)>,
+    pub audit: Vec<AuditToken>,
     pub dirty: bool,
+    pub simplified: bool,
+    /// Meshed without the parts of materials still loading, remeshed when they are ready.
+    pub pending_materials: bool,
+    /// Taken from `CHUNK_VERSION` on every edit, so it never repeats, even across
+    /// `reset_chunks` or a new `Universe`.
+    pub version: u64,
     pub builder: Option<Task<()>>,
 }
 
@@ -239,33 +321,197 @@ impl Chunk {
     pub fn new() -> Self {
         Chunk {
             entity: None,
+            id: None,
             mesh: None,
             render_object: None,
             visibility_object: None,
+            bounds: None,
+            audit: vec![],
             dirty: false,
+            simplified: false,
+            pending_materials: false,
+            version: 0,
             builder: None,
         }
     }
 
-    fn clear(&mut self, world: &mut World) {
+    /// Spawns the entity drawing the newly added `mesh`, with its render and visibility objects.
+    #[allow(clippy::too_many_arguments)]
+    fn attach(
+        &mut self,
+        mesh: DynMeshHandle,
+        position: Vec3,
+        visible_bounds: VisibleBounds,
+        world: &mut World,
+        ids: &mut StableIdRegistry,
+        visibility_region: &VisibilityRegion,
+        dyn_mesh_render_objects: &mut DynMeshRenderObjectSet,
+    ) {
+        self.mesh = Some(mesh.clone());
+        self.audit.push(AuditToken::new(AuditedHandle::DynMesh));
+
+        let transform_component = TransformComponent {
+            translation: Vec3::ZERO,
+            scale: Vec3::ONE,
+            rotation: Quat::IDENTITY,
+        };
+
+        let render_object_handle =
+            dyn_mesh_render_objects.register_render_object(DynMeshRenderObject { mesh });
+        let mesh_component = MeshComponent {
+            render_object_handle: render_object_handle.clone(),
+        };
+
+        let entity = world.push((transform_component, mesh_component));
+        let id = ids.register(entity);
+        self.entity = Some(entity);
+        self.id = Some(id);
+
+        let visibility_object_handle = {
+            let handle = visibility_region.register_static_object(
+                ObjectId::from(id),
+                CullModel::VisibleBounds(visible_bounds),
+            );
+            handle.set_transform(position, Quat::IDENTITY, Vec3::ONE);
+            handle.add_render_object(&render_object_handle);
+            handle
+        };
+        let mut entry = world.entry(entity).unwrap();
+        entry.add_component(id);
+        entry.add_component(VisibilityComponent {
+            visibility_object_handle: visibility_object_handle.clone(),
+        });
+
+        self.visibility_object = Some(visibility_object_handle);
+        self.render_object = Some(render_object_handle);
+        self.bounds = Some((position, visible_bounds));
+        self.audit
+            .push(AuditToken::new(AuditedHandle::VisibilityObject));
+        self.audit
+            .push(AuditToken::new(AuditedHandle::RenderObject));
+    }
+
+    /// Gives the attached chunk its own visibility object again, see `SectorVisibility`.
+    fn register_visibility(&mut self, world: &mut World, visibility_region: &VisibilityRegion) {
+        if self.visibility_object.is_some() {
+            return;
+        }
+        let (id, entity, render_object, (position, visible_bounds)) =
+            match (self.id, self.entity, &self.render_object, &self.bounds) {
+                (Some(id), Some(entity), Some(render_object), Some(bounds)) => {
+                    (id, entity, render_object, bounds)
+                }
+                _ => return,
+            };
+        let handle = visibility_region.register_static_object(
+            ObjectId::from(id),
+            CullModel::VisibleBounds(visible_bounds.clone()),
+        );
+        handle.set_transform(*position, Quat::IDENTITY, Vec3::ONE);
+        handle.add_render_object(render_object);
+        if let Some(mut entry) = world.entry(entity) {
+            entry.add_component(VisibilityComponent {
+                visibility_object_handle: handle.clone(),
+            });
+        }
+        self.visibility_object = Some(handle);
+    }
+
+    /// Drops the visibility object of the chunk, its sector object draws it instead.
+    fn unregister_visibility(&mut self, world: &mut World) {
+        if self.visibility_object.take().is_none() {
+            return;
+        }
+        // the component holds a clone of the handle that would keep the object alive
+        if let Some(mut entry) = self.entity.and_then(|entity| world.entry(entity)) {
+            entry.remove_component::<VisibilityComponent>();
+        }
+    }
+
+    /// Draws the attached mesh `offset` away from where it was built.
+    fn set_offset(&mut self, world: &mut World, offset: Vec3) {
+        if let Some(mut entry) = self.entity.and_then(|entity| world.entry(entity)) {
+            if let Ok(transform) = entry.get_component_mut::<TransformComponent>() {
+                transform.translation = offset;
+            }
+        }
+        if let (Some(handle), Some((position, _))) = (&self.visibility_object, &self.bounds) {
+            handle.set_transform(*position + offset, Quat::IDENTITY, Vec3::ONE);
+        }
+    }
+
+    /// World space bounds of the attached mesh.
+    fn world_aabb(&self) -> Option<(Vec3, Vec3)> {
+        self.render_object.as_ref()?;
+        self.bounds
+            .as_ref()
+            .map(|(position, bounds)| (*position + bounds.aabb.min, *position + bounds.aabb.max))
+    }
+
+    fn clear(&mut self, world: &mut World, ids: &mut StableIdRegistry) {
         self.mesh.take();
         self.render_object.take();
         self.visibility_object.take();
+        self.bounds.take();
+        self.audit.clear();
+        if let Some(id) = self.id.take() {
+            ids.unregister(id);
+        }
         if let Some(entity) = self.entity.take() {
             world.remove(entity);
         }
     }
 }
 
+/// Terrain counters of one frame, see `Universe::stats`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct UniverseStats {
+    pub mesh_jobs: usize,
+    pub chunk_meshes: usize,
+    /// Chunk meshes at least partly inside the side planes of the view.
+    pub visible_chunks: usize,
+    pub entities: usize,
+}
+
+/// One visibility object standing in for the chunk meshes of a sector, see
+/// `Universe::update_sector_visibility`.
+#[derive(Default)]
+struct SectorVisibility {
+    object: Option<VisibilityObjectArc>,
+    // the chunks have their own visibility objects instead
+    expanded: bool,
+    // chunk meshes were added or removed since the object was built
+    dirty: bool,
+}
+
+/// A mesh following the terrain that isn't part of it, see `Universe::set_overlay_mesh`.
+struct Overlay {
+    chunk: Chunk,
+    // the replacement mesh, swapped in once uploaded so the old one shows meanwhile
+    pending: Option<(DynMeshHandle, Vec3, VisibleBounds)>,
+    // see `Universe::set_overlay_offset`
+    offset: Vec3,
+}
+
 pub type MaterialVoxels = ChunkHashMap3<MaterialVoxel, ChunkMapBuilder3x1<MaterialVoxel>>;
 
 pub struct Universe {
     id: UniverseId,
     initialized: bool,
     pub world: World,
+    pub ids: StableIdRegistry,
     pub visibility_region: VisibilityRegion,
+    // built from `UniverseScheduleResource`, with the registrations version
+    schedule: Option<(u64, Schedule)>,
     pub main_view_frustum: ViewFrustumArc,
     pub main_light: Option<Entity>,
+    pub mesh_style: TerrainMeshStyle,
+    /// The terrain wraps around the xy edges of `terrain_bounds`, see `wrap_point`.
+    pub wrap: bool,
+    // bake ambient occlusion into cubic chunk meshes, see `set_chunk_ao`
+    chunk_ao: bool,
+    // pack cubic chunk vertices into `DynMeshVertexCompact`, see `set_compact_vertices`
+    compact_vertices: bool,
     materials: Vec<Handle<PbrMaterialAsset>>,
     material_names: Vec<String>,
     materials_map: HashMap<String, u16>,
@@ -274,22 +520,72 @@ pub struct Universe {
     active_meshers: usize,
     chunks: HashMap<ChunkKey3, Chunk>,
     sectors: HashMap<Point3i, HashSet<ChunkKey3>>,
+    edited_sectors: HashSet<Point3i>,
+    // empty while every chunk has its own visibility object
+    sector_visibility: HashMap<Point3i, SectorVisibility>,
     mesher_tx: Sender<ChunkTaskResults>,
     mesher_rx: Receiver<ChunkTaskResults>,
+    // kept between frames so extraction doesn't reallocate them, see `extract_mesh_voxels`
+    extract_keys: Vec<(ChunkKey3, bool)>,
+    extract_buffer: Vec<(ChunkKey3, Array3x1<MaterialVoxel>, bool)>,
     metrics: ChunkMetrics,
     mesh_cmd_tx: Sender<DynMeshCommand>,
     mesh_cmd_rx: Receiver<DynMeshCommandResults>,
     mesh_add_requests: HashMap<usize, (ChunkKey3, VisibleBounds)>,
     current_mesh_add_request: usize,
+    // identity of the committed material instances the chunks were meshed with, `None` for
+    // materials still loading
+    material_instances: Vec<Option<usize>>,
+    // solid voxels cleared since the last `take_removed_voxels`
+    removed_voxels: Vec<Point3i>,
+    undo: TerrainUndoHistory,
+    // batches applied with `apply_edits` that changed something
+    edit_count: u64,
+    pub navmesh: NavMesh,
+    // bounding extent of the voxels, kept in `reset_chunks` and `set_chunk_dirty`
+    terrain_bounds: Extent3i,
+    // one wall per side of `SKIRT_SIDES`, rebuilt in `update_skirt` when dirty
+    skirt: Vec<Chunk>,
+    skirt_depth: u32,
+    skirt_dirty: bool,
+    // side index, position and bounds of the skirt meshes being added
+    skirt_requests: HashMap<usize, (usize, Vec3, VisibleBounds)>,
+    occupancy: ChunkOccupancy,
+    // by owner and key
+    overlays: HashMap<(&'static str, Point3i), Overlay>,
+    // by chunk minimum, the topmost solid voxel of each of its columns and the chunk version
+    // they were found at, see `column_tops`
+    column_tops: HashMap<Point3i, (u64, Vec<Option<i32>>)>,
+    // background generation started by `start_reset`, whether it clears the world and what it
+    // generates
+    terrain_gen: Option<(TerrainGenJob, bool, TerrainBase)>,
+    // what the current terrain was generated from, `None` for loaded terrain without one
+    base: Option<TerrainBase>,
+    // bumped whenever the whole terrain is replaced, see `terrain_revision`
+    terrain_revision: u64,
 }
 
 const MAX_CHUNK_MESH_JOBS: usize = 16;
-const MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME: usize = 4;
+pub const MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME: usize = 4;
 const MAX_CHUNK_MESH_JOBS_INIT: usize = 65536;
 const MAX_DISTANCE_FROM_CAMERA: i32 = 256;
+// chunks further than `MAX_DISTANCE_FROM_CAMERA` plus this lose their meshes on idle frames, the
+// margin keeps the ones near the edge from being remeshed every time the camera moves a bit
+const CHUNK_EVICTION_MARGIN: i32 = 64;
+const MAX_CHUNK_EVICTIONS_PER_FRAME: usize = 8;
+// chunks (not sectors) further than this from the eye mesh with flattened materials, the ring
+// between it and `MAX_DISTANCE_FROM_CAMERA` is far enough that the material seams don't show
+const SIMPLIFIED_MESH_DISTANCE: i32 = 160;
 const SECTOR_SIZE: i32 = 256;
+const RAY_CAST_MAX_DISTANCE: f32 = 1024.; // voxels
 const TILE_EDIT_PLATFORM_SIZE: i32 = 32;
 
+// smaller extractions copy on the main thread, the task pool round trip costs more
+const PARALLEL_EXTRACT_MIN_CHUNKS: usize = 8;
+
+// shared by every universe, so colliders and samples cached per chunk key see any rebuild as a change
+static CHUNK_VERSION: AtomicU64 = AtomicU64::new(0);
+
 impl Universe {
     pub fn get_default_material_names() -> Vec<&'static str> {
         vec![
@@ -303,6 +599,15 @@ impl Universe {
             "diamond_inlay_tile",
             "black_plastic",
             "curly_tile",
+            "wreck",
+            "rain",
+            "snow",
+            "hit_flash",
+            "analysis_0",
+            "analysis_1",
+            "analysis_2",
+            "analysis_3",
+            "analysis_4",
         ]
     }
 
@@ -310,13 +615,36 @@ impl Universe {
         &self.material_names
     }
 
-    fn get_loaded_materials(&self, asset_manager: &AssetManager) -> Option<Vec<PbrMaterialAsset>> {
-        let mut materials = vec![];
-        for handle in self.materials.iter() {
-            let mat = asset_manager.committed_asset(handle)?;
-            materials.push(mat.clone());
-        }
-        Some(materials)
+    pub fn get_materials_map(&self) -> &HashMap<String, u16> {
+        &self.materials_map
+    }
+
+    /// Materials that failed to load are replaced by the fallback material, `None` while
+    /// some are still loading.
+    pub fn get_loaded_materials(&self, resources: &Resources) -> Option<Vec<PbrMaterialAsset>> {
+        self.get_material_slots(resources).into_iter().collect()
+    }
+
+    /// Like `get_loaded_materials`, but per material, `None` for the ones still loading. These
+    /// are reported to `FallbackAssetsResource`, which warns about those taking long.
+    pub fn get_material_slots(&self, resources: &Resources) -> Vec<Option<PbrMaterialAsset>> {
+        let asset_manager = resources.get::<AssetManager>().unwrap();
+        let asset_resource = resources.get::<AssetResource>().unwrap();
+        let mut fallbacks = resources.get_mut::<FallbackAssetsResource>().unwrap();
+        let slots: Vec<_> = self
+            .materials
+            .iter()
+            .zip(&self.material_names)
+            .map(|(handle, name)| fallbacks.material(&asset_manager, &asset_resource, handle, name))
+            .collect();
+        let pending: Vec<_> = slots
+            .iter()
+            .zip(&self.material_names)
+            .filter(|(slot, _)| slot.is_none())
+            .map(|(_, name)| name.as_str())
+            .collect();
+        fallbacks.set_pending_materials(&pending);
+        slots
     }
 
     pub fn get_pallete_voxel_string(
@@ -351,44 +679,312 @@ impl Universe {
             .and_then(|idx| Some(MaterialVoxel(*idx + 1)))
     }
 
+    /// First solid voxel along `ray` from `start`, up to `RAY_CAST_MAX_DISTANCE` away. Empty
+    /// sectors and chunks are skipped whole, see `ChunkOccupancy`.
     pub fn ray_cast(&self, start: Vec3, ray: Vec3) -> Option<RayCastResult> {
-        let start = PointN([start.x, start.y, start.z]);
-        let ray = PointN([ray.x, ray.y, ray.z]);
-        let mut traversal = GridRayTraversal3::new(start, ray);
-        let mut prev = PointN([start.x() as i32, start.y() as i32, start.z() as i32]);
-        for _ in 0..256 {
-            let current = traversal.current_voxel();
-            let vox = self.voxels.get_point(0, current);
-            if vox.0 != 0 {
-                return Some(RayCastResult {
-                    hit: current,
-                    before_hit: prev,
-                });
+        let t_max = RAY_CAST_MAX_DISTANCE / ray.length().max(f32::EPSILON);
+        let mut result = None;
+        self.occupancy.traverse_voxels(start, ray, t_max, |voxel| {
+            if self.voxels.get_point(0, voxel).is_empty() {
+                return true;
+            }
+            let (t, normal) = Self::ray_entry(start, ray, voxel);
+            result = Some(RayCastResult {
+                hit: voxel,
+                // the face neighbor the ray came from, possibly in a skipped chunk
+                before_hit: voxel + normal,
+                normal,
+                distance: t * ray.length(),
+            });
+            false
+        });
+        result
+    }
+
+    /// Where the ray enters the unit cube of `voxel`, 0 if it starts inside, and the normal of
+    /// the face it enters through, up if it starts inside.
+    fn ray_entry(start: Vec3, ray: Vec3, voxel: Point3i) -> (f32, Point3i) {
+        let min = Vec3::new(voxel.x() as f32, voxel.y() as f32, voxel.z() as f32);
+        let mut t_enter = 0f32;
+        let mut normal = PointN([0, 0, 1]);
+        for (axis, (s, r, m)) in [
+            (start.x, ray.x, min.x),
+            (start.y, ray.y, min.y),
+            (start.z, ray.z, min.z),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if r != 0. {
+                let (t0, t1) = ((m - s) / r, (m + 1. - s) / r);
+                if t0.min(t1) > t_enter {
+                    t_enter = t0.min(t1);
+                    normal = Point3i::ZERO;
+                    normal.0[axis] = if r > 0. { -1 } else { 1 };
+                }
             }
-            prev = current;
-            traversal.step();
         }
-        return None;
+        (t_enter, normal)
     }
 
-    pub fn update_voxel(&mut self, point: Point3i, voxel: MaterialVoxel) {
-        let vox_ref: &mut MaterialVoxel = self.voxels.get_mut_point(0, point);
-        *vox_ref = voxel;
-        let keys = self
-            .voxels
-            .indexer
-            .chunk_mins_for_extent(&Extent3i::from_min_and_shape(point, Point3i::ONES).padded(1))
-            .map(|p| ChunkKey3::new(0, p));
+    pub fn get_voxel(&self, point: Point3i) -> MaterialVoxel {
+        self.voxels.get_point(0, self.wrap_point(point))
+    }
+
+    /// `point` moved into the terrain bounds by whole terrain widths when the universe wraps,
+    /// unchanged otherwise.
+    pub fn wrap_point(&self, point: Point3i) -> Point3i {
+        if self.wrap {
+            Self::wrap_in_bounds(&self.terrain_bounds, point)
+        } else {
+            point
+        }
+    }
+
+    /// Same as `wrap_point` for world positions, eg. of the camera or the units.
+    pub fn wrap_position(&self, position: Vec3) -> Vec3 {
+        let shape = self.terrain_bounds.shape;
+        if !self.wrap || shape.x() <= 0 || shape.y() <= 0 {
+            return position;
+        }
+        let min = self.terrain_bounds.minimum;
+        let wrap =
+            |v: f32, min: i32, size: i32| min as f32 + (v - min as f32).rem_euclid(size as f32);
+        Vec3::new(
+            wrap(position.x, min.x(), shape.x()),
+            wrap(position.y, min.y(), shape.y()),
+            position.z,
+        )
+    }
+
+    fn wrap_in_bounds(bounds: &Extent3i, point: Point3i) -> Point3i {
+        let (min, shape) = (bounds.minimum, bounds.shape);
+        if shape.x() <= 0 || shape.y() <= 0 {
+            return point;
+        }
+        PointN([
+            min.x() + (point.x() - min.x()).rem_euclid(shape.x()),
+            min.y() + (point.y() - min.y()).rem_euclid(shape.y()),
+            point.z(),
+        ])
+    }
+
+    /// Moves the root entities that left the terrain bounds to the other side, when the
+    /// universe wraps. Children follow their parents in `update_transform_hierarchy`.
+    pub fn wrap_entities(&mut self) {
+        if !self.wrap {
+            return;
+        }
+        let mut moved = vec![];
+        let mut query = <(Entity, Read<TransformComponent>)>::query();
+        for (entity, transform) in query.iter(&self.world) {
+            let wrapped = self.wrap_position(transform.translation);
+            if wrapped != transform.translation {
+                moved.push((*entity, wrapped));
+            }
+        }
+        for (entity, translation) in moved {
+            if let Some(mut entry) = self.world.entry(entity) {
+                if entry.get_component::<ParentComponent>().is_ok() {
+                    continue;
+                }
+                let transform = match entry.get_component_mut::<TransformComponent>() {
+                    Ok(transform) => {
+                        transform.translation = translation;
+                        *transform
+                    }
+                    Err(_) => continue,
+                };
+                if let Ok(visibility) = entry.get_component::<VisibilityComponent>() {
+                    visibility.visibility_object_handle.set_transform(
+                        transform.translation,
+                        transform.rotation,
+                        transform.scale,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Ground path from `from` to `to` over the navmesh, see `NavMesh::find_path`.
+    pub fn find_path(&mut self, from: Vec3, to: Vec3) -> Option<Vec<Vec3>> {
+        self.navmesh.find_path(&self.voxels, &self.world, from, to)
+    }
+
+    /// Writes a batch of voxels, marking every touched chunk dirty once instead of once per
+    /// voxel. Not undoable, edits made by the player go through `apply_edits`.
+    pub fn update_voxels<I: IntoIterator<Item = (Point3i, MaterialVoxel)>>(&mut self, edits: I) {
+        let mut keys = HashSet::new();
+        for (point, voxel) in edits {
+            let point = self.wrap_point(point);
+            let vox_ref: &mut MaterialVoxel = self.voxels.get_mut_point(0, point);
+            if voxel.is_empty() && !vox_ref.is_empty() {
+                self.removed_voxels.push(point);
+            }
+            *vox_ref = voxel;
+            keys.extend(
+                self.voxels
+                    .indexer
+                    .chunk_mins_for_extent(
+                        &Extent3i::from_min_and_shape(point, Point3i::ONES).padded(1),
+                    )
+                    .map(|p| ChunkKey3::new(0, p)),
+            );
+        }
+        if self.wrap {
+            // the chunks across a seam have the edited voxels in their padding
+            keys = keys
+                .into_iter()
+                .map(|key| ChunkKey3::new(0, self.wrap_point(key.minimum)))
+                .collect();
+        }
         for key in keys {
             self.set_chunk_dirty(key);
         }
     }
 
-    pub fn clear_voxel(&mut self, point: Point3i) {
-        self.update_voxel(point, MaterialVoxel::empty());
+    /// Writes the voxels of the batch that change and records their previous values as an
+    /// undo step.
+    pub fn apply_edits(&mut self, batch: TerrainEditBatch) {
+        let (edits, merge) = batch.into_edits();
+        let changed: Vec<_> = edits
+            .into_iter()
+            .filter(|(point, voxel)| self.get_voxel(*point) != *voxel)
+            .collect();
+        let previous = changed
+            .iter()
+            .map(|(point, _)| (*point, self.get_voxel(*point)))
+            .collect();
+        if !changed.is_empty() {
+            self.edit_count += 1;
+        }
+        self.undo.push(previous, merge);
+        self.update_voxels(changed);
+    }
+
+    /// Replaces `from` with `to` in `extent` as one undo step, returns how many voxels changed.
+    pub fn replace_material(
+        &mut self,
+        extent: Extent3i,
+        from: MaterialVoxel,
+        to: MaterialVoxel,
+    ) -> usize {
+        let mut batch = TerrainEditBatch::new();
+        let mut count = 0;
+        self.extract_voxels(&extent)
+            .for_each(&extent, |p: Point3i, voxel: MaterialVoxel| {
+                if voxel == from {
+                    batch.set(p, to);
+                    count += 1;
+                }
+            });
+        self.apply_edits(batch);
+        count
+    }
+
+    /// Reverts the last batch applied with `apply_edits`, returns false if there is none.
+    pub fn undo_edit(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(step) => {
+                self.update_voxels(step.into_iter().rev());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn undo_steps(&self) -> usize {
+        self.undo.step_count()
+    }
+
+    pub fn edit_count(&self) -> u64 {
+        self.edit_count
+    }
+
+    /// The pool of the chunk meshing jobs, shared by all universes.
+    pub fn task_pool(&self) -> &TaskPool {
+        &self.task_pool
+    }
+
+    pub fn id(&self) -> UniverseId {
+        self.id
+    }
+
+    /// Solid voxels cleared by edits since the last call.
+    pub fn take_removed_voxels(&mut self) -> Vec<Point3i> {
+        std::mem::take(&mut self.removed_voxels)
+    }
+
+    /// Copy of the voxels inside `extent`, for processing off the main thread.
+    pub fn extract_voxels(&self, extent: &Extent3i) -> Array3x1<MaterialVoxel> {
+        let mut voxels = Array3x1::fill(*extent, MaterialVoxel::empty());
+        copy_extent(extent, &self.voxels.lod_view(0), &mut voxels);
+        voxels
+    }
+
+    /// Lowest voxel layer of the map, voxels resting on it are on the ground.
+    pub fn ground_level(&self) -> i32 {
+        self.voxels.bounding_extent(0).minimum.z()
+    }
+
+    /// Chunks whose center is within `radius` of `center` in the xy plane, with their edit
+    /// version. The version changes every time the chunk's voxels are modified.
+    pub fn chunks_near(&self, center: Vec3, radius: f32) -> Vec<(ChunkKey3, u64)> {
+        self.chunks
+            .iter()
+            .filter(|(key, _)| {
+                let extent = self.voxels.indexer.extent_for_chunk_with_min(key.minimum);
+                let dx = key.minimum.x() as f32 + extent.shape.x() as f32 / 2. - center.x;
+                let dy = key.minimum.y() as f32 + extent.shape.y() as f32 / 2. - center.y;
+                dx * dx + dy * dy <= radius * radius
+            })
+            .map(|(key, chunk)| (*key, chunk.version))
+            .collect()
+    }
+
+    /// Boxes covering the solid voxels of a chunk, one per vertical run of a voxel column.
+    /// Returned as `(center, half_extents)` pairs.
+    pub fn collision_boxes(&self, key: ChunkKey3) -> Vec<(Vec3, Vec3)> {
+        let extent = self.voxels.indexer.extent_for_chunk_with_min(key.minimum);
+        let min = extent.minimum;
+        let max = extent.least_upper_bound();
+        let mut boxes = vec![];
+        let mut push_run = |x: i32, y: i32, z0: i32, z1: i32| {
+            let half_height = (z1 - z0) as f32 / 2.;
+            boxes.push((
+                Vec3::new(x as f32 + 0.5, y as f32 + 0.5, z0 as f32 + half_height),
+                Vec3::new(0.5, 0.5, half_height),
+            ));
+        };
+        for y in min.y()..max.y() {
+            for x in min.x()..max.x() {
+                let mut run_start = None;
+                for z in min.z()..max.z() {
+                    let solid = self.voxels.get_point(0, PointN([x, y, z])).0 != 0;
+                    match (solid, run_start) {
+                        (true, None) => run_start = Some(z),
+                        (false, Some(z0)) => {
+                            push_run(x, y, z0, z);
+                            run_start = None;
+                        }
+                        _ => {}
+                    }
+                }
+                if let Some(z0) = run_start {
+                    push_run(x, y, z0, max.z());
+                }
+            }
+        }
+        boxes
     }
 
-    pub fn instance_tile(&mut self, tile: &TileAsset, position: Point3i) {
+    /// Stamps the voxels of `tile` at `position`, returns the terrain voxels it replaced for
+    /// `clear_tile` to restore.
+    pub fn instance_tile(
+        &mut self,
+        tile: &TileAsset,
+        position: Point3i,
+    ) -> Vec<(Point3i, MaterialVoxel)> {
         let pallete: Vec<_> = tile
             .inner
             .palette
@@ -406,6 +1002,13 @@ impl Universe {
                 *vox = pallete[vox.0 as usize - 1];
             }
         });
+        let mut ground = vec![];
+        for p in extent.iter_points() {
+            let vox = self.voxels.get_point(0, p);
+            if !vox.is_empty() {
+                ground.push((p, vox));
+            }
+        }
         copy_extent(&extent, &voxels, &mut self.voxels.lod_view_mut(0));
 
         let mut chunks = vec![];
@@ -416,9 +1019,140 @@ impl Universe {
         for chunk_key in chunks {
             self.set_chunk_dirty(chunk_key);
         }
+        ground
+    }
+
+    /// Voxels a tile placed at `position` takes, `footprint` wide and as high as its voxels.
+    pub fn tile_extent(tile: &TileAsset, position: Point3i) -> Extent3i {
+        let [w, h] = tile.inner.footprint();
+        let (w, h) = (w as i32, h as i32);
+        let height = tile.inner.voxels.extent().shape.z();
+        Extent3i::from_min_and_shape(
+            PointN([position.x() - w / 2, position.y() - h / 2, position.z()]),
+            PointN([w, h, height]),
+        )
+    }
+
+    /// Checks that a tile can stand in `extent`: the voxels right below it are solid, the
+    /// extent itself is empty, and no building footprint or unit overlaps it.
+    pub fn validate_footprint(&self, extent: Extent3i) -> Result<(), FootprintError> {
+        let min = extent.minimum;
+        let max = extent.least_upper_bound();
+        for y in min.y()..max.y() {
+            for x in min.x()..max.x() {
+                if self.get_voxel(PointN([x, y, min.z() - 1])).is_empty() {
+                    return Err(FootprintError::Uneven);
+                }
+            }
+        }
+        self.validate_space(extent)
+    }
+
+    /// Like `validate_footprint`, but the ground may be up to `max_depth` voxels lower than
+    /// the footprint. The gaps are filled with the most common material of the ground found.
+    pub fn validate_footprint_with_foundation(
+        &self,
+        extent: Extent3i,
+        max_depth: i32,
+    ) -> Result<Foundation, FootprintError> {
+        let min = extent.minimum;
+        let max = extent.least_upper_bound();
+        let mut fill = vec![];
+        let mut counts: FnvHashMap<u16, usize> = Default::default();
+        for y in min.y()..max.y() {
+            for x in min.x()..max.x() {
+                let ground = (1..=max_depth + 1)
+                    .map(|depth| PointN([x, y, min.z() - depth]))
+                    .find(|p| !self.get_voxel(*p).is_empty())
+                    .ok_or(FootprintError::Uneven)?;
+                *counts.entry(self.get_voxel(ground).0).or_insert(0) += 1;
+                fill.extend((ground.z() + 1..min.z()).map(|z| PointN([x, y, z])));
+            }
+        }
+        self.validate_space(extent)?;
+        let material = counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map_or(MaterialVoxel::empty(), |(material, _)| {
+                MaterialVoxel(material)
+            });
+        Ok(Foundation { fill, material })
+    }
+
+    // the footprint part of `validate_footprint` that doesn't depend on the ground
+    fn validate_space(&self, extent: Extent3i) -> Result<(), FootprintError> {
+        let min = extent.minimum;
+        let max = extent.least_upper_bound();
+        for z in min.z()..max.z() {
+            for y in min.y()..max.y() {
+                for x in min.x()..max.x() {
+                    if !self.get_voxel(PointN([x, y, z])).is_empty() {
+                        return Err(FootprintError::Blocked);
+                    }
+                }
+            }
+        }
+
+        let mut buildings = <(Read<TransformComponent>, Read<TileComponent>)>::query();
+        let overlaps_building = buildings.iter(&self.world).any(|(transform, tile)| {
+            building::footprint(transform, tile).map_or(false, |[x0, y0, x1, y1]| {
+                x0 < max.x() && min.x() < x1 && y0 < max.y() && min.y() < y1
+            })
+        });
+        if overlaps_building {
+            return Err(FootprintError::Building);
+        }
+
+        let mut units = <(Read<TransformComponent>, Read<UnitComponent>)>::query();
+        let overlaps_unit = units.iter(&self.world).any(|(transform, _)| {
+            let p = transform.translation;
+            p.x >= min.x() as f32
+                && p.x < max.x() as f32
+                && p.y >= min.y() as f32
+                && p.y < max.y() as f32
+                && p.z >= (min.z() - 1) as f32
+                && p.z < max.z() as f32
+        });
+        if overlaps_unit {
+            return Err(FootprintError::Unit);
+        }
+        Ok(())
     }
 
-    pub fn save_edited_tile(&self, tile: &str) -> Option<()> {
+    /// Empties the voxels `instance_tile` filled for `tile` at `position`, and puts back the
+    /// `ground` it replaced.
+    pub fn clear_tile(
+        &mut self,
+        tile: &TileAsset,
+        position: Point3i,
+        ground: &[(Point3i, MaterialVoxel)],
+    ) {
+        let voxels = &tile.inner.voxels;
+        let mut center = voxels.extent().shape / 2;
+        *center.z_mut() = 0;
+        let offset = position - center - voxels.extent().minimum;
+        let restored: HashSet<Point3i> = ground.iter().map(|(p, _)| *p).collect();
+        let mut edits = ground.to_vec();
+        voxels.for_each(voxels.extent(), |p: Point3i, vox: MaterialVoxel| {
+            if !vox.is_empty() && !restored.contains(&(p + offset)) {
+                edits.push((p + offset, MaterialVoxel::empty()));
+            }
+        });
+        self.update_voxels(edits);
+    }
+
+    pub fn save_edited_tile(&self, tile: &str, properties: TileProperties) -> Option<()> {
+        let path = TileExporter::get_tile_path(tile, true);
+        self.export_edited_tile(tile, properties, Path::new(&path))
+    }
+
+    /// Writes the tile built on the edit platform to `path`, cropped to its voxels.
+    pub fn export_edited_tile(
+        &self,
+        tile: &str,
+        properties: TileProperties,
+        path: &Path,
+    ) -> Option<()> {
         let full_extent = Extent3i::from_min_and_shape(
             PointN([
                 -TILE_EDIT_PLATFORM_SIZE / 2,
@@ -455,16 +1189,151 @@ impl Universe {
         let mut export_voxels = Array3x1::<MaterialVoxel>::fill(extent, MaterialVoxel::empty());
         copy_extent(&extent, &self.voxels.lod_view(0), &mut export_voxels);
 
-        TileExporter::export(tile.to_string(), export_voxels, self)
+        TileExporter::export(path, tile.to_string(), properties, export_voxels, self)
     }
 
+    /// Regenerates the terrain on the calling thread and clears the world, for small terrains.
     pub fn reset(&mut self, origin: Point3i, size: u32, style: TerrainFillStyle) {
         log::info!("Resetting universe...");
+        self.cancel_terrain_gen();
+        let base = self.terrain_base_for(origin, size, &style);
+        let voxels = TerrainGenerator::new(&self.materials_map, origin, size, style).generate();
+        self.finish_reset(voxels, true, Some(base));
+        log::info!("Universe reset");
+    }
+
+    /// Starts regenerating the terrain on the task pool. The current terrain and world stay
+    /// until `update_terrain_gen` installs the result.
+    pub fn start_reset(&mut self, origin: Point3i, size: u32, style: TerrainFillStyle) {
+        self.cancel_terrain_gen();
+        let base = self.terrain_base_for(origin, size, &style);
+        let generator = TerrainGenerator::new(&self.materials_map, origin, size, style);
+        let job = TerrainGenJob::start(&self.task_pool, generator);
+        self.terrain_gen = Some((job, true, base));
+    }
+
+    fn terrain_base_for(
+        &self,
+        origin: Point3i,
+        size: u32,
+        style: &TerrainFillStyle,
+    ) -> TerrainBase {
+        TerrainBase {
+            origin: origin.0,
+            size,
+            style: style.clone(),
+            mesh_style: self.mesh_style,
+            wrap: self.wrap,
+        }
+    }
+
+    /// What the terrain was generated from, `None` while the first generation runs and for
+    /// terrain loaded from a save without one.
+    pub fn terrain_base(&self) -> Option<&TerrainBase> {
+        self.base.as_ref()
+    }
+
+    /// Changes whenever the whole terrain is replaced, by a reset or a load. The sectors
+    /// `take_edited_sectors` returns are edits made since then.
+    pub fn terrain_revision(&self) -> u64 {
+        self.terrain_revision
+    }
+
+    /// Replaces the terrain with voxels read from a save and clears the world, `edited` are
+    /// the sectors that differ from `base`.
+    pub fn load_terrain(
+        &mut self,
+        voxels: MaterialVoxels,
+        base: Option<TerrainBase>,
+        edited: impl IntoIterator<Item = Point3i>,
+    ) {
+        self.cancel_terrain_gen();
+        if let Some(base) = &base {
+            self.mesh_style = base.mesh_style;
+            self.wrap = base.wrap;
+        }
+        self.finish_reset(voxels, true, base);
+        self.edited_sectors.extend(edited);
+    }
+
+    /// Progress of the running terrain generation, if any.
+    pub fn terrain_gen_progress(&self) -> Option<f32> {
+        self.terrain_gen.as_ref().map(|(job, _, _)| job.progress())
+    }
+
+    /// Stops the running terrain generation, keeping the current terrain.
+    pub fn cancel_terrain_gen(&mut self) {
+        if let Some((job, _, _)) = self.terrain_gen.take() {
+            job.cancel();
+            log::info!("Terrain generation cancelled");
+        }
+    }
+
+    /// Collects the finished columns of the terrain generation and installs the new terrain
+    /// once it is complete.
+    pub fn update_terrain_gen(&mut self) {
+        let voxels = match &mut self.terrain_gen {
+            Some((job, _, _)) => job.poll(),
+            None => return,
+        };
+        if let Some(voxels) = voxels {
+            let (_, clear_world, base) = self.terrain_gen.take().unwrap();
+            self.finish_reset(voxels, clear_world, Some(base));
+        }
+    }
+
+    fn finish_reset(
+        &mut self,
+        voxels: MaterialVoxels,
+        clear_world: bool,
+        base: Option<TerrainBase>,
+    ) {
+        if clear_world {
+            self.clear_world();
+        }
+        self.voxels = voxels;
+        self.removed_voxels.clear();
+        self.undo.clear();
+        self.navmesh.clear();
+        self.reset_chunks();
+        // edits are counted from the new terrain
+        self.edited_sectors.clear();
+        self.base = base;
+        self.terrain_revision += 1;
+    }
+
+    /// Frees what the universe holds on the GPU: the chunk, skirt and overlay meshes go back to
+    /// `dyn_mesh_manager` and the world is cleared with its render and visibility objects. The
+    /// voxels stay, their chunks are meshed again if the universe is updated after this.
+    pub fn destroy(&mut self, dyn_mesh_manager: &mut DynMeshManager) {
+        self.cancel_terrain_gen();
+        let chunks = self
+            .chunks
+            .values()
+            .chain(&self.skirt)
+            .chain(self.overlays.values().map(|overlay| &overlay.chunk));
+        for handle in chunks.filter_map(|chunk| chunk.mesh.as_ref()) {
+            dyn_mesh_manager.release(handle);
+        }
+        for overlay in self.overlays.values() {
+            if let Some((handle, _, _)) = &overlay.pending {
+                dyn_mesh_manager.release(handle);
+            }
+        }
+        // meshes still in flight are dropped when their results find no request
+        self.mesh_add_requests.clear();
+        for _ in self.mesher_rx.try_iter() {}
+        self.reset_chunks();
+        self.clear_world();
+        dyn_mesh_manager.collect_released();
+    }
 
+    fn clear_world(&mut self) {
         self.visibility_region = VisibilityRegion::new();
         self.main_view_frustum = self.visibility_region.register_view_frustum();
 
         self.world = Default::default();
+        self.ids.clear();
         if self.main_light.is_some() {
             let light_from = Vec3::new(0.0, 5.0, 4.0);
             let light_to = Vec3::ZERO;
@@ -477,82 +1346,124 @@ impl Universe {
             };
             self.main_light = Some(self.world.push((light_comp,)));
         }
-
-        self.voxels = Self::generate_voxels(&self.materials_map, origin, size, style);
-        self.reset_chunks();
-
-        log::info!("Universe reset");
     }
 
-    fn reset_chunks(&mut self) {
-        self.active_meshers = 0;
-        self.sectors.clear();
-        for chunk in self.chunks.values_mut() {
-            chunk.clear(&mut self.world);
-        }
-        self.chunks.clear();
-        let full_extent = self.voxels.bounding_extent(0);
-        let mut occupied = vec![];
-        self.voxels.visit_occupied_chunks(0, &full_extent, |chunk| {
-            occupied.push(chunk.extent().minimum);
-        });
-        for chunk_min in occupied {
-            self.set_chunk_dirty(ChunkKey3::new(0, chunk_min));
+    /// Gives `entity` a `StableId` component and registers it for lookups.
+    pub fn register_entity(&mut self, entity: Entity) -> StableId {
+        let id = self.ids.register(entity);
+        if let Some(mut entry) = self.world.entry(entity) {
+            entry.add_component(id);
         }
+        id
     }
 
-    fn generate_voxels(
-        materials: &HashMap<String, u16>,
-        origin: Point3i,
-        size: u32,
-        style: TerrainFillStyle,
-    ) -> MaterialVoxels {
-        let chunk_shape = Point3i::fill(16);
-        let ambient_value = MaterialVoxel::default();
-        let builder = ChunkMapBuilder3x1::new(chunk_shape, ambient_value);
-        let mut voxels = builder.build_with_hash_map_storage();
-        let mut lod0 = voxels.lod_view_mut(0);
-        let size = size as i32;
-        let base_min = PointN([origin.x() - size / 2, origin.y() - size / 2, origin.z() - 1]);
-        let base_extent = Extent3i::from_min_and_shape(base_min, PointN([size, size, 1]));
-        match style {
-            TerrainFillStyle::FlatBoard { material } => {
-                let voxel = MaterialVoxel(materials[&material] + 1);
-                lod0.fill_extent(&base_extent, voxel);
-            }
-            TerrainFillStyle::CheckersBoard { zero, one } => {
-                let zero_voxel = MaterialVoxel(materials[&zero] + 1);
-                let one_voxel = MaterialVoxel(materials[&one] + 1);
-                for p in base_extent.iter_points() {
-                    let px = p.x() % 2;
-                    let py = p.y() % 2;
-                    lod0.fill_extent(
-                        &Extent3i::from_min_and_shape(p, Point3i::ONES),
-                        if (px + py) % 2 == 0 {
-                            zero_voxel
-                        } else {
-                            one_voxel
-                        },
-                    );
-                }
+    /// Removes the entity and frees its `StableId`, and the ids of the units garrisoned in it.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if let Some(entry) = self.world.entry(entity) {
+            if let Ok(id) = entry.get_component::<StableId>() {
+                self.ids.unregister(*id);
             }
-            TerrainFillStyle::PerlinNoise { params, material } => {
-                let voxel = MaterialVoxel(materials[&material] + 1);
-                for p in base_extent.iter_points() {
-                    let noise = params.get_noise(p.x() as f64, p.y() as f64) as i32;
-                    let top = PointN([p.x(), p.y(), noise - 8]);
-                    lod0.fill_extent(&Extent3i::from_min_and_shape(top, PointN([1, 1, 8])), voxel);
+            if let Ok(garrison) = entry.get_component::<GarrisonComponent>() {
+                for passenger in &garrison.passengers {
+                    self.ids.unregister(passenger.id);
                 }
             }
-        };
-        voxels
+        }
+        self.world.remove(entity)
     }
 
-    fn get_sector_key(chunk: &ChunkKey3) -> Point3i {
-        let c = chunk.minimum;
-        let p = c / SECTOR_SIZE;
-        SECTOR_SIZE
-            * PointN([
+    /// Removes the entity but keeps its `StableId` reserved, so orders and references to it
+    /// resolve again once `restore` hands the id to its replacement.
+    pub fn stow(&mut self, entity: Entity) -> Option<StableId> {
+        let id = *self.world.entry(entity)?.get_component::<StableId>().ok()?;
+        self.ids.detach(id);
+        self.world.remove(entity);
+        Some(id)
+    }
+
+    /// Gives the `StableId` of a stowed entity to `entity`.
+    pub fn restore(&mut self, id: StableId, entity: Entity) -> bool {
+        if !self.ids.rebind(id, entity) {
+            return false;
+        }
+        if let Some(mut entry) = self.world.entry(entity) {
+            entry.add_component(id);
+        }
+        true
+    }
+
+    /// Positions `child` relative to `parent` from now on, see `ParentComponent`.
+    pub fn attach(&mut self, child: Entity, parent: Entity, local: TransformComponent) -> bool {
+        if child == parent {
+            return false;
+        }
+        if let Some(mut entry) = self.world.entry(child) {
+            entry.add_component(ParentComponent { parent, local });
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Stops following the parent, the child keeps its last world transform.
+    pub fn detach(&mut self, child: Entity) {
+        if let Some(mut entry) = self.world.entry(child) {
+            entry.remove_component::<ParentComponent>();
+        }
+    }
+
+    /// Runs the systems registered in `UniverseScheduleResource` on the world, in stage order.
+    #[profiling::function]
+    pub fn run_systems(&mut self, resources: &mut Resources) {
+        {
+            let systems = resources.get::<UniverseScheduleResource>().unwrap();
+            if self.schedule.as_ref().map(|(version, _)| *version) != Some(systems.version()) {
+                self.schedule = Some((systems.version(), systems.build()));
+            }
+        }
+        if let Some((_, schedule)) = &mut self.schedule {
+            schedule.execute(&mut self.world, resources);
+        }
+    }
+
+    /// Updates the world transforms of attached entities and despawns the ones whose parent
+    /// is gone, together with their own children.
+    pub fn update_transform_hierarchy(&mut self) {
+        let orphans = hierarchy::update_world_transforms(&mut self.world);
+        for orphan in orphans {
+            log::debug!("Despawning orphaned child {:?}", orphan);
+            self.despawn(orphan);
+        }
+    }
+
+    fn reset_chunks(&mut self) {
+        self.active_meshers = 0;
+        self.sectors.clear();
+        self.sector_visibility.clear();
+        self.occupancy.clear();
+        for chunk in self.chunks.values_mut() {
+            chunk.clear(&mut self.world, &mut self.ids);
+        }
+        self.chunks.clear();
+        let full_extent = self.voxels.bounding_extent(0);
+        self.terrain_bounds = full_extent;
+        self.clear_skirt();
+        self.clear_overlays();
+        self.column_tops.clear();
+        let mut occupied = vec![];
+        self.voxels.visit_occupied_chunks(0, &full_extent, |chunk| {
+            occupied.push(chunk.extent().minimum);
+        });
+        for chunk_min in occupied {
+            self.set_chunk_dirty(ChunkKey3::new(0, chunk_min));
+        }
+    }
+
+    fn get_sector_key(chunk: &ChunkKey3) -> Point3i {
+        let c = chunk.minimum;
+        let p = c / SECTOR_SIZE;
+        SECTOR_SIZE
+            * PointN([
                 if c.x() < 0 { p.x() - 1 } else { p.x() },
                 if c.y() < 0 { p.y() - 1 } else { p.y() },
                 if c.z() < 0 { p.z() - 1 } else { p.z() },
@@ -560,33 +1471,329 @@ impl Universe {
     }
 
     fn set_chunk_dirty(&mut self, key: ChunkKey3) {
+        let sector_key = Self::get_sector_key(&key);
+        self.edited_sectors.insert(sector_key);
+        self.occupancy.mark(key.minimum);
         self.sectors
-            .entry(Self::get_sector_key(&key))
+            .entry(sector_key)
             .or_insert(HashSet::new())
             .insert(key);
         let chunk = self.chunks.entry(key).or_insert(Chunk::new());
         chunk.dirty = true;
+        chunk.version = CHUNK_VERSION.fetch_add(1, Ordering::Relaxed) + 1;
+        self.navmesh.invalidate(key.minimum);
+        let chunk_extent = self.voxels.indexer.extent_for_chunk_with_min(key.minimum);
+        if !self.terrain_bounds.contains(chunk_extent.minimum)
+            || !self
+                .terrain_bounds
+                .contains(chunk_extent.least_upper_bound() - Point3i::ONES)
+        {
+            self.terrain_bounds = self.voxels.bounding_extent(0);
+            self.skirt_dirty = true;
+        }
+    }
+
+    pub fn terrain_bounds(&self) -> &Extent3i {
+        &self.terrain_bounds
+    }
+
+    /// Depth of the cliff walls below the terrain edge, see `WorldEdgeResource`.
+    pub fn set_skirt_depth(&mut self, depth: u32) {
+        if depth != self.skirt_depth {
+            self.skirt_depth = depth;
+            self.skirt_dirty = true;
+        }
+    }
+
+    fn clear_skirt(&mut self) {
+        for wall in self.skirt.iter_mut() {
+            wall.clear(&mut self.world, &mut self.ids);
+        }
+        self.skirt.clear();
+        self.skirt_requests.clear();
+        self.skirt_dirty = true;
+    }
+
+    /// Remeshes the skirt walls of a dirty skirt, once the materials are loaded.
+    fn update_skirt(&mut self, resources: &Resources) {
+        if !self.skirt_dirty {
+            return;
+        }
+        let materials = match self.get_loaded_materials(resources) {
+            Some(materials) => materials,
+            None => return,
+        };
+        self.clear_skirt();
+        self.skirt_dirty = false;
+        if self.skirt_depth == 0 || self.wrap || self.terrain_bounds.num_points() == 0 {
+            return;
+        }
+        for (idx, side) in SKIRT_SIDES.iter().enumerate() {
+            self.skirt.push(Chunk::new());
+            let extent = world_edge::skirt_extent(&self.terrain_bounds, *side, self.skirt_depth);
+            let padded = world_edge::skirt_voxels(&self.voxels, &extent);
+            let quads = world_edge::skirt_quads(&padded, *side);
+            if quads.num_quads() == 0 {
+                continue;
+            }
+            let mesh = Self::make_chunk_geometry(&padded, &quads, false, self.compact_vertices)
+                .and_then(|mut geometry| {
+                    geometry.visible_bounds = Self::make_visible_bounds(&extent, 0);
+                    geometry.into_dyn_mesh_data(&materials, false)
+                });
+            if let Some(mut mesh) = mesh {
+                let pos = extent.minimum;
+                let position = Vec3::new(pos.x() as f32, pos.y() as f32, pos.z() as f32);
+                mesh.world_position = Some(position);
+                self.current_mesh_add_request += 1;
+                let request_handle = self.current_mesh_add_request;
+                self.skirt_requests
+                    .insert(request_handle, (idx, position, mesh.visible_bounds.clone()));
+                let _res = self.mesh_cmd_tx.send(DynMeshCommand::Add {
+                    request_handle,
+                    data: mesh,
+                });
+            }
+        }
+    }
+
+    /// Sets the overlay mesh `key` of `owner` (eg. territory borders), `None` removes it. The
+    /// geometry is in world space, its visible bounds relative to `position`, and its parts
+    /// index the terrain materials. Replaced meshes stay until the new one is uploaded. Returns
+    /// false if the materials are still loading, call again later.
+    pub fn set_overlay_mesh(
+        &mut self,
+        owner: &'static str,
+        key: Point3i,
+        position: Vec3,
+        geometry: Option<ChunkGeometry>,
+        resources: &Resources,
+    ) -> bool {
+        let geometry = match geometry {
+            Some(geometry) => geometry,
+            None => {
+                if let Some(mut overlay) = self.overlays.remove(&(owner, key)) {
+                    overlay.chunk.clear(&mut self.world, &mut self.ids);
+                }
+                return true;
+            }
+        };
+        let materials = match self.get_loaded_materials(resources) {
+            Some(materials) => materials,
+            None => return false,
+        };
+        let visible_bounds = geometry.visible_bounds.clone();
+        let mut mesh = match geometry.into_dyn_mesh_data(&materials, true) {
+            Some(mesh) => mesh,
+            None => return true,
+        };
+        mesh.world_position = Some(position);
+        let mut dyn_mesh_manager = resources.get_mut::<DynMeshManager>().unwrap();
+        match dyn_mesh_manager.add_dyn_mesh(mesh) {
+            Ok(handle) => {
+                let overlay = self
+                    .overlays
+                    .entry((owner, key))
+                    .or_insert_with(|| Overlay {
+                        chunk: Chunk::new(),
+                        pending: None,
+                        offset: Vec3::ZERO,
+                    });
+                overlay.pending = Some((handle, position, visible_bounds));
+            }
+            Err(err) => log::error!("Overlay {} {:?}: {}", owner, key, err),
+        }
+        true
+    }
+
+    pub fn has_overlay_mesh(&self, owner: &'static str, key: Point3i) -> bool {
+        self.overlays.contains_key(&(owner, key))
+    }
+
+    /// Whether a replacement mesh set for the overlay is still uploading.
+    pub fn is_overlay_pending(&self, owner: &'static str, key: Point3i) -> bool {
+        self.overlays
+            .get(&(owner, key))
+            .map_or(false, |overlay| overlay.pending.is_some())
+    }
+
+    /// Draws the overlay mesh `key` of `owner` `offset` away from where it was built, for
+    /// overlays following the camera (eg. the weather particles) without rebuilding them.
+    pub fn set_overlay_offset(&mut self, owner: &'static str, key: Point3i, offset: Vec3) {
+        if let Some(overlay) = self.overlays.get_mut(&(owner, key)) {
+            overlay.offset = offset;
+            overlay.chunk.set_offset(&mut self.world, offset);
+        }
+    }
+
+    /// Swaps in the overlay meshes whose upload finished.
+    fn update_overlays(&mut self, resources: &Resources) {
+        let dyn_mesh_manager = resources.get::<DynMeshManager>().unwrap();
+        let mut dyn_mesh_render_objects = resources.get_mut::<DynMeshRenderObjectSet>().unwrap();
+        for overlay in self.overlays.values_mut() {
+            let finished = match &overlay.pending {
+                Some((handle, _, _)) => dyn_mesh_manager.is_upload_finished(handle),
+                None => false,
+            };
+            if finished {
+                let (handle, position, visible_bounds) = overlay.pending.take().unwrap();
+                overlay.chunk.clear(&mut self.world, &mut self.ids);
+                overlay.chunk.attach(
+                    handle,
+                    position,
+                    visible_bounds,
+                    &mut self.world,
+                    &mut self.ids,
+                    &self.visibility_region,
+                    &mut dyn_mesh_render_objects,
+                );
+                overlay.chunk.set_offset(&mut self.world, overlay.offset);
+            }
+        }
+    }
+
+    fn clear_overlays(&mut self) {
+        for overlay in self.overlays.values_mut() {
+            overlay.chunk.clear(&mut self.world, &mut self.ids);
+        }
+        self.overlays.clear();
+    }
+
+    /// Height of the topmost solid voxel of each `(x, y)` column, `None` for empty columns.
+    /// The tops are cached per chunk and found again when the chunk is edited, so only the
+    /// columns of edited chunks are scanned voxel by voxel.
+    pub fn column_tops(&mut self, columns: &[(i32, i32)]) -> Vec<Option<i32>> {
+        let extent = self.voxels.bounding_extent(0);
+        let shape = self
+            .voxels
+            .indexer
+            .extent_for_chunk_with_min(Point3i::ZERO)
+            .shape;
+        let z_min = extent.minimum.z().div_euclid(shape.z()) * shape.z();
+        let z_max = (extent.least_upper_bound().z() - 1).div_euclid(shape.z()) * shape.z();
+        columns
+            .iter()
+            .map(|(x, y)| {
+                let p = self.wrap_point(PointN([*x, *y, 0]));
+                let chunk_min = PointN([
+                    p.x().div_euclid(shape.x()) * shape.x(),
+                    p.y().div_euclid(shape.y()) * shape.y(),
+                    0,
+                ]);
+                let index = ((p.y() - chunk_min.y()) * shape.x() + p.x() - chunk_min.x()) as usize;
+                (z_min..=z_max)
+                    .rev()
+                    .step_by(shape.z() as usize)
+                    .find_map(|z| {
+                        let mut chunk_min = chunk_min;
+                        *chunk_min.z_mut() = z;
+                        self.chunk_column_tops(chunk_min)[index]
+                    })
+            })
+            .collect()
+    }
+
+    fn chunk_column_tops(&mut self, chunk_min: Point3i) -> &[Option<i32>] {
+        // chunks without an entry were never edited, they stay at version 0
+        let version = self
+            .chunks
+            .get(&ChunkKey3::new(0, chunk_min))
+            .map_or(0, |chunk| chunk.version);
+        let stale = self
+            .column_tops
+            .get(&chunk_min)
+            .map_or(true, |(found_at, _)| *found_at != version);
+        if stale {
+            let extent = self.voxels.indexer.extent_for_chunk_with_min(chunk_min);
+            let (min, max) = (extent.minimum, extent.least_upper_bound());
+            let mut tops = Vec::with_capacity((extent.shape.x() * extent.shape.y()) as usize);
+            for y in min.y()..max.y() {
+                for x in min.x()..max.x() {
+                    tops.push(
+                        (min.z()..max.z())
+                            .rev()
+                            .find(|z| !self.voxels.get_point(0, PointN([x, y, *z])).is_empty()),
+                    );
+                }
+            }
+            self.column_tops.insert(chunk_min, (version, tops));
+        }
+        &self.column_tops[&chunk_min].1
+    }
+
+    /// Sectors whose voxels changed since the last call.
+    pub fn take_edited_sectors(&mut self) -> HashSet<Point3i> {
+        std::mem::take(&mut self.edited_sectors)
+    }
+
+    /// Sectors containing allocated voxel chunks, including fully buried ones without a mesh.
+    pub fn occupied_sectors(&self) -> Vec<Point3i> {
+        let full_extent = self.voxels.bounding_extent(0);
+        let mut sectors = HashSet::new();
+        self.voxels.visit_occupied_chunks(0, &full_extent, |chunk| {
+            sectors.insert(Self::get_sector_key(&ChunkKey3::new(
+                0,
+                chunk.extent().minimum,
+            )));
+        });
+        sectors.into_iter().collect()
+    }
+
+    /// The voxels of a sector, `key` is its minimum.
+    pub fn sector_extent(key: Point3i) -> Extent3i {
+        Extent3i::from_min_and_shape(key, Point3i::fill(SECTOR_SIZE))
+    }
+
+    /// Copies the voxel chunks of a sector as they are, converting and serializing them is left
+    /// to `SectorSnapshot::into_save` off the main thread.
+    pub fn snapshot_sector(&self, key: Point3i) -> SectorSnapshot {
+        let mut chunks = vec![];
+        self.voxels
+            .visit_occupied_chunks(0, &Self::sector_extent(key), |chunk| {
+                chunks.push(chunk.clone());
+            });
+        SectorSnapshot { key, chunks }
     }
 
     #[profiling::function]
     pub fn update_chunks(&mut self, resources: &Resources) {
-        self.start_mesh_jobs(resources);
+        self.check_material_reload(resources);
+        let meshing = resources
+            .get::<PerfExperimentsResource>()
+            .unwrap()
+            .is_enabled(Subsystem::ChunkMeshing);
+        if meshing {
+            self.start_mesh_jobs(resources);
+        }
+        self.update_skirt(resources);
+        self.update_overlays(resources);
         self.process_job_results(resources);
-        self.check_reset_metrics(5.0, true);
+        self.evict_far_chunks(resources);
+        if self.check_reset_metrics(5.0, true).is_some() {
+            let (shared, hits, collisions) =
+                resources.get::<DynMeshManager>().unwrap().dedupe_stats();
+            log::info!(
+                "Dyn meshes shared: {}, uploads avoided: {}, hash collisions: {}",
+                shared,
+                hits,
+                collisions
+            );
+        }
     }
 
+    /// Fills `extract_buffer` with the padded voxels of the dirty chunks closest to the eye,
+    /// returns the number of task pool jobs the copies were split in. The voxel map is only
+    /// read here, so big batches (eg. after a reset) copy in parallel.
     #[profiling::function]
-    fn extract_mesh_voxels(
-        &mut self,
-        resources: &Resources,
-    ) -> Vec<(ChunkKey<[i32; 3]>, Array3x1<MaterialVoxel>)> {
-        let viewports_resource = resources.get::<ViewportsResource>().unwrap();
-        let eye = viewports_resource
-            .main_view_meta
-            .as_ref()
-            .and_then(|view| Some(view.eye_position))
-            .unwrap_or_default();
-        let eye = PointN([eye.x as i32, eye.y as i32, eye.z as i32]);
+    fn extract_mesh_voxels(&mut self, resources: &Resources) -> u32 {
+        let eye = Self::main_eye(resources);
+
+        let simplify = |key: &ChunkKey3| {
+            max(
+                (key.minimum.x() - eye.x()).abs(),
+                (key.minimum.y() - eye.y()).abs(),
+            ) > SIMPLIFIED_MESH_DISTANCE
+        };
 
         let mut changed_keys = vec![];
         let sector_center = Point3i::fill(SECTOR_SIZE / 2);
@@ -600,7 +1807,9 @@ impl Universe {
                         && (chunk_key.minimum.y() - eye.y()).abs() <= MAX_DISTANCE_FROM_CAMERA
                     {
                         let chunk = self.chunks.get(chunk_key).unwrap();
-                        if chunk.builder.is_none() && chunk.dirty {
+                        let lod_changed =
+                            chunk.mesh.is_some() && chunk.simplified != simplify(chunk_key);
+                        if chunk.builder.is_none() && (chunk.dirty || lod_changed) {
                             changed_keys.push(chunk_key.clone());
                         }
                     }
@@ -614,78 +1823,235 @@ impl Universe {
             )
         });
 
-        changed_keys
+        let jobs_per_frame = resources
+            .get::<MeshThrottleResource>()
+            .unwrap()
+            .mesh_jobs_per_frame();
+        let mut keys = std::mem::take(&mut self.extract_keys);
+        keys.clear();
+        keys.extend(
+            changed_keys
+                .iter()
+                .take(if self.initialized {
+                    min(jobs_per_frame, MAX_CHUNK_MESH_JOBS - self.active_meshers)
+                } else {
+                    MAX_CHUNK_MESH_JOBS_INIT
+                })
+                .map(|key| (key.clone(), simplify(key))),
+        );
+
+        let voxels = &self.voxels;
+        let mesh_style = self.mesh_style;
+        let wrap_bounds = self.wrap.then(|| self.terrain_bounds);
+        let extract = |key: &ChunkKey3| {
+            let chunk_extent = voxels.indexer.extent_for_chunk_with_min(key.minimum);
+            let padded_chunk_extent = match mesh_style {
+                TerrainMeshStyle::Cubic => padded_greedy_quads_chunk_extent(&chunk_extent),
+                // one voxel for surface nets and one more for the blur
+                TerrainMeshStyle::Smooth => chunk_extent.padded(2),
+            };
+            let mut padded_chunk = Array3x1::fill(padded_chunk_extent, MaterialVoxel::empty());
+            copy_extent(&padded_chunk_extent, &voxels.lod_view(0), &mut padded_chunk);
+            // padding past a seam comes from the opposite edge, so the chunks there meet
+            if let Some(bounds) = &wrap_bounds {
+                let (min, lub) = (bounds.minimum, bounds.least_upper_bound());
+                let inside = |p: Point3i| {
+                    p.x() >= min.x() && p.x() < lub.x() && p.y() >= min.y() && p.y() < lub.y()
+                };
+                if !inside(padded_chunk_extent.minimum)
+                    || !inside(padded_chunk_extent.least_upper_bound() - Point3i::ONES)
+                {
+                    for p in padded_chunk_extent.iter_points() {
+                        if !inside(p) {
+                            *padded_chunk.get_mut(p) =
+                                voxels.get_point(0, Self::wrap_in_bounds(bounds, p));
+                        }
+                    }
+                }
+            }
+            padded_chunk
+        };
+
+        self.extract_buffer.clear();
+        let batches = if keys.len() < PARALLEL_EXTRACT_MIN_CHUNKS {
+            self.extract_buffer.extend(
+                keys.iter()
+                    .map(|(key, simplified)| (key.clone(), extract(key), *simplified)),
+            );
+            1
+        } else {
+            let extract = &extract;
+            let threads = self.task_pool.thread_num().max(1);
+            let batch_size = max(
+                (keys.len() + threads - 1) / threads,
+                PARALLEL_EXTRACT_MIN_CHUNKS / 2,
+            );
+            let batches = self.task_pool.scope(|scope| {
+                for batch in keys.chunks(batch_size) {
+                    scope.spawn(async move {
+                        batch
+                            .iter()
+                            .map(|(key, simplified)| (key.clone(), extract(key), *simplified))
+                            .collect::<Vec<_>>()
+                    });
+                }
+            });
+            let count = batches.len() as u32;
+            // the scope returns the batches in spawn order, so they stay sorted by distance
+            for batch in batches {
+                self.extract_buffer.extend(batch);
+            }
+            count
+        };
+        self.extract_keys = keys;
+        batches
+    }
+
+    /// Hot reloaded materials (eg. after editing a `.pbrmaterial`) come with new material
+    /// instances, the chunks meshed with the old ones are remeshed. Materials done loading
+    /// only remesh the chunks meshed without them.
+    fn check_material_reload(&mut self, resources: &Resources) {
+        let instances: Vec<_> = self
+            .get_material_slots(resources)
             .iter()
-            .take(if self.initialized {
-                min(
-                    MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME,
-                    MAX_CHUNK_MESH_JOBS - self.active_meshers,
-                )
-            } else {
-                MAX_CHUNK_MESH_JOBS_INIT
+            .map(|slot| {
+                slot.as_ref()
+                    .map(|material| Arc::as_ptr(&material.inner) as usize)
             })
-            .map(|key| {
-                let padded_chunk_extent = padded_greedy_quads_chunk_extent(
-                    &self.voxels.indexer.extent_for_chunk_with_min(key.minimum),
-                );
-                let mut padded_chunk = Array3x1::fill(padded_chunk_extent, MaterialVoxel::empty());
-                copy_extent(
-                    &padded_chunk_extent,
-                    &self.voxels.lod_view(0),
-                    &mut padded_chunk,
-                );
-                (key.clone(), padded_chunk)
-            })
-            .collect()
+            .collect();
+        if instances == self.material_instances {
+            return;
+        }
+        let mut reloaded = false;
+        let mut ready = false;
+        for (old, new) in self.material_instances.iter().zip(&instances) {
+            match (old, new) {
+                (Some(old), Some(new)) if old != new => reloaded = true,
+                (None, Some(_)) => ready = true,
+                _ => {}
+            }
+        }
+        if reloaded {
+            log::info!("Terrain materials reloaded, remeshing chunks");
+            for chunk in self.chunks.values_mut() {
+                chunk.dirty = true;
+            }
+        } else if ready {
+            for chunk in self.chunks.values_mut() {
+                if chunk.pending_materials {
+                    chunk.dirty = true;
+                }
+            }
+        }
+        self.material_instances = instances;
     }
 
     #[profiling::function]
     fn start_mesh_jobs(&mut self, resources: &Resources) {
         if !self.initialized || self.active_meshers < MAX_CHUNK_MESH_JOBS {
             let extract_start = Instant::now();
-            let to_render = self.extract_mesh_voxels(resources);
+            let batches = self.extract_mesh_voxels(resources);
+            let mut to_render = std::mem::take(&mut self.extract_buffer);
 
             if to_render.len() > 0 {
-                let asset_manager = resources.get::<AssetManager>().unwrap();
-                let materials = self.get_loaded_materials(&asset_manager);
-                if let Some(materials) = materials {
+                // meshing waits for the first material, chunks meshed before the others are
+                // ready are patched when they are, see `check_material_reload`
+                let materials = self.get_material_slots(resources);
+                let ready_materials = materials.iter().filter(|m| m.is_some()).count();
+                if ready_materials > 0 {
                     let extract_time = (Instant::now() - extract_start).as_micros() as u32;
                     log::debug!(
-                        "Starting {} greedy mesh jobs (data extraction took {}µs)",
+                        "Starting {} greedy mesh jobs (data extraction took {}µs in {} batches)",
                         to_render.len(),
-                        extract_time
+                        extract_time,
+                        batches
                     );
                     self.metrics.extract.push(ChunkExtractMetrics {
                         tasks: to_render.len() as u32,
                         extract_time,
+                        batches,
                     });
                     self.initialized = true;
 
-                    for (key, padded_chunk) in to_render {
+                    for (key, padded_chunk, simplified) in to_render.drain(..) {
                         let builder_tx = self.mesher_tx.clone();
                         let materials = materials.clone();
                         let padded_extent = padded_chunk.extent().clone();
+                        let mesh_style = self.mesh_style;
+                        let chunk_ao = self.chunk_ao;
+                        let compact_vertices = self.compact_vertices;
                         let task = self.task_pool.spawn(async move {
                             let quads_start = Instant::now();
-                            let mut buffer = GreedyQuadsBuffer::new(
-                                padded_extent,
-                                RIGHT_HANDED_Y_UP_CONFIG.quad_groups(),
-                            );
-                            greedy_quads(&padded_chunk, &padded_extent, &mut buffer);
-                            let quads_duration = Instant::now() - quads_start;
-                            let mesh_start = Instant::now();
-                            let (mesh, failed) = if buffer.num_quads() == 0 {
-                                (None, false)
+                            let padded_chunk = if simplified {
+                                Self::flatten_materials(padded_chunk)
                             } else {
-                                let mesh =
-                                    Self::make_dyn_mesh_data(&padded_chunk, &buffer, &materials);
-                                let failed = mesh.is_none();
-                                (mesh, failed)
+                                padded_chunk
                             };
-                            let mesh_duration = Instant::now() - mesh_start;
+                            let (mesh, pending_materials, failed, quads_duration, mesh_duration) =
+                                match mesh_style {
+                                    TerrainMeshStyle::Cubic => {
+                                        let mut buffer = GreedyQuadsBuffer::new(
+                                            padded_extent,
+                                            RIGHT_HANDED_Y_UP_CONFIG.quad_groups(),
+                                        );
+                                        greedy_quads(&padded_chunk, &padded_extent, &mut buffer);
+                                        let quads_duration = Instant::now() - quads_start;
+                                        let mesh_start = Instant::now();
+                                        let (mesh, pending, failed) = if buffer.num_quads() == 0 {
+                                            (None, false, false)
+                                        } else {
+                                            let mesh = Self::make_chunk_geometry(
+                                                &padded_chunk,
+                                                &buffer,
+                                                chunk_ao,
+                                                compact_vertices,
+                                            )
+                                            .and_then(|geometry| {
+                                                geometry.into_partial_dyn_mesh_data(
+                                                    &materials, simplified,
+                                                )
+                                            });
+                                            match mesh {
+                                                Some((mesh, pending)) => (mesh, pending, false),
+                                                None => (None, false, true),
+                                            }
+                                        };
+                                        let mesh_duration = Instant::now() - mesh_start;
+                                        (mesh, pending, failed, quads_duration, mesh_duration)
+                                    }
+                                    TerrainMeshStyle::Smooth => {
+                                        let sdf = Self::smooth_sdf(&padded_chunk);
+                                        let mut buffer = SurfaceNetsBuffer::default();
+                                        surface_nets(&sdf, sdf.extent(), 1.0, false, &mut buffer);
+                                        let quads_duration = Instant::now() - quads_start;
+                                        let mesh_start = Instant::now();
+                                        let (mesh, pending, failed) =
+                                            if buffer.mesh.indices.is_empty() {
+                                                (None, false, false)
+                                            } else {
+                                                let mesh = Self::make_smooth_chunk_geometry(
+                                                    &padded_chunk,
+                                                    &buffer,
+                                                )
+                                                .and_then(|geometry| {
+                                                    geometry.into_partial_dyn_mesh_data(
+                                                        &materials, simplified,
+                                                    )
+                                                });
+                                                match mesh {
+                                                    Some((mesh, pending)) => (mesh, pending, false),
+                                                    None => (None, false, true),
+                                                }
+                                            };
+                                        let mesh_duration = Instant::now() - mesh_start;
+                                        (mesh, pending, failed, quads_duration, mesh_duration)
+                                    }
+                                };
                             let results = ChunkTaskResults {
                                 key: key.clone(),
                                 mesh,
+                                pending_materials,
+                                ready_materials,
                                 metrics: ChunkTaskMetrics {
                                     quads_time: quads_duration.as_micros() as u32,
                                     mesh_time: mesh_duration.as_micros() as u32,
@@ -697,11 +2063,74 @@ impl Universe {
                         if let Some(chunk) = self.chunks.get_mut(&key) {
                             chunk.builder = Some(task);
                             chunk.dirty = false;
+                            chunk.simplified = simplified;
                             self.active_meshers += 1;
                         }
                     }
                 }
             }
+            self.extract_buffer = to_render;
+        }
+    }
+
+    fn main_eye(resources: &Resources) -> Point3i {
+        let viewports_resource = resources.get::<ViewportsResource>().unwrap();
+        let eye = viewports_resource
+            .main_view_meta
+            .as_ref()
+            .and_then(|view| Some(view.eye_position))
+            .unwrap_or_default();
+        PointN([eye.x as i32, eye.y as i32, eye.z as i32])
+    }
+
+    /// Drops the meshes, entities and render objects of a few chunks far outside the meshing
+    /// range, on frames with no meshing going on and time to spare. The chunks are marked dirty,
+    /// so they get meshed again once the camera comes back.
+    #[profiling::function]
+    fn evict_far_chunks(&mut self, resources: &Resources) {
+        let idle = self.active_meshers == 0
+            && self.mesh_add_requests.is_empty()
+            && resources.get::<MeshThrottleResource>().unwrap().is_idle();
+        if !idle {
+            return;
+        }
+        let eye = Self::main_eye(resources);
+        let limit = MAX_DISTANCE_FROM_CAMERA + CHUNK_EVICTION_MARGIN;
+        let far = |p: Point3i| (p.x() - eye.x()).abs() > limit || (p.y() - eye.y()).abs() > limit;
+        let sector_center = Point3i::fill(SECTOR_SIZE / 2);
+        let mut evicted = vec![];
+        'sectors: for (sector_key, chunk_set) in self.sectors.iter() {
+            // every chunk of a sector this close is in range
+            let center = *sector_key + sector_center;
+            if (center.x() - eye.x()).abs() <= limit - SECTOR_SIZE / 2
+                && (center.y() - eye.y()).abs() <= limit - SECTOR_SIZE / 2
+            {
+                continue;
+            }
+            for key in chunk_set {
+                if !far(key.minimum) {
+                    continue;
+                }
+                let chunk = match self.chunks.get_mut(key) {
+                    Some(chunk) if chunk.entity.is_some() && chunk.builder.is_none() => chunk,
+                    _ => continue,
+                };
+                chunk.clear(&mut self.world, &mut self.ids);
+                chunk.dirty = true;
+                evicted.push(*sector_key);
+                if evicted.len() >= MAX_CHUNK_EVICTIONS_PER_FRAME {
+                    break 'sectors;
+                }
+            }
+        }
+        if evicted.is_empty() {
+            return;
+        }
+        log::debug!("Evicted {} far chunk meshes", evicted.len());
+        for sector_key in evicted {
+            if let Some(sector) = self.sector_visibility.get_mut(&sector_key) {
+                sector.dirty = true;
+            }
         }
     }
 
@@ -714,8 +2143,21 @@ impl Universe {
 
             if let Some(chunk) = self.chunks.get_mut(&result.key) {
                 chunk.builder = None;
+                chunk.pending_materials = result.pending_materials;
+                // materials done loading while the job ran
+                let ready = self
+                    .material_instances
+                    .iter()
+                    .filter(|m| m.is_some())
+                    .count();
+                if result.pending_materials && ready > result.ready_materials {
+                    chunk.dirty = true;
+                }
                 self.active_meshers -= 1;
-                if let Some(mesh) = result.mesh {
+                if let Some(mut mesh) = result.mesh {
+                    let pos = result.key.minimum;
+                    mesh.world_position =
+                        Some(Vec3::new(pos.x() as f32, pos.y() as f32, pos.z() as f32));
                     if let Some(handle) = &chunk.mesh {
                         let _res = self.mesh_cmd_tx.send(DynMeshCommand::Update {
                             request_handle: 0,
@@ -733,7 +2175,7 @@ impl Universe {
                         });
                     }
                 } else {
-                    chunk.clear(&mut self.world);
+                    chunk.clear(&mut self.world, &mut self.ids);
                     cleared_chunks.push(result.key.clone());
                 }
             } else {
@@ -748,62 +2190,38 @@ impl Universe {
                     request_handle,
                     result,
                 } => {
-                    if let Some((key, visible_bounds)) =
+                    let (chunk, position, visible_bounds) = if let Some((idx, position, bounds)) =
+                        self.skirt_requests.remove(&request_handle)
+                    {
+                        (self.skirt.get_mut(idx), position, bounds)
+                    } else if let Some((key, bounds)) =
                         self.mesh_add_requests.remove(&request_handle)
                     {
-                        if let Some(chunk) = self.chunks.get_mut(&key) {
-                            match result {
-                                Ok(handle) => {
-                                    chunk.mesh = Some(handle.clone());
-
-                                    let transform_component = TransformComponent {
-                                        translation: Vec3::ZERO,
-                                        scale: Vec3::ONE,
-                                        rotation: Quat::IDENTITY,
-                                    };
-
-                                    let render_object_handle = dyn_mesh_render_objects
-                                        .register_render_object(DynMeshRenderObject {
-                                            mesh: handle,
-                                        });
-                                    let mesh_component = MeshComponent {
-                                        render_object_handle: render_object_handle.clone(),
-                                    };
-
-                                    let entity =
-                                        self.world.push((transform_component, mesh_component));
-                                    chunk.entity = Some(entity);
-
-                                    let visibility_object_handle = {
-                                        let handle = self.visibility_region.register_static_object(
-                                            ObjectId::from(entity),
-                                            CullModel::VisibleBounds(visible_bounds),
-                                        );
-                                        let pos = key.minimum;
-                                        handle.set_transform(
-                                            Vec3::new(
-                                                pos.x() as f32,
-                                                pos.y() as f32,
-                                                pos.z() as f32,
-                                            ),
-                                            Quat::IDENTITY,
-                                            Vec3::ONE,
-                                        );
-                                        handle.add_render_object(&render_object_handle);
-                                        handle
-                                    };
-                                    let mut entry = self.world.entry(entity).unwrap();
-                                    entry.add_component(VisibilityComponent {
-                                        visibility_object_handle: visibility_object_handle.clone(),
-                                    });
-
-                                    chunk.visibility_object = Some(visibility_object_handle);
-                                    chunk.render_object = Some(render_object_handle);
-                                }
-                                Err(err) => log::error!("{}", err),
-                            }
+                        let pos = key.minimum;
+                        let position = Vec3::new(pos.x() as f32, pos.y() as f32, pos.z() as f32);
+                        if let Some(sector) =
+                            self.sector_visibility.get_mut(&Self::get_sector_key(&key))
+                        {
+                            sector.dirty = true;
                         }
+                        (self.chunks.get_mut(&key), position, bounds)
+                    } else {
+                        continue;
                     };
+                    if let Some(chunk) = chunk {
+                        match result {
+                            Ok(handle) => chunk.attach(
+                                handle,
+                                position,
+                                visible_bounds,
+                                &mut self.world,
+                                &mut self.ids,
+                                &self.visibility_region,
+                                &mut dyn_mesh_render_objects,
+                            ),
+                            Err(err) => log::error!("{}", err),
+                        }
+                    }
                 }
                 DynMeshCommandResults::Update {
                     request_handle: _,
@@ -818,6 +2236,9 @@ impl Universe {
 
         for chunk in cleared_chunks {
             let super_key = Self::get_sector_key(&chunk);
+            if let Some(sector) = self.sector_visibility.get_mut(&super_key) {
+                sector.dirty = true;
+            }
             if let Some(super_chunk) = self.sectors.get_mut(&super_key) {
                 super_chunk.remove(&chunk);
                 if super_chunk.is_empty() {
@@ -849,12 +2270,264 @@ impl Universe {
         }
     }
 
+    /// Replaces all solid voxels with the chunk's most common material, so greedy meshing
+    /// merges coplanar quads across material boundaries. Used for far away chunks.
     #[profiling::function]
-    fn make_dyn_mesh_data(
+    pub fn flatten_materials(mut voxels: Array3x1<MaterialVoxel>) -> Array3x1<MaterialVoxel> {
+        let extent = voxels.extent().clone();
+        let mut counts: FnvHashMap<u16, usize> = Default::default();
+        voxels.for_each_mut(&extent, |_p: Point3i, vox: &mut MaterialVoxel| {
+            if !vox.is_empty() {
+                *counts.entry(vox.0).or_insert(0) += 1;
+            }
+        });
+        if let Some((material, _)) = counts.into_iter().max_by_key(|(_, count)| *count) {
+            voxels.for_each_mut(&extent, |_p: Point3i, vox: &mut MaterialVoxel| {
+                if !vox.is_empty() {
+                    *vox = MaterialVoxel(material);
+                }
+            });
+        }
+        voxels
+    }
+
+    /// Registers one visibility object per sector, with the combined bounds of its chunks,
+    /// instead of one per chunk, which makes the visibility update cheaper on big maps. Only
+    /// the sectors crossing the edges of the `view_proj` frustum are expanded to per chunk
+    /// objects, as only there culling single chunks saves any drawing. Collapsed sectors are
+    /// rebuilt when their chunk meshes change. `None` gives every chunk its own object.
+    #[profiling::function]
+    pub fn update_sector_visibility(&mut self, view_proj: Option<Mat4>) {
+        let view_proj = match view_proj {
+            Some(view_proj) => view_proj,
+            None => {
+                if !self.sector_visibility.is_empty() {
+                    self.sector_visibility.clear();
+                    for chunk in self.chunks.values_mut() {
+                        chunk.register_visibility(&mut self.world, &self.visibility_region);
+                    }
+                }
+                return;
+            }
+        };
+
+        let m = view_proj.transpose();
+        let (x, y, w) = (m.x_axis, m.y_axis, m.w_axis);
+        let planes = [w + x, w - x, w + y, w - y];
+
+        for (sector_key, chunk_keys) in self.sectors.iter() {
+            let (mut low, mut high) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+            for key in chunk_keys {
+                if let Some((min, max)) = self.chunks.get(key).and_then(|c| c.world_aabb()) {
+                    low = low.min(min);
+                    high = high.max(max);
+                }
+            }
+            let empty = low.x > high.x;
+            let expand = empty || Self::crosses_frustum(&planes, low, high);
+            let sector = self.sector_visibility.entry(*sector_key).or_default();
+            if sector.expanded == expand && !sector.dirty && (expand || sector.object.is_some()) {
+                continue;
+            }
+            sector.expanded = expand;
+            sector.dirty = false;
+            sector.object = None;
+            if expand {
+                for key in chunk_keys {
+                    if let Some(chunk) = self.chunks.get_mut(key) {
+                        chunk.register_visibility(&mut self.world, &self.visibility_region);
+                    }
+                }
+                continue;
+            }
+
+            // named after the lowest chunk, its own object is gone while the sector is collapsed
+            let mut first: Option<(Point3i, StableId)> = None;
+            for key in chunk_keys {
+                if let Some(chunk) = self.chunks.get_mut(key) {
+                    chunk.unregister_visibility(&mut self.world);
+                    if let (Some(id), true) = (chunk.id, chunk.render_object.is_some()) {
+                        if first.map_or(true, |(first, _)| key.minimum.0 < first.0) {
+                            first = Some((key.minimum, id));
+                        }
+                    }
+                }
+            }
+            let id = match first {
+                Some((_, id)) => id,
+                None => continue,
+            };
+            let size = high - low;
+            let center = size / 2.;
+            let bounds = VisibleBounds {
+                aabb: AxisAlignedBoundingBox {
+                    min: Vec3::ZERO,
+                    max: size,
+                },
+                obb: Default::default(),
+                bounding_sphere: BoundingSphere::new(center, center.length()),
+                hash: 0,
+            };
+            let object = self
+                .visibility_region
+                .register_static_object(ObjectId::from(id), CullModel::VisibleBounds(bounds));
+            object.set_transform(low, Quat::IDENTITY, Vec3::ONE);
+            for key in chunk_keys {
+                if let Some(render_object) =
+                    self.chunks.get(key).and_then(|c| c.render_object.as_ref())
+                {
+                    object.add_render_object(render_object);
+                }
+            }
+            sector.object = Some(object);
+        }
+        let sectors = &self.sectors;
+        self.sector_visibility
+            .retain(|key, _| sectors.contains_key(key));
+    }
+
+    // true unless the box is fully inside or fully outside one of the side planes
+    /// Mesher and chunk counts, with the chunks in front of the camera counted on the CPU the
+    /// same way the sector culling does it.
+    /// Bytes of the voxel chunks held in memory.
+    pub fn voxel_bytes(&self) -> usize {
+        let mut bytes = 0;
+        let full_extent = self.voxels.bounding_extent(0);
+        self.voxels.visit_occupied_chunks(0, &full_extent, |chunk| {
+            bytes += chunk.extent().num_points() * std::mem::size_of::<MaterialVoxel>();
+        });
+        bytes
+    }
+
+    pub fn stats(&self, view_proj: Mat4) -> UniverseStats {
+        let m = view_proj.transpose();
+        let (x, y, w) = (m.x_axis, m.y_axis, m.w_axis);
+        let planes = [w + x, w - x, w + y, w - y];
+        let mut stats = UniverseStats {
+            mesh_jobs: self.active_meshers,
+            entities: self.world.len(),
+            ..Default::default()
+        };
+        for (low, high) in self.chunks.values().filter_map(|chunk| chunk.world_aabb()) {
+            stats.chunk_meshes += 1;
+            let in_front = planes.iter().all(|plane| {
+                let normal = plane.truncate();
+                let far = Vec3::new(
+                    if normal.x >= 0. { high.x } else { low.x },
+                    if normal.y >= 0. { high.y } else { low.y },
+                    if normal.z >= 0. { high.z } else { low.z },
+                );
+                normal.dot(far) + plane.w >= 0.
+            });
+            if in_front {
+                stats.visible_chunks += 1;
+            }
+        }
+        stats
+    }
+
+    fn crosses_frustum(planes: &[Vec4; 4], low: Vec3, high: Vec3) -> bool {
+        let mut inside = true;
+        for plane in planes {
+            let normal = plane.truncate();
+            let corner = |positive: bool| {
+                let pick = |n: f32, l: f32, h: f32| if (n >= 0.) == positive { h } else { l };
+                Vec3::new(
+                    pick(normal.x, low.x, high.x),
+                    pick(normal.y, low.y, high.y),
+                    pick(normal.z, low.z, high.z),
+                )
+            };
+            let (far, near) = (corner(true), corner(false));
+            if normal.dot(far) + plane.w < 0. {
+                return false;
+            }
+            if normal.dot(near) + plane.w < 0. {
+                inside = false;
+            }
+        }
+        !inside
+    }
+
+    /// Remeshes every chunk when the ambient occlusion setting changes.
+    pub fn set_chunk_ao(&mut self, chunk_ao: bool) {
+        if chunk_ao != self.chunk_ao {
+            self.chunk_ao = chunk_ao;
+            let keys: Vec<_> = self.chunks.keys().copied().collect();
+            for key in keys {
+                self.set_chunk_dirty(key);
+            }
+        }
+    }
+
+    /// Remeshes every chunk and the skirt when the cubic vertex format changes. The compact
+    /// format needs vertex fetch of 16 bit scaled integers, which some backends lack.
+    pub fn set_compact_vertices(&mut self, compact_vertices: bool) {
+        if compact_vertices != self.compact_vertices {
+            self.compact_vertices = compact_vertices;
+            let keys: Vec<_> = self.chunks.keys().copied().collect();
+            for key in keys {
+                self.set_chunk_dirty(key);
+            }
+            self.skirt_dirty = true;
+        }
+    }
+
+    /// Classic voxel ambient occlusion at the `corner` of a quad facing along `normal`: how
+    /// many of the 3 voxels touching the corner in the layer in front of the face are solid,
+    /// with both sides solid counting as fully occluded. `toward_quad` points from the corner
+    /// into the quad. Returns 1 for an open corner down to 0.
+    fn corner_ao(
+        voxels: &Array3x1<MaterialVoxel>,
+        corner: Point3i,
+        normal: Point3i,
+        toward_quad: Point3i,
+    ) -> f32 {
+        let solid = |p: Point3i| voxels.extent().contains(p) && !voxels.get(p).is_empty();
+        // the voxel in direction `sign` along an axis, from a lattice point
+        let step = |c: i32, sign: i32| if sign > 0 { c } else { c - 1 };
+        let voxel = |du: i32, dv: i32, axes: [usize; 2]| {
+            let mut p = corner;
+            for axis in 0..3 {
+                let c = corner.0[axis];
+                p.0[axis] = if normal.0[axis] != 0 {
+                    step(c, normal.0[axis])
+                } else if axis == axes[0] {
+                    step(c, du)
+                } else {
+                    step(c, dv)
+                };
+            }
+            p
+        };
+        // the two axes the face spans
+        let axes = match normal.0.iter().position(|c| *c != 0) {
+            Some(0) => [1, 2],
+            Some(1) => [0, 2],
+            _ => [0, 1],
+        };
+        let (su, sv) = (toward_quad.0[axes[0]], toward_quad.0[axes[1]]);
+        let side1 = solid(voxel(-su, sv, axes));
+        let side2 = solid(voxel(su, -sv, axes));
+        let diagonal = solid(voxel(-su, -sv, axes));
+        let occlusion = if side1 && side2 {
+            3
+        } else {
+            side1 as u32 + side2 as u32 + diagonal as u32
+        };
+        1. - occlusion as f32 / 3.
+    }
+
+    /// Builds the vertex and index buffers of a greedy quads mesh, one part per material. With
+    /// `ao`, ambient occlusion is baked into the vertices. Merged quads only sample it at
+    /// their corners, and are split along the diagonal through their darker corners, so the
+    /// gradients stay symmetric.
+    pub fn make_chunk_geometry(
         voxels: &Array3x1<MaterialVoxel>,
         quads: &GreedyQuadsBuffer,
-        materials: &Vec<PbrMaterialAsset>,
-    ) -> Option<DynMeshData> {
+        ao: bool,
+        compact: bool,
+    ) -> Option<ChunkGeometry> {
         let mut quad_parts: FnvHashMap<_, _> = Default::default();
         for (idx, group) in quads.quad_groups.iter().enumerate() {
             for quad in group.quads.iter() {
@@ -867,11 +2540,22 @@ impl Universe {
             }
         }
 
+        let (vertex_format, origin) = if compact {
+            // the interior minimum, so positions are in [0, chunk size]
+            let min = voxels.extent().minimum + Point3i::ONES;
+            (
+                DynMeshVertexFormat::Compact,
+                Vec3::new(min.x() as f32, min.y() as f32, min.z() as f32),
+            )
+        } else {
+            (DynMeshVertexFormat::Full, Vec3::ZERO)
+        };
+
         let num_quads = quads.num_quads();
         let mut all_vertices_full =
-            PushBuffer::new(num_quads * 4 * std::mem::size_of::<MeshVertexFull>());
+            PushBuffer::new(num_quads * 4 * vertex_format.vertex_full_size());
         let mut all_vertices_position =
-            PushBuffer::new(num_quads * 4 * std::mem::size_of::<MeshVertexPosition>());
+            PushBuffer::new(num_quads * 4 * vertex_format.vertex_position_size());
         let mut all_indices = PushBuffer::new(
             num_quads
                 * 6
@@ -882,133 +2566,338 @@ impl Universe {
                 },
         );
 
-        let mut mesh_parts: Vec<DynMeshDataPart> = Vec::with_capacity(quad_parts.len());
+        let mut parts: Vec<ChunkGeometryPart> = Vec::with_capacity(quad_parts.len());
         for (mat, quads) in quad_parts.iter() {
-            let mesh_part = {
-                let pbr_material = materials.get(*mat as usize);
-                if let Some(pbr_material) = pbr_material {
-                    let mut vertices_num = 0;
-                    let index_type = if quads.num_quads() * 6 >= 0xFFFF {
-                        RafxIndexType::Uint32
+            let mut vertices_num = 0;
+            let index_type = if quads.num_quads() * 6 >= 0xFFFF {
+                RafxIndexType::Uint32
+            } else {
+                RafxIndexType::Uint16
+            };
+            let vertex_full_offset = all_vertices_full.len();
+            let vertex_position_offset = all_vertices_position.len();
+            let indices_offset = all_indices.len();
+            for group in quads.quad_groups.iter() {
+                let face = &group.face;
+                let normal = face.mesh_normal().0;
+                let tangent = {
+                    let face_normal_axis = face.permutation.axes()[0];
+                    let flip_u = if face.n_sign < 0 {
+                        RIGHT_HANDED_Y_UP_CONFIG.u_flip_face != face_normal_axis
                     } else {
-                        RafxIndexType::Uint16
+                        RIGHT_HANDED_Y_UP_CONFIG.u_flip_face == face_normal_axis
                     };
-                    let vertex_full_offset = all_vertices_full.len();
-                    let vertex_position_offset = all_vertices_position.len();
-                    let indices_offset = all_indices.len();
-                    for group in quads.quad_groups.iter() {
-                        let face = &group.face;
-                        let normal = face.mesh_normal().0;
-                        let tangent = {
-                            let face_normal_axis = face.permutation.axes()[0];
-                            let flip_u = if face.n_sign < 0 {
-                                RIGHT_HANDED_Y_UP_CONFIG.u_flip_face != face_normal_axis
-                            } else {
-                                RIGHT_HANDED_Y_UP_CONFIG.u_flip_face == face_normal_axis
-                            };
-                            let flipped_u = if flip_u { -face.u } else { face.u };
-                            [
-                                flipped_u.x() as f32,
-                                flipped_u.y() as f32,
-                                flipped_u.z() as f32,
-                            ]
-                        };
-                        let binormal = {
-                            let normal = Vec3::from(normal);
-                            let tangent = Vec3::from(tangent);
-                            normal.cross(tangent).normalize().into()
+                    let flipped_u = if flip_u { -face.u } else { face.u };
+                    [
+                        flipped_u.x() as f32,
+                        flipped_u.y() as f32,
+                        flipped_u.z() as f32,
+                    ]
+                };
+                let binormal = {
+                    let normal = Vec3::from(normal);
+                    let tangent = Vec3::from(tangent);
+                    normal.cross(tangent).normalize().into()
+                };
+                for quad in group.quads.iter() {
+                    let mut positions: Vec<[f32; 3]> = Vec::new();
+                    positions.extend_from_slice(&face.quad_mesh_positions(quad, 1.0));
+                    let mut uvs: Vec<[f32; 2]> = Vec::new();
+                    uvs.extend_from_slice(&face.tex_coords(
+                        RIGHT_HANDED_Y_UP_CONFIG.u_flip_face,
+                        false,
+                        quad,
+                    ));
+                    let mut indices = face.quad_mesh_indices(vertices_num);
+                    let mut corners_ao = [1.; 4];
+                    if ao {
+                        let face_normal = face.signed_normal();
+                        let center = positions
+                            .iter()
+                            .fold(Vec3::ZERO, |acc, p| acc + Vec3::from(*p))
+                            / 4.;
+                        for (i, position) in positions.iter().enumerate() {
+                            let corner = PointN([
+                                position[0].round() as i32,
+                                position[1].round() as i32,
+                                position[2].round() as i32,
+                            ]);
+                            let toward = center - Vec3::from(*position);
+                            let toward_quad = PointN([
+                                toward.x.signum() as i32,
+                                toward.y.signum() as i32,
+                                toward.z.signum() as i32,
+                            ]);
+                            corners_ao[i] =
+                                Self::corner_ao(voxels, corner, face_normal, toward_quad);
+                        }
+                        // the default split is along the 1-2 diagonal
+                        if corners_ao[1] + corners_ao[2] > corners_ao[0] + corners_ao[3] {
+                            indices = [
+                                indices[0], indices[1], indices[4], indices[0], indices[4],
+                                indices[2],
+                            ];
+                        }
+                    }
+                    let indices_u32 = &indices;
+                    for i in 0..4 {
+                        let vertex = DynMeshVertex {
+                            position: positions[i],
+                            normal,
+                            tangent,
+                            binormal,
+                            tex_coord: uvs[i],
+                            ao: corners_ao[i],
                         };
-                        for quad in group.quads.iter() {
-                            let mut positions: Vec<[f32; 3]> = Vec::new();
-                            positions.extend_from_slice(&face.quad_mesh_positions(quad, 1.0));
-                            let mut uvs: Vec<[f32; 2]> = Vec::new();
-                            uvs.extend_from_slice(&face.tex_coords(
-                                RIGHT_HANDED_Y_UP_CONFIG.u_flip_face,
-                                false,
-                                quad,
-                            ));
-                            let indices_u32 = &face.quad_mesh_indices(vertices_num);
-                            for i in 0..4 {
-                                all_vertices_full.push(
-                                    &[MeshVertexFull {
-                                        position: positions[i],
-                                        normal,
-                                        tangent,
-                                        binormal,
-                                        tex_coord: uvs[i],
-                                    }],
-                                    1,
-                                );
-                                all_vertices_position.push(
-                                    &[MeshVertexPosition {
-                                        position: positions[i],
-                                    }],
-                                    1,
-                                );
-                            }
-                            match index_type {
-                                rafx::api::RafxIndexType::Uint16 => {
-                                    let indices_u16: Vec<u16> = indices_u32
-                                        .iter()
-                                        .map(|&x| std::convert::TryInto::try_into(x).unwrap())
-                                        .collect();
-                                    all_indices.push(&indices_u16, std::mem::size_of::<u16>());
-                                }
-                                rafx::api::RafxIndexType::Uint32 => {
-                                    all_indices.push(indices_u32, std::mem::size_of::<u32>());
-                                }
-                            }
-                            vertices_num += 4;
+                        if compact {
+                            let vertex = DynMeshVertexCompact::pack(&vertex, origin);
+                            all_vertices_full.push(&[vertex], 1);
+                            all_vertices_position
+                                .push(&[DynMeshVertexPositionCompact::from(&vertex)], 1);
+                        } else {
+                            all_vertices_full.push(&[vertex], 1);
+                            all_vertices_position.push(
+                                &[MeshVertexPosition {
+                                    position: positions[i],
+                                }],
+                                1,
+                            );
                         }
                     }
-                    let vertex_full_size = all_vertices_full.len() - vertex_full_offset;
-                    let vertex_position_size = all_vertices_position.len() - vertex_position_offset;
-                    let indices_size = all_indices.len() - indices_offset;
-
-                    if vertex_full_size == 0 || vertex_position_size == 0 || indices_size == 0 {
-                        None
-                    } else {
-                        Some(DynMeshDataPart {
-                            material_instance: pbr_material.get_material_instance(),
-                            vertex_full_buffer_offset_in_bytes: vertex_full_offset as u32,
-                            vertex_full_buffer_size_in_bytes: vertex_full_size as u32,
-                            vertex_position_buffer_offset_in_bytes: vertex_position_offset as u32,
-                            vertex_position_buffer_size_in_bytes: vertex_position_size as u32,
-                            index_buffer_offset_in_bytes: indices_offset as u32,
-                            index_buffer_size_in_bytes: indices_size as u32,
-                            index_type,
-                        })
+                    match index_type {
+                        rafx::api::RafxIndexType::Uint16 => {
+                            let indices_u16: Vec<u16> = indices_u32
+                                .iter()
+                                .map(|&x| std::convert::TryInto::try_into(x).unwrap())
+                                .collect();
+                            all_indices.push(&indices_u16, std::mem::size_of::<u16>());
+                        }
+                        rafx::api::RafxIndexType::Uint32 => {
+                            all_indices.push(indices_u32, std::mem::size_of::<u32>());
+                        }
                     }
-                } else {
-                    log::error!(
-                        "Invalid terrain material index {} (# of materials: {})",
-                        mat,
-                        materials.len()
-                    );
-                    None
+                    vertices_num += 4;
                 }
-            };
-            if let Some(mesh_part) = mesh_part {
-                mesh_parts.push(mesh_part);
-            } else {
+            }
+            let vertex_full_size = all_vertices_full.len() - vertex_full_offset;
+            let vertex_position_size = all_vertices_position.len() - vertex_position_offset;
+            let indices_size = all_indices.len() - indices_offset;
+
+            if vertex_full_size == 0 || vertex_position_size == 0 || indices_size == 0 {
                 return None;
             }
+            parts.push(ChunkGeometryPart {
+                material: *mat,
+                vertex_full_buffer_offset_in_bytes: vertex_full_offset as u32,
+                vertex_full_buffer_size_in_bytes: vertex_full_size as u32,
+                vertex_position_buffer_offset_in_bytes: vertex_position_offset as u32,
+                vertex_position_buffer_size_in_bytes: vertex_position_size as u32,
+                index_buffer_offset_in_bytes: indices_offset as u32,
+                index_buffer_size_in_bytes: indices_size as u32,
+                index_type,
+            });
         }
 
-        if mesh_parts.len() == 0 {
+        if parts.len() == 0 {
             return None;
         }
 
-        Some(DynMeshData {
-            mesh_parts,
-            vertex_full_buffer: Some(all_vertices_full.into_data()),
-            vertex_position_buffer: Some(all_vertices_position.into_data()),
-            index_buffer: Some(all_indices.into_data()),
+        Some(ChunkGeometry {
+            parts,
+            vertex_full_buffer: all_vertices_full.into_data(),
+            vertex_position_buffer: all_vertices_position.into_data(),
+            index_buffer: all_indices.into_data(),
             visible_bounds: Self::make_visible_bounds(&voxels.extent().padded(-1), 0),
+            vertex_format,
+            origin,
+        })
+    }
+
+    /// Signed distance field for surface nets: the occupancy of each voxel's 3x3x3
+    /// neighbourhood, negative inside. The blur is what rounds off the voxel corners.
+    pub fn smooth_sdf(voxels: &Array3x1<MaterialVoxel>) -> Array3x1<f32> {
+        let extent = voxels.extent().padded(-1);
+        Array3x1::fill_with(extent, |p: Point3i| {
+            let mut solid = 0;
+            for z in -1..=1 {
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        if voxels.get(p + PointN([x, y, z])).0 != 0 {
+                            solid += 1;
+                        }
+                    }
+                }
+            }
+            0.5 - solid as f32 / 27.
+        })
+    }
+
+    /// Most common material among the corners of the surface nets cube at `point`.
+    fn surface_material(voxels: &Array3x1<MaterialVoxel>, point: Point3i) -> u16 {
+        let mut corners = Vec::with_capacity(8);
+        for z in 0..=1 {
+            for y in 0..=1 {
+                for x in 0..=1 {
+                    let mat = voxels.get(point + PointN([x, y, z])).0;
+                    if mat != 0 {
+                        corners.push(mat);
+                    }
+                }
+            }
+        }
+        corners
+            .iter()
+            .max_by_key(|mat| corners.iter().filter(|m| m == mat).count())
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Area weighted vertex normals, smoother than the gradient of the blurred field.
+    fn triangle_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::ZERO; positions.len()];
+        for tri in indices.chunks_exact(3) {
+            let a = Vec3::from(positions[tri[0] as usize]);
+            let b = Vec3::from(positions[tri[1] as usize]);
+            let c = Vec3::from(positions[tri[2] as usize]);
+            let normal = (b - a).cross(c - a);
+            for idx in tri {
+                normals[*idx as usize] += normal;
+            }
+        }
+        normals
+            .into_iter()
+            .map(|n| {
+                if n.length_squared() > 0. {
+                    n.normalize()
+                } else {
+                    Vec3::Z
+                }
+            })
+            .collect()
+    }
+
+    fn smooth_vertex(position: [f32; 3], normal: Vec3) -> DynMeshVertex {
+        // planar projection along the dominant normal axis
+        let n = normal.abs();
+        let (tex_coord, u_axis) = if n.z >= n.x && n.z >= n.y {
+            ([position[0], position[1]], Vec3::X)
+        } else if n.x >= n.y {
+            ([position[1], position[2]], Vec3::Y)
+        } else {
+            ([position[0], position[2]], Vec3::X)
+        };
+        let tangent = (u_axis - normal * normal.dot(u_axis)).normalize();
+        let binormal = normal.cross(tangent);
+        DynMeshVertex {
+            position,
+            normal: normal.into(),
+            tangent: tangent.into(),
+            binormal: binormal.into(),
+            tex_coord,
+            ao: 1.,
+        }
+    }
+
+    /// Splits the surface nets mesh into one part per material. Triangles take the material
+    /// most of their vertices agree on, vertices on material boundaries are duplicated, so
+    /// materials meet along triangle edges rather than voxel faces.
+    pub fn make_smooth_chunk_geometry(
+        voxels: &Array3x1<MaterialVoxel>,
+        buffer: &SurfaceNetsBuffer,
+    ) -> Option<ChunkGeometry> {
+        let mesh = &buffer.mesh;
+        let vertex_materials: Vec<u16> = buffer
+            .surface_points
+            .iter()
+            .map(|p| Self::surface_material(voxels, *p))
+            .collect();
+        let normals = Self::triangle_normals(&mesh.positions, &mesh.indices);
+
+        let mut triangle_parts: FnvHashMap<u16, Vec<[u32; 3]>> = Default::default();
+        for tri in mesh.indices.chunks_exact(3) {
+            let mats = [
+                vertex_materials[tri[0] as usize],
+                vertex_materials[tri[1] as usize],
+                vertex_materials[tri[2] as usize],
+            ];
+            let mat = if mats[1] == mats[2] { mats[1] } else { mats[0] };
+            if mat != 0 {
+                triangle_parts
+                    .entry(mat - 1)
+                    .or_insert_with(Vec::new)
+                    .push([tri[0], tri[1], tri[2]]);
+            }
+        }
+
+        let num_indices = mesh.indices.len();
+        let mut all_vertices_full =
+            PushBuffer::new(mesh.positions.len() * std::mem::size_of::<DynMeshVertex>());
+        let mut all_vertices_position =
+            PushBuffer::new(mesh.positions.len() * std::mem::size_of::<MeshVertexPosition>());
+        let mut all_indices = PushBuffer::new(num_indices * std::mem::size_of::<u32>());
+
+        let mut parts: Vec<ChunkGeometryPart> = Vec::with_capacity(triangle_parts.len());
+        for (mat, triangles) in triangle_parts.iter() {
+            let vertex_full_offset = all_vertices_full.len();
+            let vertex_position_offset = all_vertices_position.len();
+            let indices_offset = all_indices.len();
+
+            let mut remap: FnvHashMap<u32, u32> = Default::default();
+            let mut indices = Vec::with_capacity(triangles.len() * 3);
+            for tri in triangles {
+                for vertex in tri {
+                    let next = remap.len() as u32;
+                    let idx = *remap.entry(*vertex).or_insert_with(|| {
+                        let position = mesh.positions[*vertex as usize];
+                        let normal = normals[*vertex as usize];
+                        all_vertices_full.push(&[Self::smooth_vertex(position, normal)], 1);
+                        all_vertices_position.push(&[MeshVertexPosition { position }], 1);
+                        next
+                    });
+                    indices.push(idx);
+                }
+            }
+
+            let index_type = if remap.len() >= 0xFFFF {
+                all_indices.push(&indices, std::mem::size_of::<u32>());
+                RafxIndexType::Uint32
+            } else {
+                let indices_u16: Vec<u16> = indices.iter().map(|&x| x as u16).collect();
+                all_indices.push(&indices_u16, std::mem::size_of::<u16>());
+                RafxIndexType::Uint16
+            };
+
+            parts.push(ChunkGeometryPart {
+                material: *mat,
+                vertex_full_buffer_offset_in_bytes: vertex_full_offset as u32,
+                vertex_full_buffer_size_in_bytes: (all_vertices_full.len() - vertex_full_offset)
+                    as u32,
+                vertex_position_buffer_offset_in_bytes: vertex_position_offset as u32,
+                vertex_position_buffer_size_in_bytes: (all_vertices_position.len()
+                    - vertex_position_offset)
+                    as u32,
+                index_buffer_offset_in_bytes: indices_offset as u32,
+                index_buffer_size_in_bytes: (all_indices.len() - indices_offset) as u32,
+                index_type,
+            });
+        }
+
+        if parts.len() == 0 {
+            return None;
+        }
+
+        Some(ChunkGeometry {
+            parts,
+            vertex_full_buffer: all_vertices_full.into_data(),
+            vertex_position_buffer: all_vertices_position.into_data(),
+            index_buffer: all_indices.into_data(),
+            visible_bounds: Self::make_visible_bounds(&voxels.extent().padded(-2), 0),
+            vertex_format: DynMeshVertexFormat::Full,
+            origin: Vec3::ZERO,
         })
     }
 
-    fn make_visible_bounds(extent: &Extent3i, hash: u64) -> VisibleBounds {
+    pub fn make_visible_bounds(extent: &Extent3i, hash: u64) -> VisibleBounds {
         let max = extent.shape;
         let max = Vec3::new(max.x() as f32, max.y() as f32, max.z() as f32) + Vec3::ONE;
         let sphere_center = max / 2.;
@@ -1029,6 +2918,145 @@ impl Universe {
 pub struct RayCastResult {
     pub hit: Point3i,
     pub before_hit: Point3i,
+    /// Axis aligned normal of the face of `hit` the ray entered through.
+    pub normal: Point3i,
+    /// From the ray start to where it enters `hit`, in meters.
+    pub distance: f32,
+}
+
+impl RayCastResult {
+    /// The empty voxel touching the face that was hit, where building against it goes.
+    pub fn face_neighbor(&self) -> Point3i {
+        self.hit + self.normal
+    }
+}
+
+/// Range of one material's vertices and indices in the `ChunkGeometry` buffers.
+#[derive(Clone)]
+pub struct ChunkGeometryPart {
+    pub material: u16,
+    pub vertex_full_buffer_offset_in_bytes: u32,
+    pub vertex_full_buffer_size_in_bytes: u32,
+    pub vertex_position_buffer_offset_in_bytes: u32,
+    pub vertex_position_buffer_size_in_bytes: u32,
+    pub index_buffer_offset_in_bytes: u32,
+    pub index_buffer_size_in_bytes: u32,
+    pub index_type: RafxIndexType,
+}
+
+/// Chunk mesh as built by the mesher, before its parts are bound to material instances.
+#[derive(Clone)]
+pub struct ChunkGeometry {
+    pub parts: Vec<ChunkGeometryPart>,
+    pub vertex_full_buffer: Vec<u8>,
+    pub vertex_position_buffer: Vec<u8>,
+    pub index_buffer: Vec<u8>,
+    pub visible_bounds: VisibleBounds,
+    pub vertex_format: DynMeshVertexFormat,
+    pub origin: Vec3,
+}
+
+impl ChunkGeometry {
+    pub fn num_vertices(&self) -> usize {
+        self.vertex_position_buffer.len() / self.vertex_format.vertex_position_size()
+    }
+
+    /// The content hash so far, of everything but the materials of the parts.
+    pub fn buffer_hasher(&self, untextured: bool) -> DefaultHasher {
+        let mut hasher = DefaultHasher::new();
+        self.vertex_full_buffer.hash(&mut hasher);
+        self.vertex_position_buffer.hash(&mut hasher);
+        self.index_buffer.hash(&mut hasher);
+        untextured.hash(&mut hasher);
+        // compact positions are relative to the origin, which the shared mesh carries
+        self.vertex_format.hash(&mut hasher);
+        for x in [self.origin.x, self.origin.y, self.origin.z].iter() {
+            x.to_bits().hash(&mut hasher);
+        }
+        hasher
+    }
+
+    /// Looks up the material instance of every part. Fails on material indices outside
+    /// `materials`. Buffers are chunk local, so chunks with the same voxels (eg. flat terrain)
+    /// get the same content hash and share one GPU mesh.
+    pub fn into_dyn_mesh_data(
+        self,
+        materials: &[PbrMaterialAsset],
+        untextured: bool,
+    ) -> Option<DynMeshData> {
+        let materials: Vec<_> = materials.iter().cloned().map(Some).collect();
+        self.into_partial_dyn_mesh_data(&materials, untextured)
+            .and_then(|(mesh, _)| mesh)
+    }
+
+    /// Like `into_dyn_mesh_data`, but the parts of materials still loading (`None`) are left
+    /// out, which the returned flag tells. The mesh is `None` when no part is left.
+    pub fn into_partial_dyn_mesh_data(
+        self,
+        materials: &[Option<PbrMaterialAsset>],
+        untextured: bool,
+    ) -> Option<(Option<DynMeshData>, bool)> {
+        let mut hasher = self.buffer_hasher(untextured);
+
+        let mut mesh_parts = Vec::with_capacity(self.parts.len());
+        let mut pending = false;
+        for part in self.parts {
+            let pbr_material = match materials.get(part.material as usize) {
+                Some(Some(pbr_material)) => pbr_material,
+                Some(None) => {
+                    pending = true;
+                    continue;
+                }
+                None => {
+                    log::error!(
+                        "Invalid terrain material index {} (# of materials: {})",
+                        part.material,
+                        materials.len()
+                    );
+                    return None;
+                }
+            };
+            // material instances are compared by identity
+            Arc::as_ptr(&pbr_material.inner).hash(&mut hasher);
+            part.vertex_full_buffer_offset_in_bytes.hash(&mut hasher);
+            part.vertex_position_buffer_offset_in_bytes
+                .hash(&mut hasher);
+            part.index_buffer_offset_in_bytes.hash(&mut hasher);
+            part.index_buffer_size_in_bytes.hash(&mut hasher);
+            matches!(part.index_type, RafxIndexType::Uint32).hash(&mut hasher);
+            mesh_parts.push(DynMeshDataPart {
+                material_instance: pbr_material.get_material_instance(),
+                vertex_full_buffer_offset_in_bytes: part.vertex_full_buffer_offset_in_bytes,
+                vertex_full_buffer_size_in_bytes: part.vertex_full_buffer_size_in_bytes,
+                vertex_position_buffer_offset_in_bytes: part.vertex_position_buffer_offset_in_bytes,
+                vertex_position_buffer_size_in_bytes: part.vertex_position_buffer_size_in_bytes,
+                index_buffer_offset_in_bytes: part.index_buffer_offset_in_bytes,
+                index_buffer_size_in_bytes: part.index_buffer_size_in_bytes,
+                index_type: part.index_type,
+                untextured,
+            });
+        }
+
+        if mesh_parts.is_empty() {
+            return Some((None, pending));
+        }
+        let content_hash = hasher.finish();
+        let mut visible_bounds = self.visible_bounds;
+        visible_bounds.hash = content_hash;
+
+        let mesh = DynMeshData {
+            mesh_parts,
+            vertex_full_buffer: Some(self.vertex_full_buffer),
+            vertex_position_buffer: Some(self.vertex_position_buffer),
+            index_buffer: Some(self.index_buffer),
+            visible_bounds,
+            content_hash: Some(content_hash),
+            world_position: None,
+            vertex_format: self.vertex_format,
+            origin: self.origin,
+        };
+        Some((Some(mesh), pending))
+    }
 }
 
 struct PerMaterialGreedyQuadsBuffer {
@@ -1059,7 +3087,7 @@ impl PerMaterialGreedyQuadsBuffer {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct UniverseId(usize);
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum TerrainFillStyle {
     FlatBoard {
         material: String,
@@ -1068,12 +3096,85 @@ pub enum TerrainFillStyle {
         zero: String,
         one: String,
     },
-    PerlinNoise {
-        params: PerlinNoise2D,
+    /// With `biomes` enabled, its palettes replace `material`.
+    Noise {
+        params: NoiseParams,
+        material: String,
+        biomes: BiomeParams,
+    },
+    /// Grayscale image resampled to the terrain size. When `bands` is not empty, the altitude
+    /// range is split evenly between its materials (lowest first) instead of using `material`.
+    Heightmap {
+        path: String,
+        vertical_scale: f32,
         material: String,
+        bands: Vec<String>,
     },
 }
 
+/// How chunk voxels are turned into meshes.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TerrainMeshStyle {
+    /// Greedy quads, one flat face per voxel side. Used for tiles and buildings.
+    Cubic,
+    /// Surface nets over a blurred occupancy field, with normals recomputed from the triangles.
+    Smooth,
+}
+
+impl TerrainFillStyle {
+    /// Names of the materials the style fills with.
+    pub fn materials(&self) -> Vec<&String> {
+        match self {
+            TerrainFillStyle::FlatBoard { material } => vec![material],
+            TerrainFillStyle::CheckersBoard { zero, one } => vec![zero, one],
+            TerrainFillStyle::Noise {
+                material, biomes, ..
+            } => std::iter::once(material)
+                .chain(
+                    biomes
+                        .palettes
+                        .iter()
+                        .flat_map(|palette| [&palette.surface, &palette.ground]),
+                )
+                .collect(),
+            TerrainFillStyle::Heightmap {
+                material, bands, ..
+            } => std::iter::once(material).chain(bands).collect(),
+        }
+    }
+}
+
+/// What a terrain was generated from, enough to generate it again. Saves store it with the
+/// sectors edited since, instead of the whole map.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TerrainBase {
+    pub origin: [i32; 3],
+    pub size: u32,
+    pub style: TerrainFillStyle,
+    pub mesh_style: TerrainMeshStyle,
+    pub wrap: bool,
+}
+
+impl TerrainBase {
+    /// Fails if the style uses a material missing from `materials`.
+    pub fn generator(&self, materials: &HashMap<String, u16>) -> Result<TerrainGenerator, String> {
+        if let Some(name) = self
+            .style
+            .materials()
+            .into_iter()
+            .find(|name| !materials.contains_key(*name))
+        {
+            return Err(format!("Unknown terrain material {}", name));
+        }
+        Ok(TerrainGenerator::new(
+            materials,
+            PointN(self.origin),
+            self.size,
+            self.style.clone(),
+        ))
+    }
+}
+
 pub struct Simulation {
     multiverse: HashMap<UniverseId, Universe>,
     next_universe_id: UniverseId,
@@ -1099,9 +3200,15 @@ impl Simulation {
                 id: universe_id,
                 initialized: true,
                 world: Default::default(),
+                ids: Default::default(),
                 visibility_region,
+                schedule: None,
                 main_view_frustum,
                 main_light: None,
+                mesh_style: TerrainMeshStyle::Cubic,
+                wrap: false,
+                chunk_ao: true,
+                compact_vertices: true,
                 materials: Default::default(),
                 material_names: Default::default(),
                 materials_map: Default::default(),
@@ -1110,13 +3217,33 @@ impl Simulation {
                 active_meshers: 0,
                 chunks: HashMap::new(),
                 sectors: HashMap::new(),
+                edited_sectors: HashSet::new(),
+                sector_visibility: HashMap::new(),
                 mesher_tx,
                 mesher_rx,
+                extract_keys: vec![],
+                extract_buffer: vec![],
                 metrics: Default::default(),
                 mesh_cmd_tx,
                 mesh_cmd_rx,
                 mesh_add_requests: HashMap::new(),
                 current_mesh_add_request: 0,
+                material_instances: vec![],
+                removed_voxels: vec![],
+                undo: Default::default(),
+                edit_count: 0,
+                navmesh: Default::default(),
+                terrain_bounds: Extent3i::from_min_and_shape(Point3i::ZERO, Point3i::ZERO),
+                skirt: vec![],
+                skirt_depth: 0,
+                skirt_dirty: false,
+                skirt_requests: HashMap::new(),
+                occupancy: Default::default(),
+                overlays: HashMap::new(),
+                column_tops: HashMap::new(),
+                terrain_gen: None,
+                base: None,
+                terrain_revision: 0,
             }
         };
         let mut multiverse = HashMap::new();
@@ -1136,6 +3263,8 @@ impl Simulation {
         origin: Point3i,
         size: u32,
         style: TerrainFillStyle,
+        mesh_style: TerrainMeshStyle,
+        wrap: bool,
     ) -> UniverseId {
         let universe_id = self.next_universe_id;
 
@@ -1153,7 +3282,15 @@ impl Simulation {
                 .map(|(idx, v)| (v.0.to_string(), idx as u16))
                 .collect();
             let materials = materials.iter().map(|v| v.1.clone()).collect();
-            let voxels = Universe::generate_voxels(&materials_map, origin, size, style);
+            let base = TerrainBase {
+                origin: origin.0,
+                size,
+                style: style.clone(),
+                mesh_style,
+                wrap,
+            };
+            let generator = TerrainGenerator::new(&materials_map, origin, size, style);
+            let voxels = TerrainGenerator::empty_voxels();
             let (mesher_tx, mesher_rx) = unbounded();
             let (mesh_cmd_tx, mesh_cmd_rx) = dyn_mesh_manager.get_command_channels();
             let visibility_region = VisibilityRegion::new();
@@ -1175,9 +3312,15 @@ impl Simulation {
                 id: universe_id,
                 initialized: false,
                 world,
+                ids: Default::default(),
                 visibility_region,
+                schedule: None,
                 main_view_frustum,
                 main_light,
+                mesh_style,
+                wrap,
+                chunk_ao: true,
+                compact_vertices: true,
                 materials,
                 material_names,
                 materials_map,
@@ -1186,15 +3329,37 @@ impl Simulation {
                 active_meshers: 0,
                 chunks: HashMap::new(),
                 sectors: HashMap::new(),
+                edited_sectors: HashSet::new(),
+                sector_visibility: HashMap::new(),
                 mesher_tx,
                 mesher_rx,
+                extract_keys: vec![],
+                extract_buffer: vec![],
                 metrics: Default::default(),
                 mesh_cmd_tx,
                 mesh_cmd_rx,
                 mesh_add_requests: HashMap::new(),
                 current_mesh_add_request: 0,
+                material_instances: vec![],
+                removed_voxels: vec![],
+                undo: Default::default(),
+                edit_count: 0,
+                navmesh: Default::default(),
+                terrain_bounds: Extent3i::from_min_and_shape(Point3i::ZERO, Point3i::ZERO),
+                skirt: vec![],
+                skirt_depth: 0,
+                skirt_dirty: false,
+                skirt_requests: HashMap::new(),
+                occupancy: Default::default(),
+                overlays: HashMap::new(),
+                column_tops: HashMap::new(),
+                terrain_gen: None,
+                base: None,
+                terrain_revision: 0,
             };
-            universe.reset_chunks();
+            // the world keeps its main light, only the terrain comes later
+            let job = TerrainGenJob::start(&universe.task_pool, generator);
+            universe.terrain_gen = Some((job, false, base));
             universe
         };
         self.multiverse.insert(universe_id, universe);
@@ -1230,6 +3395,42 @@ impl Simulation {
         self.get_universe_mut(self.active_universe_id)
     }
 
+    /// Polls the terrain generation of every universe, so background ones finish too.
+    pub fn update_terrain_gen(&mut self) {
+        for universe in self.multiverse.values_mut() {
+            universe.update_terrain_gen();
+        }
+    }
+
+    /// Drops the removed voxels of the background universes, only the active one is checked
+    /// for structural integrity and the others would collect them forever.
+    pub fn drain_removed_voxels(&mut self) {
+        let active = self.active_universe_id;
+        for (id, universe) in self.multiverse.iter_mut() {
+            if *id != active {
+                universe.take_removed_voxels();
+            }
+        }
+    }
+
+    /// Wraps the entities and runs the transform hierarchy of every universe, after the scene
+    /// update and before extract.
+    pub fn update_transforms(&mut self) {
+        for universe in self.multiverse.values_mut() {
+            universe.wrap_entities();
+            universe.update_transform_hierarchy();
+        }
+    }
+
+    /// Releases the GPU resources of every universe, see `Universe::destroy`, and drops all
+    /// but the default one.
+    pub fn destroy(&mut self, dyn_mesh_manager: &mut DynMeshManager) {
+        for universe in self.multiverse.values_mut() {
+            universe.destroy(dyn_mesh_manager);
+        }
+        self.reset();
+    }
+
     pub fn reset(&mut self) {
         let default_universe_id = UniverseId(0);
         self.active_universe_id = default_universe_id;
@@ -0,0 +1,150 @@
+use legion::Entity;
+use rafx::visibility::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Entity id that stays valid across world resets and can be saved or sent over the wire,
+/// unlike legion's `Entity`. Slots are reused, the generation tells stale ids apart.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StableId {
+    index: u32,
+    generation: u32,
+}
+
+impl StableId {
+    pub fn to_bits(self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+impl From<StableId> for ObjectId {
+    fn from(id: StableId) -> Self {
+        ObjectId::from(id.to_bits())
+    }
+}
+
+impl From<ObjectId> for StableId {
+    fn from(id: ObjectId) -> Self {
+        let bits: u64 = id.into();
+        StableId::from_bits(bits)
+    }
+}
+
+/// Object id of an entity drawn or lit through rafx_plugins' mesh_adv, which resolves object ids
+/// back to legion entities in its extract and keys its shadow maps by them. These are the only
+/// objects not registered with their `StableId`.
+pub fn mesh_adv_object_id(entity: Entity) -> ObjectId {
+    ObjectId::from(entity)
+}
+
+struct Slot {
+    generation: u32,
+    entity: Option<Entity>,
+    /// In use, but its entity is out of the world for now.
+    detached: bool,
+}
+
+/// Generational allocator for `StableId`s and the lookup map to the current `Entity`.
+#[derive(Default)]
+pub struct StableIdRegistry {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+}
+
+impl StableIdRegistry {
+    pub fn register(&mut self, entity: Entity) -> StableId {
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.entity = Some(entity);
+            slot.detached = false;
+            StableId {
+                index,
+                generation: slot.generation,
+            }
+        } else {
+            self.slots.push(Slot {
+                generation: 0,
+                entity: Some(entity),
+                detached: false,
+            });
+            StableId {
+                index: self.slots.len() as u32 - 1,
+                generation: 0,
+            }
+        }
+    }
+
+    /// Frees the id for reuse and returns the entity it pointed to.
+    pub fn unregister(&mut self, id: StableId) -> Option<Entity> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        let entity = slot.entity.take();
+        if entity.is_some() || slot.detached {
+            slot.generation = slot.generation.wrapping_add(1);
+            slot.detached = false;
+            self.free.push(id.index);
+        }
+        entity
+    }
+
+    /// Unlinks the id from its entity without freeing it, for entities taken out of the world
+    /// for a while. Until `rebind` the id resolves to no entity.
+    pub fn detach(&mut self, id: StableId) -> Option<Entity> {
+        let slot = self
+            .slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)?;
+        let entity = slot.entity.take();
+        if entity.is_some() {
+            slot.detached = true;
+        }
+        entity
+    }
+
+    /// Links a detached id to the entity replacing the old one.
+    pub fn rebind(&mut self, id: StableId, entity: Entity) -> bool {
+        match self.slots.get_mut(id.index as usize) {
+            Some(slot) if slot.generation == id.generation && slot.detached => {
+                slot.entity = Some(entity);
+                slot.detached = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn entity(&self, id: StableId) -> Option<Entity> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets all entities. Generations are kept so old ids don't resolve to new entities.
+    pub fn clear(&mut self) {
+        self.free.clear();
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.entity.take().is_some() || slot.detached {
+                slot.generation = slot.generation.wrapping_add(1);
+                slot.detached = false;
+            }
+            self.free.push(index as u32);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use bevy_tasks::{Task, TaskPool};
+use building_blocks::{core::prelude::*, storage::prelude::*};
+use crossbeam_channel::{unbounded, Receiver};
+
+use crate::env::{
+    biome::BiomeMap,
+    heightmap::Heightmap,
+    noise::NoiseFn2D,
+    simulation::{MaterialVoxel, MaterialVoxels, TerrainFillStyle},
+};
+
+pub const CHUNK_SIZE: i32 = 16; // voxels
+
+/// The chunks generated for one column of chunks, by chunk minimum, lowest first.
+type ColumnChunks = Vec<(Point3i, Array3x1<MaterialVoxel>)>;
+
+enum GeneratorKind {
+    Flat(MaterialVoxel),
+    Checkers(MaterialVoxel, MaterialVoxel),
+    Noise {
+        noise_fn: Box<dyn NoiseFn2D + Send + Sync>,
+        voxel: MaterialVoxel,
+        // surface and ground voxel of each biome, when enabled
+        biomes: Option<(BiomeMap, Vec<(MaterialVoxel, MaterialVoxel)>)>,
+    },
+    Heightmap {
+        heights: Vec<i32>,
+        vertical_scale: f32,
+        voxel: MaterialVoxel,
+        bands: Vec<MaterialVoxel>,
+    },
+}
+
+/// The fill rules of a `TerrainFillStyle`, with its noise, biome map or heightmap built once.
+/// Every voxel only depends on its position and the style, so the chunks come out the same no
+/// matter how the columns are split between threads or in which order they finish.
+pub struct TerrainGenerator {
+    base_min: Point3i,
+    size: i32,
+    kind: GeneratorKind,
+}
+
+impl TerrainGenerator {
+    pub fn new(
+        materials: &HashMap<String, u16>,
+        origin: Point3i,
+        size: u32,
+        style: TerrainFillStyle,
+    ) -> Self {
+        let voxel_of = |name: &String| MaterialVoxel::from_material_index(materials[name] + 1);
+        let size = size as i32;
+        let base_min = PointN([origin.x() - size / 2, origin.y() - size / 2, origin.z() - 1]);
+        let kind = match style {
+            TerrainFillStyle::FlatBoard { material } => GeneratorKind::Flat(voxel_of(&material)),
+            TerrainFillStyle::CheckersBoard { zero, one } => {
+                GeneratorKind::Checkers(voxel_of(&zero), voxel_of(&one))
+            }
+            TerrainFillStyle::Noise {
+                params,
+                material,
+                biomes,
+            } => GeneratorKind::Noise {
+                noise_fn: params.build(),
+                voxel: voxel_of(&material),
+                biomes: if biomes.enabled {
+                    let voxels = biomes
+                        .palettes
+                        .iter()
+                        .map(|palette| (voxel_of(&palette.surface), voxel_of(&palette.ground)))
+                        .collect();
+                    Some((biomes.build(params.seed), voxels))
+                } else {
+                    None
+                },
+            },
+            TerrainFillStyle::Heightmap {
+                path,
+                vertical_scale,
+                material,
+                bands,
+            } => match Heightmap::load(&path) {
+                Ok(heightmap) => GeneratorKind::Heightmap {
+                    heights: heightmap.resample(size as u32, vertical_scale),
+                    vertical_scale,
+                    voxel: voxel_of(&material),
+                    bands: bands.iter().map(voxel_of).collect(),
+                },
+                Err(err) => {
+                    log::error!("{}, falling back to a flat board", err);
+                    GeneratorKind::Flat(voxel_of(&material))
+                }
+            },
+        };
+        Self {
+            base_min,
+            size,
+            kind,
+        }
+    }
+
+    fn base_extent(&self) -> Extent3i {
+        Extent3i::from_min_and_shape(self.base_min, PointN([self.size, self.size, 1]))
+    }
+
+    /// Minimums (XY) of the chunk columns covering the terrain, row by row.
+    pub fn chunk_columns(&self) -> Vec<[i32; 2]> {
+        let min = self.base_min;
+        let max = self.base_extent().least_upper_bound() - Point3i::ONES;
+        let first = |v: i32| v.div_euclid(CHUNK_SIZE) * CHUNK_SIZE;
+        let mut columns = vec![];
+        for y in (first(min.y())..=max.y()).step_by(CHUNK_SIZE as usize) {
+            for x in (first(min.x())..=max.x()).step_by(CHUNK_SIZE as usize) {
+                columns.push([x, y]);
+            }
+        }
+        columns
+    }
+
+    /// The vertical runs of voxels at `p` on the base layer, as bottom, height and voxel.
+    fn column(&self, p: Point3i, fills: &mut Vec<(i32, i32, MaterialVoxel)>) {
+        match &self.kind {
+            GeneratorKind::Flat(voxel) => fills.push((p.z(), 1, *voxel)),
+            GeneratorKind::Checkers(zero, one) => {
+                let px = p.x() % 2;
+                let py = p.y() % 2;
+                fills.push((p.z(), 1, if (px + py) % 2 == 0 { *zero } else { *one }));
+            }
+            GeneratorKind::Noise {
+                noise_fn,
+                voxel,
+                biomes,
+            } => {
+                let noise = noise_fn.get_2d(p.x() as f64, p.y() as f64) as i32;
+                if let Some((biome_map, biome_voxels)) = biomes {
+                    let (surface, ground) = biome_voxels[biome_map.biome_at(p.x(), p.y()) as usize];
+                    fills.push((noise - 8, 7, ground));
+                    fills.push((noise - 1, 1, surface));
+                } else {
+                    fills.push((noise - 8, 8, *voxel));
+                }
+            }
+            GeneratorKind::Heightmap {
+                heights,
+                vertical_scale,
+                voxel,
+                bands,
+            } => {
+                let size = self.size;
+                let height_at = |x: i32, y: i32| {
+                    heights[(y.clamp(0, size - 1) * size + x.clamp(0, size - 1)) as usize]
+                };
+                let x = p.x() - self.base_min.x();
+                let y = p.y() - self.base_min.y();
+                let h = height_at(x, y);
+                // fill down to the lowest neighbour so cliffs have no holes
+                let low = h
+                    .min(height_at(x - 1, y))
+                    .min(height_at(x + 1, y))
+                    .min(height_at(x, y - 1))
+                    .min(height_at(x, y + 1));
+                let voxel = if bands.is_empty() {
+                    *voxel
+                } else {
+                    let band = (h as f32 / vertical_scale.max(1.) * bands.len() as f32) as usize;
+                    bands[band.min(bands.len() - 1)]
+                };
+                fills.push((p.z() + low, h - low + 1, voxel));
+            }
+        }
+    }
+
+    /// Generates the chunks of the column with minimum `column`.
+    pub fn generate_column(&self, column: [i32; 2]) -> ColumnChunks {
+        let column_extent = Extent3i::from_min_and_shape(
+            PointN([column[0], column[1], self.base_min.z()]),
+            PointN([CHUNK_SIZE, CHUNK_SIZE, 1]),
+        );
+        let mut chunks = BTreeMap::new();
+        let mut fills = vec![];
+        for p in column_extent
+            .intersection(&self.base_extent())
+            .iter_points()
+        {
+            fills.clear();
+            self.column(p, &mut fills);
+            for (bottom, height, voxel) in fills.iter() {
+                if *height <= 0 {
+                    continue;
+                }
+                let first = bottom.div_euclid(CHUNK_SIZE);
+                let last = (bottom + height - 1).div_euclid(CHUNK_SIZE);
+                for chunk_z in first..=last {
+                    let chunk_min = PointN([column[0], column[1], chunk_z * CHUNK_SIZE]);
+                    let chunk = chunks.entry(chunk_z).or_insert_with(|| {
+                        Array3x1::fill(
+                            Extent3i::from_min_and_shape(chunk_min, Point3i::fill(CHUNK_SIZE)),
+                            MaterialVoxel::empty(),
+                        )
+                    });
+                    let run = Extent3i::from_min_and_shape(
+                        PointN([p.x(), p.y(), *bottom]),
+                        PointN([1, 1, *height]),
+                    );
+                    let run = run.intersection(chunk.extent());
+                    chunk.fill_extent(&run, *voxel);
+                }
+            }
+        }
+        chunks
+            .into_iter()
+            .map(|(chunk_z, chunk)| (PointN([column[0], column[1], chunk_z * CHUNK_SIZE]), chunk))
+            .collect()
+    }
+
+    pub fn empty_voxels() -> MaterialVoxels {
+        let builder = ChunkMapBuilder3x1::new(Point3i::fill(CHUNK_SIZE), MaterialVoxel::default());
+        builder.build_with_hash_map_storage()
+    }
+
+    /// Generates the whole terrain on the calling thread.
+    pub fn generate(&self) -> MaterialVoxels {
+        let mut voxels = Self::empty_voxels();
+        for column in self.chunk_columns() {
+            for (chunk_min, chunk) in self.generate_column(column) {
+                voxels.write_chunk(ChunkKey3::new(0, chunk_min), chunk);
+            }
+        }
+        voxels
+    }
+}
+
+/// Terrain generation running on the task pool, one task per row of chunk columns. Finished
+/// columns are sent back and written into the map as they come, which is also the progress
+/// shown while it runs. Cancelling stops the tasks at the next column.
+pub struct TerrainGenJob {
+    cancel: Arc<AtomicBool>,
+    columns: usize,
+    done: usize,
+    result_rx: Receiver<ColumnChunks>,
+    voxels: MaterialVoxels,
+    _tasks: Vec<Task<()>>,
+}
+
+impl TerrainGenJob {
+    pub fn start(task_pool: &TaskPool, generator: TerrainGenerator) -> Self {
+        let columns = generator.chunk_columns();
+        let column_count = columns.len();
+        log::info!("Generating terrain, {} chunk columns...", column_count);
+        let generator = Arc::new(generator);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (result_tx, result_rx) = unbounded();
+        let row_length = columns
+            .iter()
+            .take_while(|column| column[1] == columns[0][1])
+            .count()
+            .max(1);
+        let tasks = columns
+            .chunks(row_length)
+            .map(|row| {
+                let row = row.to_vec();
+                let generator = generator.clone();
+                let cancel = cancel.clone();
+                let result_tx = result_tx.clone();
+                task_pool.spawn(async move {
+                    for column in row {
+                        if cancel.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        if result_tx.send(generator.generate_column(column)).is_err() {
+                            return;
+                        }
+                    }
+                })
+            })
+            .collect();
+        Self {
+            cancel,
+            columns: column_count,
+            done: 0,
+            result_rx,
+            voxels: TerrainGenerator::empty_voxels(),
+            _tasks: tasks,
+        }
+    }
+
+    /// Fraction of the chunk columns written so far.
+    pub fn progress(&self) -> f32 {
+        if self.columns == 0 {
+            1.
+        } else {
+            self.done as f32 / self.columns as f32
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Writes the columns finished since the last call, and returns the voxels once all are in.
+    pub fn poll(&mut self) -> Option<MaterialVoxels> {
+        for chunks in self.result_rx.try_iter() {
+            for (chunk_min, chunk) in chunks {
+                self.voxels.write_chunk(ChunkKey3::new(0, chunk_min), chunk);
+            }
+            self.done += 1;
+        }
+        if self.done < self.columns {
+            return None;
+        }
+        log::info!("Terrain generated");
+        Some(std::mem::replace(
+            &mut self.voxels,
+            TerrainGenerator::empty_voxels(),
+        ))
+    }
+}
+
+/// Window with the progress of the terrain generation, returns true if it was cancelled.
+pub fn progress_ui(context: &egui::CtxRef, progress: f32) -> bool {
+    let mut cancel = false;
+    egui::Window::new(tr!("terrain_gen.title"))
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+        .show(context, |ui| {
+            ui.add(egui::ProgressBar::new(progress).show_percentage());
+            cancel = ui.button(tr!("terrain_gen.cancel")).clicked();
+        });
+    cancel
+}
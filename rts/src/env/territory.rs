@@ -0,0 +1,321 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use building_blocks::core::prelude::*;
+use glam::Vec3;
+use legion::{IntoQuery, Read, Resources};
+use rafx_plugins::components::TransformComponent;
+
+use super::{
+    env::TileComponent,
+    overlay_mesh::OverlayQuads,
+    simulation::{ChunkGeometry, Universe},
+};
+
+const OVERLAY_OWNER: &str = "territory";
+const CELL_SIZE: i32 = 4; // voxels
+const REGION_CELLS: i32 = 16; // cells per region side, one mesh each
+const UPDATE_INTERVAL: f32 = 0.5; // s
+const RIBBON_HEIGHT: f32 = 1.5; // voxels
+
+// the ribbon floats this high above the surface and this far inside the owned cell
+const RIBBON_LIFT: f32 = 0.05; // voxels
+const RIBBON_INSET: f32 = 0.25; // voxels
+
+// the cell side facing each direction, as the offset to the neighbour and the corners
+const SIDES: [([i32; 2], [i32; 2], [i32; 2]); 4] = [
+    ([1, 0], [1, 0], [1, 1]),
+    ([-1, 0], [0, 1], [0, 0]),
+    ([0, 1], [1, 1], [0, 1]),
+    ([0, -1], [0, 0], [1, 0]),
+];
+
+/// Team territory from building influence, drawn as ribbons along its borders. Every owned
+/// building claims the grid cells within its footprint plus `building_radius`, contested cells
+/// go to the strongest influence. The ownership grid is recomputed a few times per second and
+/// only the regions where it changed get new ribbon meshes.
+pub struct TerritoryResource {
+    pub enabled: bool,
+    /// Added to half the larger side of the building footprint.
+    pub building_radius: f32, // voxels
+    /// Terrain material of each team's ribbon, cycled for more teams.
+    pub team_materials: Vec<String>,
+    owners: HashMap<[i32; 2], u8>,
+    sources_hash: u64,
+    // regions with a ribbon mesh
+    regions: HashSet<[i32; 2]>,
+    // regions to rebuild, kept while the terrain materials load
+    dirty: HashSet<[i32; 2]>,
+    timer: f32, // s
+}
+
+impl Default for TerritoryResource {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            building_radius: 24.,
+            // in the order of the classic team palette
+            team_materials: ["flat_blue", "flat_red", "flat_green", "old_bronze"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            owners: Default::default(),
+            sources_hash: 0,
+            regions: Default::default(),
+            dirty: Default::default(),
+            timer: 0.,
+        }
+    }
+}
+
+impl TerritoryResource {
+    pub fn owner(&self, x: i32, y: i32) -> Option<u8> {
+        self.owners
+            .get(&[x.div_euclid(CELL_SIZE), y.div_euclid(CELL_SIZE)])
+            .copied()
+    }
+
+    fn region_of(cell: [i32; 2]) -> [i32; 2] {
+        [
+            cell[0].div_euclid(REGION_CELLS),
+            cell[1].div_euclid(REGION_CELLS),
+        ]
+    }
+
+    fn region_key(region: [i32; 2]) -> Point3i {
+        PointN([region[0], region[1], 0])
+    }
+
+    pub fn update(&mut self, dt: f32, universe: &mut Universe, resources: &Resources) {
+        if !self.enabled {
+            if !self.owners.is_empty() {
+                self.dirty.extend(self.regions.iter().copied());
+                self.owners.clear();
+                self.sources_hash = 0;
+            }
+            self.rebuild_dirty(universe, resources);
+            return;
+        }
+        // the universe drops its overlays when the terrain is reset
+        for region in &self.regions {
+            if !universe.has_overlay_mesh(OVERLAY_OWNER, Self::region_key(*region)) {
+                self.dirty.insert(*region);
+            }
+        }
+        self.timer -= dt;
+        if self.timer <= 0. {
+            self.timer = UPDATE_INTERVAL;
+            self.update_owners(universe);
+        }
+        self.rebuild_dirty(universe, resources);
+    }
+
+    fn update_owners(&mut self, universe: &Universe) {
+        let mut sources = vec![];
+        let mut hasher = DefaultHasher::new();
+        let mut query = <(Read<TransformComponent>, Read<TileComponent>)>::query();
+        for (transform, tile) in query.iter(&universe.world) {
+            let team = match tile.team {
+                Some(team) => team,
+                None => continue,
+            };
+            let [w, d] = tile.properties.footprint.unwrap_or([1, 1]);
+            let center = transform.translation + Vec3::new(w as f32, d as f32, 0.) / 2.;
+            let radius = self.building_radius + w.max(d) as f32 / 2.;
+            (center.x as i32, center.y as i32, radius as i32, team).hash(&mut hasher);
+            sources.push((center, radius, team));
+        }
+        self.building_radius.to_bits().hash(&mut hasher);
+        let sources_hash = hasher.finish();
+        if sources_hash == self.sources_hash {
+            return;
+        }
+        self.sources_hash = sources_hash;
+
+        // the strongest influence wins, as how deep the cell is inside the radius
+        let mut best: HashMap<[i32; 2], (f32, u8)> = HashMap::new();
+        for (center, radius, team) in sources {
+            let min = [
+                ((center.x - radius) / CELL_SIZE as f32).floor() as i32,
+                ((center.y - radius) / CELL_SIZE as f32).floor() as i32,
+            ];
+            let max = [
+                ((center.x + radius) / CELL_SIZE as f32).floor() as i32,
+                ((center.y + radius) / CELL_SIZE as f32).floor() as i32,
+            ];
+            for y in min[1]..=max[1] {
+                for x in min[0]..=max[0] {
+                    let cell_center =
+                        (Vec3::new(x as f32, y as f32, 0.) + Vec3::splat(0.5)) * CELL_SIZE as f32;
+                    let distance = (cell_center - Vec3::new(center.x, center.y, 0.)).length();
+                    let influence = radius - distance;
+                    if influence < 0. {
+                        continue;
+                    }
+                    let entry = best.entry([x, y]).or_insert((influence, team));
+                    if influence > entry.0 {
+                        *entry = (influence, team);
+                    }
+                }
+            }
+        }
+        let owners: HashMap<[i32; 2], u8> = best
+            .into_iter()
+            .map(|(cell, (_, team))| (cell, team))
+            .collect();
+
+        // the ribbons of a cell depend on its neighbours, so their regions change too
+        let changed = owners
+            .iter()
+            .filter(|(cell, team)| self.owners.get(*cell) != Some(*team))
+            .map(|(cell, _)| *cell)
+            .chain(
+                self.owners
+                    .keys()
+                    .filter(|cell| !owners.contains_key(*cell))
+                    .copied(),
+            )
+            .collect::<Vec<_>>();
+        for cell in changed {
+            self.dirty.insert(Self::region_of(cell));
+            for (offset, _, _) in SIDES.iter() {
+                let neighbour = [cell[0] + offset[0], cell[1] + offset[1]];
+                self.dirty.insert(Self::region_of(neighbour));
+            }
+        }
+        self.owners = owners;
+    }
+
+    fn rebuild_dirty(&mut self, universe: &mut Universe, resources: &Resources) {
+        for region in self.dirty.drain().collect::<Vec<_>>() {
+            let min = [region[0] * REGION_CELLS, region[1] * REGION_CELLS];
+            let cells: Vec<_> = (min[1]..min[1] + REGION_CELLS)
+                .flat_map(|y| (min[0]..min[0] + REGION_CELLS).map(move |x| [x, y]))
+                .filter(|cell| self.owners.contains_key(cell))
+                .collect();
+            let (geometry, position) = match self.region_geometry(universe, &cells) {
+                Some((geometry, position)) => (Some(geometry), position),
+                None => (None, Vec3::ZERO),
+            };
+            let has_mesh = geometry.is_some();
+            if !universe.set_overlay_mesh(
+                OVERLAY_OWNER,
+                Self::region_key(region),
+                position,
+                geometry,
+                resources,
+            ) {
+                self.dirty.insert(region);
+                continue;
+            }
+            if has_mesh {
+                self.regions.insert(region);
+            } else {
+                self.regions.remove(&region);
+            }
+        }
+    }
+
+    /// Ribbons along the border sides of `cells`, one part per team, and the position their
+    /// bounds are relative to.
+    fn region_geometry(
+        &self,
+        universe: &mut Universe,
+        cells: &[[i32; 2]],
+    ) -> Option<(ChunkGeometry, Vec3)> {
+        // per team, the bottom corners of each ribbon segment
+        let mut segments: HashMap<u8, Vec<(Vec3, Vec3)>> = HashMap::new();
+        for cell in cells {
+            let team = self.owners[cell];
+            for (offset, from, to) in SIDES.iter() {
+                let neighbour = [cell[0] + offset[0], cell[1] + offset[1]];
+                if self.owners.get(&neighbour) == Some(&team) {
+                    continue;
+                }
+                let corner = |c: [i32; 2]| {
+                    Vec3::new(
+                        ((cell[0] + c[0]) * CELL_SIZE) as f32,
+                        ((cell[1] + c[1]) * CELL_SIZE) as f32,
+                        0.,
+                    )
+                };
+                let (a, b) = (corner(*from), corner(*to));
+                let inset = -Vec3::new(offset[0] as f32, offset[1] as f32, 0.) * RIBBON_INSET;
+                // one segment per voxel column, so the ribbon steps with the terrain
+                let step = (b - a) / CELL_SIZE as f32;
+                let columns: Vec<(i32, i32)> = (0..CELL_SIZE)
+                    .map(|i| {
+                        let p = a + step * (i as f32 + 0.5) + inset;
+                        (p.x.floor() as i32, p.y.floor() as i32)
+                    })
+                    .collect();
+                let tops = universe.column_tops(&columns);
+                for (i, top) in tops.iter().enumerate() {
+                    let z = match top {
+                        Some(top) => (top + 1) as f32 + RIBBON_LIFT,
+                        None => continue,
+                    };
+                    let start = a + step * i as f32 + inset + Vec3::new(0., 0., z);
+                    segments
+                        .entry(team)
+                        .or_default()
+                        .push((start, start + step));
+                }
+            }
+        }
+        if segments.is_empty() {
+            return None;
+        }
+
+        let material_names = universe.get_material_names();
+        let mut quads = OverlayQuads::default();
+        for (team, segments) in segments {
+            let name = &self.team_materials[team as usize % self.team_materials.len().max(1)];
+            let material = match material_names.iter().position(|m| m == name) {
+                Some(material) => material as u16,
+                None => {
+                    log::error!("Territory material {} not found", name);
+                    continue;
+                }
+            };
+            let up = Vec3::new(0., 0., RIBBON_HEIGHT);
+            for (a, b) in segments {
+                let along = (b - a).normalize();
+                let side = along.cross(Vec3::Z);
+                // both faces, the ribbon is seen from inside and outside
+                quads.add(material, [a, b, b + up, a + up], side, along);
+                quads.add(material, [a, a + up, b + up, b], -side, along);
+            }
+        }
+        quads.build()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("territory"))
+            .id_source("territory")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("territory.enabled"));
+                ui.add(
+                    egui::Slider::new(&mut self.building_radius, 0.0..=96.)
+                        .text(tr!("territory.building_radius")),
+                );
+                let mut cells = [0usize; 4];
+                for team in self.owners.values() {
+                    cells[*team as usize % cells.len()] += 1;
+                }
+                for (team, count) in cells.iter().enumerate() {
+                    if *count > 0 {
+                        ui.label(tr!(
+                            "territory.area",
+                            team = team,
+                            area = count * (CELL_SIZE * CELL_SIZE) as usize
+                        ));
+                    }
+                }
+                ui.label(tr!("territory.meshes", count = self.regions.len()));
+            });
+    }
+}
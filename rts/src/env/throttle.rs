@@ -0,0 +1,100 @@
+use super::simulation::MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME;
+
+const MIN_UPLOAD_SCALE: f32 = 0.125;
+const BACKOFF_INTERVAL: f32 = 0.25; // s between steps down
+const RAMP_INTERVAL: f32 = 1.; // s of fast frames before each step up
+
+// weight of the newest frame in the average
+const SMOOTHING: f32 = 0.1;
+
+/// Adaptive limit on the chunk mesh jobs started per frame and on the mesh upload budget.
+/// While the average frame time is above the target both are halved, every `BACKOFF_INTERVAL`,
+/// and once frames are fast again they ramp back up slowly. Heavy edits then remesh over more
+/// frames instead of dropping the frame rate.
+pub struct MeshThrottleResource {
+    pub enabled: bool,
+    pub target_frame_time: f32, // ms
+    average: f32,               // ms
+    jobs_per_frame: usize,
+    upload_scale: f32,
+    since_change: f32, // s
+}
+
+impl Default for MeshThrottleResource {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target_frame_time: 1000. / 60.,
+            average: 0.,
+            jobs_per_frame: MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME,
+            upload_scale: 1.,
+            since_change: 0.,
+        }
+    }
+}
+
+impl MeshThrottleResource {
+    /// Call once per frame, with the unscaled frame time.
+    pub fn update(&mut self, dt: f32) {
+        let frame_time = dt * 1000.;
+        self.average += (frame_time - self.average) * SMOOTHING;
+        self.since_change += dt;
+        if !self.enabled {
+            self.jobs_per_frame = MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME;
+            self.upload_scale = 1.;
+            return;
+        }
+        let throttled =
+            self.jobs_per_frame < MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME || self.upload_scale < 1.;
+        if self.average > self.target_frame_time * 1.1 {
+            if self.since_change >= BACKOFF_INTERVAL {
+                self.jobs_per_frame = (self.jobs_per_frame / 2).max(1);
+                self.upload_scale = (self.upload_scale / 2.).max(MIN_UPLOAD_SCALE);
+                self.since_change = 0.;
+            }
+        } else if self.average < self.target_frame_time * 0.9 {
+            if throttled && self.since_change >= RAMP_INTERVAL {
+                self.jobs_per_frame =
+                    (self.jobs_per_frame + 1).min(MAX_NEW_CHUNK_MESH_JOBS_PER_FRAME);
+                self.upload_scale = (self.upload_scale * 2.).min(1.);
+                self.since_change = 0.;
+            }
+        } else {
+            // within the band, hold
+            self.since_change = 0.;
+        }
+    }
+
+    /// Frames are comfortably under the target, there is time for housekeeping.
+    pub fn is_idle(&self) -> bool {
+        self.average < self.target_frame_time * 0.9
+    }
+
+    pub fn mesh_jobs_per_frame(&self) -> usize {
+        self.jobs_per_frame
+    }
+
+    /// Factor for `DynMeshManager::upload_budget`.
+    pub fn upload_scale(&self) -> f32 {
+        self.upload_scale
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("throttle"))
+            .id_source("remesh throttle")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("throttle.enabled"));
+                ui.add(
+                    egui::Slider::new(&mut self.target_frame_time, 4.0..=50.)
+                        .text(tr!("throttle.target")),
+                );
+                ui.label(tr!(
+                    "throttle.stats",
+                    average = format!("{:.1}", self.average),
+                    jobs = self.jobs_per_frame,
+                    budget = format!("{:.0}", self.upload_scale * 100.)
+                ));
+            });
+    }
+}
@@ -1,10 +1,15 @@
-use egui::{Button, Checkbox, Ui};
+use building_blocks::core::prelude::*;
+use egui::{Button, Checkbox, Color32, Ui};
 
-use super::simulation::TerrainFillStyle;
+use super::{
+    edit::Symmetry,
+    simulation::{FootprintError, TerrainFillStyle, TerrainMeshStyle},
+};
 use crate::{
     assets::tilesets::LoadedTileSet,
-    env::perlin::PerlinNoise2D,
+    env::noise::{preview::NoisePreview, NoiseParams},
     ui::{SpawnMode, UiState},
+    unit::creep::CreepSettings,
 };
 
 pub enum EnvUiCmd {
@@ -16,7 +21,13 @@ pub enum EnvUiCmd {
         tileset_name: Option<String>,
         tile_name: String,
     },
+    /// Asks where to write the edited tile, outside of the tilesets.
+    ExportEditedTile {
+        tile_name: String,
+    },
     FinishEditTile,
+    /// Asks for the image of the heightmap terrain style.
+    BrowseHeightmap,
     ResetTerrain(TerrainResetUiState),
 }
 
@@ -25,8 +36,17 @@ pub struct TileSpawnUiState {
     pub mode: SpawnMode,
     pub tileset: String,
     pub tile: String,
+    /// Why the tile doesn't fit where the cursor is, `None` if it does.
+    pub placement_error: Option<FootprintError>,
+    /// Footprints start on multiples of this many voxels, 1 places them anywhere.
+    pub lattice: i32,
+    /// Fills the gaps under the footprint down to solid ground, up to `MAX_FOUNDATION_DEPTH`.
+    pub auto_foundation: bool,
 }
 
+pub const LATTICE_SIZES: [i32; 4] = [1, 2, 4, 8];
+pub const MAX_FOUNDATION_DEPTH: i32 = 8; // voxels
+
 impl Default for TileSpawnUiState {
     fn default() -> Self {
         Self {
@@ -34,22 +54,65 @@ impl Default for TileSpawnUiState {
             mode: SpawnMode::OneShot,
             tileset: "Base".to_string(),
             tile: "Bilding".to_string(),
+            placement_error: None,
+            lattice: 1,
+            auto_foundation: false,
         }
     }
 }
 
 impl TileSpawnUiState {
+    /// Moves a tile `footprint` wide placed at `position` to the nearest lattice point.
+    pub fn snap(&self, position: Point3i, footprint: [u32; 2]) -> Point3i {
+        let (w, h) = (footprint[0] as i32, footprint[1] as i32);
+        let lattice = self.lattice.max(1);
+        let snap = |min: i32| (min + lattice / 2).div_euclid(lattice) * lattice;
+        PointN([
+            snap(position.x() - w / 2) + w / 2,
+            snap(position.y() - h / 2) + h / 2,
+            position.z(),
+        ])
+    }
+
+    fn placement_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label(tr!("env.tile_spawn.lattice"));
+            for size in LATTICE_SIZES {
+                let label = if size == 1 {
+                    tr!("env.tile_spawn.lattice_off")
+                } else {
+                    size.to_string()
+                };
+                ui.radio_value(&mut self.lattice, size, label);
+            }
+        });
+        ui.checkbox(
+            &mut self.auto_foundation,
+            tr!("env.tile_spawn.auto_foundation"),
+        )
+        .on_hover_text(tr!(
+            "env.tile_spawn.auto_foundation.hint",
+            depth = MAX_FOUNDATION_DEPTH
+        ));
+    }
+
     pub fn ui(ui_state: &mut UiState, ui: &mut Ui, tilesets: &Vec<LoadedTileSet>) {
         let ed = &mut ui_state.env.tile_spawn;
         if ed.active {
-            egui::CollapsingHeader::new("Spawn tile")
+            egui::CollapsingHeader::new(tr!("env.tile_spawn"))
+                .id_source("spawn tile")
                 .default_open(true)
                 .show(ui, |ui| {
                     ed.mode.ui(ui, &mut ed.active);
-                    ui.label("Click a location on the map to spawn tile");
+                    ed.placement_ui(ui);
+                    ui.label(tr!("env.tile_spawn.click"));
+                    if let Some(err) = ed.placement_error {
+                        ui.colored_label(Color32::RED, tr!("env.tile_spawn.blocked", reason = err));
+                    }
                 });
         } else if !ui_state.unit.spawning {
-            egui::CollapsingHeader::new("Spawn tile")
+            egui::CollapsingHeader::new(tr!("env.tile_spawn"))
+                .id_source("spawn tile")
                 .default_open(true)
                 .show(ui, |ui| {
                     ed.mode.ui(ui, &mut ed.active);
@@ -57,8 +120,32 @@ impl TileSpawnUiState {
                         ui.label(&tileset.name);
                         ui.horizontal_wrapped(|ui| {
                             for tile in &tileset.tiles {
+                                let [w, h] = tile.inner.footprint();
+                                let properties = &tile.inner.properties;
                                 if ui
                                     .selectable_label(false, format!("{}", &tile.inner.name))
+                                    .on_hover_text(if properties.walkable {
+                                        tr!(
+                                            "env.tile_spawn.tile.walkable",
+                                            w = w,
+                                            h = h,
+                                            hp = properties.max_health
+                                        )
+                                    } else if properties.wall {
+                                        tr!(
+                                            "env.tile_spawn.tile.wall",
+                                            w = w,
+                                            h = h,
+                                            hp = properties.max_health
+                                        )
+                                    } else {
+                                        tr!(
+                                            "env.tile_spawn.tile",
+                                            w = w,
+                                            h = h,
+                                            hp = properties.max_health
+                                        )
+                                    })
                                     .clicked()
                                 {
                                     ed.tileset = tileset.name.clone();
@@ -78,6 +165,8 @@ pub struct TileEditUiState {
     pub new_tile: bool,
     pub tileset: String,
     pub tile: String,
+    /// Voxel edits are copied around the middle of the tile.
+    pub symmetry: Symmetry,
 }
 
 impl Default for TileEditUiState {
@@ -87,6 +176,7 @@ impl Default for TileEditUiState {
             new_tile: false,
             tileset: "".to_string(),
             tile: "".to_string(),
+            symmetry: Symmetry::Off,
         }
     }
 }
@@ -100,7 +190,8 @@ impl TileEditUiState {
     ) where
         F: FnMut(EnvUiCmd) -> Option<()>,
     {
-        egui::CollapsingHeader::new("Edit tile")
+        egui::CollapsingHeader::new(tr!("env.tile_edit"))
+            .id_source("edit tile")
             .default_open(false)
             .show(ui, |ui| {
                 let ed = &mut ui_state.env.tile_edit;
@@ -111,16 +202,16 @@ impl TileEditUiState {
                     let tileset = ed.tileset.clone();
                     let tile = ed.tile.clone();
                     if ed.new_tile {
-                        ui.label(format!("Adding new tile to '{}':", tileset));
+                        ui.label(tr!("env.tile_edit.adding", tileset = tileset));
                         ui.text_edit_singleline(&mut ed.tile);
                     } else {
-                        ui.label(format!(
-                            "Editing tile '{}' from tileset '{}'",
-                            tile, tileset
-                        ));
+                        ui.label(tr!("env.tile_edit.editing", tile = tile, tileset = tileset));
                     }
                     ui.horizontal_wrapped(|ui| {
-                        if ui.add_sized([100., 30.], Button::new("Save")).clicked() {
+                        if ui
+                            .add_sized([100., 30.], Button::new(tr!("env.tile_edit.save")))
+                            .clicked()
+                        {
                             editing_failed = tile.is_empty()
                                 || cmd_exec(EnvUiCmd::SaveEditedTile {
                                     tileset_name: if ed.new_tile { Some(tileset) } else { None },
@@ -129,10 +220,27 @@ impl TileEditUiState {
                                 .is_none();
                             editing_finished = !editing_failed;
                         }
-                        if ui.add_sized([100., 30.], Button::new("Quit")).clicked() {
+                        if ui
+                            .add_sized([100., 30.], Button::new(tr!("env.tile_edit.export")))
+                            .clicked()
+                        {
+                            cmd_exec(EnvUiCmd::ExportEditedTile {
+                                tile_name: tile.clone(),
+                            });
+                        }
+                        if ui
+                            .add_sized([100., 30.], Button::new(tr!("env.tile_edit.quit")))
+                            .clicked()
+                        {
                             editing_finished = true;
                         }
                     });
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(tr!("env.tile_edit.symmetry"));
+                        for symmetry in Symmetry::ALL {
+                            ui.radio_value(&mut ed.symmetry, symmetry, format!("{}", symmetry));
+                        }
+                    });
                 } else {
                     for tileset in tilesets {
                         let tileset_name = tileset.name.clone();
@@ -179,15 +287,38 @@ impl TileEditUiState {
                     cmd_exec(EnvUiCmd::FinishEditTile);
                 }
                 if editing_failed {
-                    ui_state.error(format!("Exporting tile failed."));
+                    ui_state.error(tr!("env.tile_edit.failed"));
                 }
             });
     }
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TerrainTool {
+    Voxel,
+    Road,
+    /// Drags out a box of terrain to inspect, see `TerrainSelection`.
+    Select,
+}
+
+impl TerrainTool {
+    /// Translated tool name, as shown in the tool picker.
+    pub fn name(self) -> String {
+        match self {
+            TerrainTool::Voxel => tr!("env.terrain_edit.tool.voxel"),
+            TerrainTool::Road => tr!("env.terrain_edit.tool.road"),
+            TerrainTool::Select => tr!("env.terrain_edit.tool.select"),
+        }
+    }
+}
+
 pub struct TerrainEditUiState {
     pub active: bool,
     pub material: String,
+    pub tool: TerrainTool,
+    pub road_width: u32,
+    /// Diameter of the voxel brush at the zoom scaling reference distance.
+    pub brush_size: u32, // voxels
 }
 
 impl Default for TerrainEditUiState {
@@ -195,20 +326,61 @@ impl Default for TerrainEditUiState {
         Self {
             active: false,
             material: "basic_tile".to_string(),
+            tool: TerrainTool::Voxel,
+            road_width: 3,
+            brush_size: 1,
         }
     }
 }
 
 impl TerrainEditUiState {
-    pub fn ui(ui_state: &mut UiState, ui: &mut Ui, materials: &Vec<String>) {
+    /// Radius of the voxel brush, with `scale` from `RTSCamera::brush_scale`.
+    pub fn brush_radius(&self, scale: f32) -> i32 {
+        ((self.brush_size as f32 * scale - 1.) / 2.).round().max(0.) as i32
+    }
+
+    pub fn scaled_road_width(&self, scale: f32) -> u32 {
+        (self.road_width as f32 * scale).round().max(1.) as u32
+    }
+
+    /// Returns true when undo was clicked.
+    pub fn ui(
+        ui_state: &mut UiState,
+        ui: &mut Ui,
+        materials: &Vec<String>,
+        undo_steps: usize,
+    ) -> bool {
         let ed = &mut ui_state.env.terrain_edit;
-        egui::CollapsingHeader::new("Edit terrain")
+        let mut undo = false;
+        egui::CollapsingHeader::new(tr!("env.terrain_edit"))
+            .id_source("edit terrain")
             .default_open(true)
             .show(ui, |ui| {
-                let ck = Checkbox::new(&mut ed.active, "Edit mode active");
+                let ck = Checkbox::new(&mut ed.active, tr!("env.terrain_edit.active"));
                 ui.add(ck);
                 if ed.active {
-                    ui.label("Build material:");
+                    ui.horizontal(|ui| {
+                        ui.label(tr!("env.terrain_edit.tool"));
+                        for tool in [TerrainTool::Voxel, TerrainTool::Road, TerrainTool::Select] {
+                            ui.radio_value(&mut ed.tool, tool, tool.name());
+                        }
+                    });
+                    if ed.tool == TerrainTool::Road {
+                        ui.add(
+                            egui::Slider::new(&mut ed.road_width, 1..=9)
+                                .text(tr!("env.terrain_edit.road_width")),
+                        );
+                        ui.label(tr!("env.terrain_edit.road_hint"));
+                    } else if ed.tool == TerrainTool::Select {
+                        ui.label(tr!("env.terrain_edit.select_hint"));
+                    } else {
+                        ui.add(
+                            egui::Slider::new(&mut ed.brush_size, 1..=15)
+                                .text(tr!("env.terrain_edit.brush_size")),
+                        );
+                    }
+                    ui.label(tr!("env.terrain_edit.brush_scaling"));
+                    ui.label(tr!("env.terrain_edit.material"));
                     let mut index = materials
                         .iter()
                         .position(|mat| mat == &ed.material)
@@ -217,8 +389,15 @@ impl TerrainEditUiState {
                         ui.radio_value(&mut index, idx, material_name);
                     }
                     ed.material = materials[index].clone();
+                    if undo_steps > 0 {
+                        undo = ui
+                            .button(tr!("env.terrain_edit.undo", steps = undo_steps))
+                            .on_hover_text("Ctrl+Z")
+                            .clicked();
+                    }
                 }
             });
+        undo
     }
 }
 
@@ -226,6 +405,11 @@ impl TerrainEditUiState {
 pub struct TerrainResetUiState {
     pub size: u32,
     pub style: TerrainFillStyle,
+    pub mesh_style: TerrainMeshStyle,
+    /// Opposite edges of the terrain meet, for endless scrolling maps.
+    pub wrap: bool,
+    pub creeps: CreepSettings,
+    pub noise_preview: NoisePreview,
 }
 
 impl Default for TerrainResetUiState {
@@ -235,6 +419,10 @@ impl Default for TerrainResetUiState {
             style: TerrainFillStyle::FlatBoard {
                 material: "basic_tile".to_string(),
             },
+            mesh_style: TerrainMeshStyle::Cubic,
+            wrap: false,
+            creeps: Default::default(),
+            noise_preview: Default::default(),
         }
     }
 }
@@ -244,14 +432,15 @@ impl TerrainResetUiState {
     where
         F: FnMut(EnvUiCmd) -> Option<()>,
     {
-        egui::CollapsingHeader::new("Reset terrain")
+        egui::CollapsingHeader::new(tr!("env.terrain_reset"))
+            .id_source("reset terrain")
             .default_open(true)
             .show(ui, |ui| {
                 let ed = &mut ui_state.env.terrain_reset;
 
                 let mut size_str = format!("{}", ed.size);
                 ui.horizontal(|ui| {
-                    ui.label("Size");
+                    ui.label(tr!("env.terrain_reset.size"));
                     ui.text_edit_singleline(&mut size_str);
                     if let Ok(number) = size_str.parse() {
                         ed.size = number;
@@ -260,14 +449,13 @@ impl TerrainResetUiState {
                 let mut style_idx = match ed.style {
                     TerrainFillStyle::FlatBoard { material: _ } => 0,
                     TerrainFillStyle::CheckersBoard { zero: _, one: _ } => 1,
-                    TerrainFillStyle::PerlinNoise {
-                        params: _,
-                        material: _,
-                    } => 2,
+                    TerrainFillStyle::Noise { .. } => 2,
+                    TerrainFillStyle::Heightmap { .. } => 3,
                 };
-                ui.radio_value(&mut style_idx, 0, "Flat board");
-                ui.radio_value(&mut style_idx, 1, "Checkers board");
-                ui.radio_value(&mut style_idx, 2, "Perlin noise");
+                ui.radio_value(&mut style_idx, 0, tr!("env.terrain_reset.flat"));
+                ui.radio_value(&mut style_idx, 1, tr!("env.terrain_reset.checkers"));
+                ui.radio_value(&mut style_idx, 2, tr!("env.terrain_reset.noise"));
+                ui.radio_value(&mut style_idx, 3, tr!("env.terrain_reset.heightmap"));
 
                 ui.add_space(10.);
 
@@ -277,7 +465,8 @@ impl TerrainResetUiState {
                     } else {
                         "basic_tile".to_string()
                     };
-                    let material = UiState::combo_box(ui, &materials, &material, "mat");
+                    let label = tr!("env.terrain_reset.material");
+                    let material = UiState::combo_box(ui, &materials, &material, &label);
                     ed.style = TerrainFillStyle::FlatBoard {
                         material: material.to_string(),
                     };
@@ -288,52 +477,119 @@ impl TerrainResetUiState {
                         } else {
                             ("basic_tile".to_string(), "black_plastic".to_string())
                         };
-                    let zero = UiState::combo_box(ui, &materials, &zero, "zero");
-                    let one = UiState::combo_box(ui, &materials, &one, "one");
+                    let (zero_label, one_label) = (
+                        tr!("env.terrain_reset.checkers.zero"),
+                        tr!("env.terrain_reset.checkers.one"),
+                    );
+                    let zero = UiState::combo_box(ui, &materials, &zero, &zero_label);
+                    let one = UiState::combo_box(ui, &materials, &one, &one_label);
                     ed.style = TerrainFillStyle::CheckersBoard {
                         zero: zero.to_string(),
                         one: one.to_string(),
                     };
                 } else if style_idx == 2 {
-                    let (mut params, material) =
-                        if let TerrainFillStyle::PerlinNoise { params, material } = &ed.style {
-                            (params.clone(), material.clone())
+                    let (mut params, material, mut biomes) = if let TerrainFillStyle::Noise {
+                        params,
+                        material,
+                        biomes,
+                    } = &ed.style
+                    {
+                        (params.clone(), material.clone(), biomes.clone())
+                    } else {
+                        (
+                            NoiseParams {
+                                scale: ed.size as f64,
+                                ..Default::default()
+                            },
+                            "basic_tile".to_string(),
+                            Default::default(),
+                        )
+                    };
+                    let material = if biomes.enabled {
+                        material
+                    } else {
+                        let label = tr!("env.terrain_reset.material");
+                        UiState::combo_box(ui, &materials, &material, &label).to_string()
+                    };
+                    params.ui(ui, ed.size as f64 + 1.);
+                    ed.noise_preview.ui(ui, &params, ed.size);
+                    ui.add_space(10.);
+                    biomes.ui(ui, materials);
+
+                    ed.style = TerrainFillStyle::Noise {
+                        params,
+                        material,
+                        biomes,
+                    };
+                } else if style_idx == 3 {
+                    let (mut path, mut vertical_scale, material, mut bands) =
+                        if let TerrainFillStyle::Heightmap {
+                            path,
+                            vertical_scale,
+                            material,
+                            bands,
+                        } = &ed.style
+                        {
+                            (path.clone(), *vertical_scale, material.clone(), bands.clone())
                         } else {
-                            (
-                                PerlinNoise2D {
-                                    octaves: 6,
-                                    amplitude: 10.0,
-                                    frequency: 1.0,
-                                    persistence: 1.0,
-                                    lacunarity: 2.0,
-                                    scale: (ed.size as f64, ed.size as f64),
-                                    bias: 0.,
-                                    seed: 42,
-                                },
-                                "basic_tile".to_string(),
-                            )
+                            ("heightmap.png".to_string(), 64., "basic_tile".to_string(), vec![])
                         };
-                    let material = UiState::combo_box(ui, &materials, &material, "mat");
-                    ui.add(egui::Slider::new(&mut params.octaves, 0..=8).text("octaves"));
-                    ui.add(egui::Slider::new(&mut params.amplitude, 0.0..=64.0).text("amplitude"));
-                    ui.add(egui::Slider::new(&mut params.frequency, 0.0..=4.0).text("frequency"));
-                    ui.add(
-                        egui::Slider::new(&mut params.persistence, 0.0..=2.0).text("persistence"),
-                    );
-                    ui.add(egui::Slider::new(&mut params.lacunarity, 1.0..=4.0).text("lacunarity"));
+                    let mut browse = false;
+                    ui.horizontal(|ui| {
+                        ui.label(tr!("env.terrain_reset.path"));
+                        ui.text_edit_singleline(&mut path);
+                        browse = ui.button(tr!("env.terrain_reset.browse")).clicked();
+                    });
+                    if browse {
+                        cmd_exec(EnvUiCmd::BrowseHeightmap);
+                    }
                     ui.add(
-                        egui::Slider::new(&mut params.bias, 0.0..=ed.size as f64 + 1.).text("bias"),
+                        egui::Slider::new(&mut vertical_scale, 1.0..=256.0)
+                            .text(tr!("env.terrain_reset.vertical_scale")),
                     );
-                    ui.add(egui::Slider::new(&mut params.seed, 0..=16384).text("seed"));
-
-                    ed.style = TerrainFillStyle::PerlinNoise {
-                        params,
-                        material: material.to_string(),
+                    let mut banding = !bands.is_empty();
+                    ui.checkbox(&mut banding, tr!("env.terrain_reset.bands"));
+                    let material = if banding {
+                        if bands.is_empty() {
+                            bands = vec![material.clone(); 3];
+                        }
+                        for (idx, band) in bands.iter_mut().enumerate() {
+                            let label = tr!("env.terrain_reset.band", index = idx);
+                            *band = UiState::combo_box(ui, &materials, band.as_str(), &label)
+                                .to_string();
+                        }
+                        material
+                    } else {
+                        bands.clear();
+                        let label = tr!("env.terrain_reset.material");
+                        UiState::combo_box(ui, &materials, &material, &label).to_string()
+                    };
+                    ed.style = TerrainFillStyle::Heightmap {
+                        path,
+                        vertical_scale,
+                        material,
+                        bands,
                     };
                 }
                 ui.add_space(10.);
+                ui.horizontal(|ui| {
+                    ui.label(tr!("env.terrain_reset.mesh"));
+                    ui.radio_value(
+                        &mut ed.mesh_style,
+                        TerrainMeshStyle::Cubic,
+                        tr!("env.terrain_reset.mesh.cubic"),
+                    );
+                    ui.radio_value(
+                        &mut ed.mesh_style,
+                        TerrainMeshStyle::Smooth,
+                        tr!("env.terrain_reset.mesh.smooth"),
+                    );
+                });
+                ui.checkbox(&mut ed.wrap, tr!("env.terrain_reset.wrap"));
+                ed.creeps.ui(ui);
+                ui.add_space(10.);
                 if ui
-                    .add_sized([100., 30.], Button::new("Reset terrain"))
+                    .add_sized([100., 30.], Button::new(tr!("env.terrain_reset.reset")))
                     .clicked()
                 {
                     cmd_exec(EnvUiCmd::ResetTerrain(ui_state.env.terrain_reset.clone()));
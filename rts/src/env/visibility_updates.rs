@@ -0,0 +1,169 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use glam::Vec3;
+use legion::{systems::SystemBuilder, Entity, IntoQuery, Read, Write};
+use rafx_plugins::components::{TransformComponent, VisibilityComponent};
+
+use super::schedule::{UniverseScheduleResource, UpdateStage};
+
+/// A visibility transform waiting for the sync stage. Systems moving objects during the frame
+/// set `translation` instead of calling `VisibilityObjectArc::set_transform` themselves, and
+/// the visibility sync system applies all of them in one parallel pass after the gameplay
+/// stages, so moving thousands of units doesn't interleave visibility work with movement.
+#[derive(Clone, Copy, Default)]
+pub struct VisibilityPushComponent {
+    pub translation: Option<Vec3>,
+}
+
+/// When moving objects push their visibility transform, see `VisibilityPushComponent`. Objects
+/// near the camera focus or moving fast update every frame, the others every `interval`
+/// frames, staggered by entity, with the position predicted for the middle of the interval so
+/// the culling bounds stay centered on the object until the next update. Only culling lags,
+/// the meshes are drawn at their `TransformComponent`.
+pub struct VisibilityUpdateResource {
+    pub time_slicing: bool,
+    pub near_distance: f32, // m
+    /// Objects at least this fast update every frame wherever they are.
+    pub fast_speed: f32, // m/s
+    pub interval: u32,      // frames
+    frame: u64,
+    focus: Vec3,
+    pushed: AtomicUsize,
+    skipped: AtomicUsize,
+    push_time: AtomicU64, // ns, summed over the threads pushing
+    // of the last frame, for the panel and the stress test
+    last_pushed: usize,
+    last_skipped: usize,
+    last_push_time: u64, // ns
+}
+
+impl Default for VisibilityUpdateResource {
+    fn default() -> Self {
+        Self {
+            time_slicing: true,
+            near_distance: 80.,
+            fast_speed: 8.,
+            interval: 4,
+            frame: 0,
+            focus: Vec3::ZERO,
+            pushed: AtomicUsize::new(0),
+            skipped: AtomicUsize::new(0),
+            push_time: AtomicU64::new(0),
+            last_pushed: 0,
+            last_skipped: 0,
+            last_push_time: 0,
+        }
+    }
+}
+
+impl VisibilityUpdateResource {
+    /// Call once per frame after the camera update, before the systems run.
+    pub fn begin_frame(&mut self, focus: Vec3) {
+        self.frame += 1;
+        self.focus = focus;
+        self.last_pushed = self.pushed.swap(0, Ordering::Relaxed);
+        self.last_skipped = self.skipped.swap(0, Ordering::Relaxed);
+        self.last_push_time = self.push_time.swap(0, Ordering::Relaxed);
+    }
+
+    /// Adds the time the sync pass spent in `set_transform`.
+    pub fn record_push_time(&self, elapsed: Duration) {
+        self.push_time
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Time spent pushing visibility transforms in the last frame, in µs.
+    pub fn last_push_time(&self) -> f32 {
+        self.last_push_time as f32 / 1000.
+    }
+
+    /// The transform to push for an object at `position` moving with `velocity` (per second)
+    /// this frame, `None` if it waits for its slice. Safe to call from `par_for_each_mut`.
+    pub fn update(&self, entity: Entity, position: Vec3, velocity: Vec3, dt: f32) -> Option<Vec3> {
+        let interval = self.interval.max(1) as u64;
+        let every_frame = !self.time_slicing
+            || interval == 1
+            || position.distance(self.focus) <= self.near_distance
+            || velocity.length() >= self.fast_speed;
+        if every_frame {
+            self.pushed.fetch_add(1, Ordering::Relaxed);
+            return Some(position);
+        }
+        let mut hasher = DefaultHasher::new();
+        entity.hash(&mut hasher);
+        if (self.frame + hasher.finish()) % interval != 0 {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+        Some(position + velocity * dt * interval as f32 / 2.)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("visibility_updates"))
+            .id_source("visibility updates")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(
+                    &mut self.time_slicing,
+                    tr!("visibility_updates.time_slicing"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.near_distance, 0.0..=500.)
+                        .text(tr!("visibility_updates.near")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.fast_speed, 0.0..=50.)
+                        .text(tr!("visibility_updates.fast")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.interval, 1..=16)
+                        .text(tr!("visibility_updates.interval")),
+                );
+                let total = (self.last_pushed + self.last_skipped).max(1);
+                ui.label(tr!(
+                    "visibility_updates.stats",
+                    pushed = self.last_pushed,
+                    skipped = self.last_skipped,
+                    percent = format!("{:.0}", 100. * self.last_skipped as f32 / total as f32)
+                ));
+                ui.label(tr!(
+                    "visibility_updates.push_time",
+                    time = format!("{:.0}", self.last_push_time())
+                ));
+            });
+    }
+}
+
+/// Registers the visibility sync system, applying the transforms pushed during the frame.
+pub fn register_systems(systems: &mut UniverseScheduleResource) {
+    systems.add_system(UpdateStage::VisibilitySync, "visibility sync", |builder| {
+        builder.add_system(
+            SystemBuilder::new("visibility sync")
+                .read_resource::<VisibilityUpdateResource>()
+                .with_query(<(
+                    Read<TransformComponent>,
+                    Read<VisibilityComponent>,
+                    Write<VisibilityPushComponent>,
+                )>::query())
+                .build(move |_, world, updates, query| {
+                    query.par_for_each_mut(world, |(transform, visibility, push)| {
+                        if let Some(translation) = push.translation.take() {
+                            let push_start = Instant::now();
+                            visibility.visibility_object_handle.set_transform(
+                                translation,
+                                transform.rotation,
+                                transform.scale,
+                            );
+                            updates.record_push_time(push_start.elapsed());
+                        }
+                    });
+                }),
+        );
+    });
+}
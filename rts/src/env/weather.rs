@@ -0,0 +1,460 @@
+use std::fmt::Display;
+
+use building_blocks::core::prelude::*;
+use glam::{Vec2, Vec3};
+use legion::Resources;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{
+    overlay_mesh::OverlayQuads,
+    simulation::{ChunkGeometry, Universe},
+};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Precipitation {
+    None,
+    Rain,
+    Snow,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum WeatherPreset {
+    Clear,
+    Rain,
+    Storm,
+    Snow,
+    Fog,
+}
+
+impl WeatherPreset {
+    pub const ALL: [WeatherPreset; 5] = [
+        WeatherPreset::Clear,
+        WeatherPreset::Rain,
+        WeatherPreset::Storm,
+        WeatherPreset::Snow,
+        WeatherPreset::Fog,
+    ];
+
+    pub fn params(&self) -> WeatherParams {
+        match self {
+            WeatherPreset::Clear => WeatherParams::default(),
+            WeatherPreset::Rain => WeatherParams {
+                precipitation: Precipitation::Rain,
+                intensity: 0.5,
+                wind: Vec2::new(2., 1.),
+                fog_density: 0.2,
+                overcast: 0.4,
+                ..Default::default()
+            },
+            WeatherPreset::Storm => WeatherParams {
+                precipitation: Precipitation::Rain,
+                intensity: 1.,
+                wind: Vec2::new(12., 5.),
+                fog_density: 0.4,
+                overcast: 0.8,
+                ..Default::default()
+            },
+            WeatherPreset::Snow => WeatherParams {
+                precipitation: Precipitation::Snow,
+                intensity: 0.7,
+                wind: Vec2::new(1., 0.5),
+                fog_density: 0.3,
+                fog_color: Vec3::new(0.9, 0.9, 0.95),
+                overcast: 0.5,
+            },
+            WeatherPreset::Fog => WeatherParams {
+                fog_density: 0.8,
+                overcast: 0.3,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Display for WeatherPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            WeatherPreset::Clear => write!(f, "{}", tr!("weather.preset.clear")),
+            WeatherPreset::Rain => write!(f, "{}", tr!("weather.preset.rain")),
+            WeatherPreset::Storm => write!(f, "{}", tr!("weather.preset.storm")),
+            WeatherPreset::Snow => write!(f, "{}", tr!("weather.preset.snow")),
+            WeatherPreset::Fog => write!(f, "{}", tr!("weather.preset.fog")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WeatherParams {
+    pub precipitation: Precipitation,
+    pub intensity: f32, // 0..1
+    pub wind: Vec2,     // m/s
+    /// Haze over the whole view, see `WeatherResource::ambient_light`.
+    pub fog_density: f32, // 0..1
+    pub fog_color: Vec3,
+    pub overcast: f32, // 0..1, dims the sun
+}
+
+impl Default for WeatherParams {
+    fn default() -> Self {
+        Self {
+            precipitation: Precipitation::None,
+            intensity: 0.,
+            wind: Vec2::ZERO,
+            fog_density: 0.,
+            fog_color: Vec3::new(0.7, 0.75, 0.8),
+            overcast: 0.,
+        }
+    }
+}
+
+const OVERLAY_OWNER: &str = "weather";
+// precipitation intensity shows this many particle layers, each falling at its own speed
+const LAYERS: usize = 4;
+const PARTICLES_PER_CELL: usize = 6; // per layer
+
+// the particles of a cell repeat over the area around the camera target
+const CELL_SIZE: f32 = 8.; // m
+const AREA_RADIUS: f32 = 40.; // m
+const AREA_HEIGHT: f32 = 40.; // m
+
+// particles fall this far below the camera target before they wrap to the top
+const GROUND_MARGIN: f32 = 5.; // m
+const RAIN_SPEED: f32 = 30.; // m/s
+const RAIN_STREAK_LENGTH: f32 = 0.6; // m
+const RAIN_STREAK_WIDTH: f32 = 0.02; // m
+const SNOW_SPEED: f32 = 3.; // m/s
+const SNOW_FLAKE_SIZE: f32 = 0.08; // m
+
+// ambient light of the fog color at full density, in the units of the ambient light
+const FOG_AMBIENT: f32 = 0.05;
+const FOG_LIGHT_DIMMING: f32 = 0.5;
+
+/// Weather state for the active scene. Parameters blend towards the selected preset over
+/// `transition_time` seconds; switching precipitation kind fades the old one out first.
+///
+/// Rain and snow are layers of particles built once as overlay meshes of the universe (see
+/// `Universe::set_overlay_offset`), made of one cell of particles repeated around the camera
+/// target. Every frame the layers only move: they fall and drift with the wind modulo the cell
+/// size, so the particles stay put in the world while the camera pans. Intensity sets how many
+/// layers show. Fog has no depth term in the mesh shaders, it hazes the whole view through the
+/// lighting: the ambient light blends towards the fog color and the sun dims.
+pub struct WeatherResource {
+    pub preset: WeatherPreset,
+    pub transition_time: f32,
+    pub show_particles: bool,
+    /// Ambient light of the scene in clear weather.
+    pub ambient_light: Vec3,
+    current: WeatherParams,
+    target: WeatherParams,
+    // precipitation the mesh of each layer was built for
+    built: [Option<Precipitation>; LAYERS],
+    // fall and wind drift of each layer, wrapped to the cell
+    scroll: [Vec3; LAYERS], // m
+    time: f32,              // s
+}
+
+impl Default for WeatherResource {
+    fn default() -> Self {
+        Self {
+            preset: WeatherPreset::Clear,
+            transition_time: 5.,
+            show_particles: true,
+            ambient_light: Vec3::splat(0.005),
+            current: Default::default(),
+            target: Default::default(),
+            built: [None; LAYERS],
+            scroll: [Vec3::ZERO; LAYERS],
+            time: 0.,
+        }
+    }
+}
+
+fn layer_key(layer: usize) -> Point3i {
+    PointN([layer as i32, 0, 0])
+}
+
+// particles of a layer fall at different speeds so the layers don't move in lockstep
+fn layer_speed(layer: usize) -> f32 {
+    0.8 + 0.4 * layer as f32 / (LAYERS - 1) as f32
+}
+
+// cells repeated along x and y, enough for the area at any scroll of the cell
+fn cells_per_side() -> i32 {
+    (2. * AREA_RADIUS / CELL_SIZE).ceil() as i32 + 2
+}
+
+fn approach(current: f32, target: f32, max_delta: f32) -> f32 {
+    if (target - current).abs() <= max_delta {
+        target
+    } else {
+        current + max_delta * (target - current).signum()
+    }
+}
+
+impl WeatherResource {
+    pub fn params(&self) -> &WeatherParams {
+        &self.current
+    }
+
+    pub fn set_preset(&mut self, preset: WeatherPreset) {
+        self.preset = preset;
+        self.target = preset.params();
+    }
+
+    /// Multiplier for the main light intensity.
+    pub fn light_factor(&self) -> f32 {
+        (1. - 0.7 * self.current.overcast) * (1. - FOG_LIGHT_DIMMING * self.current.fog_density)
+    }
+
+    /// The ambient light with the fog haze added, for the mesh render options.
+    pub fn fogged_ambient_light(&self) -> Vec3 {
+        let fog = self.current.fog_color * FOG_AMBIENT;
+        self.ambient_light
+            .lerp(fog.max(self.ambient_light), self.current.fog_density)
+    }
+
+    #[profiling::function]
+    pub fn update(
+        &mut self,
+        dt: f32,
+        center: Vec3,
+        universe: &mut Universe,
+        resources: &Resources,
+    ) {
+        let rate = dt / self.transition_time.max(0.001);
+        let current = &mut self.current;
+        let target = &self.target;
+        if current.precipitation != target.precipitation {
+            current.intensity = approach(current.intensity, 0., rate);
+            if current.intensity == 0. {
+                current.precipitation = target.precipitation;
+            }
+        } else {
+            current.intensity = approach(current.intensity, target.intensity, rate);
+        }
+        current.wind = current.wind.lerp(target.wind, rate.min(1.));
+        current.fog_density = approach(current.fog_density, target.fog_density, rate);
+        current.fog_color = current.fog_color.lerp(target.fog_color, rate.min(1.));
+        current.overcast = approach(current.overcast, target.overcast, rate);
+
+        self.time += dt;
+        self.update_particles(dt, center, universe, resources);
+    }
+
+    fn velocity(&self, layer: usize) -> Vec3 {
+        let wind = self.current.wind;
+        let speed = layer_speed(layer);
+        match self.current.precipitation {
+            Precipitation::Rain => Vec3::new(wind.x, wind.y, -RAIN_SPEED * speed),
+            Precipitation::Snow => {
+                let phase = self.time * speed + layer as f32;
+                let wobble = Vec2::new(phase.sin(), phase.cos()) * 0.5;
+                Vec3::new(
+                    0.5 * wind.x + wobble.x,
+                    0.5 * wind.y + wobble.y,
+                    -SNOW_SPEED * speed,
+                )
+            }
+            Precipitation::None => Vec3::ZERO,
+        }
+    }
+
+    fn update_particles(
+        &mut self,
+        dt: f32,
+        center: Vec3,
+        universe: &mut Universe,
+        resources: &Resources,
+    ) {
+        let layers = match self.current.precipitation {
+            Precipitation::None => 0,
+            _ if !self.show_particles => 0,
+            _ => (self.current.intensity * LAYERS as f32).ceil() as usize,
+        };
+        for layer in 0..LAYERS {
+            let wanted = (layer < layers).then(|| self.current.precipitation);
+            // the universe drops its overlays when the terrain is reset
+            let current = self.built[layer] == wanted
+                && (wanted.is_none() || universe.has_overlay_mesh(OVERLAY_OWNER, layer_key(layer)));
+            if current {
+                continue;
+            }
+            let (geometry, position) = match wanted.and_then(|p| Self::geometry(universe, p, layer))
+            {
+                Some((geometry, position)) => (Some(geometry), position),
+                None => (None, Vec3::ZERO),
+            };
+            let key = layer_key(layer);
+            if universe.set_overlay_mesh(OVERLAY_OWNER, key, position, geometry, resources) {
+                self.built[layer] = wanted;
+            }
+        }
+
+        // the area starts at a multiple of the cell, so scrolling it within one cell leaves
+        // the repeated particles in place as the camera moves
+        let snap = |x: f32| (x / CELL_SIZE).floor() * CELL_SIZE;
+        let origin = Vec3::new(
+            snap(center.x - AREA_RADIUS) - CELL_SIZE,
+            snap(center.y - AREA_RADIUS) - CELL_SIZE,
+            center.z - GROUND_MARGIN - AREA_HEIGHT,
+        );
+        for layer in 0..layers {
+            let scroll = self.scroll[layer] + self.velocity(layer) * dt;
+            self.scroll[layer] = Vec3::new(
+                scroll.x.rem_euclid(CELL_SIZE),
+                scroll.y.rem_euclid(CELL_SIZE),
+                scroll.z.rem_euclid(AREA_HEIGHT),
+            );
+            universe.set_overlay_offset(
+                OVERLAY_OWNER,
+                layer_key(layer),
+                origin + self.scroll[layer],
+            );
+        }
+    }
+
+    /// The mesh of `layer`, built at the origin from a cell of particles repeated
+    /// `cells_per_side` times along x and y, and twice along z for the wrap of the fall.
+    #[profiling::function]
+    fn geometry(
+        universe: &Universe,
+        precipitation: Precipitation,
+        layer: usize,
+    ) -> Option<(ChunkGeometry, Vec3)> {
+        let name = match precipitation {
+            Precipitation::Rain => "rain",
+            Precipitation::Snow => "snow",
+            Precipitation::None => return None,
+        };
+        let material = match universe.get_material_names().iter().position(|m| m == name) {
+            Some(material) => material as u16,
+            None => {
+                log::error!("Weather material {} not found", name);
+                return None;
+            }
+        };
+        let mut rng = StdRng::seed_from_u64(layer as u64);
+        let cell: Vec<Vec3> = (0..PARTICLES_PER_CELL)
+            .map(|_| {
+                Vec3::new(
+                    rng.gen_range(0.0..CELL_SIZE),
+                    rng.gen_range(0.0..CELL_SIZE),
+                    rng.gen_range(0.0..AREA_HEIGHT),
+                )
+            })
+            .collect();
+        let cells = cells_per_side();
+        let mut quads = OverlayQuads::default();
+        for copy in 0..2 {
+            for y in 0..cells {
+                for x in 0..cells {
+                    let offset = Vec3::new(
+                        x as f32 * CELL_SIZE,
+                        y as f32 * CELL_SIZE,
+                        copy as f32 * AREA_HEIGHT,
+                    );
+                    for particle in &cell {
+                        Self::add_particle(&mut quads, material, precipitation, offset + *particle);
+                    }
+                }
+            }
+        }
+        quads.build()
+    }
+
+    // rain streaks are two crossed vertical quads, snow flakes add a horizontal one, both
+    // sides of each so they show from every direction
+    fn add_particle(
+        quads: &mut OverlayQuads,
+        material: u16,
+        precipitation: Precipitation,
+        position: Vec3,
+    ) {
+        let (half_width, height) = match precipitation {
+            Precipitation::Rain => (RAIN_STREAK_WIDTH / 2., RAIN_STREAK_LENGTH),
+            _ => (SNOW_FLAKE_SIZE / 2., SNOW_FLAKE_SIZE),
+        };
+        let up = Vec3::Z * height;
+        for across in [Vec3::X, Vec3::Y] {
+            let side = across * half_width;
+            let normal = across.cross(Vec3::Z);
+            let corners = [
+                position - side,
+                position + side,
+                position + side + up,
+                position - side + up,
+            ];
+            quads.add(material, corners, normal, across);
+            let [a, b, c, d] = corners;
+            quads.add(material, [b, a, d, c], -normal, -across);
+        }
+        if precipitation == Precipitation::Snow {
+            let (x, y) = (Vec3::X * half_width, Vec3::Y * half_width);
+            let center = position + up / 2.;
+            let corners = [
+                center - x - y,
+                center + x - y,
+                center + x + y,
+                center - x + y,
+            ];
+            quads.add(material, corners, Vec3::Z, Vec3::X);
+            let [a, b, c, d] = corners;
+            quads.add(material, [b, a, d, c], -Vec3::Z, -Vec3::X);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("weather"))
+            .id_source("weather")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut preset = self.preset;
+                egui::ComboBox::from_label(tr!("weather.preset"))
+                    .selected_text(format!("{}", preset))
+                    .show_ui(ui, |ui| {
+                        for p in WeatherPreset::ALL {
+                            ui.selectable_value(&mut preset, p, format!("{}", p));
+                        }
+                    });
+                if preset != self.preset {
+                    self.set_preset(preset);
+                }
+                ui.add(
+                    egui::Slider::new(&mut self.transition_time, 0.1..=30.0)
+                        .text(tr!("weather.transition")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.target.intensity, 0.0..=1.0)
+                        .text(tr!("weather.intensity")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.target.wind.x, -20.0..=20.0)
+                        .text(tr!("weather.wind_x")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.target.wind.y, -20.0..=20.0)
+                        .text(tr!("weather.wind_y")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.target.fog_density, 0.0..=1.0)
+                        .text(tr!("weather.fog")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.target.overcast, 0.0..=1.0)
+                        .text(tr!("weather.overcast")),
+                );
+                ui.checkbox(&mut self.show_particles, tr!("weather.show_particles"));
+                let layers = self.built.iter().filter(|p| p.is_some()).count();
+                let precipitation = match self.current.precipitation {
+                    Precipitation::None => tr!("weather.precipitation.none"),
+                    Precipitation::Rain => tr!("weather.precipitation.rain"),
+                    Precipitation::Snow => tr!("weather.precipitation.snow"),
+                };
+                ui.label(tr!(
+                    "weather.particles",
+                    precipitation = precipitation,
+                    count = layers * PARTICLES_PER_CELL * (cells_per_side() as usize).pow(2) * 2
+                ));
+            });
+    }
+}
@@ -0,0 +1,129 @@
+use building_blocks::{
+    mesh::{greedy_quads, GreedyQuadsBuffer, RIGHT_HANDED_Y_UP_CONFIG},
+    prelude::*,
+};
+use glam::Vec2;
+
+use crate::env::simulation::{MaterialVoxel, MaterialVoxels};
+
+/// Outward normals of the four sides of the terrain.
+pub const SKIRT_SIDES: [[i32; 2]; 4] = [[-1, 0], [1, 0], [0, -1], [0, 1]];
+
+/// Keeps the camera over the terrain and hides the void past its edge. The skirt is made of
+/// cliff walls continuing the sides of the terrain below its bottom layer, each column in its
+/// bottom material, meshed like the chunks but only on the outward faces.
+pub struct WorldEdgeResource {
+    pub clamp_camera: bool,
+    /// How far past the terrain edge the camera target may go, negative keeps it inside.
+    pub camera_margin: f32, // voxels
+    /// Depth of the skirt below the terrain, 0 for none.
+    pub skirt_depth: u32, // voxels
+}
+
+impl Default for WorldEdgeResource {
+    fn default() -> Self {
+        Self {
+            clamp_camera: true,
+            camera_margin: 8.,
+            skirt_depth: 48,
+        }
+    }
+}
+
+impl WorldEdgeResource {
+    /// XY rectangle the camera target is kept in, `bounds` grown by the margin.
+    pub fn camera_bounds(&self, bounds: &Extent3i) -> Option<[Vec2; 2]> {
+        if !self.clamp_camera || bounds.shape.x() <= 0 || bounds.shape.y() <= 0 {
+            return None;
+        }
+        let min = bounds.minimum;
+        let max = bounds.least_upper_bound();
+        // a negative margin can shrink the rectangle down to the center, no further
+        let margin = self
+            .camera_margin
+            .max(-(bounds.shape.x().min(bounds.shape.y()) as f32) / 2.);
+        Some([
+            Vec2::new(min.x() as f32, min.y() as f32) - Vec2::splat(margin),
+            Vec2::new(max.x() as f32, max.y() as f32) + Vec2::splat(margin),
+        ])
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("world_edge"))
+            .id_source("world edge")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.clamp_camera, tr!("world_edge.clamp_camera"));
+                ui.add(
+                    egui::Slider::new(&mut self.camera_margin, -32.0..=64.)
+                        .text(tr!("world_edge.camera_margin")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.skirt_depth, 0..=256)
+                        .text(tr!("world_edge.skirt_depth")),
+                );
+            });
+    }
+}
+
+/// Interior extent of the skirt wall on `side` of `bounds`, one voxel thick.
+pub fn skirt_extent(bounds: &Extent3i, side: [i32; 2], depth: u32) -> Extent3i {
+    let min = bounds.minimum;
+    let max = bounds.least_upper_bound() - Point3i::ONES;
+    let depth = depth as i32;
+    let (minimum, shape) = match side {
+        [-1, 0] => (
+            PointN([min.x(), min.y(), min.z() - depth]),
+            PointN([1, bounds.shape.y(), depth]),
+        ),
+        [1, 0] => (
+            PointN([max.x(), min.y(), min.z() - depth]),
+            PointN([1, bounds.shape.y(), depth]),
+        ),
+        [0, -1] => (
+            PointN([min.x(), min.y(), min.z() - depth]),
+            PointN([bounds.shape.x(), 1, depth]),
+        ),
+        _ => (
+            PointN([min.x(), max.y(), min.z() - depth]),
+            PointN([bounds.shape.x(), 1, depth]),
+        ),
+    };
+    Extent3i::from_min_and_shape(minimum, shape)
+}
+
+/// The voxels of one skirt wall, padded by one like the chunks given to the mesher.
+pub fn skirt_voxels(voxels: &MaterialVoxels, extent: &Extent3i) -> Array3x1<MaterialVoxel> {
+    let mut padded = Array3x1::fill(extent.padded(1), MaterialVoxel::empty());
+    let bottom = extent.least_upper_bound().z();
+    let column_extent = Extent3i::from_min_and_shape(
+        PointN([extent.minimum.x(), extent.minimum.y(), bottom]),
+        PointN([extent.shape.x(), extent.shape.y(), 1]),
+    );
+    for column in column_extent.iter_points() {
+        let material = voxels.get_point(0, column);
+        if material.is_empty() {
+            continue;
+        }
+        let wall = Extent3i::from_min_and_shape(
+            PointN([column.x(), column.y(), extent.minimum.z()]),
+            PointN([1, 1, extent.shape.z()]),
+        );
+        padded.fill_extent(&wall, material);
+    }
+    padded
+}
+
+/// Greedy quads of a skirt wall, keeping only the faces looking out of the world.
+pub fn skirt_quads(padded: &Array3x1<MaterialVoxel>, side: [i32; 2]) -> GreedyQuadsBuffer {
+    let extent = *padded.extent();
+    let mut buffer = GreedyQuadsBuffer::new(extent, RIGHT_HANDED_Y_UP_CONFIG.quad_groups());
+    greedy_quads(padded, &extent, &mut buffer);
+    let outward = PointN([side[0], side[1], 0]);
+    for group in buffer.quad_groups.iter_mut() {
+        if group.face.signed_normal() != outward {
+            group.quads.clear();
+        }
+    }
+    buffer
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use glam::Vec3;
 use rafx::{
     api::RafxIndexType,
     assets::MaterialInstanceAsset,
@@ -9,13 +10,110 @@ use rafx::{
 };
 use rafx_plugins::{
     features::mesh_adv::{
-        MeshAdvUntexturedRenderFeatureFlag as MeshUntexturedRenderFeatureFlag, MeshVertexFull,
+        MeshAdvUntexturedRenderFeatureFlag as MeshUntexturedRenderFeatureFlag, MeshVertexPosition,
     },
     phases::{DepthPrepassRenderPhase, OpaqueRenderPhase, WireframeRenderPhase},
 };
 
 pub use super::buffer_upload::BufferUploaderConfig;
 
+/// `MeshVertexFull` plus the ambient occlusion the chunk mesher bakes per vertex, in [0, 1]
+/// where 1 is unoccluded. Bound as the `AO` attribute, which the `mesh_adv` shaders of
+/// rafx-plugins ignore until their vertex stage declares it and scales the ambient term.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct DynMeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 3],
+    pub binormal: [f32; 3],
+    pub tex_coord: [f32; 2],
+    pub ao: f32,
+}
+
+/// Layout of the vertex buffers of a dyn mesh.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DynMeshVertexFormat {
+    /// `DynMeshVertex` and `MeshVertexPosition`.
+    Full,
+    /// `DynMeshVertexCompact` and `DynMeshVertexPositionCompact`, for meshes with integer
+    /// positions and tex coords, like the cubic chunks.
+    Compact,
+}
+
+impl DynMeshVertexFormat {
+    pub fn vertex_full_size(self) -> usize {
+        match self {
+            DynMeshVertexFormat::Full => std::mem::size_of::<DynMeshVertex>(),
+            DynMeshVertexFormat::Compact => std::mem::size_of::<DynMeshVertexCompact>(),
+        }
+    }
+
+    pub fn vertex_position_size(self) -> usize {
+        match self {
+            DynMeshVertexFormat::Full => std::mem::size_of::<MeshVertexPosition>(),
+            DynMeshVertexFormat::Compact => std::mem::size_of::<DynMeshVertexPositionCompact>(),
+        }
+    }
+}
+
+/// `DynMeshVertex` in 28 bytes instead of 60. Positions are 16 bit integers relative to the
+/// mesh origin, normals, tangents and binormals are signed normalized bytes (exact for the
+/// axis aligned faces of voxels), tex coords 16 bit integers and the ambient occlusion a
+/// normalized byte. The vertex fetch converts them all to floats, so the layout binds to the
+/// same shader inputs as the full vertex.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct DynMeshVertexCompact {
+    pub position: [i16; 4],
+    pub normal: [i8; 4],
+    pub tangent: [i8; 4],
+    pub binormal: [i8; 4],
+    pub tex_coord: [i16; 2],
+    pub ao: [u8; 4],
+}
+
+impl DynMeshVertexCompact {
+    pub fn pack(vertex: &DynMeshVertex, origin: Vec3) -> Self {
+        let position = Vec3::from(vertex.position) - origin;
+        let direction = |v: [f32; 3]| {
+            let snorm = |x: f32| (x.max(-1.).min(1.) * 127.).round() as i8;
+            [snorm(v[0]), snorm(v[1]), snorm(v[2]), 0]
+        };
+        Self {
+            position: [
+                position.x.round() as i16,
+                position.y.round() as i16,
+                position.z.round() as i16,
+                1,
+            ],
+            normal: direction(vertex.normal),
+            tangent: direction(vertex.tangent),
+            binormal: direction(vertex.binormal),
+            tex_coord: [
+                vertex.tex_coord[0].round() as i16,
+                vertex.tex_coord[1].round() as i16,
+            ],
+            ao: [(vertex.ao.max(0.).min(1.) * 255.).round() as u8, 0, 0, 0],
+        }
+    }
+}
+
+/// Position only counterpart of `DynMeshVertexCompact`, for the depth passes.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct DynMeshVertexPositionCompact {
+    pub position: [i16; 4],
+}
+
+impl From<&DynMeshVertexCompact> for DynMeshVertexPositionCompact {
+    fn from(vertex: &DynMeshVertexCompact) -> Self {
+        Self {
+            position: vertex.position,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct DynMeshDataPart {
     pub material_instance: MaterialInstanceAsset,
@@ -26,6 +124,8 @@ pub struct DynMeshDataPart {
     pub index_buffer_offset_in_bytes: u32,
     pub index_buffer_size_in_bytes: u32,
     pub index_type: RafxIndexType,
+    /// Always draw with the untextured pass (eg. simplified far geometry).
+    pub untextured: bool,
 }
 
 #[derive(Clone)]
@@ -35,13 +135,22 @@ pub struct DynMeshData {
     pub vertex_position_buffer: Option<Vec<u8>>,
     pub index_buffer: Option<Vec<u8>>,
     pub visible_bounds: VisibleBounds,
+    /// Set by producers that can hash their buffers cheaply, meshes with the same hash are
+    /// uploaded once and shared. `None` opts out of deduplication.
+    pub content_hash: Option<u64>,
+    /// Where the mesh is placed in the world, for prioritizing its upload. `None` uploads it
+    /// before the positioned meshes.
+    pub world_position: Option<Vec3>,
+    pub vertex_format: DynMeshVertexFormat,
+    /// Vertex positions are relative to it, added to the model matrix when drawing.
+    pub origin: Vec3,
 }
 
 impl std::fmt::Display for DynMeshData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let vtx_sz = self.vertex_full_buffer.as_ref().unwrap().len();
         let idx_sz = self.index_buffer.as_ref().unwrap().len();
-        let vtx_q = 4 * std::mem::size_of::<MeshVertexFull>() as u32;
+        let vtx_q = 4 * self.vertex_format.vertex_full_size() as u32;
         let idx_q = 6 * std::mem::size_of::<u16>() as u32;
         write!(
             f,
@@ -87,11 +196,78 @@ pub struct DynMeshPart {
     pub index_buffer_offset_in_bytes: u32,
     pub index_buffer_size_in_bytes: u32,
     pub index_type: RafxIndexType,
+    pub untextured: bool,
 }
 
 pub const PER_MATERIAL_DESCRIPTOR_SET_LAYOUT_INDEX: usize = 1;
 
 impl DynMeshPart {
+    /// Indices of the textured, untextured and wireframe passes of a mesh part material.
+    pub fn pass_indices(material_instance: &MaterialInstanceAsset) -> (usize, usize, usize) {
+        let textured_pass_index = material_instance
+            .material
+            .find_pass_by_name("mesh textured")
+            .expect("could not find `mesh textured` pass in mesh part material");
+
+        let textured_z_pass_index = material_instance
+            .material
+            .find_pass_by_name("mesh textured z")
+            .expect("could not find `mesh textured z` pass in mesh part material");
+
+        assert_eq!(
+            textured_z_pass_index,
+            textured_pass_index + 1,
+            "expected `mesh textured z` to occur after `mesh textured`"
+        );
+
+        let untextured_pass_index = material_instance
+            .material
+            .find_pass_by_name("mesh untextured")
+            .expect("could not find `mesh untextured` pass in mesh part material");
+
+        let untextured_z_pass_index = material_instance
+            .material
+            .find_pass_by_name("mesh untextured z")
+            .expect("could not find `mesh untextured z` pass in mesh part material");
+
+        assert_eq!(
+            untextured_z_pass_index,
+            untextured_pass_index + 1,
+            "expected `mesh untextured z` to occur after `mesh untextured`"
+        );
+
+        let wireframe_pass_index = material_instance
+            .material
+            .find_pass_by_name("mesh wireframe")
+            .expect("could not find `mesh wireframe` pass in mesh part material");
+
+        (
+            textured_pass_index,
+            untextured_pass_index,
+            wireframe_pass_index,
+        )
+    }
+
+    /// The same geometry drawn with another material.
+    pub fn with_material(&self, material_instance: MaterialInstanceAsset) -> Self {
+        let (textured_pass_index, untextured_pass_index, wireframe_pass_index) =
+            Self::pass_indices(&material_instance);
+        Self {
+            material_instance,
+            textured_pass_index,
+            untextured_pass_index,
+            wireframe_pass_index,
+            vertex_full_buffer_offset_in_bytes: self.vertex_full_buffer_offset_in_bytes,
+            vertex_full_buffer_size_in_bytes: self.vertex_full_buffer_size_in_bytes,
+            vertex_position_buffer_offset_in_bytes: self.vertex_position_buffer_offset_in_bytes,
+            vertex_position_buffer_size_in_bytes: self.vertex_position_buffer_size_in_bytes,
+            index_buffer_offset_in_bytes: self.index_buffer_offset_in_bytes,
+            index_buffer_size_in_bytes: self.index_buffer_size_in_bytes,
+            index_type: self.index_type,
+            untextured: self.untextured,
+        }
+    }
+
     pub fn get_material_pass_index(
         &self,
         view: &RenderView,
@@ -99,7 +275,9 @@ impl DynMeshPart {
     ) -> usize {
         if render_phase_index == OpaqueRenderPhase::render_phase_index() {
             let offset = !view.phase_is_relevant::<DepthPrepassRenderPhase>() as usize;
-            return if view.feature_flag_is_relevant::<MeshUntexturedRenderFeatureFlag>() {
+            return if self.untextured
+                || view.feature_flag_is_relevant::<MeshUntexturedRenderFeatureFlag>()
+            {
                 self.untextured_pass_index + offset
             } else {
                 self.textured_pass_index + offset
@@ -143,9 +321,50 @@ pub struct DynMeshInner {
     pub vertex_position_buffer: ResourceArc<BufferResource>,
     pub index_buffer: ResourceArc<BufferResource>,
     pub visible_bounds: VisibleBounds,
+    pub vertex_format: DynMeshVertexFormat,
+    pub origin: Vec3,
 }
 
 #[derive(Clone)]
 pub struct DynMesh {
     pub inner: Arc<DynMeshInner>,
 }
+
+impl DynMesh {
+    /// A mesh sharing these buffers with the material of one part replaced, `None` if there is
+    /// no such part. Other users of this mesh keep the old material.
+    pub fn with_part_material(
+        &self,
+        part_index: usize,
+        material_instance: MaterialInstanceAsset,
+    ) -> Option<DynMesh> {
+        let inner = &self.inner;
+        inner.mesh_parts.get(part_index)?.as_ref()?;
+        let mesh_parts = inner
+            .mesh_parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| {
+                part.as_ref().map(|part| {
+                    let material_instance = if index == part_index {
+                        material_instance.clone()
+                    } else {
+                        part.material_instance.clone()
+                    };
+                    part.with_material(material_instance)
+                })
+            })
+            .collect();
+        Some(DynMesh {
+            inner: Arc::new(DynMeshInner {
+                mesh_parts,
+                vertex_full_buffer: inner.vertex_full_buffer.clone(),
+                vertex_position_buffer: inner.vertex_position_buffer.clone(),
+                index_buffer: inner.index_buffer.clone(),
+                visible_bounds: inner.visible_bounds,
+                vertex_format: inner.vertex_format,
+                origin: inner.origin,
+            }),
+        })
+    }
+}
@@ -1,10 +1,14 @@
-use std::{ops::Deref, sync::Arc};
+use std::{
+    ops::Deref,
+    sync::{Arc, Weak},
+};
 
 use crossbeam_channel::{Receiver, Sender};
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet};
+use glam::Vec3;
 use rafx::{
-    api::{RafxBuffer, RafxDeviceContext, RafxError, RafxQueue, RafxResourceType},
-    assets::AssetManager,
+    api::{RafxBuffer, RafxDeviceContext, RafxError, RafxIndexType, RafxQueue, RafxResourceType},
+    assets::{AssetManager, MaterialInstanceAsset},
     base::{
         memory::force_to_static_lifetime,
         slab::{DropSlab, GenericDropSlabKey},
@@ -15,11 +19,56 @@ use rafx::{
 pub use super::buffer_upload::BufferUploaderConfig;
 use super::{
     buffer_upload::{BufferUploadId, BufferUploadResult, BufferUploader},
-    DynMesh, DynMeshData, DynMeshInner, DynMeshPart,
+    DynMesh, DynMeshData, DynMeshInner, DynMeshPart, DynMeshVertexFormat,
 };
 
+/// What has to match besides the content hash for two meshes to share buffers. The hash alone
+/// can collide, and a collision would show the wrong mesh, this at least catches the ones with
+/// different sizes or parts, which then upload separately.
+#[derive(Clone, PartialEq, Debug)]
+struct DynMeshLayout {
+    buffer_sizes: [usize; 3],
+    parts: Vec<([u32; 6], RafxIndexType, bool)>,
+    vertex_format: DynMeshVertexFormat,
+    origin: Vec3,
+}
+
+impl DynMeshLayout {
+    fn of(mesh_data: &DynMeshData) -> Self {
+        let size = |buffer: &Option<Vec<u8>>| buffer.as_ref().map_or(0, |buffer| buffer.len());
+        Self {
+            buffer_sizes: [
+                size(&mesh_data.vertex_full_buffer),
+                size(&mesh_data.vertex_position_buffer),
+                size(&mesh_data.index_buffer),
+            ],
+            parts: mesh_data
+                .mesh_parts
+                .iter()
+                .map(|part| {
+                    (
+                        [
+                            part.vertex_full_buffer_offset_in_bytes,
+                            part.vertex_full_buffer_size_in_bytes,
+                            part.vertex_position_buffer_offset_in_bytes,
+                            part.vertex_position_buffer_size_in_bytes,
+                            part.index_buffer_offset_in_bytes,
+                            part.index_buffer_size_in_bytes,
+                        ],
+                        part.index_type,
+                        part.untextured,
+                    )
+                })
+                .collect(),
+            vertex_format: mesh_data.vertex_format,
+            origin: mesh_data.origin,
+        }
+    }
+}
+
 struct DynMeshUpload {
     pub mesh_data: DynMeshData,
+    pub content_hash: Option<u64>,
     pub vertex_full_upload_id: BufferUploadId,
     pub vertex_full_rx: Receiver<BufferUploadResult>,
     pub vertex_full_buffer: Option<RafxBuffer>,
@@ -35,7 +84,13 @@ struct DynMeshUpload {
 }
 
 enum DynMeshState {
+    /// Waiting for upload budget, see `DynMeshManager::upload_budget`. The ticket tells the
+    /// current queue entry of the handle from stale ones.
+    Queued(u64, DynMeshData, Option<DynMesh>),
     Uploading(DynMeshUpload, Option<DynMesh>),
+    /// Identical data is being uploaded for another handle, its mesh is shared once complete.
+    /// The data is kept in case that upload fails or gets replaced.
+    Waiting(u64, DynMeshData, Option<DynMesh>),
     Completed(DynMesh),
     UploadError,
 }
@@ -74,8 +129,35 @@ pub enum DynMeshCommandResults {
     },
 }
 
+const DEFAULT_UPLOAD_BUDGET: usize = 8 * 1024 * 1024; // bytes per frame
+
+/// Owns the GPU buffers of dynamic meshes. Data with a `content_hash` is deduplicated: adding
+/// or updating a handle with data identical to a live mesh shares that mesh's buffers instead
+/// of uploading them again. Shared meshes are reference counted through `DynMesh`, the
+/// buffers are freed when no handle uses them anymore.
+///
+/// New data is queued and at most `upload_budget` bytes start uploading per frame, meshes
+/// nearest to `upload_focus` first, so big terrain resets are spread over several frames.
 pub struct DynMeshManager {
+    /// Bytes of mesh data to start uploading per frame, 0 for no limit. At least one mesh
+    /// starts every frame, however large.
+    pub upload_budget: usize,
+    /// Applied to a limited `upload_budget`, set by the remesh throttle on slow frames.
+    pub upload_budget_scale: f32,
+    /// World space point the queued uploads are sorted by, usually where the camera looks at.
+    pub upload_focus: Vec3,
+    /// Keeps new uploads queued, eg. while the window is being resized. Uploads already
+    /// started still complete.
+    pub uploads_paused: bool,
+    queue: Vec<(u64, DynMeshHandle)>,
+    next_ticket: u64,
+    uploaded_last_frame: usize,
     storage: DropSlab<DynMeshState>,
+    shared: FnvHashMap<u64, (DynMeshLayout, Weak<DynMeshInner>)>,
+    // handles waiting for an upload in flight, by content hash
+    waiting: FnvHashMap<u64, (DynMeshLayout, Vec<DynMeshHandle>)>,
+    hash_collisions: u64,
+    dedupe_hits: u64,
     cmd_in_tx: Sender<DynMeshCommand>,
     cmd_in_rx: Receiver<DynMeshCommand>,
     cmd_out_tx: Sender<DynMeshCommandResults>,
@@ -100,7 +182,18 @@ impl DynMeshManager {
         let (vertex_position_tx, vertex_position_rx) = crossbeam_channel::unbounded();
         let (index_tx, index_rx) = crossbeam_channel::unbounded();
         Self {
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            upload_budget_scale: 1.,
+            upload_focus: Vec3::ZERO,
+            uploads_paused: false,
+            queue: vec![],
+            next_ticket: 0,
+            uploaded_last_frame: 0,
             storage: Default::default(),
+            shared: Default::default(),
+            waiting: Default::default(),
+            hash_collisions: 0,
+            dedupe_hits: 0,
             cmd_in_tx,
             cmd_in_rx,
             cmd_out_tx,
@@ -146,30 +239,180 @@ impl DynMeshManager {
         (self.cmd_in_tx.clone(), self.cmd_out_rx.clone())
     }
 
+    /// Reuses a live or in flight mesh with the same content, or queues the data for upload.
+    fn prepare(
+        &mut self,
+        mut mesh_data: DynMeshData,
+        old_dyn_mesh: Option<DynMesh>,
+    ) -> RafxResult<DynMeshState> {
+        if let Some(hash) = mesh_data.content_hash {
+            let layout = DynMeshLayout::of(&mesh_data);
+            let shared = self
+                .shared
+                .get(&hash)
+                .and_then(|(shared_layout, inner)| Some((shared_layout, inner.upgrade()?)));
+            let waiting = self
+                .waiting
+                .get(&hash)
+                .map(|(waiting_layout, _)| waiting_layout);
+            match (shared, waiting) {
+                (Some((shared_layout, inner)), _) if *shared_layout == layout => {
+                    self.dedupe_hits += 1;
+                    return Ok(DynMeshState::Completed(DynMesh { inner }));
+                }
+                (None, Some(waiting_layout)) if *waiting_layout == layout => {
+                    self.dedupe_hits += 1;
+                    return Ok(DynMeshState::Waiting(hash, mesh_data, old_dyn_mesh));
+                }
+                (None, None) => {}
+                _ => {
+                    // same hash, different mesh: keep it out of the sharing altogether
+                    self.hash_collisions += 1;
+                    log::warn!("Dyn mesh content hash collision on {:x}", hash);
+                    mesh_data.content_hash = None;
+                }
+            }
+        }
+
+        match (
+            &mesh_data.vertex_full_buffer,
+            &mesh_data.vertex_position_buffer,
+            &mesh_data.index_buffer,
+        ) {
+            (Some(vertex_full_data), Some(vertex_position_data), Some(index_data)) => {
+                if vertex_full_data.is_empty()
+                    || vertex_position_data.is_empty()
+                    || index_data.is_empty()
+                {
+                    return Err(RafxError::StringError(
+                        "Dyn mesh data does not contain data".to_string(),
+                    ));
+                }
+            }
+            _ => {
+                return Err(RafxError::StringError(
+                    "Dyn mesh data is not initialized".to_string(),
+                ))
+            }
+        }
+
+        // identical data arriving while this one is queued waits for it
+        if let Some(hash) = mesh_data.content_hash {
+            self.waiting
+                .entry(hash)
+                .or_insert_with(|| (DynMeshLayout::of(&mesh_data), vec![]));
+        }
+        self.next_ticket += 1;
+        Ok(DynMeshState::Queued(
+            self.next_ticket,
+            mesh_data,
+            old_dyn_mesh,
+        ))
+    }
+
+    fn upload_size(mesh_data: &DynMeshData) -> usize {
+        [
+            &mesh_data.vertex_full_buffer,
+            &mesh_data.vertex_position_buffer,
+            &mesh_data.index_buffer,
+        ]
+        .iter()
+        .map(|buffer| buffer.as_ref().map_or(0, |buffer| buffer.len()))
+        .sum()
+    }
+
+    /// Meshes without a world position go first, they are not terrain.
+    fn upload_distance(&self, mesh_data: &DynMeshData) -> f32 {
+        mesh_data.world_position.map_or(0., |position| {
+            let center = position + mesh_data.visible_bounds.bounding_sphere.position;
+            center.distance(self.upload_focus)
+        })
+    }
+
+    fn queued_data(&self, ticket: u64, handle: &DynMeshHandle) -> Option<&DynMeshData> {
+        match self.get(handle) {
+            DynMeshState::Queued(queued_ticket, mesh_data, _) if *queued_ticket == ticket => {
+                Some(mesh_data)
+            }
+            _ => None,
+        }
+    }
+
+    /// Starts the queued uploads nearest to `upload_focus`, until the budget is spent.
+    #[profiling::function]
+    fn start_queued_uploads(&mut self) {
+        let mut queue = std::mem::take(&mut self.queue);
+        // drop entries of handles that got other data meanwhile
+        queue.retain(|(ticket, handle)| self.queued_data(*ticket, handle).is_some());
+        let mut queue: Vec<_> = queue
+            .into_iter()
+            .map(|(ticket, handle)| {
+                let distance = self.upload_distance(self.queued_data(ticket, &handle).unwrap());
+                (distance, ticket, handle)
+            })
+            .collect();
+        queue.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let budget = (self.upload_budget as f32 * self.upload_budget_scale) as usize;
+        let mut uploaded = 0;
+        let mut started = 0;
+        for (_, ticket, handle) in &queue {
+            let size = Self::upload_size(self.queued_data(*ticket, handle).unwrap());
+            if self.upload_budget > 0 && started > 0 && uploaded + size > budget {
+                break;
+            }
+            started += 1;
+            uploaded += size;
+            let (mesh_data, old_dyn_mesh) =
+                match std::mem::replace(self.get_mut(handle), DynMeshState::UploadError) {
+                    DynMeshState::Queued(_, mesh_data, old_dyn_mesh) => (mesh_data, old_dyn_mesh),
+                    _ => unreachable!(),
+                };
+            let content_hash = mesh_data.content_hash;
+            match self.start_upload(mesh_data, old_dyn_mesh) {
+                Ok(mesh_state) => self.install(handle, mesh_state),
+                Err(err) => {
+                    log::error!("Dyn mesh {} upload failed: {}", handle, err);
+                    if let Some(hash) = content_hash {
+                        self.restart_waiters(hash);
+                    }
+                }
+            }
+        }
+        self.uploaded_last_frame = uploaded;
+
+        // failed uploads may have queued their waiters meanwhile
+        let requeued = std::mem::take(&mut self.queue);
+        self.queue = queue
+            .into_iter()
+            .skip(started)
+            .map(|(_, ticket, handle)| (ticket, handle))
+            .chain(requeued)
+            .collect();
+    }
+
+    /// Meshes waiting for upload budget and their size in bytes, bytes started last frame.
+    pub fn upload_queue_stats(&self) -> (usize, usize, usize) {
+        let (count, bytes) = self
+            .queue
+            .iter()
+            .filter_map(|(ticket, handle)| self.queued_data(*ticket, handle))
+            .fold((0, 0), |(count, bytes), mesh_data| {
+                (count + 1, bytes + Self::upload_size(mesh_data))
+            });
+        (count, bytes, self.uploaded_last_frame)
+    }
+
     #[profiling::function]
     fn start_upload(
         &mut self,
         mut mesh_data: DynMeshData,
-        handle: Option<&DynMeshHandle>,
+        old_dyn_mesh: Option<DynMesh>,
     ) -> RafxResult<DynMeshState> {
-        if mesh_data.vertex_full_buffer.is_none()
-            || mesh_data.vertex_position_buffer.is_none()
-            || mesh_data.index_buffer.is_none()
-        {
-            return Err(RafxError::StringError(
-                "Dyn mesh data is not initialized".to_string(),
-            ));
-        }
         let vertex_full_data = std::mem::take(&mut mesh_data.vertex_full_buffer).unwrap();
         let vertex_position_data = std::mem::take(&mut mesh_data.vertex_position_buffer).unwrap();
         let index_data = std::mem::take(&mut mesh_data.index_buffer).unwrap();
 
-        if vertex_full_data.is_empty() || vertex_position_data.is_empty() || index_data.is_empty() {
-            return Err(RafxError::StringError(
-                "Dyn mesh data does not contain data".to_string(),
-            ));
-        }
-
         let uploader = self.uploader.as_ref().unwrap();
         let vertex_full_upload_id = uploader.upload_buffer(
             RafxResourceType::VERTEX_BUFFER,
@@ -187,17 +430,12 @@ impl DynMeshManager {
             self.index_tx.clone(),
         )?;
 
-        let old_dyn_mash = handle.and_then(|handle| {
-            if let DynMeshState::Completed(dyn_mesh) = self.get(handle) {
-                Some(dyn_mesh.clone())
-            } else {
-                None
-            }
-        });
+        let content_hash = mesh_data.content_hash;
 
         Ok(DynMeshState::Uploading(
             DynMeshUpload {
                 mesh_data,
+                content_hash,
                 vertex_full_upload_id,
                 vertex_full_rx: self.vertex_full_rx.clone(),
                 vertex_full_buffer: None,
@@ -211,10 +449,153 @@ impl DynMeshManager {
                 index_buffer: None,
                 index_buffer_uploaded: false,
             },
-            old_dyn_mash,
+            old_dyn_mesh,
         ))
     }
 
+    /// Replaces the state of `handle`, registering its upload or its wait.
+    fn install(&mut self, handle: &DynMeshHandle, mesh_state: DynMeshState) {
+        match &mesh_state {
+            DynMeshState::Uploading(upload, _) => {
+                self.vertex_full_uploads
+                    .insert(upload.vertex_full_upload_id.clone(), handle.clone());
+                self.vertex_position_uploads
+                    .insert(upload.vertex_position_upload_id.clone(), handle.clone());
+                self.index_uploads
+                    .insert(upload.index_upload_id.clone(), handle.clone());
+            }
+            DynMeshState::Waiting(hash, mesh_data, _) => {
+                self.waiting
+                    .entry(*hash)
+                    .or_insert_with(|| (DynMeshLayout::of(mesh_data), vec![]))
+                    .1
+                    .push(handle.clone());
+            }
+            DynMeshState::Queued(ticket, _, _) => {
+                self.queue.push((*ticket, handle.clone()));
+            }
+            _ => {}
+        }
+        let old = std::mem::replace(self.get_mut(handle), mesh_state);
+        self.abandon(old);
+    }
+
+    fn fail_upload(&mut self, handle: &DynMeshHandle) {
+        let old = std::mem::replace(self.get_mut(handle), DynMeshState::UploadError);
+        self.abandon(old);
+    }
+
+    /// Forgets the buffer uploads of a replaced state, their results are ignored.
+    fn abandon(&mut self, mesh_state: DynMeshState) {
+        match mesh_state {
+            DynMeshState::Uploading(upload, _) => {
+                self.vertex_full_uploads
+                    .remove(&upload.vertex_full_upload_id);
+                self.vertex_position_uploads
+                    .remove(&upload.vertex_position_upload_id);
+                self.index_uploads.remove(&upload.index_upload_id);
+                if let Some(hash) = upload.content_hash {
+                    self.restart_waiters(hash);
+                }
+            }
+            // its queue entry goes stale and is dropped on the next update
+            DynMeshState::Queued(_, mesh_data, _) => {
+                if let Some(hash) = mesh_data.content_hash {
+                    self.restart_waiters(hash);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The upload the waiters of `hash` relied on is gone, they upload (or wait) again.
+    fn restart_waiters(&mut self, hash: u64) {
+        let waiters = self
+            .waiting
+            .remove(&hash)
+            .map_or(vec![], |(_, handles)| handles);
+        for handle in waiters {
+            match std::mem::replace(self.get_mut(&handle), DynMeshState::UploadError) {
+                DynMeshState::Waiting(waiting_hash, mesh_data, old_dyn_mesh)
+                    if waiting_hash == hash =>
+                {
+                    match self.prepare(mesh_data, old_dyn_mesh) {
+                        Ok(mesh_state) => self.install(&handle, mesh_state),
+                        Err(err) => log::error!("Dyn mesh {} upload failed: {}", handle, err),
+                    }
+                }
+                mesh_state => *self.get_mut(&handle) = mesh_state,
+            }
+        }
+    }
+
+    fn resolve_waiters(&mut self, hash: u64, dyn_mesh: &DynMesh) {
+        let waiters = self
+            .waiting
+            .remove(&hash)
+            .map_or(vec![], |(_, handles)| handles);
+        for handle in waiters {
+            let mesh_state = self.get_mut(&handle);
+            if matches!(mesh_state, DynMeshState::Waiting(waiting_hash, ..) if *waiting_hash == hash)
+            {
+                *mesh_state = DynMeshState::Completed(dyn_mesh.clone());
+            }
+        }
+    }
+
+    /// Forgets the data and uploads of `handle` before its owner drops it, its buffers are
+    /// freed by `collect_released` unless other handles share them.
+    pub fn release(&mut self, handle: &DynMeshHandle) {
+        self.fail_upload(handle);
+    }
+
+    /// Frees the storage of the dropped handles and the queue entries of released ones.
+    pub fn collect_released(&mut self) {
+        let queue = std::mem::take(&mut self.queue);
+        self.queue = queue
+            .into_iter()
+            .filter(|(ticket, handle)| self.queued_data(*ticket, handle).is_some())
+            .collect();
+        self.storage.process_drops();
+        self.shared.retain(|_, (_, inner)| inner.strong_count() > 0);
+    }
+
+    /// Distinct meshes held by the live handles and the bytes of their GPU buffers, meshes
+    /// shared by several handles count once.
+    pub fn memory_stats(&self) -> (usize, u64) {
+        let mut seen = FnvHashSet::default();
+        let mut bytes = 0;
+        for mesh_state in self.storage.iter() {
+            let dyn_mesh = match mesh_state {
+                DynMeshState::Queued(_, _, dyn_mesh)
+                | DynMeshState::Uploading(_, dyn_mesh)
+                | DynMeshState::Waiting(_, _, dyn_mesh) => dyn_mesh.as_ref(),
+                DynMeshState::Completed(dyn_mesh) => Some(dyn_mesh),
+                DynMeshState::UploadError => None,
+            };
+            if let Some(dyn_mesh) = dyn_mesh {
+                if seen.insert(Arc::as_ptr(&dyn_mesh.inner)) {
+                    let inner = &dyn_mesh.inner;
+                    bytes += [
+                        &inner.vertex_full_buffer,
+                        &inner.vertex_position_buffer,
+                        &inner.index_buffer,
+                    ]
+                    .iter()
+                    .map(|buffer| buffer.get_raw().buffer.buffer_def().size)
+                    .sum::<u64>();
+                }
+            }
+        }
+        (seen.len(), bytes)
+    }
+
+    /// Meshes currently shared by content hash, how many uploads were avoided so far and how
+    /// many hash collisions were caught.
+    pub fn dedupe_stats(&self) -> (usize, u64, u64) {
+        (self.shared.len(), self.dedupe_hits, self.hash_collisions)
+    }
+
     #[profiling::function]
     fn process_upload_results(&mut self, asset_manager: &mut AssetManager) {
         for upload_result in self.vertex_full_rx.try_iter().collect::<Vec<_>>() {
@@ -223,7 +604,11 @@ impl DynMeshManager {
                 BufferUploadResult::UploadDrop(upload_id) => (upload_id, None),
                 BufferUploadResult::UploadComplete(upload_id, buffer) => (upload_id, Some(buffer)),
             };
-            let handle = self.vertex_full_uploads.get(&upload_id).unwrap().clone();
+            let handle = match self.vertex_full_uploads.get(&upload_id) {
+                Some(handle) => handle.clone(),
+                // the handle got new data meanwhile
+                None => continue,
+            };
             if let (Some(buffer), DynMeshState::Uploading(ref mut upload, _)) =
                 (buffer, self.get_mut(&handle))
             {
@@ -235,7 +620,7 @@ impl DynMeshManager {
                     upload_id,
                     handle
                 );
-                let _old = std::mem::replace(self.get_mut(&handle), DynMeshState::UploadError);
+                self.fail_upload(&handle);
             }
             self.vertex_full_uploads.remove(&upload_id);
             self.check_finished_upload(&handle, asset_manager);
@@ -246,11 +631,10 @@ impl DynMeshManager {
                 BufferUploadResult::UploadDrop(upload_id) => (upload_id, None),
                 BufferUploadResult::UploadComplete(upload_id, buffer) => (upload_id, Some(buffer)),
             };
-            let handle = self
-                .vertex_position_uploads
-                .get(&upload_id)
-                .unwrap()
-                .clone();
+            let handle = match self.vertex_position_uploads.get(&upload_id) {
+                Some(handle) => handle.clone(),
+                None => continue,
+            };
             if let (Some(buffer), DynMeshState::Uploading(ref mut upload, _)) =
                 (buffer, self.get_mut(&handle))
             {
@@ -262,7 +646,7 @@ impl DynMeshManager {
                     upload_id,
                     handle
                 );
-                let _old = std::mem::replace(self.get_mut(&handle), DynMeshState::UploadError);
+                self.fail_upload(&handle);
             }
             self.vertex_position_uploads.remove(&upload_id);
             self.check_finished_upload(&handle, asset_manager);
@@ -273,7 +657,10 @@ impl DynMeshManager {
                 BufferUploadResult::UploadDrop(upload_id) => (upload_id, None),
                 BufferUploadResult::UploadComplete(upload_id, buffer) => (upload_id, Some(buffer)),
             };
-            let handle = self.index_uploads.get(&upload_id).unwrap().clone();
+            let handle = match self.index_uploads.get(&upload_id) {
+                Some(handle) => handle.clone(),
+                None => continue,
+            };
             if let (Some(buffer), DynMeshState::Uploading(ref mut upload, _)) =
                 (buffer, self.get_mut(&handle))
             {
@@ -285,7 +672,7 @@ impl DynMeshManager {
                     upload_id,
                     handle
                 );
-                let _old = std::mem::replace(self.get_mut(&handle), DynMeshState::UploadError);
+                self.fail_upload(&handle);
             }
             self.index_uploads.remove(&upload_id);
             self.check_finished_upload(&handle, asset_manager);
@@ -319,45 +706,8 @@ impl DynMeshManager {
                     .iter()
                     .map(|mesh_part| {
                         let material_instance = mesh_part.material_instance.clone();
-
-                        let textured_pass_index = material_instance
-                            .material
-                            .find_pass_by_name("mesh textured")
-                            .expect("could not find `mesh textured` pass in mesh part material");
-
-                        let textured_z_pass_index = material_instance
-                            .material
-                            .find_pass_by_name("mesh textured z")
-                            .expect("could not find `mesh textured z` pass in mesh part material");
-
-                        assert_eq!(
-                            textured_z_pass_index,
-                            textured_pass_index + 1,
-                            "expected `mesh textured z` to occur after `mesh textured`"
-                        );
-
-                        let untextured_pass_index = material_instance
-                            .material
-                            .find_pass_by_name("mesh untextured")
-                            .expect("could not find `mesh untextured` pass in mesh part material");
-
-                        let untextured_z_pass_index = material_instance
-                            .material
-                            .find_pass_by_name("mesh untextured z")
-                            .expect(
-                                "could not find `mesh untextured z` pass in mesh part material",
-                            );
-
-                        assert_eq!(
-                            untextured_z_pass_index,
-                            untextured_pass_index + 1,
-                            "expected `mesh untextured z` to occur after `mesh untextured`"
-                        );
-
-                        let wireframe_pass_index = material_instance
-                            .material
-                            .find_pass_by_name("mesh wireframe")
-                            .expect("could not find `mesh wireframe` pass in mesh part material");
+                        let (textured_pass_index, untextured_pass_index, wireframe_pass_index) =
+                            DynMeshPart::pass_indices(&material_instance);
 
                         Some(DynMeshPart {
                             material_instance,
@@ -375,6 +725,7 @@ impl DynMeshManager {
                             index_buffer_offset_in_bytes: mesh_part.index_buffer_offset_in_bytes,
                             index_buffer_size_in_bytes: mesh_part.index_buffer_size_in_bytes,
                             index_type: mesh_part.index_type,
+                            untextured: mesh_part.untextured,
                         })
                     })
                     .collect();
@@ -385,39 +736,38 @@ impl DynMeshManager {
                     index_buffer,
                     mesh_parts,
                     visible_bounds,
+                    vertex_format: upload.mesh_data.vertex_format,
+                    origin: upload.mesh_data.origin,
                 };
                 let dyn_mesh = DynMesh {
                     inner: Arc::new(inner),
                 };
 
-                let _old = std::mem::replace(mesh_state, DynMeshState::Completed(dyn_mesh));
+                let content_hash = upload.content_hash;
+                let layout = DynMeshLayout::of(&upload.mesh_data);
+                let _old = std::mem::replace(mesh_state, DynMeshState::Completed(dyn_mesh.clone()));
+                if let Some(hash) = content_hash {
+                    self.shared
+                        .insert(hash, (layout, Arc::downgrade(&dyn_mesh.inner)));
+                    self.resolve_waiters(hash, &dyn_mesh);
+                }
             } else {
                 unreachable!();
             }
         }
     }
 
+    /// Starts uploading a new mesh. Prefer `DynMeshCommand::Add` from other threads.
     #[profiling::function]
-    fn add_dyn_mesh(&mut self, mesh_data: DynMeshData) -> RafxResult<DynMeshHandle> {
-        let mesh_state = self.start_upload(mesh_data, None)?;
+    pub fn add_dyn_mesh(&mut self, mesh_data: DynMeshData) -> RafxResult<DynMeshHandle> {
+        let mesh_state = self.prepare(mesh_data, None)?;
 
         self.storage.process_drops();
-        let drop_slab_key = self.storage.allocate(mesh_state);
+        let drop_slab_key = self.storage.allocate(DynMeshState::UploadError);
         let handle = DynMeshHandle {
             key: drop_slab_key.generic_drop_slab_key(),
         };
-
-        let mesh_state = self.storage.get(&drop_slab_key).unwrap();
-        if let DynMeshState::Uploading(upload, _) = mesh_state {
-            self.vertex_full_uploads
-                .insert(upload.vertex_full_upload_id.clone(), handle.clone());
-            self.vertex_position_uploads
-                .insert(upload.vertex_position_upload_id.clone(), handle.clone());
-            self.index_uploads
-                .insert(upload.index_upload_id.clone(), handle.clone());
-        } else {
-            unreachable!();
-        }
+        self.install(&handle, mesh_state);
 
         Ok(handle)
     }
@@ -434,20 +784,110 @@ impl DynMeshManager {
             .unwrap_or_else(|| panic!("DynMeshStorage did not contain handle {:?}.", handle))
     }
 
+    /// False while the buffers of the latest data are still queued or being uploaded.
+    pub fn is_upload_finished(&self, handle: &DynMeshHandle) -> bool {
+        !matches!(
+            self.get(handle),
+            DynMeshState::Queued(..) | DynMeshState::Uploading(..) | DynMeshState::Waiting(..)
+        )
+    }
+
     pub fn get_dyn_mesh(&self, handle: &DynMeshHandle) -> Option<DynMesh> {
         match self.get(handle) {
+            DynMeshState::Queued(_, _, old_dyn_mesh) => old_dyn_mesh.clone(),
             DynMeshState::Uploading(_, old_dyn_mesh) => old_dyn_mesh.clone(),
+            DynMeshState::Waiting(_, _, old_dyn_mesh) => old_dyn_mesh.clone(),
             DynMeshState::Completed(mesh) => Some(mesh.clone()),
             DynMeshState::UploadError => None,
         }
     }
 
+    /// Draws one part of the mesh of `handle` with another material, keeping its buffers. Other
+    /// handles sharing the buffers through deduplication are not affected. While new data for
+    /// the handle is in flight the mesh shown meanwhile changes right away, and so does the
+    /// pending data, which then no longer shares uploads by content hash since its materials
+    /// differ now. The override lasts until the next data for the handle.
+    pub fn set_part_material(
+        &mut self,
+        handle: &DynMeshHandle,
+        part_index: usize,
+        material_instance: MaterialInstanceAsset,
+    ) -> RafxResult<()> {
+        let no_part =
+            || RafxError::StringError(format!("Dyn mesh {} has no part {}", handle, part_index));
+        let override_old = |old_dyn_mesh: &mut Option<DynMesh>| {
+            if let Some(old) = old_dyn_mesh {
+                if let Some(mesh) = old.with_part_material(part_index, material_instance.clone()) {
+                    *old = mesh;
+                }
+            }
+        };
+        let override_data = |mesh_data: &mut DynMeshData| -> RafxResult<Option<u64>> {
+            let part = mesh_data
+                .mesh_parts
+                .get_mut(part_index)
+                .ok_or_else(no_part)?;
+            part.material_instance = material_instance.clone();
+            Ok(mesh_data.content_hash.take())
+        };
+        match std::mem::replace(self.get_mut(handle), DynMeshState::UploadError) {
+            DynMeshState::Completed(dyn_mesh) => {
+                let result = dyn_mesh.with_part_material(part_index, material_instance.clone());
+                *self.get_mut(handle) = DynMeshState::Completed(result.clone().unwrap_or(dyn_mesh));
+                result.map(|_| ()).ok_or_else(no_part)
+            }
+            DynMeshState::Queued(ticket, mut mesh_data, mut old_dyn_mesh) => {
+                override_old(&mut old_dyn_mesh);
+                let result = override_data(&mut mesh_data);
+                *self.get_mut(handle) = DynMeshState::Queued(ticket, mesh_data, old_dyn_mesh);
+                // handles waiting for this upload can't have it anymore
+                if let Ok(Some(hash)) = result {
+                    self.restart_waiters(hash);
+                }
+                result.map(|_| ())
+            }
+            DynMeshState::Uploading(mut upload, mut old_dyn_mesh) => {
+                override_old(&mut old_dyn_mesh);
+                let result = override_data(&mut upload.mesh_data);
+                let hash = upload.content_hash.take();
+                *self.get_mut(handle) = DynMeshState::Uploading(upload, old_dyn_mesh);
+                if result.is_ok() {
+                    if let Some(hash) = hash {
+                        self.restart_waiters(hash);
+                    }
+                }
+                result.map(|_| ())
+            }
+            DynMeshState::Waiting(hash, mut mesh_data, mut old_dyn_mesh) => {
+                override_old(&mut old_dyn_mesh);
+                match override_data(&mut mesh_data) {
+                    // the shared mesh would come with the old materials, upload it separately
+                    Ok(_) => {
+                        let mesh_state = self.prepare(mesh_data, old_dyn_mesh)?;
+                        self.install(handle, mesh_state);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        *self.get_mut(handle) =
+                            DynMeshState::Waiting(hash, mesh_data, old_dyn_mesh);
+                        Err(err)
+                    }
+                }
+            }
+            DynMeshState::UploadError => Err(RafxError::StringError(format!(
+                "Dyn mesh {} failed to upload",
+                handle
+            ))),
+        }
+    }
+
     #[profiling::function]
     pub fn update(&mut self, asset_manager: &mut AssetManager) {
         if let Some(ref mut upload) = self.uploader {
             let _res = upload.update();
         }
         self.process_upload_results(asset_manager);
+        self.shared.retain(|_, (_, inner)| inner.strong_count() > 0);
 
         let mut commands = vec![];
         for cmd in self.cmd_in_rx.try_iter() {
@@ -470,23 +910,10 @@ impl DynMeshManager {
                     handle,
                     data,
                 } => {
-                    let result = match self.start_upload(data, Some(&handle)) {
+                    let old_dyn_mesh = self.get_dyn_mesh(&handle);
+                    let result = match self.prepare(data, old_dyn_mesh) {
                         Ok(mesh_state) => {
-                            if let DynMeshState::Uploading(ref upload, _) = mesh_state {
-                                self.vertex_full_uploads
-                                    .insert(upload.vertex_full_upload_id.clone(), handle.clone());
-                                self.vertex_position_uploads.insert(
-                                    upload.vertex_position_upload_id.clone(),
-                                    handle.clone(),
-                                );
-                                self.index_uploads
-                                    .insert(upload.index_upload_id.clone(), handle.clone());
-                            } else {
-                                unreachable!();
-                            }
-
-                            let old_mesh_state = self.get_mut(&handle);
-                            let _old = std::mem::replace(old_mesh_state, mesh_state);
+                            self.install(&handle, mesh_state);
                             Ok(())
                         }
                         Err(err) => Err(err),
@@ -498,6 +925,39 @@ impl DynMeshManager {
                 }
             }
         }
+
+        if !self.uploads_paused {
+            self.start_queued_uploads();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("mesh_uploads"))
+            .id_source("mesh uploads")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut budget = self.upload_budget as f32 / (1024. * 1024.);
+                if ui
+                    .add(egui::Slider::new(&mut budget, 0.0..=64.).text(tr!("mesh_uploads.budget")))
+                    .changed()
+                {
+                    self.upload_budget = (budget * 1024. * 1024.) as usize;
+                }
+                let (count, bytes, uploaded) = self.upload_queue_stats();
+                ui.label(tr!(
+                    "mesh_uploads.queue",
+                    count = count,
+                    queued = format!("{:.1}", bytes as f32 / (1024. * 1024.)),
+                    started = format!("{:.1}", uploaded as f32 / (1024. * 1024.))
+                ));
+                let (shared, hits, collisions) = self.dedupe_stats();
+                ui.label(tr!(
+                    "mesh_uploads.dedupe",
+                    shared = shared,
+                    hits = hits,
+                    collisions = collisions
+                ));
+            });
     }
 }
 
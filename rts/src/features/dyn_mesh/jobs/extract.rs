@@ -13,9 +13,11 @@ use rafx_plugins::{
 };
 
 use super::*;
+use crate::env::stable_id::{mesh_adv_object_id, StableId, StableIdRegistry};
 
 pub struct DynMeshExtractJob<'extract> {
     world: ResourceRefBorrow<'extract, World>,
+    ids: ResourceRefBorrow<'extract, StableIdRegistry>,
     mesh_render_options: Option<ResourceRefBorrow<'extract, MeshRenderOptions>>,
     asset_manager: AssetManagerExtractRef,
     depth_material: Handle<MaterialAsset>,
@@ -37,6 +39,7 @@ impl<'extract> DynMeshExtractJob<'extract> {
         Arc::new(ExtractJob::new(
             Self {
                 world: extract_context.extract_resources.fetch::<World>(),
+                ids: extract_context.extract_resources.fetch::<StableIdRegistry>(),
                 mesh_render_options: extract_context
                     .extract_resources
                     .try_fetch::<MeshRenderOptions>(),
@@ -81,7 +84,8 @@ impl<'extract> ExtractJobEntryPoints<'extract> for DynMeshExtractJob<'extract> {
             self.mesh_manager
                 .get_dyn_mesh(&render_object_static_data.mesh)
                 .and_then(|dyn_mesh| {
-                    let entry = self.world.entry_ref(context.object_id().into()).unwrap();
+                    let entity = self.ids.entity(StableId::from(context.object_id()))?;
+                    let entry = self.world.entry_ref(entity).unwrap();
                     let transform_component = entry.get_component::<TransformComponent>().unwrap();
                     Some(DynMeshRenderObjectInstanceData {
                         dyn_mesh,
@@ -108,7 +112,7 @@ impl<'extract> ExtractJobEntryPoints<'extract> for DynMeshExtractJob<'extract> {
 
         let mut query = <(Entity, Read<DirectionalLightComponent>)>::query();
         for light in query.iter(world).map(|(e, l)| ExtractedDirectionalLight {
-            object_id: ObjectId::from(*e),
+            object_id: mesh_adv_object_id(*e),
             light: l.clone(),
         }) {
             let next_index = per_view.num_directional_lights;
@@ -118,7 +122,7 @@ impl<'extract> ExtractJobEntryPoints<'extract> for DynMeshExtractJob<'extract> {
 
         let mut query = <(Entity, Read<TransformComponent>, Read<PointLightComponent>)>::query();
         for light in query.iter(world).map(|(e, p, l)| ExtractedPointLight {
-            object_id: ObjectId::from(*e),
+            object_id: mesh_adv_object_id(*e),
             light: l.clone(),
             transform: p.clone(),
         }) {
@@ -129,7 +133,7 @@ impl<'extract> ExtractJobEntryPoints<'extract> for DynMeshExtractJob<'extract> {
 
         let mut query = <(Entity, Read<TransformComponent>, Read<SpotLightComponent>)>::query();
         for light in query.iter(world).map(|(e, p, l)| ExtractedSpotLight {
-            object_id: ObjectId::from(*e),
+            object_id: mesh_adv_object_id(*e),
             light: l.clone(),
             transform: p.clone(),
         }) {
@@ -232,7 +232,7 @@ impl<'prepare> PrepareJobEntryPoints<'prepare> for DynMeshPrepareJob<'prepare> {
             extracted_data.scale,
             extracted_data.rotation,
             extracted_data.translation,
-        );
+        ) * Mat4::from_translation(extracted_data.dyn_mesh.inner.origin);
 
         let model = world_transform.to_cols_array_2d();
         let model_matrix_offset = self.render_object_instance_transforms.push(model);
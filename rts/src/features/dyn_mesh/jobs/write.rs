@@ -9,7 +9,7 @@ use rafx::{
     render_feature_write_job_prelude::*,
 };
 use rafx_plugins::{
-    features::mesh_adv::{MeshVertexFull, MeshVertexPosition},
+    features::mesh_adv::MeshVertexPosition,
     phases::{
         DepthPrepassRenderPhase, OpaqueRenderPhase, ShadowMapRenderPhase, WireframeRenderPhase,
     },
@@ -28,12 +28,13 @@ lazy_static::lazy_static! {
     pub static ref MESH_VERTEX_FULL_LAYOUT : VertexDataSetLayout = {
         use rafx::api::RafxFormat;
 
-        let per_vertex = VertexDataLayout::build_vertex_layout(&MeshVertexFull::default(), RafxVertexAttributeRate::Vertex, |builder, vertex| {
+        let per_vertex = VertexDataLayout::build_vertex_layout(&DynMeshVertex::default(), RafxVertexAttributeRate::Vertex, |builder, vertex| {
             builder.add_member(&vertex.position, "POSITION", RafxFormat::R32G32B32_SFLOAT);
             builder.add_member(&vertex.normal, "NORMAL", RafxFormat::R32G32B32_SFLOAT);
             builder.add_member(&vertex.tangent, "TANGENT", RafxFormat::R32G32B32_SFLOAT);
             builder.add_member(&vertex.binormal, "BINORMAL", RafxFormat::R32G32B32_SFLOAT);
             builder.add_member(&vertex.tex_coord, "TEXCOORD", RafxFormat::R32G32_SFLOAT);
+            builder.add_member(&vertex.ao, "AO", RafxFormat::R32_SFLOAT);
         });
 
         let per_instance = VertexDataLayout::build_vertex_layout(&DynMeshModelMatrix::default(), RafxVertexAttributeRate::Instance,  |builder, vertex| {
@@ -62,6 +63,45 @@ lazy_static::lazy_static! {
 
         VertexDataSetLayout::new(vec![per_vertex, per_instance], RafxPrimitiveTopology::TriangleList)
     };
+
+    pub static ref MESH_VERTEX_COMPACT_LAYOUT : VertexDataSetLayout = {
+        use rafx::api::RafxFormat;
+
+        let per_vertex = VertexDataLayout::build_vertex_layout(&DynMeshVertexCompact::default(), RafxVertexAttributeRate::Vertex, |builder, vertex| {
+            builder.add_member(&vertex.position, "POSITION", RafxFormat::R16G16B16A16_SSCALED);
+            builder.add_member(&vertex.normal, "NORMAL", RafxFormat::R8G8B8A8_SNORM);
+            builder.add_member(&vertex.tangent, "TANGENT", RafxFormat::R8G8B8A8_SNORM);
+            builder.add_member(&vertex.binormal, "BINORMAL", RafxFormat::R8G8B8A8_SNORM);
+            builder.add_member(&vertex.tex_coord, "TEXCOORD", RafxFormat::R16G16_SSCALED);
+            builder.add_member(&vertex.ao, "AO", RafxFormat::R8G8B8A8_UNORM);
+        });
+
+        let per_instance = VertexDataLayout::build_vertex_layout(&DynMeshModelMatrix::default(), RafxVertexAttributeRate::Instance,  |builder, vertex| {
+            builder.add_member(&vertex.model_matrix[0], "MODELMATRIX0", RafxFormat::R32G32B32A32_SFLOAT);
+            builder.add_member(&vertex.model_matrix[1], "MODELMATRIX1", RafxFormat::R32G32B32A32_SFLOAT);
+            builder.add_member(&vertex.model_matrix[2], "MODELMATRIX2", RafxFormat::R32G32B32A32_SFLOAT);
+            builder.add_member(&vertex.model_matrix[3], "MODELMATRIX3", RafxFormat::R32G32B32A32_SFLOAT);
+        });
+
+        VertexDataSetLayout::new(vec![per_vertex, per_instance], RafxPrimitiveTopology::TriangleList)
+    };
+
+    pub static ref MESH_VERTEX_POSITION_COMPACT_LAYOUT : VertexDataSetLayout = {
+        use rafx::api::RafxFormat;
+
+        let per_vertex = VertexDataLayout::build_vertex_layout(&DynMeshVertexPositionCompact::default(), RafxVertexAttributeRate::Vertex, |builder, vertex| {
+            builder.add_member(&vertex.position, "POSITION", RafxFormat::R16G16B16A16_SSCALED);
+        });
+
+        let per_instance = VertexDataLayout::build_vertex_layout(&DynMeshModelMatrix::default(), RafxVertexAttributeRate::Instance,  |builder, vertex| {
+            builder.add_member(&vertex.model_matrix[0], "MODELMATRIX0", RafxFormat::R32G32B32A32_SFLOAT);
+            builder.add_member(&vertex.model_matrix[1], "MODELMATRIX1", RafxFormat::R32G32B32A32_SFLOAT);
+            builder.add_member(&vertex.model_matrix[2], "MODELMATRIX2", RafxFormat::R32G32B32A32_SFLOAT);
+            builder.add_member(&vertex.model_matrix[3], "MODELMATRIX3", RafxFormat::R32G32B32A32_SFLOAT);
+        });
+
+        VertexDataSetLayout::new(vec![per_vertex, per_instance], RafxPrimitiveTopology::TriangleList)
+    };
 }
 
 pub struct DynMeshWriteJob<'write> {
@@ -150,16 +190,25 @@ impl<'write> RenderFeatureWriteJob<'write> for DynMeshWriteJob<'write> {
 
         // Bind the correct pipeline.
 
+        let compact = dyn_mesh.inner.vertex_format == DynMeshVertexFormat::Compact;
         let (mesh_vertex_layout, vertex_buffer, vertex_buffer_offset_in_bytes) =
             if render_phase_index == OpaqueRenderPhase::render_phase_index() {
                 (
-                    &*MESH_VERTEX_FULL_LAYOUT,
+                    if compact {
+                        &*MESH_VERTEX_COMPACT_LAYOUT
+                    } else {
+                        &*MESH_VERTEX_FULL_LAYOUT
+                    },
                     &dyn_mesh.inner.vertex_full_buffer,
                     mesh_part.vertex_full_buffer_offset_in_bytes,
                 )
             } else {
                 (
-                    &*MESH_VERTEX_POSITION_LAYOUT,
+                    if compact {
+                        &*MESH_VERTEX_POSITION_COMPACT_LAYOUT
+                    } else {
+                        &*MESH_VERTEX_POSITION_LAYOUT
+                    },
                     &dyn_mesh.inner.vertex_position_buffer,
                     mesh_part.vertex_position_buffer_offset_in_bytes,
                 )
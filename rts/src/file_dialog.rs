@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use bevy_tasks::{Task, TaskPool, TaskPoolBuilder};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// What a dialog is opened for, each flow remembers its own directory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DialogPurpose {
+    /// Picks the folder a savegame slot is written to.
+    SaveGame,
+    /// Picks a savegame folder to load, in the directory saves go to.
+    LoadGame,
+    TileExport,
+    HeightmapImport,
+    Packfile,
+}
+
+impl DialogPurpose {
+    fn title(&self) -> String {
+        match self {
+            DialogPurpose::SaveGame => tr!("file_dialog.save_game"),
+            DialogPurpose::LoadGame => tr!("file_dialog.load_game"),
+            DialogPurpose::TileExport => tr!("file_dialog.tile_export"),
+            DialogPurpose::HeightmapImport => tr!("file_dialog.heightmap_import"),
+            DialogPurpose::Packfile => tr!("file_dialog.packfile"),
+        }
+    }
+
+    // starting directory the first time, relative to the working directory like the defaults
+    fn default_dir(&self) -> &'static str {
+        match self {
+            DialogPurpose::SaveGame | DialogPurpose::LoadGame => "saves",
+            DialogPurpose::TileExport => "assets/tiles",
+            DialogPurpose::HeightmapImport => ".",
+            DialogPurpose::Packfile => ".",
+        }
+    }
+}
+
+/// The last directory used by each kind of file dialog.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct FileDialogSettings {
+    pub save_game: Option<PathBuf>,
+    pub tile_export: Option<PathBuf>,
+    pub heightmap_import: Option<PathBuf>,
+    pub packfile: Option<PathBuf>,
+}
+
+impl FileDialogSettings {
+    fn dir_mut(&mut self, purpose: DialogPurpose) -> &mut Option<PathBuf> {
+        match purpose {
+            DialogPurpose::SaveGame | DialogPurpose::LoadGame => &mut self.save_game,
+            DialogPurpose::TileExport => &mut self.tile_export,
+            DialogPurpose::HeightmapImport => &mut self.heightmap_import,
+            DialogPurpose::Packfile => &mut self.packfile,
+        }
+    }
+
+    fn dir(&self, purpose: DialogPurpose) -> PathBuf {
+        match purpose {
+            DialogPurpose::SaveGame | DialogPurpose::LoadGame => self.save_game.clone(),
+            DialogPurpose::TileExport => self.tile_export.clone(),
+            DialogPurpose::HeightmapImport => self.heightmap_import.clone(),
+            DialogPurpose::Packfile => self.packfile.clone(),
+        }
+        .unwrap_or_else(|| PathBuf::from(purpose.default_dir()))
+    }
+}
+
+/// Native open and save dialogs. The dialog runs on a background task so the frame keeps
+/// rendering, only one is open at a time. The picked path waits in the resource until the flow
+/// that opened the dialog `take`s it.
+pub struct FileDialogResource {
+    pub settings: FileDialogSettings,
+    task_pool: TaskPool,
+    task: Option<Task<()>>,
+    result_tx: Sender<(DialogPurpose, Option<PathBuf>)>,
+    result_rx: Receiver<(DialogPurpose, Option<PathBuf>)>,
+    picked: Option<(DialogPurpose, PathBuf)>,
+}
+
+impl FileDialogResource {
+    pub fn new(settings: FileDialogSettings) -> Self {
+        let (result_tx, result_rx) = unbounded();
+        Self {
+            settings,
+            task_pool: TaskPoolBuilder::new().num_threads(1).build(),
+            task: None,
+            result_tx,
+            result_rx,
+            picked: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// Opens the dialog for `purpose` in the directory last used for it, `file_name` is the
+    /// suggested name for save dialogs. Does nothing while another dialog is open.
+    pub fn open(&mut self, purpose: DialogPurpose, file_name: Option<&str>) {
+        if self.is_open() {
+            log::warn!("A file dialog is already open");
+            return;
+        }
+        let mut dialog = rfd::AsyncFileDialog::new()
+            .set_title(&purpose.title())
+            .set_directory(&self.settings.dir(purpose));
+        if let Some(file_name) = file_name {
+            dialog = dialog.set_file_name(file_name);
+        }
+        // the futures are created here, some platforms need the dialogs opened on the main thread
+        let result_tx = self.result_tx.clone();
+        self.task = Some(match purpose {
+            DialogPurpose::SaveGame | DialogPurpose::LoadGame => {
+                let picked = dialog.pick_folder();
+                self.task_pool.spawn(async move {
+                    let path = picked.await.map(|handle| handle.path().to_path_buf());
+                    let _result = result_tx.send((purpose, path));
+                })
+            }
+            DialogPurpose::TileExport => {
+                let picked = dialog.add_filter("Tile", &["tile"]).save_file();
+                self.task_pool.spawn(async move {
+                    let path = picked.await.map(|handle| handle.path().to_path_buf());
+                    let _result = result_tx.send((purpose, path));
+                })
+            }
+            DialogPurpose::HeightmapImport => {
+                let picked = dialog
+                    .add_filter("Image", &["png", "jpg", "jpeg", "tga", "bmp", "tiff"])
+                    .pick_file();
+                self.task_pool.spawn(async move {
+                    let path = picked.await.map(|handle| handle.path().to_path_buf());
+                    let _result = result_tx.send((purpose, path));
+                })
+            }
+            DialogPurpose::Packfile => {
+                let picked = dialog.add_filter("Packfile", &["pack"]).pick_file();
+                self.task_pool.spawn(async move {
+                    let path = picked.await.map(|handle| handle.path().to_path_buf());
+                    let _result = result_tx.send((purpose, path));
+                })
+            }
+        });
+    }
+
+    /// Collects the path of a closed dialog and remembers its directory.
+    pub fn update(&mut self) {
+        for (purpose, path) in self.result_rx.try_iter() {
+            self.task = None;
+            let path = match path {
+                Some(path) => path,
+                None => continue,
+            };
+            // a picked folder is remembered itself, a file by the folder holding it
+            let dir = if matches!(purpose, DialogPurpose::SaveGame | DialogPurpose::LoadGame) {
+                Some(path.clone())
+            } else {
+                path.parent().map(Path::to_path_buf)
+            };
+            if dir.is_some() && *self.settings.dir_mut(purpose) != dir {
+                *self.settings.dir_mut(purpose) = dir;
+                let settings = self.settings.clone();
+                Settings::update(|s| s.file_dialogs = settings);
+            }
+            self.picked = Some((purpose, path));
+        }
+    }
+
+    /// The path picked in the last dialog opened for `purpose`, once.
+    pub fn take(&mut self, purpose: DialogPurpose) -> Option<PathBuf> {
+        match self.picked.take() {
+            Some((picked_purpose, path)) if picked_purpose == purpose => Some(path),
+            picked => {
+                self.picked = picked;
+                None
+            }
+        }
+    }
+}
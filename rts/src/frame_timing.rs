@@ -0,0 +1,100 @@
+use std::{collections::VecDeque, time::Duration};
+
+const HISTORY: usize = 120; // frames
+
+// the side that takes this much longer than the other is the bottleneck
+const BOUND_MARGIN: f32 = 1.2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameBound {
+    /// Simulation, UI and extract on the main thread.
+    MainThread,
+    /// Waiting for the render thread to finish the previous frame and for a swapchain image.
+    /// A slow GPU makes this wait longer, but so does render thread work, they can't be told
+    /// apart from here.
+    Render,
+    Balanced,
+}
+
+#[derive(Clone, Copy, Default)]
+struct FrameTiming {
+    main: f32,        // ms
+    render_wait: f32, // ms
+}
+
+/// Splits the frame into main thread work and time spent in `start_rendering_next_frame`.
+/// This is CPU time only: the passes are recorded by the render graph of rafx-plugins and
+/// rafx-api has no timestamp queries, so there is no GPU time or per pass breakdown.
+#[derive(Default)]
+pub struct FrameTimingResource {
+    history: VecDeque<FrameTiming>,
+}
+
+impl FrameTimingResource {
+    pub fn record(&mut self, main: Duration, render_wait: Duration) {
+        self.history.push_back(FrameTiming {
+            main: main.as_secs_f32() * 1000.,
+            render_wait: render_wait.as_secs_f32() * 1000.,
+        });
+        if self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    fn average(&self) -> FrameTiming {
+        let count = self.history.len().max(1) as f32;
+        let sum = self
+            .history
+            .iter()
+            .fold(FrameTiming::default(), |sum, timing| FrameTiming {
+                main: sum.main + timing.main,
+                render_wait: sum.render_wait + timing.render_wait,
+            });
+        FrameTiming {
+            main: sum.main / count,
+            render_wait: sum.render_wait / count,
+        }
+    }
+
+    pub fn bound(&self) -> FrameBound {
+        let average = self.average();
+        if average.render_wait > average.main * BOUND_MARGIN {
+            FrameBound::Render
+        } else if average.main > average.render_wait * BOUND_MARGIN {
+            FrameBound::MainThread
+        } else {
+            FrameBound::Balanced
+        }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("frame_timing"))
+            .id_source("frame timing")
+            .default_open(false)
+            .show(ui, |ui| {
+                let average = self.average();
+                let max = self
+                    .history
+                    .iter()
+                    .fold(FrameTiming::default(), |max, t| FrameTiming {
+                        main: max.main.max(t.main),
+                        render_wait: max.render_wait.max(t.render_wait),
+                    });
+                ui.label(tr!(
+                    "frame_timing.main",
+                    time = format!("{:.2}", average.main),
+                    max = format!("{:.2}", max.main)
+                ));
+                ui.label(tr!(
+                    "frame_timing.render_wait",
+                    time = format!("{:.2}", average.render_wait),
+                    max = format!("{:.2}", max.render_wait)
+                ));
+                ui.label(match self.bound() {
+                    FrameBound::MainThread => tr!("frame_timing.bound.main"),
+                    FrameBound::Render => tr!("frame_timing.bound.render"),
+                    FrameBound::Balanced => tr!("frame_timing.bound.balanced"),
+                });
+            });
+    }
+}
@@ -0,0 +1,248 @@
+use rafx::api::RafxDeviceContext;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+// the renderer creates its swapchain with vsync, which every backend maps to FIFO
+const PRESENT_MODE: &str = "FIFO (vsync)";
+
+/// Graphics API options read once, when the renderer is created.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    /// Enable the API validation layers, if installed.
+    pub validation: bool,
+}
+
+pub struct AdapterInfo {
+    pub name: String,
+    pub kind: String,
+    pub api_version: String,
+    pub driver_version: u32,
+    pub in_use: bool,
+    pub limits: Vec<(&'static str, String)>,
+    /// Formats with their supported uses for optimally tiled images.
+    pub formats: Vec<(&'static str, String)>,
+}
+
+impl AdapterInfo {
+    fn log(&self, index: usize) {
+        log::info!(
+            "  [{}] {} ({}), API {}, driver {:#x}{}",
+            index,
+            self.name,
+            self.kind,
+            self.api_version,
+            self.driver_version,
+            if self.in_use { ", in use" } else { "" }
+        );
+        for (name, value) in &self.limits {
+            log::info!("      {}: {}", name, value);
+        }
+        for (name, uses) in &self.formats {
+            log::info!("      {}: {}", name, uses);
+        }
+    }
+}
+
+/// Startup report of the adapters, their limits and format support, the present mode and the
+/// graphics options the renderer was created with. rafx-api picks the adapter itself, so the
+/// report only marks the one in use. The settings UI edits the options for the next start.
+pub struct GpuInfoResource {
+    adapters: Vec<AdapterInfo>,
+    /// The settings at startup, before `--validation`, to tell when they changed since.
+    running: GraphicsSettings,
+    settings: GraphicsSettings,
+    validation: bool,
+}
+
+impl GpuInfoResource {
+    /// `validation` is what the renderer was created with, the settings or `--validation`.
+    pub fn new(
+        device_context: &RafxDeviceContext,
+        settings: GraphicsSettings,
+        validation: bool,
+    ) -> Self {
+        let info = Self {
+            adapters: Self::query_adapters(device_context),
+            running: settings.clone(),
+            settings,
+            validation,
+        };
+        info.log_report();
+        info
+    }
+
+    fn log_report(&self) {
+        log::info!(
+            "Graphics validation {}",
+            if self.validation { "requested" } else { "off" }
+        );
+        log::info!("Present mode: {}", PRESENT_MODE);
+        log::info!("{} adapters:", self.adapters.len());
+        for (index, adapter) in self.adapters.iter().enumerate() {
+            adapter.log(index);
+        }
+    }
+
+    #[cfg(feature = "rafx-vulkan")]
+    fn query_adapters(device_context: &RafxDeviceContext) -> Vec<AdapterInfo> {
+        use rafx::api::ash::vk;
+
+        const FORMATS: [(&str, vk::Format); 8] = [
+            ("R8G8B8A8_SRGB", vk::Format::R8G8B8A8_SRGB),
+            ("B8G8R8A8_SRGB", vk::Format::B8G8R8A8_SRGB),
+            ("R16G16B16A16_SFLOAT", vk::Format::R16G16B16A16_SFLOAT),
+            ("R32G32B32A32_SFLOAT", vk::Format::R32G32B32A32_SFLOAT),
+            ("D32_SFLOAT", vk::Format::D32_SFLOAT),
+            ("D24_UNORM_S8_UINT", vk::Format::D24_UNORM_S8_UINT),
+            ("BC7_SRGB_BLOCK", vk::Format::BC7_SRGB_BLOCK),
+            ("ASTC_4x4_SRGB_BLOCK", vk::Format::ASTC_4X4_SRGB_BLOCK),
+        ];
+
+        let vk_context = match device_context.vk_device_context() {
+            Some(vk_context) => vk_context,
+            None => return vec![],
+        };
+        let instance = vk_context.instance();
+        let physical_devices = match unsafe { instance.enumerate_physical_devices() } {
+            Ok(physical_devices) => physical_devices,
+            Err(err) => {
+                log::error!("Cannot enumerate adapters: {:?}", err);
+                return vec![];
+            }
+        };
+        physical_devices
+            .into_iter()
+            .map(|physical_device| {
+                let properties =
+                    unsafe { instance.get_physical_device_properties(physical_device) };
+                let name = unsafe { std::ffi::CStr::from_ptr(properties.device_name.as_ptr()) }
+                    .to_string_lossy()
+                    .to_string();
+                let version = properties.api_version;
+                let limits = &properties.limits;
+                let formats = FORMATS
+                    .iter()
+                    .map(|(name, format)| {
+                        let features = unsafe {
+                            instance.get_physical_device_format_properties(physical_device, *format)
+                        }
+                        .optimal_tiling_features;
+                        let uses = [
+                            (vk::FormatFeatureFlags::SAMPLED_IMAGE, "sampled"),
+                            (vk::FormatFeatureFlags::COLOR_ATTACHMENT, "color"),
+                            (vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT, "depth"),
+                            (vk::FormatFeatureFlags::STORAGE_IMAGE, "storage"),
+                        ]
+                        .iter()
+                        .filter(|(flag, _)| features.contains(*flag))
+                        .map(|(_, use_name)| *use_name)
+                        .collect::<Vec<_>>();
+                        let uses = if uses.is_empty() {
+                            "unsupported".to_string()
+                        } else {
+                            uses.join(", ")
+                        };
+                        (*name, uses)
+                    })
+                    .collect();
+                AdapterInfo {
+                    name,
+                    kind: format!("{:?}", properties.device_type),
+                    api_version: format!(
+                        "{}.{}.{}",
+                        version >> 22,
+                        (version >> 12) & 0x3ff,
+                        version & 0xfff
+                    ),
+                    driver_version: properties.driver_version,
+                    in_use: physical_device == vk_context.physical_device(),
+                    limits: vec![
+                        (
+                            "max image size 2D",
+                            limits.max_image_dimension2_d.to_string(),
+                        ),
+                        (
+                            "max uniform buffer range",
+                            limits.max_uniform_buffer_range.to_string(),
+                        ),
+                        (
+                            "max storage buffer range",
+                            limits.max_storage_buffer_range.to_string(),
+                        ),
+                        (
+                            "max push constants size",
+                            limits.max_push_constants_size.to_string(),
+                        ),
+                        (
+                            "max bound descriptor sets",
+                            limits.max_bound_descriptor_sets.to_string(),
+                        ),
+                        (
+                            "max sampler anisotropy",
+                            limits.max_sampler_anisotropy.to_string(),
+                        ),
+                        (
+                            "max color samples",
+                            format!("{:?}", limits.framebuffer_color_sample_counts),
+                        ),
+                        ("timestamp period (ns)", limits.timestamp_period.to_string()),
+                    ],
+                    formats,
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "rafx-vulkan"))]
+    fn query_adapters(_device_context: &RafxDeviceContext) -> Vec<AdapterInfo> {
+        vec![]
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("gpu_info"))
+            .id_source("graphics adapter")
+            .default_open(false)
+            .show(ui, |ui| {
+                let old_settings = self.settings.clone();
+                ui.label(tr!("gpu_info.present_mode", mode = PRESENT_MODE));
+                ui.checkbox(&mut self.settings.validation, tr!("gpu_info.validation"));
+                if self.validation && !self.running.validation {
+                    ui.label(tr!("gpu_info.validation.cli"));
+                }
+                if self.settings != old_settings {
+                    let settings = self.settings.clone();
+                    Settings::update(|s| s.graphics = settings);
+                }
+                if self.settings != self.running {
+                    ui.colored_label(egui::Color32::YELLOW, tr!("gpu_info.restart"));
+                }
+
+                for (index, adapter) in self.adapters.iter().enumerate() {
+                    let title = if adapter.in_use {
+                        tr!(
+                            "gpu_info.adapter.in_use",
+                            index = index,
+                            name = adapter.name
+                        )
+                    } else {
+                        tr!("gpu_info.adapter", index = index, name = adapter.name)
+                    };
+                    egui::CollapsingHeader::new(title)
+                        .id_source(format!("adapter {}", index))
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.label(tr!(
+                                "gpu_info.adapter.kind",
+                                kind = adapter.kind,
+                                api = adapter.api_version
+                            ));
+                            for (name, value) in adapter.limits.iter().chain(&adapter.formats) {
+                                ui.label(format!("{}: {}", name, value));
+                            }
+                        });
+                }
+            });
+    }
+}
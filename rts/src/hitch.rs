@@ -0,0 +1,222 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rafx::base::Instant;
+
+const LOG_PATH: &str = "hitches.log";
+const MAX_HITCHES: usize = 32;
+const STAGES: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HitchStage {
+    /// Asset loader updates.
+    Assets,
+    /// Dyn mesh uploads.
+    Uploads,
+    /// Chunk meshing jobs, part of the scene update but reported apart.
+    ChunkJobs,
+    /// Scene update without the chunk jobs, includes the UI.
+    Scene,
+    /// `start_rendering_next_frame`, the wait for the render thread and the GPU.
+    Renderer,
+}
+
+impl HitchStage {
+    pub const ALL: [HitchStage; STAGES] = [
+        HitchStage::Assets,
+        HitchStage::Uploads,
+        HitchStage::ChunkJobs,
+        HitchStage::Scene,
+        HitchStage::Renderer,
+    ];
+}
+
+impl HitchStage {
+    /// Translated stage name for the UI, the log file keeps the `Display` name.
+    pub fn label(&self) -> String {
+        match self {
+            HitchStage::Assets => tr!("hitch.stage.assets"),
+            HitchStage::Uploads => tr!("hitch.stage.uploads"),
+            HitchStage::ChunkJobs => tr!("hitch.stage.chunk_jobs"),
+            HitchStage::Scene => tr!("hitch.stage.scene"),
+            HitchStage::Renderer => tr!("hitch.stage.renderer"),
+        }
+    }
+}
+
+impl fmt::Display for HitchStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HitchStage::Assets => "assets",
+            HitchStage::Uploads => "uploads",
+            HitchStage::ChunkJobs => "chunk jobs",
+            HitchStage::Scene => "scene",
+            HitchStage::Renderer => "renderer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub struct Hitch {
+    pub frame: u64,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    pub total: f32,            // ms
+    pub stages: [f32; STAGES], // ms
+    /// Frame time not covered by any stage.
+    pub other: f32, // ms
+}
+
+impl Hitch {
+    /// The stage that took the longest.
+    pub fn worst_stage(&self) -> HitchStage {
+        let mut worst = 0;
+        for i in 1..self.stages.len() {
+            if self.stages[i] > self.stages[worst] {
+                worst = i;
+            }
+        }
+        HitchStage::ALL[worst]
+    }
+}
+
+impl fmt::Display for Hitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frame {}: {:.1} ms, worst {} |",
+            self.timestamp,
+            self.frame,
+            self.total,
+            self.worst_stage()
+        )?;
+        for (stage, time) in HitchStage::ALL.iter().zip(self.stages.iter()) {
+            write!(f, " {} {:.1}", stage, time)?;
+        }
+        write!(f, " | other {:.1}", self.other)
+    }
+}
+
+/// Catches frames that take longer than `threshold` and keeps a breakdown of where the time
+/// went. Stages are timed around the calls in the main loop, the breakdown of every hitch is
+/// logged and appended to `hitches.log`.
+pub struct HitchResource {
+    pub enabled: bool,
+    pub threshold: f32, // ms
+    pub write_log: bool,
+    stages: [f32; STAGES], // ms
+    hitches: VecDeque<Hitch>,
+    count: usize,
+}
+
+impl Default for HitchResource {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 50.,
+            write_log: true,
+            stages: Default::default(),
+            hitches: VecDeque::new(),
+            count: 0,
+        }
+    }
+}
+
+impl HitchResource {
+    /// Adds the time since `start` to `stage` for this frame.
+    pub fn record(&mut self, stage: HitchStage, start: Instant) {
+        self.stages[stage as usize] += (Instant::now() - start).as_secs_f32() * 1000.;
+    }
+
+    /// Checks the frame that took `total` and starts the next one.
+    pub fn end_frame(&mut self, frame: u64, total: Duration) {
+        let mut stages = std::mem::take(&mut self.stages);
+        let total = total.as_secs_f32() * 1000.;
+        if !self.enabled || total < self.threshold {
+            return;
+        }
+        let scene = HitchStage::Scene as usize;
+        stages[scene] = (stages[scene] - stages[HitchStage::ChunkJobs as usize]).max(0.);
+        let hitch = Hitch {
+            frame,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |t| t.as_secs()),
+            total,
+            stages,
+            other: (total - stages.iter().sum::<f32>()).max(0.),
+        };
+        log::warn!("Hitch at {}", hitch);
+        if self.write_log {
+            if let Err(err) = Self::append(&hitch) {
+                log::error!("Cannot write {}: {}", LOG_PATH, err);
+            }
+        }
+        if self.hitches.len() == MAX_HITCHES {
+            self.hitches.pop_front();
+        }
+        self.hitches.push_back(hitch);
+        self.count += 1;
+    }
+
+    fn append(hitch: &Hitch) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(LOG_PATH)?;
+        writeln!(file, "{}", hitch)
+    }
+
+    pub fn hitches(&self) -> impl Iterator<Item = &Hitch> {
+        self.hitches.iter()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("hitch", count = self.count))
+            .id_source("hitches")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("hitch.enabled"));
+                ui.add(
+                    egui::Slider::new(&mut self.threshold, 20.0..=500.)
+                        .text(tr!("hitch.threshold")),
+                );
+                ui.checkbox(&mut self.write_log, tr!("hitch.write_log", path = LOG_PATH));
+                egui::ScrollArea::vertical()
+                    .max_height(200.)
+                    .show(ui, |ui| {
+                        for hitch in self.hitches.iter().rev() {
+                            egui::CollapsingHeader::new(tr!(
+                                "hitch.frame",
+                                frame = hitch.frame,
+                                time = format!("{:.1}", hitch.total),
+                                stage = hitch.worst_stage().label()
+                            ))
+                            .id_source(hitch.frame)
+                            .show(ui, |ui| {
+                                for (stage, time) in HitchStage::ALL.iter().zip(hitch.stages.iter())
+                                {
+                                    ui.label(tr!(
+                                        "hitch.stage_time",
+                                        stage = stage.label(),
+                                        time = format!("{:.2}", time)
+                                    ));
+                                }
+                                ui.label(tr!(
+                                    "hitch.other_time",
+                                    time = format!("{:.2}", hitch.other)
+                                ));
+                            });
+                        }
+                    });
+                if !self.hitches.is_empty() && ui.button(tr!("hitch.clear")).clicked() {
+                    self.hitches.clear();
+                    self.count = 0;
+                }
+            });
+    }
+}
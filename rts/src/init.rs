@@ -2,7 +2,9 @@ use std::sync::Arc;
 
 use legion::Resources;
 use rafx::{
-    api::{RafxApi, RafxApiDef, RafxDeviceContext, RafxResult, RafxSwapchainHelper},
+    api::{
+        RafxApi, RafxApiDef, RafxDeviceContext, RafxResult, RafxSwapchainHelper, RafxValidationMode,
+    },
     assets::{distill_impl::AssetResource, AssetManager},
     render_features::{ExtractResources, RenderRegistry},
     renderer::{
@@ -25,11 +27,12 @@ use raw_window_handle::HasRawWindowHandle;
 
 use crate::{
     assets::{
-        pbr_material::PbrMaterialAssetTypeRendererPlugin, tile::TileAssetTypeRendererPlugin,
-        tilesets::TileSetsAssetTypeRendererPlugin,
+        pbr_material::PbrMaterialAssetTypeRendererPlugin, texture_quality::TextureQuality,
+        tile::TileAssetTypeRendererPlugin, tilesets::TileSetsAssetTypeRendererPlugin,
     },
     camera::RTSCamera,
     features::dyn_mesh::{BufferUploaderConfig, DynMeshManager, DynMeshRendererPlugin},
+    gpu_info::{GpuInfoResource, GraphicsSettings},
 };
 
 pub fn rendering_init(
@@ -38,6 +41,9 @@ pub fn rendering_init(
     window: &dyn HasRawWindowHandle,
     window_width: u32,
     window_height: u32,
+    graphics: GraphicsSettings,
+    force_validation: bool,
+    texture_quality: TextureQuality,
 ) -> RafxResult<()> {
     resources.insert(ViewportsResource::default());
     resources.insert(RTSCamera::default());
@@ -63,7 +69,14 @@ pub fn rendering_init(
     //
 
     #[allow(unused_mut)]
-    let mut api_def = RafxApiDef::default();
+    let mut api_def = RafxApiDef {
+        validation_mode: if graphics.validation || force_validation {
+            RafxValidationMode::EnabledIfAvailable
+        } else {
+            RafxValidationMode::Disabled
+        },
+        ..Default::default()
+    };
 
     // For vulkan on the modern pipeline, we need to enable shader_clip_distance. The default-enabled
     // options in rafx-api are fine for the basic pipeline
@@ -92,9 +105,17 @@ pub fn rendering_init(
         true
     };
 
+    let asset_dirs = match &asset_source {
+        AssetSource::Daemon { daemon_args, .. } => daemon_args.asset_dirs.clone(),
+        _ => vec![],
+    };
+
     let mut renderer_builder = RendererBuilder::default();
     renderer_builder = renderer_builder
-        .add_asset(Arc::new(PbrMaterialAssetTypeRendererPlugin))
+        .add_asset(Arc::new(PbrMaterialAssetTypeRendererPlugin {
+            quality: texture_quality,
+            asset_dirs,
+        }))
         .add_asset(Arc::new(TileAssetTypeRendererPlugin))
         .add_asset(Arc::new(TileSetsAssetTypeRendererPlugin))
         .add_asset(Arc::new(FontAssetTypeRendererPlugin))
@@ -134,6 +155,11 @@ pub fn rendering_init(
         window_height,
     )?;
 
+    resources.insert(GpuInfoResource::new(
+        &rafx_api.device_context(),
+        graphics.clone(),
+        graphics.validation || force_validation,
+    ));
     resources.insert(rafx_api.device_context());
     resources.insert(rafx_api);
     resources.insert(swapchain_helper);
@@ -1,6 +1,6 @@
 use std::ops::{Deref, DerefMut};
 
-use crate::input::InputState;
+use crate::{input::InputState, settings::Settings};
 
 // For now just wrap the input helper that skulpin provides
 pub struct InputResource {
@@ -21,6 +21,31 @@ impl InputResource {
     pub fn input_state_mut(&mut self) -> &mut InputState {
         &mut self.input_state
     }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("mouse"))
+            .id_source("mouse")
+            .default_open(false)
+            .show(ui, |ui| {
+                let config = &mut self.input_state.config;
+                let mut changed = ui
+                    .add(
+                        egui::Slider::new(&mut config.drag_threshold, 1.0..=32.)
+                            .text(tr!("mouse.drag_threshold")),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut config.double_click_time, 0.1..=1.)
+                            .text(tr!("mouse.double_click_time")),
+                    )
+                    .changed();
+                if changed {
+                    let config = *config;
+                    Settings::update(|settings| settings.input = config);
+                }
+            });
+    }
 }
 
 impl Deref for InputResource {
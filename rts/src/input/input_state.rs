@@ -1,3 +1,7 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
 // End-users should provide their own layer to translate from these general values to something
 // appropriate to their platform or windowing system
 // These match winit
@@ -217,14 +221,62 @@ pub struct MouseScrollDelta {
 }
 
 impl MouseScrollDelta {
-    pub fn new(
-        x: f32,
-        y: f32,
-    ) -> Self {
+    pub fn new(x: f32, y: f32) -> Self {
         MouseScrollDelta { x, y }
     }
 }
 
+/// Modifier keys held, left and right variants are not told apart
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct KeyModifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl KeyModifiers {
+    pub const NONE: KeyModifiers = KeyModifiers {
+        shift: false,
+        ctrl: false,
+        alt: false,
+    };
+    pub const SHIFT: KeyModifiers = KeyModifiers {
+        shift: true,
+        ctrl: false,
+        alt: false,
+    };
+    pub const CTRL: KeyModifiers = KeyModifiers {
+        shift: false,
+        ctrl: true,
+        alt: false,
+    };
+    pub const ALT: KeyModifiers = KeyModifiers {
+        shift: false,
+        ctrl: false,
+        alt: true,
+    };
+}
+
+/// Thresholds of the mouse gestures, kept in the user settings
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    /// Distance in pixels the mouse has to be dragged to be considered a drag rather than a
+    /// click
+    pub drag_threshold: f32,
+    /// Longest time between two clicks of a double click, in seconds
+    pub double_click_time: f32,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            drag_threshold: 6.0,
+            double_click_time: 0.3,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum ButtonState {
     Released,
@@ -242,6 +294,8 @@ pub struct MouseDragState {
 
 /// State of input devices. This is maintained by processing events from winit
 pub struct InputState {
+    pub config: InputConfig,
+
     key_is_down: [bool; Self::KEYBOARD_BUTTON_COUNT],
     key_just_down: [bool; Self::KEYBOARD_BUTTON_COUNT],
     key_just_up: [bool; Self::KEYBOARD_BUTTON_COUNT],
@@ -254,6 +308,8 @@ pub struct InputState {
     mouse_button_just_up: [Option<glam::Vec2>; Self::MOUSE_BUTTON_COUNT as usize],
 
     mouse_button_just_clicked: [Option<glam::Vec2>; Self::MOUSE_BUTTON_COUNT as usize],
+    mouse_button_just_double_clicked: [Option<glam::Vec2>; Self::MOUSE_BUTTON_COUNT as usize],
+    mouse_button_last_click: [Option<(Instant, glam::Vec2)>; Self::MOUSE_BUTTON_COUNT as usize],
 
     mouse_button_went_down_position: [Option<glam::Vec2>; Self::MOUSE_BUTTON_COUNT as usize],
     mouse_button_went_up_position: [Option<glam::Vec2>; Self::MOUSE_BUTTON_COUNT as usize],
@@ -269,16 +325,13 @@ impl InputState {
 
     /// Number of mouse buttons we will track. Any button with a higher index will be ignored.
     pub const MOUSE_BUTTON_COUNT: u16 = 7;
-
-    /// Distance in LogicalPosition units that the mouse has to be dragged to be considered a drag
-    /// rather than a click
-    const MIN_DRAG_DISTANCE: f32 = 2.0;
 }
 
 impl InputState {
     /// Create a new input state to track the given window
     pub fn new() -> InputState {
         InputState {
+            config: Default::default(),
             key_is_down: [false; Self::KEYBOARD_BUTTON_COUNT],
             key_just_down: [false; Self::KEYBOARD_BUTTON_COUNT],
             key_just_up: [false; Self::KEYBOARD_BUTTON_COUNT],
@@ -289,6 +342,8 @@ impl InputState {
             mouse_button_just_down: [None; Self::MOUSE_BUTTON_COUNT as usize],
             mouse_button_just_up: [None; Self::MOUSE_BUTTON_COUNT as usize],
             mouse_button_just_clicked: [None; Self::MOUSE_BUTTON_COUNT as usize],
+            mouse_button_just_double_clicked: [None; Self::MOUSE_BUTTON_COUNT as usize],
+            mouse_button_last_click: [None; Self::MOUSE_BUTTON_COUNT as usize],
             mouse_button_went_down_position: [None; Self::MOUSE_BUTTON_COUNT as usize],
             mouse_button_went_up_position: [None; Self::MOUSE_BUTTON_COUNT as usize],
             mouse_drag_in_progress: [None; Self::MOUSE_BUTTON_COUNT as usize],
@@ -297,10 +352,7 @@ impl InputState {
     }
 
     /// Returns true if the given key is down
-    pub fn is_key_down(
-        &self,
-        key: KeyboardKey,
-    ) -> bool {
+    pub fn is_key_down(&self, key: KeyboardKey) -> bool {
         if let Some(index) = Self::keyboard_button_to_index(key) {
             self.key_is_down[index]
         } else {
@@ -309,10 +361,7 @@ impl InputState {
     }
 
     /// Returns true if the key went down during this frame
-    pub fn is_key_just_down(
-        &self,
-        key: KeyboardKey,
-    ) -> bool {
+    pub fn is_key_just_down(&self, key: KeyboardKey) -> bool {
         if let Some(index) = Self::keyboard_button_to_index(key) {
             self.key_just_down[index]
         } else {
@@ -321,10 +370,7 @@ impl InputState {
     }
 
     /// Returns true if the key went up during this frame
-    pub fn is_key_just_up(
-        &self,
-        key: KeyboardKey,
-    ) -> bool {
+    pub fn is_key_just_up(&self, key: KeyboardKey) -> bool {
         if let Some(index) = Self::keyboard_button_to_index(key) {
             self.key_just_up[index]
         } else {
@@ -332,6 +378,43 @@ impl InputState {
         }
     }
 
+    /// Returns the modifier keys currently held
+    pub fn modifiers(&self) -> KeyModifiers {
+        KeyModifiers {
+            shift: self.is_key_down(KeyboardKey::LShift) || self.is_key_down(KeyboardKey::RShift),
+            ctrl: self.is_key_down(KeyboardKey::LControl)
+                || self.is_key_down(KeyboardKey::RControl),
+            alt: self.is_key_down(KeyboardKey::LAlt) || self.is_key_down(KeyboardKey::RAlt),
+        }
+    }
+
+    /// Returns true if the key went down during this frame while exactly the given modifiers
+    /// were held
+    pub fn is_key_chord_just_down(&self, modifiers: KeyModifiers, key: KeyboardKey) -> bool {
+        self.modifiers() == modifiers && self.is_key_just_down(key)
+    }
+
+    /// Returns true if the button went down during this frame while exactly the given modifiers
+    /// were held (e.g. Shift+RightClick)
+    pub fn is_mouse_chord_just_down(
+        &self,
+        modifiers: KeyModifiers,
+        mouse_button: MouseButton,
+    ) -> bool {
+        self.modifiers() == modifiers && self.is_mouse_just_down(mouse_button)
+    }
+
+    /// Returns true if any key or mouse button was released, or the mouse moved or scrolled,
+    /// during this frame. Releases rather than presses, so a scene switched on this doesn't
+    /// get the release of the same key.
+    pub fn any_input(&self) -> bool {
+        self.key_just_up.iter().any(|up| *up)
+            || self.mouse_button_just_up.iter().any(|up| up.is_some())
+            || self.mouse_motion != glam::Vec2::ZERO
+            || self.mouse_wheel_delta.x != 0.0
+            || self.mouse_wheel_delta.y != 0.0
+    }
+
     /// Get the current mouse position
     pub fn mouse_position(&self) -> glam::Vec2 {
         self.mouse_position
@@ -347,10 +430,7 @@ impl InputState {
     }
 
     /// Returns true if the given button is down
-    pub fn is_mouse_down(
-        &self,
-        mouse_button: MouseButton,
-    ) -> bool {
+    pub fn is_mouse_down(&self, mouse_button: MouseButton) -> bool {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_is_down[index]
         } else {
@@ -359,10 +439,7 @@ impl InputState {
     }
 
     /// Returns true if the button went down during this frame
-    pub fn is_mouse_just_down(
-        &self,
-        mouse_button: MouseButton,
-    ) -> bool {
+    pub fn is_mouse_just_down(&self, mouse_button: MouseButton) -> bool {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_just_down[index].is_some()
         } else {
@@ -371,10 +448,7 @@ impl InputState {
     }
 
     /// Returns the position the mouse just went down at, otherwise returns None
-    pub fn mouse_just_down_position(
-        &self,
-        mouse_button: MouseButton,
-    ) -> Option<glam::Vec2> {
+    pub fn mouse_just_down_position(&self, mouse_button: MouseButton) -> Option<glam::Vec2> {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_just_down[index]
         } else {
@@ -383,10 +457,7 @@ impl InputState {
     }
 
     /// Returns true if the button went up during this frame
-    pub fn is_mouse_just_up(
-        &self,
-        mouse_button: MouseButton,
-    ) -> bool {
+    pub fn is_mouse_just_up(&self, mouse_button: MouseButton) -> bool {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_just_up[index].is_some()
         } else {
@@ -395,10 +466,7 @@ impl InputState {
     }
 
     /// Returns the position the mouse just went up at, otherwise returns None
-    pub fn mouse_just_up_position(
-        &self,
-        mouse_button: MouseButton,
-    ) -> Option<glam::Vec2> {
+    pub fn mouse_just_up_position(&self, mouse_button: MouseButton) -> Option<glam::Vec2> {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_just_up[index]
         } else {
@@ -408,10 +476,7 @@ impl InputState {
 
     /// Returns true if the button was just clicked. "Clicked" means the button went down and came
     /// back up without being moved much. If it was moved, it would be considered a drag.
-    pub fn is_mouse_button_just_clicked(
-        &self,
-        mouse_button: MouseButton,
-    ) -> bool {
+    pub fn is_mouse_button_just_clicked(&self, mouse_button: MouseButton) -> bool {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_just_clicked[index].is_some()
         } else {
@@ -433,11 +498,21 @@ impl InputState {
         }
     }
 
-    /// Returns the position the button went down at previously. This could have been some time ago.
-    pub fn mouse_button_went_down_position(
+    /// Returns the position of the second click if the button was just double clicked, otherwise
+    /// None. The first click of the pair was reported as a click in an earlier frame.
+    pub fn mouse_button_just_double_clicked_position(
         &self,
         mouse_button: MouseButton,
     ) -> Option<glam::Vec2> {
+        if let Some(index) = Self::mouse_button_to_index(mouse_button) {
+            self.mouse_button_just_double_clicked[index]
+        } else {
+            None
+        }
+    }
+
+    /// Returns the position the button went down at previously. This could have been some time ago.
+    pub fn mouse_button_went_down_position(&self, mouse_button: MouseButton) -> Option<glam::Vec2> {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_went_down_position[index]
         } else {
@@ -446,10 +521,7 @@ impl InputState {
     }
 
     /// Returns the position the button went up at previously. This could have been some time ago.
-    pub fn mouse_button_went_up_position(
-        &self,
-        mouse_button: MouseButton,
-    ) -> Option<glam::Vec2> {
+    pub fn mouse_button_went_up_position(&self, mouse_button: MouseButton) -> Option<glam::Vec2> {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_button_went_up_position[index]
         } else {
@@ -459,10 +531,7 @@ impl InputState {
 
     /// Return true if the mouse is being dragged. (A drag means the button went down and mouse
     /// moved, but button hasn't come back up yet)
-    pub fn is_mouse_drag_in_progress(
-        &self,
-        mouse_button: MouseButton,
-    ) -> bool {
+    pub fn is_mouse_drag_in_progress(&self, mouse_button: MouseButton) -> bool {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_drag_in_progress[index].is_some()
         } else {
@@ -471,10 +540,7 @@ impl InputState {
     }
 
     /// Returns the mouse drag state if a drag is in process, otherwise None.
-    pub fn mouse_drag_in_progress(
-        &self,
-        mouse_button: MouseButton,
-    ) -> Option<MouseDragState> {
+    pub fn mouse_drag_in_progress(&self, mouse_button: MouseButton) -> Option<MouseDragState> {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_drag_in_progress[index]
         } else {
@@ -483,10 +549,7 @@ impl InputState {
     }
 
     /// Return true if a mouse drag completed in the previous frame, otherwise false
-    pub fn is_mouse_drag_just_finished(
-        &self,
-        mouse_button: MouseButton,
-    ) -> bool {
+    pub fn is_mouse_drag_just_finished(&self, mouse_button: MouseButton) -> bool {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_drag_just_finished[index].is_some()
         } else {
@@ -495,10 +558,7 @@ impl InputState {
     }
 
     /// Returns information about a mouse drag if it just completed, otherwise None
-    pub fn mouse_drag_just_finished(
-        &self,
-        mouse_button: MouseButton,
-    ) -> Option<MouseDragState> {
+    pub fn mouse_drag_just_finished(&self, mouse_button: MouseButton) -> Option<MouseDragState> {
         if let Some(index) = Self::mouse_button_to_index(mouse_button) {
             self.mouse_drag_just_finished[index]
         } else {
@@ -534,6 +594,10 @@ impl InputState {
             *value = None;
         }
 
+        for value in self.mouse_button_just_double_clicked.iter_mut() {
+            *value = None;
+        }
+
         for value in self.mouse_drag_just_finished.iter_mut() {
             *value = None;
         }
@@ -571,11 +635,7 @@ impl InputState {
     }
 
     /// Call when a mouse button event occurs
-    pub fn handle_mouse_button_event(
-        &mut self,
-        button: MouseButton,
-        button_event: ButtonState,
-    ) {
+    pub fn handle_mouse_button_event(&mut self, button: MouseButton, button_event: ButtonState) {
         if let Some(button_index) = Self::mouse_button_to_index(button) {
             assert!(button_index < InputState::MOUSE_BUTTON_COUNT as usize);
 
@@ -608,7 +668,28 @@ impl InputState {
                             });
                         }
                         None => {
-                            self.mouse_button_just_clicked[button_index] = Some(self.mouse_position)
+                            self.mouse_button_just_clicked[button_index] =
+                                Some(self.mouse_position);
+
+                            let now = Instant::now();
+                            let is_double_click = match self.mouse_button_last_click[button_index] {
+                                Some((time, position)) => {
+                                    now.duration_since(time).as_secs_f32()
+                                        <= self.config.double_click_time
+                                        && glam::Vec2::length(position - self.mouse_position)
+                                            <= self.config.drag_threshold
+                                }
+                                None => false,
+                            };
+                            if is_double_click {
+                                // a third click starts a new pair
+                                self.mouse_button_just_double_clicked[button_index] =
+                                    Some(self.mouse_position);
+                                self.mouse_button_last_click[button_index] = None;
+                            } else {
+                                self.mouse_button_last_click[button_index] =
+                                    Some((now, self.mouse_position));
+                            }
                         }
                     }
 
@@ -619,18 +700,12 @@ impl InputState {
     }
 
     /// Call when the cursor moves at all (even if it's locked or outside the window)
-    pub fn handle_mouse_motion_event(
-        &mut self,
-        delta: glam::Vec2,
-    ) {
+    pub fn handle_mouse_motion_event(&mut self, delta: glam::Vec2) {
         self.mouse_motion += delta
     }
 
     /// Call when a cursor moves within the window
-    pub fn handle_mouse_update_position(
-        &mut self,
-        position: glam::Vec2,
-    ) {
+    pub fn handle_mouse_update_position(&mut self, position: glam::Vec2) {
         // Update mouse position
         self.mouse_position = position;
 
@@ -644,7 +719,7 @@ impl InputState {
                             Some(went_down_position) => {
                                 let min_drag_distance_met =
                                     glam::Vec2::length(went_down_position - self.mouse_position)
-                                        > Self::MIN_DRAG_DISTANCE;
+                                        > self.config.drag_threshold;
                                 if min_drag_distance_met {
                                     let delta = self.mouse_position - went_down_position;
 
@@ -684,10 +759,7 @@ impl InputState {
         }
     }
 
-    pub fn handle_mouse_wheel_event(
-        &mut self,
-        delta: MouseScrollDelta,
-    ) {
+    pub fn handle_mouse_wheel_event(&mut self, delta: MouseScrollDelta) {
         self.mouse_wheel_delta.x += delta.x;
         self.mouse_wheel_delta.y += delta.y;
     }
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use distill::loader::handle::Handle;
+use glam::{Vec3, Vec4};
+use legion::{Entity, IntoQuery, Read};
+use rafx::assets::AssetManager;
+use rafx_plugins::{
+    assets::font::FontAsset, components::TransformComponent, features::text::TextResource,
+};
+
+use crate::{camera::RTSCamera, env::simulation::Universe};
+
+const LABEL_SIZE: f32 = 16.; // px, before the window scale factor
+
+// glyphs of the label font are about this wide, for centering the text on its anchor
+const GLYPH_WIDTH: f32 = 0.5; // of the font size
+const OCCLUSION_INTERVAL: f32 = 0.2; // s
+
+// a hit closer than this to the anchor is the ground under it, not an occluder
+const OCCLUSION_MARGIN: f32 = 2.; // m
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LabelKind {
+    Unit,
+    Building,
+    Debug,
+}
+
+/// Text drawn above the entity, anchored at its translation plus `offset`.
+#[derive(Clone)]
+pub struct LabelComponent {
+    pub text: String,
+    pub kind: LabelKind,
+    pub offset: Vec3, // m
+    pub color: Vec4,
+}
+
+impl LabelComponent {
+    pub fn new(text: impl Into<String>, kind: LabelKind, offset: Vec3) -> Self {
+        Self {
+            text: text.into(),
+            kind,
+            offset,
+            color: Vec4::ONE,
+        }
+    }
+}
+
+/// World anchored labels drawn with the screen space text feature: the anchors are projected
+/// every frame, labels off-screen or hidden from the eye by terrain are skipped and the rest
+/// fade out with the distance to the camera. Besides the entities with a `LabelComponent`,
+/// anything can add a debug annotation for the current frame with `annotate`.
+pub struct LabelResource {
+    pub show_units: bool,
+    pub show_buildings: bool,
+    pub show_debug: bool,
+    pub occlusion: bool,
+    /// Labels start fading at this distance from the eye...
+    pub fade_start: f32, // m
+    /// ...and are gone at this one.
+    pub max_distance: f32, // m
+    annotations: Vec<(Vec3, String, Vec4)>,
+    occluded: HashMap<Entity, bool>,
+    occlusion_timer: f32, // s
+    drawn: usize,
+}
+
+impl Default for LabelResource {
+    fn default() -> Self {
+        Self {
+            show_units: true,
+            show_buildings: true,
+            show_debug: true,
+            occlusion: true,
+            fade_start: 80.,
+            max_distance: 160.,
+            annotations: vec![],
+            occluded: HashMap::new(),
+            occlusion_timer: 0.,
+            drawn: 0,
+        }
+    }
+}
+
+impl LabelResource {
+    /// Draws `text` at `position` this frame only.
+    pub fn annotate(&mut self, position: Vec3, text: impl Into<String>, color: Vec4) {
+        self.annotations.push((position, text.into(), color));
+    }
+
+    fn is_shown(&self, kind: LabelKind) -> bool {
+        match kind {
+            LabelKind::Unit => self.show_units,
+            LabelKind::Building => self.show_buildings,
+            LabelKind::Debug => self.show_debug,
+        }
+    }
+
+    /// Call once per frame after the camera update, with unscaled time.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        dt: f32,
+        universe: &Universe,
+        camera: &RTSCamera,
+        window_size: [f32; 2],
+        text_resource: &mut TextResource,
+        asset_manager: &AssetManager,
+        font: &Handle<FontAsset>,
+    ) {
+        let annotations = std::mem::take(&mut self.annotations);
+        self.drawn = 0;
+        if asset_manager.committed_asset(font).is_none() {
+            return;
+        }
+
+        self.occlusion_timer -= dt;
+        let check_occlusion = self.occlusion_timer <= 0.;
+        if check_occlusion {
+            self.occlusion_timer = OCCLUSION_INTERVAL;
+        }
+        let mut occluded = HashMap::new();
+        let mut labels = vec![];
+        let mut query = <(Entity, Read<TransformComponent>, Read<LabelComponent>)>::query();
+        for (entity, transform, label) in query.iter(&universe.world) {
+            if !self.is_shown(label.kind) {
+                continue;
+            }
+            let anchor = transform.translation + label.offset;
+            if self.occlusion && label.kind != LabelKind::Debug {
+                // hidden labels are only checked against terrain every `OCCLUSION_INTERVAL`
+                let hidden = match self.occluded.get(entity) {
+                    Some(hidden) if !check_occlusion => *hidden,
+                    _ => Self::is_occluded(universe, camera.eye(), anchor, self.max_distance),
+                };
+                occluded.insert(*entity, hidden);
+                if hidden {
+                    continue;
+                }
+            }
+            labels.push((anchor, label.text.as_str(), label.color));
+        }
+        self.occluded = occluded;
+        if self.show_debug {
+            labels.extend(
+                annotations
+                    .iter()
+                    .map(|(position, text, color)| (*position, text.as_str(), *color)),
+            );
+        }
+
+        let view_proj = camera.view_proj();
+        let eye = camera.eye();
+        let size = LABEL_SIZE * camera.win_scale_factor;
+        for (anchor, text, color) in labels {
+            let distance = anchor.distance(eye);
+            if distance >= self.max_distance {
+                continue;
+            }
+            let clip = view_proj * anchor.extend(1.);
+            if clip.w <= 0. {
+                continue;
+            }
+            let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+            if x.abs() > 1. || y.abs() > 1. {
+                continue;
+            }
+            let fade = ((distance - self.fade_start)
+                / (self.max_distance - self.fade_start).max(f32::EPSILON))
+            .max(0.)
+            .min(1.);
+            let alpha = color.w * (1. - fade * fade * (3. - 2. * fade));
+            let half_width = text.chars().count() as f32 * size * GLYPH_WIDTH / 2.;
+            text_resource.add_text(
+                text.to_string(),
+                Vec3::new(
+                    (x + 1.) / 2. * window_size[0] - half_width,
+                    (1. - y) / 2. * window_size[1] - size,
+                    0.,
+                ),
+                font,
+                size,
+                Vec4::new(color.x, color.y, color.z, alpha),
+            );
+            self.drawn += 1;
+        }
+    }
+
+    fn is_occluded(universe: &Universe, eye: Vec3, anchor: Vec3, max_distance: f32) -> bool {
+        let to_anchor = anchor - eye;
+        let distance = to_anchor.length();
+        if distance <= OCCLUSION_MARGIN || distance >= max_distance {
+            return false;
+        }
+        universe.ray_cast(eye, to_anchor).map_or(false, |result| {
+            let hit = Vec3::new(
+                result.hit.x() as f32,
+                result.hit.y() as f32,
+                result.hit.z() as f32,
+            ) + Vec3::splat(0.5);
+            hit.distance(eye) < distance - OCCLUSION_MARGIN
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("labels", count = self.drawn))
+            .id_source("labels")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.show_units, tr!("labels.units"));
+                ui.checkbox(&mut self.show_buildings, tr!("labels.buildings"));
+                ui.checkbox(&mut self.show_debug, tr!("labels.debug"));
+                ui.checkbox(&mut self.occlusion, tr!("labels.occlusion"));
+                ui.add(
+                    egui::Slider::new(&mut self.fade_start, 10.0..=500.)
+                        .text(tr!("labels.fade_start")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.max_distance, 10.0..=1000.)
+                        .text(tr!("labels.max_distance")),
+                );
+            });
+    }
+}
@@ -10,7 +10,10 @@ use rafx::{
     renderer::{AssetSource, Renderer, RendererConfigResource, ViewportsResource},
 };
 use rafx_plugins::{
-    features::{egui::WinitEguiManager, mesh_adv::MeshAdvRenderOptions as MeshRenderOptions},
+    features::{
+        egui::{EguiContextResource, WinitEguiManager},
+        mesh_adv::MeshAdvRenderOptions as MeshRenderOptions,
+    },
     pipelines::modern::{
         ModernPipelineRenderOptions as PipelineRenderOptions,
         ModernPipelineTonemapDebugData as PipelineTonemapDebugData,
@@ -25,23 +28,106 @@ use winit::{
 };
 
 use crate::{
-    camera::RTSCamera, daemon_args::AssetDaemonArgs, env::simulation::Simulation,
-    features::dyn_mesh::DynMeshManager, input::InputResource, scenes::SceneManager,
-    scenes::SceneManagerAction, time::PeriodicEvent, time::TimeState, ui::UiState,
+    accessibility::AccessibilityResource,
+    assets::{
+        browser::AssetBrowserResource,
+        fallback::FallbackAssetsResource,
+        status::{AssetStatusResource, SourceRequest},
+        texture_quality::TextureQualityResource,
+    },
+    audio::AudioResource,
+    bench::BenchModeState,
+    camera::RTSCamera,
+    camera_path::CameraDirectorResource,
+    camera_shake::CameraShakeResource,
+    console::{ConsoleCommand, ConsoleResource},
+    cursor::CursorResource,
+    daemon_args::AssetDaemonArgs,
+    debug_cull::DebugCullResource,
+    env::{
+        analysis::TerrainAnalysisResource,
+        grid_overlay::GridOverlayResource,
+        integrity::IntegrityResource,
+        lighting::LightingResource,
+        save::AutosaveResource,
+        schedule::UniverseScheduleResource,
+        simulation::{Simulation, TerrainFillStyle, TerrainMeshStyle},
+        territory::TerritoryResource,
+        throttle::MeshThrottleResource,
+        ui::TerrainResetUiState,
+        visibility_updates::VisibilityUpdateResource,
+        weather::WeatherResource,
+        world_edge::WorldEdgeResource,
+    },
+    features::dyn_mesh::DynMeshManager,
+    file_dialog::FileDialogResource,
+    frame_timing::FrameTimingResource,
+    hitch::{HitchResource, HitchStage},
+    input::InputResource,
+    labels::LabelResource,
+    locale::LocaleResource,
+    memory::{MemoryRegion, MemoryResource},
+    notifications::NotificationResource,
+    packfile::PackBuilderResource,
+    perf_experiments::PerfExperimentsResource,
+    power::PowerSavingResource,
+    resize::ResizeResource,
+    scenes::{GameState, SceneManager, SceneManagerAction},
+    settings::Settings,
+    stats_export::{FrameStats, StatsExportResource},
+    time::PeriodicEvent,
+    time::TimeState,
+    tutorial::TutorialResource,
+    ui::UiState,
+    ui_style::UiStyleResource,
+    unit::{
+        combat::CombatRulesResource, creep::CreepResource, economy::PlayerEconomy,
+        hit_flash::HitFlashResource, lod::UnitLodResource, orders::PendingOrdersResource,
+        outline::OutlineResource, prefab::PrefabRegistry, tech::TechResource,
+        trigger::TriggerResource, wreck::WreckResource,
+    },
 };
 
+#[macro_use]
+mod locale;
+mod accessibility;
 mod assets;
+mod audio;
+mod audit;
+pub mod bench;
 mod camera;
+mod camera_path;
+mod camera_shake;
+mod console;
+mod cursor;
 pub mod daemon_args;
+mod debug_cull;
 mod demo_renderer_thread_pool;
 mod env;
 mod features;
+mod file_dialog;
+mod frame_timing;
+mod gpu_info;
+mod hitch;
 mod init;
 mod input;
+mod labels;
+mod memory;
+mod notifications;
+pub mod packfile;
+mod perf_experiments;
+mod picking;
+mod power;
+mod resize;
 mod scenes;
+mod settings;
+mod stats_export;
 mod time;
+mod tutorial;
 mod ui;
+mod ui_style;
 mod unit;
+mod zoom_scaling;
 
 #[cfg(all(feature = "profile-with-tracy-memory", not(feature = "stats_alloc")))]
 #[global_allocator]
@@ -53,34 +139,7 @@ static GLOBAL: profiling::tracy_client::ProfiledAllocator<std::alloc::System> =
 pub static STATS_ALLOC: &stats_alloc::StatsAlloc<std::alloc::System> =
     &stats_alloc::INSTRUMENTED_SYSTEM;
 
-struct StatsAllocMemoryRegion<'a> {
-    region_name: &'a str,
-    #[cfg(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory")))]
-    region: stats_alloc::Region<'a, std::alloc::System>,
-}
-
-impl<'a> StatsAllocMemoryRegion<'a> {
-    pub fn new(region_name: &'a str) -> Self {
-        StatsAllocMemoryRegion {
-            region_name,
-            #[cfg(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory")))]
-            region: stats_alloc::Region::new(STATS_ALLOC),
-        }
-    }
-}
-
-#[cfg(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory")))]
-impl Drop for StatsAllocMemoryRegion<'_> {
-    fn drop(&mut self) {
-        log::info!(
-            "({}) | {:?}",
-            self.region_name,
-            self.region.change_and_reset()
-        );
-    }
-}
-
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct RenderOptions {
     pub enable_msaa: bool,
     pub enable_hdr: bool,
@@ -96,6 +155,13 @@ pub struct RenderOptions {
     pub blur_pass_count: usize,
     pub tonemapper_type: TonemapperType,
     pub enable_visibility_update: bool,
+    /// One visibility object per terrain sector away from the frustum edges, see
+    /// `Universe::update_sector_visibility`.
+    pub enable_sector_visibility: bool,
+    pub enable_outlines: bool,
+    pub outline_thickness: f32,
+    pub enable_chunk_ao: bool,
+    pub enable_compact_chunk_vertices: bool,
 }
 
 impl RenderOptions {
@@ -115,6 +181,11 @@ impl RenderOptions {
             blur_pass_count: 0,
             tonemapper_type: TonemapperType::None,
             enable_visibility_update: true,
+            enable_sector_visibility: true,
+            enable_outlines: true,
+            outline_thickness: 2.,
+            enable_chunk_ao: true,
+            enable_compact_chunk_vertices: true,
         }
     }
 
@@ -134,14 +205,19 @@ impl RenderOptions {
             blur_pass_count: 5,
             tonemapper_type: TonemapperType::Bergstrom,
             enable_visibility_update: true,
+            enable_sector_visibility: true,
+            enable_outlines: true,
+            outline_thickness: 2.,
+            enable_chunk_ao: true,
+            enable_compact_chunk_vertices: true,
         }
     }
 }
 
 impl RenderOptions {
     pub fn ui(&mut self, ui: &mut egui::Ui) {
-        ui.checkbox(&mut self.enable_msaa, "enable_msaa");
-        ui.checkbox(&mut self.enable_hdr, "enable_hdr");
+        ui.checkbox(&mut self.enable_msaa, tr!("render_options.msaa"));
+        ui.checkbox(&mut self.enable_hdr, tr!("render_options.hdr"));
 
         if self.enable_hdr {
             ui.indent("HDR options", |ui| {
@@ -149,7 +225,7 @@ impl RenderOptions {
                     .map(|t| TonemapperType::from(t).display_name())
                     .collect();
 
-                egui::ComboBox::from_label("tonemapper_type")
+                egui::ComboBox::from_label(tr!("render_options.tonemapper"))
                     .selected_text(tonemapper_names[self.tonemapper_type as usize])
                     .show_ui(ui, |ui| {
                         for (i, name) in tonemapper_names.iter().enumerate() {
@@ -161,13 +237,13 @@ impl RenderOptions {
                         }
                     });
 
-                ui.checkbox(&mut self.enable_bloom, "enable_bloom");
+                ui.checkbox(&mut self.enable_bloom, tr!("render_options.bloom"));
                 if self.enable_bloom {
                     ui.indent("", |ui| {
                         ui.add(
                             egui::Slider::new(&mut self.blur_pass_count, 0..=10)
                                 .clamp_to_range(true)
-                                .text("blur_pass_count"),
+                                .text(tr!("render_options.blur_passes")),
                         );
                     });
                 }
@@ -175,30 +251,49 @@ impl RenderOptions {
         }
 
         if self.show_feature_toggles {
-            ui.checkbox(&mut self.show_wireframes, "show_wireframes");
-            ui.checkbox(&mut self.show_surfaces, "show_surfaces");
+            ui.checkbox(&mut self.show_wireframes, tr!("render_options.wireframes"));
+            ui.checkbox(&mut self.show_surfaces, tr!("render_options.surfaces"));
 
             if self.show_surfaces {
                 ui.indent("", |ui| {
-                    ui.checkbox(&mut self.enable_textures, "enable_textures");
-                    ui.checkbox(&mut self.enable_lighting, "enable_lighting");
+                    ui.checkbox(&mut self.enable_textures, tr!("render_options.textures"));
+                    ui.checkbox(&mut self.enable_lighting, tr!("render_options.lighting"));
 
                     if self.enable_lighting {
                         ui.indent("", |ui| {
-                            ui.checkbox(&mut self.show_shadows, "show_shadows");
+                            ui.checkbox(&mut self.show_shadows, tr!("render_options.shadows"));
                         });
                     }
                 });
             }
 
-            ui.checkbox(&mut self.show_debug3d, "show_debug3d_feature");
-            ui.checkbox(&mut self.show_text, "show_text_feature");
+            ui.checkbox(&mut self.show_debug3d, tr!("render_options.debug3d"));
+            ui.checkbox(&mut self.show_text, tr!("render_options.text"));
         }
 
         ui.checkbox(
             &mut self.enable_visibility_update,
-            "enable_visibility_update",
+            tr!("render_options.visibility_update"),
+        );
+        ui.checkbox(
+            &mut self.enable_sector_visibility,
+            tr!("render_options.sector_visibility"),
+        );
+
+        ui.checkbox(&mut self.enable_chunk_ao, tr!("render_options.chunk_ao"));
+        ui.checkbox(
+            &mut self.enable_compact_chunk_vertices,
+            tr!("render_options.compact_chunk_vertices"),
         );
+        ui.checkbox(&mut self.enable_outlines, tr!("render_options.outlines"));
+        if self.enable_outlines {
+            ui.indent("", |ui| {
+                ui.add(
+                    egui::Slider::new(&mut self.outline_thickness, 1.0..=8.0)
+                        .text(tr!("render_options.outline_thickness")),
+                );
+            });
+        }
     }
 }
 
@@ -206,6 +301,7 @@ impl RenderOptions {
 pub struct DebugUiState {
     show_render_options: bool,
     show_asset_list: bool,
+    show_asset_status: bool,
     show_tonemap_debug: bool,
     show_shadow_map_debug: bool,
 
@@ -213,7 +309,7 @@ pub struct DebugUiState {
     show_profiler: bool,
 }
 
-#[derive(StructOpt)]
+#[derive(StructOpt, Clone)]
 pub struct DemoArgs {
     /// Path to the packfile
     #[structopt(name = "packfile", long, parse(from_os_str))]
@@ -224,6 +320,38 @@ pub struct DemoArgs {
 
     #[structopt(flatten)]
     pub daemon_args: AssetDaemonArgs,
+
+    /// Generate the main terrain from a grayscale heightmap image
+    #[structopt(name = "heightmap", long)]
+    pub heightmap: Option<String>,
+
+    /// Height in voxels of the heightmap's white level
+    #[structopt(name = "heightmap-scale", long, default_value = "64")]
+    pub heightmap_scale: f32,
+
+    /// Mesh the main terrain with surface nets instead of cubes
+    #[structopt(name = "smooth-terrain", long)]
+    pub smooth_terrain: bool,
+
+    /// Wrap the main terrain around its edges, for an endless scrolling map
+    #[structopt(name = "wrap-terrain", long)]
+    pub wrap_terrain: bool,
+
+    /// Track render handle lifetimes and report the ones left alive after scene cleanup
+    #[structopt(name = "audit-handles", long)]
+    pub audit_handles: bool,
+
+    /// Time the terrain mesher and mesh uploads, log the results and exit
+    #[structopt(name = "bench-mode", long)]
+    pub bench_mode: bool,
+
+    /// Enable the graphics API validation layers, overriding the settings
+    #[structopt(name = "validation", long)]
+    pub validation: bool,
+
+    /// Append per-frame stats to this file, as JSON lines for .json/.jsonl and CSV otherwise
+    #[structopt(name = "stats-out", long, parse(from_os_str))]
+    pub stats_out: Option<std::path::PathBuf>,
 }
 
 impl DemoArgs {
@@ -237,6 +365,23 @@ impl DemoArgs {
             }
         }
     }
+
+    fn terrain_reset(&self) -> TerrainResetUiState {
+        let mut terrain_reset = TerrainResetUiState::default();
+        if let Some(path) = &self.heightmap {
+            terrain_reset.style = TerrainFillStyle::Heightmap {
+                path: path.clone(),
+                vertical_scale: self.heightmap_scale,
+                material: "basic_tile".to_string(),
+                bands: vec![],
+            };
+        }
+        if self.smooth_terrain {
+            terrain_reset.mesh_style = TerrainMeshStyle::Smooth;
+        }
+        terrain_reset.wrap = self.wrap_terrain;
+        terrain_reset
+    }
 }
 
 struct DemoApp {
@@ -245,6 +390,8 @@ struct DemoApp {
     resources: Resources,
     simulation: Simulation,
     print_time_event: PeriodicEvent,
+    synced_render_options: Option<RenderOptions>,
+    bench_mode: Option<BenchModeState>,
 }
 
 impl DemoApp {
@@ -254,18 +401,93 @@ impl DemoApp {
         #[cfg(feature = "profile-with-optick")]
         profiling::optick::register_thread("Main Thread");
 
+        audit::set_enabled(args.audit_handles);
+
         let scene_manager = SceneManager::default();
 
         let mut resources = Resources::default();
         resources.insert(TimeState::new());
+        resources.insert(GameState::Playing);
         resources.insert(RenderOptions::default_2d());
         resources.insert(MeshRenderOptions::default());
         resources.insert(PipelineRenderOptions::default());
         resources.insert(PipelineTonemapDebugData::default());
         resources.insert(DebugUiState::default());
-        resources.insert(InputResource::new());
+        resources.insert(WeatherResource::default());
+        resources.insert(TerrainAnalysisResource::default());
+        resources.insert(AutosaveResource::default());
+        resources.insert(IntegrityResource::default());
+        resources.insert(TerritoryResource::default());
+        resources.insert(GridOverlayResource::default());
+        resources.insert(TechResource::default());
+        resources.insert(PlayerEconomy::default());
+        resources.insert(TriggerResource::default());
+        resources.insert(PrefabRegistry::load());
+        resources.insert(CombatRulesResource::load());
+        resources.insert(WreckResource::default());
+        resources.insert(CreepResource::default());
+        resources.insert(NotificationResource::default());
+        resources.insert(PendingOrdersResource::default());
+        resources.insert(CameraDirectorResource::default());
+        resources.insert(FrameTimingResource::default());
+        resources.insert(StatsExportResource::new(args.stats_out.clone()));
+        resources.insert(HitchResource::default());
+        resources.insert(MemoryResource::default());
+        resources.insert(PerfExperimentsResource::default());
+        resources.insert(ConsoleResource::default());
+        resources.insert(LabelResource::default());
+        resources.insert(MeshThrottleResource::default());
+        resources.insert(VisibilityUpdateResource::default());
+        resources.insert(WorldEdgeResource::default());
+        resources.insert(TutorialResource::default());
+        resources.insert(LightingResource::default());
+        resources.insert(DebugCullResource::default());
+        resources.insert(ResizeResource::default());
+        let mut universe_systems = UniverseScheduleResource::default();
+        unit::unit::register_systems(&mut universe_systems);
+        env::visibility_updates::register_systems(&mut universe_systems);
+        resources.insert(universe_systems);
+        let settings = Settings::load();
+        resources.insert(UiStyleResource::new(settings.ui_style));
+        resources.insert(OutlineResource::new(settings.team_palette));
+        resources.insert(UnitLodResource::new(settings.unit_lod));
+        resources.insert(FileDialogResource::new(settings.file_dialogs.clone()));
+        resources.insert(CameraShakeResource::new(settings.camera_shake));
+        resources.insert(AudioResource::new(settings.audio));
+        resources.insert(AccessibilityResource::new(settings.accessibility));
+        resources.insert(HitFlashResource::new(
+            !settings.accessibility.reduce_flashing,
+        ));
+        resources.insert(LocaleResource::new(&settings.language));
+        // bench timings must not depend on window focus
+        resources.insert(PowerSavingResource::new(
+            settings.power_saving && !args.bench_mode,
+        ));
+        resources.insert(CursorResource::new(settings.software_cursor));
+        let mut input = InputResource::new();
+        input.config = settings.input;
+        resources.insert(input);
+        let daemon_asset_dirs = if args.packfile.is_none() {
+            args.daemon_args.asset_dirs.clone()
+        } else {
+            vec![]
+        };
+        resources.insert(AssetBrowserResource::new(daemon_asset_dirs.clone()));
+        resources.insert(PackBuilderResource::new(daemon_asset_dirs));
+        resources.insert(TextureQualityResource::new(
+            settings.texture_quality,
+            args.packfile.is_none() && !args.external_daemon,
+        ));
+        #[cfg(feature = "physics")]
+        resources.insert(crate::env::physics::PhysicsResource::default());
+
+        let terrain_reset = args.terrain_reset();
+        resources.insert(terrain_reset.clone());
+        let mut ui_state = UiState::default();
+        ui_state.env.terrain_reset = terrain_reset;
 
         let asset_source = args.asset_source();
+        resources.insert(AssetStatusResource::new(&asset_source));
 
         let physical_size = window.inner_size();
         init::rendering_init(
@@ -274,20 +496,153 @@ impl DemoApp {
             window,
             physical_size.width,
             physical_size.height,
+            settings.graphics.clone(),
+            args.validation,
+            settings.texture_quality,
         )?;
+        {
+            let mut camera = resources.get_mut::<RTSCamera>().unwrap();
+            camera.zoom_scaling = settings.zoom_scaling;
+            camera.rotation = settings.camera_rotation;
+        }
+
+        let fallbacks = {
+            let mut asset_manager = resources.get_mut::<AssetManager>().unwrap();
+            let mut asset_resource = resources.get_mut::<AssetResource>().unwrap();
+            FallbackAssetsResource::new(&mut asset_manager, &mut asset_resource)?
+        };
+        resources.insert(fallbacks);
 
         let simulation = Simulation::new(&resources);
         let print_time_event = crate::time::PeriodicEvent::default();
 
         Ok(DemoApp {
-            ui_state: Default::default(),
+            ui_state,
             scene_manager,
             resources,
             simulation,
             print_time_event,
+            synced_render_options: None,
+            bench_mode: args.bench_mode.then(BenchModeState::new),
         })
     }
 
+    /// Pushes `RenderOptions` into the renderer config and pipeline/mesh option resources.
+    /// Only writes (and locks the target resources) when the options changed since the last sync.
+    #[profiling::function]
+    fn sync_render_settings(&mut self) {
+        let mut render_options = self.resources.get::<RenderOptions>().unwrap().clone();
+        self.resources
+            .get::<PerfExperimentsResource>()
+            .unwrap()
+            .apply(&mut render_options);
+        if self.synced_render_options.as_ref() == Some(&render_options) {
+            return;
+        }
+
+        {
+            let mut render_config_resource =
+                self.resources.get_mut::<RendererConfigResource>().unwrap();
+            render_config_resource
+                .visibility_config
+                .enable_visibility_update = render_options.enable_visibility_update;
+        }
+
+        {
+            let mut pipeline_render_options =
+                self.resources.get_mut::<PipelineRenderOptions>().unwrap();
+            pipeline_render_options.enable_msaa = render_options.enable_msaa;
+            pipeline_render_options.enable_hdr = render_options.enable_hdr;
+            pipeline_render_options.enable_bloom = render_options.enable_bloom;
+            pipeline_render_options.enable_textures = render_options.enable_textures;
+            pipeline_render_options.show_surfaces = render_options.show_surfaces;
+            pipeline_render_options.show_wireframes = render_options.show_wireframes;
+            pipeline_render_options.show_debug3d = render_options.show_debug3d;
+            pipeline_render_options.show_text = render_options.show_text;
+            pipeline_render_options.show_skybox = false;
+            pipeline_render_options.show_feature_toggles = render_options.show_feature_toggles;
+            pipeline_render_options.blur_pass_count = render_options.blur_pass_count;
+            pipeline_render_options.tonemapper_type = render_options.tonemapper_type;
+            pipeline_render_options.enable_visibility_update =
+                render_options.enable_visibility_update;
+        }
+
+        {
+            let mut mesh_render_options = self.resources.get_mut::<MeshRenderOptions>().unwrap();
+            mesh_render_options.show_surfaces = render_options.show_surfaces;
+            mesh_render_options.show_shadows = render_options.show_shadows;
+            mesh_render_options.enable_lighting = render_options.enable_lighting;
+        }
+
+        self.synced_render_options = Some(render_options);
+    }
+
+    /// Collects all resources needed by the render extract jobs and kicks off the next frame.
+    #[profiling::function]
+    fn start_rendering_next_frame(&mut self) -> RafxResult<()> {
+        let dt = self
+            .resources
+            .get::<TimeState>()
+            .unwrap()
+            .previous_update_time();
+
+        let renderer = self.resources.get::<Renderer>().unwrap();
+
+        let mut extract_resources = ExtractResources::default();
+
+        macro_rules! add_to_extract_resources {
+            ($ty: ident) => {
+                #[allow(non_snake_case)]
+                let mut $ty = self.resources.get_mut::<$ty>().unwrap();
+                extract_resources.insert(&mut *$ty);
+            };
+            ($ty: path, $name: ident) => {
+                let mut $name = self.resources.get_mut::<$ty>().unwrap();
+                extract_resources.insert(&mut *$name);
+            };
+        }
+
+        unsafe {
+            let visibility_region = &mut self.simulation.universe().visibility_region;
+            extract_resources.insert(force_to_static_lifetime_mut(visibility_region));
+        }
+        add_to_extract_resources!(RafxSwapchainHelper);
+        add_to_extract_resources!(ViewportsResource);
+        add_to_extract_resources!(AssetManager);
+        add_to_extract_resources!(DynMeshManager);
+        add_to_extract_resources!(TimeState);
+        add_to_extract_resources!(RenderOptions);
+        add_to_extract_resources!(PipelineRenderOptions);
+        add_to_extract_resources!(MeshRenderOptions);
+        add_to_extract_resources!(RendererConfigResource);
+        add_to_extract_resources!(
+            rafx_plugins::features::mesh_adv::MeshAdvRenderObjectSet,
+            mesh_render_object_set
+        );
+        add_to_extract_resources!(
+            crate::features::dyn_mesh::DynMeshRenderObjectSet,
+            dyn_mesh_render_object_set
+        );
+        add_to_extract_resources!(
+            rafx_plugins::features::debug3d::Debug3DResource,
+            debug_draw_3d_resource
+        );
+        add_to_extract_resources!(
+            rafx_plugins::features::debug_pip::DebugPipResource,
+            debug_pip_resource
+        );
+        add_to_extract_resources!(rafx_plugins::features::text::TextResource, text_resource);
+        add_to_extract_resources!(WinitEguiManager, winit_egui_manager);
+        add_to_extract_resources!(camera::RTSCamera, camera);
+        unsafe {
+            let universe = self.simulation.universe();
+            extract_resources.insert(force_to_static_lifetime_mut(&mut universe.world));
+            extract_resources.insert(force_to_static_lifetime_mut(&mut universe.ids));
+        }
+
+        renderer.start_rendering_next_frame(&mut extract_resources, dt)
+    }
+
     pub fn update(&mut self, window: &Window) -> RafxResult<ControlFlow> {
         profiling::scope!("Main Loop");
 
@@ -297,6 +652,10 @@ impl DemoApp {
 
         {
             self.resources.get_mut::<TimeState>().unwrap().update();
+            self.resources
+                .get_mut::<PowerSavingResource>()
+                .unwrap()
+                .frame_started();
         }
 
         {
@@ -315,8 +674,15 @@ impl DemoApp {
         {
             let mut viewports_resource = self.resources.get_mut::<ViewportsResource>().unwrap();
             let mut camera = self.resources.get_mut::<RTSCamera>().unwrap();
+            let mut resize = self.resources.get_mut::<ResizeResource>().unwrap();
+            resize.update();
+            self.resources
+                .get_mut::<DynMeshManager>()
+                .unwrap()
+                .uploads_paused = resize.is_resizing();
+            // the swapchain follows the viewport size, keep it until the resize is over
             let window_size = window.inner_size();
-            if window_size.width > 0 && window_size.height > 0 {
+            if !resize.is_resizing() && window_size.width > 0 && window_size.height > 0 {
                 viewports_resource.main_window_size = RafxExtents2D {
                     width: window_size.width,
                     height: window_size.height,
@@ -330,6 +696,11 @@ impl DemoApp {
         if let SceneManagerAction::Scene(scene) = self.scene_manager.scene_action {
             self.scene_manager
                 .try_cleanup_current_scene(&mut self.simulation, &self.resources);
+            audit::report_leaks("scene cleanup");
+            self.resources
+                .get_mut::<StatsExportResource>()
+                .unwrap()
+                .flush();
 
             {
                 // NOTE(dvd): Legion leaks memory because the entity IDs aren't reset when the
@@ -344,11 +715,13 @@ impl DemoApp {
 
             *self.resources.get_mut::<MeshRenderOptions>().unwrap() = Default::default();
             *self.resources.get_mut::<RenderOptions>().unwrap() = RenderOptions::default_3d();
+            self.synced_render_options = None;
 
             self.scene_manager
                 .try_load_scene(&mut self.simulation, &self.resources, scene);
         }
 
+        let assets_start = rafx::base::Instant::now();
         {
             profiling::scope!("update asset resource");
             let mut asset_resource = self.resources.get_mut::<AssetResource>().unwrap();
@@ -357,24 +730,78 @@ impl DemoApp {
 
         {
             profiling::scope!("update asset loaders");
+            let _region = MemoryRegion::new("assets");
             let mut asset_manager = self.resources.get_mut::<AssetManager>().unwrap();
             asset_manager.update_asset_loaders().unwrap();
         }
 
+        {
+            let asset_resource = self.resources.get::<AssetResource>().unwrap();
+            let mut asset_status = self.resources.get_mut::<AssetStatusResource>().unwrap();
+            asset_status.update(&asset_resource);
+            self.resources
+                .get_mut::<PackBuilderResource>()
+                .unwrap()
+                .update(&self.resources.get::<TextureQualityResource>().unwrap());
+            for error in asset_status.take_new_errors() {
+                self.ui_state.error(tr!(
+                    "assets.import_failed",
+                    path = error.path,
+                    error = error.message
+                ));
+            }
+        }
+        self.resources
+            .get_mut::<HitchResource>()
+            .unwrap()
+            .record(HitchStage::Assets, assets_start);
+
         {
             profiling::scope!("update dyn mesh");
+            let _region = MemoryRegion::new("dyn mesh");
+            let uploads_start = rafx::base::Instant::now();
             let mut asset_manager = self.resources.get_mut::<AssetManager>().unwrap();
             let mut dyn_mesh_manager = self.resources.get_mut::<DynMeshManager>().unwrap();
             dyn_mesh_manager.update(&mut asset_manager);
+            self.resources
+                .get_mut::<HitchResource>()
+                .unwrap()
+                .record(HitchStage::Uploads, uploads_start);
+        }
+
+        if let Some(bench_mode) = &mut self.bench_mode {
+            if bench_mode.update(&self.resources, &mut self.simulation) {
+                control_flow = ControlFlow::Exit;
+            }
         }
 
         {
             let egui_manager = self.resources.get::<WinitEguiManager>().unwrap();
             egui_manager.begin_frame(window)?;
+            let context = self
+                .resources
+                .get::<EguiContextResource>()
+                .unwrap()
+                .context();
+            self.resources
+                .get_mut::<UiStyleResource>()
+                .unwrap()
+                .update(&context);
+            self.resources
+                .get_mut::<CursorResource>()
+                .unwrap()
+                .begin_frame();
+            self.resources
+                .get_mut::<ConsoleResource>()
+                .unwrap()
+                .ui(&context, &self.resources.get::<InputResource>().unwrap());
+            self.run_source_commands();
         }
 
         {
             profiling::scope!("update scene");
+            let _region = MemoryRegion::new("scene");
+            let scene_start = rafx::base::Instant::now();
             self.scene_manager.scene_action = self.scene_manager.update_scene(
                 &mut self.simulation,
                 &mut self.resources,
@@ -383,39 +810,27 @@ impl DemoApp {
             if self.scene_manager.scene_action == SceneManagerAction::Exit {
                 control_flow = ControlFlow::Exit
             }
+            self.simulation.update_transforms();
+            self.resources
+                .get_mut::<HitchResource>()
+                .unwrap()
+                .record(HitchStage::Scene, scene_start);
         }
 
         {
-            let render_options = self.resources.get::<RenderOptions>().unwrap();
-            let mut render_config_resource =
-                self.resources.get_mut::<RendererConfigResource>().unwrap();
-            render_config_resource
-                .visibility_config
-                .enable_visibility_update = render_options.enable_visibility_update;
-            let mut basic_pipeline_render_options =
-                self.resources.get_mut::<PipelineRenderOptions>().unwrap();
-            basic_pipeline_render_options.enable_msaa = render_options.enable_msaa;
-            basic_pipeline_render_options.enable_hdr = render_options.enable_hdr;
-            basic_pipeline_render_options.enable_bloom = render_options.enable_bloom;
-            basic_pipeline_render_options.enable_textures = render_options.enable_textures;
-            basic_pipeline_render_options.show_surfaces = render_options.show_surfaces;
-            basic_pipeline_render_options.show_wireframes = render_options.show_wireframes;
-            basic_pipeline_render_options.show_debug3d = render_options.show_debug3d;
-            basic_pipeline_render_options.show_text = render_options.show_text;
-            basic_pipeline_render_options.show_skybox = false;
-            basic_pipeline_render_options.show_feature_toggles =
-                render_options.show_feature_toggles;
-            basic_pipeline_render_options.blur_pass_count = render_options.blur_pass_count;
-            basic_pipeline_render_options.tonemapper_type = render_options.tonemapper_type;
-            basic_pipeline_render_options.enable_visibility_update =
-                render_options.enable_visibility_update;
-
-            let mut mesh_render_options = self.resources.get_mut::<MeshRenderOptions>().unwrap();
-            mesh_render_options.show_surfaces = render_options.show_surfaces;
-            mesh_render_options.show_shadows = render_options.show_shadows;
-            mesh_render_options.enable_lighting = render_options.enable_lighting;
+            let context = self
+                .resources
+                .get::<EguiContextResource>()
+                .unwrap()
+                .context();
+            self.resources
+                .get_mut::<CursorResource>()
+                .unwrap()
+                .apply(window, &context);
         }
 
+        self.sync_render_settings();
+
         //
         // Close egui input for this frame
         //
@@ -434,70 +849,9 @@ impl DemoApp {
         // Redraw
         //
         {
-            let dt = self
-                .resources
-                .get::<TimeState>()
-                .unwrap()
-                .previous_update_time();
-
             profiling::scope!("Start next frame render");
-            let renderer = self.resources.get::<Renderer>().unwrap();
-
-            let mut extract_resources = ExtractResources::default();
-
-            macro_rules! add_to_extract_resources {
-                ($ty: ident) => {
-                    #[allow(non_snake_case)]
-                    let mut $ty = self.resources.get_mut::<$ty>().unwrap();
-                    extract_resources.insert(&mut *$ty);
-                };
-                ($ty: path, $name: ident) => {
-                    let mut $name = self.resources.get_mut::<$ty>().unwrap();
-                    extract_resources.insert(&mut *$name);
-                };
-            }
-
-            unsafe {
-                let visibility_region = &mut self.simulation.universe().visibility_region;
-                extract_resources.insert(force_to_static_lifetime_mut(visibility_region));
-            }
-            add_to_extract_resources!(RafxSwapchainHelper);
-            add_to_extract_resources!(ViewportsResource);
-            add_to_extract_resources!(AssetManager);
-            add_to_extract_resources!(DynMeshManager);
-            add_to_extract_resources!(TimeState);
-            add_to_extract_resources!(RenderOptions);
-            add_to_extract_resources!(PipelineRenderOptions);
-            add_to_extract_resources!(MeshRenderOptions);
-            add_to_extract_resources!(RendererConfigResource);
-            add_to_extract_resources!(
-                rafx_plugins::features::mesh_adv::MeshAdvRenderObjectSet,
-                mesh_render_object_set
-            );
-            add_to_extract_resources!(
-                crate::features::dyn_mesh::DynMeshRenderObjectSet,
-                dyn_mesh_render_object_set
-            );
-            add_to_extract_resources!(
-                rafx_plugins::features::debug3d::Debug3DResource,
-                debug_draw_3d_resource
-            );
-            add_to_extract_resources!(
-                rafx_plugins::features::debug_pip::DebugPipResource,
-                debug_pip_resource
-            );
-            add_to_extract_resources!(rafx_plugins::features::text::TextResource, text_resource);
-            add_to_extract_resources!(WinitEguiManager, winit_egui_manager);
-            let mut camera = self.resources.get_mut::<camera::RTSCamera>().unwrap();
-            extract_resources.insert(&mut *camera);
-            unsafe {
-                let world = &mut self.simulation.universe().world;
-                extract_resources.insert(force_to_static_lifetime_mut(world));
-            }
-
-            renderer
-                .start_rendering_next_frame(&mut extract_resources, dt)
-                .unwrap();
+            let _region = MemoryRegion::new("render");
+            self.start_rendering_next_frame().unwrap();
         }
 
         let t2 = rafx::base::Instant::now();
@@ -505,6 +859,49 @@ impl DemoApp {
             "[main] start rendering took {} ms",
             (t2 - t1).as_secs_f32() * 1000.0
         );
+        self.resources
+            .get_mut::<FrameTimingResource>()
+            .unwrap()
+            .record(t1 - t0, t2 - t1);
+        self.resources
+            .get_mut::<PerfExperimentsResource>()
+            .unwrap()
+            .record(t2 - t0);
+        {
+            let frame = self.resources.get::<TimeState>().unwrap().update_count();
+            let mut hitches = self.resources.get_mut::<HitchResource>().unwrap();
+            hitches.record(HitchStage::Renderer, t1);
+            hitches.end_frame(frame, t2 - t0);
+        }
+        {
+            let context = self
+                .resources
+                .get::<EguiContextResource>()
+                .unwrap()
+                .context();
+            self.resources.get_mut::<MemoryResource>().unwrap().update(
+                self.simulation.universe(),
+                &self.resources.get::<DynMeshManager>().unwrap(),
+                &context,
+            );
+        }
+        {
+            let mut stats_export = self.resources.get_mut::<StatsExportResource>().unwrap();
+            if stats_export.is_enabled() {
+                let frame = self.resources.get::<TimeState>().unwrap().update_count();
+                let view_proj = self.resources.get::<RTSCamera>().unwrap().view_proj();
+                let universe = self.simulation.universe().stats(view_proj);
+                let mut stats = FrameStats::new(frame, t1 - t0, t2 - t1, universe);
+                let (_, queued, uploaded) = self
+                    .resources
+                    .get::<DynMeshManager>()
+                    .unwrap()
+                    .upload_queue_stats();
+                stats.upload_bytes = uploaded;
+                stats.upload_queue_bytes = queued;
+                stats_export.record(&stats);
+            }
+        }
 
         profiling::finish_frame!();
 
@@ -516,6 +913,60 @@ impl DemoApp {
         Ok(control_flow)
     }
 
+    /// Queues the asset source switches typed in the console.
+    fn run_source_commands(&mut self) {
+        let mut console = self.resources.get_mut::<ConsoleResource>().unwrap();
+        let mut asset_status = self.resources.get_mut::<AssetStatusResource>().unwrap();
+        while let Some(result) = console.take(|command| match command {
+            ConsoleCommand::MountPackfile(path) => Some(asset_status.request_packfile(path)),
+            ConsoleCommand::UseDaemon => Some(asset_status.request_daemon()),
+        }) {
+            match result {
+                Ok(()) => console.print(tr!("console.switching")),
+                Err(err) => console.print(err),
+            }
+        }
+    }
+
+    /// Arguments to rebuild the app with when the user asked for another asset source.
+    fn take_source_switch(&mut self, args: &DemoArgs, daemon_started: bool) -> Option<DemoArgs> {
+        let request = self
+            .resources
+            .get_mut::<AssetStatusResource>()
+            .unwrap()
+            .take_source_request()?;
+        let mut args = args.clone();
+        match request {
+            SourceRequest::Packfile(path) => args.packfile = Some(path),
+            SourceRequest::Daemon => {
+                args.packfile = None;
+                // the in-process daemon keeps running after the switch, connect to it
+                args.external_daemon |= daemon_started;
+            }
+        }
+        Some(args)
+    }
+
+    fn loaded_asset_paths(&self) -> Vec<String> {
+        AssetStatusResource::loaded_paths(&*self.resources.get::<AssetResource>().unwrap())
+    }
+
+    fn expect_assets(&mut self, paths: Vec<String>) {
+        let asset_resource = self.resources.get::<AssetResource>().unwrap();
+        self.resources
+            .get_mut::<AssetStatusResource>()
+            .unwrap()
+            .expect(&asset_resource, paths);
+    }
+
+    /// When to render the next frame, `None` meaning as soon as possible.
+    fn next_frame_time(&self) -> Option<rafx::base::Instant> {
+        self.resources
+            .get::<PowerSavingResource>()
+            .unwrap()
+            .next_frame_time()
+    }
+
     fn process_input(&mut self, event: &Event<()>, window: &Window) -> bool {
         Self::do_process_input(&self.resources, event, window)
     }
@@ -523,6 +974,17 @@ impl DemoApp {
     fn do_process_input(resources: &Resources, event: &Event<()>, window: &Window) -> bool {
         use winit::event::*;
 
+        if let Event::WindowEvent { event, .. } = event {
+            resources
+                .get_mut::<PowerSavingResource>()
+                .unwrap()
+                .handle_window_event(event);
+            resources
+                .get_mut::<ResizeResource>()
+                .unwrap()
+                .handle_window_event(event);
+        }
+
         let egui_manager = resources
             .get::<rafx_plugins::features::egui::WinitEguiManager>()
             .unwrap();
@@ -594,22 +1056,76 @@ impl DemoApp {
 
 impl Drop for DemoApp {
     fn drop(&mut self) {
-        init::rendering_destroy(&mut self.resources).unwrap()
+        // already destroyed when the app is rebuilt for another asset source
+        if self.resources.contains::<Renderer>() {
+            init::rendering_destroy(&mut self.resources).unwrap()
+        }
+    }
+}
+
+/// Rebuilds `app` on the asset source of `new_args`, re-resolving the assets it had loaded.
+/// When the new source fails to start, the app comes back on the old one with the error in the
+/// asset panel. Returns whether the switch happened.
+fn switch_source(app: &mut DemoApp, args: &DemoArgs, new_args: &DemoArgs, window: &Window) -> bool {
+    match &new_args.packfile {
+        Some(path) => log::info!("Mounting packfile {}", path.display()),
+        None => log::info!("Switching to the asset daemon"),
+    }
+    let expected = app.loaded_asset_paths();
+    if let Err(err) = init::rendering_destroy(&mut app.resources) {
+        log::error!("Failed to tear down the renderer: {:?}", err);
     }
+    let (new_app, error) = match DemoApp::init(new_args, window) {
+        Ok(new_app) => (new_app, None),
+        Err(err) => {
+            let error = tr!("assets.source.failed", error = format!("{:?}", err));
+            log::error!("{}", error);
+            // the old source was running a frame ago, if it fails too there is nothing to show
+            (DemoApp::init(args, window).unwrap(), Some(error))
+        }
+    };
+    *app = new_app;
+    app.expect_assets(expected);
+    let switched = error.is_none();
+    if let Some(error) = error {
+        let mut asset_status = app.resources.get_mut::<AssetStatusResource>().unwrap();
+        asset_status.set_source_result(error.clone());
+        app.resources
+            .get_mut::<ConsoleResource>()
+            .unwrap()
+            .print(error);
+    }
+    switched
 }
 
 pub fn update_loop(args: &DemoArgs, window: Window, event_loop: EventLoop<()>) -> RafxResult<()> {
     log::debug!("calling init");
     let mut app = DemoApp::init(args, &window).unwrap();
+    let mut args = args.clone();
+    let mut daemon_started = args.packfile.is_none() && !args.external_daemon;
 
     log::debug!("start update loop");
     event_loop.run(move |event, _, control_flow| match event {
-        Event::MainEventsCleared => {
-            window.request_redraw();
-        }
+        Event::MainEventsCleared => match app.next_frame_time() {
+            Some(next_frame_time) => *control_flow = ControlFlow::WaitUntil(next_frame_time),
+            None => window.request_redraw(),
+        },
         Event::RedrawRequested(_) => {
             *control_flow = app.update(&window).unwrap();
+            // switching sources rebuilds the renderer and the scenes, between two frames
+            if let Some(new_args) = app.take_source_switch(&args, daemon_started) {
+                if switch_source(&mut app, &args, &new_args, &window) {
+                    daemon_started |= new_args.packfile.is_none() && !new_args.external_daemon;
+                    args = new_args;
+                }
+            }
         }
+        // winit exits the process without dropping the app, flush what is buffered
+        Event::LoopDestroyed => app
+            .resources
+            .get_mut::<StatsExportResource>()
+            .unwrap()
+            .flush(),
         event @ _ => {
             if !app.process_input(&event, &window) {
                 *control_flow = ControlFlow::Exit;
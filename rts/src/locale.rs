@@ -0,0 +1,188 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+    sync::{Mutex, RwLock},
+};
+
+use lazy_static::lazy_static;
+
+use crate::settings::Settings;
+
+const LOCALE_DIR: &str = "locale";
+/// Every key should exist in this table, the others fall back to it.
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+/// Looks `$key` up in the string table of the current language. Named arguments replace
+/// `{name}` placeholders: `tr!("hud.fps", fps = 60)`.
+macro_rules! tr {
+    ($key:literal) => {
+        $crate::locale::tr($key)
+    };
+    ($key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::locale::tr_args($key, &[$((stringify!($name), $value.to_string())),+])
+    };
+}
+
+struct StringTables {
+    language: String,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl StringTables {
+    fn load(language: &str) -> Result<Self, String> {
+        let fallback = Self::load_table(FALLBACK_LANGUAGE)?;
+        let strings = if language == FALLBACK_LANGUAGE {
+            fallback.clone()
+        } else {
+            Self::load_table(language)?
+        };
+        Ok(Self {
+            language: language.to_string(),
+            strings,
+            fallback,
+        })
+    }
+
+    /// `locale/<language>.json`, a flat object of keys to strings.
+    fn load_table(language: &str) -> Result<HashMap<String, String>, String> {
+        let path = Path::new(LOCALE_DIR).join(format!("{}.json", language));
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        serde_json::from_str(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+}
+
+lazy_static! {
+    static ref TABLES: RwLock<StringTables> = RwLock::new(StringTables {
+        language: FALLBACK_LANGUAGE.to_string(),
+        strings: Default::default(),
+        fallback: Default::default(),
+    });
+    static ref MISSING: Mutex<BTreeSet<(String, &'static str)>> = Default::default();
+}
+
+pub fn tr(key: &'static str) -> String {
+    let tables = TABLES.read().unwrap();
+    if let Some(text) = tables.strings.get(key) {
+        return text.clone();
+    }
+    let mut missing = MISSING.lock().unwrap();
+    if missing.insert((tables.language.clone(), key)) {
+        log::warn!("Missing {} string: {}", tables.language, key);
+    }
+    tables
+        .fallback
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+pub fn tr_args(key: &'static str, args: &[(&str, String)]) -> String {
+    let mut text = tr(key);
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+/// Switches the string tables, keeping the current ones if `language` doesn't load.
+pub fn set_language(language: &str) -> Result<(), String> {
+    let tables = StringTables::load(language)?;
+    log::info!("Language set to {}", language);
+    *TABLES.write().unwrap() = tables;
+    Ok(())
+}
+
+pub fn language() -> String {
+    TABLES.read().unwrap().language.clone()
+}
+
+/// Language picker for the settings, and the keys used so far that had no string in the
+/// current language (or in any language, when shown as the key itself).
+pub struct LocaleResource {
+    /// Codes of the `locale/*.json` files.
+    languages: Vec<String>,
+    error: Option<String>,
+}
+
+impl LocaleResource {
+    pub fn new(language: &str) -> Self {
+        let error = set_language(language)
+            .or_else(|err| {
+                log::error!("{}", err);
+                set_language(FALLBACK_LANGUAGE)
+            })
+            .err();
+        if let Some(err) = &error {
+            log::error!("{}, UI strings are shown as keys", err);
+        }
+        Self {
+            languages: Self::find_languages(),
+            error,
+        }
+    }
+
+    fn find_languages() -> Vec<String> {
+        let mut languages: Vec<_> = std::fs::read_dir(LOCALE_DIR)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        let path = entry.path();
+                        if path.extension()? != "json" {
+                            return None;
+                        }
+                        Some(path.file_stem()?.to_str()?.to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        languages.sort();
+        languages
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.language"))
+            .id_source("language")
+            .default_open(false)
+            .show(ui, |ui| {
+                let current = language();
+                let mut selected = current.clone();
+                ui.horizontal_wrapped(|ui| {
+                    for language in &self.languages {
+                        ui.radio_value(&mut selected, language.clone(), language);
+                    }
+                });
+                if selected != current {
+                    self.error = set_language(&selected).err();
+                    if self.error.is_none() {
+                        Settings::update(|s| s.language = selected);
+                    }
+                }
+                if let Some(err) = &self.error {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+                let missing: Vec<_> = MISSING
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|(language, _)| *language == current)
+                    .map(|(_, key)| *key)
+                    .collect();
+                if !missing.is_empty() {
+                    egui::CollapsingHeader::new(tr!(
+                        "settings.language.missing",
+                        count = missing.len()
+                    ))
+                    .id_source("missing translations")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        for key in missing {
+                            ui.label(key);
+                        }
+                    });
+                }
+            });
+    }
+}
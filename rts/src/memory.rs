@@ -0,0 +1,240 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use egui::plot::{Legend, Line, Plot, Value, Values};
+use lazy_static::lazy_static;
+use legion::{IntoQuery, Read};
+use rafx::base::Instant;
+
+use crate::{
+    env::{env::TileComponent, simulation::Universe},
+    features::dyn_mesh::DynMeshManager,
+    unit::unit::UnitComponent,
+};
+
+const HISTORY: usize = 120; // samples
+const SAMPLE_INTERVAL: f32 = 1.; // s
+const MB: f64 = 1024. * 1024.;
+
+#[derive(Clone, Copy, Default)]
+struct RegionDelta {
+    allocated: u64, // bytes
+    net: i64,       // bytes
+}
+
+lazy_static! {
+    // deltas of the regions dropped since the last sample, by name
+    static ref REGIONS: Mutex<HashMap<&'static str, RegionDelta>> = Mutex::new(HashMap::new());
+}
+
+/// Counts the global allocator activity while it lives toward the `name` row of the memory
+/// dashboard. The allocator is global, so allocations on other threads meanwhile count too.
+/// Only measures with the `stats_alloc` feature.
+pub struct MemoryRegion<'a> {
+    name: &'static str,
+    #[cfg(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory")))]
+    region: stats_alloc::Region<'a, std::alloc::System>,
+    #[cfg(not(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory"))))]
+    _lifetime: std::marker::PhantomData<&'a ()>,
+}
+
+impl MemoryRegion<'_> {
+    pub fn new(name: &'static str) -> Self {
+        MemoryRegion {
+            name,
+            #[cfg(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory")))]
+            region: stats_alloc::Region::new(crate::STATS_ALLOC),
+            #[cfg(not(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory"))))]
+            _lifetime: std::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory")))]
+impl Drop for MemoryRegion<'_> {
+    fn drop(&mut self) {
+        let change = self.region.change();
+        let mut regions = REGIONS.lock().unwrap();
+        let delta = regions.entry(self.name).or_default();
+        delta.allocated += change.bytes_allocated as u64;
+        delta.net += change.bytes_allocated as i64 - change.bytes_deallocated as i64
+            + change.bytes_reallocated as i64;
+    }
+}
+
+// bytes live on the heap, `None` without the `stats_alloc` feature
+fn heap_bytes() -> Option<i64> {
+    #[cfg(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory")))]
+    {
+        let stats = crate::STATS_ALLOC.stats();
+        Some(
+            stats.bytes_allocated as i64 - stats.bytes_deallocated as i64
+                + stats.bytes_reallocated as i64,
+        )
+    }
+    #[cfg(not(all(feature = "stats_alloc", not(feature = "profile-with-tracy-memory"))))]
+    None
+}
+
+#[derive(Default)]
+struct MemorySample {
+    heap: Option<i64>, // bytes
+    /// Allocator deltas of the regions over the sample interval.
+    regions: HashMap<&'static str, RegionDelta>,
+    voxels: usize,   // bytes
+    dyn_meshes: u64, // bytes, GPU
+    egui: usize,     // bytes
+    entities: usize,
+    units: usize,
+    tiles: usize,
+}
+
+/// Samples memory use once per `SAMPLE_INTERVAL` for the debug UI: the heap and the
+/// allocator deltas of the `MemoryRegion`s, voxel storage and dyn mesh GPU buffers, entity
+/// counts of the world, and the font atlas egui keeps. The last `HISTORY` samples are plotted.
+pub struct MemoryResource {
+    history: VecDeque<MemorySample>,
+    last_sample: Instant,
+}
+
+impl Default for MemoryResource {
+    fn default() -> Self {
+        Self {
+            history: VecDeque::with_capacity(HISTORY),
+            last_sample: Instant::now(),
+        }
+    }
+}
+
+impl MemoryResource {
+    #[profiling::function]
+    pub fn update(
+        &mut self,
+        universe: &Universe,
+        dyn_mesh_manager: &DynMeshManager,
+        egui_context: &egui::CtxRef,
+    ) {
+        let now = Instant::now();
+        if (now - self.last_sample).as_secs_f32() < SAMPLE_INTERVAL {
+            return;
+        }
+        self.last_sample = now;
+        let regions = std::mem::take(&mut *REGIONS.lock().unwrap());
+        let (_, dyn_meshes) = dyn_mesh_manager.memory_stats();
+        let mut units = <Read<UnitComponent>>::query();
+        let mut tiles = <Read<TileComponent>>::query();
+        self.history.push_back(MemorySample {
+            heap: heap_bytes(),
+            regions,
+            voxels: universe.voxel_bytes(),
+            dyn_meshes,
+            egui: egui_context.texture().pixels.len(),
+            entities: universe.world.len(),
+            units: units.iter(&universe.world).count(),
+            tiles: tiles.iter(&universe.world).count(),
+        });
+        if self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    fn line<F>(&self, name: &str, value: F) -> Line
+    where
+        F: Fn(&MemorySample) -> f64,
+    {
+        let values = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| Value::new(i as f64 * SAMPLE_INTERVAL as f64, value(sample)));
+        Line::new(Values::from_values_iter(values)).name(name)
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("memory"))
+            .id_source("memory")
+            .default_open(false)
+            .show(ui, |ui| {
+                let last = match self.history.back() {
+                    Some(last) => last,
+                    None => return,
+                };
+                match last.heap {
+                    Some(heap) => {
+                        ui.label(tr!("memory.heap", mb = format!("{:.1}", heap as f64 / MB)))
+                    }
+                    None => ui.label(tr!("memory.heap.unavailable")),
+                };
+                ui.label(tr!(
+                    "memory.totals",
+                    voxels = format!("{:.1}", last.voxels as f64 / MB),
+                    dyn_meshes = format!("{:.1}", last.dyn_meshes as f64 / MB),
+                    egui = format!("{:.1}", last.egui as f64 / MB)
+                ));
+                ui.label(tr!(
+                    "memory.counts",
+                    entities = last.entities,
+                    units = last.units,
+                    tiles = last.tiles
+                ));
+                let mut plot = Plot::new("memory_totals")
+                    .height(120.)
+                    .include_y(0.)
+                    .legend(Legend::default())
+                    .line(self.line(&tr!("memory.voxels"), |s| s.voxels as f64 / MB))
+                    .line(self.line(&tr!("memory.dyn_meshes"), |s| s.dyn_meshes as f64 / MB))
+                    .line(self.line(&tr!("memory.egui"), |s| s.egui as f64 / MB));
+                if last.heap.is_some() {
+                    plot = plot.line(self.line(&tr!("memory.heap_line"), |s| {
+                        s.heap.unwrap_or(0) as f64 / MB
+                    }));
+                }
+                ui.label(tr!("memory.unit"));
+                ui.add(plot);
+
+                let mut names: Vec<_> = self
+                    .history
+                    .iter()
+                    .flat_map(|sample| sample.regions.keys().copied())
+                    .collect();
+                names.sort_unstable();
+                names.dedup();
+                if !names.is_empty() {
+                    ui.label(tr!("memory.regions"));
+                    let mut plot = Plot::new("memory_regions")
+                        .height(120.)
+                        .include_y(0.)
+                        .legend(Legend::default());
+                    for name in names {
+                        plot = plot.line(self.line(name, |s| {
+                            s.regions
+                                .get(name)
+                                .map_or(0., |delta| delta.allocated as f64)
+                                / MB
+                                / SAMPLE_INTERVAL as f64
+                        }));
+                        let net = last.regions.get(name).map_or(0, |delta| delta.net);
+                        ui.label(tr!(
+                            "memory.region_net",
+                            region = name,
+                            mb = format!("{:+.2}", net as f64 / MB)
+                        ));
+                    }
+                    ui.add(plot);
+                }
+
+                ui.label(tr!("memory.entities"));
+                ui.add(
+                    Plot::new("memory_entities")
+                        .height(120.)
+                        .include_y(0.)
+                        .legend(Legend::default())
+                        .line(self.line(&tr!("memory.entities_line"), |s| s.entities as f64))
+                        .line(self.line(&tr!("memory.units"), |s| s.units as f64))
+                        .line(self.line(&tr!("memory.tiles"), |s| s.tiles as f64)),
+                );
+            });
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+
+use glam::{Vec3, Vec4};
+use rafx_plugins::features::debug3d::Debug3DResource;
+
+use crate::audio::{AudioResource, SoundCategory};
+
+const TOAST_LIFETIME: f32 = 6.; // s
+const MAX_TOASTS: usize = 4;
+const MAX_HISTORY: usize = 50;
+const CUE_DURATION: f32 = 1.; // s
+
+// attacks closer than this to a recent one are the same fight and don't notify again
+const ATTACK_MERGE_RADIUS: f32 = 40.; // m
+const ATTACK_MERGE_TIME: f32 = 15.; // s
+
+const PING_LIFETIME: f32 = 3.; // s
+const PING_PULSES: f32 = 3.;
+const PING_RADIUS: f32 = 12.; // m, when a pulse fades out
+const PING_SEGMENTS: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NotificationKind {
+    UnderAttack,
+    ConstructionComplete,
+    ResearchDone,
+    /// A `TriggerAction::Message` of a fired trigger.
+    Mission,
+}
+
+impl NotificationKind {
+    fn sound(&self) -> &'static str {
+        match self {
+            NotificationKind::UnderAttack => "alert_attack",
+            NotificationKind::ConstructionComplete => "alert_construction",
+            NotificationKind::ResearchDone => "alert_research",
+            NotificationKind::Mission => "alert_mission",
+        }
+    }
+
+    fn color(&self) -> Vec4 {
+        match self {
+            NotificationKind::UnderAttack => Vec4::new(1., 0.2, 0.1, 1.),
+            NotificationKind::ConstructionComplete => Vec4::new(0.3, 0.9, 0.3, 1.),
+            NotificationKind::ResearchDone => Vec4::new(0.3, 0.6, 1., 1.),
+            NotificationKind::Mission => Vec4::new(1., 0.85, 0.1, 1.),
+        }
+    }
+
+    fn color32(&self) -> egui::Color32 {
+        let color = self.color();
+        egui::Color32::from_rgb(
+            (color.x * 255.) as u8,
+            (color.y * 255.) as u8,
+            (color.z * 255.) as u8,
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub message: String,
+    /// Where the camera jumps on click, `None` for events without a place.
+    pub position: Option<Vec3>,
+    /// Seconds since it was raised.
+    pub age: f32,
+}
+
+struct Event {
+    kind: NotificationKind,
+    team: u8,
+    message: String,
+    position: Option<Vec3>,
+}
+
+struct Ping {
+    position: Vec3,
+    color: Vec4,
+    age: f32, // s
+}
+
+/// Alerts for the player's team. Game systems `push` events for any team, `update` drains them
+/// once per frame and turns the ones of the team being played into toasts, a history entry, an
+/// audio cue and a ping on the map. Clicking a toast or a history entry moves the camera there.
+#[derive(Default)]
+pub struct NotificationResource {
+    events: Vec<Event>,
+    toasts: Vec<Notification>,
+    history: VecDeque<Notification>,
+    pings: Vec<Ping>,
+    // recent attack alerts, to merge the hits of one fight
+    attacks: Vec<(u8, Vec3, f32)>,
+}
+
+impl NotificationResource {
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+
+    pub fn push(
+        &mut self,
+        kind: NotificationKind,
+        team: u8,
+        message: String,
+        position: Option<Vec3>,
+    ) {
+        if kind == NotificationKind::UnderAttack {
+            let position = position.unwrap_or(Vec3::ZERO);
+            let merged = self.attacks.iter_mut().find(|(attacked, at, _)| {
+                *attacked == team && at.distance(position) < ATTACK_MERGE_RADIUS
+            });
+            if let Some((_, _, age)) = merged {
+                // a fight that goes on stays quiet until it pauses
+                *age = 0.;
+                return;
+            }
+            self.attacks.push((team, position, 0.));
+        }
+        self.events.push(Event {
+            kind,
+            team,
+            message,
+            position,
+        });
+    }
+
+    /// Call once per frame with unscaled time, `team` is the one being played.
+    pub fn update(&mut self, dt: f32, team: u8, audio: &mut AudioResource) {
+        for toast in &mut self.toasts {
+            toast.age += dt;
+        }
+        self.toasts.retain(|toast| toast.age < TOAST_LIFETIME);
+        for notification in &mut self.history {
+            notification.age += dt;
+        }
+        for ping in &mut self.pings {
+            ping.age += dt;
+        }
+        self.pings.retain(|ping| ping.age < PING_LIFETIME);
+        for (_, _, age) in &mut self.attacks {
+            *age += dt;
+        }
+        self.attacks.retain(|(_, _, age)| *age < ATTACK_MERGE_TIME);
+
+        for event in std::mem::take(&mut self.events) {
+            if event.team != team {
+                continue;
+            }
+            log::info!("Team {}: {}", team, event.message);
+            audio.play(
+                event.kind.sound(),
+                SoundCategory::Ui,
+                None,
+                Some(CUE_DURATION),
+            );
+            if let Some(position) = event.position {
+                self.pings.push(Ping {
+                    position,
+                    color: event.kind.color(),
+                    age: 0.,
+                });
+            }
+            let notification = Notification {
+                kind: event.kind,
+                message: event.message,
+                position: event.position,
+                age: 0.,
+            };
+            self.toasts.push(notification.clone());
+            if self.toasts.len() > MAX_TOASTS {
+                self.toasts.remove(0);
+            }
+            self.history.push_front(notification);
+            self.history.truncate(MAX_HISTORY);
+        }
+    }
+
+    /// Toasts stacked at the top right corner, below `top`. Returns where to move the camera
+    /// when one is clicked.
+    pub fn toasts(&mut self, context: &egui::CtxRef, top: f32) -> Option<Vec3> {
+        if self.toasts.is_empty() {
+            return None;
+        }
+        let mut jump = None;
+        let mut dismissed = None;
+        egui::Area::new("notifications")
+            .anchor(egui::Align2::RIGHT_TOP, [-8., top + 8.])
+            .show(context, |ui| {
+                for (idx, toast) in self.toasts.iter().enumerate().rev() {
+                    let mut frame = egui::Frame::popup(ui.style());
+                    frame.stroke = egui::Stroke::new(1., toast.kind.color32());
+                    frame.show(ui, |ui| {
+                        let button = egui::Button::new(&toast.message)
+                            .text_color(toast.kind.color32())
+                            .frame(false);
+                        if ui.add(button).clicked() {
+                            jump = toast.position;
+                            dismissed = Some(idx);
+                        }
+                    });
+                }
+            });
+        if let Some(idx) = dismissed {
+            self.toasts.remove(idx);
+        }
+        jump
+    }
+
+    /// The notification history, newest first. Returns where to move the camera when an entry
+    /// is clicked.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<Vec3> {
+        let mut jump = None;
+        egui::CollapsingHeader::new(tr!("notifications.history"))
+            .id_source("notification history")
+            .default_open(false)
+            .show(ui, |ui| {
+                if self.history.is_empty() {
+                    ui.label(tr!("notifications.empty"));
+                    return;
+                }
+                for notification in &self.history {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{:.0}s", notification.age));
+                        let button = egui::Button::new(&notification.message)
+                            .text_color(notification.kind.color32())
+                            .frame(false);
+                        if ui.add(button).clicked() {
+                            jump = notification.position;
+                        }
+                    });
+                }
+                if ui.button(tr!("notifications.clear")).clicked() {
+                    self.history.clear();
+                }
+            });
+        jump
+    }
+
+    /// Rings pulsing out of the places of recent notifications.
+    pub fn add_debug_draw(&self, debug_draw: &mut Debug3DResource) {
+        for ping in &self.pings {
+            let t = (ping.age / PING_LIFETIME * PING_PULSES).fract();
+            let radius = PING_RADIUS * t;
+            let color = Vec4::new(ping.color.x, ping.color.y, ping.color.z, 1. - t);
+            let center = ping.position + Vec3::Z * 0.5;
+            let points: Vec<_> = (0..=PING_SEGMENTS)
+                .map(|idx| {
+                    let angle = idx as f32 / PING_SEGMENTS as f32 * std::f32::consts::TAU;
+                    center + radius * Vec3::new(angle.cos(), angle.sin(), 0.)
+                })
+                .collect();
+            for segment in points.windows(2) {
+                debug_draw.add_line(segment[0], segment[1], color);
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use bevy_tasks::{Task, TaskPool, TaskPoolBuilder};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rafx::{base::Instant, distill::daemon::AssetDaemon, renderer::RendererAssetPlugin};
+
+use crate::{
+    assets::{
+        pbr_material::PbrMaterialAssetTypeRendererPlugin,
+        texture_quality::{TextureQuality, TextureQualityResource},
+        tile::TileAssetTypeRendererPlugin,
+        tilesets::TileSetsAssetTypeRendererPlugin,
+    },
+    settings::Settings,
+};
+
+/// Registers the importers of the game's own asset types with a daemon hosted outside the game,
+/// like the one of the cli. The materials are imported at the texture quality of `preset`.
+pub fn configure_asset_daemon(
+    asset_daemon: AssetDaemon,
+    preset: PackPreset,
+    asset_dirs: Vec<PathBuf>,
+) -> AssetDaemon {
+    let quality = preset
+        .quality()
+        .unwrap_or_else(|| Settings::load().texture_quality);
+    let asset_daemon = PbrMaterialAssetTypeRendererPlugin {
+        quality,
+        asset_dirs,
+    }
+    .configure_asset_daemon(asset_daemon);
+    let asset_daemon = TileAssetTypeRendererPlugin.configure_asset_daemon(asset_daemon);
+    TileSetsAssetTypeRendererPlugin.configure_asset_daemon(asset_daemon)
+}
+
+/// Runs the `pack` command of the cli, built next to the game, against the daemon of the game.
+/// Blocks the calling thread until the packfile is written.
+fn pack_with_cli(path: &Path) -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|err| err.to_string())?;
+    let cli = exe.with_file_name(format!("cli{}", std::env::consts::EXE_SUFFIX));
+    let output = Command::new(&cli)
+        .arg("--external-daemon")
+        .arg("pack")
+        .arg(path)
+        .output()
+        .map_err(|err| tr!("pack.cannot_run", path = cli.display(), error = err))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(tr!(
+            "pack.failed",
+            path = path.display(),
+            error = stderr.lines().last().unwrap_or("")
+        ))
+    }
+}
+
+/// Texture quality the materials are imported with for the packfile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PackPreset {
+    /// Whatever the texture quality settings are.
+    Current,
+    Low,
+    Medium,
+    High,
+}
+
+impl PackPreset {
+    pub const ALL: [PackPreset; 4] = [
+        PackPreset::Current,
+        PackPreset::Low,
+        PackPreset::Medium,
+        PackPreset::High,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PackPreset::Current => "current",
+            PackPreset::Low => "low",
+            PackPreset::Medium => "medium",
+            PackPreset::High => "high",
+        }
+    }
+
+    /// `None` for `Current`.
+    fn quality(&self) -> Option<TextureQuality> {
+        let (max_resolution, anisotropy) = match self {
+            PackPreset::Current => return None,
+            PackPreset::Low => (512, 4.),
+            PackPreset::Medium => (1024, 8.),
+            PackPreset::High => (2048, 16.),
+        };
+        Some(TextureQuality {
+            max_resolution,
+            anisotropy,
+            mip_bias: 0.,
+        })
+    }
+}
+
+impl FromStr for PackPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|preset| preset.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "Unknown preset {}, expected current, low, medium or high",
+                    s
+                )
+            })
+    }
+}
+
+enum PackStage {
+    Idle,
+    Packing,
+}
+
+/// Builds a packfile from the running asset daemon by running the `pack` command of the cli on a
+/// background task, `progress_ui` shows a dialog meanwhile. The daemon imports the materials at
+/// the texture quality it was started with, so other presets are packed by the cli hosting a
+/// daemon of its own, which needs the game closed.
+pub struct PackBuilderResource {
+    pub path: String,
+    pub preset: PackPreset,
+    asset_dirs: Vec<PathBuf>,
+    stage: PackStage,
+    start_requested: bool,
+    started: Instant,
+    task_pool: TaskPool,
+    task: Option<Task<()>>,
+    result_tx: Sender<Result<(), String>>,
+    result_rx: Receiver<Result<(), String>>,
+    status: String,
+}
+
+impl PackBuilderResource {
+    /// `asset_dirs` are the directories watched by the daemon, empty when there is none.
+    pub fn new(asset_dirs: Vec<PathBuf>) -> Self {
+        let (result_tx, result_rx) = unbounded();
+        Self {
+            path: "assets.pack".to_string(),
+            preset: PackPreset::Current,
+            asset_dirs,
+            stage: PackStage::Idle,
+            start_requested: false,
+            started: Instant::now(),
+            task_pool: TaskPoolBuilder::new().num_threads(1).build(),
+            task: None,
+            result_tx,
+            result_rx,
+            status: "".to_string(),
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !matches!(self.stage, PackStage::Idle)
+    }
+
+    #[profiling::function]
+    pub fn update(&mut self, texture_quality: &TextureQualityResource) {
+        if std::mem::take(&mut self.start_requested) && !self.is_busy() {
+            self.start(texture_quality);
+        }
+        match self.stage {
+            PackStage::Idle => {}
+            PackStage::Packing => {
+                for result in self.result_rx.try_iter() {
+                    self.task = None;
+                    self.stage = PackStage::Idle;
+                    let duration = (Instant::now() - self.started).as_secs_f32();
+                    self.status = match result {
+                        Ok(()) => {
+                            log::info!("Packed assets into {} in {:.1}s", self.path, duration);
+                            tr!(
+                                "pack.done",
+                                path = self.path,
+                                seconds = format!("{:.1}", duration)
+                            )
+                        }
+                        Err(err) => {
+                            log::error!("{}", err);
+                            err
+                        }
+                    };
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, texture_quality: &TextureQualityResource) {
+        self.started = Instant::now();
+        match self.preset.quality() {
+            // the daemon of the game imported the materials at another quality
+            Some(quality) if !texture_quality.applies() || quality != texture_quality.running() => {
+                self.status = tr!(
+                    "pack.use_cli",
+                    preset = self.preset.name(),
+                    path = self.path.trim()
+                );
+            }
+            _ => self.spawn_pack(),
+        }
+    }
+
+    fn spawn_pack(&mut self) {
+        let path = PathBuf::from(self.path.trim());
+        let result_tx = self.result_tx.clone();
+        self.task = Some(self.task_pool.spawn(async move {
+            let _result = result_tx.send(pack_with_cli(&path));
+        }));
+        self.stage = PackStage::Packing;
+    }
+
+    /// Modal while a pack is being built.
+    pub fn progress_ui(&self, context: &egui::CtxRef) {
+        let (progress, text) = match self.stage {
+            PackStage::Idle => return,
+            PackStage::Packing => (0.6, tr!("pack.writing")),
+        };
+        egui::Window::new(tr!("pack.progress"))
+            .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+            .collapsible(false)
+            .resizable(false)
+            .show(context, |ui| {
+                ui.label(text);
+                ui.add(egui::ProgressBar::new(progress));
+                ui.label(format!(
+                    "{:.0}s",
+                    (Instant::now() - self.started).as_secs_f32()
+                ));
+            });
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("pack"))
+            .id_source("pack assets")
+            .default_open(false)
+            .show(ui, |ui| {
+                if self.asset_dirs.is_empty() {
+                    ui.label(tr!("pack.needs_daemon"));
+                    return;
+                }
+                ui.horizontal(|ui| {
+                    ui.label(tr!("assets.packfile.label"));
+                    ui.text_edit_singleline(&mut self.path);
+                });
+                ui.horizontal(|ui| {
+                    ui.label(tr!("pack.textures"));
+                    for preset in PackPreset::ALL {
+                        ui.radio_value(&mut self.preset, preset, format!("{:?}", preset));
+                    }
+                });
+                if ui.button(tr!("pack.start")).clicked() && !self.is_busy() {
+                    if self.path.trim().is_empty() {
+                        self.status = tr!("pack.no_path");
+                    } else {
+                        self.start_requested = true;
+                    }
+                }
+                if !self.status.is_empty() {
+                    ui.label(&self.status);
+                }
+            });
+    }
+}
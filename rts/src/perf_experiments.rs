@@ -0,0 +1,163 @@
+use std::{collections::VecDeque, fmt, time::Duration};
+
+use crate::RenderOptions;
+
+const EXPERIMENTS: usize = 6;
+// frames averaged for the frame time, before and after a toggle
+const WINDOW: usize = 60; // frames
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Subsystem {
+    /// New chunk mesh jobs, running ones still finish.
+    ChunkMeshing,
+    VisibilityUpdates,
+    Debug3D,
+    /// Everything but the perf experiments window.
+    Egui,
+    /// Unit commands, movement, combat and the universe systems.
+    UnitUpdates,
+    Shadows,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; EXPERIMENTS] = [
+        Subsystem::ChunkMeshing,
+        Subsystem::VisibilityUpdates,
+        Subsystem::Debug3D,
+        Subsystem::Egui,
+        Subsystem::UnitUpdates,
+        Subsystem::Shadows,
+    ];
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Subsystem::ChunkMeshing => tr!("perf.chunk_meshing"),
+            Subsystem::VisibilityUpdates => tr!("perf.visibility_updates"),
+            Subsystem::Debug3D => tr!("perf.debug3d"),
+            Subsystem::Egui => tr!("perf.egui"),
+            Subsystem::UnitUpdates => tr!("perf.unit_updates"),
+            Subsystem::Shadows => tr!("perf.shadows"),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+struct Toggle {
+    /// What changed, eg. "disabling shadows".
+    label: String,
+    baseline: f32, // ms
+}
+
+/// Kill-switches turning subsystems off at runtime, for finding the bottleneck without
+/// recompiling. The frame time averaged over `WINDOW` frames before the last toggle is kept,
+/// so the panel shows what the toggle changed as soon as new frames come in. The switches
+/// override the render options without changing them, and are never saved.
+pub struct PerfExperimentsResource {
+    enabled: [bool; EXPERIMENTS],
+    frames: VecDeque<f32>, // ms
+    last_toggle: Option<Toggle>,
+}
+
+impl Default for PerfExperimentsResource {
+    fn default() -> Self {
+        Self {
+            enabled: [true; EXPERIMENTS],
+            frames: VecDeque::with_capacity(WINDOW),
+            last_toggle: None,
+        }
+    }
+}
+
+impl PerfExperimentsResource {
+    fn index(subsystem: Subsystem) -> usize {
+        Subsystem::ALL.iter().position(|s| *s == subsystem).unwrap()
+    }
+
+    pub fn is_enabled(&self, subsystem: Subsystem) -> bool {
+        self.enabled[Self::index(subsystem)]
+    }
+
+    pub fn any_disabled(&self) -> bool {
+        self.enabled.iter().any(|enabled| !enabled)
+    }
+
+    /// `render_options` with the disabled render subsystems turned off.
+    pub fn apply(&self, render_options: &mut RenderOptions) {
+        render_options.enable_visibility_update &= self.is_enabled(Subsystem::VisibilityUpdates);
+        render_options.show_debug3d &= self.is_enabled(Subsystem::Debug3D);
+        render_options.show_shadows &= self.is_enabled(Subsystem::Shadows);
+    }
+
+    pub fn record(&mut self, frame: Duration) {
+        self.frames.push_back(frame.as_secs_f32() * 1000.);
+        if self.frames.len() > WINDOW {
+            self.frames.pop_front();
+        }
+    }
+
+    fn average(&self) -> f32 {
+        self.frames.iter().sum::<f32>() / self.frames.len().max(1) as f32
+    }
+
+    fn toggled(&mut self, label: String) {
+        log::info!("Perf experiment: {}", label);
+        self.last_toggle = Some(Toggle {
+            label,
+            baseline: self.average(),
+        });
+        self.frames.clear();
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("perf"))
+            .id_source("perf experiments")
+            .default_open(self.any_disabled())
+            .show(ui, |ui| self.experiments_ui(ui));
+    }
+
+    /// The panel alone, in a window, for when egui itself is switched off.
+    pub fn window(&mut self, context: &egui::CtxRef) {
+        egui::Window::new(tr!("perf"))
+            .resizable(false)
+            .show(context, |ui| self.experiments_ui(ui));
+    }
+
+    fn experiments_ui(&mut self, ui: &mut egui::Ui) {
+        for subsystem in Subsystem::ALL {
+            let mut enabled = self.is_enabled(subsystem);
+            if ui
+                .checkbox(&mut enabled, format!("{}", subsystem))
+                .changed()
+            {
+                self.enabled[Self::index(subsystem)] = enabled;
+                let label = if enabled {
+                    tr!("perf.enabling", subsystem = subsystem)
+                } else {
+                    tr!("perf.disabling", subsystem = subsystem)
+                };
+                self.toggled(label);
+            }
+        }
+        let average = self.average();
+        ui.label(tr!(
+            "perf.frame",
+            ms = format!("{:.2}", average),
+            frames = self.frames.len()
+        ));
+        if let Some(toggle) = &self.last_toggle {
+            ui.label(tr!(
+                "perf.toggle",
+                label = toggle.label,
+                before = format!("{:.2}", toggle.baseline),
+                after = format!("{:.2}", average),
+                delta = format!("{:+.2}", average - toggle.baseline)
+            ));
+        }
+        if self.any_disabled() && ui.button(tr!("perf.enable_all")).clicked() {
+            self.enabled = [true; EXPERIMENTS];
+            self.toggled(tr!("perf.enabling_all"));
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::ops::BitOr;
+
+use glam::Vec3;
+use legion::{Entity, IntoQuery, Read, Resources};
+use rafx_plugins::components::TransformComponent;
+#[cfg(feature = "physics")]
+use rapier3d::prelude::ColliderHandle;
+
+#[cfg(feature = "physics")]
+use crate::env::physics::PhysicsResource;
+use crate::{
+    env::simulation::{RayCastResult, Universe},
+    unit::{
+        building,
+        trigger::{TriggerId, TriggerResource},
+        unit::UnitComponent,
+    },
+};
+
+// radius of the sphere units are hit as, scaled by the unit and by `PickRay::unit_scale`
+const UNIT_PICK_RADIUS: f32 = 1.; // m
+const MAX_PICK_DISTANCE: f32 = 2048.; // m
+
+/// Which kinds of objects a pick can hit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PickLayers(u8);
+
+impl PickLayers {
+    /// Solid voxels, buildings included, the hit says which building was hit.
+    pub const TERRAIN: PickLayers = PickLayers(1);
+    pub const UNITS: PickLayers = PickLayers(2);
+    /// Physics bodies, only with the `physics` feature.
+    pub const BODIES: PickLayers = PickLayers(4);
+    pub const TRIGGERS: PickLayers = PickLayers(8);
+
+    pub fn contains(&self, other: PickLayers) -> bool {
+        self.0 & other.0 == other.0 && other.0 != 0
+    }
+}
+
+impl BitOr for PickLayers {
+    type Output = PickLayers;
+
+    fn bitor(self, rhs: PickLayers) -> PickLayers {
+        PickLayers(self.0 | rhs.0)
+    }
+}
+
+pub enum PickTarget {
+    Terrain {
+        voxel: RayCastResult,
+        /// The tile standing over the voxel, if any.
+        building: Option<Entity>,
+    },
+    Unit {
+        entity: Entity,
+        position: Vec3,
+    },
+    #[cfg(feature = "physics")]
+    Body(ColliderHandle),
+    Trigger(TriggerId),
+}
+
+pub struct PickHit {
+    pub target: PickTarget,
+    pub point: Vec3,
+    /// From the ray origin, in meters.
+    pub distance: f32,
+}
+
+impl PickHit {
+    pub fn unit(&self) -> Option<Entity> {
+        match self.target {
+            PickTarget::Unit { entity, .. } => Some(entity),
+            _ => None,
+        }
+    }
+
+    pub fn trigger(&self) -> Option<TriggerId> {
+        match self.target {
+            PickTarget::Trigger(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn building(&self) -> Option<Entity> {
+        match self.target {
+            PickTarget::Terrain { building, .. } => building,
+            _ => None,
+        }
+    }
+
+    /// Where to place things at the hit, on top of the voxel for terrain hits, where the unit
+    /// stands for unit hits.
+    pub fn ground(&self) -> Vec3 {
+        match &self.target {
+            PickTarget::Terrain { voxel, .. } => {
+                let p = voxel.hit;
+                Vec3::new(p.x() as f32, p.y() as f32, p.z() as f32 + 1.)
+            }
+            PickTarget::Unit { position, .. } => *position,
+            _ => self.point,
+        }
+    }
+}
+
+/// Ray from the camera through a point on the screen, see `RTSCamera::pick_ray`.
+pub struct PickRay {
+    pub origin: Vec3,
+    /// Normalized.
+    pub direction: Vec3,
+    /// Units are hit this much bigger than they are, so they stay clickable when zoomed out.
+    pub unit_scale: f32,
+}
+
+impl PickRay {
+    /// Nearest hit across `layers`. Spawning, selection and commands all pick through this, so
+    /// what the cursor is over is the same for all of them.
+    #[profiling::function]
+    pub fn pick(
+        &self,
+        layers: PickLayers,
+        universe: &Universe,
+        resources: &Resources,
+    ) -> Option<PickHit> {
+        let mut hits = vec![];
+        if layers.contains(PickLayers::TERRAIN) {
+            hits.extend(self.pick_terrain(universe));
+        }
+        if layers.contains(PickLayers::UNITS) {
+            hits.extend(self.pick_unit(universe));
+        }
+        #[cfg(feature = "physics")]
+        if layers.contains(PickLayers::BODIES) {
+            if let Some(physics) = resources.get::<PhysicsResource>() {
+                hits.extend(self.pick_body(&physics));
+            }
+        }
+        if layers.contains(PickLayers::TRIGGERS) {
+            if let Some(triggers) = resources.get::<TriggerResource>() {
+                hits.extend(self.pick_trigger(&triggers));
+            }
+        }
+        hits.into_iter().min_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+
+    fn pick_terrain(&self, universe: &Universe) -> Option<PickHit> {
+        let voxel = universe.ray_cast(self.origin, self.direction)?;
+        let building = building::pick_building(&universe.world, voxel.hit);
+        Some(PickHit {
+            point: self.origin + voxel.distance * self.direction,
+            distance: voxel.distance,
+            target: PickTarget::Terrain { voxel, building },
+        })
+    }
+
+    fn pick_unit(&self, universe: &Universe) -> Option<PickHit> {
+        let mut query = <(Entity, Read<TransformComponent>, Read<UnitComponent>)>::query();
+        query
+            .iter(&universe.world)
+            .filter_map(|(entity, transform, _)| {
+                let radius = UNIT_PICK_RADIUS * transform.scale.x.max(1.) * self.unit_scale;
+                let distance = self.sphere_entry(transform.translation, radius)?;
+                Some(PickHit {
+                    target: PickTarget::Unit {
+                        entity: *entity,
+                        position: transform.translation,
+                    },
+                    point: self.origin + distance * self.direction,
+                    distance,
+                })
+            })
+            .min_by(|a, b| {
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    #[cfg(feature = "physics")]
+    fn pick_body(&self, physics: &PhysicsResource) -> Option<PickHit> {
+        let hit = physics.ray_cast_bodies(self.origin, self.direction, MAX_PICK_DISTANCE)?;
+        Some(PickHit {
+            target: PickTarget::Body(hit.collider),
+            point: hit.point,
+            distance: hit.distance,
+        })
+    }
+
+    fn pick_trigger(&self, triggers: &TriggerResource) -> Option<PickHit> {
+        triggers
+            .iter()
+            .filter_map(|(id, trigger)| {
+                let (min, max) = trigger.region.bounds();
+                let distance = self.box_entry(min, max)?;
+                Some(PickHit {
+                    target: PickTarget::Trigger(id),
+                    point: self.origin + distance * self.direction,
+                    distance,
+                })
+            })
+            .min_by(|a, b| {
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    /// Distance to where the ray enters the sphere, 0 if it starts inside.
+    fn sphere_entry(&self, center: Vec3, radius: f32) -> Option<f32> {
+        let to_center = center - self.origin;
+        let along = to_center.dot(self.direction);
+        let off_axis_sq = to_center.length_squared() - along * along;
+        if off_axis_sq > radius * radius {
+            return None;
+        }
+        let half_chord = (radius * radius - off_axis_sq).sqrt();
+        let (enter, exit) = (along - half_chord, along + half_chord);
+        (exit >= 0. && enter <= MAX_PICK_DISTANCE).then(|| enter.max(0.))
+    }
+
+    /// Distance to where the ray enters the box, 0 if it starts inside.
+    fn box_entry(&self, min: Vec3, max: Vec3) -> Option<f32> {
+        let (origin, direction): ([f32; 3], [f32; 3]) = (self.origin.into(), self.direction.into());
+        let (min, max): ([f32; 3], [f32; 3]) = (min.into(), max.into());
+        let mut enter = 0f32;
+        let mut exit = MAX_PICK_DISTANCE;
+        for axis in 0..3 {
+            let (o, d) = (origin[axis], direction[axis]);
+            if d.abs() < f32::EPSILON {
+                if o < min[axis] || o > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (min[axis] - o) / d;
+            let t1 = (max[axis] - o) / d;
+            enter = enter.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+        }
+        (enter <= exit).then(|| enter)
+    }
+}
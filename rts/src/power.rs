@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use rafx::base::Instant;
+use winit::event::WindowEvent;
+
+use crate::settings::Settings;
+
+const IDLE_FRAME_TIME: f32 = 0.1; // s
+
+/// Drops rendering to ~10 FPS and pauses cosmetic systems (weather particles, chunk meshing)
+/// while the window is unfocused or minimized. `enabled` is the user's opt-out, kept in
+/// `Settings`.
+pub struct PowerSavingResource {
+    enabled: bool,
+    focused: bool,
+    minimized: bool,
+    last_frame: Option<Instant>,
+}
+
+impl PowerSavingResource {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            focused: true,
+            minimized: false,
+            last_frame: None,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                if *focused {
+                    self.last_frame = None;
+                }
+            }
+            WindowEvent::Resized(size) => self.minimized = size.width == 0 || size.height == 0,
+            _ => {}
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.enabled && (!self.focused || self.minimized)
+    }
+
+    /// When the next frame should be rendered, `None` meaning right away.
+    pub fn next_frame_time(&self) -> Option<Instant> {
+        if !self.is_idle() {
+            return None;
+        }
+        self.last_frame
+            .map(|last_frame| last_frame + Duration::from_secs_f32(IDLE_FRAME_TIME))
+            .filter(|next| *next > Instant::now())
+    }
+
+    pub fn frame_started(&mut self) {
+        self.last_frame = Some(Instant::now());
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(&mut self.enabled, tr!("settings.power_saving"))
+            .changed()
+        {
+            let enabled = self.enabled;
+            Settings::update(|settings| settings.power_saving = enabled);
+        }
+    }
+}
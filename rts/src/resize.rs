@@ -0,0 +1,38 @@
+use rafx::base::Instant;
+use winit::{dpi::PhysicalSize, event::WindowEvent};
+
+// winit has no events for the start and end of a resize drag, a resize counts as finished once
+// the size stops changing for this long
+const SETTLE_TIME: f32 = 0.2; // s
+
+/// Debounces window resizes. While the window is being resized the viewport keeps its old
+/// size, so the swapchain is recreated once with the final size instead of on every step of
+/// the drag, and chunk meshing and mesh uploads are suspended so they don't compete with it.
+#[derive(Default)]
+pub struct ResizeResource {
+    // latest size and when it was reported, while resizing
+    pending: Option<(PhysicalSize<u32>, Instant)>,
+}
+
+impl ResizeResource {
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::Resized(size) = event {
+            // minimizing is handled by power saving, not a resize to wait out
+            self.pending = (size.width > 0 && size.height > 0).then(|| (*size, Instant::now()));
+        }
+    }
+
+    /// Ends the resize once it settled, call once per frame before reading `is_resizing`.
+    pub fn update(&mut self) {
+        if let Some((size, last_event)) = self.pending {
+            if (Instant::now() - last_event).as_secs_f32() >= SETTLE_TIME {
+                log::info!("Window resized to {}x{}", size.width, size.height);
+                self.pending = None;
+            }
+        }
+    }
+
+    pub fn is_resizing(&self) -> bool {
+        self.pending.is_some()
+    }
+}
@@ -0,0 +1,253 @@
+use building_blocks::core::prelude::*;
+use egui::Align2;
+use glam::Vec3;
+use legion::Resources;
+use rafx::base::Instant;
+use rafx_plugins::features::egui::EguiContextResource;
+use rand::{thread_rng, Rng};
+
+use super::{main_scene::MainScene, GameScene, Scene, SceneManagerAction};
+use crate::{
+    camera::RTSCamera,
+    camera_path::{CameraDirectorResource, CameraKeyframe, CameraPath},
+    env::{
+        biome::BiomeParams,
+        noise::NoiseParams,
+        simulation::{Simulation, TerrainFillStyle},
+        stable_id::StableId,
+        ui::TerrainResetUiState,
+    },
+    input::InputResource,
+    time::TimeState,
+    ui::UiState,
+    unit::unit::{UnitComponent, UnitType},
+};
+
+const TERRAIN_SIZE: u32 = 512; // voxels
+const UNIT_COUNT: usize = 48;
+const UNIT_SPACING: f32 = 4.; // m
+const ORDER_INTERVAL: f32 = 8.; // s
+
+// the units roam this share of the terrain around its center
+const ROAM_FRACTION: f32 = 0.6;
+const ORBIT_KEYFRAMES: usize = 12;
+const ORBIT_DURATION: f32 = 90.; // s
+const ORBIT_HEIGHT: f32 = 120.; // m, above the terrain center
+const ORBIT_RADIUS_FRACTION: f32 = 0.45;
+
+/// The demo the menu falls back to when left alone: a small noise terrain with units wandering
+/// between random targets, filmed by a looping camera path. Any input returns to the menu.
+/// It runs the full main scene update without the UI, so it also works as a soak test, the
+/// frame rate over the run is logged when it ends.
+pub(super) struct AttractScene {
+    main: MainScene,
+    // the director path it replaced, restored in `cleanup`
+    saved_path: Option<(CameraPath, bool)>,
+    units: Vec<StableId>,
+    started: bool,
+    order_timer: f32, // s
+    start: Instant,
+    frames: u64,
+}
+
+impl AttractScene {
+    pub(super) fn new(simulation: &mut Simulation, resources: &Resources) -> Self {
+        let terrain = std::mem::replace(
+            &mut *resources.get_mut::<TerrainResetUiState>().unwrap(),
+            TerrainResetUiState {
+                size: TERRAIN_SIZE,
+                style: TerrainFillStyle::Noise {
+                    params: NoiseParams {
+                        amplitude: 24.,
+                        bias: 16.,
+                        scale: 512.,
+                        ..Default::default()
+                    },
+                    material: "basic_tile".to_string(),
+                    biomes: BiomeParams {
+                        enabled: true,
+                        scale: 256.,
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            },
+        );
+        let main = MainScene::new(simulation, resources);
+        *resources.get_mut::<TerrainResetUiState>().unwrap() = terrain;
+
+        Self {
+            main,
+            saved_path: None,
+            units: vec![],
+            started: false,
+            order_timer: 0.,
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    // units and camera wait for the terrain generation
+    fn start(&mut self, simulation: &mut Simulation, resources: &Resources) {
+        let bounds = *simulation.universe().terrain_bounds();
+        let center = Self::center(&bounds);
+        let units = self.main.units();
+        let side = (UNIT_COUNT as f32).sqrt().ceil() as usize;
+        let half = (side as f32 - 1.) * UNIT_SPACING / 2.;
+        for idx in 0..UNIT_COUNT {
+            let unit_type = UnitType::ALL[idx % UnitType::ALL.len()];
+            let xy = Vec3::new(
+                center.x + (idx % side) as f32 * UNIT_SPACING - half,
+                center.y + (idx / side) as f32 * UNIT_SPACING - half,
+                0.,
+            );
+            let position = Self::ground(simulation, xy) + Vec3::Z;
+            let team = (idx % 2) as u8;
+            match units.spawn_prefab(
+                unit_type.prefab_name(),
+                team,
+                position,
+                resources,
+                simulation.universe(),
+            ) {
+                Ok(id) => self.units.push(id),
+                Err(err) => log::error!("Attract mode: {}", err),
+            }
+        }
+
+        let mut camera = resources.get_mut::<RTSCamera>().unwrap();
+        let mut director = resources.get_mut::<CameraDirectorResource>().unwrap();
+        let look_at = Self::ground(simulation, center);
+        let radius = ORBIT_RADIUS_FRACTION * (bounds.shape.x().min(bounds.shape.y()) as f32);
+        let keyframes = (0..=ORBIT_KEYFRAMES)
+            .map(|idx| {
+                let t = idx as f32 / ORBIT_KEYFRAMES as f32;
+                let angle = t * std::f32::consts::TAU;
+                let (sin, cos) = angle.sin_cos();
+                // the height bobs so the view sweeps between the horizon and the ground
+                let height = ORBIT_HEIGHT * (1. + 0.3 * (2. * angle).sin());
+                CameraKeyframe {
+                    time: t * ORBIT_DURATION,
+                    eye: look_at + Vec3::new(radius * cos, radius * sin, height),
+                    look_at: look_at + Vec3::new(0.3 * radius * cos, -0.3 * radius * sin, 0.),
+                    fov_y: camera.fov_y(),
+                }
+            })
+            .collect();
+        let path = std::mem::replace(&mut director.path, CameraPath { keyframes });
+        self.saved_path = Some((path, director.looping));
+        director.looping = true;
+        director.play();
+        director.update(0., &mut camera);
+
+        self.started = true;
+        self.start = Instant::now();
+        self.frames = 0;
+    }
+
+    fn center(bounds: &Extent3i) -> Vec3 {
+        let min = bounds.minimum;
+        let shape = bounds.shape;
+        Vec3::new(
+            min.x() as f32 + shape.x() as f32 / 2.,
+            min.y() as f32 + shape.y() as f32 / 2.,
+            0.,
+        )
+    }
+
+    fn ground(simulation: &mut Simulation, xy: Vec3) -> Vec3 {
+        let z = simulation
+            .universe()
+            .ray_cast(Vec3::new(xy.x, xy.y, 1024.), -Vec3::Z)
+            .map_or(0., |result| result.hit.z() as f32 + 1.);
+        Vec3::new(xy.x, xy.y, z)
+    }
+
+    fn give_orders(&mut self, simulation: &mut Simulation) {
+        let bounds = *simulation.universe().terrain_bounds();
+        let center = Self::center(&bounds);
+        let half = ROAM_FRACTION * bounds.shape.x().min(bounds.shape.y()) as f32 / 2.;
+        let mut rng = thread_rng();
+        for id in &self.units {
+            let xy = Vec3::new(
+                center.x + rng.gen_range(-half..half),
+                center.y + rng.gen_range(-half..half),
+                0.,
+            );
+            let target = Self::ground(simulation, xy) + Vec3::Z;
+            let universe = simulation.universe();
+            if let Some(mut entry) = universe
+                .ids
+                .entity(*id)
+                .and_then(|e| universe.world.entry(e))
+            {
+                if let Ok(unit) = entry.get_component_mut::<UnitComponent>() {
+                    unit.move_target = Some(target);
+                }
+            }
+        }
+    }
+}
+
+impl GameScene for AttractScene {
+    fn update(
+        &mut self,
+        simulation: &mut Simulation,
+        resources: &mut Resources,
+        ui_state: &mut UiState,
+    ) -> SceneManagerAction {
+        if resources.get::<InputResource>().unwrap().any_input() {
+            return SceneManagerAction::Scene(Scene::Menu);
+        }
+
+        self.main.update_world(simulation, resources, ui_state);
+
+        let universe = simulation.universe();
+        if !self.started
+            && universe.terrain_gen_progress().is_none()
+            && universe.terrain_bounds().num_points() > 0
+        {
+            self.start(simulation, resources);
+        }
+        if self.started {
+            self.frames += 1;
+            self.order_timer -= resources.get::<TimeState>().unwrap().scaled_dt();
+            if self.order_timer <= 0. {
+                self.order_timer = ORDER_INTERVAL;
+                self.give_orders(simulation);
+            }
+        }
+
+        let context = resources.get::<EguiContextResource>().unwrap().context();
+        egui::Area::new("Attract")
+            .anchor(Align2::CENTER_BOTTOM, [0., -40.])
+            .movable(false)
+            .show(&context, |ui| {
+                ui.heading(tr!("attract.hint"));
+            });
+
+        SceneManagerAction::None
+    }
+
+    fn cleanup(&mut self, simulation: &mut Simulation, resources: &Resources) {
+        if self.started {
+            let seconds = (Instant::now() - self.start).as_secs_f32();
+            log::info!(
+                "Attract mode ran {} frames in {:.0} s, {:.1} FPS on average",
+                self.frames,
+                seconds,
+                self.frames as f32 / seconds.max(f32::EPSILON)
+            );
+        }
+        {
+            let mut camera = resources.get_mut::<RTSCamera>().unwrap();
+            let mut director = resources.get_mut::<CameraDirectorResource>().unwrap();
+            director.stop(&mut camera);
+            if let Some((path, looping)) = self.saved_path.take() {
+                director.path = path;
+                director.looping = looping;
+            }
+        }
+        self.main.cleanup(simulation, resources);
+    }
+}
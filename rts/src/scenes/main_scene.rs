@@ -1,4 +1,5 @@
 use distill::loader::handle::Handle;
+use egui::{Align2, Button};
 use glam::{Vec3, Vec4};
 use legion::Resources;
 use rafx::{
@@ -7,16 +8,52 @@ use rafx::{
 };
 use rafx_plugins::{
     assets::font::FontAsset,
-    features::{mesh_adv::MeshAdvRenderOptions as MeshRenderOptions, text::TextResource},
+    features::{egui::EguiContextResource, text::TextResource},
 };
 
-use super::{Scene, SceneManagerAction};
+use super::{set_game_state, GameState, Scene, SceneManagerAction};
 use crate::{
+    accessibility::AccessibilityResource,
+    assets::texture_quality::TextureQualityResource,
+    audio::AudioResource,
     camera::RTSCamera,
-    env::{env::EnvState, simulation::Simulation},
+    camera_path::CameraDirectorResource,
+    camera_shake::CameraShakeResource,
+    cursor::CursorResource,
+    debug_cull::DebugCullResource,
+    env::{
+        analysis::TerrainAnalysisResource,
+        env::EnvState,
+        integrity::IntegrityResource,
+        lighting::LightingResource,
+        save::AutosaveResource,
+        schedule::UniverseScheduleResource,
+        simulation::Simulation,
+        territory::TerritoryResource,
+        throttle::MeshThrottleResource,
+        ui::TerrainResetUiState,
+        visibility_updates::VisibilityUpdateResource,
+        weather::{WeatherPreset, WeatherResource},
+        world_edge::WorldEdgeResource,
+    },
+    file_dialog::FileDialogResource,
+    gpu_info::GpuInfoResource,
     input::{InputResource, KeyboardKey},
+    labels::LabelResource,
+    locale::LocaleResource,
+    notifications::NotificationResource,
+    perf_experiments::{PerfExperimentsResource, Subsystem},
+    power::PowerSavingResource,
+    time::TimeState,
+    tutorial::TutorialResource,
     ui::UiState,
-    unit::unit::UnitsState,
+    ui_style::UiStyleResource,
+    unit::{
+        combat::CombatRulesResource, creep::CreepResource, economy::PlayerEconomy,
+        hit_flash::HitFlashResource, lod::UnitLodResource, orders::PendingOrdersResource,
+        outline::OutlineResource, prefab::PrefabRegistry, tech::TechResource,
+        trigger::TriggerResource, unit::UnitsState, wreck::WreckResource,
+    },
     RenderOptions,
 };
 
@@ -25,7 +62,7 @@ pub struct MainState {}
 impl MainState {
     pub fn update_ui(
         &mut self,
-        _simulation: &mut Simulation,
+        simulation: &mut Simulation,
         resources: &mut Resources,
         ui_state: &mut UiState,
         ui: &mut egui::Ui,
@@ -33,21 +70,72 @@ impl MainState {
         {
             let mut camera = resources.get_mut::<RTSCamera>().unwrap();
             camera.update_ui(ui_state, ui);
+            resources
+                .get_mut::<CameraDirectorResource>()
+                .unwrap()
+                .ui(ui, &mut camera);
+        }
+        {
+            let mut time_state = resources.get_mut::<TimeState>().unwrap();
+            let mut time_scale = time_state.time_scale();
+            let slider = egui::Slider::new(&mut time_scale, 0.25..=4.)
+                .logarithmic(true)
+                .text(tr!("main_scene.game_speed"));
+            if ui.add(slider).changed() {
+                time_state.set_time_scale(time_scale);
+            }
+        }
+
+        resources.get_mut::<WeatherResource>().unwrap().ui(ui);
+        resources
+            .get_mut::<TerrainAnalysisResource>()
+            .unwrap()
+            .ui(ui);
+        simulation.universe().navmesh.ui(ui);
+        {
+            let mut file_dialogs = resources.get_mut::<FileDialogResource>().unwrap();
+            let mut autosave = resources.get_mut::<AutosaveResource>().unwrap();
+            autosave.ui(ui, &mut file_dialogs);
         }
+        resources.get_mut::<IntegrityResource>().unwrap().ui(ui);
+        resources.get_mut::<TerritoryResource>().unwrap().ui(ui);
+        resources.get::<AudioResource>().unwrap().debug_ui(ui);
+        resources.get_mut::<MeshThrottleResource>().unwrap().ui(ui);
+        resources
+            .get_mut::<VisibilityUpdateResource>()
+            .unwrap()
+            .ui(ui);
+        resources.get_mut::<WorldEdgeResource>().unwrap().ui(ui);
+        resources.get::<UniverseScheduleResource>().unwrap().ui(ui);
+        resources.get_mut::<PrefabRegistry>().unwrap().ui(ui);
+        resources.get_mut::<CombatRulesResource>().unwrap().ui(ui);
+        resources.get_mut::<WreckResource>().unwrap().ui(ui);
+        resources.get_mut::<CreepResource>().unwrap().ui(ui);
+        resources.get_mut::<DebugCullResource>().unwrap().ui(ui);
+        resources.get_mut::<LabelResource>().unwrap().ui(ui);
+        #[cfg(feature = "physics")]
+        resources
+            .get_mut::<crate::env::physics::PhysicsResource>()
+            .unwrap()
+            .ui(ui);
 
-        egui::CollapsingHeader::new("Directional light")
+        egui::CollapsingHeader::new(tr!("main_light"))
+            .id_source("directional light")
             .default_open(false)
             .show(ui, |ui| {
-                let ck = egui::Checkbox::new(&mut ui_state.main_light_rotates, "Auto rotates");
+                let ck = egui::Checkbox::new(
+                    &mut ui_state.main_light_rotates,
+                    tr!("main_light.rotates"),
+                );
                 ui.add(ck);
                 if !ui_state.main_light_rotates {
                     ui.add(
                         egui::Slider::new(&mut ui_state.main_light_pitch, 180.0..=360.)
-                            .text("pitch"),
+                            .text(tr!("main_light.pitch")),
                     );
                 }
                 ui.horizontal(|ui| {
-                    ui.label("Color (rgb):");
+                    ui.label(tr!("main_light.color"));
                     let mut r_str = format!("{}", (ui_state.main_light_color.x * 256.) as u8);
                     ui.add(egui::TextEdit::singleline(&mut r_str).desired_width(30.));
                     let mut g_str = format!("{}", (ui_state.main_light_color.y * 256.) as u8);
@@ -65,9 +153,13 @@ impl MainState {
                 });
                 ui.add(
                     egui::Slider::new(&mut ui_state.main_light_intensity, 0.0..=10.0)
-                        .text("Intensity"),
+                        .text(tr!("main_light.intensity")),
                 );
             });
+        resources
+            .get_mut::<LightingResource>()
+            .unwrap()
+            .ui(ui, ui_state);
     }
 }
 
@@ -76,6 +168,7 @@ pub struct MainScene {
     main_state: MainState,
     units: UnitsState,
     env: EnvState,
+    show_settings: bool,
 }
 
 impl MainScene {
@@ -83,14 +176,45 @@ impl MainScene {
         let mut render_options = resources.get_mut::<RenderOptions>().unwrap();
         *render_options = RenderOptions::default_3d();
 
-        let mut mesh_render_options = resources.get_mut::<MeshRenderOptions>().unwrap();
-        mesh_render_options.ambient_light = glam::Vec3::new(0.005, 0.005, 0.005);
-
         let font = {
             let asset_resource = resources.get_mut::<AssetResource>().unwrap();
             asset_resource.load_asset_path::<FontAsset, _>("fonts/mplus-1p-regular.ttf")
         };
 
+        {
+            let mut weather = resources.get_mut::<WeatherResource>().unwrap();
+            weather.ambient_light = glam::Vec3::new(0.005, 0.005, 0.005);
+            weather.set_preset(WeatherPreset::Clear);
+        }
+        resources.get_mut::<AutosaveResource>().unwrap().reset();
+        resources.get_mut::<IntegrityResource>().unwrap().reset();
+        resources.get_mut::<TechResource>().unwrap().reset();
+        resources.get_mut::<PlayerEconomy>().unwrap().reset();
+        resources.get_mut::<WreckResource>().unwrap().reset();
+        resources.get_mut::<NotificationResource>().unwrap().reset();
+        {
+            let terrain = resources.get::<TerrainResetUiState>().unwrap();
+            let mut creeps = resources.get_mut::<CreepResource>().unwrap();
+            creeps.reset();
+            creeps.start(terrain.creeps, terrain.size);
+        }
+        resources.get_mut::<TriggerResource>().unwrap().clear();
+        resources.get_mut::<HitFlashResource>().unwrap().clear();
+        resources
+            .get_mut::<LightingResource>()
+            .unwrap()
+            .start_scene("main");
+        resources
+            .get_mut::<PendingOrdersResource>()
+            .unwrap()
+            .clear();
+        resources.get_mut::<TimeState>().unwrap().set_time_scale(1.);
+        #[cfg(feature = "physics")]
+        resources
+            .get_mut::<crate::env::physics::PhysicsResource>()
+            .unwrap()
+            .reset();
+
         let env = EnvState::new(resources, simulation);
         let units = UnitsState::new(resources);
 
@@ -99,8 +223,113 @@ impl MainScene {
             main_state: MainState {},
             units,
             env,
+            show_settings: false,
+        }
+    }
+
+    /// Updates the terrain, the units and the universe systems, without the UI or the tutorial.
+    pub(super) fn update_world(
+        &mut self,
+        simulation: &mut Simulation,
+        resources: &mut Resources,
+        ui_state: &mut UiState,
+    ) {
+        self.env.update(simulation, resources, ui_state);
+        if Self::units_enabled(resources) {
+            self.units.update(simulation, resources, ui_state);
+            simulation.universe().run_systems(resources);
         }
     }
+
+    fn units_enabled(resources: &Resources) -> bool {
+        resources
+            .get::<PerfExperimentsResource>()
+            .unwrap()
+            .is_enabled(Subsystem::UnitUpdates)
+    }
+
+    pub(super) fn units(&self) -> &UnitsState {
+        &self.units
+    }
+
+    fn pause_menu_ui(&mut self, resources: &Resources) -> SceneManagerAction {
+        let mut action = SceneManagerAction::None;
+
+        let context = resources.get::<EguiContextResource>().unwrap().context();
+        let scale_factor = context.pixels_per_point();
+
+        egui::Window::new(tr!("pause.title"))
+            .anchor(Align2::CENTER_CENTER, [0., 0.])
+            .collapsible(false)
+            .resizable(false)
+            .show(&context, |ui| {
+                let btn_size = [150.0 / scale_factor, 40.0 / scale_factor];
+                if ui
+                    .add_sized(btn_size, Button::new(tr!("pause.resume")))
+                    .clicked()
+                {
+                    set_game_state(resources, GameState::Playing);
+                }
+                if ui
+                    .add_sized(btn_size, Button::new(tr!("pause.restart")))
+                    .clicked()
+                {
+                    action = SceneManagerAction::Scene(Scene::Main);
+                }
+                if ui
+                    .add_sized(btn_size, Button::new(tr!("pause.settings")))
+                    .clicked()
+                {
+                    self.show_settings = !self.show_settings;
+                }
+                let mut autosave = resources.get_mut::<AutosaveResource>().unwrap();
+                let saving = autosave.is_saving();
+                let save = ui.add_sized(
+                    btn_size,
+                    Button::new(if saving {
+                        tr!("pause.saving")
+                    } else {
+                        tr!("pause.save")
+                    }),
+                );
+                if save.clicked() && !saving {
+                    autosave.request_save();
+                }
+                if ui
+                    .add_sized(btn_size, Button::new(tr!("pause.quit_to_menu")))
+                    .clicked()
+                {
+                    action = SceneManagerAction::Scene(Scene::Menu);
+                }
+                if self.show_settings {
+                    ui.separator();
+                    resources.get_mut::<LocaleResource>().unwrap().ui(ui);
+                    resources.get_mut::<RenderOptions>().unwrap().ui(ui);
+                    resources.get_mut::<GpuInfoResource>().unwrap().ui(ui);
+                    resources.get_mut::<UnitLodResource>().unwrap().ui(ui);
+                    resources
+                        .get_mut::<TextureQualityResource>()
+                        .unwrap()
+                        .ui(ui);
+                    resources.get_mut::<UiStyleResource>().unwrap().ui(ui);
+                    resources.get_mut::<OutlineResource>().unwrap().ui(ui);
+                    resources.get_mut::<CameraShakeResource>().unwrap().ui(ui);
+                    resources.get_mut::<AudioResource>().unwrap().ui(ui);
+                    resources
+                        .get_mut::<RTSCamera>()
+                        .unwrap()
+                        .zoom_scaling
+                        .ui(ui);
+                    resources.get_mut::<RTSCamera>().unwrap().rotation.ui(ui);
+                    resources.get_mut::<PowerSavingResource>().unwrap().ui(ui);
+                    resources.get_mut::<CursorResource>().unwrap().ui(ui);
+                    resources.get_mut::<InputResource>().unwrap().ui(ui);
+                    resources.get_mut::<AccessibilityResource>().unwrap().ui(ui);
+                }
+            });
+
+        action
+    }
 }
 
 impl super::GameScene for MainScene {
@@ -112,6 +341,14 @@ impl super::GameScene for MainScene {
     ) -> SceneManagerAction {
         //super::add_light_debug_draw(&resources, &world);
 
+        let paused = resources.get::<GameState>().unwrap().is_paused();
+        if !paused {
+            resources
+                .get_mut::<AccessibilityResource>()
+                .unwrap()
+                .handle_input(resources, ui_state);
+        }
+
         ui_state.update(
             simulation,
             resources,
@@ -121,18 +358,44 @@ impl super::GameScene for MainScene {
         );
 
         self.env.update(simulation, resources, ui_state);
-        self.units.update(simulation, resources, ui_state);
+        if !paused {
+            if Self::units_enabled(resources) {
+                self.units.update(simulation, resources, ui_state);
+                simulation.universe().run_systems(resources);
+            }
+            resources
+                .get_mut::<TutorialResource>()
+                .unwrap()
+                .update(simulation, resources, ui_state);
+            resources
+                .get_mut::<AccessibilityResource>()
+                .unwrap()
+                .update(simulation, resources, ui_state);
+        }
 
         {
             let asset_manager = resources.get::<AssetManager>().unwrap();
+            let viewports_resource = resources.get::<ViewportsResource>().unwrap();
+            let mut text_resource = resources.get_mut::<TextResource>().unwrap();
+            let camera = resources.get::<RTSCamera>().unwrap();
+            let window_size = viewports_resource.main_window_size;
+            resources.get_mut::<LabelResource>().unwrap().update(
+                resources.get::<TimeState>().unwrap().unscaled_dt(),
+                simulation.universe(),
+                &camera,
+                [window_size.width as f32, window_size.height as f32],
+                &mut text_resource,
+                &asset_manager,
+                &self.font,
+            );
             if asset_manager.committed_asset(&self.font).is_some() {
-                let viewports_resource = resources.get::<ViewportsResource>().unwrap();
-                let mut text_resource = resources.get_mut::<TextResource>().unwrap();
-                let camera = resources.get::<RTSCamera>().unwrap();
                 let scale = camera.win_scale_factor;
                 let pos_y = viewports_resource.main_window_size.height as f32 - 30. * scale;
                 text_resource.add_text(
-                    format!("camera: {:.2}m", camera.look_at_dist),
+                    tr!(
+                        "hud.camera_distance",
+                        distance = format!("{:.2}", camera.look_at_dist)
+                    ),
                     Vec3::new(300.0 * scale, pos_y, 0.0),
                     &self.font,
                     20.0 * scale,
@@ -141,17 +404,30 @@ impl super::GameScene for MainScene {
             }
         }
 
-        {
-            let input = resources.get::<InputResource>().unwrap();
-            if input.is_key_just_up(KeyboardKey::Escape) {
-                SceneManagerAction::Scene(Scene::Menu)
+        let action = if paused {
+            self.pause_menu_ui(resources)
+        } else {
+            SceneManagerAction::None
+        };
+
+        let escape = resources
+            .get::<InputResource>()
+            .unwrap()
+            .is_key_just_up(KeyboardKey::Escape);
+        if escape {
+            let state = if paused {
+                GameState::Playing
             } else {
-                SceneManagerAction::None
-            }
+                GameState::Paused
+            };
+            set_game_state(resources, state);
         }
+
+        action
     }
 
-    fn cleanup(&mut self, simulation: &mut Simulation, _resources: &Resources) {
+    fn cleanup(&mut self, simulation: &mut Simulation, resources: &Resources) {
+        resources.get_mut::<TutorialResource>().unwrap().stop();
         simulation.reset();
     }
 }
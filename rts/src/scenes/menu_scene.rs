@@ -19,10 +19,18 @@ use crate::{
     env::simulation::Simulation,
     input::{InputResource, KeyboardKey},
     scenes::Scene,
+    time::TimeState,
+    tutorial::{TutorialResource, TutorialScript},
     ui::UiState,
 };
 
-pub(super) struct MenuScene {}
+// the attract mode starts after this long without input
+const ATTRACT_DELAY: f32 = 60.; // s
+
+pub(super) struct MenuScene {
+    tutorials: Vec<String>,
+    idle: f32, // s
+}
 
 impl MenuScene {
     pub(super) fn new(simulation: &mut Simulation, resources: &Resources) -> Self {
@@ -73,7 +81,10 @@ impl MenuScene {
             debug_name: "main".to_string(),
         });
 
-        MenuScene {}
+        MenuScene {
+            tutorials: TutorialScript::list(),
+            idle: 0.,
+        }
     }
 }
 
@@ -97,10 +108,31 @@ impl super::GameScene for MenuScene {
             .movable(false)
             .show(&context, |ui| {
                 let btn_size = [150.0 / scale_factor, 75.0 / scale_factor];
-                if ui.add_sized(btn_size, Button::new("PLAY")).clicked() {
+                if ui
+                    .add_sized(btn_size, Button::new(tr!("menu.play")))
+                    .clicked()
+                {
                     action = SceneManagerAction::Scene(Scene::Main);
                 }
-                if ui.add_sized(btn_size, Button::new("EXIT")).clicked() {
+                for name in &self.tutorials {
+                    if ui
+                        .add_sized(
+                            btn_size,
+                            Button::new(tr!("menu.tutorial", name = name.to_uppercase())),
+                        )
+                        .clicked()
+                    {
+                        let mut tutorial = resources.get_mut::<TutorialResource>().unwrap();
+                        match tutorial.start(name) {
+                            Ok(()) => action = SceneManagerAction::Scene(Scene::Main),
+                            Err(err) => ui_state.error(err),
+                        }
+                    }
+                }
+                if ui
+                    .add_sized(btn_size, Button::new(tr!("menu.exit")))
+                    .clicked()
+                {
                     action = SceneManagerAction::Exit;
                 }
             });
@@ -112,6 +144,14 @@ impl super::GameScene for MenuScene {
         if input.is_key_just_up(KeyboardKey::S) {
             action = SceneManagerAction::Scene(Scene::Main);
         }
+        if input.any_input() {
+            self.idle = 0.;
+        } else {
+            self.idle += resources.get::<TimeState>().unwrap().unscaled_dt();
+        }
+        if self.idle >= ATTRACT_DELAY && action == SceneManagerAction::None {
+            action = SceneManagerAction::Scene(Scene::Attract);
+        }
 
         action
     }
@@ -13,13 +13,19 @@ use menu_scene::MenuScene;
 mod main_scene;
 use main_scene::MainScene;
 pub use main_scene::MainState;
+mod attract_scene;
+use attract_scene::AttractScene;
 
-use crate::{env::simulation::Simulation, ui::UiState};
+use crate::{
+    env::simulation::Simulation, features::dyn_mesh::DynMeshManager, time::TimeState, ui::UiState,
+};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Scene {
     Menu,
     Main,
+    /// Demo started by the menu when idle, see `AttractScene`.
+    Attract,
 }
 
 fn random_color(rng: &mut impl Rng) -> Vec3 {
@@ -38,9 +44,36 @@ fn create_scene(
     match scene {
         Scene::Menu => Box::new(MenuScene::new(simulation, resources)),
         Scene::Main => Box::new(MainScene::new(simulation, resources)),
+        Scene::Attract => Box::new(AttractScene::new(simulation, resources)),
     }
 }
 
+/// Layer over the active scene. While `Paused` the simulation clock is frozen and the scene
+/// shows its pause menu, rendering and UI keep running.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameState {
+    Playing,
+    Paused,
+}
+
+impl GameState {
+    pub fn is_paused(&self) -> bool {
+        *self == GameState::Paused
+    }
+}
+
+pub fn set_game_state(resources: &Resources, state: GameState) {
+    let mut game_state = resources.get_mut::<GameState>().unwrap();
+    if *game_state != state {
+        log::info!("Game state {:?}", state);
+        *game_state = state;
+    }
+    resources
+        .get_mut::<TimeState>()
+        .unwrap()
+        .set_simulation_paused(state.is_paused());
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SceneManagerAction {
     None,
@@ -86,6 +119,7 @@ impl SceneManager {
             scene.cleanup(simulation, resources);
         }
         //simulation.clear();
+        set_game_state(resources, GameState::Playing);
         log::info!("Load scene {:?}", next_scene);
         self.scene = Some(create_scene(next_scene, simulation, resources));
     }
@@ -108,11 +142,22 @@ impl SceneManager {
         simulation: &mut Simulation,
         resources: &Resources,
     ) {
-        if let Some(scene) = &mut self.scene {
+        // the scene is dropped here so the handles it owns are released before the leak audit
+        if let Some(mut scene) = self.scene.take() {
             scene.cleanup(simulation, resources);
         }
 
-        //simulation.clear();
+        let mut dyn_mesh_manager = resources.get_mut::<DynMeshManager>().unwrap();
+        let (meshes_before, bytes_before) = dyn_mesh_manager.memory_stats();
+        simulation.destroy(&mut dyn_mesh_manager);
+        let (meshes_after, bytes_after) = dyn_mesh_manager.memory_stats();
+        log::info!(
+            "Scene teardown: {} dyn meshes ({:.1} MB) before, {} ({:.1} MB) after",
+            meshes_before,
+            bytes_before as f64 / (1024. * 1024.),
+            meshes_after,
+            bytes_after as f64 / (1024. * 1024.)
+        );
     }
 }
 
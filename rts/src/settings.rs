@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accessibility::AccessibilitySettings, assets::texture_quality::TextureQuality,
+    audio::AudioSettings, camera::CameraRotationSettings, camera_shake::CameraShakeSettings,
+    file_dialog::FileDialogSettings, gpu_info::GraphicsSettings, input::InputConfig,
+    locale::FALLBACK_LANGUAGE, ui_style::UiStyle, unit::lod::UnitLodSettings,
+    unit::outline::TeamPalette, zoom_scaling::ZoomScalingSettings,
+};
+
+const SETTINGS_PATH: &str = "settings.ron";
+
+/// User preferences kept in `settings.ron` in the working directory.
+/// Missing fields fall back to their defaults, so older files keep loading.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub ui_style: UiStyle,
+    /// Throttle rendering while the window is in the background.
+    pub power_saving: bool,
+    /// Draw the mouse cursor with egui instead of the OS cursor.
+    pub software_cursor: bool,
+    pub texture_quality: TextureQuality,
+    pub input: InputConfig,
+    pub team_palette: TeamPalette,
+    pub camera_shake: CameraShakeSettings,
+    /// Code of the string table in `locale/`.
+    pub language: String,
+    pub graphics: GraphicsSettings,
+    pub accessibility: AccessibilitySettings,
+    pub zoom_scaling: ZoomScalingSettings,
+    pub audio: AudioSettings,
+    pub camera_rotation: CameraRotationSettings,
+    pub unit_lod: UnitLodSettings,
+    pub file_dialogs: FileDialogSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            ui_style: Default::default(),
+            power_saving: true,
+            software_cursor: false,
+            texture_quality: Default::default(),
+            input: Default::default(),
+            team_palette: Default::default(),
+            camera_shake: Default::default(),
+            language: FALLBACK_LANGUAGE.to_string(),
+            graphics: Default::default(),
+            accessibility: Default::default(),
+            zoom_scaling: Default::default(),
+            audio: Default::default(),
+            camera_rotation: Default::default(),
+            unit_lod: Default::default(),
+            file_dialogs: Default::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        match std::fs::read_to_string(SETTINGS_PATH) {
+            Ok(contents) => ron::de::from_str(&contents).unwrap_or_else(|err| {
+                log::error!("Cannot parse {}: {}", SETTINGS_PATH, err);
+                Default::default()
+            }),
+            Err(_) => Default::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let contents =
+            ron::ser::to_string_pretty(self, Default::default()).map_err(|err| err.to_string())?;
+        std::fs::write(SETTINGS_PATH, contents)
+            .map_err(|err| format!("Cannot write {}: {}", SETTINGS_PATH, err))
+    }
+
+    /// Loads the file, lets `f` change one section and writes it back.
+    pub fn update<F: FnOnce(&mut Settings)>(f: F) {
+        let mut settings = Self::load();
+        f(&mut settings);
+        if let Err(err) = settings.save() {
+            log::error!("{}", err);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::env::simulation::UniverseStats;
+
+// the file is flushed at least this often, so a crash loses little
+const FLUSH_INTERVAL: u64 = 600; // frames
+
+const CSV_HEADER: &str = "frame,frame_ms,main_ms,render_wait_ms,mesh_jobs,chunk_meshes,\
+visible_chunks,entities,upload_bytes,upload_queue_bytes";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum StatsFormat {
+    Csv,
+    JsonLines,
+}
+
+/// One row of the export. rafx doesn't report the draw calls it records, the visible chunk
+/// meshes are the closest measure of the draw load.
+#[derive(Serialize, Clone, Copy, Default, Debug)]
+pub struct FrameStats {
+    pub frame: u64,
+    pub frame_ms: f32,
+    pub main_ms: f32,
+    pub render_wait_ms: f32,
+    pub mesh_jobs: usize,
+    pub chunk_meshes: usize,
+    pub visible_chunks: usize,
+    pub entities: usize,
+    /// Mesh bytes whose upload started this frame.
+    pub upload_bytes: usize,
+    pub upload_queue_bytes: usize,
+}
+
+impl FrameStats {
+    pub fn new(frame: u64, main: Duration, render_wait: Duration, universe: UniverseStats) -> Self {
+        let main_ms = main.as_secs_f32() * 1000.;
+        let render_wait_ms = render_wait.as_secs_f32() * 1000.;
+        Self {
+            frame,
+            frame_ms: main_ms + render_wait_ms,
+            main_ms,
+            render_wait_ms,
+            mesh_jobs: universe.mesh_jobs,
+            chunk_meshes: universe.chunk_meshes,
+            visible_chunks: universe.visible_chunks,
+            entities: universe.entities,
+            ..Default::default()
+        }
+    }
+
+    fn csv_row(&self) -> String {
+        format!(
+            "{},{:.3},{:.3},{:.3},{},{},{},{},{},{}",
+            self.frame,
+            self.frame_ms,
+            self.main_ms,
+            self.render_wait_ms,
+            self.mesh_jobs,
+            self.chunk_meshes,
+            self.visible_chunks,
+            self.entities,
+            self.upload_bytes,
+            self.upload_queue_bytes
+        )
+    }
+}
+
+/// Appends a row of `FrameStats` per frame to the file given with `--stats-out`, as JSON lines
+/// when its extension is `json` or `jsonl` and as CSV otherwise. Rows are buffered and flushed
+/// periodically, on scene changes and on exit. Writing stops at the first error.
+pub struct StatsExportResource {
+    path: Option<PathBuf>,
+    format: StatsFormat,
+    writer: Option<BufWriter<File>>,
+    rows: u64,
+}
+
+impl StatsExportResource {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let format = match path
+            .as_ref()
+            .and_then(|path| path.extension())
+            .and_then(|extension| extension.to_str())
+        {
+            Some("json") | Some("jsonl") => StatsFormat::JsonLines,
+            _ => StatsFormat::Csv,
+        };
+        let writer = path
+            .as_ref()
+            .and_then(|path| match Self::open(path, format) {
+                Ok(writer) => {
+                    log::info!("Exporting frame stats to {}", path.display());
+                    Some(writer)
+                }
+                Err(err) => {
+                    log::error!("Cannot open stats file {}: {}", path.display(), err);
+                    None
+                }
+            });
+        Self {
+            path,
+            format,
+            writer,
+            rows: 0,
+        }
+    }
+
+    fn open(path: &Path, format: StatsFormat) -> std::io::Result<BufWriter<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if empty && format == StatsFormat::Csv {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn record(&mut self, stats: &FrameStats) {
+        let format = self.format;
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+        let result = match format {
+            StatsFormat::Csv => writeln!(writer, "{}", stats.csv_row()),
+            StatsFormat::JsonLines => serde_json::to_writer(&mut *writer, stats)
+                .map_err(std::io::Error::from)
+                .and_then(|_| writeln!(writer)),
+        };
+        self.rows += 1;
+        match result {
+            Ok(()) if self.rows % FLUSH_INTERVAL == 0 => self.flush(),
+            Ok(()) => {}
+            Err(err) => self.fail(err),
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let result = match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => return,
+        };
+        if let Err(err) = result {
+            self.fail(err);
+        }
+    }
+
+    fn fail(&mut self, err: std::io::Error) {
+        if let Some(path) = &self.path {
+            log::error!("Cannot write stats file {}: {}", path.display(), err);
+        }
+        self.writer = None;
+    }
+}
+
+impl Drop for StatsExportResource {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
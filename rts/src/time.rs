@@ -163,6 +163,11 @@ pub struct TimeState {
     // This contains each context that we support. This will likely be removed in a future version
     // of skulpin
     app_time_context: TimeContext,
+
+    // Game time, stands still while the simulation is paused and runs at `time_scale`
+    simulation_time_context: TimeContext,
+    simulation_paused: bool,
+    time_scale: f32,
 }
 
 impl TimeState {
@@ -175,6 +180,9 @@ impl TimeState {
             app_start_instant: now_instant,
             previous_update_instant: now_instant,
             app_time_context: TimeContext::new(),
+            simulation_time_context: TimeContext::new(),
+            simulation_paused: false,
+            time_scale: 1.,
         }
     }
 
@@ -185,6 +193,12 @@ impl TimeState {
         let elapsed = now_instant - self.previous_update_instant;
         self.previous_update_instant = now_instant;
         self.app_time_context.update(elapsed);
+        self.simulation_time_context
+            .update(if self.simulation_paused {
+                Duration::from_secs(0)
+            } else {
+                elapsed.mul_f32(self.time_scale)
+            });
     }
 
     /// rust Instant object captured when the application started
@@ -197,6 +211,46 @@ impl TimeState {
         &self.app_time_context
     }
 
+    /// Get the simulation time context.
+    pub fn simulation_time_context(&self) -> &TimeContext {
+        &self.simulation_time_context
+    }
+
+    pub fn is_simulation_paused(&self) -> bool {
+        self.simulation_paused
+    }
+
+    /// Freezes the simulation clock. The app clock keeps running so the camera and UI stay alive.
+    pub fn set_simulation_paused(&mut self, paused: bool) {
+        self.simulation_paused = paused;
+    }
+
+    /// Game speed, simulation seconds per real second.
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.);
+    }
+
+    /// Duration of simulation time passed, excluding pauses
+    pub fn simulation_total_time(&self) -> Duration {
+        self.simulation_time_context.total_time
+    }
+
+    /// previous simulation update time in f32 seconds, scaled by the game speed and zero while
+    /// paused. For everything that is part of the simulation.
+    pub fn scaled_dt(&self) -> f32 {
+        self.simulation_time_context.previous_update_dt
+    }
+
+    /// previous update time in f32 seconds, ignoring game speed and pauses. For the camera, UI
+    /// animations and debug overlays, which must keep running while the simulation is paused.
+    pub fn unscaled_dt(&self) -> f32 {
+        self.app_time_context.previous_update_dt
+    }
+
     /// Duration of time passed
     pub fn total_time(&self) -> Duration {
         self.app_time_context.total_time
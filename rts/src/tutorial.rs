@@ -0,0 +1,387 @@
+use std::{collections::HashMap, path::Path};
+
+use egui::{Align2, Color32, Id, LayerId, Order, Stroke};
+use glam::{Vec3, Vec4};
+use legion::{IntoQuery, Read, Resources};
+use rafx_plugins::features::{debug3d::Debug3DResource, egui::EguiContextResource};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::RTSCamera,
+    env::{env::TileComponent, simulation::Simulation},
+    time::TimeState,
+    ui::UiState,
+    unit::{
+        trigger::{
+            Trigger, TriggerCursor, TriggerEventKind, TriggerId, TriggerRegion, TriggerResource,
+        },
+        unit::UnitComponent,
+    },
+};
+
+const TUTORIAL_DIR: &str = "tutorials";
+const TUTORIAL_EXTENSION: &str = "tutorial";
+const MARKER_HEIGHT: f32 = 6.; // voxels above the target
+const MARKER_BOB: f32 = 0.5; // voxels
+const MARKER_COLOR: [f32; 4] = [1., 0.85, 0.1, 1.];
+const HIGHLIGHT_COLOR: Color32 = Color32::from_rgb(255, 215, 25);
+
+/// Part of the screen a step points at.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum TutorialHighlight {
+    /// An area the UI registered this frame with `TutorialResource::mark`, eg. "top_bar",
+    /// "side_panel", "unit_spawn", "tile_spawn" or "terrain_edit".
+    Anchor(String),
+    /// Screen rectangle, min and max corners in points.
+    Rect([f32; 2], [f32; 2]),
+}
+
+/// What the player has to do to finish a step, counted from the moment the step starts.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum TutorialCondition {
+    /// Clicking the "Next" button.
+    Next,
+    Wait(f32), // s
+    /// Moving the camera target this far.
+    MoveCamera(f32), // voxels
+    SpawnUnits(u32),
+    SelectUnits(u32),
+    PlaceBuildings(u32),
+    /// Applying terrain edits (undoable batches) this many times.
+    EditTerrain(u32),
+    /// Moving this many units into the box, watched by a trigger the step adds for its duration.
+    ReachArea {
+        min: Vec3,
+        max: Vec3,
+        units: u32,
+    },
+    /// The trigger with this name firing, eg. one placed in the "Triggers" panel.
+    Trigger(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TutorialStep {
+    pub text: String,
+    #[serde(default)]
+    pub highlight: Option<TutorialHighlight>,
+    /// World position an arrow points down at.
+    #[serde(default)]
+    pub marker: Option<Vec3>,
+    pub complete: TutorialCondition,
+}
+
+/// A sequence of steps, stored as RON in `tutorials/<name>.tutorial`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TutorialScript {
+    pub title: String,
+    pub steps: Vec<TutorialStep>,
+}
+
+impl TutorialScript {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+        ron::de::from_str(&contents).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Names of the scripts in the tutorial directory, sorted.
+    pub fn list() -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(TUTORIAL_DIR)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.extension().and_then(|ext| ext.to_str()) == Some(TUTORIAL_EXTENSION)
+                    })
+                    .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    fn path(name: &str) -> String {
+        format!("{}/{}.{}", TUTORIAL_DIR, name, TUTORIAL_EXTENSION)
+    }
+}
+
+/// Counters the conditions are measured against, taken when a step starts.
+#[derive(Clone, Copy)]
+struct TutorialProgress {
+    elapsed: f32,
+    look_at: Vec3,
+    units: usize,
+    buildings: usize,
+    edits: u64,
+}
+
+impl TutorialProgress {
+    fn take(simulation: &mut Simulation, camera: &RTSCamera) -> Self {
+        let universe = simulation.universe();
+        Self {
+            elapsed: 0.,
+            look_at: camera.look_at,
+            units: <Read<UnitComponent>>::query().iter(&universe.world).count(),
+            buildings: <Read<TileComponent>>::query().iter(&universe.world).count(),
+            edits: universe.edit_count(),
+        }
+    }
+}
+
+struct RunningTutorial {
+    script: TutorialScript,
+    step: usize,
+    // `None` until the first update of the step
+    start: Option<TutorialProgress>,
+    next_clicked: bool,
+    // the trigger a `ReachArea` step added
+    trigger: Option<TriggerId>,
+    // trigger events are read from when the step started
+    cursor: TriggerCursor,
+}
+
+/// Guided overlay for new players. A script is picked from the menu scene and played over the
+/// main scene: each step shows its text in a window, outlines a UI element and points an arrow
+/// at a spot of the world, and moves on once its condition is met. UI code marks the areas
+/// steps can highlight by name with `mark`.
+#[derive(Default)]
+pub struct TutorialResource {
+    running: Option<RunningTutorial>,
+    marks: HashMap<String, egui::Rect>,
+    finished: Option<String>,
+}
+
+impl TutorialResource {
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn start(&mut self, name: &str) -> Result<(), String> {
+        let script = TutorialScript::load(Path::new(&TutorialScript::path(name)))?;
+        log::info!("Tutorial {} started ({} steps)", name, script.steps.len());
+        self.running = Some(RunningTutorial {
+            script,
+            step: 0,
+            start: None,
+            next_clicked: false,
+            trigger: None,
+            cursor: Default::default(),
+        });
+        self.finished = None;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.running = None;
+        self.finished = None;
+        self.marks.clear();
+    }
+
+    /// Records the area a step can highlight as `Anchor(name)`, valid for the current frame.
+    pub fn mark(&mut self, name: &str, rect: egui::Rect) {
+        if self.running.is_some() {
+            self.marks.insert(name.to_string(), rect);
+        }
+    }
+
+    /// Area of `ui` between `top` and its cursor, for marking a section after drawing it.
+    pub fn mark_section(&mut self, name: &str, ui: &egui::Ui, top: f32) {
+        let rect = ui.max_rect();
+        self.mark(
+            name,
+            egui::Rect::from_min_max(
+                egui::pos2(rect.left(), top),
+                egui::pos2(rect.right(), ui.cursor().top()),
+            ),
+        );
+    }
+
+    fn is_complete(
+        condition: &TutorialCondition,
+        start: &TutorialProgress,
+        now: &TutorialProgress,
+        next_clicked: bool,
+        selected: u32,
+        fired: bool,
+    ) -> bool {
+        if next_clicked {
+            return true;
+        }
+        match condition {
+            TutorialCondition::Next => false,
+            TutorialCondition::Wait(time) => start.elapsed >= *time,
+            TutorialCondition::MoveCamera(distance) => {
+                start.look_at.distance(now.look_at) >= *distance
+            }
+            TutorialCondition::SpawnUnits(count) => now.units >= start.units + *count as usize,
+            TutorialCondition::SelectUnits(count) => selected >= *count,
+            TutorialCondition::PlaceBuildings(count) => {
+                now.buildings >= start.buildings + *count as usize
+            }
+            TutorialCondition::EditTerrain(count) => now.edits >= start.edits + *count as u64,
+            TutorialCondition::ReachArea { .. } | TutorialCondition::Trigger(_) => fired,
+        }
+    }
+
+    /// Checks the current step and draws the overlay. Call after the scene UI, so the marks of
+    /// this frame are in.
+    pub fn update(
+        &mut self,
+        simulation: &mut Simulation,
+        resources: &Resources,
+        ui_state: &UiState,
+    ) {
+        let context = resources.get::<EguiContextResource>().unwrap().context();
+        if let Some(title) = &self.finished {
+            let mut close = false;
+            egui::Window::new(title.as_str())
+                .anchor(Align2::CENTER_BOTTOM, [0., -40.])
+                .collapsible(false)
+                .resizable(false)
+                .show(&context, |ui| {
+                    ui.label(tr!("tutorial.done"));
+                    close = ui.button(tr!("tutorial.close")).clicked();
+                });
+            if close {
+                self.finished = None;
+            }
+        }
+        let running = match &mut self.running {
+            Some(running) => running,
+            None => return,
+        };
+
+        let now = {
+            let camera = resources.get::<RTSCamera>().unwrap();
+            TutorialProgress::take(simulation, &camera)
+        };
+        let dt = resources.get::<TimeState>().unwrap().unscaled_dt();
+        let step = &running.script.steps[running.step];
+        let fired = {
+            let mut triggers = resources.get_mut::<TriggerResource>().unwrap();
+            if running.start.is_none() {
+                running.cursor = triggers.subscribe();
+                if let TutorialCondition::ReachArea { min, max, units } = step.complete {
+                    let trigger = Trigger::new(
+                        &running.script.title,
+                        TriggerRegion::Aabb { min, max },
+                        units,
+                    );
+                    running.trigger = Some(triggers.add(trigger));
+                }
+            }
+            let watched = match &step.complete {
+                TutorialCondition::ReachArea { .. } => running.trigger,
+                TutorialCondition::Trigger(name) => triggers.find(name),
+                _ => None,
+            };
+            triggers.read(&mut running.cursor).any(|event| {
+                event.kind == TriggerEventKind::Fired && Some(event.trigger) == watched
+            })
+        };
+        let start = running.start.get_or_insert(now);
+        start.elapsed += dt;
+        let complete = Self::is_complete(
+            &step.complete,
+            start,
+            &now,
+            running.next_clicked,
+            ui_state.unit.selected_count,
+            fired,
+        );
+        if complete {
+            if let Some(trigger) = running.trigger.take() {
+                resources
+                    .get_mut::<TriggerResource>()
+                    .unwrap()
+                    .remove(trigger);
+            }
+            running.step += 1;
+            running.start = None;
+            running.next_clicked = false;
+            if running.step >= running.script.steps.len() {
+                log::info!("Tutorial {} finished", running.script.title);
+                self.finished = Some(running.script.title.clone());
+                self.running = None;
+                self.marks.clear();
+                return;
+            }
+        }
+
+        let step = &running.script.steps[running.step];
+        let step_count = running.script.steps.len();
+        let mut quit = false;
+        egui::Window::new(running.script.title.as_str())
+            .anchor(Align2::CENTER_BOTTOM, [0., -40.])
+            .collapsible(false)
+            .resizable(false)
+            .show(&context, |ui| {
+                ui.label(tr!(
+                    "tutorial.step",
+                    step = running.step + 1,
+                    count = step_count
+                ));
+                ui.separator();
+                ui.label(&step.text);
+                ui.horizontal(|ui| {
+                    let next = if matches!(step.complete, TutorialCondition::Next) {
+                        tr!("tutorial.next")
+                    } else {
+                        tr!("tutorial.skip")
+                    };
+                    // skipping counts as meeting any condition
+                    running.next_clicked |= ui.button(next).clicked();
+                    quit = ui.button(tr!("tutorial.quit")).clicked();
+                });
+            });
+
+        match &step.highlight {
+            Some(TutorialHighlight::Anchor(name)) => {
+                if let Some(rect) = self.marks.get(name) {
+                    Self::draw_highlight(&context, *rect);
+                }
+            }
+            Some(TutorialHighlight::Rect(min, max)) => {
+                let rect = egui::Rect::from_min_max(min.into(), max.into());
+                Self::draw_highlight(&context, rect);
+            }
+            None => {}
+        }
+        if let Some(position) = step.marker {
+            let time = resources
+                .get::<TimeState>()
+                .unwrap()
+                .total_time()
+                .as_secs_f32();
+            let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+            Self::draw_marker(&mut debug_draw, position, time);
+        }
+
+        self.marks.clear();
+        if quit {
+            log::info!("Tutorial {} quit", running.script.title);
+            if let Some(trigger) = running.trigger.take() {
+                resources
+                    .get_mut::<TriggerResource>()
+                    .unwrap()
+                    .remove(trigger);
+            }
+            self.stop();
+        }
+    }
+
+    fn draw_highlight(context: &egui::CtxRef, rect: egui::Rect) {
+        let painter = context.layer_painter(LayerId::new(Order::Foreground, Id::new("tutorial")));
+        painter.rect_stroke(rect.expand(2.), 4., Stroke::new(3., HIGHLIGHT_COLOR));
+    }
+
+    /// A bobbing arrow pointing down at `position`.
+    fn draw_marker(debug_draw: &mut Debug3DResource, position: Vec3, time: f32) {
+        let tip = position + Vec3::Z * MARKER_BOB * (1. + (time * 4.).sin());
+        let base = tip + Vec3::Z * 1.5;
+        let color = Vec4::from(MARKER_COLOR);
+        debug_draw.add_cone(tip, base, 0.6, color, 12);
+        debug_draw.add_line(base, tip + Vec3::Z * MARKER_HEIGHT, color);
+    }
+}
@@ -1,17 +1,38 @@
 use egui::{Align, Checkbox, Color32};
 use glam::Vec4;
 use legion::Resources;
-use rafx::render_feature_renderer_prelude::AssetResource;
+use rafx::{assets::AssetManager, render_feature_renderer_prelude::AssetResource};
 use rafx_plugins::{
     features::egui::EguiContextResource,
     pipelines::modern::ModernPipelineTonemapDebugData as PipelineTonemapDebugData,
 };
 
 use crate::{
+    assets::{
+        browser::AssetBrowserResource, fallback::FallbackAssetsResource,
+        status::AssetStatusResource,
+    },
+    audit,
+    camera::RTSCamera,
+    cursor::CursorResource,
     env::{env::EnvState, simulation::Simulation, ui::EnvUiState},
+    features::dyn_mesh::DynMeshManager,
+    file_dialog::FileDialogResource,
+    frame_timing::{FrameBound, FrameTimingResource},
+    hitch::HitchResource,
+    memory::MemoryResource,
+    notifications::NotificationResource,
+    packfile::PackBuilderResource,
+    perf_experiments::{PerfExperimentsResource, Subsystem},
+    power::PowerSavingResource,
     scenes::MainState,
     time::TimeState,
-    unit::unit::{UnitUiState, UnitsState},
+    tutorial::TutorialResource,
+    ui_style::UiStyleResource,
+    unit::{
+        economy::PlayerEconomy,
+        unit::{UnitUiState, UnitsState},
+    },
     DebugUiState, RenderOptions,
 };
 
@@ -24,7 +45,7 @@ pub enum SpawnMode {
 impl SpawnMode {
     pub fn ui(&mut self, ui: &mut egui::Ui, spawning: &mut bool) {
         let mut multi_spawn = *self == SpawnMode::MultiShot;
-        let ck = Checkbox::new(&mut multi_spawn, "Multi spawn mode");
+        let ck = Checkbox::new(&mut multi_spawn, tr!("ui.multi_spawn"));
         let changed = ui.add(ck).changed();
         if !multi_spawn && changed {
             *spawning = false;
@@ -73,6 +94,32 @@ impl UiState {
     ) {
         let context = resources.get::<EguiContextResource>().unwrap().context();
         profiling::scope!("egui");
+        {
+            let mut perf = resources.get_mut::<PerfExperimentsResource>().unwrap();
+            if !perf.is_enabled(Subsystem::Egui) {
+                perf.window(&context);
+                return;
+            }
+        }
+        let top_bar = resources
+            .get::<PlayerEconomy>()
+            .unwrap()
+            .top_bar(&context, self.unit.team);
+        resources
+            .get_mut::<TutorialResource>()
+            .unwrap()
+            .mark("top_bar", top_bar);
+        let jump = resources
+            .get_mut::<NotificationResource>()
+            .unwrap()
+            .toasts(&context, top_bar.bottom());
+        if let Some(position) = jump {
+            resources.get_mut::<RTSCamera>().unwrap().look_at = position;
+        }
+        resources
+            .get::<PackBuilderResource>()
+            .unwrap()
+            .progress_ui(&context);
         egui::SidePanel::left("ui_panel")
             .default_width(250.)
             .show(&context, |ui| {
@@ -82,32 +129,78 @@ impl UiState {
                     let mut render_options = resources.get_mut::<RenderOptions>().unwrap();
                     let tonemap_debug_data = resources.get::<PipelineTonemapDebugData>().unwrap();
                     let asset_resource = resources.get::<AssetResource>().unwrap();
+                    let mut asset_status = resources.get_mut::<AssetStatusResource>().unwrap();
+                    let mut asset_browser = resources.get_mut::<AssetBrowserResource>().unwrap();
+                    let mut pack_builder = resources.get_mut::<PackBuilderResource>().unwrap();
+                    let asset_manager = resources.get::<AssetManager>().unwrap();
+                    let fallbacks = resources.get::<FallbackAssetsResource>().unwrap();
+                    let mut ui_style = resources.get_mut::<UiStyleResource>().unwrap();
+                    let mut power_saving = resources.get_mut::<PowerSavingResource>().unwrap();
+                    let mut cursor = resources.get_mut::<CursorResource>().unwrap();
+                    let mut dyn_mesh_manager = resources.get_mut::<DynMeshManager>().unwrap();
+                    let frame_timing = resources.get::<FrameTimingResource>().unwrap();
+                    let mut hitches = resources.get_mut::<HitchResource>().unwrap();
+                    let memory = resources.get::<MemoryResource>().unwrap();
+                    let mut perf = resources.get_mut::<PerfExperimentsResource>().unwrap();
+                    let mut file_dialogs = resources.get_mut::<FileDialogResource>().unwrap();
 
                     ui.horizontal(|ui| {
                         ui.with_layout(egui::Layout::right_to_left(), |ui| {
-                            ui.label(format!("Frame: {}", time_state.update_count()));
+                            ui.label(tr!("hud.frame", frame = time_state.update_count()));
                             ui.separator();
-                            ui.label(format!(
-                                "FPS: {:.1}",
-                                time_state.updates_per_second_smoothed()
+                            ui.label(tr!(
+                                "hud.fps",
+                                fps = format!("{:.1}", time_state.updates_per_second_smoothed())
                             ));
+                            if frame_timing.bound() == FrameBound::Render {
+                                ui.label(tr!("hud.render_bound"));
+                            }
+                            let (queued, _, _) = dyn_mesh_manager.upload_queue_stats();
+                            if queued > 0 {
+                                ui.separator();
+                                ui.label(tr!("hud.uploads", count = queued));
+                            }
                         });
                     });
 
-                    egui::CollapsingHeader::new("Options")
+                    egui::CollapsingHeader::new(tr!("options.title"))
+                        .id_source("options")
                         .default_open(true)
                         .show(ui, |ui| {
-                            ui.checkbox(&mut debug_ui_state.show_render_options, "Render options");
-                            ui.checkbox(&mut debug_ui_state.show_asset_list, "Asset list");
-                            ui.checkbox(&mut debug_ui_state.show_tonemap_debug, "Tonemap debug");
+                            ui.checkbox(
+                                &mut debug_ui_state.show_render_options,
+                                tr!("options.render_options"),
+                            );
+                            ui.checkbox(
+                                &mut debug_ui_state.show_asset_list,
+                                tr!("options.asset_list"),
+                            );
+                            ui.checkbox(
+                                &mut debug_ui_state.show_asset_status,
+                                tr!("options.asset_pipeline"),
+                            );
+                            let mut audit_enabled = audit::is_enabled();
+                            if ui
+                                .checkbox(&mut audit_enabled, tr!("options.handle_audit"))
+                                .changed()
+                            {
+                                audit::set_enabled(audit_enabled);
+                            }
+                            ui.checkbox(
+                                &mut debug_ui_state.show_tonemap_debug,
+                                tr!("options.tonemap_debug"),
+                            );
                             ui.checkbox(
                                 &mut debug_ui_state.show_shadow_map_debug,
-                                "Shadow map debug",
+                                tr!("options.shadow_map_debug"),
                             );
 
                             #[cfg(feature = "profile-with-puffin")]
                             if ui
-                                .checkbox(&mut debug_ui_state.show_profiler, "Profiler")
+                                .checkbox(
+                                    &mut debug_ui_state.show_profiler,
+                                    tr!("options.profiler"),
+                                )
                                 .changed()
                             {
                                 log::info!(
@@ -118,8 +211,16 @@ impl UiState {
                             }
                         });
 
+                    frame_timing.ui(ui);
+                    hitches.ui(ui);
+                    memory.ui(ui);
+                    perf.ui(ui);
+                    dyn_mesh_manager.ui(ui);
+                    fallbacks.ui(ui);
+
                     if debug_ui_state.show_render_options {
-                        egui::CollapsingHeader::new("Render options")
+                        egui::CollapsingHeader::new(tr!("options.render_options"))
+                            .id_source("render options")
                             .default_open(true)
                             .show(ui, |ui| {
                                 render_options.ui(ui);
@@ -127,7 +228,8 @@ impl UiState {
                     }
 
                     if debug_ui_state.show_shadow_map_debug {
-                        egui::CollapsingHeader::new("Shadow map debug")
+                        egui::CollapsingHeader::new(tr!("options.shadow_map_debug"))
+                            .id_source("shadow map debug")
                             .default_open(true)
                             .show(ui, |ui| {
                                 //TODO: Build a UI for this
@@ -136,42 +238,39 @@ impl UiState {
                     }
 
                     if debug_ui_state.show_asset_list {
-                        egui::CollapsingHeader::new("Asset list")
+                        egui::CollapsingHeader::new(tr!("options.asset_list"))
+                            .id_source("asset list")
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                asset_browser.ui(
+                                    ui,
+                                    &asset_resource,
+                                    &asset_manager,
+                                    &mut asset_status,
+                                );
+                            });
+                    }
+
+                    if debug_ui_state.show_asset_status {
+                        egui::CollapsingHeader::new(tr!("options.asset_pipeline"))
+                            .id_source("asset pipeline")
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                asset_status.ui(ui, &mut file_dialogs);
+                                pack_builder.ui(ui);
+                            });
+                    }
+
+                    ui_style.ui(ui);
+                    power_saving.ui(ui);
+                    cursor.ui(ui);
+
+                    if audit::is_enabled() {
+                        egui::CollapsingHeader::new(tr!("options.handle_audit"))
+                            .id_source("handle audit")
                             .default_open(true)
                             .show(ui, |ui| {
-                                egui::ScrollArea::vertical()
-                                    .max_height(400.)
-                                    .show(ui, |ui| {
-                                        let loader = asset_resource.loader();
-                                        let mut asset_info = loader
-                                            .get_active_loads()
-                                            .into_iter()
-                                            .map(|item| loader.get_load_info(item))
-                                            .collect::<Vec<_>>();
-                                        asset_info.sort_by(|x, y| {
-                                            x.as_ref()
-                                                .map(|x| &x.path)
-                                                .cmp(&y.as_ref().map(|y| &y.path))
-                                        });
-                                        for info in asset_info {
-                                            if let Some(info) = info {
-                                                let id = info.asset_id;
-                                                let _res = ui.selectable_label(
-                                                    false,
-                                                    format!(
-                                                        "{}:{} .. {}",
-                                                        info.file_name
-                                                            .unwrap_or_else(|| "???".to_string()),
-                                                        info.asset_name
-                                                            .unwrap_or_else(|| format!("{}", id)),
-                                                        info.refs
-                                                    ),
-                                                );
-                                            } else {
-                                                ui.label("NO INFO");
-                                            }
-                                        }
-                                    });
+                                audit::ui(ui);
                             });
                     }
 
@@ -182,7 +281,8 @@ impl UiState {
                         .enable_debug_data_collection = debug_ui_state.show_tonemap_debug;
 
                     if debug_ui_state.show_tonemap_debug {
-                        egui::CollapsingHeader::new("Tonemap debug")
+                        egui::CollapsingHeader::new(tr!("options.tonemap_debug"))
+                            .id_source("tonemap debug")
                             .default_open(true)
                             .show(ui, |ui| {
                                 let data = tonemap_debug_data.inner.lock().unwrap();
@@ -248,6 +348,11 @@ impl UiState {
                         }
                     });
                 }
+
+                resources
+                    .get_mut::<TutorialResource>()
+                    .unwrap()
+                    .mark("side_panel", ui.max_rect());
             });
     }
 
@@ -256,6 +361,11 @@ impl UiState {
         log::error!("{}", message);
     }
 
+    /// The error shown in the side panel, empty once dismissed.
+    pub fn last_error(&self) -> &str {
+        &self.error
+    }
+
     pub fn combo_box<'a>(
         ui: &mut egui::Ui,
         list: &'a Vec<String>,
@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use egui::{Color32, CtxRef, FontDefinitions, Style, Visuals};
+use rafx::base::Instant;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+// changes are written to disk once the sliders stop moving
+const SAVE_DELAY: f32 = 1.; // s
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UiTheme {
+    Dark,
+    Light,
+}
+
+impl Display for UiTheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            UiTheme::Dark => write!(f, "Dark"),
+            UiTheme::Light => write!(f, "Light"),
+        }
+    }
+}
+
+/// Look of the egui interface. `scale` multiplies text and spacing on top of the window's
+/// scale factor, `font_size` only the text.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct UiStyle {
+    pub theme: UiTheme,
+    pub accent: [u8; 3],
+    pub scale: f32,
+    pub font_size: f32,
+}
+
+impl Default for UiStyle {
+    fn default() -> Self {
+        Self {
+            theme: UiTheme::Dark,
+            accent: [0, 92, 128],
+            scale: 1.,
+            font_size: 1.,
+        }
+    }
+}
+
+impl UiStyle {
+    pub fn apply(&self, context: &CtxRef) {
+        let accent = Color32::from_rgb(self.accent[0], self.accent[1], self.accent[2]);
+        let mut visuals = match self.theme {
+            UiTheme::Dark => Visuals::dark(),
+            UiTheme::Light => Visuals::light(),
+        };
+        visuals.selection.bg_fill = accent;
+        visuals.hyperlink_color = accent;
+        visuals.widgets.active.bg_fill = accent;
+
+        let mut style = Style::default();
+        style.visuals = visuals;
+        let spacing = &mut style.spacing;
+        spacing.item_spacing = spacing.item_spacing * self.scale;
+        spacing.button_padding = spacing.button_padding * self.scale;
+        spacing.interact_size = spacing.interact_size * self.scale;
+        spacing.indent *= self.scale;
+        spacing.slider_width *= self.scale;
+        spacing.icon_width *= self.scale;
+        context.set_style(style);
+
+        let mut fonts = FontDefinitions::default();
+        for (_, size) in fonts.family_and_size.values_mut() {
+            *size *= self.scale * self.font_size;
+        }
+        context.set_fonts(fonts);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(tr!("settings.ui_style.theme"));
+            for theme in [UiTheme::Dark, UiTheme::Light] {
+                ui.radio_value(&mut self.theme, theme, format!("{}", theme));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(tr!("settings.ui_style.accent"));
+            ui.color_edit_button_srgb(&mut self.accent);
+        });
+        ui.add(egui::Slider::new(&mut self.scale, 0.5..=3.0).text(tr!("settings.ui_style.scale")));
+        ui.add(
+            egui::Slider::new(&mut self.font_size, 0.5..=2.0)
+                .text(tr!("settings.ui_style.font_size")),
+        );
+        if ui.button(tr!("settings.defaults")).clicked() {
+            *self = Default::default();
+        }
+    }
+}
+
+/// Applies the UI style to the egui context whenever it changes and persists it in `Settings`.
+pub struct UiStyleResource {
+    pub style: UiStyle,
+    applied: Option<UiStyle>,
+    changed_at: Option<Instant>,
+}
+
+impl UiStyleResource {
+    pub fn new(style: UiStyle) -> Self {
+        Self {
+            style,
+            applied: None,
+            changed_at: None,
+        }
+    }
+
+    pub fn update(&mut self, context: &CtxRef) {
+        if self.applied != Some(self.style) {
+            if self.applied.is_some() {
+                self.changed_at = Some(Instant::now());
+            }
+            self.style.apply(context);
+            self.applied = Some(self.style);
+        }
+        if let Some(changed_at) = self.changed_at {
+            if (Instant::now() - changed_at).as_secs_f32() > SAVE_DELAY {
+                self.changed_at = None;
+                let style = self.style;
+                Settings::update(|settings| settings.ui_style = style);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.ui_style"))
+            .id_source("ui style")
+            .default_open(false)
+            .show(ui, |ui| {
+                self.style.ui(ui);
+            });
+    }
+}
@@ -0,0 +1,164 @@
+use building_blocks::prelude::*;
+use glam::{Vec3, Vec4};
+use legion::{Entity, EntityStore, IntoQuery, Read, World};
+use rafx::assets::AssetManager;
+use rafx_plugins::{components::TransformComponent, features::debug3d::Debug3DResource};
+
+use crate::{
+    debug_cull::DebugCullResource,
+    env::{env::TileComponent, stable_id::StableId},
+    unit::garrison::GarrisonComponent,
+};
+
+/// Order given from the building panel.
+#[derive(Clone, Copy, Debug)]
+pub enum BuildingAction {
+    /// Sends the selected workers to the repair task of the building.
+    Repair(StableId),
+    /// Removes the building and its voxels, unloading its garrison first.
+    Demolish(StableId),
+}
+
+/// Ground cells `[min_x, min_y, max_x, max_y)` covered by a tile.
+pub fn footprint(transform: &TransformComponent, tile: &TileComponent) -> Option<[i32; 4]> {
+    let [w, h] = tile.properties.footprint?;
+    let min_x = transform.translation.x.floor() as i32 - w as i32 / 2;
+    let min_y = transform.translation.y.floor() as i32 - h as i32 / 2;
+    Some([min_x, min_y, min_x + w as i32, min_y + h as i32])
+}
+
+/// The tile standing over the voxel `hit`, if any.
+pub fn pick_building(world: &World, hit: Point3i) -> Option<Entity> {
+    let mut query = <(Entity, Read<TransformComponent>, Read<TileComponent>)>::query();
+    query
+        .iter(world)
+        .find(|(_, transform, tile)| {
+            let [x0, y0, x1, y1] = match footprint(transform, tile) {
+                Some(footprint) => footprint,
+                None => return false,
+            };
+            hit.x() >= x0
+                && hit.x() < x1
+                && hit.y() >= y0
+                && hit.y() < y1
+                && hit.z() >= transform.translation.z as i32
+        })
+        .map(|(entity, _, _)| *entity)
+}
+
+/// Type, health and garrison of the selected buildings, with their actions.
+pub fn building_panel(
+    ui: &mut egui::Ui,
+    world: &World,
+    asset_manager: &AssetManager,
+    workers_selected: bool,
+) -> Option<BuildingAction> {
+    let mut query = <(
+        Entity,
+        Read<StableId>,
+        Read<TransformComponent>,
+        Read<TileComponent>,
+    )>::query();
+    let selected: Vec<_> = query
+        .iter(world)
+        .filter(|(_, _, _, tile)| tile.selected)
+        .collect();
+    if selected.is_empty() {
+        return None;
+    }
+    let mut action = None;
+    egui::CollapsingHeader::new(tr!("building"))
+        .id_source("building selection")
+        .default_open(true)
+        .show(ui, |ui| {
+            for (entity, id, transform, tile) in selected {
+                let name = asset_manager
+                    .committed_asset(&tile.asset)
+                    .map_or(tr!("building.loading"), |asset| asset.inner.name.clone());
+                let p = transform.translation;
+                ui.label(tr!(
+                    "building.position",
+                    name = name,
+                    position = format!("({:.0}, {:.0}, {:.0})", p.x, p.y, p.z)
+                ));
+                let max_health = tile.properties.max_health;
+                ui.label(tr!(
+                    "building.health",
+                    health = format!("{:.0}", tile.health * max_health),
+                    max = format!("{:.0}", max_health)
+                ));
+                if let Some([w, h]) = tile.properties.footprint {
+                    ui.label(if tile.properties.walkable {
+                        tr!("building.footprint.walkable", w = w, h = h)
+                    } else if tile.properties.wall {
+                        tr!("building.footprint.wall", w = w, h = h)
+                    } else {
+                        tr!("building.footprint", w = w, h = h)
+                    });
+                }
+                if let Ok(entry) = world.entry_ref(*entity) {
+                    if let Ok(garrison) = entry.get_component::<GarrisonComponent>() {
+                        ui.label(tr!(
+                            "building.garrison",
+                            count = garrison.passengers.len(),
+                            capacity = garrison.capacity
+                        ));
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if tile.health < 1. {
+                        if !workers_selected {
+                            ui.label(tr!("building.repair.no_workers"));
+                        } else if ui
+                            .button(tr!("building.repair"))
+                            .on_hover_text(tr!("building.repair.hint"))
+                            .clicked()
+                        {
+                            action = Some(BuildingAction::Repair(*id));
+                        }
+                    }
+                    if ui
+                        .button(tr!("building.demolish"))
+                        .on_hover_text(tr!("building.demolish.hint"))
+                        .clicked()
+                    {
+                        action = Some(BuildingAction::Demolish(*id));
+                    }
+                });
+                ui.separator();
+            }
+        });
+    action
+}
+
+/// Footprint outlines of the selected buildings.
+pub fn add_debug_draw(
+    world: &World,
+    debug_draw: &mut Debug3DResource,
+    cull: &mut DebugCullResource,
+) {
+    let color = Vec4::new(1., 1., 1., 1.);
+    let mut query = <(Read<TransformComponent>, Read<TileComponent>)>::query();
+    for (transform, tile) in query.iter(world).filter(|(_, tile)| tile.selected) {
+        let [x0, y0, x1, y1] = match footprint(transform, tile) {
+            Some(footprint) => footprint,
+            None => continue,
+        };
+        let z = transform.translation.z + 0.1;
+        if !cull.is_line_visible(
+            Vec3::new(x0 as f32, y0 as f32, z),
+            Vec3::new(x1 as f32, y1 as f32, z),
+        ) {
+            continue;
+        }
+        let corners = [
+            Vec3::new(x0 as f32, y0 as f32, z),
+            Vec3::new(x1 as f32, y0 as f32, z),
+            Vec3::new(x1 as f32, y1 as f32, z),
+            Vec3::new(x0 as f32, y1 as f32, z),
+        ];
+        for idx in 0..4 {
+            debug_draw.add_line(corners[idx], corners[(idx + 1) % 4], color);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::unit::tech::UnitDef;
+
+const RULES_PATH: &str = "combat_rules.ron";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DamageType {
+    Kinetic,
+    Explosive,
+}
+
+impl DamageType {
+    pub const ALL: [DamageType; 2] = [DamageType::Kinetic, DamageType::Explosive];
+}
+
+impl Display for DamageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            DamageType::Kinetic => write!(f, "{}", tr!("combat.damage.kinetic")),
+            DamageType::Explosive => write!(f, "{}", tr!("combat.damage.explosive")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ArmorClass {
+    Light,
+    Medium,
+    Heavy,
+}
+
+impl ArmorClass {
+    pub const ALL: [ArmorClass; 3] = [ArmorClass::Light, ArmorClass::Medium, ArmorClass::Heavy];
+}
+
+impl Display for ArmorClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            ArmorClass::Light => write!(f, "{}", tr!("combat.armor.light")),
+            ArmorClass::Medium => write!(f, "{}", tr!("combat.armor.medium")),
+            ArmorClass::Heavy => write!(f, "{}", tr!("combat.armor.heavy")),
+        }
+    }
+}
+
+/// Damage of `damage` hits on `armor` is multiplied by `multiplier`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct DamageRule {
+    pub damage: DamageType,
+    pub armor: ArmorClass,
+    pub multiplier: f32,
+}
+
+/// How hits are resolved, stored as RON in `combat_rules.ron`. Pairs missing from `table`
+/// deal full damage.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CombatRules {
+    pub table: Vec<DamageRule>,
+    /// Splash damage at the edge of the radius, as a fraction of the damage at the impact.
+    pub falloff_edge: f32,
+    /// Shapes the falloff between the impact and the edge, 1 is linear.
+    pub falloff_exponent: f32,
+    /// Splash hurts units of the attacker's team.
+    pub friendly_fire: bool,
+    /// Multiplier of the damage friendly fire deals.
+    pub friendly_fire_scale: f32,
+}
+
+impl Default for CombatRules {
+    fn default() -> Self {
+        use ArmorClass::*;
+        use DamageType::*;
+        let rule = |damage, armor, multiplier| DamageRule {
+            damage,
+            armor,
+            multiplier,
+        };
+        Self {
+            table: vec![
+                rule(Kinetic, Light, 1.),
+                rule(Kinetic, Medium, 0.75),
+                rule(Kinetic, Heavy, 0.4),
+                rule(Explosive, Light, 1.25),
+                rule(Explosive, Medium, 1.),
+                rule(Explosive, Heavy, 1.5),
+            ],
+            falloff_edge: 0.25,
+            falloff_exponent: 1.,
+            friendly_fire: false,
+            friendly_fire_scale: 0.5,
+        }
+    }
+}
+
+impl CombatRules {
+    pub fn multiplier(&self, damage: DamageType, armor: ArmorClass) -> f32 {
+        self.table
+            .iter()
+            .find(|rule| rule.damage == damage && rule.armor == armor)
+            .map_or(1., |rule| rule.multiplier)
+    }
+
+    /// Damage factor of a hit `distance` away from the impact of a splash with `radius`,
+    /// 0 outside of it.
+    pub fn falloff(&self, distance: f32, radius: f32) -> f32 {
+        if radius <= 0. {
+            return if distance <= 0. { 1. } else { 0. };
+        }
+        if distance > radius {
+            return 0.;
+        }
+        let t = (distance / radius).powf(self.falloff_exponent.max(0.01));
+        1. - (1. - self.falloff_edge) * t
+    }
+
+    /// The hp a hit of `attacker` takes from `defender`, `distance` away from the impact.
+    /// `None` when it does no damage.
+    pub fn resolve(
+        &self,
+        attacker: &UnitDef,
+        attacker_team: u8,
+        defender: &UnitDef,
+        defender_team: u8,
+        distance: f32,
+    ) -> Option<f32> {
+        let mut damage = attacker.damage
+            * self.multiplier(attacker.damage_type, defender.armor)
+            * self.falloff(distance, attacker.splash_radius);
+        if attacker_team == defender_team {
+            if !self.friendly_fire {
+                return None;
+            }
+            damage *= self.friendly_fire_scale;
+        }
+        Some(damage).filter(|damage| *damage > 0.)
+    }
+}
+
+/// The `CombatRules` in use, hot reloaded from the debug panel.
+pub struct CombatRulesResource {
+    pub rules: CombatRules,
+    error: Option<String>,
+}
+
+impl CombatRulesResource {
+    /// Falls back to the default rules when the file is missing or broken.
+    pub fn load() -> Self {
+        let rules = match std::fs::read_to_string(RULES_PATH) {
+            Ok(contents) => ron::de::from_str::<CombatRules>(&contents)
+                .map_err(|err| tr!("combat.load_failed", path = RULES_PATH, error = err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(err) => Err(tr!("combat.read_failed", path = RULES_PATH, error = err)),
+        };
+        match rules {
+            Ok(rules) => Self { rules, error: None },
+            Err(err) => {
+                log::error!("{}", err);
+                Self {
+                    rules: Default::default(),
+                    error: Some(err),
+                }
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(&self.rules, Default::default())
+            .map_err(|err| err.to_string())?;
+        std::fs::write(RULES_PATH, contents).map_err(|err| err.to_string())
+    }
+
+    /// Armor, health and weapon of `def`, with the multipliers of every damage type against
+    /// its armor, for unit tooltips.
+    pub fn describe(&self, def: &UnitDef) -> String {
+        let mut text = tr!(
+            "combat.describe",
+            health = format!("{:.0}", def.max_health),
+            armor = def.armor,
+            damage = format!("{:.0}", def.damage),
+            damage_type = def.damage_type,
+            interval = format!("{:.1}", def.attack_interval)
+        );
+        if def.splash_radius > 0. {
+            text += &tr!(
+                "combat.describe.splash",
+                radius = format!("{:.1}", def.splash_radius)
+            );
+        }
+        for damage in DamageType::ALL {
+            text += "\n";
+            text += &tr!(
+                "combat.describe.multiplier",
+                multiplier = format!("{:.2}", self.multiplier(damage, def.armor)),
+                damage = damage
+            );
+        }
+        text
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("combat"))
+            .id_source("combat rules")
+            .default_open(false)
+            .show(ui, |ui| {
+                egui::Grid::new("combat_rules_table").show(ui, |ui| {
+                    ui.label("");
+                    for armor in ArmorClass::ALL {
+                        ui.label(format!("{}", armor));
+                    }
+                    ui.end_row();
+                    for damage in DamageType::ALL {
+                        ui.label(format!("{}", damage));
+                        for armor in ArmorClass::ALL {
+                            let mut multiplier = self.rules.multiplier(damage, armor);
+                            let drag = egui::DragValue::new(&mut multiplier)
+                                .speed(0.05)
+                                .clamp_range(0.0..=4.);
+                            if ui.add(drag).changed() {
+                                self.set_multiplier(damage, armor, multiplier);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.add(
+                    egui::Slider::new(&mut self.rules.falloff_edge, 0.0..=1.)
+                        .text(tr!("combat.falloff_edge")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut self.rules.falloff_exponent, 0.25..=4.)
+                        .logarithmic(true)
+                        .text(tr!("combat.falloff_exponent")),
+                );
+                ui.checkbox(&mut self.rules.friendly_fire, tr!("combat.friendly_fire"));
+                if self.rules.friendly_fire {
+                    ui.add(
+                        egui::Slider::new(&mut self.rules.friendly_fire_scale, 0.0..=1.)
+                            .text(tr!("combat.friendly_fire_scale")),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    if ui.button(tr!("combat.save")).clicked() {
+                        self.error = self.save().err();
+                        if let Some(err) = &self.error {
+                            log::error!("Cannot save {}: {}", RULES_PATH, err);
+                        }
+                    }
+                    if ui.button(tr!("combat.reload")).clicked() {
+                        *self = Self::load();
+                    }
+                });
+                if let Some(err) = &self.error {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+            });
+    }
+
+    fn set_multiplier(&mut self, damage: DamageType, armor: ArmorClass, multiplier: f32) {
+        match self
+            .rules
+            .table
+            .iter_mut()
+            .find(|rule| rule.damage == damage && rule.armor == armor)
+        {
+            Some(rule) => rule.multiplier = multiplier,
+            None => self.rules.table.push(DamageRule {
+                damage,
+                armor,
+                multiplier,
+            }),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt::Display;
+
+use glam::{Vec3, Vec4};
+use rafx_plugins::features::debug3d::Debug3DResource;
+
+use crate::{env::stable_id::StableId, unit::task::TaskId};
+
+/// Order resolved from what is under the cursor when right-clicking.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnitCommand {
+    Move(Vec3),
+    Attack(StableId),
+    Harvest(TaskId),
+    Enter(StableId),
+}
+
+impl UnitCommand {
+    pub fn marker_color(&self) -> Vec4 {
+        match self {
+            UnitCommand::Move(_) => Vec4::new(0., 1., 0., 1.),
+            UnitCommand::Attack(_) => Vec4::new(1., 0., 0., 1.),
+            UnitCommand::Harvest(_) => Vec4::new(1., 1., 0., 1.),
+            UnitCommand::Enter(_) => Vec4::new(0., 0.5, 1., 1.),
+        }
+    }
+}
+
+impl Display for UnitCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            UnitCommand::Move(_) => write!(f, "Move"),
+            UnitCommand::Attack(_) => write!(f, "Attack"),
+            UnitCommand::Harvest(_) => write!(f, "Harvest"),
+            UnitCommand::Enter(_) => write!(f, "Enter"),
+        }
+    }
+}
+
+struct CommandMarker {
+    position: Vec3,
+    color: Vec4,
+    age: f32,
+}
+
+const MARKER_LIFETIME: f32 = 0.8; // s
+const MARKER_RADIUS: f32 = 1.5;
+const MARKER_SEGMENTS: usize = 16;
+
+/// Click markers that shrink and fade out where commands were issued.
+#[derive(Default)]
+pub struct CommandMarkers {
+    markers: Vec<CommandMarker>,
+}
+
+impl CommandMarkers {
+    pub fn add(&mut self, command: &UnitCommand, position: Vec3) {
+        self.markers.push(CommandMarker {
+            position,
+            color: command.marker_color(),
+            age: 0.,
+        });
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for marker in &mut self.markers {
+            marker.age += dt;
+        }
+        self.markers.retain(|marker| marker.age < MARKER_LIFETIME);
+    }
+
+    pub fn add_debug_draw(&self, debug_draw: &mut Debug3DResource) {
+        for marker in &self.markers {
+            let t = marker.age / MARKER_LIFETIME;
+            let radius = MARKER_RADIUS * (1. - 0.7 * t);
+            let color = Vec4::new(marker.color.x, marker.color.y, marker.color.z, 1. - t);
+            let center = marker.position + Vec3::Z * 0.1;
+            let points: Vec<_> = (0..=MARKER_SEGMENTS)
+                .map(|idx| {
+                    let angle = idx as f32 / MARKER_SEGMENTS as f32 * std::f32::consts::TAU;
+                    center + radius * Vec3::new(angle.cos(), angle.sin(), 0.)
+                })
+                .collect();
+            for segment in points.windows(2) {
+                debug_draw.add_line(segment[0], segment[1], color);
+            }
+            debug_draw.add_line(center, center + Vec3::Z * radius * 2., color);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use glam::{Vec2, Vec3};
+use legion::{IntoQuery, Read};
+use rafx_plugins::components::TransformComponent;
+use rand::{prelude::ThreadRng, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    env::{simulation::Universe, stable_id::StableId},
+    unit::{
+        command::UnitCommand,
+        tech::MAX_TEAMS,
+        unit::{UnitComponent, UnitType},
+    },
+};
+
+/// Creeps fight for the last team, players are expected on the others.
+pub const CREEP_TEAM: u8 = MAX_TEAMS as u8 - 1;
+
+const CAMP_RADIUS: f32 = 12.; // m, members wander inside it
+const AGGRO_RADIUS: f32 = 30.; // m, from the camp center
+
+// members chasing farther than this from the camp give up and walk back
+const LEASH_RADIUS: f32 = 60.; // m
+const WANDER_INTERVAL: f32 = 8.; // s, on average per member
+const RESPAWN_TIME: f32 = 120.; // s, after the last member died
+const MIN_CAMP_DISTANCE: f32 = 100.; // m
+
+// camps stay off the middle of the map, where players start
+const START_CLEARANCE: f32 = 150.; // m
+const PLACEMENT_ATTEMPTS: u32 = 20; // per camp
+
+/// Neutral hostile camps placed on new terrain, part of the terrain reset parameters.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct CreepSettings {
+    pub enabled: bool,
+    /// Camps per square kilometer.
+    pub density: f32,
+    /// From 1 to 5, sets the size of the camps and the units in them.
+    pub difficulty: u32,
+}
+
+impl Default for CreepSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            density: 4.,
+            difficulty: 2,
+        }
+    }
+}
+
+impl CreepSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, tr!("creep.enabled"));
+        if self.enabled {
+            ui.add(egui::Slider::new(&mut self.density, 0.5..=20.).text(tr!("creep.density")));
+            ui.add(egui::Slider::new(&mut self.difficulty, 1..=5).text(tr!("creep.difficulty")));
+        }
+    }
+
+    fn camp_size(&self) -> usize {
+        1 + 2 * self.difficulty as usize
+    }
+
+    fn unit_type(&self, rng: &mut ThreadRng) -> UnitType {
+        match self.difficulty {
+            0 | 1 => UnitType::Container1,
+            2 if rng.gen_bool(0.5) => UnitType::Container1,
+            2 | 3 => UnitType::Container2,
+            _ if rng.gen_bool(0.5) => UnitType::Container2,
+            _ => UnitType::BlueIcosphere,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CreepCamp {
+    pub position: [f32; 3],
+    pub unit_type: UnitType,
+    pub size: usize,
+    pub members: Vec<StableId>,
+    /// Seconds until the camp is back, while it is cleared.
+    pub respawn: Option<f32>,
+}
+
+impl CreepCamp {
+    fn center(&self) -> Vec3 {
+        self.position.into()
+    }
+}
+
+/// What savegames store of the creeps.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct CreepSave {
+    pub settings: CreepSettings,
+    pub camps: Vec<CreepCamp>,
+}
+
+/// Units for the camp at index `camp`, standing around the camp center.
+pub struct CreepSpawn {
+    pub camp: usize,
+    pub unit_type: UnitType,
+    pub positions: Vec<Vec3>,
+}
+
+/// Creep camps of the main universe. Camps are placed once the terrain they stand on is
+/// generated, their members wander around the camp, attack units of other teams coming close
+/// and walk back when lured too far. Cleared camps respawn after a while. The units are spawned
+/// by `UnitsState` from `take_spawns`.
+#[derive(Default)]
+pub struct CreepResource {
+    settings: CreepSettings,
+    camps: Vec<CreepCamp>,
+    // size of the terrain being generated, to place camps on once it is done
+    pending: Option<u32>,
+    spawns: Vec<CreepSpawn>,
+}
+
+impl CreepResource {
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+
+    /// Drops the camps, new ones are placed with `settings` on the terrain of `size` being
+    /// generated.
+    pub fn start(&mut self, settings: CreepSettings, size: u32) {
+        self.settings = settings;
+        self.camps.clear();
+        self.spawns.clear();
+        self.pending = if settings.enabled { Some(size) } else { None };
+    }
+
+    pub fn save(&self) -> CreepSave {
+        CreepSave {
+            settings: self.settings,
+            camps: self.camps.clone(),
+        }
+    }
+
+    /// Puts back saved camps. Their members keep the ids they were saved with until
+    /// `remap_members` matches them to the respawned units.
+    pub fn restore(&mut self, save: CreepSave) {
+        self.settings = save.settings;
+        self.camps = save.camps;
+        self.pending = None;
+        self.spawns.clear();
+    }
+
+    /// Replaces the saved ids of camp members by the ids `ids` maps them to, members missing
+    /// from it are dropped.
+    pub fn remap_members(&mut self, ids: &HashMap<StableId, StableId>) {
+        for camp in &mut self.camps {
+            camp.members = camp
+                .members
+                .iter()
+                .filter_map(|id| ids.get(id).copied())
+                .collect();
+        }
+    }
+
+    pub fn take_spawns(&mut self) -> Vec<CreepSpawn> {
+        std::mem::take(&mut self.spawns)
+    }
+
+    pub fn add_members(&mut self, camp: usize, ids: Vec<StableId>) {
+        if let Some(camp) = self.camps.get_mut(camp) {
+            camp.members.extend(ids);
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, universe: &mut Universe) {
+        if let Some(size) = self.pending {
+            if universe.terrain_gen_progress().is_none() {
+                self.pending = None;
+                self.place_camps(size, universe);
+            }
+            return;
+        }
+
+        for (idx, camp) in self.camps.iter_mut().enumerate() {
+            camp.members.retain(|id| universe.ids.entity(*id).is_some());
+            match &mut camp.respawn {
+                Some(respawn) => {
+                    *respawn -= dt;
+                    if *respawn <= 0. {
+                        camp.respawn = None;
+                        self.spawns.push(Self::camp_spawn(idx, camp));
+                    }
+                }
+                None if camp.members.is_empty() => {
+                    log::debug!("Creep camp at {:?} cleared", camp.position);
+                    camp.respawn = Some(RESPAWN_TIME);
+                }
+                None => {}
+            }
+        }
+
+        let mut query = <(
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<UnitComponent>,
+        )>::query();
+        let intruders: Vec<_> = query
+            .iter(&universe.world)
+            .filter(|(_, _, unit)| unit.team != CREEP_TEAM)
+            .map(|(id, transform, _)| (*id, transform.translation))
+            .collect();
+        let mut rng = thread_rng();
+        for camp in &self.camps {
+            let center = camp.center();
+            let from_center = |p: Vec3| (p - center).truncate().length();
+            let intruder = intruders
+                .iter()
+                .map(|(id, position)| (*id, from_center(*position)))
+                .filter(|(_, distance)| *distance < AGGRO_RADIUS)
+                .min_by(|(_, d0), (_, d1)| d0.partial_cmp(d1).unwrap())
+                .map(|(id, _)| id);
+            for id in &camp.members {
+                let mut entry = match universe.ids.entity(*id) {
+                    Some(entity) => universe.world.entry(entity).unwrap(),
+                    None => continue,
+                };
+                let position = match entry.get_component::<TransformComponent>() {
+                    Ok(transform) => transform.translation,
+                    Err(_) => continue,
+                };
+                let unit = match entry.get_component_mut::<UnitComponent>() {
+                    Ok(unit) => unit,
+                    Err(_) => continue,
+                };
+                if from_center(position) > LEASH_RADIUS {
+                    unit.command = None;
+                    unit.waypoints.clear();
+                    unit.move_target = Some(center);
+                } else if let Some(target) = intruder {
+                    if unit.command.is_none() {
+                        unit.command = Some(UnitCommand::Attack(target));
+                    }
+                } else if unit.command.is_none()
+                    && unit.move_target.is_none()
+                    && rng.gen::<f32>() < dt / WANDER_INTERVAL
+                {
+                    unit.move_target = Some(center + Self::around(&mut rng, CAMP_RADIUS));
+                }
+            }
+        }
+    }
+
+    fn place_camps(&mut self, size: u32, universe: &Universe) {
+        let bounds = universe.terrain_bounds();
+        let (min, max) = (bounds.minimum, bounds.max());
+        let area = (size as f32 / 1000.).powi(2); // km²
+        let count = (area * self.settings.density).round() as usize;
+        let middle = Vec2::new(
+            (min.x() + max.x()) as f32 / 2.,
+            (min.y() + max.y()) as f32 / 2.,
+        );
+        let mut rng = thread_rng();
+        for _ in 0..count {
+            for _ in 0..PLACEMENT_ATTEMPTS {
+                let p = Vec2::new(
+                    rng.gen_range(min.x() as f32..max.x() as f32 + 1.),
+                    rng.gen_range(min.y() as f32..max.y() as f32 + 1.),
+                );
+                let crowded = self.camps.iter().any(|camp| {
+                    Vec2::new(camp.position[0], camp.position[1]).distance(p) < MIN_CAMP_DISTANCE
+                });
+                if crowded || p.distance(middle) < START_CLEARANCE {
+                    continue;
+                }
+                let hit = universe.ray_cast(
+                    Vec3::new(p.x, p.y, max.z() as f32 + 1.),
+                    Vec3::new(0., 0., -1.),
+                );
+                let ground = match hit {
+                    Some(result) => result.hit.z() as f32,
+                    None => continue,
+                };
+                let camp = CreepCamp {
+                    position: [p.x, p.y, ground + 1.],
+                    unit_type: self.settings.unit_type(&mut rng),
+                    size: self.settings.camp_size(),
+                    members: vec![],
+                    respawn: None,
+                };
+                self.spawns.push(Self::camp_spawn(self.camps.len(), &camp));
+                self.camps.push(camp);
+                break;
+            }
+        }
+        log::info!("Placed {} of {} creep camps", self.camps.len(), count);
+    }
+
+    fn camp_spawn(idx: usize, camp: &CreepCamp) -> CreepSpawn {
+        let mut rng = thread_rng();
+        let center = camp.center();
+        CreepSpawn {
+            camp: idx,
+            unit_type: camp.unit_type,
+            positions: (0..camp.size)
+                .map(|_| center + Self::around(&mut rng, CAMP_RADIUS / 2.))
+                .collect(),
+        }
+    }
+
+    fn around(rng: &mut ThreadRng, radius: f32) -> Vec3 {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let distance = rng.gen_range(0.0..radius);
+        distance * Vec3::new(angle.cos(), angle.sin(), 0.)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("creep"))
+            .id_source("creep camps")
+            .default_open(false)
+            .show(ui, |ui| {
+                let cleared = self
+                    .camps
+                    .iter()
+                    .filter(|camp| camp.respawn.is_some())
+                    .count();
+                let creeps: usize = self.camps.iter().map(|camp| camp.members.len()).sum();
+                ui.label(tr!(
+                    "creep.stats",
+                    camps = self.camps.len(),
+                    cleared = cleared,
+                    creeps = creeps
+                ));
+                if self.pending.is_some() {
+                    ui.label(tr!("creep.waiting"));
+                }
+                if ui.button(tr!("creep.respawn")).clicked() {
+                    for camp in &mut self.camps {
+                        if let Some(respawn) = &mut camp.respawn {
+                            *respawn = 0.;
+                        }
+                    }
+                }
+            });
+    }
+}
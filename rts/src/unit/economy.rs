@@ -0,0 +1,201 @@
+use std::fmt::Display;
+
+use crate::unit::{tech::MAX_TEAMS, unit::UnitType};
+
+const STARTING_MINERALS: u32 = 300;
+const STARTING_ENERGY: u32 = 100;
+const BASE_ENERGY_RATE: f32 = 0.5; // per s
+
+// extra energy for each building garrisoned by the team
+const GARRISON_ENERGY_RATE: f32 = 1.; // per s
+
+// income rates shown in the top bar are averaged over about this long
+const INCOME_WINDOW: f32 = 30.; // s
+const SHORTFALL_DISPLAY_TIME: f32 = 3.; // s
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Cost {
+    pub minerals: u32,
+    pub energy: u32,
+}
+
+impl Cost {
+    pub fn unit(unit_type: UnitType) -> Self {
+        match unit_type {
+            UnitType::Container1 => Cost {
+                minerals: 50,
+                energy: 0,
+            },
+            UnitType::Container2 => Cost {
+                minerals: 100,
+                energy: 20,
+            },
+            UnitType::BlueIcosphere => Cost {
+                minerals: 75,
+                energy: 40,
+            },
+        }
+    }
+
+    /// Scales with the ground area the building covers.
+    pub fn building(footprint: [u32; 2]) -> Self {
+        let area = footprint[0] * footprint[1];
+        Cost {
+            minerals: 2 * area,
+            energy: area / 2,
+        }
+    }
+}
+
+impl Display for Cost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} minerals, {} energy", self.minerals, self.energy)
+    }
+}
+
+#[derive(Clone)]
+struct Account {
+    minerals: u32,
+    energy: u32,
+    // energy income not yet worth a whole unit
+    energy_fraction: f32,
+    // smoothed income, per s
+    mineral_rate: f32,
+    energy_rate: f32,
+    // minerals gained since the last update
+    mined: u32,
+}
+
+impl Default for Account {
+    fn default() -> Self {
+        Self {
+            minerals: STARTING_MINERALS,
+            energy: STARTING_ENERGY,
+            energy_fraction: 0.,
+            mineral_rate: 0.,
+            energy_rate: 0.,
+            mined: 0,
+        }
+    }
+}
+
+/// Minerals and energy of each player (team). Minerals come from finished gather tasks,
+/// energy trickles in, faster for each building the team garrisons. Producing units and
+/// placing buildings spend both, and orders the team can't pay for are refused with a
+/// message in the top bar.
+pub struct PlayerEconomy {
+    accounts: Vec<Account>,
+    shortfall: Option<(String, f32)>,
+}
+
+impl Default for PlayerEconomy {
+    fn default() -> Self {
+        Self {
+            accounts: vec![Default::default(); MAX_TEAMS],
+            shortfall: None,
+        }
+    }
+}
+
+impl PlayerEconomy {
+    fn account(&mut self, team: u8) -> &mut Account {
+        &mut self.accounts[team as usize % MAX_TEAMS]
+    }
+
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+
+    pub fn add_minerals(&mut self, team: u8, amount: u32) {
+        let account = self.account(team);
+        account.minerals += amount;
+        account.mined += amount;
+    }
+
+    pub fn can_afford(&self, team: u8, cost: Cost) -> bool {
+        let account = &self.accounts[team as usize % MAX_TEAMS];
+        account.minerals >= cost.minerals && account.energy >= cost.energy
+    }
+
+    /// Minerals and energy `team` has in stock.
+    pub fn stock(&self, team: u8) -> (u32, u32) {
+        let account = &self.accounts[team as usize % MAX_TEAMS];
+        (account.minerals, account.energy)
+    }
+
+    /// The message of the last refused order, while it is shown.
+    pub fn shortfall(&self) -> Option<&str> {
+        self.shortfall.as_ref().map(|(message, _)| message.as_str())
+    }
+
+    /// Pays for `what`, or refuses and shows what is missing.
+    pub fn try_spend(&mut self, team: u8, cost: Cost, what: &str) -> bool {
+        if !self.can_afford(team, cost) {
+            let account = &self.accounts[team as usize % MAX_TEAMS];
+            let missing = Cost {
+                minerals: cost.minerals.saturating_sub(account.minerals),
+                energy: cost.energy.saturating_sub(account.energy),
+            };
+            let message = tr!("economy.insufficient", what = what, missing = missing);
+            log::info!("Team {}: {}", team, message);
+            self.shortfall = Some((message, SHORTFALL_DISPLAY_TIME));
+            return false;
+        }
+        let account = self.account(team);
+        account.minerals -= cost.minerals;
+        account.energy -= cost.energy;
+        true
+    }
+
+    /// `garrisons` counts the buildings each team holds.
+    pub fn update(&mut self, dt: f32, garrisons: &[u32; MAX_TEAMS]) {
+        if dt <= 0. {
+            return;
+        }
+        let smoothing = (dt / INCOME_WINDOW).min(1.);
+        for (account, garrisoned) in self.accounts.iter_mut().zip(garrisons) {
+            let energy_rate = BASE_ENERGY_RATE + GARRISON_ENERGY_RATE * *garrisoned as f32;
+            account.energy_fraction += energy_rate * dt;
+            let whole = account.energy_fraction.floor();
+            account.energy += whole as u32;
+            account.energy_fraction -= whole;
+            account.energy_rate = energy_rate;
+            let mineral_rate = account.mined as f32 / dt;
+            account.mineral_rate += (mineral_rate - account.mineral_rate) * smoothing;
+            account.mined = 0;
+        }
+        if let Some((_, time_left)) = &mut self.shortfall {
+            *time_left -= dt;
+            if *time_left <= 0. {
+                self.shortfall = None;
+            }
+        }
+    }
+
+    /// Always visible bar with the stock and income of `team`, returns the area it covers.
+    pub fn top_bar(&self, context: &egui::CtxRef, team: u8) -> egui::Rect {
+        let account = &self.accounts[team as usize % MAX_TEAMS];
+        let panel = egui::TopBottomPanel::top("economy_bar").show(context, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(tr!("economy.team", team = team));
+                ui.separator();
+                ui.label(tr!(
+                    "economy.minerals",
+                    amount = account.minerals,
+                    rate = format!("{:.0}", account.mineral_rate * 60.)
+                ));
+                ui.separator();
+                ui.label(tr!(
+                    "economy.energy",
+                    amount = account.energy,
+                    rate = format!("{:.1}", account.energy_rate)
+                ));
+                if let Some((message, _)) = &self.shortfall {
+                    ui.separator();
+                    ui.colored_label(egui::Color32::RED, message);
+                }
+            });
+        });
+        panel.response.rect
+    }
+}
@@ -0,0 +1,64 @@
+use glam::Vec3;
+
+use crate::{env::stable_id::StableId, unit::unit::UnitComponent};
+
+/// Units a building holds when it has no `GarrisonComponent` of its own yet.
+pub const BUILDING_CAPACITY: usize = 8;
+
+/// A unit inside a garrison, with what is needed to put it back on the map.
+#[derive(Clone)]
+pub struct Passenger {
+    pub id: StableId,
+    pub unit: UnitComponent,
+    pub scale: Vec3,
+    /// Name of the prefab the unit came from, for its mesh and attachments.
+    pub prefab: Option<String>,
+}
+
+/// Units inside a building or a transport. Passengers are taken out of the world, so they are
+/// not drawn, culled or simulated, but their `StableId`s stay reserved (`Universe::stow`) and
+/// the unloaded entities get them back.
+pub struct GarrisonComponent {
+    pub capacity: usize,
+    pub passengers: Vec<Passenger>,
+}
+
+impl GarrisonComponent {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            passengers: vec![],
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.passengers.len() >= self.capacity
+    }
+
+    /// Lists the passengers, returns true when unloading was requested.
+    pub fn ui(&self, ui: &mut egui::Ui, label: &str) -> bool {
+        let mut unload = false;
+        ui.horizontal(|ui| {
+            ui.label(tr!(
+                "garrison.occupancy",
+                garrison = label,
+                count = self.passengers.len(),
+                capacity = self.capacity
+            ));
+            if !self.passengers.is_empty() {
+                unload = ui
+                    .small_button(tr!("garrison.unload"))
+                    .on_hover_text(tr!("garrison.unload.hint"))
+                    .clicked();
+            }
+        });
+        for passenger in &self.passengers {
+            ui.label(tr!(
+                "garrison.passenger",
+                unit = passenger.unit.object_type,
+                health = format!("{:.0}", passenger.unit.health * 100.)
+            ));
+        }
+        unload
+    }
+}
@@ -0,0 +1,291 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use building_blocks::core::prelude::*;
+use glam::Vec3;
+use legion::{Entity, EntityStore, Resources};
+use rafx_plugins::components::TransformComponent;
+use rand::{thread_rng, Rng};
+
+use crate::{
+    audio::{AudioResource, SoundCategory},
+    env::{overlay_mesh::OverlayQuads, simulation::Universe},
+};
+
+const OVERLAY_OWNER: &str = "hit_flash";
+// emissive terrain material of the shells and sparks
+const MATERIAL: &str = "hit_flash";
+const SPARKS_KEY: Point3i = PointN([0, 1, 0]);
+
+const FLASH_DURATION: f32 = 0.15; // s
+
+// intensity of a hit taking no health, bigger hits add the fraction of health they took
+const FLASH_BASE_INTENSITY: f32 = 0.5;
+// stacked hits saturate here instead of flashing brighter and longer
+const MAX_FLASH_INTENSITY: f32 = 1.5;
+// one spark burst and hit sound per unit this often, however fast it is hit
+const BURST_INTERVAL: f32 = 0.1; // s
+const SPARKS_PER_BURST: usize = 6;
+const MAX_SPARKS: usize = 512;
+const SPARK_LIFETIME: f32 = 0.4; // s
+const SPARK_SPEED: f32 = 4.; // m/s
+const SPARK_GRAVITY: f32 = 9.81; // m/s²
+const SPARK_LENGTH: f32 = 0.03; // s of travel drawn as a streak
+const SPARK_WIDTH: f32 = 0.03; // m
+
+// radius of the shell around a flashing unit, in unit scales, plus this per intensity
+const SHELL_SIZE: f32 = 0.6;
+const SHELL_GROWTH: f32 = 0.2;
+const SHELL_RINGS: usize = 6;
+const SHELL_SEGMENTS: usize = 10;
+const HIT_SOUND_DURATION: f32 = 0.2; // s
+
+struct Flash {
+    intensity: f32,
+    remaining: f32,   // s
+    since_burst: f32, // s
+    // overlay key of the shell, unique per flash
+    slot: i32,
+    shell: bool,
+}
+
+struct Spark {
+    position: Vec3,
+    velocity: Vec3,
+    age: f32, // s
+}
+
+/// Feedback on units taking damage: a short flash on the unit and a burst of sparks, with a
+/// hit sound. The flash is a glowing shell around the unit and the sparks are streaks, overlay
+/// meshes of the universe since unit meshes have no per-object tint. Hits landing while a unit
+/// is still flashing stack into one brighter flash up to `MAX_FLASH_INTENSITY`, bursts and
+/// sounds are limited to one per `BURST_INTERVAL` per unit.
+/// With `flashing` off, for photosensitive players, only the sparks and sounds remain.
+pub struct HitFlashResource {
+    pub flashing: bool,
+    flashes: HashMap<Entity, Flash>,
+    sparks: Vec<Spark>,
+    next_slot: i32,
+}
+
+impl HitFlashResource {
+    pub fn new(flashing: bool) -> Self {
+        Self {
+            flashing,
+            flashes: Default::default(),
+            sparks: vec![],
+            next_slot: 0,
+        }
+    }
+
+    /// `damage` is the fraction of its health the unit lost.
+    pub fn hit(&mut self, entity: Entity, position: Vec3, damage: f32, audio: &mut AudioResource) {
+        let next_slot = &mut self.next_slot;
+        let flash = self.flashes.entry(entity).or_insert_with(|| {
+            *next_slot = next_slot.wrapping_add(1);
+            Flash {
+                intensity: 0.,
+                remaining: 0.,
+                since_burst: BURST_INTERVAL,
+                slot: *next_slot,
+                shell: false,
+            }
+        });
+        if flash.remaining <= 0. {
+            flash.intensity = 0.;
+        }
+        flash.intensity =
+            (flash.intensity + FLASH_BASE_INTENSITY + damage.max(0.)).min(MAX_FLASH_INTENSITY);
+        flash.remaining = FLASH_DURATION;
+        if flash.since_burst < BURST_INTERVAL {
+            return;
+        }
+        flash.since_burst = 0.;
+        audio.play(
+            "unit_hit",
+            SoundCategory::Effects,
+            Some(position),
+            Some(HIT_SOUND_DURATION),
+        );
+        let mut rng = thread_rng();
+        let count = SPARKS_PER_BURST.min(MAX_SPARKS.saturating_sub(self.sparks.len()));
+        for _ in 0..count {
+            let direction = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(0.2..1.0),
+            )
+            .normalize();
+            self.sparks.push(Spark {
+                position,
+                velocity: direction * SPARK_SPEED * rng.gen_range(0.5..1.0),
+                age: 0.,
+            });
+        }
+    }
+
+    /// Forgets everything, for scene changes.
+    pub fn clear(&mut self) {
+        self.flashes.clear();
+        self.sparks.clear();
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for flash in self.flashes.values_mut() {
+            flash.remaining -= dt;
+            flash.since_burst += dt;
+        }
+        // kept until bursts are allowed again, so stacking hits can't skip the interval, and
+        // until `update_meshes` removed the shell
+        self.flashes.retain(|_, flash| {
+            flash.remaining > 0. || flash.since_burst < BURST_INTERVAL || flash.shell
+        });
+        for spark in &mut self.sparks {
+            spark.velocity.z -= SPARK_GRAVITY * dt;
+            spark.position += spark.velocity * dt;
+            spark.age += dt;
+        }
+        self.sparks.retain(|spark| spark.age < SPARK_LIFETIME);
+    }
+
+    /// Keeps a shell mesh around every flashing unit and rebuilds the spark mesh, both
+    /// overlay meshes of `universe` in the emissive `hit_flash` terrain material.
+    #[profiling::function]
+    pub fn update_meshes(&mut self, universe: &mut Universe, resources: &Resources) {
+        let material = match universe
+            .get_material_names()
+            .iter()
+            .position(|m| m == MATERIAL)
+        {
+            Some(material) => material as u16,
+            None => return,
+        };
+        for (entity, flash) in self.flashes.iter_mut() {
+            if !flash.shell {
+                continue;
+            }
+            let translation = universe
+                .world
+                .entry_ref(*entity)
+                .ok()
+                .and_then(|entry| entry.get_component::<TransformComponent>().ok().cloned())
+                .map(|transform| transform.translation);
+            let key = shell_key(flash.slot);
+            match translation {
+                Some(translation) if flash.remaining > 0. && self.flashing => {
+                    universe.set_overlay_offset(OVERLAY_OWNER, key, translation);
+                }
+                _ => {
+                    universe.set_overlay_mesh(OVERLAY_OWNER, key, Vec3::ZERO, None, resources);
+                    flash.shell = false;
+                }
+            }
+        }
+        for (entity, flash) in &mut self.flashes {
+            if !self.flashing || flash.remaining <= 0. || flash.shell {
+                continue;
+            }
+            let (translation, scale) = match universe.world.entry_ref(*entity) {
+                Ok(entry) => match entry.get_component::<TransformComponent>() {
+                    Ok(transform) => (transform.translation, transform.scale.x),
+                    Err(_) => continue,
+                },
+                Err(_) => continue,
+            };
+            let radius = scale.max(1.) * (SHELL_SIZE + SHELL_GROWTH * flash.intensity);
+            let (geometry, position) = match shell_quads(material, radius).build() {
+                Some(built) => built,
+                None => continue,
+            };
+            let key = shell_key(flash.slot);
+            if universe.set_overlay_mesh(OVERLAY_OWNER, key, position, Some(geometry), resources) {
+                universe.set_overlay_offset(OVERLAY_OWNER, key, translation);
+                flash.shell = true;
+            }
+        }
+
+        // rebuilt every frame, but not over an upload still in flight, or it would never show
+        if universe.is_overlay_pending(OVERLAY_OWNER, SPARKS_KEY) {
+            return;
+        }
+        let mut quads = OverlayQuads::default();
+        for spark in &self.sparks {
+            let tail = spark.position - spark.velocity * SPARK_LENGTH;
+            add_streak(&mut quads, material, tail, spark.position);
+        }
+        match quads.build() {
+            Some((geometry, position)) => {
+                universe.set_overlay_mesh(
+                    OVERLAY_OWNER,
+                    SPARKS_KEY,
+                    position,
+                    Some(geometry),
+                    resources,
+                );
+            }
+            None if universe.has_overlay_mesh(OVERLAY_OWNER, SPARKS_KEY) => {
+                universe.set_overlay_mesh(OVERLAY_OWNER, SPARKS_KEY, Vec3::ZERO, None, resources);
+            }
+            None => {}
+        }
+    }
+}
+
+fn shell_key(slot: i32) -> Point3i {
+    PointN([slot, 0, 0])
+}
+
+// a sphere of `radius` around the origin, moved onto the unit with the overlay offset
+fn shell_quads(material: u16, radius: f32) -> OverlayQuads {
+    let point = |ring: usize, segment: usize| {
+        let theta = PI * ring as f32 / SHELL_RINGS as f32;
+        let phi = 2. * PI * segment as f32 / SHELL_SEGMENTS as f32;
+        radius
+            * Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            )
+    };
+    let mut quads = OverlayQuads::default();
+    for ring in 0..SHELL_RINGS {
+        for segment in 0..SHELL_SEGMENTS {
+            let corners = [
+                point(ring, segment),
+                point(ring + 1, segment),
+                point(ring + 1, segment + 1),
+                point(ring, segment + 1),
+            ];
+            let center = corners.iter().fold(Vec3::ZERO, |sum, c| sum + *c);
+            let phi = 2. * PI * (segment as f32 + 0.5) / SHELL_SEGMENTS as f32;
+            quads.add(
+                material,
+                corners,
+                center.normalize(),
+                Vec3::new(-phi.sin(), phi.cos(), 0.),
+            );
+        }
+    }
+    quads
+}
+
+// two crossed quads along the streak, both sides of each so it shows from every direction
+fn add_streak(quads: &mut OverlayQuads, material: u16, tail: Vec3, head: Vec3) {
+    let length = head - tail;
+    if length.length_squared() < 1e-8 {
+        return;
+    }
+    let dir = length.normalize();
+    let side = if dir.cross(Vec3::Z).length_squared() > 1e-6 {
+        dir.cross(Vec3::Z).normalize()
+    } else {
+        Vec3::X
+    };
+    for across in [side, dir.cross(side)] {
+        let offset = across * SPARK_WIDTH / 2.;
+        let normal = across.cross(dir);
+        let corners = [tail - offset, tail + offset, head + offset, head - offset];
+        quads.add(material, corners, normal, across);
+        let [a, b, c, d] = corners;
+        quads.add(material, [b, a, d, c], -normal, -across);
+    }
+}
@@ -0,0 +1,123 @@
+use rafx::framework::render_features::RenderObjectHandle;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+// a unit switches level only this far past the threshold, so it doesn't flicker on the edge
+const HYSTERESIS: f32 = 0.1; // of the threshold
+
+/// How far unit LOD meshes are used.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct UnitLodSettings {
+    pub enabled: bool,
+    /// Multiplies the switch distances, higher keeps the detailed meshes farther away.
+    pub bias: f32,
+}
+
+impl Default for UnitLodSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bias: 1.,
+        }
+    }
+}
+
+/// The LOD chain of a unit mesh: the render object of every level and the camera distance it
+/// is used from, the full mesh at 0 first.
+#[derive(Clone)]
+pub struct UnitLodComponent {
+    pub levels: Vec<(f32, RenderObjectHandle)>, // m
+    pub current: usize,
+}
+
+impl UnitLodComponent {
+    pub fn new(levels: Vec<(f32, RenderObjectHandle)>) -> Self {
+        Self { levels, current: 0 }
+    }
+}
+
+/// Picks the level of the units with a `UnitLodComponent` from their distance to the camera,
+/// see `UnitsState::update_lods`. Keeps the unit count per level of the last frame.
+pub struct UnitLodResource {
+    pub settings: UnitLodSettings,
+    counts: Vec<usize>,
+    switches: usize,
+}
+
+impl UnitLodResource {
+    pub fn new(settings: UnitLodSettings) -> Self {
+        Self {
+            settings,
+            counts: vec![],
+            switches: 0,
+        }
+    }
+
+    /// The level of `lod` to draw at `distance` from the camera.
+    pub fn select(&self, lod: &UnitLodComponent, distance: f32) -> usize {
+        if !self.settings.enabled {
+            return 0;
+        }
+        let distance = distance / self.settings.bias.max(0.01);
+        let target = lod
+            .levels
+            .iter()
+            .rposition(|(threshold, _)| distance >= *threshold)
+            .unwrap_or(0);
+        let current = lod.current.min(lod.levels.len() - 1);
+        if target > current && distance < lod.levels[target].0 * (1. + HYSTERESIS) {
+            target - 1
+        } else if target < current && distance > lod.levels[current].0 * (1. - HYSTERESIS) {
+            current
+        } else {
+            target
+        }
+    }
+
+    /// Starts counting the units of a new frame.
+    pub fn begin_frame(&mut self) {
+        self.counts.clear();
+        self.switches = 0;
+    }
+
+    pub fn count(&mut self, level: usize, switched: bool) {
+        if self.counts.len() <= level {
+            self.counts.resize(level + 1, 0);
+        }
+        self.counts[level] += 1;
+        if switched {
+            self.switches += 1;
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.unit_lod"))
+            .id_source("unit lod")
+            .default_open(false)
+            .show(ui, |ui| {
+                let settings = &mut self.settings;
+                let mut changed = ui
+                    .checkbox(&mut settings.enabled, tr!("settings.unit_lod.enabled"))
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut settings.bias, 0.25..=4.)
+                            .logarithmic(true)
+                            .text(tr!("settings.unit_lod.bias")),
+                    )
+                    .changed();
+                if changed {
+                    let settings = *settings;
+                    Settings::update(|s| s.unit_lod = settings);
+                }
+                for (level, count) in self.counts.iter().enumerate() {
+                    ui.label(tr!("unit_lod.level", level = level, count = count));
+                }
+                if !self.counts.is_empty() {
+                    ui.label(tr!("unit_lod.switches", count = self.switches));
+                }
+            });
+    }
+}
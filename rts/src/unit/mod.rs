@@ -1 +1,19 @@
+pub mod building;
+pub mod combat;
+pub mod command;
+pub mod creep;
+pub mod economy;
+pub mod garrison;
+pub mod hit_flash;
+pub mod lod;
+pub mod orders;
+pub mod outline;
+pub mod prefab;
+pub mod roster;
+pub mod stress_test;
+pub mod task;
+pub mod tech;
+pub mod trigger;
 pub mod unit;
+pub mod wall;
+pub mod wreck;
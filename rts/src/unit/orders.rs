@@ -0,0 +1,235 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use building_blocks::core::prelude::*;
+use glam::Vec3;
+use legion::{IntoQuery, Read, Resources};
+use rafx_plugins::components::TransformComponent;
+
+use crate::{
+    env::{overlay_mesh::OverlayQuads, simulation::Universe, stable_id::StableId},
+    unit::command::UnitCommand,
+};
+
+const OVERLAY_OWNER: &str = "order_ack";
+const ACK_LIFETIME: f32 = 0.4; // s
+const ACK_HEIGHT: f32 = 2.5; // m above the unit center
+const ACK_BOUNCE: f32 = 0.5; // m
+const ACK_SIZE: f32 = 0.6; // m
+const ACK_THICKNESS: f32 = 0.12; // m
+
+/// An order as issued by the player, waiting for the simulation to apply it.
+pub struct PendingOrder {
+    pub command: UnitCommand,
+    pub target: Vec3,
+    /// The selection when the order was issued, later selection changes don't affect it.
+    pub units: Vec<StableId>,
+    frames_left: u32,
+}
+
+struct Ack {
+    command: UnitCommand,
+    age: f32, // s
+    // overlay key of the chevron, unique per acknowledging unit
+    slot: i32,
+    // the material the chevron mesh was built with
+    built: Option<&'static str>,
+}
+
+/// Acknowledgment of an order by one unit, the hook for voice lines once there is audio.
+#[derive(Clone, Copy, Debug)]
+pub struct OrderAck {
+    pub unit: StableId,
+    pub command: UnitCommand,
+}
+
+/// Orders between the click and the simulation tick applying them. The player gets feedback
+/// right away (command marker, a chevron mesh bouncing over each ordered unit and an
+/// `OrderAck` per unit), so latency between input and simulation is hidden. `latency_frames`
+/// delays the application on purpose, to check that feedback and simulation stay consistent.
+#[derive(Default)]
+pub struct PendingOrdersResource {
+    pending: Vec<PendingOrder>,
+    acks: HashMap<StableId, Ack>,
+    new_acks: Vec<OrderAck>,
+    next_slot: i32,
+    pub latency_frames: u32,
+}
+
+impl PendingOrdersResource {
+    pub fn issue(&mut self, command: UnitCommand, target: Vec3, units: Vec<StableId>) {
+        for unit in &units {
+            let next_slot = &mut self.next_slot;
+            let ack = self.acks.entry(*unit).or_insert_with(|| {
+                *next_slot = next_slot.wrapping_add(1);
+                Ack {
+                    command,
+                    age: 0.,
+                    slot: *next_slot,
+                    built: None,
+                }
+            });
+            // a new order restarts the bounce, in the new command's color
+            ack.command = command;
+            ack.age = 0.;
+            self.new_acks.push(OrderAck {
+                unit: *unit,
+                command,
+            });
+        }
+        self.pending.push(PendingOrder {
+            command,
+            target,
+            units,
+            frames_left: self.latency_frames,
+        });
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Acknowledgments since the last call.
+    pub fn take_acks(&mut self) -> Vec<OrderAck> {
+        std::mem::take(&mut self.new_acks)
+    }
+
+    /// Orders due this tick, in the order they were issued.
+    pub fn take_due(&mut self) -> Vec<PendingOrder> {
+        let (due, waiting) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|order| order.frames_left == 0);
+        self.pending = waiting;
+        for order in &mut self.pending {
+            order.frames_left -= 1;
+        }
+        due
+    }
+
+    /// Ages the acknowledgments, `update_meshes` removes the expired ones.
+    pub fn update(&mut self, dt: f32) {
+        for ack in self.acks.values_mut() {
+            ack.age += dt;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.acks.clear();
+        self.new_acks.clear();
+    }
+
+    /// Keeps a chevron mesh bouncing over every acknowledging unit, overlay meshes of
+    /// `universe` in the color of the command.
+    #[profiling::function]
+    pub fn update_meshes(&mut self, universe: &mut Universe, resources: &Resources) {
+        if self.acks.is_empty() {
+            return;
+        }
+        let mut query = <(Read<StableId>, Read<TransformComponent>)>::query();
+        let tips: HashMap<StableId, Vec3> = query
+            .iter(&universe.world)
+            .filter_map(|(id, transform)| {
+                let ack = self.acks.get(id)?;
+                let t = ack.age / ACK_LIFETIME;
+                // one bounce, up and back down
+                let lift = (t * PI).sin() * ACK_BOUNCE;
+                Some((
+                    *id,
+                    transform.translation
+                        + Vec3::Z * (ACK_HEIGHT * transform.scale.z.max(1.) + lift),
+                ))
+            })
+            .collect();
+        for (id, ack) in self.acks.iter_mut() {
+            let key = ack_key(ack.slot);
+            let tip = match tips.get(id) {
+                Some(tip) if ack.age < ACK_LIFETIME => *tip,
+                _ => {
+                    if ack.built.take().is_some() {
+                        universe.set_overlay_mesh(OVERLAY_OWNER, key, Vec3::ZERO, None, resources);
+                    }
+                    ack.age = ACK_LIFETIME;
+                    continue;
+                }
+            };
+            let name = ack_material(ack.command);
+            if ack.built != Some(name) {
+                let material = match universe.get_material_names().iter().position(|m| m == name) {
+                    Some(material) => material as u16,
+                    None => continue,
+                };
+                let (geometry, position) = match chevron_quads(material).build() {
+                    Some(built) => built,
+                    None => continue,
+                };
+                if !universe.set_overlay_mesh(
+                    OVERLAY_OWNER,
+                    key,
+                    position,
+                    Some(geometry),
+                    resources,
+                ) {
+                    continue;
+                }
+                ack.built = Some(name);
+            }
+            universe.set_overlay_offset(OVERLAY_OWNER, key, tip);
+        }
+        self.acks
+            .retain(|_, ack| ack.age < ACK_LIFETIME || ack.built.is_some());
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("orders"))
+            .id_source("orders")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.add(
+                    egui::Slider::new(&mut self.latency_frames, 0..=30).text(tr!("orders.latency")),
+                );
+                ui.label(tr!("orders.pending", count = self.pending.len()));
+            });
+    }
+}
+
+// the terrain material of the chevron, in the color of the command marker
+fn ack_material(command: UnitCommand) -> &'static str {
+    match command {
+        UnitCommand::Move(_) => "flat_green",
+        UnitCommand::Attack(_) => "flat_red",
+        UnitCommand::Harvest(_) => "old_bronze",
+        UnitCommand::Enter(_) => "flat_blue",
+    }
+}
+
+fn ack_key(slot: i32) -> Point3i {
+    PointN([slot, 0, 0])
+}
+
+// a chevron pointing down at the origin, crossed with a second one so it shows from any side,
+// moved onto the unit with the overlay offset
+fn chevron_quads(material: u16) -> OverlayQuads {
+    let mut quads = OverlayQuads::default();
+    for (axis, across) in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::X)] {
+        let half = across * ACK_THICKNESS / 2.;
+        for side in [-1., 1.] {
+            let end = (side * axis + Vec3::Z) * ACK_SIZE;
+            let along = end.normalize();
+            let normal = along.cross(across);
+            // both faces of the arm
+            quads.add(
+                material,
+                [-half, end - half, end + half, half],
+                normal,
+                along,
+            );
+            quads.add(
+                material,
+                [-half, half, end + half, end - half],
+                -normal,
+                along,
+            );
+        }
+    }
+    quads
+}
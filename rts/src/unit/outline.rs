@@ -0,0 +1,308 @@
+use std::fmt::Display;
+
+use glam::{Mat3, Vec3, Vec4};
+use legion::{Entity, IntoQuery, Read, World};
+use rafx_plugins::components::TransformComponent;
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::RTSCamera, settings::Settings, unit::unit::UnitComponent};
+
+const OUTLINE_SEGMENTS: usize = 12;
+// the outlined bounds of a unit at scale 1, around its origin at the base
+const UNIT_RADIUS: f32 = 1.; // m
+const UNIT_HEIGHT: f32 = 3.; // m
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutlineState {
+    Owned,
+    Selected,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TeamPalette {
+    Classic,
+    /// Okabe & Ito, distinguishable with all common color vision deficiencies.
+    OkabeIto,
+    /// Paul Tol's bright scheme, also colorblind safe but closer to the classic colors.
+    TolBright,
+}
+
+impl Default for TeamPalette {
+    fn default() -> Self {
+        TeamPalette::Classic
+    }
+}
+
+impl Display for TeamPalette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            TeamPalette::Classic => write!(f, "{}", tr!("team_colors.palette.classic")),
+            TeamPalette::OkabeIto => write!(f, "{}", tr!("team_colors.palette.okabe_ito")),
+            TeamPalette::TolBright => write!(f, "{}", tr!("team_colors.palette.tol_bright")),
+        }
+    }
+}
+
+impl TeamPalette {
+    pub const ALL: [TeamPalette; 3] = [
+        TeamPalette::Classic,
+        TeamPalette::OkabeIto,
+        TeamPalette::TolBright,
+    ];
+
+    pub fn colors(&self) -> [[f32; 3]; 4] {
+        match self {
+            TeamPalette::Classic => [
+                [0.2, 0.5, 1.0],
+                [1.0, 0.25, 0.2],
+                [0.3, 0.9, 0.3],
+                [1.0, 0.85, 0.2],
+            ],
+            TeamPalette::OkabeIto => [
+                [0.0, 0.45, 0.7],
+                [0.84, 0.37, 0.0],
+                [0.0, 0.62, 0.45],
+                [0.94, 0.89, 0.26],
+            ],
+            TeamPalette::TolBright => [
+                [0.27, 0.47, 0.67],
+                [0.93, 0.4, 0.47],
+                [0.13, 0.53, 0.2],
+                [0.8, 0.73, 0.27],
+            ],
+        }
+    }
+
+    pub fn color(&self, team: u8) -> Vec3 {
+        let colors = self.colors();
+        colors[team as usize % colors.len()].into()
+    }
+}
+
+/// Color vision deficiency to simulate, for checking that the team colors stay apart.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorVision {
+    Normal,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Display for ColorVision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            ColorVision::Normal => write!(f, "{}", tr!("team_colors.vision.normal")),
+            ColorVision::Protanopia => write!(f, "{}", tr!("team_colors.vision.protanopia")),
+            ColorVision::Deuteranopia => write!(f, "{}", tr!("team_colors.vision.deuteranopia")),
+            ColorVision::Tritanopia => write!(f, "{}", tr!("team_colors.vision.tritanopia")),
+        }
+    }
+}
+
+impl ColorVision {
+    pub const ALL: [ColorVision; 4] = [
+        ColorVision::Normal,
+        ColorVision::Protanopia,
+        ColorVision::Deuteranopia,
+        ColorVision::Tritanopia,
+    ];
+
+    /// Machado et al. 2009 matrices at full severity.
+    pub fn simulate(&self, color: Vec3) -> Vec3 {
+        let rows = match self {
+            ColorVision::Normal => return color,
+            ColorVision::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorVision::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.01182, 0.04294, 0.968881],
+            ],
+            ColorVision::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.3039],
+            ],
+        };
+        let matrix = Mat3::from_cols_array_2d(&rows).transpose();
+        (matrix * color).max(Vec3::ZERO).min(Vec3::ONE)
+    }
+}
+
+fn color32(color: Vec3) -> egui::Color32 {
+    egui::Color32::from_rgb(
+        (color.x * 255.) as u8,
+        (color.y * 255.) as u8,
+        (color.z * 255.) as u8,
+    )
+}
+
+// Andrew's monotone chain, counter-clockwise on screen
+fn convex_hull(mut points: Vec<egui::Pos2>) -> Vec<egui::Pos2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    let cross = |o: egui::Pos2, a: egui::Pos2, b: egui::Pos2| {
+        (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+    };
+    let mut hull: Vec<egui::Pos2> = Vec::with_capacity(points.len() + 1);
+    for pass in 0..2 {
+        let start = hull.len();
+        for point in points.iter() {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], *point) <= 0.
+            {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        hull.pop();
+        if pass == 0 {
+            points.reverse();
+        }
+    }
+    hull
+}
+
+pub struct Outline {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub color: Vec4,
+}
+
+/// Objects to outline this frame, with their color, stroked in screen space by `paint`.
+/// Also owns the team colors, which follow the `palette` from the settings. `preview` shows the
+/// world as seen with a color vision deficiency, it is not saved.
+pub struct OutlineResource {
+    pub outlines: Vec<Outline>,
+    pub palette: TeamPalette,
+    pub preview: ColorVision,
+}
+
+impl OutlineResource {
+    pub fn new(palette: TeamPalette) -> Self {
+        Self {
+            outlines: vec![],
+            palette,
+            preview: ColorVision::Normal,
+        }
+    }
+
+    pub fn team_color(&self, team: u8) -> Vec3 {
+        self.preview.simulate(self.palette.color(team))
+    }
+
+    /// Team color, brightened towards white for the selection.
+    pub fn outline_color(&self, team: u8, state: OutlineState) -> Vec4 {
+        let color = self.team_color(team);
+        match state {
+            OutlineState::Owned => (color * 0.6, 1.).into(),
+            OutlineState::Selected => (color.lerp(Vec3::ONE, 0.35), 1.).into(),
+        }
+    }
+
+    pub fn team_swatch(&self, ui: &mut egui::Ui, team: u8) {
+        let size = ui.spacing().interact_size.y;
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
+        ui.painter()
+            .rect_filled(rect, 2., color32(self.team_color(team)));
+    }
+
+    pub fn update(&mut self, world: &World) {
+        self.outlines.clear();
+        let mut query = <(Entity, Read<TransformComponent>, Read<UnitComponent>)>::query();
+        for (entity, transform, unit) in query.iter(world) {
+            if !unit.selected {
+                continue;
+            }
+            self.outlines.push(Outline {
+                entity: *entity,
+                position: transform.translation,
+                radius: UNIT_RADIUS * transform.scale.x.max(transform.scale.y),
+                height: UNIT_HEIGHT * transform.scale.z,
+                color: self.outline_color(unit.team, OutlineState::Selected),
+            });
+        }
+    }
+
+    /// Strokes the screen space silhouette of each outlined object's bounds, `thickness`
+    /// pixels wide, under the egui windows. The bounds are a cylinder around the object's
+    /// origin, projected and wrapped in their convex hull.
+    #[profiling::function]
+    pub fn paint(&self, context: &egui::CtxRef, camera: &RTSCamera, thickness: f32) {
+        let view_proj = camera.view_proj();
+        let points_per_pixel = 1. / context.pixels_per_point();
+        let screen =
+            egui::vec2(camera.win_width as f32, camera.win_height as f32) * points_per_pixel;
+        let stroke_width = thickness.max(1.) * points_per_pixel;
+        let painter = context.layer_painter(egui::LayerId::background());
+        for outline in &self.outlines {
+            let mut points = Vec::with_capacity(2 * OUTLINE_SEGMENTS);
+            for height in [0., outline.height] {
+                for i in 0..OUTLINE_SEGMENTS {
+                    let angle = i as f32 / OUTLINE_SEGMENTS as f32 * 2. * std::f32::consts::PI;
+                    let offset = Vec3::new(angle.cos(), angle.sin(), 0.) * outline.radius;
+                    let clip =
+                        view_proj * (outline.position + offset + height * Vec3::Z).extend(1.);
+                    if clip.w <= 0. {
+                        break;
+                    }
+                    points.push(egui::pos2(
+                        (clip.x / clip.w + 1.) / 2. * screen.x,
+                        (1. - clip.y / clip.w) / 2. * screen.y,
+                    ));
+                }
+            }
+            // partly behind the camera
+            if points.len() < 2 * OUTLINE_SEGMENTS {
+                continue;
+            }
+            let hull = convex_hull(points);
+            let bounds = egui::Rect::from_points(&hull);
+            if !bounds.intersects(egui::Rect::from_min_size(egui::Pos2::ZERO, screen)) {
+                continue;
+            }
+            let color = outline.color;
+            painter.add(egui::Shape::closed_line(
+                hull,
+                egui::Stroke::new(stroke_width, color32(color.truncate())),
+            ));
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("team_colors"))
+            .id_source("team colors")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(tr!("team_colors.palette"));
+                    for palette in TeamPalette::ALL {
+                        let label = format!("{}", palette);
+                        if ui.radio_value(&mut self.palette, palette, label).changed() {
+                            Settings::update(|settings| settings.team_palette = palette);
+                        }
+                    }
+                });
+                // every palette as seen with each deficiency, teams in a row should stay apart
+                for vision in ColorVision::ALL {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.preview, vision, format!("{}", vision));
+                        let size = ui.spacing().interact_size.y;
+                        for color in self.palette.colors() {
+                            let (rect, _) = ui
+                                .allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
+                            let color = vision.simulate(color.into());
+                            ui.painter().rect_filled(rect, 2., color32(color));
+                        }
+                    });
+                }
+                if self.preview != ColorVision::Normal {
+                    ui.label(tr!("team_colors.simulated"));
+                }
+            });
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::unit::unit::UnitType;
+
+const PREFAB_DIR: &str = "prefabs";
+const PREFAB_EXTENSION: &str = "prefab";
+
+/// How a prefab is placed relative to the spawn position.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PrefabTransform {
+    pub offset: Vec3, // voxels
+    /// The XY scale is picked uniformly in `scale_min..=scale_max`, Z stays 1.
+    pub scale_min: f32,
+    pub scale_max: f32,
+    /// Face a random direction instead of +X.
+    pub random_yaw: bool,
+}
+
+impl Default for PrefabTransform {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::new(0., 0., 1.),
+            scale_min: 0.5,
+            scale_max: 2.,
+            random_yaw: true,
+        }
+    }
+}
+
+/// A mesh carried by the unit, following it through the transform hierarchy.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PrefabAttachment {
+    /// Asset path of the mesh, eg. "blender/storage_container1.glb".
+    pub mesh: String,
+    /// Position relative to the unit, before its scale and rotation.
+    #[serde(default)]
+    pub offset: Vec3, // voxels
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+/// A lower detail mesh of the unit, drawn from `distance` to the camera on.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PrefabLod {
+    /// Asset path of the mesh, eg. "blender/storage_container1_lod1.glb".
+    pub mesh: String,
+    pub distance: f32, // m
+}
+
+fn default_scale() -> f32 {
+    1.
+}
+
+fn default_health() -> f32 {
+    1.
+}
+
+/// Everything needed to spawn a unit, stored as RON in `prefabs/<name>.prefab`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Prefab {
+    pub unit_type: UnitType,
+    /// Asset path of the mesh, the unit type's mesh if not set.
+    #[serde(default)]
+    pub mesh: Option<String>,
+    #[serde(default)]
+    pub transform: PrefabTransform,
+    /// Starting health, as a fraction of the maximum.
+    #[serde(default = "default_health")]
+    pub health: f32,
+    #[serde(default)]
+    pub attachments: Vec<PrefabAttachment>,
+    /// LOD chain of `mesh`, by increasing distance. Attachments are drawn at full detail.
+    #[serde(default)]
+    pub lods: Vec<PrefabLod>,
+}
+
+impl Prefab {
+    fn builtin(unit_type: UnitType) -> Self {
+        Self {
+            unit_type,
+            mesh: None,
+            transform: Default::default(),
+            health: default_health(),
+            attachments: vec![],
+            lods: vec![],
+        }
+    }
+}
+
+/// Named unit templates. Every unit type has a built-in prefab under `UnitType::prefab_name`,
+/// and the files in the prefab directory override them or add new ones. Everything that puts
+/// units on the map goes through `UnitsState::spawn_prefab` with one of these names.
+pub struct PrefabRegistry {
+    prefabs: BTreeMap<String, Prefab>,
+    errors: Vec<String>,
+}
+
+impl Default for PrefabRegistry {
+    fn default() -> Self {
+        Self {
+            prefabs: UnitType::ALL
+                .iter()
+                .map(|unit_type| {
+                    (
+                        unit_type.prefab_name().to_string(),
+                        Prefab::builtin(*unit_type),
+                    )
+                })
+                .collect(),
+            errors: vec![],
+        }
+    }
+}
+
+impl PrefabRegistry {
+    pub fn load() -> Self {
+        let mut registry = Self::default();
+        let paths = std::fs::read_dir(PREFAB_DIR)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.extension().and_then(|ext| ext.to_str()) == Some(PREFAB_EXTENSION)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for path in paths {
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let prefab = std::fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| {
+                    ron::de::from_str::<Prefab>(&contents).map_err(|err| err.to_string())
+                });
+            match prefab {
+                Ok(prefab) => {
+                    registry.prefabs.insert(name, prefab);
+                }
+                Err(err) => {
+                    let err = tr!("prefabs.load_failed", path = path.display(), error = err);
+                    log::error!("{}", err);
+                    registry.errors.push(err);
+                }
+            }
+        }
+        log::info!("{} prefabs", registry.prefabs.len());
+        registry
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    /// Asset paths of the meshes the prefabs use, for loading them up front.
+    pub fn mesh_paths(&self) -> BTreeSet<String> {
+        self.prefabs
+            .values()
+            .flat_map(|prefab| {
+                prefab
+                    .mesh
+                    .iter()
+                    .cloned()
+                    .chain(prefab.attachments.iter().map(|a| a.mesh.clone()))
+                    .chain(prefab.lods.iter().map(|lod| lod.mesh.clone()))
+            })
+            .collect()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("prefabs", count = self.prefabs.len()))
+            .id_source("prefabs")
+            .default_open(false)
+            .show(ui, |ui| {
+                for (name, prefab) in &self.prefabs {
+                    ui.label(tr!(
+                        "prefabs.entry",
+                        name = name,
+                        unit = prefab.unit_type,
+                        attachments = prefab.attachments.len(),
+                        lods = prefab.lods.len()
+                    ));
+                }
+                for err in &self.errors {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+                if ui
+                    .button(tr!("prefabs.reload"))
+                    .on_hover_text(tr!("prefabs.reload.hint"))
+                    .clicked()
+                {
+                    *self = Self::load();
+                }
+            });
+    }
+}
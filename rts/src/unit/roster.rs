@@ -0,0 +1,138 @@
+use glam::Vec3;
+use legion::{Entity, IntoQuery, Read, TryRead, World};
+use rafx_plugins::components::TransformComponent;
+
+use crate::unit::{
+    task::{WorkerComponent, WorkerState},
+    unit::{UnitComponent, UnitType},
+};
+
+#[derive(Clone, Copy)]
+pub struct RosterEntry {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub health: f32,
+    pub idle_worker: bool,
+}
+
+pub enum RosterAction {
+    Center(Vec3),
+    Select(Entity, Vec3),
+}
+
+/// The units of one team grouped by type, rebuilt every simulation tick so the panel doesn't
+/// query the world while drawing. Idle workers are cycled in grouping order.
+#[derive(Default)]
+pub struct UnitRoster {
+    groups: Vec<(UnitType, Vec<RosterEntry>)>,
+    idle_cursor: usize,
+}
+
+impl UnitRoster {
+    #[profiling::function]
+    pub fn update(&mut self, world: &World, team: u8) {
+        let mut groups: Vec<(UnitType, Vec<RosterEntry>)> =
+            UnitType::ALL.iter().map(|ty| (*ty, vec![])).collect();
+        let mut query = <(
+            Entity,
+            Read<TransformComponent>,
+            Read<UnitComponent>,
+            TryRead<WorkerComponent>,
+        )>::query();
+        for (entity, transform, unit, worker) in query.iter(world) {
+            if unit.team != team {
+                continue;
+            }
+            let idle_worker = matches!(worker, Some(w) if w.state == WorkerState::Idle)
+                && unit.move_target.is_none()
+                && unit.command.is_none();
+            if let Some((_, entries)) = groups.iter_mut().find(|(ty, _)| *ty == unit.object_type) {
+                entries.push(RosterEntry {
+                    entity: *entity,
+                    position: transform.translation,
+                    health: unit.health,
+                    idle_worker,
+                });
+            }
+        }
+        self.groups = groups;
+    }
+
+    pub fn idle_worker_count(&self) -> usize {
+        self.idle_workers().count()
+    }
+
+    fn idle_workers(&self) -> impl Iterator<Item = &RosterEntry> {
+        self.groups
+            .iter()
+            .flat_map(|(_, entries)| entries.iter())
+            .filter(|entry| entry.idle_worker)
+    }
+
+    pub fn next_idle_worker(&mut self) -> Option<RosterEntry> {
+        let count = self.idle_worker_count();
+        if count == 0 {
+            return None;
+        }
+        let idx = self.idle_cursor % count;
+        self.idle_cursor = idx + 1;
+        self.idle_workers().nth(idx).copied()
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<RosterAction> {
+        let mut action = None;
+        egui::CollapsingHeader::new(tr!("roster"))
+            .id_source("roster")
+            .default_open(false)
+            .show(ui, |ui| {
+                let idle = self.idle_worker_count();
+                if idle > 0 {
+                    if ui
+                        .button(tr!("roster.next_idle", count = idle))
+                        .on_hover_text(tr!("roster.next_idle.hotkey"))
+                        .clicked()
+                    {
+                        if let Some(entry) = self.next_idle_worker() {
+                            action = Some(RosterAction::Select(entry.entity, entry.position));
+                        }
+                    }
+                } else {
+                    ui.label(tr!("roster.no_idle"));
+                }
+                for (ty, entries) in &self.groups {
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    egui::CollapsingHeader::new(format!("{} ({})", ty, entries.len()))
+                        .id_source(format!("roster {}", ty))
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            egui::ScrollArea::vertical()
+                                .max_height(200.)
+                                .show(ui, |ui| {
+                                    for entry in entries {
+                                        let health = format!("{:.0}", entry.health);
+                                        let text = if entry.idle_worker {
+                                            tr!("roster.entry.idle", health = health)
+                                        } else {
+                                            tr!("roster.entry", health = health)
+                                        };
+                                        let label = ui
+                                            .selectable_label(false, text)
+                                            .on_hover_text(tr!("roster.entry.hint"));
+                                        if label.double_clicked() {
+                                            action = Some(RosterAction::Select(
+                                                entry.entity,
+                                                entry.position,
+                                            ));
+                                        } else if label.clicked() {
+                                            action = Some(RosterAction::Center(entry.position));
+                                        }
+                                    }
+                                });
+                        });
+                }
+            });
+        action
+    }
+}
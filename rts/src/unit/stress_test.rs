@@ -0,0 +1,256 @@
+use egui::Button;
+use glam::Vec3;
+use rand::{thread_rng, Rng};
+
+use crate::unit::unit::UnitType;
+
+const SAMPLE_FRAMES: u32 = 120;
+
+#[derive(Clone)]
+pub struct StressTestParams {
+    pub unit_type: UnitType,
+    pub count: u32,
+    pub spacing: f32,
+    pub random_orders: bool,
+}
+
+impl Default for StressTestParams {
+    fn default() -> Self {
+        Self {
+            unit_type: UnitType::BlueIcosphere,
+            count: 2000,
+            spacing: 3.,
+            random_orders: true,
+        }
+    }
+}
+
+impl StressTestParams {
+    /// Square-ish grid of `count` positions centered on `center`.
+    pub fn grid_positions(&self, center: Vec3) -> Vec<Vec3> {
+        let side = (self.count as f32).sqrt().ceil() as u32;
+        let half = (side as f32 - 1.) * self.spacing / 2.;
+        (0..self.count)
+            .map(|idx| {
+                let x = (idx % side) as f32 * self.spacing - half;
+                let y = (idx / side) as f32 * self.spacing - half;
+                Vec3::new(center.x + x, center.y + y, center.z)
+            })
+            .collect()
+    }
+
+    pub fn random_target(&self, center: Vec3) -> Vec3 {
+        let side = (self.count as f32).sqrt().ceil() * self.spacing;
+        let mut rng = thread_rng();
+        Vec3::new(
+            center.x + rng.gen_range(-side..side),
+            center.y + rng.gen_range(-side..side),
+            center.z,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct FrameSamples {
+    pub frames: u32,
+    pub frame_time: f32,      // s
+    pub visibility_time: f32, // µs
+}
+
+impl FrameSamples {
+    fn push(&mut self, dt: f32, visibility_time: f32) {
+        self.frames += 1;
+        self.frame_time += dt;
+        self.visibility_time += visibility_time;
+    }
+
+    pub fn avg_fps(&self) -> f32 {
+        if self.frame_time > 0. {
+            self.frames as f32 / self.frame_time
+        } else {
+            0.
+        }
+    }
+
+    pub fn avg_frame_ms(&self) -> f32 {
+        1000. * self.frame_time / self.frames.max(1) as f32
+    }
+
+    pub fn avg_visibility_us(&self) -> f32 {
+        self.visibility_time / self.frames.max(1) as f32
+    }
+}
+
+pub struct StressTestReport {
+    pub units: u32,
+    pub before: FrameSamples,
+    pub after: FrameSamples,
+}
+
+impl StressTestReport {
+    pub fn info_log(&self) {
+        log::info!(
+            "stress test :: units: {}, fps: {:.1} -> {:.1}, frame: {:.2} ms -> {:.2} ms, visibility update: {:.0} µs -> {:.0} µs",
+            self.units,
+            self.before.avg_fps(),
+            self.after.avg_fps(),
+            self.before.avg_frame_ms(),
+            self.after.avg_frame_ms(),
+            self.before.avg_visibility_us(),
+            self.after.avg_visibility_us(),
+        );
+    }
+
+    fn ui(&self, ui: &mut egui::Ui) {
+        ui.label(tr!("stress_test.spawned", count = self.units));
+        egui::Grid::new("stress_test_report").show(ui, |ui| {
+            ui.label("");
+            ui.label(tr!("stress_test.before"));
+            ui.label(tr!("stress_test.after"));
+            ui.end_row();
+            ui.label(tr!("stress_test.fps"));
+            ui.label(format!("{:.1}", self.before.avg_fps()));
+            ui.label(format!("{:.1}", self.after.avg_fps()));
+            ui.end_row();
+            ui.label(tr!("stress_test.frame"));
+            ui.label(tr!(
+                "stress_test.ms",
+                time = format!("{:.2}", self.before.avg_frame_ms())
+            ));
+            ui.label(tr!(
+                "stress_test.ms",
+                time = format!("{:.2}", self.after.avg_frame_ms())
+            ));
+            ui.end_row();
+            ui.label(tr!("stress_test.visibility"));
+            ui.label(tr!(
+                "stress_test.us",
+                time = format!("{:.0}", self.before.avg_visibility_us())
+            ));
+            ui.label(tr!(
+                "stress_test.us",
+                time = format!("{:.0}", self.after.avg_visibility_us())
+            ));
+            ui.end_row();
+        });
+    }
+}
+
+enum StressTestPhase {
+    Idle,
+    Baseline(FrameSamples),
+    Measure(FrameSamples, FrameSamples),
+}
+
+pub enum StressTestAction {
+    None,
+    Spawn,
+}
+
+pub struct StressTestUiState {
+    pub params: StressTestParams,
+    pub report: Option<StressTestReport>,
+    phase: StressTestPhase,
+}
+
+impl Default for StressTestUiState {
+    fn default() -> Self {
+        Self {
+            params: Default::default(),
+            report: None,
+            phase: StressTestPhase::Idle,
+        }
+    }
+}
+
+impl StressTestUiState {
+    pub fn is_running(&self) -> bool {
+        !matches!(self.phase, StressTestPhase::Idle)
+    }
+
+    pub fn start(&mut self) {
+        if !self.is_running() {
+            log::info!(
+                "Starting stress test with {} units (spacing: {})",
+                self.params.count,
+                self.params.spacing
+            );
+            self.report = None;
+            self.phase = StressTestPhase::Baseline(Default::default());
+        }
+    }
+
+    /// Feeds the frame time and the visibility push time of the last frame, see
+    /// `VisibilityUpdateResource::last_push_time`. Asks for the units to be spawned once the
+    /// baseline samples are collected, then publishes a report after the same number of frames.
+    pub fn sample(&mut self, dt: f32, visibility_time: f32) -> StressTestAction {
+        match &mut self.phase {
+            StressTestPhase::Idle => StressTestAction::None,
+            StressTestPhase::Baseline(before) => {
+                before.push(dt, visibility_time);
+                if before.frames >= SAMPLE_FRAMES {
+                    self.phase = StressTestPhase::Measure(*before, Default::default());
+                    StressTestAction::Spawn
+                } else {
+                    StressTestAction::None
+                }
+            }
+            StressTestPhase::Measure(before, after) => {
+                after.push(dt, visibility_time);
+                if after.frames >= SAMPLE_FRAMES {
+                    let report = StressTestReport {
+                        units: self.params.count,
+                        before: *before,
+                        after: *after,
+                    };
+                    report.info_log();
+                    self.report = Some(report);
+                    self.phase = StressTestPhase::Idle;
+                }
+                StressTestAction::None
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, unit_types: &[UnitType]) {
+        egui::CollapsingHeader::new(tr!("stress_test"))
+            .id_source("stress test")
+            .default_open(false)
+            .show(ui, |ui| {
+                let params = &mut self.params;
+                egui::ComboBox::from_label(tr!("stress_test.unit"))
+                    .selected_text(format!("{}", params.unit_type))
+                    .show_ui(ui, |ui| {
+                        for unit_type in unit_types {
+                            ui.selectable_value(
+                                &mut params.unit_type,
+                                *unit_type,
+                                format!("{}", unit_type),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut params.count, 100..=20000)
+                        .text(tr!("stress_test.count")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut params.spacing, 1.0..=10.0)
+                        .text(tr!("stress_test.spacing")),
+                );
+                ui.checkbox(&mut params.random_orders, tr!("stress_test.random_orders"));
+
+                if self.is_running() {
+                    ui.label(tr!("stress_test.measuring"));
+                } else if ui
+                    .add_sized([100., 30.], Button::new(tr!("stress_test.run")))
+                    .clicked()
+                {
+                    self.start();
+                }
+
+                if let Some(report) = &self.report {
+                    report.ui(ui);
+                }
+            });
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, fmt::Display};
+
+use glam::Vec3;
+
+use crate::env::stable_id::StableId;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum TaskKind {
+    Gather,
+    Build,
+    Repair,
+}
+
+impl TaskKind {
+    pub const ALL: [TaskKind; 3] = [TaskKind::Gather, TaskKind::Build, TaskKind::Repair];
+
+    /// Seconds of work a single worker needs to finish a task of this kind.
+    pub fn work(&self) -> f32 {
+        match self {
+            TaskKind::Gather => 5.,
+            TaskKind::Build => 10.,
+            TaskKind::Repair => 3.,
+        }
+    }
+}
+
+impl Display for TaskKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            TaskKind::Gather => write!(f, "{}", tr!("task.gather")),
+            TaskKind::Build => write!(f, "{}", tr!("task.build")),
+            TaskKind::Repair => write!(f, "{}", tr!("task.repair")),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct TaskId(u64);
+
+#[derive(Clone)]
+pub struct Task {
+    pub kind: TaskKind,
+    pub position: Vec3,
+    pub target: Option<StableId>,
+    pub work_left: f32,
+    pub worker: Option<StableId>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WorkerState {
+    Idle,
+    Moving(TaskId),
+    Working(TaskId),
+}
+
+#[derive(Clone)]
+pub struct WorkerComponent {
+    pub state: WorkerState,
+}
+
+impl Default for WorkerComponent {
+    fn default() -> Self {
+        Self {
+            state: WorkerState::Idle,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TaskScheduler {
+    tasks: HashMap<TaskId, Task>,
+    next_id: u64,
+}
+
+impl TaskScheduler {
+    pub fn post(&mut self, kind: TaskKind, position: Vec3, target: Option<StableId>) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        log::info!("Posted {} task {:?} at {}", kind, id, position);
+        self.tasks.insert(
+            id,
+            Task {
+                kind,
+                position,
+                target,
+                work_left: kind.work(),
+                worker: None,
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: TaskId) -> Option<&Task> {
+        self.tasks.get(&id)
+    }
+
+    pub fn tasks(&self) -> impl Iterator<Item = (&TaskId, &Task)> {
+        self.tasks.iter()
+    }
+
+    pub fn has_task_for(&self, kind: TaskKind, target: StableId) -> bool {
+        self.tasks
+            .values()
+            .any(|task| task.kind == kind && task.target == Some(target))
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.tasks.values().filter(|t| t.worker.is_none()).count()
+    }
+
+    pub fn claimed_count(&self) -> usize {
+        self.tasks.len() - self.open_count()
+    }
+
+    /// Assigns the nearest unclaimed task to `worker` and returns it.
+    pub fn claim_nearest(&mut self, worker: StableId, position: Vec3) -> Option<(TaskId, Vec3)> {
+        let (id, task) = self
+            .tasks
+            .iter_mut()
+            .filter(|(_, task)| task.worker.is_none())
+            .min_by(|(_, t0), (_, t1)| {
+                let d0 = (t0.position - position).length_squared();
+                let d1 = (t1.position - position).length_squared();
+                d0.partial_cmp(&d1).unwrap_or(std::cmp::Ordering::Equal)
+            })?;
+        task.worker = Some(worker);
+        Some((*id, task.position))
+    }
+
+    /// Assigns a specific task to `worker` if nobody else claimed it yet.
+    pub fn claim(&mut self, id: TaskId, worker: StableId) -> Option<Vec3> {
+        let task = self.tasks.get_mut(&id)?;
+        if task.worker.is_some() {
+            return None;
+        }
+        task.worker = Some(worker);
+        Some(task.position)
+    }
+
+    pub fn release(&mut self, id: TaskId) {
+        if let Some(task) = self.tasks.get_mut(&id) {
+            task.worker = None;
+        }
+    }
+
+    /// Advances the task by `dt` seconds of work. Returns the finished task when done.
+    pub fn progress(&mut self, id: TaskId, dt: f32) -> Option<Task> {
+        let task = self.tasks.get_mut(&id)?;
+        task.work_left -= dt;
+        if task.work_left <= 0. {
+            let task = self.tasks.remove(&id);
+            if let Some(task) = &task {
+                log::info!("Finished {} task {:?}", task.kind, id);
+            }
+            task
+        } else {
+            None
+        }
+    }
+
+    /// Drops the task, its worker goes idle on the next update.
+    pub fn cancel(&mut self, id: TaskId) -> Option<Task> {
+        self.tasks.remove(&id)
+    }
+
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Display,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::unit::{
+    combat::{ArmorClass, DamageType},
+    unit::UnitType,
+};
+
+pub const MAX_TEAMS: usize = 4;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum UnitStat {
+    Speed,
+    WorkRate,
+}
+
+impl Display for UnitStat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            UnitStat::Speed => write!(f, "{}", tr!("tech.stat.speed")),
+            UnitStat::WorkRate => write!(f, "{}", tr!("tech.stat.work_rate")),
+        }
+    }
+}
+
+/// Stats of a unit type as read by the simulation, after the team's upgrades.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct UnitDef {
+    pub speed: f32, // m/s
+    pub work_rate: f32,
+    pub max_health: f32, // hp
+    pub armor: ArmorClass,
+    /// Per hit, before the multipliers of `CombatRules`.
+    pub damage: f32, // hp
+    pub damage_type: DamageType,
+    pub attack_interval: f32, // s
+    /// Hits also damage units this close to the target, see `CombatRules::falloff`.
+    pub splash_radius: f32, // m
+}
+
+impl UnitDef {
+    pub fn base(unit_type: UnitType) -> Self {
+        let def = Self {
+            speed: 10.,
+            work_rate: 1.,
+            max_health: 80.,
+            armor: ArmorClass::Light,
+            damage: 6.,
+            damage_type: DamageType::Kinetic,
+            attack_interval: 0.5,
+            splash_radius: 0.,
+        };
+        match unit_type {
+            UnitType::Container1 => Self {
+                speed: 7.,
+                work_rate: 1.5,
+                max_health: 200.,
+                armor: ArmorClass::Heavy,
+                damage: 12.,
+                attack_interval: 1.,
+                ..def
+            },
+            UnitType::Container2 => Self {
+                speed: 8.,
+                work_rate: 0.5,
+                max_health: 150.,
+                armor: ArmorClass::Medium,
+                damage: 30.,
+                damage_type: DamageType::Explosive,
+                attack_interval: 2.5,
+                splash_radius: 4.,
+                ..def
+            },
+            UnitType::BlueIcosphere => Self { speed: 12., ..def },
+        }
+    }
+
+    /// A building as a combat target, heavily armored and unarmed.
+    pub fn building(max_health: f32) -> Self {
+        Self {
+            speed: 0.,
+            work_rate: 0.,
+            max_health,
+            armor: ArmorClass::Heavy,
+            damage: 0.,
+            damage_type: DamageType::Kinetic,
+            attack_interval: 0.,
+            splash_radius: 0.,
+        }
+    }
+
+    fn stat_mut(&mut self, stat: UnitStat) -> &mut f32 {
+        match stat {
+            UnitStat::Speed => &mut self.speed,
+            UnitStat::WorkRate => &mut self.work_rate,
+        }
+    }
+}
+
+/// Changes `stat` to `(base + add) * mul`, for all unit types or only `unit_type`.
+#[derive(Clone, Copy, Debug)]
+pub struct Modifier {
+    pub stat: UnitStat,
+    pub unit_type: Option<UnitType>,
+    pub add: f32,
+    pub mul: f32,
+}
+
+impl Modifier {
+    pub fn add(stat: UnitStat, unit_type: Option<UnitType>, add: f32) -> Self {
+        Self {
+            stat,
+            unit_type,
+            add,
+            mul: 1.,
+        }
+    }
+
+    pub fn mul(stat: UnitStat, unit_type: Option<UnitType>, mul: f32) -> Self {
+        Self {
+            stat,
+            unit_type,
+            add: 0.,
+            mul,
+        }
+    }
+}
+
+impl Display for Modifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.add != 0. {
+            write!(f, "{:+} {}", self.add, self.stat)?;
+        }
+        if self.mul != 1. {
+            if self.add != 0. {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:+.0}% {}", (self.mul - 1.) * 100., self.stat)?;
+        }
+        if let Some(unit_type) = self.unit_type {
+            write!(f, " ({})", unit_type)?;
+        }
+        Ok(())
+    }
+}
+
+/// All modifiers affecting a team. Additions are summed before the multipliers, so the
+/// order techs were researched in does not matter.
+#[derive(Default)]
+pub struct ModifierStack {
+    modifiers: Vec<Modifier>,
+}
+
+impl ModifierStack {
+    pub fn push(&mut self, modifier: Modifier) {
+        self.modifiers.push(modifier);
+    }
+
+    pub fn apply(&self, unit_type: UnitType, mut def: UnitDef) -> UnitDef {
+        for stat in [UnitStat::Speed, UnitStat::WorkRate] {
+            let mut add = 0.;
+            let mut mul = 1.;
+            for modifier in self
+                .modifiers
+                .iter()
+                .filter(|m| m.stat == stat && m.unit_type.map_or(true, |ty| ty == unit_type))
+            {
+                add += modifier.add;
+                mul *= modifier.mul;
+            }
+            let value = def.stat_mut(stat);
+            *value = (*value + add) * mul;
+        }
+        def
+    }
+}
+
+pub struct Tech {
+    pub id: &'static str,
+    /// Locale key of the name shown to players.
+    pub name: &'static str,
+    pub cost: u32,
+    pub duration: f32, // s
+    /// Tile that must stand on the map to research it.
+    pub building: &'static str,
+    pub requires: Vec<&'static str>,
+    pub modifiers: Vec<Modifier>,
+}
+
+fn tech_tree() -> Vec<Tech> {
+    vec![
+        Tech {
+            id: "efficient_tools",
+            name: "tech.efficient_tools",
+            cost: 30,
+            duration: 10.,
+            building: "building",
+            requires: vec![],
+            modifiers: vec![Modifier::mul(UnitStat::WorkRate, None, 1.5)],
+        },
+        Tech {
+            id: "light_alloys",
+            name: "tech.light_alloys",
+            cost: 40,
+            duration: 15.,
+            building: "building",
+            requires: vec![],
+            modifiers: vec![Modifier::mul(UnitStat::Speed, None, 1.2)],
+        },
+        Tech {
+            id: "automation",
+            name: "tech.automation",
+            cost: 80,
+            duration: 30.,
+            building: "building",
+            requires: vec!["efficient_tools"],
+            modifiers: vec![Modifier::add(UnitStat::WorkRate, None, 0.5)],
+        },
+        Tech {
+            id: "hover_drives",
+            name: "tech.hover_drives",
+            cost: 100,
+            duration: 30.,
+            building: "bronze_statue",
+            requires: vec!["light_alloys"],
+            modifiers: vec![Modifier::add(
+                UnitStat::Speed,
+                Some(UnitType::BlueIcosphere),
+                6.,
+            )],
+        },
+    ]
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Research {
+    pub tech: String,
+    pub progress: f32, // s
+}
+
+/// Research state and resource stock of one team. This is what savegames store.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct TeamTech {
+    pub stock: u32,
+    pub researched: BTreeSet<String>,
+    pub research: Option<Research>,
+}
+
+pub enum TechStatus {
+    Researched,
+    Researching(f32),
+    Available,
+    MissingBuilding,
+    MissingRequirements,
+    TooExpensive,
+}
+
+pub struct TechResource {
+    techs: Vec<Tech>,
+    teams: Vec<TeamTech>,
+    // `unit_def` of every team and unit type, rebuilt when research finishes
+    defs: HashMap<(u8, UnitType), UnitDef>,
+}
+
+impl Default for TechResource {
+    fn default() -> Self {
+        let mut tech = Self {
+            techs: tech_tree(),
+            teams: vec![Default::default(); MAX_TEAMS],
+            defs: HashMap::new(),
+        };
+        tech.update_defs();
+        tech
+    }
+}
+
+impl TechResource {
+    pub fn get(&self, id: &str) -> Option<&Tech> {
+        self.techs.iter().find(|tech| tech.id == id)
+    }
+
+    pub fn team(&self, team: u8) -> &TeamTech {
+        &self.teams[team as usize % MAX_TEAMS]
+    }
+
+    fn team_mut(&mut self, team: u8) -> &mut TeamTech {
+        &mut self.teams[team as usize % MAX_TEAMS]
+    }
+
+    pub fn add_stock(&mut self, team: u8, amount: u32) {
+        self.team_mut(team).stock += amount;
+    }
+
+    pub fn modifiers(&self, team: u8) -> ModifierStack {
+        let mut stack = ModifierStack::default();
+        for id in &self.team(team).researched {
+            if let Some(tech) = self.get(id) {
+                for modifier in &tech.modifiers {
+                    stack.push(*modifier);
+                }
+            }
+        }
+        stack
+    }
+
+    /// The unit type's stats for `team`, all unit stats should be read through this. Cheap
+    /// enough to call per unit and frame.
+    pub fn unit_def(&self, team: u8, unit_type: UnitType) -> UnitDef {
+        self.defs
+            .get(&(team % MAX_TEAMS as u8, unit_type))
+            .copied()
+            .unwrap_or_else(|| UnitDef::base(unit_type))
+    }
+
+    fn update_defs(&mut self) {
+        self.defs = (0..MAX_TEAMS as u8)
+            .flat_map(|team| {
+                let modifiers = self.modifiers(team);
+                UnitType::ALL.map(move |ty| ((team, ty), modifiers.apply(ty, UnitDef::base(ty))))
+            })
+            .collect();
+    }
+
+    pub fn status(&self, team: u8, tech: &Tech, buildings: &HashSet<String>) -> TechStatus {
+        let state = self.team(team);
+        if state.researched.contains(tech.id) {
+            TechStatus::Researched
+        } else if let Some(research) = state.research.as_ref().filter(|r| r.tech == tech.id) {
+            TechStatus::Researching(research.progress / tech.duration)
+        } else if !tech
+            .requires
+            .iter()
+            .all(|id| state.researched.contains(*id))
+        {
+            TechStatus::MissingRequirements
+        } else if !buildings.contains(tech.building) {
+            TechStatus::MissingBuilding
+        } else if state.stock < tech.cost {
+            TechStatus::TooExpensive
+        } else {
+            TechStatus::Available
+        }
+    }
+
+    /// Pays for the tech and starts researching it, replacing (and refunding) the current
+    /// research of the team.
+    pub fn start_research(&mut self, team: u8, id: &str, buildings: &HashSet<String>) -> bool {
+        let (cost, available) = match self.get(id) {
+            Some(tech) => (
+                tech.cost,
+                matches!(self.status(team, tech, buildings), TechStatus::Available),
+            ),
+            None => return false,
+        };
+        if !available {
+            return false;
+        }
+        self.cancel_research(team);
+        let state = self.team_mut(team);
+        state.stock -= cost;
+        state.research = Some(Research {
+            tech: id.to_string(),
+            progress: 0.,
+        });
+        true
+    }
+
+    pub fn cancel_research(&mut self, team: u8) {
+        let research = self.team_mut(team).research.take();
+        if let Some(cost) = research
+            .and_then(|r| self.get(&r.tech))
+            .map(|tech| tech.cost)
+        {
+            self.team_mut(team).stock += cost;
+        }
+    }
+
+    /// Advances research, returns the team and name of the techs finished.
+    pub fn update(&mut self, dt: f32) -> Vec<(u8, &'static str)> {
+        let mut finished = vec![];
+        for (team, state) in self.teams.iter_mut().enumerate() {
+            let done = match &mut state.research {
+                Some(research) => {
+                    research.progress += dt;
+                    let duration = self
+                        .techs
+                        .iter()
+                        .find(|tech| tech.id == research.tech)
+                        .map_or(0., |tech| tech.duration);
+                    research.progress >= duration
+                }
+                None => false,
+            };
+            if done {
+                let research = state.research.take().unwrap();
+                log::info!("Team {} researched {}", team, research.tech);
+                if let Some(tech) = self.techs.iter().find(|tech| tech.id == research.tech) {
+                    finished.push((team as u8, tech.name));
+                }
+                state.researched.insert(research.tech);
+            }
+        }
+        if !finished.is_empty() {
+            self.update_defs();
+        }
+        finished
+    }
+
+    pub fn save(&self) -> Vec<TeamTech> {
+        self.teams.clone()
+    }
+
+    pub fn restore(&mut self, teams: Vec<TeamTech>) {
+        self.teams = teams;
+        self.teams.resize(MAX_TEAMS, Default::default());
+        self.update_defs();
+    }
+
+    pub fn reset(&mut self) {
+        self.restore(vec![]);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, team: u8, buildings: &HashSet<String>) {
+        egui::CollapsingHeader::new(tr!("tech"))
+            .id_source("tech tree")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.label(tr!(
+                    "tech.stock",
+                    team = team,
+                    stock = self.team(team).stock
+                ));
+                let mut start = None;
+                let mut cancel = false;
+                for tech in &self.techs {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label(crate::locale::tr(tech.name));
+                        match self.status(team, tech, buildings) {
+                            TechStatus::Researched => {
+                                ui.label(tr!("tech.researched"));
+                            }
+                            TechStatus::Researching(progress) => {
+                                ui.label(format!("{:.0}%", progress * 100.));
+                                if ui.button(tr!("tech.cancel")).clicked() {
+                                    cancel = true;
+                                }
+                            }
+                            TechStatus::Available => {
+                                if ui.button(tr!("tech.research", cost = tech.cost)).clicked() {
+                                    start = Some(tech.id);
+                                }
+                            }
+                            TechStatus::MissingBuilding => {
+                                ui.label(tr!("tech.needs_building", building = tech.building));
+                            }
+                            TechStatus::MissingRequirements => {
+                                ui.label(tr!("tech.locked"));
+                            }
+                            TechStatus::TooExpensive => {
+                                ui.label(tr!("tech.costs", cost = tech.cost));
+                            }
+                        }
+                    });
+                    for modifier in &tech.modifiers {
+                        ui.label(format!("  {}", modifier));
+                    }
+                    if !tech.requires.is_empty() {
+                        let requires: Vec<_> = tech
+                            .requires
+                            .iter()
+                            .filter_map(|id| self.techs.iter().find(|tech| tech.id == *id))
+                            .map(|tech| crate::locale::tr(tech.name))
+                            .collect();
+                        ui.label(tr!("tech.requires", techs = requires.join(", ")));
+                    }
+                }
+                if cancel {
+                    self.cancel_research(team);
+                }
+                if let Some(id) = start {
+                    self.start_research(team, id, buildings);
+                }
+            });
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::{HashSet, VecDeque};
+
+use building_blocks::core::prelude::*;
+use glam::{Vec3, Vec4};
+use legion::{IntoQuery, Read, World};
+use rafx_plugins::{components::TransformComponent, features::debug3d::Debug3DResource};
+
+use crate::{
+    env::stable_id::StableId,
+    unit::unit::{UnitComponent, UnitType},
+};
+
+// events kept for subscribers that read less often than every frame
+const MAX_EVENT_LOG: usize = 256;
+const CIRCLE_SEGMENTS: usize = 24;
+
+#[derive(Clone, Copy, Debug)]
+pub enum TriggerRegion {
+    Aabb {
+        min: Vec3,
+        max: Vec3,
+    },
+    /// Voxel coordinates, a unit is inside when the voxel it stands in is.
+    Voxels(Extent3i),
+    /// Upright cylinder standing on `base`.
+    Cylinder {
+        base: Vec3,
+        radius: f32,
+        height: f32,
+    },
+}
+
+impl TriggerRegion {
+    pub fn contains(&self, point: Vec3) -> bool {
+        match self {
+            TriggerRegion::Aabb { min, max } => point.cmpge(*min).all() && point.cmple(*max).all(),
+            TriggerRegion::Voxels(extent) => extent.contains(PointN([
+                point.x.floor() as i32,
+                point.y.floor() as i32,
+                point.z.floor() as i32,
+            ])),
+            TriggerRegion::Cylinder {
+                base,
+                radius,
+                height,
+            } => {
+                let offset = point - *base;
+                offset.truncate().length() <= *radius && offset.z >= 0. && offset.z <= *height
+            }
+        }
+    }
+
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        match self {
+            TriggerRegion::Aabb { min, max } => (*min, *max),
+            TriggerRegion::Voxels(extent) => {
+                let min = extent.minimum;
+                let max = extent.least_upper_bound();
+                (
+                    Vec3::new(min.x() as f32, min.y() as f32, min.z() as f32),
+                    Vec3::new(max.x() as f32, max.y() as f32, max.z() as f32),
+                )
+            }
+            TriggerRegion::Cylinder {
+                base,
+                radius,
+                height,
+            } => (
+                *base - Vec3::new(*radius, *radius, 0.),
+                *base + Vec3::new(*radius, *radius, *height),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        let (min, max) = self.bounds();
+        (min + max) / 2.
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TriggerAction {
+    SpawnUnits {
+        unit_type: UnitType,
+        team: u8,
+        count: u32,
+        position: Vec3,
+    },
+    Message(String),
+}
+
+/// Fires its actions once at least `min_units` units (of `team`, if set) are inside `region`.
+/// A `repeat` trigger re-arms when the count drops below the threshold again.
+pub struct Trigger {
+    pub name: String,
+    pub region: TriggerRegion,
+    pub team: Option<u8>,
+    pub min_units: u32,
+    pub repeat: bool,
+    pub actions: Vec<TriggerAction>,
+    inside: HashSet<StableId>,
+    armed: bool,
+    fired_count: u32,
+}
+
+impl Trigger {
+    pub fn new(name: &str, region: TriggerRegion, min_units: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            region,
+            team: None,
+            min_units: min_units.max(1),
+            repeat: false,
+            actions: vec![],
+            inside: Default::default(),
+            armed: true,
+            fired_count: 0,
+        }
+    }
+
+    pub fn units_inside(&self) -> usize {
+        self.inside.len()
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct TriggerId(u64);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TriggerEventKind {
+    Enter(StableId),
+    Leave(StableId),
+    Fired,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TriggerEvent {
+    pub trigger: TriggerId,
+    pub kind: TriggerEventKind,
+}
+
+/// Read position of a subscriber in the event log, see `TriggerResource::subscribe`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TriggerCursor(u64);
+
+struct TriggerEditor {
+    size: f32,
+    round: bool,
+    min_units: u32,
+    repeat: bool,
+    spawn_team: u8,
+    spawn_count: u32,
+    message: String,
+}
+
+impl Default for TriggerEditor {
+    fn default() -> Self {
+        Self {
+            size: 16.,
+            round: false,
+            min_units: 5,
+            repeat: false,
+            spawn_team: 1,
+            spawn_count: 5,
+            message: "".to_string(),
+        }
+    }
+}
+
+/// Trigger volumes for mission logic. `update` records the enter, leave and fire events of
+/// the frame, which stay readable until the next update, and queues the actions of fired
+/// triggers for whoever runs them (`take_actions`). Systems that don't look every frame, like
+/// tutorial scripts, `subscribe` and `read` the events logged since with their cursor.
+#[derive(Default)]
+pub struct TriggerResource {
+    triggers: Vec<(TriggerId, Trigger)>,
+    next_id: u64,
+    events: Vec<TriggerEvent>,
+    log: VecDeque<TriggerEvent>,
+    // sequence number of the first event in `log`
+    log_start: u64,
+    actions: Vec<(TriggerId, TriggerAction)>,
+    pub show_bounds: bool,
+    /// The trigger under the cursor while the bounds are shown, drawn highlighted.
+    pub hovered: Option<TriggerId>,
+    editor: TriggerEditor,
+}
+
+impl TriggerResource {
+    pub fn add(&mut self, trigger: Trigger) -> TriggerId {
+        self.next_id += 1;
+        let id = TriggerId(self.next_id);
+        self.triggers.push((id, trigger));
+        id
+    }
+
+    pub fn remove(&mut self, id: TriggerId) -> Option<Trigger> {
+        let idx = self.triggers.iter().position(|(t, _)| *t == id)?;
+        Some(self.triggers.remove(idx).1)
+    }
+
+    pub fn get(&self, id: TriggerId) -> Option<&Trigger> {
+        self.triggers.iter().find(|(t, _)| *t == id).map(|(_, t)| t)
+    }
+
+    pub fn find(&self, name: &str) -> Option<TriggerId> {
+        self.iter()
+            .find(|(_, trigger)| trigger.name == name)
+            .map(|(id, _)| id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (TriggerId, &Trigger)> {
+        self.triggers.iter().map(|(id, trigger)| (*id, trigger))
+    }
+
+    pub fn clear(&mut self) {
+        self.triggers.clear();
+        self.events.clear();
+        self.log_start += self.log.len() as u64;
+        self.log.clear();
+        self.actions.clear();
+    }
+
+    /// The events of the last update.
+    pub fn events(&self) -> &[TriggerEvent] {
+        &self.events
+    }
+
+    /// A cursor past the events logged so far.
+    pub fn subscribe(&self) -> TriggerCursor {
+        TriggerCursor(self.log_start + self.log.len() as u64)
+    }
+
+    /// The events logged since `cursor`, which is moved past them. A cursor left behind for
+    /// more than `MAX_EVENT_LOG` events misses the oldest ones.
+    pub fn read(&self, cursor: &mut TriggerCursor) -> impl Iterator<Item = &TriggerEvent> {
+        let skip = cursor.0.saturating_sub(self.log_start) as usize;
+        *cursor = self.subscribe();
+        self.log.iter().skip(skip)
+    }
+
+    /// The actions of the triggers fired since the last call, with the trigger that fired them.
+    pub fn take_actions(&mut self) -> Vec<(TriggerId, TriggerAction)> {
+        std::mem::take(&mut self.actions)
+    }
+
+    #[profiling::function]
+    pub fn update(&mut self, world: &World) {
+        self.events.clear();
+        if self.triggers.is_empty() {
+            return;
+        }
+        let mut query = <(
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<UnitComponent>,
+        )>::query();
+        let units: Vec<_> = query
+            .iter(world)
+            .map(|(id, transform, unit)| (*id, transform.translation, unit.team))
+            .collect();
+
+        for (trigger_id, trigger) in self.triggers.iter_mut() {
+            let inside: HashSet<_> = units
+                .iter()
+                .filter(|(_, position, team)| {
+                    trigger.team.map_or(true, |t| t == *team) && trigger.region.contains(*position)
+                })
+                .map(|(id, _, _)| *id)
+                .collect();
+            for id in inside.difference(&trigger.inside) {
+                self.events.push(TriggerEvent {
+                    trigger: *trigger_id,
+                    kind: TriggerEventKind::Enter(*id),
+                });
+            }
+            for id in trigger.inside.difference(&inside) {
+                self.events.push(TriggerEvent {
+                    trigger: *trigger_id,
+                    kind: TriggerEventKind::Leave(*id),
+                });
+            }
+            trigger.inside = inside;
+
+            let reached = trigger.inside.len() >= trigger.min_units as usize;
+            if reached && trigger.armed {
+                log::info!("Trigger {} fired", trigger.name);
+                trigger.armed = false;
+                trigger.fired_count += 1;
+                self.events.push(TriggerEvent {
+                    trigger: *trigger_id,
+                    kind: TriggerEventKind::Fired,
+                });
+                self.actions.extend(
+                    trigger
+                        .actions
+                        .iter()
+                        .map(|action| (*trigger_id, action.clone())),
+                );
+            } else if !reached && trigger.repeat {
+                trigger.armed = true;
+            }
+        }
+
+        self.log.extend(self.events.iter().copied());
+        let overflow = self.log.len().saturating_sub(MAX_EVENT_LOG);
+        self.log.drain(..overflow);
+        self.log_start += overflow as u64;
+    }
+
+    pub fn add_debug_draw(&self, debug_draw: &mut Debug3DResource) {
+        if !self.show_bounds {
+            return;
+        }
+        for (id, trigger) in &self.triggers {
+            let color = if self.hovered == Some(*id) {
+                Vec4::new(1., 1., 1., 1.)
+            } else if !trigger.armed {
+                Vec4::new(1., 0.2, 0.2, 1.)
+            } else if trigger.inside.is_empty() {
+                Vec4::new(0.2, 1., 0.2, 1.)
+            } else {
+                Vec4::new(1., 1., 0.2, 1.)
+            };
+            if let TriggerRegion::Cylinder {
+                base,
+                radius,
+                height,
+            } = trigger.region
+            {
+                let point = |i: usize, z: f32| {
+                    let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                    base + Vec3::new(radius * angle.cos(), radius * angle.sin(), z)
+                };
+                for i in 0..CIRCLE_SEGMENTS {
+                    for z in [0., height] {
+                        debug_draw.add_line(point(i, z), point(i + 1, z), color);
+                    }
+                    if i % (CIRCLE_SEGMENTS / 4) == 0 {
+                        debug_draw.add_line(point(i, 0.), point(i, height), color);
+                    }
+                }
+                continue;
+            }
+            let (min, max) = trigger.region.bounds();
+            let corner = |i: usize| {
+                Vec3::new(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 { min.z } else { max.z },
+                )
+            };
+            // corners differing in exactly one bit share an edge
+            for i in 0..8 {
+                for bit in [1, 2, 4] {
+                    if i & bit == 0 {
+                        debug_draw.add_line(corner(i), corner(i | bit), color);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, center: Vec3) {
+        egui::CollapsingHeader::new(tr!("triggers"))
+            .id_source("triggers")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.show_bounds, tr!("triggers.show_bounds"));
+                let mut remove = None;
+                for (id, trigger) in &self.triggers {
+                    let hovered = if self.hovered == Some(*id) { "> " } else { "" };
+                    ui.horizontal(|ui| {
+                        ui.label(tr!(
+                            "triggers.entry",
+                            hovered = hovered,
+                            name = trigger.name,
+                            inside = trigger.units_inside(),
+                            min = trigger.min_units,
+                            fired = trigger.fired_count
+                        ));
+                        if ui
+                            .small_button("x")
+                            .on_hover_text(tr!("triggers.remove"))
+                            .clicked()
+                        {
+                            remove = Some(*id);
+                        }
+                    });
+                }
+                if let Some(id) = remove {
+                    self.remove(id);
+                }
+
+                ui.separator();
+                let editor = &mut self.editor;
+                ui.add(egui::Slider::new(&mut editor.size, 2.0..=64.0).text(tr!("triggers.size")));
+                ui.checkbox(&mut editor.round, tr!("triggers.round"));
+                ui.add(
+                    egui::Slider::new(&mut editor.min_units, 1..=50)
+                        .text(tr!("triggers.min_units")),
+                );
+                ui.checkbox(&mut editor.repeat, tr!("triggers.repeat"));
+                ui.add(
+                    egui::Slider::new(&mut editor.spawn_team, 0..=3)
+                        .text(tr!("triggers.spawn_team")),
+                );
+                ui.add(
+                    egui::Slider::new(&mut editor.spawn_count, 0..=50)
+                        .text(tr!("triggers.spawn_count")),
+                );
+                ui.horizontal(|ui| {
+                    ui.label(tr!("triggers.message"));
+                    ui.text_edit_singleline(&mut editor.message);
+                });
+                if ui.button(tr!("triggers.add")).clicked() {
+                    let half = Vec3::new(editor.size, editor.size, 8.) / 2.;
+                    let region = if editor.round {
+                        TriggerRegion::Cylinder {
+                            base: center - Vec3::Z * half.z,
+                            radius: half.x,
+                            height: 2. * half.z,
+                        }
+                    } else {
+                        TriggerRegion::Aabb {
+                            min: center - half,
+                            max: center + half,
+                        }
+                    };
+                    let mut trigger = Trigger::new(
+                        &tr!("triggers.default_name", index = self.next_id + 1),
+                        region,
+                        editor.min_units,
+                    );
+                    trigger.repeat = editor.repeat;
+                    if editor.spawn_count > 0 {
+                        trigger.actions.push(TriggerAction::SpawnUnits {
+                            unit_type: UnitType::BlueIcosphere,
+                            team: editor.spawn_team,
+                            count: editor.spawn_count,
+                            position: center + Vec3::new(editor.size, 0., 0.),
+                        });
+                    }
+                    if !editor.message.trim().is_empty() {
+                        trigger
+                            .actions
+                            .push(TriggerAction::Message(editor.message.trim().to_string()));
+                    }
+                    self.add(trigger);
+                    self.show_bounds = true;
+                }
+            });
+    }
+}
@@ -1,14 +1,15 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
+use building_blocks::core::prelude::PointN;
 use egui::{epaint::Shadow, Color32, Frame, Stroke};
 use glam::{Quat, Vec2, Vec3, Vec4};
-use legion::{IntoQuery, Read, Resources, World, Write};
+use legion::{Entity, EntityStore, IntoQuery, Read, Resources, SystemBuilder, World, Write};
 use rafx::{
     assets::{distill_impl::AssetResource, AssetManager},
-    framework::{
-        render_features::RenderObjectHandle,
-        visibility::{ObjectId, VisibilityRegion},
-    },
+    framework::render_features::RenderObjectHandle,
     renderer::ViewportsResource,
     visibility::CullModel,
 };
@@ -24,22 +25,109 @@ use rafx_plugins::{
     },
 };
 use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    accessibility::AccessibilityResource,
+    assets::fallback::FallbackAssetsResource,
+    audio::{AudioResource, SoundCategory},
+    audit::{AuditComponent, AuditToken, Audited, AuditedHandle},
     camera::RTSCamera,
-    env::simulation::Simulation,
-    input::{InputResource, MouseButton, MouseDragState},
+    console::{ConsoleCommand, ConsoleResource},
+    cursor::{CursorMode, CursorResource},
+    debug_cull::DebugCullResource,
+    env::{
+        env::TileComponent,
+        lighting::LightingResource,
+        save::{AutosaveResource, UnitSave},
+        schedule::{UniverseScheduleResource, UpdateStage},
+        simulation::{Simulation, Universe},
+        stable_id::{mesh_adv_object_id, StableId},
+        visibility_updates::{VisibilityPushComponent, VisibilityUpdateResource},
+    },
+    input::{InputResource, KeyModifiers, KeyboardKey, MouseButton, MouseDragState},
+    labels::{LabelComponent, LabelKind, LabelResource},
+    notifications::{NotificationKind, NotificationResource},
+    picking::{PickHit, PickLayers},
     time::TimeState,
+    tutorial::TutorialResource,
     ui::{SpawnMode, UiState},
+    unit::{
+        building::{self, BuildingAction},
+        combat::{CombatRules, CombatRulesResource},
+        command::{CommandMarkers, UnitCommand},
+        creep::{CreepResource, CREEP_TEAM},
+        economy::{Cost, PlayerEconomy},
+        garrison::{GarrisonComponent, Passenger, BUILDING_CAPACITY},
+        hit_flash::HitFlashResource,
+        lod::{UnitLodComponent, UnitLodResource},
+        orders::PendingOrdersResource,
+        outline::{OutlineResource, OutlineState},
+        prefab::{Prefab, PrefabRegistry},
+        roster::{RosterAction, UnitRoster},
+        stress_test::{StressTestAction, StressTestUiState},
+        task::{TaskKind, TaskScheduler, WorkerComponent, WorkerState},
+        tech::{TechResource, UnitDef, MAX_TEAMS},
+        trigger::{TriggerAction, TriggerResource},
+        wall,
+        wreck::WreckResource,
+    },
+    RenderOptions,
 };
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+// units with an attack command stop this close to the target and start hitting it
+const ATTACK_RANGE: f32 = 5.; // m
+const LABEL_HEIGHT: f32 = 4.; // m, above the unit origin
+
+// a dragged formation is cut off at this many units
+const MAX_FORMATION_UNITS: usize = 400;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum UnitType {
     Container1,
     Container2,
     BlueIcosphere,
 }
 
+impl UnitType {
+    pub const ALL: [UnitType; 3] = [
+        UnitType::Container1,
+        UnitType::Container2,
+        UnitType::BlueIcosphere,
+    ];
+
+    /// Name of the prefab spawned for this type, see `PrefabRegistry`.
+    pub fn prefab_name(&self) -> &'static str {
+        match self {
+            UnitType::Container1 => "container1",
+            UnitType::Container2 => "container2",
+            UnitType::BlueIcosphere => "blue_icosphere",
+        }
+    }
+
+    /// How many units this type carries, 0 for non-transports.
+    pub fn transport_capacity(&self) -> usize {
+        match self {
+            UnitType::Container2 => 4,
+            _ => 0,
+        }
+    }
+
+    /// If units of this type take gather, build and repair tasks, see `WorkerComponent`.
+    pub fn is_worker(&self) -> bool {
+        matches!(self, UnitType::Container1 | UnitType::BlueIcosphere)
+    }
+
+    /// Radius of the wreck left when a unit of this type dies, 0 for none.
+    pub fn wreck_radius(&self) -> i32 {
+        match self {
+            UnitType::Container1 => 0,
+            UnitType::Container2 => 4,
+            UnitType::BlueIcosphere => 3,
+        }
+    }
+}
+
 impl Display for UnitType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -57,16 +145,34 @@ pub struct UnitComponent {
     pub aim: Vec3,
     pub speed: f32,
     pub move_target: Option<Vec3>,
+    // the rest of a navmesh path after `move_target`, last waypoint first
+    pub waypoints: Vec<Vec3>,
     pub selected: bool,
+    pub team: u8,
+    pub command: Option<UnitCommand>,
+    // until the next attack
+    pub cooldown: f32, // s
 }
 
 pub struct UnitUiState {
     pub spawning: bool,
     pub spawn_mode: SpawnMode,
     pub object_type: UnitType,
+    pub team: u8,
     pub selecting: bool,
+    /// The left button went down over the map outside of any placement mode, so its release
+    /// selects.
+    pub select_armed: bool,
     pub selected_count: u32,
     pub selected: HashMap<UnitType, u32>,
+    pub stress_test: StressTestUiState,
+    pub posting_task: Option<TaskKind>,
+    pub task_kind: TaskKind,
+    /// Garrison waiting for a click on the map to unload at.
+    pub unloading: Option<StableId>,
+    /// Spawning fills a rectangle dragged on the map with a grid of units.
+    pub formation: bool,
+    pub formation_spacing: f32, // m
 }
 
 impl Default for UnitUiState {
@@ -75,15 +181,46 @@ impl Default for UnitUiState {
             spawning: false,
             spawn_mode: SpawnMode::OneShot,
             object_type: UnitType::Container1,
+            team: 0,
             selecting: false,
+            select_armed: false,
             selected_count: 0,
             selected: Default::default(),
+            stress_test: Default::default(),
+            posting_task: None,
+            task_kind: TaskKind::Gather,
+            unloading: None,
+            formation: false,
+            formation_spacing: 3.,
+        }
+    }
+}
+
+impl UnitUiState {
+    fn formation_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.formation, tr!("units.formation"));
+        if self.formation {
+            ui.add(
+                egui::Slider::new(&mut self.formation_spacing, 1.5..=10.)
+                    .text(tr!("units.formation.spacing")),
+            );
         }
     }
 }
 
+/// The prefab a unit was spawned from, to rebuild it after leaving a garrison.
+#[derive(Clone)]
+pub struct PrefabComponent {
+    pub name: String,
+}
+
 pub struct UnitsState {
-    meshes: HashMap<UnitType, RenderObjectHandle>,
+    meshes: HashMap<UnitType, Audited<RenderObjectHandle>>,
+    // by asset path, the meshes of `PrefabRegistry::mesh_paths`
+    prefab_meshes: HashMap<String, Audited<RenderObjectHandle>>,
+    tasks: TaskScheduler,
+    markers: CommandMarkers,
+    roster: UnitRoster,
 }
 
 impl UnitsState {
@@ -91,6 +228,7 @@ impl UnitsState {
         let mut asset_manager = resources.get_mut::<AssetManager>().unwrap();
         let mut asset_resource = resources.get_mut::<AssetResource>().unwrap();
         let mut mesh_render_objects = resources.get_mut::<MeshRenderObjectSet>().unwrap();
+        let mut fallbacks = resources.get_mut::<FallbackAssetsResource>().unwrap();
 
         log::info!("Loading units meshes...");
 
@@ -98,40 +236,73 @@ impl UnitsState {
         let container_2_asset = asset_resource.load_asset_path("blender/storage_container2.glb");
         let blue_icosphere_asset =
             asset_resource.load_asset::<MeshAsset>("d5aed900-1e31-4f47-94ba-e356b0b0b8b0".into());
+        let prefab_mesh_assets: Vec<_> = resources
+            .get::<PrefabRegistry>()
+            .unwrap()
+            .mesh_paths()
+            .into_iter()
+            .map(|path| {
+                let handle = asset_resource.load_asset_path::<MeshAsset, _>(path.as_str());
+                (path, handle)
+            })
+            .collect();
 
-        asset_manager
-            .wait_for_asset_to_load(&container_1_asset, &mut asset_resource, "")
-            .unwrap();
-        asset_manager
-            .wait_for_asset_to_load(&container_2_asset, &mut asset_resource, "")
-            .unwrap();
-        asset_manager
-            .wait_for_asset_to_load(&blue_icosphere_asset, &mut asset_resource, "")
-            .unwrap();
+        let mut wait_for_mesh = |handle, name| {
+            fallbacks.wait_for_mesh(&mut asset_manager, &mut asset_resource, handle, name)
+        };
+        let container_1_asset = wait_for_mesh(container_1_asset, "storage_container1");
+        let container_2_asset = wait_for_mesh(container_2_asset, "storage_container2");
+        let blue_icosphere_asset = wait_for_mesh(blue_icosphere_asset, "blue_icosphere");
 
         let mut meshes = HashMap::new();
         meshes.insert(
             UnitType::Container1,
-            mesh_render_objects.register_render_object(MeshRenderObject {
-                mesh: container_1_asset,
-            }),
+            Audited::new(
+                mesh_render_objects.register_render_object(MeshRenderObject {
+                    mesh: container_1_asset,
+                }),
+                AuditedHandle::RenderObject,
+            ),
         );
         meshes.insert(
             UnitType::Container2,
-            mesh_render_objects.register_render_object(MeshRenderObject {
-                mesh: container_2_asset,
-            }),
+            Audited::new(
+                mesh_render_objects.register_render_object(MeshRenderObject {
+                    mesh: container_2_asset,
+                }),
+                AuditedHandle::RenderObject,
+            ),
         );
         meshes.insert(
             UnitType::BlueIcosphere,
-            mesh_render_objects.register_render_object(MeshRenderObject {
-                mesh: blue_icosphere_asset,
-            }),
+            Audited::new(
+                mesh_render_objects.register_render_object(MeshRenderObject {
+                    mesh: blue_icosphere_asset,
+                }),
+                AuditedHandle::RenderObject,
+            ),
         );
 
+        let mut prefab_meshes = HashMap::new();
+        for (path, handle) in prefab_mesh_assets {
+            let handle = wait_for_mesh(handle, &path);
+            let render_object =
+                mesh_render_objects.register_render_object(MeshRenderObject { mesh: handle });
+            prefab_meshes.insert(
+                path,
+                Audited::new(render_object, AuditedHandle::RenderObject),
+            );
+        }
+
         log::info!("Units meshes loaded");
 
-        UnitsState { meshes }
+        UnitsState {
+            meshes,
+            prefab_meshes,
+            tasks: Default::default(),
+            markers: Default::default(),
+            roster: Default::default(),
+        }
     }
 
     pub fn update_ui(
@@ -144,29 +315,101 @@ impl UnitsState {
         let universe = simulation.universe();
 
         self.add_debug_draw(resources, &universe.world);
+        {
+            // overlays keep animating while paused
+            let dt = resources.get::<TimeState>().unwrap().unscaled_dt();
+            self.markers.update(dt);
+            let mut orders = resources.get_mut::<PendingOrdersResource>().unwrap();
+            orders.update(dt);
+            orders.update_meshes(universe, resources);
+            resources.get_mut::<NotificationResource>().unwrap().update(
+                dt,
+                ui_state.unit.team,
+                &mut resources.get_mut::<AudioResource>().unwrap(),
+            );
+        }
+        let jump = resources.get_mut::<NotificationResource>().unwrap().ui(ui);
+        if let Some(position) = jump {
+            resources.get_mut::<RTSCamera>().unwrap().look_at = position;
+        }
+
+        let mut roster_action = self.roster.ui(ui);
+        if resources
+            .get::<InputResource>()
+            .unwrap()
+            .is_key_just_down(KeyboardKey::Period)
+        {
+            roster_action = self
+                .roster
+                .next_idle_worker()
+                .map(|entry| RosterAction::Select(entry.entity, entry.position));
+        }
+        match roster_action {
+            Some(RosterAction::Center(position)) => {
+                resources.get_mut::<RTSCamera>().unwrap().look_at = position;
+            }
+            Some(RosterAction::Select(entity, position)) => {
+                resources.get_mut::<RTSCamera>().unwrap().look_at = position;
+                let mut query = <(Entity, Write<UnitComponent>)>::query();
+                for (unit_entity, unit) in query.iter_mut(&mut universe.world) {
+                    unit.selected = *unit_entity == entity;
+                }
+                Self::count_selection(&universe.world, ui_state);
+            }
+            None => {}
+        }
 
         let input = resources.get::<InputResource>().unwrap();
         let camera = resources.get::<RTSCamera>().unwrap();
 
         ui_state.unit.selecting = false;
-        if let Some(MouseDragState { .. }) = input.mouse_drag_just_finished(MouseButton::LEFT) {
-            ui_state.unit.selecting = !ui_state.unit.spawning
-                && !ui_state.env.tile_spawn.active
-                && !ui_state.env.terrain_edit.active;
+        let can_select = !ui_state.unit.spawning
+            && ui_state.unit.posting_task.is_none()
+            && ui_state.unit.unloading.is_none()
+            && !ui_state.env.tile_spawn.active
+            && !ui_state.env.terrain_edit.active
+            && !resources.get::<LightingResource>().unwrap().is_placing();
+        if input.is_mouse_just_down(MouseButton::LEFT) {
+            ui_state.unit.select_armed = can_select;
+        }
+        if input.is_mouse_drag_just_finished(MouseButton::LEFT)
+            || input.is_mouse_button_just_clicked(MouseButton::LEFT)
+        {
+            ui_state.unit.selecting = ui_state.unit.select_armed && can_select;
+            ui_state.unit.select_armed = false;
         }
 
+        let spawn_top = ui.cursor().top();
         if ui_state.unit.spawning {
-            egui::CollapsingHeader::new("Spawn unit")
+            egui::CollapsingHeader::new(tr!("units.spawn"))
+                .id_source("spawn unit")
                 .default_open(true)
                 .show(ui, |ui| {
                     ui_state.unit.spawn_mode.ui(ui, &mut ui_state.unit.spawning);
-                    ui.label("Click a location on the map to spawn unit");
+                    ui_state.unit.formation_ui(ui);
+                    if ui_state.unit.formation {
+                        ui.label(tr!("units.spawn.drag"));
+                    } else {
+                        ui.label(tr!("units.spawn.click"));
+                    }
                 });
         } else if !ui_state.env.tile_spawn.active {
-            egui::CollapsingHeader::new("Spawn unit")
+            egui::CollapsingHeader::new(tr!("units.spawn"))
+                .id_source("spawn unit")
                 .default_open(true)
                 .show(ui, |ui| {
                     ui_state.unit.spawn_mode.ui(ui, &mut ui_state.unit.spawning);
+                    ui_state.unit.formation_ui(ui);
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut ui_state.unit.team, 0..=3)
+                                .text(tr!("units.team")),
+                        );
+                        resources
+                            .get::<OutlineResource>()
+                            .unwrap()
+                            .team_swatch(ui, ui_state.unit.team);
+                    });
                     ui.horizontal_wrapped(|ui| {
                         for (obj, _) in &self.meshes {
                             if ui.selectable_label(false, format!("{}", obj)).clicked() {
@@ -177,19 +420,182 @@ impl UnitsState {
                     });
                 });
         }
+        resources
+            .get_mut::<TutorialResource>()
+            .unwrap()
+            .mark_section("unit_spawn", ui, spawn_top);
+
+        egui::CollapsingHeader::new(tr!("tasks"))
+            .id_source("worker tasks")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.label(tr!(
+                    "tasks.count",
+                    open = self.tasks.open_count(),
+                    claimed = self.tasks.claimed_count()
+                ));
+                if let Some(kind) = ui_state.unit.posting_task {
+                    ui.label(tr!("tasks.click", kind = kind));
+                    if ui.button(tr!("tasks.cancel")).clicked() {
+                        ui_state.unit.posting_task = None;
+                    }
+                } else {
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_source("task kind")
+                            .selected_text(format!("{}", ui_state.unit.task_kind))
+                            .show_ui(ui, |ui| {
+                                for kind in TaskKind::ALL {
+                                    ui.selectable_value(
+                                        &mut ui_state.unit.task_kind,
+                                        kind,
+                                        format!("{}", kind),
+                                    );
+                                }
+                            });
+                        if ui.button(tr!("tasks.post")).clicked() {
+                            ui_state.unit.posting_task = Some(ui_state.unit.task_kind);
+                        }
+                    });
+                    if ui.button(tr!("tasks.clear")).clicked() {
+                        self.tasks.clear();
+                    }
+                }
+            });
+
+        {
+            let buildings = Self::building_names(resources, &universe.world);
+            let mut tech = resources.get_mut::<TechResource>().unwrap();
+            tech.ui(ui, ui_state.unit.team, &buildings);
+        }
+
+        let hovered_trigger = if resources.get::<TriggerResource>().unwrap().show_bounds {
+            let cursor_pos = input.mouse_position();
+            camera
+                .pick_ray(cursor_pos.x as u32, cursor_pos.y as u32)
+                .pick(PickLayers::TRIGGERS, universe, resources)
+                .and_then(|hit| hit.trigger())
+        } else {
+            None
+        };
+        {
+            let mut triggers = resources.get_mut::<TriggerResource>().unwrap();
+            triggers.hovered = hovered_trigger;
+            triggers.ui(ui, camera.look_at);
+        }
+        resources.get_mut::<PendingOrdersResource>().unwrap().ui(ui);
+
+        let unit_types: Vec<_> = self.meshes.keys().copied().collect();
+        ui_state.unit.stress_test.ui(ui, &unit_types);
+        if input.is_key_just_up(KeyboardKey::F5) {
+            ui_state.unit.stress_test.start();
+        }
+        {
+            let mut console = resources.get_mut::<ConsoleResource>().unwrap();
+            let stress_test = console.take(|command| match command {
+                ConsoleCommand::StressTest(count, spacing) => Some((*count, *spacing)),
+                _ => None,
+            });
+            if let Some((count, spacing)) = stress_test {
+                let params = &mut ui_state.unit.stress_test.params;
+                params.count = count.unwrap_or(params.count).max(1);
+                params.spacing = spacing.unwrap_or(params.spacing).max(0.5);
+                console.print(tr!("console.stress", count = params.count));
+                ui_state.unit.stress_test.start();
+            }
+        }
 
+        let mut unload = None;
         if ui_state.unit.selected_count > 0 {
-            egui::CollapsingHeader::new("Object selection")
+            egui::CollapsingHeader::new(tr!("units.selection"))
+                .id_source("object selection")
                 .default_open(true)
                 .show(ui, |ui| {
-                    ui.label(format!("{} units selected", ui_state.unit.selected_count));
+                    ui.label(tr!(
+                        "units.selection.count",
+                        count = ui_state.unit.selected_count
+                    ));
+                    let tech = resources.get::<TechResource>().unwrap();
+                    let rules = resources.get::<CombatRulesResource>().unwrap();
                     for (ty, count) in &ui_state.unit.selected {
-                        ui.label(format!("- {:?}: {}", ty, count));
+                        let def = tech.unit_def(ui_state.unit.team, *ty);
+                        ui.label(format!("- {:?}: {}", ty, count))
+                            .on_hover_text(rules.describe(&def));
+                    }
+                    let mut query =
+                        <(Read<StableId>, Read<UnitComponent>, Read<GarrisonComponent>)>::query();
+                    for (id, unit, garrison) in query.iter(&universe.world) {
+                        if unit.selected && garrison.ui(ui, &format!("{}", unit.object_type)) {
+                            unload = Some(*id);
+                        }
+                    }
+                });
+        }
+        {
+            let team = ui_state.unit.team;
+            let mut query = <(
+                Read<StableId>,
+                Read<TransformComponent>,
+                Read<TileComponent>,
+                Read<GarrisonComponent>,
+            )>::query();
+            let buildings: Vec<_> = query
+                .iter(&universe.world)
+                .filter(|(_, _, _, garrison)| {
+                    garrison.passengers.iter().any(|p| p.unit.team == team)
+                })
+                .collect();
+            if !buildings.is_empty() {
+                egui::CollapsingHeader::new(tr!("units.garrisoned"))
+                    .id_source("garrisoned buildings")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for (id, transform, _, garrison) in buildings {
+                            let p = transform.translation;
+                            let label = tr!(
+                                "units.garrisoned.building",
+                                x = format!("{:.0}", p.x),
+                                y = format!("{:.0}", p.y)
+                            );
+                            if garrison.ui(ui, &label) {
+                                unload = Some(*id);
+                            }
+                        }
+                    });
+            }
+        }
+        if unload.is_some() {
+            ui_state.unit.unloading = unload;
+        }
+        let building_action = {
+            let mut query = <(Read<UnitComponent>, Read<WorkerComponent>)>::query();
+            let workers_selected = query.iter(&universe.world).any(|(unit, _)| unit.selected);
+            let asset_manager = resources.get::<AssetManager>().unwrap();
+            building::building_panel(ui, &universe.world, &asset_manager, workers_selected)
+        };
+        match building_action {
+            Some(BuildingAction::Repair(target)) => self.repair(target, universe),
+            Some(BuildingAction::Demolish(target)) => self.demolish(target, resources, universe),
+            None => {}
+        }
+        if let Some(garrison) = ui_state.unit.unloading {
+            egui::CollapsingHeader::new(tr!("units.unload"))
+                .id_source("unload")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.label(tr!("units.unload.click"));
+                    if ui.button(tr!("tasks.cancel")).clicked() {
+                        ui_state.unit.unloading = None;
                     }
                 });
+            if universe.ids.entity(garrison).is_none() {
+                ui_state.unit.unloading = None;
+            }
         }
 
-        if !ui_state.unit.spawning
+        // the selection rectangle, or the one a formation is spawned in
+        if (!ui_state.unit.spawning || ui_state.unit.formation)
+            && ui_state.unit.posting_task.is_none()
+            && ui_state.unit.unloading.is_none()
             && !ui_state.env.tile_spawn.active
             && !ui_state.env.terrain_edit.active
         {
@@ -237,235 +643,1937 @@ impl UnitsState {
             }
         }
 
-        if ui_state.unit.spawning {
+        self.update_cursor(resources, universe, ui_state);
+
+        if ui_state.unit.spawning && ui_state.unit.formation {
+            if let Some(MouseDragState {
+                begin_position: p0,
+                end_position: p1,
+                ..
+            }) = input.mouse_drag_just_finished(MouseButton::LEFT)
+            {
+                self.spawn_formation(p0, p1, &camera, resources, universe, ui_state);
+                if ui_state.unit.spawn_mode == SpawnMode::OneShot {
+                    ui_state.unit.spawning = false;
+                }
+            }
+        } else if ui_state.unit.spawning {
             if input.is_mouse_just_down(MouseButton::LEFT) {
-                let cursor_pos = input.mouse_position();
-                let cast_result = camera.ray_cast_terrain(
-                    cursor_pos.x as u32,
-                    cursor_pos.y as u32,
-                    universe,
-                    ui_state,
-                );
-                if let Some(result) = cast_result {
-                    let p = result.hit;
-                    self.spawn(
-                        ui_state.unit.object_type,
-                        Vec3::new(p.x() as f32, p.y() as f32, p.z() as f32 + 1.),
-                        resources,
-                        &mut universe.world,
-                        &universe.visibility_region,
+                let hit = Self::pick_cursor(PickLayers::TERRAIN, resources, universe, ui_state);
+                if let Some(hit) = hit {
+                    let (unit_type, team) = (ui_state.unit.object_type, ui_state.unit.team);
+                    let paid = resources.get_mut::<PlayerEconomy>().unwrap().try_spend(
+                        team,
+                        Cost::unit(unit_type),
+                        &unit_type.to_string(),
                     );
+                    if paid {
+                        let spawned = self.spawn_prefab(
+                            unit_type.prefab_name(),
+                            team,
+                            hit.ground(),
+                            resources,
+                            universe,
+                        );
+                        if let Err(err) = spawned {
+                            ui_state.error(err);
+                        }
+                    }
                 }
                 if ui_state.unit.spawn_mode == SpawnMode::OneShot {
                     ui_state.unit.spawning = false;
                 }
             }
+        } else if let Some(kind) = ui_state.unit.posting_task {
+            if input.is_mouse_just_down(MouseButton::LEFT) {
+                let hit = Self::pick_cursor(PickLayers::TERRAIN, resources, universe, ui_state);
+                if let Some(hit) = hit {
+                    self.tasks.post(kind, hit.ground() + Vec3::Z, None);
+                }
+                ui_state.unit.posting_task = None;
+            }
+        } else if let Some(garrison) = ui_state.unit.unloading {
+            if input.is_mouse_just_down(MouseButton::LEFT) {
+                let hit = Self::pick_cursor(PickLayers::TERRAIN, resources, universe, ui_state);
+                if let Some(hit) = hit {
+                    self.unload(garrison, hit.ground() + Vec3::Z, resources, universe);
+                }
+                ui_state.unit.unloading = None;
+            }
         } else if input.is_mouse_just_down(MouseButton::RIGHT) {
-            let mut first = true;
-            let cursor_pos = input.mouse_position();
-            let cast_result = camera.ray_cast_terrain(
-                cursor_pos.x as u32,
-                cursor_pos.y as u32,
-                universe,
-                ui_state,
-            );
-            if let Some(result) = cast_result {
-                let p = result.hit;
-                let mut target = Vec3::new(p.x() as f32, p.y() as f32, p.z() as f32 + 2.);
-                let mut query = <(Read<TransformComponent>, Write<UnitComponent>)>::query();
-                for (transform, dyn_object) in query.iter_mut(&mut universe.world) {
-                    if dyn_object.selected {
-                        if !first {
-                            target.x += transform.scale.x;
-                        }
-                        dyn_object.move_target = Some(target);
-                        target.x += transform.scale.x;
-                        first = false;
-                    }
+            let layers = PickLayers::UNITS | PickLayers::TERRAIN;
+            if let Some(hit) = Self::pick_cursor(layers, resources, universe, ui_state) {
+                let target = hit.ground() + Vec3::Z;
+                // Shift+RightClick moves there whatever is under the cursor
+                let command = if input.modifiers() == KeyModifiers::SHIFT {
+                    Some(UnitCommand::Move(target))
+                } else {
+                    self.resolve_command(target, hit.building(), universe)
+                };
+                if let Some(command) = command {
+                    let mut query = <(Read<StableId>, Read<UnitComponent>)>::query();
+                    let units = query
+                        .iter(&universe.world)
+                        .filter(|(_, unit)| unit.selected)
+                        .map(|(id, _)| *id)
+                        .collect();
+                    resources
+                        .get_mut::<PendingOrdersResource>()
+                        .unwrap()
+                        .issue(command, target, units);
+                    self.markers.add(&command, hit.ground());
                 }
             }
         }
     }
 
-    #[profiling::function]
-    pub fn update(
-        &mut self,
-        simulation: &mut Simulation,
-        resources: &mut Resources,
+    /// What the cursor is over on `layers`, misses are reported as errors.
+    fn pick_cursor(
+        layers: PickLayers,
+        resources: &Resources,
+        universe: &Universe,
         ui_state: &mut UiState,
-    ) {
-        let camera = resources.get::<RTSCamera>().unwrap();
-        let view_proj = camera.view_proj();
-        let dt = resources.get::<TimeState>().unwrap().previous_update_dt();
+    ) -> Option<PickHit> {
         let input = resources.get::<InputResource>().unwrap();
-        let universe = simulation.universe();
+        let camera = resources.get::<RTSCamera>().unwrap();
+        let cursor_pos = input.mouse_position();
+        let ray = camera.pick_ray(cursor_pos.x as u32, cursor_pos.y as u32);
+        let hit = ray.pick(layers, universe, resources);
+        if hit.is_none() {
+            ui_state.error(tr!(
+                "unit.pick_failed",
+                start = ray.origin,
+                ray = ray.direction
+            ));
+        }
+        hit
+    }
 
-        let (x0, y0, x1, y1) = if let Some(MouseDragState {
-            begin_position: p0,
-            end_position: p1,
-            ..
-        }) = input.mouse_drag_just_finished(MouseButton::LEFT)
-        {
-            let window_size = resources
-                .get::<ViewportsResource>()
-                .unwrap()
-                .main_window_size;
-            (
-                (p0.x.min(p1.x) / window_size.width as f32) * 2. - 1.,
-                (p0.y.max(p1.y) / window_size.height as f32) * -2. + 1.,
-                (p0.x.max(p1.x) / window_size.width as f32) * 2. - 1.,
-                (p0.y.min(p1.y) / window_size.height as f32) * -2. + 1.,
-            )
+    /// Tells the cursor manager what a click would do at the hovered location.
+    fn update_cursor(&self, resources: &Resources, universe: &Universe, ui_state: &UiState) {
+        let mode = if ui_state.env.terrain_edit.active {
+            CursorMode::TerrainEdit
         } else {
-            (0., 0., 0., 0.)
+            let placing = ui_state.unit.spawning
+                || ui_state.unit.posting_task.is_some()
+                || ui_state.unit.unloading.is_some()
+                || ui_state.env.tile_spawn.active;
+            if !placing && ui_state.unit.selected_count == 0 {
+                return;
+            }
+            let layers = if placing {
+                PickLayers::TERRAIN
+            } else {
+                PickLayers::UNITS | PickLayers::TERRAIN
+            };
+            // missing everything is not an error here, unlike in `pick_cursor`
+            let hit = {
+                let input = resources.get::<InputResource>().unwrap();
+                let camera = resources.get::<RTSCamera>().unwrap();
+                let cursor_pos = input.mouse_position();
+                camera
+                    .pick_ray(cursor_pos.x as u32, cursor_pos.y as u32)
+                    .pick(layers, universe, resources)
+            };
+            match hit {
+                Some(_) if placing => CursorMode::Spawn,
+                None if placing => CursorMode::Invalid,
+                Some(hit) => {
+                    match self.resolve_command(hit.ground() + Vec3::Z, hit.building(), universe) {
+                        Some(UnitCommand::Attack(_)) => CursorMode::Attack,
+                        Some(UnitCommand::Harvest(_)) | Some(UnitCommand::Enter(_)) => {
+                            CursorMode::Interact
+                        }
+                        _ => CursorMode::Default,
+                    }
+                }
+                None => CursorMode::Default,
+            }
         };
+        resources.get_mut::<CursorResource>().unwrap().mode = mode;
+    }
+
+    /// Names of the tiles standing on the map, research needs some of them.
+    fn building_names(resources: &Resources, world: &World) -> HashSet<String> {
+        let asset_manager = resources.get::<AssetManager>().unwrap();
+        let mut query = <Read<TileComponent>>::query();
+        query
+            .iter(world)
+            .filter(|tile| tile.built)
+            .filter_map(|tile| asset_manager.committed_asset(&tile.asset))
+            .map(|tile| tile.inner.name.clone())
+            .collect()
+    }
+
+    /// Picks the order for the selected units from what is under the cursor: an enemy unit,
+    /// a friendly transport, an open gather task, the `building` the pick ray hit or just the
+    /// ground.
+    fn resolve_command(
+        &self,
+        target: Vec3,
+        building: Option<Entity>,
+        universe: &Universe,
+    ) -> Option<UnitCommand> {
+        const PICK_RADIUS: f32 = 1.5;
+        const HARVEST_PICK_RADIUS: f32 = 3.;
+
+        let xy_dist = |p: Vec3| (p - target).truncate().length();
+
+        let mut query = <Read<UnitComponent>>::query();
+        let team = query
+            .iter(&universe.world)
+            .find(|unit| unit.selected)
+            .map(|unit| unit.team)?;
 
         let mut query = <(
-            Write<TransformComponent>,
-            Read<VisibilityComponent>,
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<UnitComponent>,
+        )>::query();
+        let enemy = query
+            .iter(&universe.world)
+            .filter(|(_, transform, unit)| {
+                !unit.selected
+                    && unit.team != team
+                    && xy_dist(transform.translation) < PICK_RADIUS * transform.scale.x.max(1.)
+            })
+            .min_by(|(_, t0, _), (_, t1, _)| {
+                xy_dist(t0.translation)
+                    .partial_cmp(&xy_dist(t1.translation))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        if let Some((id, _, _)) = enemy {
+            return Some(UnitCommand::Attack(*id));
+        }
+
+        let transport = query.iter(&universe.world).find(|(_, transform, unit)| {
+            !unit.selected
+                && unit.team == team
+                && unit.object_type.transport_capacity() > 0
+                && xy_dist(transform.translation) < PICK_RADIUS * transform.scale.x.max(1.)
+        });
+        if let Some((id, _, _)) = transport {
+            return Some(UnitCommand::Enter(*id));
+        }
+
+        let gather_task = self.tasks.tasks().find(|(_, task)| {
+            task.kind == TaskKind::Gather
+                && task.worker.is_none()
+                && xy_dist(task.position) < HARVEST_PICK_RADIUS
+        });
+        if let Some((id, _)) = gather_task {
+            return Some(UnitCommand::Harvest(*id));
+        }
+
+        let building = building.and_then(|entity| universe.world.entry_ref(entity).ok());
+        let enemy_building = building.as_ref().and_then(|entry| {
+            let tile = entry.get_component::<TileComponent>().ok()?;
+            match tile.team {
+                Some(owner) if owner != team => entry.get_component::<StableId>().ok().copied(),
+                _ => None,
+            }
+        });
+        if let Some(id) = enemy_building {
+            return Some(UnitCommand::Attack(id));
+        }
+
+        // only buildings that take units in, a garrison or the walkway of a wall
+        let building = building
+            .filter(|entry| {
+                entry.get_component::<GarrisonComponent>().is_ok()
+                    || entry
+                        .get_component::<TileComponent>()
+                        .map_or(false, |tile| tile.properties.wall)
+            })
+            .and_then(|entry| entry.get_component::<StableId>().ok().copied());
+        if let Some(id) = building {
+            return Some(UnitCommand::Enter(id));
+        }
+
+        Some(UnitCommand::Move(target))
+    }
+
+    fn issue_command(
+        &mut self,
+        command: UnitCommand,
+        target: Vec3,
+        units: &[StableId],
+        universe: &mut Universe,
+    ) {
+        log::info!("Issue command {} at {}", command, target);
+        let units: HashSet<_> = units.iter().copied().collect();
+        let mut first = true;
+        let mut target = target;
+        let mut paths = vec![];
+        let mut query = <(
+            Entity,
+            Read<StableId>,
+            Read<TransformComponent>,
             Write<UnitComponent>,
+            Write<WorkerComponent>,
         )>::query();
-        query.par_for_each_mut(&mut universe.world, |(transform, visibility, unit)| {
-            if let Some(target) = unit.move_target {
-                let target_dir = (target - transform.translation).normalize();
-                let orig_dir = Vec3::X;
-                if (target_dir - orig_dir).length() > 0.001 {
-                    transform.rotation = Quat::from_rotation_arc(orig_dir, target_dir);
-                }
-                if (target_dir - unit.aim).length() > 0.001 {
-                    unit.aim = (unit.aim + (target_dir - unit.aim) * dt).normalize();
-                }
-                const TARGET_SPEED: f32 = 10.; // m/s
-                if unit.speed < TARGET_SPEED {
-                    unit.speed = (unit.speed + 2. * dt).min(TARGET_SPEED);
-                }
-                transform.translation += unit.speed * dt * target_dir;
-                visibility.visibility_object_handle.set_transform(
-                    transform.translation,
-                    transform.rotation,
-                    transform.scale,
-                );
-                if (target - transform.translation).length() < 0.1 {
-                    unit.move_target = None;
-                    unit.speed = 0.;
-                }
+        for (entity, id, transform, unit, worker) in query.iter_mut(&mut universe.world) {
+            if !units.contains(id) {
+                continue;
             }
-            if ui_state.unit.selecting {
-                let pos_hom: Vec4 = (transform.translation, 1.).into();
-                let pos_view = view_proj * pos_hom;
-                let pos_screen = Vec2::new(pos_view.x / pos_view.w, pos_view.y / pos_view.w);
-                unit.selected = pos_screen.x > x0
-                    && pos_screen.x < x1
-                    && pos_screen.y > y0
-                    && pos_screen.y < y1;
+            if let WorkerState::Moving(task) | WorkerState::Working(task) = worker.state {
+                self.tasks.release(task);
+                worker.state = WorkerState::Idle;
             }
-        });
+            if !first {
+                target.x += transform.scale.x;
+            }
+            unit.command = None;
+            unit.move_target = Some(target);
+            unit.waypoints.clear();
+            match command {
+                UnitCommand::Move(_) => paths.push((*entity, transform.translation, target)),
+                UnitCommand::Harvest(task) => {
+                    if let Some(position) = self.tasks.claim(task, *id) {
+                        worker.state = WorkerState::Moving(task);
+                        unit.move_target = Some(position);
+                    }
+                }
+                UnitCommand::Attack(_) | UnitCommand::Enter(_) => {
+                    unit.command = Some(command);
+                }
+            }
+            target.x += transform.scale.x;
+            first = false;
+        }
 
-        if ui_state.unit.selecting {
-            ui_state.unit.selected_count = 0;
-            ui_state.unit.selected.clear();
-            let mut query = <Read<UnitComponent>>::query();
-            for dyn_object in query.iter(&universe.world) {
-                if dyn_object.selected {
-                    ui_state.unit.selected_count += 1;
-                    let entry = ui_state.unit.selected.entry(dyn_object.object_type);
-                    entry.and_modify(|e| *e += 1).or_insert(1);
+        for (entity, from, target) in paths {
+            let path = match universe.find_path(from, target) {
+                Some(path) => path,
+                None => continue,
+            };
+            if let Some(mut entry) = universe.world.entry(entity) {
+                if let Ok(unit) = entry.get_component_mut::<UnitComponent>() {
+                    unit.waypoints = path.into_iter().rev().collect();
+                    unit.move_target = unit.waypoints.pop();
                 }
             }
         }
     }
 
-    pub fn spawn(
-        &self,
-        unit_type: UnitType,
-        position: Vec3,
-        resources: &Resources,
-        world: &mut World,
-        visibility_region: &VisibilityRegion,
-    ) {
-        // transform component
-        const SCALE_MIN: f32 = 0.5;
-        const SCALE_MAX: f32 = 2.;
-        let position = Vec3::new(position.x, position.y, position.z + 1.);
-        let mut rng = thread_rng();
-        let rand_scale_xy = rng.gen_range(SCALE_MIN..SCALE_MAX);
-        let transform_component = TransformComponent {
-            translation: position,
-            scale: Vec3::new(rand_scale_xy, rand_scale_xy, 1.),
-            rotation: Quat::from_rotation_z(rng.gen_range(0.0..2.0 * std::f32::consts::PI)),
-        };
+    /// Steers units with target-bound commands towards their (possibly moving) targets.
+    /// Returns true when some units entered a garrison and left the map.
+    fn update_commands(&mut self, universe: &mut Universe) -> bool {
+        const ENTER_RANGE: f32 = 3.;
 
-        // mesh component
-        let mesh_render_object = self.meshes.get(&unit_type).unwrap().clone();
-        let mesh_component = MeshComponent {
-            render_object_handle: mesh_render_object.clone(),
-        };
+        let posted = wall::posted_units(&universe.world);
+        Self::acquire_targets(&posted, universe);
 
-        // unit component
-        let unit_component = UnitComponent {
-            object_type: unit_type,
-            health: 1.,
-            aim: Vec3::new(1., 0., 0.),
-            speed: 0.,
-            move_target: None,
-            selected: false,
-        };
+        let mut query = <Read<UnitComponent>>::query();
+        let targets: Vec<_> = query
+            .iter(&universe.world)
+            .filter_map(|unit| match unit.command {
+                Some(UnitCommand::Attack(id)) | Some(UnitCommand::Enter(id)) => Some(id),
+                _ => None,
+            })
+            .collect();
+        let positions: HashMap<_, _> = targets
+            .into_iter()
+            .filter_map(|id| {
+                let entity = universe.ids.entity(id)?;
+                let entry = universe.world.entry_ref(entity).ok()?;
+                let transform = entry.get_component::<TransformComponent>().ok()?;
+                let radius = entry
+                    .get_component::<TileComponent>()
+                    .map_or(0., building_radius);
+                Some((id, (transform.translation, radius)))
+            })
+            .collect();
 
-        // entity
-        log::info!("Spawn entity {:?} at: {}", unit_type, position);
-        let entity = world.push((transform_component, mesh_component, unit_component));
+        let mut arrived = vec![];
+        let mut query = <(Entity, Read<TransformComponent>, Write<UnitComponent>)>::query();
+        for (entity, transform, unit) in query.iter_mut(&mut universe.world) {
+            let elevation = posted.get(entity).copied();
+            let (target, range) = match unit.command {
+                Some(UnitCommand::Attack(id)) => (
+                    id,
+                    wall::attack_range(ATTACK_RANGE, elevation.unwrap_or(0.)),
+                ),
+                Some(UnitCommand::Enter(id)) => (id, ENTER_RANGE),
+                _ => continue,
+            };
+            let (position, radius) = if let Some(target) = positions.get(&target) {
+                *target
+            } else {
+                unit.command = None;
+                continue;
+            };
+            unit.waypoints.clear();
+            let distance = (position - transform.translation).truncate().length() - radius;
+            if let (Some(elevation), Some(UnitCommand::Attack(_))) = (elevation, unit.command) {
+                // units on walls hold their post, and give up on targets they lost sight of
+                if distance > range {
+                    unit.move_target = None;
+                    unit.speed = 0.;
+                    if distance > wall::vision_range(elevation) {
+                        unit.command = None;
+                    }
+                    continue;
+                }
+            }
+            if distance > range {
+                let target = Vec3::new(position.x, position.y, transform.translation.z);
+                unit.move_target = Some(target);
+            } else {
+                unit.move_target = None;
+                unit.speed = 0.;
+                if let Some(UnitCommand::Enter(target)) = unit.command {
+                    // the order ends at the door, inside or not
+                    unit.command = None;
+                    arrived.push((*entity, target));
+                }
+            }
+        }
 
-        // visibility component
-        let asset_manager = resources.get::<AssetManager>().unwrap();
-        let mesh_render_objects = resources.get::<MeshRenderObjectSet>().unwrap();
-        let mesh_render_objects = mesh_render_objects.read();
-        let asset_handle = &mesh_render_objects.get(&mesh_render_object).mesh;
-        let mut entry = world.entry(entity).unwrap();
-        entry.add_component(VisibilityComponent {
-            visibility_object_handle: {
-                let handle = visibility_region.register_dynamic_object(
-                    ObjectId::from(entity),
-                    CullModel::VisibleBounds(
-                        asset_manager
-                            .committed_asset(&asset_handle)
-                            .unwrap()
-                            .inner
-                            .asset_data
-                            .visible_bounds,
-                    ),
-                );
-                handle.set_transform(
-                    transform_component.translation,
-                    transform_component.rotation,
-                    transform_component.scale,
-                );
-                handle.add_render_object(&mesh_render_object);
-                handle
-            },
-        });
+        let mut entered = false;
+        for (entity, target) in arrived {
+            if Self::is_wall(target, universe) {
+                if Self::climb_wall(entity, target, universe).is_none() {
+                    log::debug!("Unit {:?} found no room on wall {:?}", entity, target);
+                }
+            } else if Self::enter_garrison(entity, target, universe).is_some() {
+                entered = true;
+            } else {
+                log::debug!("Unit {:?} can't enter {:?}", entity, target);
+            }
+        }
+        entered
     }
 
-    pub fn add_debug_draw(&self, resources: &Resources, world: &World) {
-        let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+    /// Units in range of their attack target hit it when their cooldown is over, the damage
+    /// of every hit resolved by `rules` against the target and, for splash weapons, the units
+    /// around it. Returns true when some units died.
+    fn update_combat(
+        &mut self,
+        universe: &mut Universe,
+        tech: &TechResource,
+        rules: &CombatRules,
+        wrecks: &mut WreckResource,
+        notifications: &mut NotificationResource,
+        hit_flashes: &mut HitFlashResource,
+        audio: &mut AudioResource,
+        destroyed: &mut Vec<StableId>,
+    ) -> bool {
+        struct Target {
+            entity: Entity,
+            id: StableId,
+            position: Vec3,
+            // half the footprint of buildings, attackers stop at their side
+            radius: f32,
+            team: u8,
+            // `None` for buildings
+            unit_type: Option<UnitType>,
+            def: UnitDef,
+        }
+        struct Hit {
+            team: u8,
+            def: UnitDef,
+            target: usize,
+        }
 
-        let normal_col = Vec4::new(1., 0., 0., 1.);
-        let selected_col = Vec4::new(0., 1., 0., 1.);
+        let mut query = <(
+            Entity,
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<UnitComponent>,
+        )>::query();
+        let mut ids = HashMap::new();
+        let mut targets = vec![];
+        for (entity, id, transform, unit) in query.iter(&universe.world) {
+            ids.insert(*id, targets.len());
+            targets.push(Target {
+                entity: *entity,
+                id: *id,
+                position: transform.translation,
+                radius: 0.,
+                team: unit.team,
+                unit_type: Some(unit.object_type),
+                def: tech.unit_def(unit.team, unit.object_type),
+            });
+        }
+        let mut query = <(
+            Entity,
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<TileComponent>,
+        )>::query();
+        for (entity, id, transform, tile) in query.iter(&universe.world) {
+            let team = match tile.team {
+                Some(team) => team,
+                None => continue,
+            };
+            ids.insert(*id, targets.len());
+            targets.push(Target {
+                entity: *entity,
+                id: *id,
+                position: transform.translation,
+                radius: building_radius(tile),
+                team,
+                unit_type: None,
+                def: UnitDef::building(tile.properties.max_health),
+            });
+        }
 
-        let mut query = <(Read<TransformComponent>, Read<UnitComponent>)>::query();
-        for (transform, dyn_object) in query.iter(world) {
-            let color = if dyn_object.selected {
-                selected_col
-            } else {
-                normal_col
+        let posted = wall::posted_units(&universe.world);
+        let mut hits = vec![];
+        let mut query = <(Entity, Read<TransformComponent>, Write<UnitComponent>)>::query();
+        for (entity, transform, unit) in query.iter_mut(&mut universe.world) {
+            let target = match unit.command {
+                Some(UnitCommand::Attack(id)) => ids.get(&id).copied(),
+                _ => None,
             };
+            let target = match target {
+                Some(target) if unit.cooldown <= 0. => target,
+                _ => continue,
+            };
+            let distance = (targets[target].position - transform.translation)
+                .truncate()
+                .length()
+                - targets[target].radius;
+            let elevation = posted.get(entity).copied().unwrap_or(0.);
+            if distance > wall::attack_range(ATTACK_RANGE, elevation) {
+                continue;
+            }
+            let def = tech.unit_def(unit.team, unit.object_type);
+            unit.cooldown = def.attack_interval;
+            hits.push(Hit {
+                team: unit.team,
+                def,
+                target,
+            });
+        }
+        if hits.is_empty() {
+            return false;
+        }
+
+        let mut damage = vec![0.; targets.len()];
+        for hit in &hits {
+            let impact = targets[hit.target].position;
+            for (idx, target) in targets.iter().enumerate() {
+                let distance = if idx == hit.target {
+                    0.
+                } else if hit.def.splash_radius > 0. {
+                    target.position.distance(impact)
+                } else {
+                    continue;
+                };
+                if let Some(hp) =
+                    rules.resolve(&hit.def, hit.team, &target.def, target.team, distance)
+                {
+                    damage[idx] += hp;
+                }
+            }
+        }
+
+        let mut dead = vec![];
+        for (target, hp) in targets.iter().zip(damage) {
+            if hp <= 0. {
+                continue;
+            }
+            let mut entry = match universe.world.entry(target.entity) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            notifications.push(
+                NotificationKind::UnderAttack,
+                target.team,
+                tr!("notifications.under_attack"),
+                Some(target.position),
+            );
+            let damage = hp / target.def.max_health.max(1.);
+            hit_flashes.hit(target.entity, target.position, damage, audio);
+            if let Ok(unit) = entry.get_component_mut::<UnitComponent>() {
+                unit.health -= damage;
+                if unit.health <= 0. {
+                    dead.push(target);
+                }
+            } else if let Ok(tile) = entry.get_component_mut::<TileComponent>() {
+                // demolished by the caller, which has the tile assets
+                tile.health = (tile.health - damage).max(0.);
+                if tile.health <= 0. {
+                    destroyed.push(target.id);
+                }
+            }
+        }
+        for target in &dead {
+            let unit_type = match target.unit_type {
+                Some(unit_type) => unit_type,
+                None => continue,
+            };
+            log::debug!("Unit {:?} died", target.entity);
+            universe.despawn(target.entity);
+            wrecks.spawn(
+                target.position,
+                unit_type.wreck_radius(),
+                universe,
+                &mut self.tasks,
+            );
+        }
+        !dead.is_empty()
+    }
+
+    fn is_wall(target: StableId, universe: &Universe) -> bool {
+        universe
+            .ids
+            .entity(target)
+            .map_or(false, |entity| wall::is_wall(&universe.world, entity))
+    }
+
+    /// Sends the unit to the nearest free spot on top of the wall `target`.
+    fn climb_wall(entity: Entity, target: StableId, universe: &mut Universe) -> Option<()> {
+        let wall_entity = universe.ids.entity(target)?;
+        let from = universe
+            .world
+            .entry_ref(entity)
+            .ok()?
+            .get_component::<TransformComponent>()
+            .ok()?
+            .translation;
+        let post = wall::free_post(universe, wall_entity, from)?;
+        let mut entry = universe.world.entry(entity)?;
+        let unit = entry.get_component_mut::<UnitComponent>().ok()?;
+        unit.move_target = Some(post);
+        unit.waypoints.clear();
+        Some(())
+    }
+
+    /// Idle units standing on walls attack the nearest enemy they see.
+    fn acquire_targets(posted: &HashMap<Entity, f32>, universe: &mut Universe) {
+        if posted.is_empty() {
+            return;
+        }
+        let mut query = <(
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<UnitComponent>,
+        )>::query();
+        let units: Vec<_> = query
+            .iter(&universe.world)
+            .map(|(id, transform, unit)| (*id, transform.translation, unit.team))
+            .collect();
+        let mut query = <(Entity, Read<TransformComponent>, Write<UnitComponent>)>::query();
+        for (entity, transform, unit) in query.iter_mut(&mut universe.world) {
+            let elevation = match posted.get(entity) {
+                Some(elevation) if unit.command.is_none() && unit.move_target.is_none() => {
+                    *elevation
+                }
+                _ => continue,
+            };
+            let vision = wall::vision_range(elevation);
+            let enemy = units
+                .iter()
+                .filter(|(_, _, team)| *team != unit.team)
+                .map(|(id, position, _)| {
+                    (id, (*position - transform.translation).truncate().length())
+                })
+                .filter(|(_, distance)| *distance <= vision)
+                .min_by(|(_, d0), (_, d1)| d0.partial_cmp(d1).unwrap_or(std::cmp::Ordering::Equal));
+            if let Some((id, _)) = enemy {
+                unit.command = Some(UnitCommand::Attack(*id));
+            }
+        }
+    }
+
+    /// Takes the unit off the map into the garrison of `target`, if there is room. Buildings
+    /// get their garrison on first use, transports only take their own team and don't nest.
+    fn enter_garrison(entity: Entity, target: StableId, universe: &mut Universe) -> Option<()> {
+        let target_entity = universe.ids.entity(target).filter(|e| *e != entity)?;
+        let passenger = {
+            let entry = universe.world.entry_ref(entity).ok()?;
+            let unit = entry.get_component::<UnitComponent>().ok()?;
+            if unit.object_type.transport_capacity() > 0 {
+                return None;
+            }
+            Passenger {
+                id: *entry.get_component::<StableId>().ok()?,
+                unit: UnitComponent {
+                    speed: 0.,
+                    move_target: None,
+                    waypoints: vec![],
+                    selected: false,
+                    command: None,
+                    ..unit.clone()
+                },
+                scale: entry.get_component::<TransformComponent>().ok()?.scale,
+                prefab: entry
+                    .get_component::<PrefabComponent>()
+                    .ok()
+                    .map(|prefab| prefab.name.clone()),
+            }
+        };
+        {
+            let mut entry = universe.world.entry(target_entity)?;
+            if let Ok(transport) = entry.get_component::<UnitComponent>() {
+                if transport.team != passenger.unit.team {
+                    return None;
+                }
+            } else if entry.get_component::<GarrisonComponent>().is_err() {
+                entry.get_component::<TileComponent>().ok()?;
+                entry.add_component(GarrisonComponent::new(BUILDING_CAPACITY));
+            }
+        }
+        let mut entry = universe.world.entry(target_entity)?;
+        let garrison = entry.get_component_mut::<GarrisonComponent>().ok()?;
+        if garrison.is_full() {
+            return None;
+        }
+        garrison.passengers.push(passenger);
+        universe.stow(entity);
+        Some(())
+    }
+
+    /// Puts the passengers of `garrison` back on the map around it, heading to `target`.
+    fn unload(
+        &self,
+        garrison: StableId,
+        target: Vec3,
+        resources: &Resources,
+        universe: &mut Universe,
+    ) {
+        const UNLOAD_RADIUS: f32 = 4.;
+
+        let (position, passengers) = {
+            let entity = match universe.ids.entity(garrison) {
+                Some(entity) => entity,
+                None => return,
+            };
+            let mut entry = match universe.world.entry(entity) {
+                Some(entry) => entry,
+                None => return,
+            };
+            let position = match entry.get_component::<TransformComponent>() {
+                Ok(transform) => transform.translation,
+                Err(_) => return,
+            };
+            match entry.get_component_mut::<GarrisonComponent>() {
+                Ok(garrison) => (position, std::mem::take(&mut garrison.passengers)),
+                Err(_) => return,
+            }
+        };
+        log::info!("Unloading {} units at {}", passengers.len(), target);
+        let count = passengers.len().max(1) as f32;
+        for (idx, passenger) in passengers.into_iter().enumerate() {
+            let angle = idx as f32 / count * std::f32::consts::TAU;
+            let p = position + UNLOAD_RADIUS * Vec3::new(angle.cos(), angle.sin(), 0.);
+            let z = universe
+                .ray_cast(Vec3::new(p.x, p.y, p.z + 128.), Vec3::new(0., 0., -1.))
+                .map_or(p.z, |result| result.hit.z() as f32);
+            let transform = TransformComponent {
+                translation: Vec3::new(p.x, p.y, z + 2.),
+                scale: passenger.scale,
+                rotation: Quat::IDENTITY,
+            };
+            let unit = UnitComponent {
+                move_target: Some(target + (p - position)),
+                ..passenger.unit
+            };
+            self.spawn_unit(
+                transform,
+                unit,
+                Some(passenger.id),
+                passenger.prefab.as_deref(),
+                resources,
+                universe,
+            );
+        }
+    }
+
+    /// Sends the selected workers to the repair task of `target`, posting one if needed.
+    fn repair(&mut self, target: StableId, universe: &mut Universe) {
+        let position = match universe
+            .ids
+            .entity(target)
+            .and_then(|entity| universe.world.entry_ref(entity).ok())
+            .and_then(|entry| entry.get_component::<TransformComponent>().ok().copied())
+        {
+            Some(transform) => transform.translation,
+            None => return,
+        };
+        let task = self
+            .tasks
+            .tasks()
+            .find(|(_, task)| task.kind == TaskKind::Repair && task.target == Some(target))
+            .map(|(id, _)| *id);
+        let task =
+            task.unwrap_or_else(|| self.tasks.post(TaskKind::Repair, position, Some(target)));
+        let mut query = <(Read<StableId>, Read<UnitComponent>, Read<WorkerComponent>)>::query();
+        let workers: Vec<_> = query
+            .iter(&universe.world)
+            .filter(|(_, unit, _)| unit.selected)
+            .map(|(id, _, _)| *id)
+            .collect();
+        self.issue_command(UnitCommand::Harvest(task), position, &workers, universe);
+    }
+
+    /// Unloads the garrison of a building, clears the voxels it stamped and removes it.
+    fn demolish(&self, target: StableId, resources: &Resources, universe: &mut Universe) {
+        let (entity, position, asset, ground) = {
+            let entity = match universe.ids.entity(target) {
+                Some(entity) => entity,
+                None => return,
+            };
+            let entry = match universe.world.entry_ref(entity) {
+                Ok(entry) => entry,
+                Err(_) => return,
+            };
+            match (
+                entry.get_component::<TransformComponent>(),
+                entry.get_component::<TileComponent>(),
+            ) {
+                (Ok(transform), Ok(tile)) => (
+                    entity,
+                    transform.translation,
+                    tile.asset.clone(),
+                    tile.ground.clone(),
+                ),
+                _ => return,
+            }
+        };
+        let tile = resources
+            .get::<AssetManager>()
+            .unwrap()
+            .committed_asset(&asset)
+            .cloned();
+        let tile = match tile {
+            Some(tile) => tile,
+            None => {
+                log::warn!("Cannot demolish {:?}, its tile is not loaded", target);
+                return;
+            }
+        };
+        log::info!("Demolish tile {} at: {}", tile.inner.name, position);
+        self.unload(target, position, resources, universe);
+        universe.clear_tile(
+            &tile,
+            PointN([position.x as i32, position.y as i32, position.z as i32]),
+            &ground,
+        );
+        universe.despawn(entity);
+    }
+
+    #[profiling::function]
+    pub fn update(
+        &mut self,
+        simulation: &mut Simulation,
+        resources: &mut Resources,
+        ui_state: &mut UiState,
+    ) {
+        const ACK_SOUND_DURATION: f32 = 0.3; // s
+
+        let camera = resources.get::<RTSCamera>().unwrap();
+        let view_proj = camera.view_proj();
+        let (dt, unscaled_dt) = {
+            let time_state = resources.get::<TimeState>().unwrap();
+            (time_state.scaled_dt(), time_state.unscaled_dt())
+        };
+        let input = resources.get::<InputResource>().unwrap();
+        let universe = simulation.universe();
+
+        let window_size = resources
+            .get::<ViewportsResource>()
+            .unwrap()
+            .main_window_size;
+        let (x0, y0, x1, y1) = if let Some(MouseDragState {
+            begin_position: p0,
+            end_position: p1,
+            ..
+        }) = input.mouse_drag_just_finished(MouseButton::LEFT)
+        {
+            (
+                (p0.x.min(p1.x) / window_size.width as f32) * 2. - 1.,
+                (p0.y.max(p1.y) / window_size.height as f32) * -2. + 1.,
+                (p0.x.max(p1.x) / window_size.width as f32) * 2. - 1.,
+                (p0.y.min(p1.y) / window_size.height as f32) * -2. + 1.,
+            )
+        } else {
+            (0., 0., 0., 0.)
+        };
+        // a click selects the unit under the cursor, a double click all units of its type on
+        // screen, Shift adds to the selection
+        let additive = input.modifiers() == KeyModifiers::SHIFT;
+        let click = input
+            .mouse_button_just_clicked_position(MouseButton::LEFT)
+            .filter(|_| ui_state.unit.selecting);
+        let double_click = input
+            .mouse_button_just_double_clicked_position(MouseButton::LEFT)
+            .is_some();
+        // a unit in front of a building gets the click, the building otherwise
+        let click_hit = click.and_then(|click| {
+            camera.pick_ray(click.x as u32, click.y as u32).pick(
+                PickLayers::UNITS | PickLayers::TERRAIN,
+                universe,
+                resources,
+            )
+        });
+        let picked = click_hit
+            .as_ref()
+            .and_then(|hit| hit.unit())
+            .and_then(|entity| {
+                let entry = universe.world.entry_ref(entity).ok()?;
+                let unit = entry.get_component::<UnitComponent>().ok()?;
+                Some((entity, unit.object_type))
+            });
+        let picked_building = click_hit.as_ref().and_then(|hit| hit.building());
+
+        {
+            let mut orders = resources.get_mut::<PendingOrdersResource>().unwrap();
+            let acks = orders.take_acks();
+            for ack in &acks {
+                log::debug!("Unit {:?} acknowledges {}", ack.unit, ack.command);
+            }
+            if !acks.is_empty() {
+                // one confirmation per order, not per unit
+                resources.get_mut::<AudioResource>().unwrap().play(
+                    "order_ack",
+                    SoundCategory::Ui,
+                    None,
+                    Some(ACK_SOUND_DURATION),
+                );
+            }
+            for order in orders.take_due() {
+                self.issue_command(order.command, order.target, &order.units, universe);
+            }
+        }
+        if self.update_commands(universe) {
+            Self::count_selection(&universe.world, ui_state);
+        }
+
+        let mut tech = resources.get_mut::<TechResource>().unwrap();
+        let mut notifications = resources.get_mut::<NotificationResource>().unwrap();
+        for (team, name) in tech.update(dt) {
+            let message = tr!("notifications.research", what = crate::locale::tr(name));
+            notifications.push(NotificationKind::ResearchDone, team, message, None);
+        }
+        let mut destroyed = vec![];
+        let died = {
+            let rules = resources.get::<CombatRulesResource>().unwrap();
+            let mut wrecks = resources.get_mut::<WreckResource>().unwrap();
+            let mut hit_flashes = resources.get_mut::<HitFlashResource>().unwrap();
+            let mut audio = resources.get_mut::<AudioResource>().unwrap();
+            hit_flashes.flashing = !resources
+                .get::<AccessibilityResource>()
+                .unwrap()
+                .settings
+                .reduce_flashing;
+            let died = self.update_combat(
+                universe,
+                &tech,
+                &rules.rules,
+                &mut wrecks,
+                &mut notifications,
+                &mut hit_flashes,
+                &mut audio,
+                &mut destroyed,
+            );
+            hit_flashes.update(dt);
+            hit_flashes.update_meshes(universe, resources);
+            wrecks.update(dt, universe, &mut self.tasks);
+            died
+        };
+        for target in destroyed {
+            log::debug!("Building {:?} destroyed", target);
+            self.demolish(target, resources, universe);
+        }
+        if died {
+            Self::count_selection(&universe.world, ui_state);
+        }
+
+        if ui_state.unit.selecting {
+            let mut query = <(Entity, Read<TransformComponent>, Write<UnitComponent>)>::query();
+            query.par_for_each_mut(&mut universe.world, |(entity, transform, unit)| {
+                let pos_hom: Vec4 = (transform.translation, 1.).into();
+                let pos_view = view_proj * pos_hom;
+                let pos_screen = Vec2::new(pos_view.x / pos_view.w, pos_view.y / pos_view.w);
+                let in_rect = |x0: f32, y0: f32, x1: f32, y1: f32| {
+                    pos_screen.x > x0 && pos_screen.x < x1 && pos_screen.y > y0 && pos_screen.y < y1
+                };
+                let inside = match (click, picked) {
+                    (None, _) => in_rect(x0, y0, x1, y1),
+                    (Some(_), Some((_, ty))) if double_click => {
+                        unit.object_type == ty && in_rect(-1., -1., 1., 1.)
+                    }
+                    (Some(_), Some((picked, _))) => *entity == picked,
+                    (Some(_), None) => false,
+                };
+                unit.selected = inside || (additive && unit.selected);
+            });
+            let mut query = <(Entity, Write<TileComponent>)>::query();
+            for (entity, tile) in query.iter_mut(&mut universe.world) {
+                tile.selected = picked_building == Some(*entity) || (additive && tile.selected);
+            }
+
+            Self::count_selection(&universe.world, ui_state);
+        }
+
+        {
+            let mut economy = resources.get_mut::<PlayerEconomy>().unwrap();
+            let asset_manager = resources.get::<AssetManager>().unwrap();
+            self.update_workers(
+                universe,
+                dt,
+                &mut tech,
+                &mut economy,
+                &mut notifications,
+                &asset_manager,
+            );
+            let mut garrisons = [0; MAX_TEAMS];
+            let mut query = <(Read<TileComponent>, Read<GarrisonComponent>)>::query();
+            for (_, garrison) in query.iter(&universe.world) {
+                if let Some(passenger) = garrison.passengers.first() {
+                    garrisons[passenger.unit.team as usize % MAX_TEAMS] += 1;
+                }
+            }
+            economy.update(dt, &garrisons);
+        }
+        self.roster.update(&universe.world, ui_state.unit.team);
+        self.update_triggers(resources, universe);
+        // creeps live in the main universe, not on the tile editing platform
+        if !ui_state.env.tile_edit.active {
+            self.update_loaded_units(resources, universe);
+            self.update_creeps(dt, resources, universe);
+        }
+
+        // pushed by the movement system of the last frame
+        let visibility_time = resources
+            .get::<VisibilityUpdateResource>()
+            .unwrap()
+            .last_push_time();
+        if let StressTestAction::Spawn = ui_state
+            .unit
+            .stress_test
+            .sample(unscaled_dt, visibility_time)
+        {
+            self.spawn_stress_test(camera.look_at, resources, ui_state, universe);
+        }
+        Self::update_lods(camera.eye(), resources, universe);
+    }
+
+    /// Swaps the mesh of the units with a LOD chain for the level their distance to `eye`
+    /// calls for. The visibility object is registered again with the new render object, which
+    /// only happens when a unit crosses a switch distance.
+    fn update_lods(eye: Vec3, resources: &Resources, universe: &mut Universe) {
+        let mut lod_resource = resources.get_mut::<UnitLodResource>().unwrap();
+        lod_resource.begin_frame();
+        let mut switched = vec![];
+        let mut query = <(Entity, Read<TransformComponent>, Write<UnitLodComponent>)>::query();
+        for (entity, transform, lod) in query.iter_mut(&mut universe.world) {
+            let level = lod_resource.select(lod, transform.translation.distance(eye));
+            lod_resource.count(level, level != lod.current);
+            if level != lod.current {
+                lod.current = level;
+                switched.push((*entity, *transform, lod.levels[level].1.clone()));
+            }
+        }
+        drop(lod_resource);
+
+        for (entity, transform, mesh_render_object) in switched {
+            if let Some(mut entry) = universe.world.entry(entity) {
+                entry.add_component(MeshComponent {
+                    render_object_handle: mesh_render_object.clone(),
+                });
+                // dropping the old visibility object unregisters it
+                entry.remove_component::<VisibilityComponent>();
+            }
+            Self::add_visibility(entity, &transform, &mesh_render_object, resources, universe);
+        }
+    }
+
+    fn update_workers(
+        &mut self,
+        universe: &mut Universe,
+        dt: f32,
+        tech: &mut TechResource,
+        economy: &mut PlayerEconomy,
+        notifications: &mut NotificationResource,
+        asset_manager: &AssetManager,
+    ) {
+        const GATHER_YIELD: u32 = 10;
+
+        let mut posts = vec![];
+        let mut query = <(
+            Read<StableId>,
+            Read<TransformComponent>,
+            Read<TileComponent>,
+        )>::query();
+        for (id, transform, tile) in query.iter(&universe.world) {
+            let kind = if !tile.built {
+                TaskKind::Build
+            } else if tile.health < 1. {
+                TaskKind::Repair
+            } else {
+                continue;
+            };
+            if !self.tasks.has_task_for(kind, *id) {
+                posts.push((kind, *id, transform.translation));
+            }
+        }
+        for (kind, id, position) in posts {
+            self.tasks.post(kind, position, Some(id));
+        }
+
+        let mut finished = vec![];
+        let mut query = <(
+            Read<StableId>,
+            Read<TransformComponent>,
+            Write<UnitComponent>,
+            Write<WorkerComponent>,
+        )>::query();
+        for (worker_id, transform, unit, worker) in query.iter_mut(&mut universe.world) {
+            worker.state = match worker.state {
+                WorkerState::Idle => {
+                    let claimed = if unit.move_target.is_none() && unit.command.is_none() {
+                        self.tasks.claim_nearest(*worker_id, transform.translation)
+                    } else {
+                        None
+                    };
+                    if let Some((id, position)) = claimed {
+                        unit.move_target = Some(position);
+                        WorkerState::Moving(id)
+                    } else {
+                        WorkerState::Idle
+                    }
+                }
+                WorkerState::Moving(id) => match self.tasks.get(id).map(|t| t.position) {
+                    None => {
+                        unit.move_target = None;
+                        WorkerState::Idle
+                    }
+                    Some(_) if unit.move_target.is_none() => WorkerState::Working(id),
+                    Some(position) if unit.move_target != Some(position) => {
+                        self.tasks.release(id);
+                        WorkerState::Idle
+                    }
+                    Some(_) => WorkerState::Moving(id),
+                },
+                WorkerState::Working(id) => {
+                    if self.tasks.get(id).is_none() {
+                        WorkerState::Idle
+                    } else if unit.move_target.is_some() {
+                        self.tasks.release(id);
+                        WorkerState::Idle
+                    } else if let Some(task) = self.tasks.progress(
+                        id,
+                        dt * tech.unit_def(unit.team, unit.object_type).work_rate,
+                    ) {
+                        finished.push((task, unit.team));
+                        WorkerState::Idle
+                    } else {
+                        WorkerState::Working(id)
+                    }
+                }
+            };
+        }
+
+        for (task, team) in finished {
+            if task.kind == TaskKind::Gather {
+                tech.add_stock(team, GATHER_YIELD);
+                economy.add_minerals(team, GATHER_YIELD);
+            }
+            let target = task.target.and_then(|target| universe.ids.entity(target));
+            let mut entry = match target.and_then(|entity| universe.world.entry(entity)) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let tile = match entry.get_component_mut::<TileComponent>() {
+                Ok(tile) => tile,
+                Err(_) => continue,
+            };
+            match task.kind {
+                TaskKind::Build if !tile.built => {
+                    tile.built = true;
+                    let name = asset_manager
+                        .committed_asset(&tile.asset)
+                        .map_or(String::new(), |asset| asset.inner.name.clone());
+                    notifications.push(
+                        NotificationKind::ConstructionComplete,
+                        tile.team.unwrap_or(team),
+                        tr!("notifications.construction", what = name),
+                        Some(task.position),
+                    );
+                }
+                TaskKind::Repair => tile.health = 1.,
+                _ => {}
+            }
+        }
+    }
+
+    fn update_triggers(&self, resources: &Resources, universe: &mut Universe) {
+        let actions = {
+            let mut triggers = resources.get_mut::<TriggerResource>().unwrap();
+            triggers.update(&universe.world);
+            let actions = triggers.take_actions();
+            // removed triggers can't have fired this frame
+            actions
+                .into_iter()
+                .map(|(id, action)| {
+                    let trigger = triggers.get(id).unwrap();
+                    (trigger.team, trigger.region.center(), action)
+                })
+                .collect::<Vec<_>>()
+        };
+        for (trigger_team, center, action) in actions {
+            match action {
+                TriggerAction::SpawnUnits {
+                    unit_type,
+                    team,
+                    count,
+                    position,
+                } => {
+                    let columns = (count as f32).sqrt().ceil().max(1.) as u32;
+                    for i in 0..count {
+                        let offset = Vec3::new((i % columns) as f32, (i / columns) as f32, 0.) * 3.;
+                        let p = position + offset;
+                        let z = universe
+                            .ray_cast(Vec3::new(p.x, p.y, p.z + 128.), Vec3::new(0., 0., -1.))
+                            .map_or(p.z, |result| result.hit.z() as f32);
+                        let spawned = self.spawn_prefab(
+                            unit_type.prefab_name(),
+                            team,
+                            Vec3::new(p.x, p.y, z + 1.),
+                            resources,
+                            universe,
+                        );
+                        if let Err(err) = spawned {
+                            log::error!("{}", err);
+                        }
+                    }
+                }
+                TriggerAction::Message(message) => {
+                    // a trigger for any team tells every team
+                    let teams = match trigger_team {
+                        Some(team) => team..team + 1,
+                        None => 0..MAX_TEAMS as u8,
+                    };
+                    let mut notifications = resources.get_mut::<NotificationResource>().unwrap();
+                    for team in teams {
+                        notifications.push(
+                            NotificationKind::Mission,
+                            team,
+                            message.clone(),
+                            Some(center),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Spawns the units of a loaded save, batched by type and team, and points the creep camps
+    /// at the new ids of their members.
+    fn update_loaded_units(&self, resources: &Resources, universe: &mut Universe) {
+        let units = resources
+            .get_mut::<AutosaveResource>()
+            .unwrap()
+            .take_loaded_units();
+        if units.is_empty() {
+            return;
+        }
+        let mut batches: HashMap<(UnitType, u8), Vec<UnitSave>> = HashMap::new();
+        for unit in units {
+            batches
+                .entry((unit.unit_type, unit.team))
+                .or_default()
+                .push(unit);
+        }
+        let mut ids = HashMap::new();
+        for ((unit_type, team), units) in batches {
+            let positions: Vec<_> = units.iter().map(|unit| Vec3::from(unit.position)).collect();
+            let spawned = match self.spawn_batch(unit_type, team, &positions, resources, universe) {
+                Ok(spawned) => spawned,
+                Err(err) => {
+                    log::error!("{}", err);
+                    continue;
+                }
+            };
+            for (unit, id) in units.iter().zip(spawned) {
+                ids.insert(unit.id, id);
+                let entity = match universe.ids.entity(id) {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+                if let Some(mut entry) = universe.world.entry(entity) {
+                    if let Ok(component) = entry.get_component_mut::<UnitComponent>() {
+                        component.health = unit.health;
+                        component.move_target = unit.move_target.map(Vec3::from);
+                    }
+                }
+            }
+        }
+        resources
+            .get_mut::<CreepResource>()
+            .unwrap()
+            .remap_members(&ids);
+    }
+
+    fn update_creeps(&self, dt: f32, resources: &Resources, universe: &mut Universe) {
+        let spawns = {
+            let mut creeps = resources.get_mut::<CreepResource>().unwrap();
+            creeps.update(dt, universe);
+            creeps.take_spawns()
+        };
+        for spawn in spawns {
+            let positions: Vec<_> = spawn
+                .positions
+                .iter()
+                .map(|p| Vec3::new(p.x, p.y, Self::ground_z(universe, *p) + 1.))
+                .collect();
+            match self.spawn_batch(spawn.unit_type, CREEP_TEAM, &positions, resources, universe) {
+                Ok(ids) => resources
+                    .get_mut::<CreepResource>()
+                    .unwrap()
+                    .add_members(spawn.camp, ids),
+                Err(err) => log::error!("{}", err),
+            }
+        }
+    }
+
+    fn spawn_stress_test(
+        &self,
+        center: Vec3,
+        resources: &Resources,
+        ui_state: &UiState,
+        universe: &mut Universe,
+    ) {
+        let params = &ui_state.unit.stress_test.params;
+        let (positions, targets): (Vec<_>, Vec<_>) = params
+            .grid_positions(center)
+            .into_iter()
+            .map(|p| {
+                let position = Vec3::new(p.x, p.y, Self::ground_z(universe, p) + 1.);
+                let target = if params.random_orders {
+                    let t = params.random_target(center);
+                    Some(Vec3::new(t.x, t.y, Self::ground_z(universe, t) + 2.))
+                } else {
+                    None
+                };
+                (position, target)
+            })
+            .unzip();
+
+        let ids = match self.spawn_batch(params.unit_type, 0, &positions, resources, universe) {
+            Ok(ids) => ids,
+            Err(err) => {
+                log::error!("{}", err);
+                return;
+            }
+        };
+        for (id, target) in ids.into_iter().zip(targets) {
+            if let Some(target) = target {
+                let entity = universe.ids.entity(id).unwrap();
+                let mut entry = universe.world.entry(entity).unwrap();
+                if let Ok(unit) = entry.get_component_mut::<UnitComponent>() {
+                    unit.move_target = Some(target);
+                }
+            }
+        }
+    }
+
+    /// The height of the ground below `p`, or `p.z` off the terrain.
+    fn ground_z(universe: &Universe, p: Vec3) -> f32 {
+        universe
+            .ray_cast(Vec3::new(p.x, p.y, p.z + 128.), Vec3::new(0., 0., -1.))
+            .map_or(p.z, |result| result.hit.z() as f32)
+    }
+
+    /// Fills the terrain under the screen rectangle from `p0` to `p1` with a grid of the unit type
+    /// being spawned, as many as the team can pay for.
+    fn spawn_formation(
+        &self,
+        p0: Vec2,
+        p1: Vec2,
+        camera: &RTSCamera,
+        resources: &Resources,
+        universe: &mut Universe,
+        ui_state: &mut UiState,
+    ) {
+        let corner0 = camera.ray_cast_terrain(p0.x as u32, p0.y as u32, universe, ui_state);
+        let corner1 = camera.ray_cast_terrain(p1.x as u32, p1.y as u32, universe, ui_state);
+        let (corner0, corner1) = match (corner0, corner1) {
+            (Some(corner0), Some(corner1)) => (corner0.hit, corner1.hit),
+            _ => return,
+        };
+        let min = Vec2::new(
+            corner0.x().min(corner1.x()) as f32,
+            corner0.y().min(corner1.y()) as f32,
+        );
+        let extent = Vec2::new(
+            (corner0.x() - corner1.x()).abs() as f32,
+            (corner0.y() - corner1.y()).abs() as f32,
+        );
+        let top = corner0.z().max(corner1.z()) as f32;
+
+        let spacing = ui_state.unit.formation_spacing;
+        let columns = (extent.x / spacing) as usize + 1;
+        let rows = (extent.y / spacing) as usize + 1;
+        // centered in the rectangle, the leftover space split on both sides
+        let origin =
+            min + (extent - spacing * Vec2::new(columns as f32 - 1., rows as f32 - 1.)) / 2.;
+        let mut positions: Vec<_> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (row, column)))
+            .take(MAX_FORMATION_UNITS)
+            .map(|(row, column)| {
+                let p = origin + spacing * Vec2::new(column as f32, row as f32);
+                Vec3::new(
+                    p.x,
+                    p.y,
+                    Self::ground_z(universe, Vec3::new(p.x, p.y, top)) + 1.,
+                )
+            })
+            .collect();
+
+        let (unit_type, team) = (ui_state.unit.object_type, ui_state.unit.team);
+        let paid = {
+            let mut economy = resources.get_mut::<PlayerEconomy>().unwrap();
+            let what = unit_type.to_string();
+            positions
+                .iter()
+                .take_while(|_| economy.try_spend(team, Cost::unit(unit_type), &what))
+                .count()
+        };
+        positions.truncate(paid);
+        if positions.is_empty() {
+            return;
+        }
+        if let Err(err) = self.spawn_batch(unit_type, team, &positions, resources, universe) {
+            ui_state.error(err);
+        }
+    }
+
+    fn find_prefab(name: &str, resources: &Resources) -> Result<Prefab, String> {
+        resources
+            .get::<PrefabRegistry>()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown prefab {}", name))
+    }
+
+    /// The components of a `prefab` unit of `team` standing at `position`, with its random scale
+    /// and yaw rolled.
+    fn prefab_components(
+        prefab: &Prefab,
+        team: u8,
+        position: Vec3,
+    ) -> (TransformComponent, UnitComponent) {
+        // transform component
+        let defaults = &prefab.transform;
+        let position = position + defaults.offset;
+        let mut rng = thread_rng();
+        let scale_xy = if defaults.scale_max > defaults.scale_min {
+            rng.gen_range(defaults.scale_min..defaults.scale_max)
+        } else {
+            defaults.scale_min
+        };
+        let yaw = if defaults.random_yaw {
+            rng.gen_range(0.0..2.0 * std::f32::consts::PI)
+        } else {
+            0.
+        };
+        let transform_component = TransformComponent {
+            translation: position,
+            scale: Vec3::new(scale_xy, scale_xy, 1.),
+            rotation: Quat::from_rotation_z(yaw),
+        };
+
+        // unit component
+        let unit_component = UnitComponent {
+            object_type: prefab.unit_type,
+            health: prefab.health,
+            aim: Vec3::new(1., 0., 0.),
+            speed: 0.,
+            move_target: None,
+            waypoints: vec![],
+            selected: false,
+            team,
+            command: None,
+            cooldown: 0.,
+        };
+
+        (transform_component, unit_component)
+    }
+
+    /// Spawns the prefab `name` for `team` standing at `position`.
+    pub fn spawn_prefab(
+        &self,
+        name: &str,
+        team: u8,
+        position: Vec3,
+        resources: &Resources,
+        universe: &mut Universe,
+    ) -> Result<StableId, String> {
+        let prefab = Self::find_prefab(name, resources)?;
+        let (transform_component, unit_component) =
+            Self::prefab_components(&prefab, team, position);
+
+        log::debug!("Spawn prefab {} at: {}", name, position);
+        Ok(self.spawn_unit(
+            transform_component,
+            unit_component,
+            None,
+            Some(name),
+            resources,
+            universe,
+        ))
+    }
+
+    /// Spawns a unit of `unit_type` for `team` at each of `positions`, from the type's prefab.
+    /// Unlike calling `spawn_prefab` in a loop, the entities are pushed with a single
+    /// `World::extend` and the mesh resources are locked once for all visibility objects.
+    pub fn spawn_batch(
+        &self,
+        unit_type: UnitType,
+        team: u8,
+        positions: &[Vec3],
+        resources: &Resources,
+        universe: &mut Universe,
+    ) -> Result<Vec<StableId>, String> {
+        let name = unit_type.prefab_name();
+        let prefab = Self::find_prefab(name, resources)?;
+        let mesh_render_object = self.unit_mesh(Some(&prefab), unit_type);
+        let lod = self.unit_lod(Some(&prefab), &mesh_render_object);
+        let capacity = unit_type.transport_capacity();
+
+        let components: Vec<_> = positions
+            .iter()
+            .map(|position| {
+                let (transform_component, unit_component) =
+                    Self::prefab_components(&prefab, team, *position);
+                (
+                    transform_component,
+                    MeshComponent {
+                        render_object_handle: mesh_render_object.clone(),
+                    },
+                    unit_component,
+                    LabelComponent::new(name.to_string(), LabelKind::Unit, LABEL_HEIGHT * Vec3::Z),
+                    PrefabComponent {
+                        name: name.to_string(),
+                    },
+                )
+            })
+            .collect();
+        let transforms: Vec<_> = components.iter().map(|components| components.0).collect();
+        let entities = universe.world.extend(components).to_vec();
+        let ids = entities
+            .iter()
+            .map(|entity| universe.register_entity(*entity))
+            .collect();
+        if capacity > 0 || lod.is_some() || unit_type.is_worker() {
+            for entity in &entities {
+                let mut entry = universe.world.entry(*entity).unwrap();
+                if capacity > 0 {
+                    entry.add_component(GarrisonComponent::new(capacity));
+                }
+                if unit_type.is_worker() {
+                    entry.add_component(WorkerComponent::default());
+                }
+                if let Some(lod) = &lod {
+                    entry.add_component(lod.clone());
+                }
+            }
+        }
+
+        let units: Vec<_> = entities.into_iter().zip(transforms).collect();
+        Self::add_visibility_batch(&units, &mesh_render_object, resources, universe);
+        self.spawn_attachments(&prefab, &units, resources, universe);
+
+        log::debug!("Spawn {} prefabs {}", units.len(), name);
+        Ok(ids)
+    }
+
+    /// The mesh of a unit, prefab meshes added after the scene was loaded fall back to the type's.
+    fn unit_mesh(&self, prefab: Option<&Prefab>, unit_type: UnitType) -> RenderObjectHandle {
+        prefab
+            .and_then(|prefab| prefab.mesh.as_ref())
+            .and_then(|path| self.prefab_meshes.get(path))
+            .or_else(|| self.meshes.get(&unit_type))
+            .unwrap()
+            .handle()
+            .clone()
+    }
+
+    /// The LOD chain starting at `mesh_render_object`, when the prefab has loaded LOD meshes.
+    fn unit_lod(
+        &self,
+        prefab: Option<&Prefab>,
+        mesh_render_object: &RenderObjectHandle,
+    ) -> Option<UnitLodComponent> {
+        let lods: Vec<_> = prefab
+            .iter()
+            .flat_map(|prefab| &prefab.lods)
+            .filter_map(|lod| match self.prefab_meshes.get(&lod.mesh) {
+                Some(mesh) => Some((lod.distance, mesh.handle().clone())),
+                None => {
+                    log::warn!("LOD mesh {} is not loaded", lod.mesh);
+                    None
+                }
+            })
+            .collect();
+        if lods.is_empty() {
+            return None;
+        }
+        let levels = std::iter::once((0., mesh_render_object.clone()))
+            .chain(lods)
+            .collect();
+        Some(UnitLodComponent::new(levels))
+    }
+
+    /// Pushes the unit entity with its mesh and visibility object, and the attachments of its
+    /// prefab. With `id`, the entity takes over that stowed `StableId` instead of registering a
+    /// new one.
+    fn spawn_unit(
+        &self,
+        transform_component: TransformComponent,
+        unit_component: UnitComponent,
+        id: Option<StableId>,
+        prefab_name: Option<&str>,
+        resources: &Resources,
+        universe: &mut Universe,
+    ) -> StableId {
+        let prefab = prefab_name.and_then(|name| {
+            let registry = resources.get::<PrefabRegistry>().unwrap();
+            registry.get(name).cloned()
+        });
+
+        let mesh_render_object = self.unit_mesh(prefab.as_ref(), unit_component.object_type);
+        let mesh_component = MeshComponent {
+            render_object_handle: mesh_render_object.clone(),
+        };
+        let lod = self.unit_lod(prefab.as_ref(), &mesh_render_object);
+
+        // entity
+        let object_type = unit_component.object_type;
+        let capacity = object_type.transport_capacity();
+        let entity = universe
+            .world
+            .push((transform_component, mesh_component, unit_component));
+        let id = match id {
+            Some(id) if universe.restore(id, entity) => id,
+            _ => universe.register_entity(entity),
+        };
+        if let Some(mut entry) = universe.world.entry(entity) {
+            if capacity > 0 {
+                entry.add_component(GarrisonComponent::new(capacity));
+            }
+            if object_type.is_worker() {
+                entry.add_component(WorkerComponent::default());
+            }
+            if let Some(lod) = lod {
+                entry.add_component(lod);
+            }
+            let name = prefab_name.map_or_else(|| format!("{}", object_type), str::to_string);
+            entry.add_component(LabelComponent::new(
+                name,
+                LabelKind::Unit,
+                LABEL_HEIGHT * Vec3::Z,
+            ));
+            if let Some(name) = prefab_name {
+                entry.add_component(PrefabComponent {
+                    name: name.to_string(),
+                });
+            }
+        }
+        Self::add_visibility(
+            entity,
+            &transform_component,
+            &mesh_render_object,
+            resources,
+            universe,
+        );
+
+        if let Some(prefab) = &prefab {
+            self.spawn_attachments(
+                prefab,
+                &[(entity, transform_component)],
+                resources,
+                universe,
+            );
+        }
+
+        id
+    }
+
+    /// Pushes the attachments of `prefab` onto each of the `units` spawned from it, an extend
+    /// per attachment.
+    fn spawn_attachments(
+        &self,
+        prefab: &Prefab,
+        units: &[(Entity, TransformComponent)],
+        resources: &Resources,
+        universe: &mut Universe,
+    ) {
+        for attachment in &prefab.attachments {
+            let mesh_render_object = match self.prefab_meshes.get(&attachment.mesh) {
+                Some(mesh) => mesh.handle().clone(),
+                None => {
+                    log::warn!("Attachment mesh {} is not loaded", attachment.mesh);
+                    continue;
+                }
+            };
+            let local = TransformComponent {
+                translation: attachment.offset,
+                scale: Vec3::splat(attachment.scale),
+                rotation: Quat::IDENTITY,
+            };
+            // placed right away, the hierarchy keeps it on the unit from the next frame
+            let transforms: Vec<_> = units
+                .iter()
+                .map(|(_, parent)| TransformComponent {
+                    translation: parent.translation
+                        + parent.rotation * (parent.scale * attachment.offset),
+                    scale: parent.scale * attachment.scale,
+                    rotation: parent.rotation,
+                })
+                .collect();
+            let children = universe
+                .world
+                .extend(
+                    transforms
+                        .iter()
+                        .map(|transform| {
+                            (
+                                *transform,
+                                MeshComponent {
+                                    render_object_handle: mesh_render_object.clone(),
+                                },
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .to_vec();
+            let children: Vec<_> = children.into_iter().zip(transforms).collect();
+            Self::add_visibility_batch(&children, &mesh_render_object, resources, universe);
+            for ((child, _), (parent, _)) in children.iter().zip(units) {
+                universe.attach(*child, *parent, local);
+            }
+        }
+    }
+
+    fn add_visibility(
+        entity: Entity,
+        transform_component: &TransformComponent,
+        mesh_render_object: &RenderObjectHandle,
+        resources: &Resources,
+        universe: &mut Universe,
+    ) {
+        Self::add_visibility_batch(
+            &[(entity, *transform_component)],
+            mesh_render_object,
+            resources,
+            universe,
+        );
+    }
+
+    /// Registers a visibility object for each of `entities`, all drawing `mesh_render_object`.
+    fn add_visibility_batch(
+        entities: &[(Entity, TransformComponent)],
+        mesh_render_object: &RenderObjectHandle,
+        resources: &Resources,
+        universe: &mut Universe,
+    ) {
+        let asset_manager = resources.get::<AssetManager>().unwrap();
+        let mesh_render_objects = resources.get::<MeshRenderObjectSet>().unwrap();
+        let mesh_render_objects = mesh_render_objects.read();
+        let asset_handle = &mesh_render_objects.get(mesh_render_object).mesh;
+        let visible_bounds = asset_manager
+            .committed_asset(&asset_handle)
+            .unwrap()
+            .inner
+            .asset_data
+            .visible_bounds;
+        for (entity, transform_component) in entities {
+            let mut entry = universe.world.entry(*entity).unwrap();
+            entry.add_component(VisibilityComponent {
+                visibility_object_handle: {
+                    let handle = universe.visibility_region.register_dynamic_object(
+                        mesh_adv_object_id(*entity),
+                        CullModel::VisibleBounds(visible_bounds),
+                    );
+                    handle.set_transform(
+                        transform_component.translation,
+                        transform_component.rotation,
+                        transform_component.scale,
+                    );
+                    handle.add_render_object(mesh_render_object);
+                    handle
+                },
+            });
+            entry.add_component(VisibilityPushComponent::default());
+            entry.add_component(AuditComponent(vec![AuditToken::new(
+                AuditedHandle::VisibilityObject,
+            )]));
+        }
+    }
+
+    fn count_selection(world: &World, ui_state: &mut UiState) {
+        ui_state.unit.selected_count = 0;
+        ui_state.unit.selected.clear();
+        let mut query = <Read<UnitComponent>>::query();
+        for dyn_object in query.iter(world) {
+            if dyn_object.selected {
+                ui_state.unit.selected_count += 1;
+                let entry = ui_state.unit.selected.entry(dyn_object.object_type);
+                entry.and_modify(|e| *e += 1).or_insert(1);
+            }
+        }
+    }
+
+    pub fn add_debug_draw(&self, resources: &Resources, world: &World) {
+        let mut debug_draw = resources.get_mut::<Debug3DResource>().unwrap();
+        let mut cull = resources.get_mut::<DebugCullResource>().unwrap();
+
+        building::add_debug_draw(world, &mut debug_draw, &mut cull);
+
+        // selection is shown by the outline, the markers only carry the team color
+        let mut outlines = resources.get_mut::<OutlineResource>().unwrap();
+        let mut labels = resources.get_mut::<LabelResource>().unwrap();
+        let mut query = <(Read<TransformComponent>, Read<UnitComponent>)>::query();
+        for (transform, dyn_object) in query.iter(world) {
+            let color = outlines.outline_color(dyn_object.team, OutlineState::Owned);
             let pos = transform.translation;
+            if let Some(move_target) = dyn_object.move_target {
+                if cull.is_line_visible(pos, move_target) {
+                    debug_draw.add_line(pos, move_target, color);
+                }
+            }
+            // the stem and the aim arrow are 5 long
+            if !cull.is_visible(pos, 5.) {
+                continue;
+            }
             let aim = pos + 5. * dyn_object.aim;
+            if let Some(command) = &dyn_object.command {
+                labels.annotate(pos + 6. * Vec3::Z, format!("{}", command), color);
+            }
             debug_draw.add_line(pos, Vec3::new(pos.x, pos.y, pos.z + 5.), color);
             debug_draw.add_line(pos, aim, color);
             debug_draw.add_cone(aim, pos + 4.7 * dyn_object.aim, 0.1, color, 6);
-            if let Some(move_target) = dyn_object.move_target {
-                debug_draw.add_line(pos, move_target, color);
+        }
+
+        self.markers.add_debug_draw(&mut debug_draw);
+        resources
+            .get::<NotificationResource>()
+            .unwrap()
+            .add_debug_draw(&mut debug_draw);
+        resources
+            .get::<TriggerResource>()
+            .unwrap()
+            .add_debug_draw(&mut debug_draw);
+
+        let render_options = resources.get::<RenderOptions>().unwrap();
+        outlines.update(world);
+        if render_options.enable_outlines {
+            let context = resources.get::<EguiContextResource>().unwrap().context();
+            let camera = resources.get::<RTSCamera>().unwrap();
+            outlines.paint(&context, &camera, render_options.outline_thickness);
+        }
+
+        let task_col = |kind: TaskKind| match kind {
+            TaskKind::Gather => Vec4::new(1., 1., 0., 1.),
+            TaskKind::Build => Vec4::new(0., 0.5, 1., 1.),
+            TaskKind::Repair => Vec4::new(1., 0.5, 0., 1.),
+        };
+        for (_, task) in self.tasks.tasks() {
+            let pos = task.position;
+            let top = Vec3::new(pos.x, pos.y, pos.z + 3.);
+            if cull.is_line_visible(pos, top) {
+                debug_draw.add_cone(top, pos, 0.5, task_col(task.kind), 8);
+            }
+        }
+
+        let mut query = <(Read<TransformComponent>, Read<WorkerComponent>)>::query();
+        for (transform, worker) in query.iter(world) {
+            if let WorkerState::Moving(id) | WorkerState::Working(id) = worker.state {
+                if let Some(task) = self.tasks.get(id) {
+                    if cull.is_line_visible(transform.translation, task.position) {
+                        debug_draw.add_line(
+                            transform.translation,
+                            task.position,
+                            task_col(task.kind),
+                        );
+                    }
+                }
             }
         }
     }
 }
+
+// half the larger side of a building's footprint
+fn building_radius(tile: &TileComponent) -> f32 {
+    let [w, h] = tile.properties.footprint.unwrap_or([0, 0]);
+    w.max(h) as f32 / 2.
+}
+
+/// Registers the unit systems with the universe schedule. Movement takes units towards their
+/// `move_target`, then along their `waypoints`, at the speed of their team's `UnitDef`. Attack
+/// cooldowns tick down in the combat stage, hits are resolved in `UnitsState::update_combat`.
+pub fn register_systems(systems: &mut UniverseScheduleResource) {
+    systems.add_system(UpdateStage::Movement, "unit movement", |builder| {
+        builder.add_system(
+            SystemBuilder::new("unit movement")
+                .read_resource::<TimeState>()
+                .read_resource::<TechResource>()
+                .read_resource::<VisibilityUpdateResource>()
+                .with_query(<(
+                    Entity,
+                    Write<TransformComponent>,
+                    Write<VisibilityPushComponent>,
+                    Write<UnitComponent>,
+                )>::query())
+                .build(move |_, world, (time_state, tech, updates), query| {
+                    let dt = time_state.scaled_dt();
+                    query.par_for_each_mut(world, |(entity, transform, push, unit)| {
+                        if let Some(target) = unit.move_target {
+                            let target_dir = (target - transform.translation).normalize();
+                            let orig_dir = Vec3::X;
+                            if (target_dir - orig_dir).length() > 0.001 {
+                                transform.rotation = Quat::from_rotation_arc(orig_dir, target_dir);
+                            }
+                            if (target_dir - unit.aim).length() > 0.001 {
+                                unit.aim = (unit.aim + (target_dir - unit.aim) * dt).normalize();
+                            }
+                            let target_speed = tech.unit_def(unit.team, unit.object_type).speed;
+                            if unit.speed < target_speed {
+                                unit.speed = (unit.speed + 2. * dt).min(target_speed);
+                            }
+                            transform.translation += unit.speed * dt * target_dir;
+                            if (target - transform.translation).length() < 0.1 {
+                                unit.move_target = unit.waypoints.pop();
+                                if unit.move_target.is_none() {
+                                    unit.speed = 0.;
+                                }
+                            }
+                            // a unit that just stopped pushes its final position, applied in
+                            // the visibility sync stage
+                            let velocity = unit.speed * target_dir;
+                            let update = match unit.move_target {
+                                None => Some(transform.translation),
+                                Some(_) => {
+                                    updates.update(*entity, transform.translation, velocity, dt)
+                                }
+                            };
+                            if update.is_some() {
+                                push.translation = update;
+                            }
+                        }
+                    });
+                }),
+        );
+    });
+    systems.add_system(UpdateStage::Combat, "attack cooldown", |builder| {
+        builder.add_system(
+            SystemBuilder::new("attack cooldown")
+                .read_resource::<TimeState>()
+                .with_query(<Write<UnitComponent>>::query())
+                .build(move |_, world, time_state, query| {
+                    let dt = time_state.scaled_dt();
+                    query.par_for_each_mut(world, |unit| {
+                        unit.cooldown = (unit.cooldown - dt).max(0.);
+                    });
+                }),
+        );
+    });
+}
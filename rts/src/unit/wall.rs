@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use building_blocks::prelude::*;
+use glam::Vec3;
+use legion::{Entity, EntityStore, IntoQuery, Read, World};
+use rafx_plugins::components::TransformComponent;
+
+use crate::{
+    env::{env::TileComponent, simulation::Universe},
+    unit::{building, unit::UnitComponent},
+};
+
+// unit origins are this far above the surface they stand on
+const UNIT_HEIGHT: f32 = 1.; // m
+
+// empty voxels a post needs above it
+const POST_CLEARANCE: i32 = 2; // voxels
+
+// posts are searched this high above the base of the wall
+const MAX_WALL_HEIGHT: i32 = 64; // voxels
+
+// a post this close to a unit or to where one is heading is taken
+const POST_SPACING: f32 = 1.; // m
+
+// of units standing on a wall, they shoot at enemies coming this close on their own
+const VISION_RANGE: f32 = 20.; // m
+
+// attack and vision range gained per voxel of height above the ground the wall stands on
+const RANGE_PER_VOXEL: f32 = 0.5; // m
+const MAX_RANGE_BONUS: f32 = 10.; // m
+
+fn range_bonus(elevation: f32) -> f32 {
+    (elevation.max(0.) * RANGE_PER_VOXEL).min(MAX_RANGE_BONUS)
+}
+
+/// `range` of a unit standing `elevation` voxels above the ground.
+pub fn attack_range(range: f32, elevation: f32) -> f32 {
+    range + range_bonus(elevation)
+}
+
+/// How far a unit standing on a wall `elevation` voxels above the ground spots enemies.
+pub fn vision_range(elevation: f32) -> f32 {
+    VISION_RANGE + range_bonus(elevation)
+}
+
+// footprint and the first voxel above the ground of every wall tile
+fn walls(world: &World) -> Vec<([i32; 4], i32)> {
+    let mut query = <(Read<TransformComponent>, Read<TileComponent>)>::query();
+    query
+        .iter(world)
+        .filter(|(_, tile)| tile.properties.wall)
+        .filter_map(|(transform, tile)| {
+            let footprint = building::footprint(transform, tile)?;
+            Some((footprint, transform.translation.z.floor() as i32))
+        })
+        .collect()
+}
+
+pub fn is_wall(world: &World, entity: Entity) -> bool {
+    world
+        .entry_ref(entity)
+        .ok()
+        .and_then(|entry| {
+            entry
+                .get_component::<TileComponent>()
+                .ok()
+                .map(|t| t.properties.wall)
+        })
+        .unwrap_or(false)
+}
+
+/// Units standing on walls, with how many voxels above the ground the wall stands on.
+pub fn posted_units(world: &World) -> HashMap<Entity, f32> {
+    let walls = walls(world);
+    if walls.is_empty() {
+        return HashMap::new();
+    }
+    let mut query = <(Entity, Read<TransformComponent>, Read<UnitComponent>)>::query();
+    query
+        .iter(world)
+        .filter_map(|(entity, transform, _)| {
+            let p = transform.translation;
+            let (x, y) = (p.x.floor() as i32, p.y.floor() as i32);
+            let feet = p.z - UNIT_HEIGHT;
+            walls
+                .iter()
+                .filter(|([x0, y0, x1, y1], _)| x >= *x0 && x < *x1 && y >= *y0 && y < *y1)
+                .map(|(_, base)| feet - *base as f32)
+                // on the ground next to the wall otherwise
+                .find(|elevation| *elevation >= 0.5)
+                .map(|elevation| (*entity, elevation))
+        })
+        .collect()
+}
+
+/// Free spot on top of `wall` nearest to `from`, where a unit entering it goes to stand.
+/// `None` when `wall` isn't a wall tile or it's full.
+pub fn free_post(universe: &Universe, wall: Entity, from: Vec3) -> Option<Vec3> {
+    let entry = universe.world.entry_ref(wall).ok()?;
+    let transform = entry.get_component::<TransformComponent>().ok()?;
+    let tile = entry.get_component::<TileComponent>().ok()?;
+    if !tile.properties.wall {
+        return None;
+    }
+    let [x0, y0, x1, y1] = building::footprint(transform, tile)?;
+    let base = transform.translation.z.floor() as i32;
+
+    let mut query = <(Read<TransformComponent>, Read<UnitComponent>)>::query();
+    let taken: Vec<Vec3> = query
+        .iter(&universe.world)
+        .flat_map(|(transform, unit)| {
+            std::iter::once(transform.translation).chain(unit.move_target)
+        })
+        .collect();
+    let solid = |x: i32, y: i32, z: i32| !universe.get_voxel(PointN([x, y, z])).is_empty();
+
+    let mut posts = vec![];
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let top = (base..base + MAX_WALL_HEIGHT)
+                .rev()
+                .find(|z| solid(x, y, *z));
+            let top = match top {
+                Some(top) if (1..=POST_CLEARANCE).all(|dz| !solid(x, y, top + dz)) => top,
+                _ => continue,
+            };
+            posts.push(Vec3::new(
+                x as f32 + 0.5,
+                y as f32 + 0.5,
+                (top + 1) as f32 + UNIT_HEIGHT,
+            ));
+        }
+    }
+    posts
+        .into_iter()
+        .filter(|post| taken.iter().all(|p| p.distance(*post) > POST_SPACING))
+        .min_by(|a, b| {
+            a.distance(from)
+                .partial_cmp(&b.distance(from))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+}
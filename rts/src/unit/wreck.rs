@@ -0,0 +1,175 @@
+use building_blocks::core::prelude::*;
+use glam::Vec3;
+use rand::{thread_rng, Rng};
+
+use crate::{
+    env::simulation::{MaterialVoxel, Universe},
+    unit::task::{TaskId, TaskKind, TaskScheduler},
+};
+
+// voxels on the outer shell of the heap are left out with this chance, for a ragged outline
+const SHELL_GAP_CHANCE: f64 = 0.35;
+
+// the salvage task sits this far above the top of the heap, like tasks posted by the player
+const SALVAGE_LIFT: f32 = 2.; // m
+
+struct Wreck {
+    position: Vec3,
+    // the voxels the heap replaced, restored when it goes away
+    stamped: Vec<(Point3i, MaterialVoxel)>,
+    salvage: TaskId,
+    time_left: f32, // s
+}
+
+/// Wreckage left on the terrain by large units when they die: a heap of voxels of the wreck
+/// material around the spot, with a gather task on top that workers can salvage. The heap is
+/// removed when the salvage is done or after `decay_time`, whichever comes first.
+pub struct WreckResource {
+    pub enabled: bool,
+    pub material: String,
+    pub decay_time: f32, // s
+    wrecks: Vec<Wreck>,
+}
+
+impl Default for WreckResource {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            material: "wreck".to_string(),
+            decay_time: 180.,
+            wrecks: vec![],
+        }
+    }
+}
+
+impl WreckResource {
+    /// Forgets the wrecks without touching the terrain, for a new world.
+    pub fn reset(&mut self) {
+        self.wrecks.clear();
+    }
+
+    /// Stamps a heap of `radius` voxels on the ground under `position` and posts its salvage.
+    pub fn spawn(
+        &mut self,
+        position: Vec3,
+        radius: i32,
+        universe: &mut Universe,
+        tasks: &mut TaskScheduler,
+    ) {
+        if !self.enabled || radius <= 0 {
+            return;
+        }
+        let material_names = universe.get_material_names();
+        let voxel = match material_names.iter().position(|m| *m == self.material) {
+            Some(idx) => MaterialVoxel::from_material_index(idx as u16 + 1),
+            None => {
+                log::error!("Wreck material {} not found", self.material);
+                return;
+            }
+        };
+        let ground = match universe.ray_cast(position + Vec3::Z, -Vec3::Z) {
+            Some(result) => result.hit,
+            None => return,
+        };
+
+        // a dome half as high as it is wide, resting on the voxel the unit stood on
+        let base = ground + PointN([0, 0, 1]);
+        let extent = Extent3i::from_min_and_shape(
+            base - PointN([radius, radius, 0]),
+            PointN([2 * radius + 1, 2 * radius + 1, radius / 2 + 1]),
+        );
+        let mut rng = thread_rng();
+        let mut stamped = vec![];
+        let mut top = base.z();
+        for point in extent.iter_points() {
+            let d = point - base;
+            let distance_sq = d.x() * d.x() + d.y() * d.y() + 4 * d.z() * d.z();
+            if distance_sq > radius * radius {
+                continue;
+            }
+            let on_shell = distance_sq > (radius - 1) * (radius - 1);
+            if on_shell && rng.gen_bool(SHELL_GAP_CHANCE) {
+                continue;
+            }
+            let previous = universe.get_voxel(point);
+            if !previous.is_empty() {
+                continue;
+            }
+            stamped.push((point, previous));
+            top = top.max(point.z());
+        }
+        if stamped.is_empty() {
+            return;
+        }
+        universe.update_voxels(stamped.iter().map(|(point, _)| (*point, voxel)));
+
+        let salvage_position =
+            Vec3::new(base.x() as f32, base.y() as f32, top as f32 + SALVAGE_LIFT);
+        let salvage = tasks.post(TaskKind::Gather, salvage_position, None);
+        log::debug!(
+            "Wreck of {} voxels at {}, salvage task {:?}",
+            stamped.len(),
+            salvage_position,
+            salvage
+        );
+        self.wrecks.push(Wreck {
+            position: salvage_position,
+            stamped,
+            salvage,
+            time_left: self.decay_time,
+        });
+    }
+
+    /// Clears the heaps that were salvaged or decayed. Voxels edited since the heap was stamped
+    /// are left alone.
+    pub fn update(&mut self, dt: f32, universe: &mut Universe, tasks: &mut TaskScheduler) {
+        let material_names = universe.get_material_names();
+        let voxel = material_names
+            .iter()
+            .position(|m| *m == self.material)
+            .map(|idx| MaterialVoxel::from_material_index(idx as u16 + 1));
+        for wreck in &mut self.wrecks {
+            wreck.time_left -= dt;
+        }
+        // the salvage task is gone once a worker finished it
+        let (done, wrecks): (Vec<_>, Vec<_>) = std::mem::take(&mut self.wrecks)
+            .into_iter()
+            .partition(|wreck| wreck.time_left <= 0. || tasks.get(wreck.salvage).is_none());
+        self.wrecks = wrecks;
+        if done.is_empty() {
+            return;
+        }
+        let mut restored = vec![];
+        for wreck in done {
+            tasks.cancel(wreck.salvage);
+            restored.extend(
+                wreck
+                    .stamped
+                    .into_iter()
+                    .filter(|(point, _)| Some(universe.get_voxel(*point)) == voxel),
+            );
+        }
+        universe.update_voxels(restored);
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("wrecks", count = self.wrecks.len()))
+            .id_source("wrecks")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.enabled, tr!("wrecks.enabled"));
+                ui.add(
+                    egui::Slider::new(&mut self.decay_time, 10.0..=600.).text(tr!("wrecks.decay")),
+                );
+                for wreck in &self.wrecks {
+                    ui.label(tr!(
+                        "wrecks.entry",
+                        x = format!("{:.0}", wreck.position.x),
+                        y = format!("{:.0}", wreck.position.y),
+                        voxels = wreck.stamped.len(),
+                        time = format!("{:.0}", wreck.time_left)
+                    ));
+                }
+            });
+    }
+}
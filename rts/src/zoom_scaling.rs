@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// `(distance / reference_distance) ^ exponent`, clamped to `min..=max`. An exponent of 1
+/// keeps a world size constant on screen, 0 turns the scaling off.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct ZoomCurve {
+    pub exponent: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for ZoomCurve {
+    fn default() -> Self {
+        Self {
+            exponent: 1.,
+            min: 0.25,
+            max: 16.,
+        }
+    }
+}
+
+impl ZoomCurve {
+    fn ui(&mut self, ui: &mut egui::Ui, label: &str) -> bool {
+        let mut changed = false;
+        ui.label(label);
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut self.exponent, -1.0..=2.)
+                    .text(tr!("settings.zoom_scaling.exponent")),
+            )
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(&mut self.min, 0.05..=1.).text(tr!("settings.zoom_scaling.min")))
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(&mut self.max, 1.0..=32.).text(tr!("settings.zoom_scaling.max")))
+            .changed();
+        changed
+    }
+}
+
+/// How interaction sizes follow the camera distance, so editing up close and commanding from
+/// far away feel the same.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct ZoomScalingSettings {
+    pub enabled: bool,
+    /// Look-at distance where every factor is 1.
+    pub reference_distance: f32, // m
+    /// Terrain brush size and road width.
+    pub brush: ZoomCurve,
+    /// Keyboard pan speed.
+    pub pan: ZoomCurve,
+    /// Unit click radius in pixels, units are smaller on screen when far.
+    pub pick: ZoomCurve,
+}
+
+impl Default for ZoomScalingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reference_distance: 40.,
+            brush: Default::default(),
+            pan: Default::default(),
+            pick: ZoomCurve {
+                exponent: -0.5,
+                min: 0.5,
+                max: 1.5,
+            },
+        }
+    }
+}
+
+impl ZoomScalingSettings {
+    pub fn factor(&self, curve: &ZoomCurve, distance: f32) -> f32 {
+        if !self.enabled || self.reference_distance <= 0. {
+            return 1.;
+        }
+        (distance / self.reference_distance)
+            .max(f32::EPSILON)
+            .powf(curve.exponent)
+            .max(curve.min)
+            .min(curve.max)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(tr!("settings.zoom_scaling"))
+            .id_source("zoom scaling")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut changed = ui
+                    .checkbox(&mut self.enabled, tr!("settings.enabled"))
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.reference_distance, 5.0..=200.)
+                            .text(tr!("settings.zoom_scaling.reference")),
+                    )
+                    .changed();
+                changed |= self.brush.ui(ui, &tr!("settings.zoom_scaling.brush"));
+                changed |= self.pan.ui(ui, &tr!("settings.zoom_scaling.pan"));
+                changed |= self.pick.ui(ui, &tr!("settings.zoom_scaling.pick"));
+                if ui.button(tr!("settings.defaults")).clicked() {
+                    *self = Default::default();
+                    changed = true;
+                }
+                if changed {
+                    let settings = *self;
+                    Settings::update(|s| s.zoom_scaling = settings);
+                }
+            });
+    }
+}